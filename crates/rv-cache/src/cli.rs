@@ -1,8 +1,9 @@
 use camino::Utf8PathBuf;
 use clap::Parser;
 use std::io;
+use std::time::Duration;
 
-use crate::Cache;
+use crate::{Cache, LockWait};
 
 #[derive(Parser, Debug, Clone, Default)]
 #[command(next_help_heading = "Cache Options")]
@@ -24,12 +25,43 @@ pub struct CacheArgs {
     /// Defaults to $XDG_CACHE_HOME/rv or ~/.cache/rv
     #[arg(global = true, hide = true, long, env = "RV_CACHE_DIR")]
     pub cache_dir: Option<Utf8PathBuf>,
+
+    /// Fail immediately, instead of waiting, if another rv process holds a lock on the cache or
+    /// on a ruby install directory.
+    #[arg(
+        global = true,
+        long,
+        value_parser = clap::builder::BoolishValueParser::new(),
+        env = "RV_NO_LOCK_WAIT"
+    )]
+    pub no_lock_wait: bool,
+
+    /// How many seconds to wait for another rv process to release a lock before giving up.
+    #[arg(
+        global = true,
+        long,
+        env = "RV_LOCK_TIMEOUT",
+        value_name = "SECONDS",
+        conflicts_with = "no_lock_wait"
+    )]
+    pub lock_timeout: Option<u64>,
 }
 
 impl CacheArgs {
     pub fn to_cache(&self) -> io::Result<Cache> {
         self.try_into()
     }
+
+    /// What to do when another `rv` process holds a lock we need.
+    pub fn lock_wait(&self) -> LockWait {
+        if self.no_lock_wait {
+            LockWait::NoWait
+        } else {
+            self.lock_timeout
+                .map(|secs| LockWait::Timeout(Duration::from_secs(secs)))
+                .unwrap_or_default()
+        }
+    }
 }
 
 impl Cache {
@@ -59,7 +91,10 @@ impl TryFrom<&CacheArgs> for Cache {
     type Error = io::Error;
 
     fn try_from(value: &CacheArgs) -> Result<Self, Self::Error> {
-        Cache::from_settings(value.no_cache, value.cache_dir.as_ref())
+        Ok(
+            Cache::from_settings(value.no_cache, value.cache_dir.as_ref())?
+                .with_lock_wait(value.lock_wait()),
+        )
     }
 }
 
@@ -108,6 +143,7 @@ mod tests {
         let args = CacheArgs {
             no_cache: false,
             cache_dir: Some(cache_path.clone()),
+            ..Default::default()
         };
 
         let cache: Cache = args.try_into().unwrap();
@@ -120,9 +156,28 @@ mod tests {
         let args = CacheArgs {
             no_cache: true,
             cache_dir: None,
+            ..Default::default()
         };
 
         let cache: Cache = args.try_into().unwrap();
         assert!(cache.is_temporary());
     }
+
+    #[test]
+    fn test_cache_args_lock_wait() {
+        assert_eq!(CacheArgs::default().lock_wait(), LockWait::default());
+
+        let args = CacheArgs {
+            lock_timeout: Some(5),
+            ..Default::default()
+        };
+        assert_eq!(args.lock_wait(), LockWait::Timeout(Duration::from_secs(5)));
+
+        let args = CacheArgs {
+            no_lock_wait: true,
+            ..Default::default()
+        };
+        let cache: Cache = args.try_into().unwrap();
+        assert_eq!(cache.lock_wait(), LockWait::NoWait);
+    }
 }
@@ -14,12 +14,16 @@ pub use crate::removal::{Removal, rm_rf};
 
 // Re-export our custom caching utilities
 pub use crate::cache_key::{CacheKey, CacheKeyHasher, cache_digest};
+pub use crate::lock::{DEFAULT_LOCK_TIMEOUT, LockWait, LockedFile};
+pub use crate::stats::{BucketStats, cache_stats, record_hit, record_lookup, record_miss};
 pub use crate::timestamp::Timestamp;
 
 mod cache_key;
 #[cfg(feature = "clap")]
 mod cli;
+mod lock;
 mod removal;
+mod stats;
 mod timestamp;
 
 /// A [`CacheEntry`] which may or may not exist yet.
@@ -119,6 +123,8 @@ pub struct Cache {
     /// Included to ensure that the temporary directory exists for the length of the operation, but
     /// is dropped at the end as appropriate.
     temp_dir: Option<Arc<tempfile::TempDir>>,
+    /// What to do when another `rv` process holds a lock we need.
+    lock_wait: LockWait,
 }
 
 impl Cache {
@@ -127,6 +133,7 @@ impl Cache {
         Self {
             root: root.into(),
             temp_dir: None,
+            lock_wait: LockWait::default(),
         }
     }
 
@@ -138,6 +145,7 @@ impl Cache {
         Ok(Self {
             root,
             temp_dir: Some(Arc::new(temp_dir)),
+            lock_wait: LockWait::default(),
         })
     }
 
@@ -166,6 +174,50 @@ impl Cache {
         CacheEntry::new(self.bucket(cache_bucket).join(dir), file)
     }
 
+    /// Set what to do when another `rv` process holds a lock we need.
+    #[must_use]
+    pub fn with_lock_wait(self, lock_wait: LockWait) -> Self {
+        Self { lock_wait, ..self }
+    }
+
+    /// What to do when another `rv` process holds a lock we need.
+    pub fn lock_wait(&self) -> LockWait {
+        self.lock_wait
+    }
+
+    /// Take an exclusive lock on a cache shard, so that no other `rv` process writes to it
+    /// until the returned guard is dropped. The lock file sits next to the shard rather than
+    /// inside it, so the shard only ever holds cache entries.
+    pub fn lock_shard(&self, shard: &CacheShard) -> Result<LockedFile, io::Error> {
+        LockedFile::acquire(
+            format!("{}.lock", shard.0),
+            &format!("cache shard {}", shard.0),
+            self.lock_wait,
+        )
+    }
+
+    /// Like [`Cache::lock_shard`], but waits for the lock without blocking the async runtime.
+    pub async fn lock_shard_async(&self, shard: &CacheShard) -> Result<LockedFile, io::Error> {
+        LockedFile::acquire_async(
+            format!("{}.lock", shard.0),
+            &format!("cache shard {}", shard.0),
+            self.lock_wait,
+        )
+        .await
+    }
+
+    /// Take an exclusive lock on the single cache entry at `path`, so that other `rv` processes
+    /// can write other entries of its shard meanwhile. The lock file is `path` with `.lock`
+    /// added.
+    pub async fn lock_entry_async(&self, path: &Utf8Path) -> Result<LockedFile, io::Error> {
+        LockedFile::acquire_async(
+            format!("{path}.lock"),
+            &format!("cache entry {path}"),
+            self.lock_wait,
+        )
+        .await
+    }
+
     /// Returns `true` if the [`Cache`] is temporary.
     pub fn is_temporary(&self) -> bool {
         self.temp_dir.is_some()
@@ -243,6 +295,24 @@ impl Cache {
     }
 }
 
+/// Write `contents` to `path` by way of a temporary file in the same directory, so that concurrent
+/// readers never observe a partially-written cache entry.
+pub fn write_atomic(
+    path: impl AsRef<Utf8Path>,
+    contents: impl AsRef<[u8]>,
+) -> Result<(), io::Error> {
+    let path = path.as_ref();
+    let dir = path
+        .parent()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Path has no parent"))?;
+    fs_err::create_dir_all(dir)?;
+
+    let mut temp_file = tempfile::NamedTempFile::new_in(dir)?;
+    temp_file.write_all(contents.as_ref())?;
+    temp_file.persist(path).map_err(|err| err.error)?;
+    Ok(())
+}
+
 pub trait CleanReporter: Send + Sync {
     /// Called after one file or directory is removed.
     fn on_clean(&self);
@@ -266,6 +336,8 @@ pub enum CacheBucket {
     Gemspec,
     /// Getting all transitive dependencies of a gem
     GemDeps,
+    /// Compiled native extensions of gems.
+    Extensions,
 }
 
 impl CacheBucket {
@@ -276,6 +348,19 @@ impl CacheBucket {
             Self::Git => "git-v0",
             Self::Gemspec => "gemspec-v0",
             Self::GemDeps => "gemdeps-v0",
+            Self::Extensions => "extensions-v0",
+        }
+    }
+
+    /// The bucket's name, without the version of its layout.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Ruby => "ruby",
+            Self::Gem => "gem",
+            Self::Git => "git",
+            Self::Gemspec => "gemspec",
+            Self::GemDeps => "gemdeps",
+            Self::Extensions => "extensions",
         }
     }
 
@@ -611,4 +696,51 @@ mod tests {
         reporter.on_complete();
         assert!(reporter.is_completed());
     }
+
+    #[test]
+    fn test_write_atomic_replaces_contents() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = camino::Utf8PathBuf::from(temp_dir.path().to_str().unwrap())
+            .join("shard")
+            .join("entry.json");
+
+        write_atomic(&path, "first").unwrap();
+        write_atomic(&path, "second").unwrap();
+
+        assert_eq!(fs_err::read_to_string(&path).unwrap(), "second");
+        assert_eq!(fs_err::read_dir(path.parent().unwrap()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_lock_shard_uses_configured_wait() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let cache =
+            Cache::from_path(temp_dir.path().to_str().unwrap()).with_lock_wait(LockWait::NoWait);
+        let shard = cache.shard(CacheBucket::Ruby, "tarballs");
+
+        let _held = cache.lock_shard(&shard).unwrap();
+        let err = cache.lock_shard(&shard).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+    }
+
+    #[tokio::test]
+    async fn test_lock_entry_async_locks_only_that_entry() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let cache =
+            Cache::from_path(temp_dir.path().to_str().unwrap()).with_lock_wait(LockWait::NoWait);
+        let shard = cache.shard(CacheBucket::Ruby, "tarballs");
+        fs_err::create_dir_all(&*shard).unwrap();
+        let first = shard.join("first.tar.gz");
+
+        let held = cache.lock_entry_async(&first).await.unwrap();
+        assert_eq!(held.path(), shard.join("first.tar.gz.lock"));
+        let err = cache.lock_entry_async(&first).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+
+        cache
+            .lock_entry_async(&shard.join("second.tar.gz"))
+            .await
+            .unwrap();
+        cache.lock_shard_async(&shard).await.unwrap();
+    }
 }
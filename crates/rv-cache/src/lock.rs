@@ -0,0 +1,242 @@
+use std::fs::{File, TryLockError};
+use std::io;
+use std::time::{Duration, Instant};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use tracing::{debug, warn};
+
+/// How long to wait for another `rv` process before giving up, unless configured otherwise.
+pub const DEFAULT_LOCK_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// How often to re-check a lock that is held by another process.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// What to do when a lock is already held by another process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockWait {
+    /// Fail immediately.
+    NoWait,
+    /// Wait up to the given duration for the other process to finish.
+    Timeout(Duration),
+}
+
+impl Default for LockWait {
+    fn default() -> Self {
+        Self::Timeout(DEFAULT_LOCK_TIMEOUT)
+    }
+}
+
+/// An advisory, exclusive lock on a file, released when dropped.
+///
+/// Used to keep concurrent `rv` invocations from writing to the same cache shard or ruby
+/// install directory at the same time.
+#[derive(Debug)]
+pub struct LockedFile {
+    path: Utf8PathBuf,
+    // Held only for its lock, which the OS releases when the file is closed.
+    _file: File,
+}
+
+impl LockedFile {
+    /// Acquire an exclusive lock on the file at `path`, creating it (and its parent directories)
+    /// if needed. `resource` is a human-readable name for what the lock protects.
+    ///
+    /// This blocks the thread while waiting; async code should use [`LockedFile::acquire_async`].
+    pub fn acquire(
+        path: impl Into<Utf8PathBuf>,
+        resource: &str,
+        wait: LockWait,
+    ) -> Result<Self, io::Error> {
+        let mut attempt = Attempt::new(path.into(), resource, wait)?;
+        while !attempt.try_lock()? {
+            std::thread::sleep(POLL_INTERVAL);
+        }
+        Ok(attempt.into_locked())
+    }
+
+    /// Like [`LockedFile::acquire`], but waits for the lock without blocking the async runtime.
+    pub async fn acquire_async(
+        path: impl Into<Utf8PathBuf>,
+        resource: &str,
+        wait: LockWait,
+    ) -> Result<Self, io::Error> {
+        let mut attempt = Attempt::new(path.into(), resource, wait)?;
+        while !attempt.try_lock()? {
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+        Ok(attempt.into_locked())
+    }
+
+    /// The path of the lock file.
+    pub fn path(&self) -> &Utf8Path {
+        &self.path
+    }
+}
+
+/// An open lock file that we're trying to lock.
+struct Attempt<'a> {
+    path: Utf8PathBuf,
+    file: File,
+    resource: &'a str,
+    wait: LockWait,
+    /// When we started waiting for another process, if we have.
+    waiting_since: Option<Instant>,
+}
+
+impl<'a> Attempt<'a> {
+    fn new(path: Utf8PathBuf, resource: &'a str, wait: LockWait) -> Result<Self, io::Error> {
+        if let Some(parent) = path.parent() {
+            fs_err::create_dir_all(parent)?;
+        }
+        let file = fs_err::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?
+            .into_parts()
+            .0;
+        Ok(Self {
+            path,
+            file,
+            resource,
+            wait,
+            waiting_since: None,
+        })
+    }
+
+    /// Try to take the lock once. Returns whether we got it, or an error once we shouldn't wait
+    /// any longer.
+    fn try_lock(&mut self) -> Result<bool, io::Error> {
+        let resource = self.resource;
+        match self.file.try_lock() {
+            Ok(()) => {
+                match self.waiting_since {
+                    Some(started) => debug!(
+                        "Acquired lock for {resource} after {:.1}s",
+                        started.elapsed().as_secs_f64()
+                    ),
+                    None => debug!("Acquired lock for {resource}"),
+                }
+                return Ok(true);
+            }
+            Err(TryLockError::WouldBlock) => {}
+            Err(TryLockError::Error(err)) => return Err(err),
+        }
+
+        let timeout = match self.wait {
+            LockWait::NoWait => return Err(locked_error(resource, &self.path)),
+            LockWait::Timeout(timeout) => timeout,
+        };
+        match self.waiting_since {
+            None => {
+                warn!("Waiting for another rv process to release the lock on {resource}");
+                self.waiting_since = Some(Instant::now());
+                Ok(false)
+            }
+            Some(started) if started.elapsed() < timeout => Ok(false),
+            Some(_) => Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!(
+                    "timed out after {}s waiting for the lock on {resource} ({})",
+                    timeout.as_secs(),
+                    self.path
+                ),
+            )),
+        }
+    }
+
+    fn into_locked(self) -> LockedFile {
+        LockedFile {
+            path: self.path,
+            _file: self.file,
+        }
+    }
+}
+
+fn locked_error(resource: &str, path: &Utf8Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::WouldBlock,
+        format!("{resource} is locked by another rv process ({path})"),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lock_path(dir: &tempfile::TempDir) -> Utf8PathBuf {
+        Utf8PathBuf::try_from(dir.path().join("nested").join(".lock")).unwrap()
+    }
+
+    #[test]
+    fn test_acquire_creates_lock_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = lock_path(&dir);
+
+        let lock = LockedFile::acquire(&path, "test", LockWait::NoWait).unwrap();
+        assert_eq!(lock.path(), path);
+        assert!(path.exists());
+    }
+
+    #[test]
+    fn test_no_wait_fails_while_held() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = lock_path(&dir);
+
+        let _held = LockedFile::acquire(&path, "test", LockWait::NoWait).unwrap();
+        let err = LockedFile::acquire(&path, "test", LockWait::NoWait).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+    }
+
+    #[test]
+    fn test_timeout_expires_while_held() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = lock_path(&dir);
+
+        let _held = LockedFile::acquire(&path, "test", LockWait::NoWait).unwrap();
+        let wait = LockWait::Timeout(Duration::from_millis(250));
+        let err = LockedFile::acquire(&path, "test", wait).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    }
+
+    #[test]
+    fn test_lock_is_released_on_drop() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = lock_path(&dir);
+
+        drop(LockedFile::acquire(&path, "test", LockWait::NoWait).unwrap());
+        LockedFile::acquire(&path, "test", LockWait::NoWait).unwrap();
+    }
+
+    #[test]
+    fn test_waiter_acquires_after_release() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = lock_path(&dir);
+
+        let held = LockedFile::acquire(&path, "test", LockWait::NoWait).unwrap();
+        let waiter = std::thread::spawn(move || {
+            LockedFile::acquire(path, "test", LockWait::Timeout(Duration::from_secs(10)))
+        });
+        std::thread::sleep(Duration::from_millis(200));
+        drop(held);
+
+        assert!(waiter.join().unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_async_waiter_acquires_after_release() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = lock_path(&dir);
+
+        let held = LockedFile::acquire(&path, "test", LockWait::NoWait).unwrap();
+        let waiter = tokio::spawn(async move {
+            LockedFile::acquire_async(path, "test", LockWait::Timeout(Duration::from_secs(10)))
+                .await
+        });
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        drop(held);
+
+        assert!(waiter.await.unwrap().is_ok());
+    }
+}
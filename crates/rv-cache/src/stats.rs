@@ -0,0 +1,68 @@
+//! How often each cache bucket had what rv looked for, for `--stats`.
+
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use serde::Serialize;
+
+use crate::CacheBucket;
+
+static STATS: Mutex<BTreeMap<&'static str, BucketStats>> = Mutex::new(BTreeMap::new());
+
+/// The lookups in one cache bucket.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct BucketStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+/// Count a lookup in `bucket` that found what it looked for.
+pub fn record_hit(bucket: CacheBucket) {
+    record(bucket, |stats| stats.hits += 1);
+}
+
+/// Count a lookup in `bucket` that didn't, so it had to be fetched or built.
+pub fn record_miss(bucket: CacheBucket) {
+    record(bucket, |stats| stats.misses += 1);
+}
+
+/// Count a lookup in `bucket`, which found what it looked for if `hit`.
+pub fn record_lookup(bucket: CacheBucket, hit: bool) {
+    if hit {
+        record_hit(bucket);
+    } else {
+        record_miss(bucket);
+    }
+}
+
+fn record(bucket: CacheBucket, f: impl FnOnce(&mut BucketStats)) {
+    let mut stats = STATS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    f(stats.entry(bucket.name()).or_default());
+}
+
+/// The lookups so far in each bucket that had any, by the bucket's name.
+pub fn cache_stats() -> BTreeMap<&'static str, BucketStats> {
+    STATS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record() {
+        record_hit(CacheBucket::Extensions);
+        record_hit(CacheBucket::Extensions);
+        record_miss(CacheBucket::Extensions);
+        // Other tests may count lookups too, but none in this bucket.
+        assert_eq!(
+            cache_stats()["extensions"],
+            BucketStats { hits: 2, misses: 1 }
+        );
+    }
+}
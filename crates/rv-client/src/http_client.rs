@@ -1,7 +1,72 @@
+use std::sync::Arc;
+
+use crate::pin::{PinningVerifier, certificate_pins};
 use crate::tls::Certificates;
-use reqwest::Client;
+use reqwest::header::{HeaderName, HeaderValue};
+use reqwest::{Body, IntoUrl, Method, Response};
 
 pub fn rv_http_client(command: &'static str) -> Result<Client, reqwest::Error> {
+    Ok(Client(client_builder(command).build()?))
+}
+
+/// Like [`rv_http_client`], but hands back redirects instead of following them.
+pub fn rv_http_client_without_redirects(command: &'static str) -> Result<Client, reqwest::Error> {
+    let client = client_builder(command)
+        .redirect(reqwest::redirect::Policy::none())
+        .build()?;
+    Ok(Client(client))
+}
+
+/// An HTTP client that counts every response for `--stats`, and reads every response body no
+/// faster than `--limit-rate` allows.
+#[derive(Debug, Clone)]
+pub struct Client(reqwest::Client);
+
+impl Client {
+    pub fn get(&self, url: impl IntoUrl) -> RequestBuilder {
+        self.request(Method::GET, url)
+    }
+
+    pub fn request(&self, method: Method, url: impl IntoUrl) -> RequestBuilder {
+        RequestBuilder(self.0.request(method, url))
+    }
+}
+
+/// A request to send with a [`Client`].
+#[derive(Debug)]
+pub struct RequestBuilder(reqwest::RequestBuilder);
+
+impl RequestBuilder {
+    pub fn header<K, V>(self, key: K, value: V) -> Self
+    where
+        HeaderName: TryFrom<K>,
+        <HeaderName as TryFrom<K>>::Error: Into<http::Error>,
+        HeaderValue: TryFrom<V>,
+        <HeaderValue as TryFrom<V>>::Error: Into<http::Error>,
+    {
+        Self(self.0.header(key, value))
+    }
+
+    pub fn basic_auth<U, P>(self, username: U, password: Option<P>) -> Self
+    where
+        U: std::fmt::Display,
+        P: std::fmt::Display,
+    {
+        Self(self.0.basic_auth(username, password))
+    }
+
+    pub fn body(self, body: impl Into<Body>) -> Self {
+        Self(self.0.body(body))
+    }
+
+    pub async fn send(self) -> reqwest::Result<Response> {
+        let response = self.0.send().await?;
+        crate::stats::record_response(&response);
+        Ok(crate::rate_limit::limit(response))
+    }
+}
+
+fn client_builder(command: &'static str) -> reqwest::ClientBuilder {
     use reqwest::header;
     let mut headers = header::HeaderMap::new();
 
@@ -29,7 +94,28 @@ pub fn rv_http_client(command: &'static str) -> Result<Client, reqwest::Error> {
         client_builder
     };
 
-    let client = client_builder.build()?;
+    // Hosts with pinned certificates need a verifier that checks the pins, which takes a rustls
+    // config of our own. It verifies chains with the platform's verifier, or against only the
+    // custom certificates when they're set, like reqwest does, and checks the pins on top.
+    let pins = certificate_pins();
+    let client_builder = if pins.is_empty() {
+        client_builder
+    } else {
+        client_builder.tls_backend_preconfigured(pinned_tls_config(pins))
+    };
+
+    client_builder
+}
 
-    Ok(client)
+fn pinned_tls_config(pins: Vec<crate::pin::CertificatePin>) -> rustls::ClientConfig {
+    let provider = Arc::new(rustls::crypto::aws_lc_rs::default_provider());
+    let verifier = PinningVerifier::new(pins, provider.clone());
+    let mut config = rustls::ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .expect("the default crypto provider supports the default protocol versions")
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(verifier))
+        .with_no_client_auth();
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    config
 }
@@ -1,2 +1,6 @@
 pub mod http_client;
+pub mod pin;
+pub mod rate_limit;
+pub mod retry;
+pub mod stats;
 pub mod tls;
@@ -0,0 +1,380 @@
+//! Certificate pinning for gem servers. A pin names the one certificate, or the one public key, a
+//! host's certificate chain must include, on top of the chain being trusted as usual. This guards
+//! against certificates that a compromised or coerced certificate authority issued for the host.
+//!
+//! Pins are written like OkHttp's and HPKP's: `sha256/<base64>` is the SHA-256 hash of a
+//! certificate's DER-encoded SubjectPublicKeyInfo, which stays the same when a certificate is
+//! renewed with the same key, and `cert-sha256/<hex>` is the SHA-256 fingerprint of a whole
+//! certificate, like `openssl x509 -fingerprint -sha256` prints.
+
+use std::sync::{Arc, RwLock};
+
+use base64::Engine;
+use rustls::client::WebPkiServerVerifier;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::CryptoProvider;
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{CertificateError, DigitallySignedStruct, OtherError, RootCertStore, SignatureScheme};
+use sha2::{Digest, Sha256};
+use tracing::warn;
+
+use crate::tls::Certificates;
+
+/// The pins of every HTTP client rv creates, set once the settings are loaded.
+static PINS: RwLock<Vec<CertificatePin>> = RwLock::new(Vec::new());
+
+/// Pin the certificates of hosts for the HTTP clients created from now on.
+pub fn set_certificate_pins(pins: Vec<CertificatePin>) {
+    *PINS.write().unwrap() = pins;
+}
+
+pub(crate) fn certificate_pins() -> Vec<CertificatePin> {
+    PINS.read().unwrap().clone()
+}
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum ParsePinError {
+    #[error("pins start with `sha256/` or `cert-sha256/`")]
+    UnknownKind,
+    #[error("the hash of a pin must be a SHA-256 hash")]
+    InvalidHash,
+}
+
+/// What a pin hashes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PinKind {
+    /// The certificate's SubjectPublicKeyInfo.
+    PublicKey,
+    /// The whole certificate.
+    Certificate,
+}
+
+/// A certificate, or a public key, that the chain of `host` must include.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CertificatePin {
+    pub host: String,
+    pub kind: PinKind,
+    pub digest: Vec<u8>,
+}
+
+impl CertificatePin {
+    /// Parse a pin like `sha256/<base64>` or `cert-sha256/<hex>` for `host`.
+    pub fn new(host: &str, pin: &str) -> Result<Self, ParsePinError> {
+        let (kind, digest) = if let Some(hash) = pin.strip_prefix("sha256/") {
+            let digest = base64::engine::general_purpose::STANDARD
+                .decode(hash)
+                .map_err(|_| ParsePinError::InvalidHash)?;
+            (PinKind::PublicKey, digest)
+        } else if let Some(hash) = pin.strip_prefix("cert-sha256/") {
+            let digest = base16ct::mixed::decode_vec(hash.replace(':', ""))
+                .map_err(|_| ParsePinError::InvalidHash)?;
+            (PinKind::Certificate, digest)
+        } else {
+            return Err(ParsePinError::UnknownKind);
+        };
+        if digest.len() != 32 {
+            return Err(ParsePinError::InvalidHash);
+        }
+
+        Ok(Self {
+            host: host.to_ascii_lowercase(),
+            kind,
+            digest,
+        })
+    }
+
+    /// Whether `cert` is the pinned certificate, or has the pinned public key.
+    fn matches(&self, cert: &[u8]) -> bool {
+        let hash = match self.kind {
+            PinKind::Certificate => Sha256::digest(cert),
+            PinKind::PublicKey => match subject_public_key_info(cert) {
+                Some(spki) => Sha256::digest(spki),
+                None => return false,
+            },
+        };
+        hash.as_slice() == self.digest
+    }
+}
+
+/// The public key pin of `cert`, like `sha256/<base64>`, if it can be parsed.
+pub fn public_key_pin(cert: &[u8]) -> Option<String> {
+    let hash = Sha256::digest(subject_public_key_info(cert)?);
+    Some(format!(
+        "sha256/{}",
+        base64::engine::general_purpose::STANDARD.encode(hash)
+    ))
+}
+
+/// A connection to `host` failed, because its certificates didn't match its pins.
+#[derive(Debug, Clone, thiserror::Error)]
+#[error(
+    "the certificate of {host} doesn't match the pins configured for it (its public key is {actual})"
+)]
+pub struct PinMismatch {
+    pub host: String,
+    /// The public key pin of the certificate the host presented.
+    pub actual: String,
+}
+
+/// The pin mismatch that made a request fail, if that's why it did.
+pub fn pin_mismatch(err: &reqwest::Error) -> Option<PinMismatch> {
+    let mut source: Option<&(dyn std::error::Error + 'static)> = Some(err);
+    while let Some(err) = source {
+        let tls_error = err.downcast_ref::<rustls::Error>().or_else(|| {
+            err.downcast_ref::<std::io::Error>()
+                .and_then(|err| err.get_ref())
+                .and_then(|err| err.downcast_ref::<rustls::Error>())
+        });
+        if let Some(rustls::Error::InvalidCertificate(CertificateError::Other(other))) = tls_error
+            && let Some(mismatch) = other.0.downcast_ref::<PinMismatch>()
+        {
+            return Some(mismatch.clone());
+        }
+        source = err.source();
+    }
+    None
+}
+
+/// Verifies certificate chains the way clients without pins do, and then checks the pins of the
+/// host.
+#[derive(Debug)]
+pub(crate) struct PinningVerifier {
+    inner: Arc<dyn ServerCertVerifier>,
+    pins: Vec<CertificatePin>,
+}
+
+impl PinningVerifier {
+    /// A verifier that trusts only the certificates in `SSL_CERT_FILE` and `SSL_CERT_DIR` when
+    /// they're set, like `rv_http_client` does, and the platform's verifier otherwise.
+    pub(crate) fn new(pins: Vec<CertificatePin>, provider: Arc<CryptoProvider>) -> Self {
+        let mut roots = RootCertStore::empty();
+        if let Some(certs) = Certificates::from_env() {
+            roots.add_parsable_certificates(certs.iter().cloned());
+        }
+        if !roots.is_empty() {
+            let inner = webpki_verifier(roots, provider);
+            return Self { inner, pins };
+        }
+
+        let inner: Arc<dyn ServerCertVerifier> = match rustls_platform_verifier::Verifier::new(
+            provider.clone(),
+        ) {
+            Ok(verifier) => Arc::new(verifier),
+            Err(err) => {
+                warn!(
+                    "Can't use the platform's certificate verifier ({err}), so trusting Mozilla's root certificates."
+                );
+                roots.add_parsable_certificates(
+                    webpki_root_certs::TLS_SERVER_ROOT_CERTS.iter().cloned(),
+                );
+                webpki_verifier(roots, provider)
+            }
+        };
+        Self { inner, pins }
+    }
+}
+
+/// A verifier that trusts `roots`, and nothing else.
+fn webpki_verifier(
+    roots: RootCertStore,
+    provider: Arc<CryptoProvider>,
+) -> Arc<dyn ServerCertVerifier> {
+    WebPkiServerVerifier::builder_with_provider(Arc::new(roots), provider)
+        .build()
+        .expect("a verifier with root certificates and no CRLs can always be built")
+}
+
+impl ServerCertVerifier for PinningVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let verified = self.inner.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            ocsp_response,
+            now,
+        )?;
+
+        let host = server_name.to_str().to_ascii_lowercase();
+        let mut pins = self.pins.iter().filter(|pin| pin.host == host).peekable();
+        if pins.peek().is_none() {
+            return Ok(verified);
+        }
+        let chain: Vec<&[u8]> = std::iter::once(end_entity)
+            .chain(intermediates)
+            .map(|cert| cert.as_ref())
+            .collect();
+        if pins.any(|pin| chain.iter().any(|cert| pin.matches(cert))) {
+            return Ok(verified);
+        }
+
+        let mismatch = PinMismatch {
+            host,
+            actual: public_key_pin(end_entity).unwrap_or_else(|| "unknown".to_string()),
+        };
+        Err(rustls::Error::InvalidCertificate(CertificateError::Other(
+            OtherError(Arc::new(mismatch)),
+        )))
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
+}
+
+/// The DER-encoded SubjectPublicKeyInfo of the DER-encoded X.509 certificate `cert`. It's the
+/// seventh field of the TBSCertificate, counting the optional version.
+fn subject_public_key_info(cert: &[u8]) -> Option<&[u8]> {
+    const SEQUENCE: u8 = 0x30;
+    const VERSION: u8 = 0xa0;
+
+    let (tag, _, certificate, _) = read_tlv(cert)?;
+    if tag != SEQUENCE {
+        return None;
+    }
+    let (tag, _, tbs_certificate, _) = read_tlv(certificate)?;
+    if tag != SEQUENCE {
+        return None;
+    }
+
+    let mut fields = tbs_certificate;
+    if fields.first() == Some(&VERSION) {
+        fields = read_tlv(fields)?.3;
+    }
+    // The serial number, signature algorithm, issuer, validity and subject.
+    for _ in 0..5 {
+        fields = read_tlv(fields)?.3;
+    }
+    let (tag, spki, _, _) = read_tlv(fields)?;
+    (tag == SEQUENCE).then_some(spki)
+}
+
+/// Read one DER value from the start of `input`, returning its tag, its whole encoding, its
+/// contents and what comes after it.
+fn read_tlv(input: &[u8]) -> Option<(u8, &[u8], &[u8], &[u8])> {
+    let tag = *input.first()?;
+    let first = *input.get(1)?;
+    let (length, header) = if first < 0x80 {
+        (first as usize, 2)
+    } else {
+        let octets = (first & 0x7f) as usize;
+        if octets == 0 || octets > std::mem::size_of::<usize>() {
+            return None;
+        }
+        let bytes = input.get(2..2 + octets)?;
+        let length = bytes
+            .iter()
+            .fold(0usize, |length, byte| (length << 8) | *byte as usize);
+        (length, 2 + octets)
+    };
+    let end = header.checked_add(length)?;
+    let contents = input.get(header..end)?;
+    Some((tag, &input[..end], contents, &input[end..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_pins() {
+        let pin = CertificatePin::new(
+            "Gems.Example.com",
+            "sha256/47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=",
+        )
+        .unwrap();
+        assert_eq!(pin.host, "gems.example.com");
+        assert_eq!(pin.kind, PinKind::PublicKey);
+        assert_eq!(pin.digest.len(), 32);
+
+        let pin = CertificatePin::new(
+            "gems.example.com",
+            "cert-sha256/E3:B0:C4:42:98:FC:1C:14:9A:FB:F4:C8:99:6F:B9:24:27:AE:41:E4:64:9B:93:4C:A4:95:99:1B:78:52:B8:55",
+        )
+        .unwrap();
+        assert_eq!(pin.kind, PinKind::Certificate);
+        assert_eq!(pin.digest, Sha256::digest(b"").as_slice());
+
+        assert_eq!(
+            CertificatePin::new("gems.example.com", "sha1/abc"),
+            Err(ParsePinError::UnknownKind)
+        );
+        assert_eq!(
+            CertificatePin::new("gems.example.com", "sha256/YWJj"),
+            Err(ParsePinError::InvalidHash)
+        );
+    }
+
+    #[test]
+    fn test_pins_match_certificates() {
+        let key = rcgen::KeyPair::generate().unwrap();
+        let cert = rcgen::CertificateParams::new(vec!["localhost".to_string()])
+            .unwrap()
+            .self_signed(&key)
+            .unwrap();
+        let der = cert.der().as_ref();
+
+        // The SubjectPublicKeyInfo is the public key rcgen generated.
+        assert_eq!(
+            subject_public_key_info(der),
+            Some(key.public_key_der().as_slice())
+        );
+
+        let public_key = public_key_pin(der).unwrap();
+        assert!(
+            CertificatePin::new("localhost", &public_key)
+                .unwrap()
+                .matches(der)
+        );
+        let fingerprint = format!(
+            "cert-sha256/{}",
+            base16ct::lower::encode_string(&Sha256::digest(der))
+        );
+        assert!(
+            CertificatePin::new("localhost", &fingerprint)
+                .unwrap()
+                .matches(der)
+        );
+
+        let other = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        assert!(
+            !CertificatePin::new("localhost", &public_key)
+                .unwrap()
+                .matches(other.cert.der())
+        );
+    }
+
+    #[test]
+    fn test_subject_public_key_info_of_garbage() {
+        assert_eq!(subject_public_key_info(b""), None);
+        assert_eq!(subject_public_key_info(&[0x30, 0x82, 0xff]), None);
+        assert_eq!(
+            subject_public_key_info(&[0x30, 0x03, 0x30, 0x01, 0x00]),
+            None
+        );
+    }
+}
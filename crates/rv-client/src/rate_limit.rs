@@ -0,0 +1,279 @@
+//! Limiting the bandwidth of downloads, so that rv doesn't starve other work on shared machines.
+//!
+//! Every download takes from one token bucket, which holds up to a second's worth of bytes and
+//! refills at the configured rate. A download that takes more than the bucket holds goes into
+//! debt, and waits until the bucket would have refilled, so concurrent downloads share the rate
+//! between them instead of each getting all of it.
+//!
+//! The [`Client`](crate::http_client::Client) limits every response body it hands back, so that
+//! no download can forget to.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, RwLock};
+use std::task::{Context, Poll, ready};
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use http_body::{Frame, SizeHint};
+use reqwest::{Response, ResponseBuilderExt};
+use tokio::time::Sleep;
+
+/// The limiter every download goes through, if a rate is configured.
+static LIMITER: RwLock<Option<Arc<RateLimiter>>> = RwLock::new(None);
+
+/// Limit every download from now on to `rate`, or lift the limit.
+pub fn set_download_rate(rate: Option<Rate>) {
+    *LIMITER.write().unwrap() = rate.map(|rate| Arc::new(RateLimiter::new(rate)));
+}
+
+fn limiter() -> Option<Arc<RateLimiter>> {
+    LIMITER.read().unwrap().clone()
+}
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+#[error("{0} is not a rate like `10MB/s` or `500K`")]
+pub struct ParseRateError(String);
+
+/// A number of bytes per second.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rate(pub u64);
+
+impl FromStr for Rate {
+    type Err = ParseRateError;
+
+    /// Parse a rate like `500K`, `10MB/s` or `1.5MiB/s`. `K`, `M` and `G` and their `B` forms
+    /// are powers of 1000, and `KiB`, `MiB` and `GiB` powers of 1024. A bare number is bytes.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || ParseRateError(s.to_string());
+        let trimmed = s.trim();
+        let trimmed = trimmed.strip_suffix("/s").unwrap_or(trimmed);
+        let split = trimmed
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(trimmed.len());
+        let (number, unit) = trimmed.split_at(split);
+        let number: f64 = number.parse().map_err(|_| invalid())?;
+        let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+            "" | "b" => 1,
+            "k" | "kb" => 1000,
+            "m" | "mb" => 1000 * 1000,
+            "g" | "gb" => 1000 * 1000 * 1000,
+            "kib" => 1 << 10,
+            "mib" => 1 << 20,
+            "gib" => 1 << 30,
+            _ => return Err(invalid()),
+        };
+        let rate = (number * multiplier as f64) as u64;
+        if rate == 0 {
+            return Err(invalid());
+        }
+        Ok(Self(rate))
+    }
+}
+
+/// A token bucket, shared by every download.
+#[derive(Debug)]
+struct RateLimiter {
+    rate: Rate,
+    bucket: Mutex<Bucket>,
+}
+
+impl RateLimiter {
+    fn new(rate: Rate) -> Self {
+        Self {
+            rate,
+            bucket: Mutex::new(Bucket {
+                tokens: rate.0 as f64,
+                updated: Instant::now(),
+            }),
+        }
+    }
+
+    /// Take `bytes` from the bucket, returning how long to wait before using them.
+    fn take(&self, bytes: usize) -> Duration {
+        self.bucket
+            .lock()
+            .unwrap()
+            .take(self.rate, bytes, Instant::now())
+    }
+}
+
+#[derive(Debug)]
+struct Bucket {
+    /// How many bytes can be downloaded right away. Negative when downloads are in debt.
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    /// Take `bytes` from the bucket at `now`, returning how long to wait before using them.
+    fn take(&mut self, rate: Rate, bytes: usize, now: Instant) -> Duration {
+        let rate = rate.0 as f64;
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate).min(rate);
+        self.updated = now;
+
+        self.tokens -= bytes as f64;
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens / rate)
+        }
+    }
+}
+
+/// `response`, with a body that's counted for `--stats` as it's read, and read no faster than
+/// downloads are limited to.
+pub(crate) fn limit(response: Response) -> Response {
+    let url = response.url().clone();
+    let (mut parts, body) = http::Response::<reqwest::Body>::from(response).into_parts();
+    // The URL of a response is kept in its extensions, which don't survive the conversion.
+    let (url_parts, ()) = http::Response::builder()
+        .url(url)
+        .body(())
+        .expect("a response with only a URL is valid")
+        .into_parts();
+    parts.extensions.extend(url_parts.extensions);
+
+    let body = LimitedBody {
+        inner: body,
+        limiter: limiter(),
+        delayed: None,
+    };
+    http::Response::from_parts(parts, reqwest::Body::wrap(body)).into()
+}
+
+/// A response body that holds back each chunk until the rate limit allows it.
+struct LimitedBody {
+    inner: reqwest::Body,
+    limiter: Option<Arc<RateLimiter>>,
+    /// A frame that's been read, and the wait before it can be handed on.
+    delayed: Option<(Frame<Bytes>, Pin<Box<Sleep>>)>,
+}
+
+impl http_body::Body for LimitedBody {
+    type Data = Bytes;
+    type Error = reqwest::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, reqwest::Error>>> {
+        let this = self.get_mut();
+        if let Some((_, sleep)) = &mut this.delayed {
+            ready!(sleep.as_mut().poll(cx));
+            let (frame, _) = this.delayed.take().unwrap();
+            return Poll::Ready(Some(Ok(frame)));
+        }
+
+        let frame = match ready!(Pin::new(&mut this.inner).poll_frame(cx)) {
+            Some(Ok(frame)) => frame,
+            other => return Poll::Ready(other),
+        };
+        let Some(data) = frame.data_ref() else {
+            return Poll::Ready(Some(Ok(frame)));
+        };
+        crate::stats::record_bytes(data.len());
+        let wait = match &this.limiter {
+            Some(limiter) => limiter.take(data.len()),
+            None => Duration::ZERO,
+        };
+        if wait.is_zero() {
+            return Poll::Ready(Some(Ok(frame)));
+        }
+        this.delayed = Some((frame, Box::pin(tokio::time::sleep(wait))));
+        Pin::new(this).poll_frame(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.delayed.is_none() && self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        let mut hint = self.inner.size_hint();
+        let delayed = self
+            .delayed
+            .as_ref()
+            .and_then(|(frame, _)| frame.data_ref());
+        if let Some(data) = delayed {
+            let len = data.len() as u64;
+            if let Some(upper) = hint.upper() {
+                hint.set_upper(upper + len);
+            }
+            hint.set_lower(hint.lower() + len);
+        }
+        hint
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rate() {
+        assert_eq!("10MB/s".parse(), Ok(Rate(10_000_000)));
+        assert_eq!("500K".parse(), Ok(Rate(500_000)));
+        assert_eq!("1.5MiB/s".parse(), Ok(Rate(1_572_864)));
+        assert_eq!("2048".parse(), Ok(Rate(2048)));
+        assert_eq!("1 gb".parse(), Ok(Rate(1_000_000_000)));
+        assert!("fast".parse::<Rate>().is_err());
+        assert!("10MB/h".parse::<Rate>().is_err());
+        assert!("0K".parse::<Rate>().is_err());
+    }
+
+    #[test]
+    fn test_bucket_paces_downloads() {
+        let rate = Rate(1000);
+        let start = Instant::now();
+        let mut bucket = Bucket {
+            tokens: 1000.0,
+            updated: start,
+        };
+
+        // A second's worth of bytes can be downloaded right away.
+        assert_eq!(bucket.take(rate, 1000, start), Duration::ZERO);
+        // After that, downloads wait for the bucket to refill.
+        assert_eq!(bucket.take(rate, 500, start), Duration::from_millis(500));
+        // A concurrent download waits for both.
+        assert_eq!(bucket.take(rate, 500, start), Duration::from_secs(1));
+        // Once that's paid off, the bucket refills, but only up to a second's worth.
+        let later = start + Duration::from_secs(5);
+        assert_eq!(bucket.take(rate, 1000, later), Duration::ZERO);
+        assert_eq!(bucket.take(rate, 100, later), Duration::from_millis(100));
+    }
+
+    #[tokio::test]
+    async fn test_limit_keeps_the_response() {
+        let url = reqwest::Url::parse("https://rubygems.org/gems/rake-13.3.0.gem").unwrap();
+        let response: Response = http::Response::builder()
+            .url(url.clone())
+            .body("hello")
+            .unwrap()
+            .into();
+        let before = crate::stats::http_stats().bytes;
+
+        let response = limit(response);
+        assert_eq!(response.url(), &url);
+        assert_eq!(response.content_length(), Some(5));
+        assert_eq!(response.bytes().await.unwrap(), "hello");
+        assert!(crate::stats::http_stats().bytes >= before + 5);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_limited_body_waits_for_the_bucket() {
+        use http_body_util::BodyExt;
+
+        let body = LimitedBody {
+            inner: reqwest::Body::from("hello"),
+            limiter: Some(Arc::new(RateLimiter::new(Rate(4)))),
+            delayed: None,
+        };
+        let start = tokio::time::Instant::now();
+        let body = body.collect().await.unwrap().to_bytes();
+        assert_eq!(body, "hello");
+        // The bucket holds 4 bytes, so the fifth waits a quarter of a second for it to refill.
+        assert!(start.elapsed() >= Duration::from_millis(200));
+    }
+}
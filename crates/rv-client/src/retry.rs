@@ -0,0 +1,175 @@
+use std::future::Future;
+use std::hash::{BuildHasher, RandomState};
+use std::time::Duration;
+
+use reqwest::StatusCode;
+use tracing::debug;
+
+/// How often, and how patiently, to retry requests that failed for transient reasons.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// How many times to retry after the first attempt.
+    pub max_retries: u32,
+    /// The delay before the first retry, doubled for every retry after that.
+    pub base_delay: Duration,
+    /// The longest delay between two attempts.
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(10),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A policy that makes a single attempt.
+    pub fn none() -> Self {
+        Self {
+            max_retries: 0,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_max_retries(self, max_retries: u32) -> Self {
+        Self {
+            max_retries,
+            ..self
+        }
+    }
+
+    /// The longest we might wait before the given retry (counting from 0), before jitter.
+    fn backoff(&self, retry: u32) -> Duration {
+        self.base_delay
+            .saturating_mul(2u32.saturating_pow(retry))
+            .min(self.max_delay)
+    }
+
+    /// How long to wait before the given retry (counting from 0). Uses "full jitter", so that
+    /// many concurrent requests failing at once don't all retry at the same moment.
+    pub fn delay(&self, retry: u32) -> Duration {
+        let backoff = self.backoff(retry);
+        let random = RandomState::new().hash_one(retry);
+        backoff.mul_f64((random % 1000) as f64 / 1000.0)
+    }
+
+    /// Run `request` until it succeeds, fails with an error that isn't worth retrying, or runs
+    /// out of retries. `what` describes the request for log messages.
+    pub async fn run<T, F, Fut>(&self, what: &str, mut request: F) -> Result<T, reqwest::Error>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, reqwest::Error>>,
+    {
+        let mut retry = 0;
+        loop {
+            match request().await {
+                Err(err) if retry < self.max_retries && is_transient(&err) => {
+                    let delay = self.delay(retry);
+                    debug!(
+                        "Retrying {what} in {}ms after error: {err}",
+                        delay.as_millis()
+                    );
+                    tokio::time::sleep(delay).await;
+                    retry += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// Whether a failed request might succeed if it's tried again.
+pub fn is_transient(err: &reqwest::Error) -> bool {
+    if let Some(status) = err.status() {
+        return status.is_server_error()
+            || status == StatusCode::TOO_MANY_REQUESTS
+            || status == StatusCode::REQUEST_TIMEOUT;
+    }
+    // The server will present the same certificate next time.
+    if crate::pin::pin_mismatch(err).is_some() {
+        return false;
+    }
+
+    // Other request errors, like a malformed response, and bodies that can't be decoded happen
+    // again.
+    err.is_timeout() || err.is_connect() || err.is_body()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_doubles_up_to_max_delay() {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.backoff(0), Duration::from_millis(500));
+        assert_eq!(policy.backoff(1), Duration::from_secs(1));
+        assert_eq!(policy.backoff(2), Duration::from_secs(2));
+        assert_eq!(policy.backoff(10), Duration::from_secs(10));
+        assert_eq!(policy.backoff(u32::MAX), Duration::from_secs(10));
+    }
+
+    #[test]
+    fn test_delay_is_jittered_within_backoff() {
+        let policy = RetryPolicy::default();
+        for retry in 0..5 {
+            assert!(policy.delay(retry) <= policy.backoff(retry));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_no_retries_without_transient_errors() {
+        let policy = RetryPolicy::default();
+        let mut attempts = 0;
+        let result: Result<u32, reqwest::Error> = policy
+            .run("test", || {
+                attempts += 1;
+                async { Ok(1) }
+            })
+            .await;
+        assert_eq!(result.unwrap(), 1);
+        assert_eq!(attempts, 1);
+    }
+
+    /// Answer one request with `response` as is, or close the connection without answering when
+    /// it's empty, and return the URL to request.
+    async fn serve_once(response: &'static str) -> String {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = [0; 1024];
+            let _ = stream.read(&mut request).await;
+            let _ = stream.write_all(response.as_bytes()).await;
+        });
+        format!("http://{addr}/")
+    }
+
+    #[tokio::test]
+    async fn test_decode_errors_are_not_transient() {
+        let url = serve_once("HTTP/1.1 200 OK\r\ncontent-length: 8\r\n\r\nnot json").await;
+        let err = reqwest::get(url)
+            .await
+            .unwrap()
+            .json::<Vec<u32>>()
+            .await
+            .unwrap_err();
+        assert!(err.is_decode());
+        assert!(!is_transient(&err));
+    }
+
+    #[tokio::test]
+    async fn test_request_errors_are_not_transient() {
+        let url = serve_once("HTTP/1.1 200 OK\r\nthis is not a header\r\n\r\n").await;
+        let err = reqwest::get(url).await.unwrap_err();
+        assert!(err.is_request());
+        assert!(!err.is_connect());
+        assert!(!is_transient(&err));
+    }
+}
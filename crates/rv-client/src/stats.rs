@@ -0,0 +1,41 @@
+//! Counts of the HTTP requests rv made and the bytes it downloaded, for `--stats`.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use reqwest::{Response, Version};
+
+static REQUESTS: AtomicU64 = AtomicU64::new(0);
+static HTTP2_REQUESTS: AtomicU64 = AtomicU64::new(0);
+static BYTES: AtomicU64 = AtomicU64::new(0);
+
+/// Everything downloaded so far.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HttpStats {
+    /// Responses received, including ones that were retried.
+    pub requests: u64,
+    /// Responses that came over HTTP/2.
+    pub http2_requests: u64,
+    /// Bytes of response bodies.
+    pub bytes: u64,
+}
+
+/// Count a response.
+pub(crate) fn record_response(response: &Response) {
+    REQUESTS.fetch_add(1, Ordering::Relaxed);
+    if response.version() == Version::HTTP_2 {
+        HTTP2_REQUESTS.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Count `bytes` more bytes of response bodies.
+pub(crate) fn record_bytes(bytes: usize) {
+    BYTES.fetch_add(bytes as u64, Ordering::Relaxed);
+}
+
+pub fn http_stats() -> HttpStats {
+    HttpStats {
+        requests: REQUESTS.load(Ordering::Relaxed),
+        http2_requests: HTTP2_REQUESTS.load(Ordering::Relaxed),
+        bytes: BYTES.load(Ordering::Relaxed),
+    }
+}
@@ -218,8 +218,7 @@ impl Certificates {
     }
 
     /// Iterate over raw DER certificates.
-    #[cfg(test)]
-    fn iter(&self) -> impl Iterator<Item = &CertificateDer<'static>> {
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &CertificateDer<'static>> {
         self.0.iter()
     }
 }
@@ -0,0 +1,160 @@
+//! Computes the environment that activates a Ruby: `PATH`, `GEM_HOME`, `GEM_PATH` and friends.
+
+use std::{
+    env::{self, JoinPathsError, join_paths, split_paths},
+    path::PathBuf,
+};
+
+use camino::Utf8PathBuf;
+use indexmap::IndexSet;
+use rv_ruby::Ruby;
+
+/// Every variable other than PATH that activating a Ruby can change, so that shell integrations
+/// can save them before they first activate one, and restore them when they're deactivated.
+pub const ACTIVATION_VARS: [&str; 8] = [
+    "MANPATH",
+    "RUBY_ROOT",
+    "RUBY_ENGINE",
+    "RUBY_VERSION",
+    "RUBYOPT",
+    "GEM_HOME",
+    "GEM_PATH",
+    "JRUBY_HOME",
+];
+
+/// How to activate a Ruby, beyond which one it is.
+#[derive(Debug, Clone, Default)]
+pub struct EnvOptions {
+    /// Where gems are installed, if not the Ruby's own default `GEM_HOME`.
+    pub gem_home: Option<Utf8PathBuf>,
+    /// Leave gems installed with `gem install --user-install` off `GEM_PATH`.
+    pub isolated: bool,
+    /// More directories to put on `PATH`, after the existing ones.
+    pub extra_paths: Vec<PathBuf>,
+}
+
+/// The environment that activates `ruby`, or that deactivates any Ruby if there's none, starting
+/// from the current process's `PATH`.
+pub fn env_for(ruby: Option<&Ruby>, options: EnvOptions) -> Result<Env, JoinPathsError> {
+    let mut env = Env::default();
+
+    let pathstr = env::var("PATH").unwrap_or_else(|_| String::new());
+    let mut paths = split_paths(&pathstr).collect::<IndexSet<_>>();
+    for extra_path in options.extra_paths {
+        paths.insert(extra_path);
+    }
+
+    let old_ruby_paths: Vec<PathBuf> = ["RUBY_ROOT", "GEM_HOME"]
+        .iter()
+        .filter_map(|var| env::var(var).ok())
+        .map(|p| std::path::Path::new(&p).join("bin"))
+        .collect();
+
+    // Both the gem directories and their executables, which is what activation puts on PATH.
+    let old_gem_paths: Vec<PathBuf> = env::var("GEM_PATH").map_or_else(
+        |_| vec![],
+        |p| {
+            split_paths(&p)
+                .flat_map(|p| [p.join("bin"), p])
+                .collect::<Vec<_>>()
+        },
+    );
+
+    // Remove old Ruby and Gem paths from PATH
+    paths.retain(|p| !old_ruby_paths.contains(p) && !old_gem_paths.contains(p));
+
+    if let Some(ruby) = ruby {
+        let mut gem_paths = vec![];
+        paths.insert_before(0, ruby.bin_path().into());
+        env.insert("RUBY_ROOT", ruby.path.to_string());
+        env.insert("RUBY_ENGINE", ruby.version.engine.name().into());
+        env.insert("RUBY_VERSION", ruby.version.number());
+        for (var, val) in ruby.version.engine.quirks().env(&ruby.path) {
+            env.insert(var, val);
+        }
+        let gem_home = options.gem_home.unwrap_or_else(|| ruby.gem_home());
+        paths.insert_before(0, gem_home.join("bin").into());
+        gem_paths.insert(0, gem_home.clone());
+        env.insert("GEM_HOME", gem_home.into_string());
+        if !options.isolated {
+            let user_home = ruby.user_home();
+            paths.insert_before(0, user_home.join("bin").into());
+            gem_paths.insert(0, user_home);
+        }
+        let gem_path = join_paths(gem_paths)?;
+        if let Some(gem_path) = gem_path.to_str() {
+            env.insert("GEM_PATH", gem_path.into());
+        }
+
+        // Set MANPATH so `man ruby`, `man irb`, etc. work correctly.
+        // MANPATH is a Unix concept — Windows has no man page system.
+        // A trailing colon means "also search system man directories".
+        #[cfg(not(windows))]
+        if let Some(man_path) = ruby.man_path() {
+            let existing = env::var("MANPATH").unwrap_or_default();
+            let man_paths = split_paths(&existing).collect::<Vec<_>>();
+
+            if !man_paths.contains(&man_path.to_path_buf().into_std_path_buf()) {
+                env.insert("MANPATH", format!("{}:{}", man_path, existing));
+            }
+        }
+    }
+
+    let path = join_paths(paths)?;
+    if let Some(path) = path.to_str() {
+        env.insert("PATH", path.into());
+    }
+
+    Ok(env)
+}
+
+/// The environment variables that activate a Ruby, and those to unset because they'd point at
+/// another one.
+pub struct Env {
+    unset: Vec<&'static str>,
+
+    set: Vec<(&'static str, String)>,
+}
+
+impl Default for Env {
+    fn default() -> Self {
+        Self {
+            set: vec![],
+            unset: Self::ENV_VARS.into(),
+        }
+    }
+}
+
+impl Env {
+    const ENV_VARS: [&str; 6] = [
+        "RUBY_ROOT",
+        "RUBY_ENGINE",
+        "RUBY_VERSION",
+        "RUBYOPT",
+        "GEM_HOME",
+        "GEM_PATH",
+    ];
+
+    pub fn insert(&mut self, var: &'static str, val: String) {
+        // PATH is never in the list to unset
+        if let Some(i) = self.unset.iter().position(|i| *i == var) {
+            self.unset.remove(i);
+        }
+
+        self.set.push((var, val));
+    }
+
+    /// The variables to set, with their values.
+    pub fn set(&self) -> &[(&'static str, String)] {
+        &self.set
+    }
+
+    /// The variables to unset.
+    pub fn unset(&self) -> &[&'static str] {
+        &self.unset
+    }
+
+    pub fn split(&self) -> (Vec<&'static str>, Vec<(&'static str, String)>) {
+        (self.unset.clone(), self.set.clone())
+    }
+}
@@ -0,0 +1,127 @@
+//! Rubies that came with the operating system or a package manager, rather than from rv. rv lists
+//! them so people can see what else is installed, but never picks them for a project.
+
+use camino::{Utf8Path, Utf8PathBuf};
+use serde::Serialize;
+
+/// The prefixes Homebrew installs into: `/opt/homebrew` on Apple silicon, `/usr/local` on Intel.
+const HOMEBREW_PREFIXES: [&str; 2] = ["opt/homebrew", "usr/local"];
+
+/// Who installed a Ruby that rv didn't.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExternalKind {
+    /// The operating system's Ruby in `/usr`, like the one macOS ships.
+    System,
+    /// A Ruby installed with `brew install ruby`.
+    Homebrew,
+}
+
+impl ExternalKind {
+    /// Who installed the Ruby in, or the Ruby executable at, `path`, if it wasn't rv. `root` is
+    /// the directory `/usr` and the Homebrew prefixes are in.
+    pub fn of(path: &Utf8Path, root: &Utf8Path) -> Option<Self> {
+        let is_homebrew = HOMEBREW_PREFIXES.iter().any(|prefix| {
+            let prefix = root.join(prefix);
+            path.starts_with(prefix.join("Cellar")) || path.starts_with(prefix.join("opt"))
+        });
+        if is_homebrew {
+            return Some(Self::Homebrew);
+        }
+
+        let usr = root.join("usr");
+        if path.starts_with(usr.join("local")) {
+            None
+        } else if path == usr || path.starts_with(usr.join("bin")) {
+            Some(Self::System)
+        } else {
+            None
+        }
+    }
+}
+
+/// The directories of every external Ruby under `root`, which may not all be valid rubies. That's
+/// `/usr` if it has a `ruby`, and every version of Homebrew's `ruby` and `ruby@X.Y` formulae.
+pub fn external_ruby_paths(root: &Utf8Path) -> Vec<Utf8PathBuf> {
+    let mut paths = Vec::new();
+
+    let usr = root.join("usr");
+    if rv_ruby::find_ruby_executable(&usr).is_some() {
+        paths.push(usr);
+    }
+
+    for prefix in HOMEBREW_PREFIXES {
+        let cellar = root.join(prefix).join("Cellar");
+        let Ok(formulae) = cellar.read_dir_utf8() else {
+            continue;
+        };
+        let mut versions: Vec<Utf8PathBuf> = formulae
+            .flatten()
+            .filter(|formula| {
+                let name = formula.file_name();
+                name == "ruby" || name.starts_with("ruby@")
+            })
+            .filter_map(|formula| formula.path().read_dir_utf8().ok())
+            .flat_map(|versions| versions.flatten().map(|version| version.into_path()))
+            .filter(|path| path.is_dir())
+            .collect();
+        versions.sort();
+        paths.extend(versions);
+    }
+
+    paths
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_external_kind() {
+        let root = Utf8Path::new("/");
+        let kind = |path: &str| ExternalKind::of(Utf8Path::new(path), root);
+
+        assert_eq!(kind("/usr"), Some(ExternalKind::System));
+        assert_eq!(kind("/usr/bin/ruby"), Some(ExternalKind::System));
+        assert_eq!(
+            kind("/opt/homebrew/Cellar/ruby/3.4.1"),
+            Some(ExternalKind::Homebrew)
+        );
+        assert_eq!(
+            kind("/opt/homebrew/opt/ruby/bin/ruby"),
+            Some(ExternalKind::Homebrew)
+        );
+        assert_eq!(
+            kind("/usr/local/Cellar/ruby@3.3/3.3.6/bin/ruby"),
+            Some(ExternalKind::Homebrew)
+        );
+        assert_eq!(kind("/usr/local/bin/ruby"), None);
+        assert_eq!(kind("/opt/rubies/ruby-3.4.1"), None);
+    }
+
+    #[test]
+    fn test_external_ruby_paths() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let root = Utf8Path::from_path(temp_dir.path()).unwrap();
+        assert!(external_ruby_paths(root).is_empty());
+
+        fs_err::create_dir_all(root.join("usr/bin")).unwrap();
+        fs_err::write(root.join("usr/bin/ruby"), "").unwrap();
+        for dir in [
+            "opt/homebrew/Cellar/ruby/3.4.1",
+            "opt/homebrew/Cellar/ruby@3.3/3.3.6",
+            "opt/homebrew/Cellar/rbenv/1.3.0",
+        ] {
+            fs_err::create_dir_all(root.join(dir)).unwrap();
+        }
+
+        assert_eq!(
+            external_ruby_paths(root),
+            vec![
+                root.join("usr"),
+                root.join("opt/homebrew/Cellar/ruby/3.4.1"),
+                root.join("opt/homebrew/Cellar/ruby@3.3/3.3.6"),
+            ]
+        );
+    }
+}
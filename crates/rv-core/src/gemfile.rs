@@ -0,0 +1,139 @@
+//! Reads the `ruby` directive out of a Gemfile, e.g.
+//! `ruby "3.3.0", engine: "jruby", engine_version: "9.4.5.0"`.
+//!
+//! This isn't a Ruby parser. It only understands the literal forms of the directive that Bundler
+//! documents.
+
+use std::str::FromStr;
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use rv_ruby::request::{RequestError, RubyRequest};
+
+/// Matches a string argument, optionally preceded by a keyword (`engine: "x"` or `:engine => "x"`).
+pub static ARGUMENT_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"(?:(\w+):\s*|:(\w+)\s*=>\s*)?["']([^"']*)["']"#).expect("valid regex")
+});
+
+#[derive(Debug, Default, PartialEq, Eq)]
+struct RubyDirective<'a> {
+    versions: Vec<&'a str>,
+    engine: Option<&'a str>,
+    engine_version: Option<&'a str>,
+}
+
+/// Find the ruby requested by the Gemfile's `ruby` directive, if it has one.
+pub fn ruby_request(gemfile: &str) -> Option<Result<RubyRequest, RequestError>> {
+    let directive = gemfile.lines().find_map(parse_directive)?;
+
+    let request = match directive.engine {
+        Some(engine) if engine != "ruby" => match directive.engine_version {
+            Some(engine_version) => format!("{engine}-{engine_version}"),
+            None => engine.to_string(),
+        },
+        _ if directive.versions.is_empty() => return None,
+        _ => directive.versions.join(", "),
+    };
+
+    Some(RubyRequest::from_str(&request))
+}
+
+/// `line` without its trailing `#` comment, if it has one. A `#` inside a string, like the one in
+/// `"#{ENV['RUBY']}"`, doesn't start a comment.
+pub fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match quote {
+            Some(_) if escaped => escaped = false,
+            Some(_) if c == '\\' => escaped = true,
+            Some(open) if c == open => quote = None,
+            Some(_) => {}
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if c == '#' => return &line[..i],
+            None => {}
+        }
+    }
+    line
+}
+
+fn parse_directive(line: &str) -> Option<RubyDirective<'_>> {
+    let args = strip_comment(line).trim().strip_prefix("ruby")?;
+    if !args.starts_with([' ', '\t', '(']) {
+        return None;
+    }
+
+    let mut directive = RubyDirective::default();
+    for captures in ARGUMENT_REGEX.captures_iter(args) {
+        let key = captures.get(1).or_else(|| captures.get(2));
+        let value = captures.get(3).map_or("", |value| value.as_str());
+        match key.map(|key| key.as_str()) {
+            None => directive.versions.push(value),
+            Some("engine") => directive.engine = Some(value),
+            Some("engine_version") => directive.engine_version = Some(value),
+            // `file:` points at a .ruby-version or .tool-versions, which we read ourselves, and
+            // `patchlevel:` can't be used to pick a ruby.
+            Some(_) => {}
+        }
+    }
+
+    Some(directive)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[track_caller]
+    fn request(gemfile: &str) -> Option<String> {
+        ruby_request(gemfile).map(|request| request.unwrap().to_string())
+    }
+
+    #[test]
+    fn test_plain_version() {
+        let gemfile = "source \"https://rubygems.org\"\n\nruby \"3.3.0\"\ngem \"rails\"\n";
+        assert_eq!(request(gemfile).as_deref(), Some("ruby-3.3.0"));
+    }
+
+    #[test]
+    fn test_engine_and_engine_version() {
+        let gemfile = r#"ruby "3.1.4", engine: "jruby", engine_version: "9.4.5.0""#;
+        assert_eq!(request(gemfile).as_deref(), Some("jruby-9.4.5.0"));
+
+        let gemfile = r#"ruby '3.1.4', :engine => 'jruby', :engine_version => '9.4.5.0'"#;
+        assert_eq!(request(gemfile).as_deref(), Some("jruby-9.4.5.0"));
+    }
+
+    #[test]
+    fn test_engine_without_engine_version() {
+        let gemfile = r#"ruby "3.3.0", engine: "truffleruby""#;
+        assert_eq!(request(gemfile).as_deref(), Some("truffleruby"));
+    }
+
+    #[test]
+    fn test_requirements() {
+        assert_eq!(request(r#"ruby "~> 3.3""#).as_deref(), Some("~> 3.3"));
+        assert_eq!(
+            request(r#"ruby(">= 3.2", "< 3.4")"#).as_deref(),
+            Some(">= 3.2, < 3.4")
+        );
+    }
+
+    #[test]
+    fn test_comments() {
+        let gemfile = r#"ruby "3.3.0" # was "3.2.0", engine: "jruby""#;
+        assert_eq!(request(gemfile).as_deref(), Some("ruby-3.3.0"));
+        assert_eq!(request(r#"# ruby "3.3.0""#), None);
+
+        assert_eq!(strip_comment(r#"gem "a#b" # c"#), r#"gem "a#b" "#);
+        assert_eq!(strip_comment(r#"gem 'it\'s#' # c"#), r#"gem 'it\'s#' "#);
+        assert_eq!(strip_comment("gem \"rails\""), "gem \"rails\"");
+    }
+
+    #[test]
+    fn test_no_directive() {
+        assert_eq!(request("gem \"ruby-progressbar\"\n"), None);
+        assert_eq!(request("rubygems_version = 1\n"), None);
+        assert_eq!(request("ruby file: \".ruby-version\"\n"), None);
+    }
+}
@@ -0,0 +1,293 @@
+//! Knows where rv's Ruby builds are published, and how to unpack them. Downloading them is left
+//! to the caller.
+
+use std::path::PathBuf;
+
+use camino::{Utf8Component, Utf8Path};
+use rv_platform::HostPlatform;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Zip(#[from] zip::result::ZipError),
+    #[error(transparent)]
+    SevenZip(#[from] sevenz_rust2::Error),
+    #[error("Paths that are absolute or include .. are not allowed inside archives, but found {0}")]
+    DirectoryTraversal(String),
+    #[error(transparent)]
+    UnsupportedPlatform(#[from] rv_platform::UnsupportedPlatformError),
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Where rv's build of this Ruby version for `host` is published. `RV_INSTALL_URL` overrides the
+/// server it's downloaded from.
+pub fn ruby_url(version: &str, host: &HostPlatform) -> String {
+    let download_base =
+        std::env::var("RV_INSTALL_URL").unwrap_or_else(|_| download_base_for(version, host));
+    let download_path = download_path_for(version, host);
+
+    format!("{download_base}/{download_path}")
+}
+
+fn download_base_for(version: &str, host: &HostPlatform) -> String {
+    if host.is_windows() {
+        "https://github.com/oneclick/rubyinstaller2/releases/download".to_owned()
+    } else if version == "dev" {
+        "https://github.com/spinel-coop/rv-ruby-dev/releases/latest/download".to_owned()
+    } else {
+        "https://github.com/spinel-coop/rv-ruby/releases/latest/download".to_owned()
+    }
+}
+
+fn download_path_for(version: &str, host: &HostPlatform) -> String {
+    let arch = host.ruby_arch_str();
+    let ext = host.archive_ext();
+
+    if host.is_windows() {
+        if version == "dev" {
+            // Dev builds use the rubyinstaller-head release (no revision number)
+            format!("rubyinstaller-head/rubyinstaller-head-{arch}.{ext}")
+        } else {
+            format!("RubyInstaller-{version}-1/rubyinstaller-{version}-1-{arch}.{ext}")
+        }
+    } else {
+        format!("ruby-{version}.{arch}.{ext}")
+    }
+}
+
+/// Unpack a Ruby archive, a tarball from rv or a zip or 7z from RubyInstaller, into
+/// `rubies_dir/ruby-{version}`.
+pub fn extract_ruby_archive(
+    archive_path: &Utf8Path,
+    rubies_dir: &Utf8Path,
+    version: &str,
+) -> Result<()> {
+    let host = HostPlatform::current()?;
+
+    if !rubies_dir.exists() {
+        fs_err::create_dir_all(rubies_dir)?;
+    }
+
+    // Determine archive type by extension
+    let extension = archive_path.extension().unwrap_or("");
+    match extension {
+        "zip" => extract_zip(archive_path, rubies_dir, version),
+        "7z" => extract_7z(archive_path, rubies_dir, version, &host),
+        _ => extract_tarball(archive_path, rubies_dir, version),
+    }
+}
+
+fn extract_tarball(tarball_path: &Utf8Path, rubies_dir: &Utf8Path, version: &str) -> Result<()> {
+    let tarball = fs_err::File::open(tarball_path)?;
+    let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(tarball));
+    let dst_dir: PathBuf = rubies_dir.as_std_path().join(format!("ruby-{}", version));
+    // rv's builds have the Ruby two directories deep, like `rv-ruby@3.4.1/3.4.1/bin/ruby`.
+    crate::tar_utils::unpack_tar_stripped(&mut archive, &dst_dir, 2)?;
+    Ok(())
+}
+
+fn extract_zip(zip_path: &Utf8Path, rubies_dir: &Utf8Path, version: &str) -> Result<()> {
+    let file = fs_err::File::open(zip_path)?;
+    let mut archive = zip::ZipArchive::new(file)?;
+
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)?;
+        let entry_path = entry.name().to_string();
+
+        // Normalize path: repackage RubyInstaller format to rv format
+        let path = entry_path
+            .replace(
+                &format!("rubyinstaller-{}", version),
+                &format!("ruby-{}", version),
+            )
+            .replace('\\', "/"); // Normalize Windows path separators
+
+        let is_relative = Utf8Path::new(&path)
+            .components()
+            .all(|part| matches!(part, Utf8Component::Normal(_) | Utf8Component::CurDir));
+        if path.contains("..") || !is_relative {
+            return Err(Error::DirectoryTraversal(path));
+        }
+
+        let dst = rubies_dir.join(&path);
+
+        if entry.is_dir() {
+            fs_err::create_dir_all(&dst)?;
+        } else {
+            if let Some(parent) = dst.parent() {
+                fs_err::create_dir_all(parent)?;
+            }
+            let mut outfile = fs_err::File::create(&dst)?;
+            std::io::copy(&mut entry, &mut outfile)?;
+        }
+    }
+    Ok(())
+}
+
+fn entry_extract_fn(
+    entry: &sevenz_rust2::ArchiveEntry,
+    reader: &mut dyn std::io::Read,
+    dest: &PathBuf,
+) -> std::result::Result<bool, sevenz_rust2::Error> {
+    sevenz_rust2::default_entry_extract_fn(entry, reader, dest)
+}
+
+fn extract_7z(
+    archive_path: &Utf8Path,
+    rubies_dir: &Utf8Path,
+    version: &str,
+    host: &HostPlatform,
+) -> Result<()> {
+    // Extract 7z archive to rubies_dir
+    sevenz_rust2::decompress_file_with_extract_fn(
+        archive_path.as_std_path(),
+        rubies_dir.as_std_path(),
+        entry_extract_fn,
+    )?;
+
+    // RubyInstaller2 extracts to: rubyinstaller-{request}-1-{arch}/
+    // Dev builds extract to: rubyinstaller-head-{arch}/ (no revision number)
+    // We need to rename it to: ruby-{request}/
+    let arch = host.ruby_arch_str();
+    let extracted_dir = if version == "dev" {
+        rubies_dir.join(format!("rubyinstaller-head-{arch}"))
+    } else {
+        rubies_dir.join(format!("rubyinstaller-{}-1-{arch}", version))
+    };
+    let target_dir = rubies_dir.join(format!("ruby-{}", version));
+
+    if extracted_dir.exists() {
+        fs_err::rename(&extracted_dir, &target_dir)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::TempDir;
+    use assert_fs::prelude::*;
+    use std::io::Write as _;
+
+    #[test]
+    fn test_ruby_url_unix() {
+        let host = HostPlatform::from_target_triple("aarch64-apple-darwin").unwrap();
+        let url = ruby_url("3.4.1", &host);
+
+        assert_eq!(
+            url,
+            "https://github.com/spinel-coop/rv-ruby/releases/latest/download/ruby-3.4.1.arm64_sonoma.tar.gz"
+        );
+    }
+
+    #[test]
+    fn test_ruby_url_windows() {
+        let host = HostPlatform::from_target_triple("x86_64-pc-windows-msvc").unwrap();
+        let url = ruby_url("3.4.1", &host);
+
+        assert_eq!(
+            url,
+            "https://github.com/oneclick/rubyinstaller2/releases/download/RubyInstaller-3.4.1-1/rubyinstaller-3.4.1-1-x64.7z"
+        );
+    }
+
+    #[test]
+    fn test_ruby_url_windows_arm64() {
+        let host = HostPlatform::from_target_triple("aarch64-pc-windows-msvc").unwrap();
+        let url = ruby_url("3.4.1", &host);
+
+        assert_eq!(
+            url,
+            "https://github.com/oneclick/rubyinstaller2/releases/download/RubyInstaller-3.4.1-1/rubyinstaller-3.4.1-1-arm.7z"
+        );
+    }
+
+    #[test]
+    fn test_ruby_url_unix_dev() {
+        let host = HostPlatform::from_target_triple("aarch64-apple-darwin").unwrap();
+        let url = ruby_url("dev", &host);
+
+        assert_eq!(
+            url,
+            "https://github.com/spinel-coop/rv-ruby-dev/releases/latest/download/ruby-dev.arm64_sonoma.tar.gz"
+        );
+    }
+
+    #[test]
+    fn test_ruby_url_windows_dev() {
+        let host = HostPlatform::from_target_triple("x86_64-pc-windows-msvc").unwrap();
+        let url = ruby_url("dev", &host);
+
+        assert_eq!(
+            url,
+            "https://github.com/oneclick/rubyinstaller2/releases/download/rubyinstaller-head/rubyinstaller-head-x64.7z"
+        );
+    }
+    #[test]
+    fn test_extract_zip_creates_correct_structure() {
+        let temp_dir = TempDir::new().unwrap();
+        let rubies_dir = temp_dir.child("rubies");
+        rubies_dir.create_dir_all().unwrap();
+
+        let zip_path = temp_dir.child("test-ruby.zip");
+        {
+            let file = std::fs::File::create(zip_path.path()).unwrap();
+            let mut zip = zip::ZipWriter::new(file);
+
+            let options: zip::write::SimpleFileOptions = Default::default();
+            zip.add_directory::<_, ()>("rubyinstaller-3.4.1/", options)
+                .unwrap();
+            zip.add_directory::<_, ()>("rubyinstaller-3.4.1/bin/", options)
+                .unwrap();
+
+            zip.start_file("rubyinstaller-3.4.1/bin/ruby.exe", options)
+                .unwrap();
+            zip.write_all(b"fake ruby executable").unwrap();
+
+            zip.finish().unwrap();
+        }
+
+        let rubies_path = Utf8Path::from_path(rubies_dir.path()).unwrap();
+        let zip_utf8_path = Utf8Path::from_path(zip_path.path()).unwrap();
+        extract_zip(zip_utf8_path, rubies_path, "3.4.1").unwrap();
+
+        let ruby_dir = rubies_dir.child("ruby-3.4.1");
+        assert!(ruby_dir.exists(), "ruby-3.4.1 directory should exist");
+
+        let bin_dir = ruby_dir.child("bin");
+        assert!(bin_dir.exists(), "bin directory should exist");
+
+        let ruby_exe = bin_dir.child("ruby.exe");
+        assert!(ruby_exe.exists(), "ruby.exe should exist");
+
+        let content = std::fs::read_to_string(ruby_exe.path()).unwrap();
+        assert_eq!(content, "fake ruby executable");
+    }
+
+    #[test]
+    fn test_extract_ruby_archive_delegates_to_zip_extractor() {
+        let temp_dir = TempDir::new().unwrap();
+        let rubies_dir = temp_dir.child("rubies");
+        rubies_dir.create_dir_all().unwrap();
+
+        let zip_path = temp_dir.child("test.zip");
+        {
+            let file = std::fs::File::create(zip_path.path()).unwrap();
+            let mut zip = zip::ZipWriter::new(file);
+            let options: zip::write::SimpleFileOptions = Default::default();
+            zip.add_directory::<_, ()>("rubyinstaller-3.4.1/", options)
+                .unwrap();
+            zip.finish().unwrap();
+        }
+
+        let rubies_path = Utf8Path::from_path(rubies_dir.path()).unwrap();
+        let zip_utf8_path = Utf8Path::from_path(zip_path.path()).unwrap();
+
+        let result = extract_ruby_archive(zip_utf8_path, rubies_path, "3.4.1");
+        assert!(result.is_ok());
+    }
+}
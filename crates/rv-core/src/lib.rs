@@ -0,0 +1,51 @@
+//! The parts of rv that find, install and activate Ruby versions, as a library.
+//!
+//! Everything here returns structured results, and nothing prints or draws progress bars, so
+//! other tools can embed rv's behaviour. The `rv` command line is built on top of it.
+//!
+//! - [`request`] works out which Ruby a project asks for, from `.ruby-version`,
+//!   `.tool-versions`, `Gemfile.lock` or the Gemfile's `ruby` directive.
+//! - [`gemfile`] reads the `ruby` directive out of a Gemfile.
+//! - [`discovery`] lists the rubies installed in a set of directories, and picks the best one for
+//!   a request.
+//! - [`external`] finds the rubies the operating system or Homebrew installed, which rv lists
+//!   but doesn't use.
+//! - [`install`] knows where rv's Ruby builds are published, and unpacks their archives.
+//! - [`tar_utils`] unpacks tarballs without letting their entries escape the destination.
+//! - [`provenance`] records where an installed Ruby came from.
+//! - [`linked`] keeps track of rubies installed elsewhere that rv should find anyway.
+//! - [`env`] computes the environment variables that activate a Ruby.
+//!
+//! ```no_run
+//! use camino::{Utf8Path, Utf8PathBuf};
+//! use rv_core::{discovery::RubyDirs, env::EnvOptions, request::RequestedRuby};
+//!
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let home = rv_dirs::home_dir();
+//! let project = Utf8PathBuf::from("/src/my-app");
+//! let requested = RequestedRuby::find(&home, &project, Utf8Path::new("/"))?;
+//!
+//! let ruby_dirs = rv_dirs::default_ruby_dirs(Utf8Path::new("/")).into_iter().collect();
+//! let cache = rv_cache::Cache::temp()?;
+//! let rubies = RubyDirs::new(&ruby_dirs, &cache);
+//! if let Some(ruby) = rubies.highest_matching(&requested.ruby_request()) {
+//!     let env = rv_core::env::env_for(Some(&ruby), EnvOptions::default())?;
+//!     for (var, value) in env.set() {
+//!         println!("{var}={value}");
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod discovery;
+pub mod env;
+pub mod external;
+pub mod gemfile;
+pub mod install;
+pub mod linked;
+pub mod provenance;
+pub mod request;
+pub mod tar_utils;
+
+pub use rv_ruby::{Ruby, request::RubyRequest, version::RubyVersion};
@@ -0,0 +1,85 @@
+//! The registry of rubies that were built or installed outside of rv, and linked with
+//! `rv ruby link` so that rv finds them wherever they are.
+
+use camino::{Utf8Path, Utf8PathBuf};
+use serde::{Deserialize, Serialize};
+
+/// The name of the registry file, in rv's data directory.
+pub const FILE_NAME: &str = "linked-rubies.json";
+
+/// The rubies linked into rv, by the path of each one's directory.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LinkedRubies {
+    rubies: Vec<Utf8PathBuf>,
+}
+
+impl LinkedRubies {
+    /// The registry in `data_dir`, which is empty if nothing was ever linked.
+    pub fn load(data_dir: &Utf8Path) -> std::io::Result<Self> {
+        let path = data_dir.join(FILE_NAME);
+        match fs_err::read_to_string(&path) {
+            Ok(text) => Ok(serde_json::from_str(&text)?),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Write the registry into `data_dir`.
+    pub fn save(&self, data_dir: &Utf8Path) -> std::io::Result<()> {
+        fs_err::create_dir_all(data_dir)?;
+        let json = serde_json::to_string_pretty(self)?;
+        rv_cache::write_atomic(data_dir.join(FILE_NAME), json)
+    }
+
+    /// The directories of the linked rubies, in the order they were linked.
+    pub fn paths(&self) -> &[Utf8PathBuf] {
+        &self.rubies
+    }
+
+    /// Link the Ruby in `path`. Returns whether it wasn't linked already.
+    pub fn add(&mut self, path: Utf8PathBuf) -> bool {
+        if self.rubies.contains(&path) {
+            return false;
+        }
+        self.rubies.push(path);
+        true
+    }
+
+    /// Unlink the Ruby in `path`. Returns whether it was linked.
+    pub fn remove(&mut self, path: &Utf8Path) -> bool {
+        let len = self.rubies.len();
+        self.rubies.retain(|ruby| ruby != path);
+        self.rubies.len() != len
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_link_and_unlink() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let data_dir = Utf8Path::from_path(temp_dir.path()).unwrap().join("rv");
+
+        let mut linked = LinkedRubies::load(&data_dir).unwrap();
+        assert!(linked.paths().is_empty());
+
+        assert!(linked.add("/opt/custom/ruby-3.3".into()));
+        assert!(!linked.add("/opt/custom/ruby-3.3".into()));
+        assert!(linked.add("/opt/custom/ruby-3.4".into()));
+        linked.save(&data_dir).unwrap();
+
+        let mut linked = LinkedRubies::load(&data_dir).unwrap();
+        assert_eq!(
+            linked.paths(),
+            [
+                Utf8PathBuf::from("/opt/custom/ruby-3.3"),
+                Utf8PathBuf::from("/opt/custom/ruby-3.4")
+            ]
+        );
+        assert!(linked.remove(Utf8Path::new("/opt/custom/ruby-3.3")));
+        assert!(!linked.remove(Utf8Path::new("/opt/custom/ruby-3.3")));
+        assert_eq!(linked.paths(), [Utf8PathBuf::from("/opt/custom/ruby-3.4")]);
+    }
+}
@@ -0,0 +1,107 @@
+//! Where an installed Ruby came from. rv writes a `.rv-meta.json` into every Ruby it installs,
+//! which is also how it tells its own installations apart from rubies installed by other tools
+//! into the same directories.
+
+use std::collections::BTreeMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use camino::Utf8Path;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+/// The name of the file, inside a Ruby's directory, that its provenance is recorded in.
+pub const FILE_NAME: &str = ".rv-meta.json";
+
+/// How a Ruby was installed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Provenance {
+    /// The URL the Ruby's archive was downloaded from, or the path of the local archive.
+    pub source: String,
+    /// The SHA256 digest of the archive, as lowercase hex.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    /// When the Ruby was installed, in seconds since the Unix epoch.
+    pub installed_at: u64,
+    /// The version of rv that installed the Ruby.
+    pub rv_version: String,
+    /// The patches and `./configure` flags, for rubies built from source.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub build_flags: Vec<String>,
+    /// The gems rv updated or installed right after installing the Ruby, like `rubygems`,
+    /// `bundler` and the `default-gems` from rv's settings, and the versions it ended up with.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub default_gem_updates: BTreeMap<String, String>,
+}
+
+impl Provenance {
+    /// The provenance of a Ruby installed just now, by the given version of rv.
+    pub fn new(source: impl Into<String>, sha256: Option<String>, rv_version: &str) -> Self {
+        let installed_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+        Self {
+            source: source.into(),
+            sha256,
+            installed_at,
+            rv_version: rv_version.to_owned(),
+            build_flags: Vec::new(),
+            default_gem_updates: BTreeMap::new(),
+        }
+    }
+
+    /// The recorded provenance of the Ruby in `ruby_dir`, if rv installed it. A file that can't
+    /// be read is treated like a missing one, since it can't be trusted either.
+    pub fn read(ruby_dir: &Utf8Path) -> Option<Self> {
+        let path = ruby_dir.join(FILE_NAME);
+        let text = fs_err::read_to_string(&path).ok()?;
+        serde_json::from_str(&text)
+            .inspect_err(|err| debug!("Ignoring invalid {path}: {err}"))
+            .ok()
+    }
+
+    /// Record this as the provenance of the Ruby in `ruby_dir`.
+    pub fn write(&self, ruby_dir: &Utf8Path) -> std::io::Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        fs_err::write(ruby_dir.join(FILE_NAME), json)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use camino::Utf8PathBuf;
+
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = Utf8PathBuf::from_path_buf(dir.path().to_path_buf()).unwrap();
+        assert_eq!(Provenance::read(&dir), None);
+
+        let mut provenance = Provenance::new(
+            "https://example.com/ruby-3.4.1.tar.gz",
+            Some("abc123".into()),
+            "0.6.0",
+        );
+        provenance.build_flags = vec!["--disable-install-doc".into()];
+        provenance
+            .default_gem_updates
+            .insert("rubygems".into(), "3.6.9".into());
+        provenance.write(&dir).unwrap();
+        assert_eq!(Provenance::read(&dir), Some(provenance));
+
+        fs_err::write(dir.join(FILE_NAME), "{").unwrap();
+        assert_eq!(Provenance::read(&dir), None);
+    }
+
+    #[test]
+    fn test_optional_fields() {
+        let provenance: Provenance = serde_json::from_str(
+            r#"{"source": "/tmp/ruby.tar.gz", "installed_at": 1, "rv_version": "0.6.0"}"#,
+        )
+        .unwrap();
+        assert_eq!(provenance.sha256, None);
+        assert!(provenance.build_flags.is_empty());
+        assert!(provenance.default_gem_updates.is_empty());
+    }
+}
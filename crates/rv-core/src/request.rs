@@ -0,0 +1,378 @@
+//! Works out which Ruby was asked for: explicitly, by a version file in the project, by one in
+//! the home directory, or not at all.
+
+use camino::{Utf8Path, Utf8PathBuf};
+use rv_ruby::{
+    engine::RubyEngine,
+    request::{RequestError, RubyRequest, Source},
+};
+use tracing::{debug, warn};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Request(#[from] RequestError),
+}
+
+/// The Ruby a command should use, and where that choice came from.
+#[derive(Debug, Clone)]
+pub enum RequestedRuby {
+    Explicit(RubyRequest),
+    Project((RubyRequest, Source)),
+    User((RubyRequest, Source)),
+    Global,
+}
+
+impl RequestedRuby {
+    /// Find the requested Ruby. Without an explicit request, look for one of `version_files`
+    /// in `current_dir` and its ancestors, at most `max_depth` of them (see
+    /// [`project_search_dirs`]), then in `home_dir`.
+    pub fn new(
+        request: Option<RubyRequest>,
+        home_dir: &Utf8PathBuf,
+        current_dir: &Utf8Path,
+        root: &Utf8Path,
+        version_files: &[VersionFile],
+        max_depth: Option<usize>,
+    ) -> Result<Self, Error> {
+        let requested_ruby = match request {
+            Some(req) => {
+                debug!("Explicit ruby request for {} received", req);
+                Self::Explicit(req)
+            }
+            None => {
+                let mut project_request = None;
+                for dir in project_search_dirs(current_dir, home_dir, root, max_depth) {
+                    if let Some(req) = find_directory_ruby(&dir, version_files)? {
+                        project_request = Some(req);
+                        break;
+                    }
+                    debug!("No ruby version request found in {}", dir);
+                }
+
+                if let Some(req) = project_request {
+                    debug!("Found project ruby request for {} in {:?}", req.0, req.1);
+                    Self::Project(req)
+                } else if let Some(req) = find_directory_ruby(home_dir, version_files)? {
+                    debug!("Found user ruby request for {} in {:?}", req.0, req.1);
+                    Self::User(req)
+                } else {
+                    Self::Global
+                }
+            }
+        };
+
+        Ok(requested_ruby)
+    }
+
+    /// Look for a version file, like [`RequestedRuby::new`] without an explicit request, reading
+    /// them in the default order and searching every ancestor.
+    pub fn find(
+        home_dir: &Utf8PathBuf,
+        current_dir: &Utf8Path,
+        root: &Utf8Path,
+    ) -> Result<Self, Error> {
+        Self::new(
+            None,
+            home_dir,
+            current_dir,
+            root,
+            &VersionFile::DEFAULT_ORDER,
+            None,
+        )
+    }
+
+    /// The request itself, where the latest Ruby stands in for no request at all.
+    pub fn ruby_request(&self) -> RubyRequest {
+        match self {
+            Self::Explicit(request) => request.clone(),
+            Self::Project((request, _)) => request.clone(),
+            Self::User((request, _)) => request.clone(),
+            Self::Global => RubyRequest::default(),
+        }
+    }
+
+    /// A line for `rv ruby list` saying why this Ruby is the default.
+    pub fn explain(&self, installed: bool) -> String {
+        match self {
+            Self::Explicit(_) => "* Default version explicitly selected".to_string(),
+            Self::Project((_, source)) => format!(
+                "* Default version pinned by {}",
+                rv_dirs::relativize(source.path())
+            ),
+            Self::User((_, source)) => format!(
+                "* Default version pinned by {}",
+                rv_dirs::unexpand(source.path())
+            ),
+            Self::Global => {
+                let installed_or_available = if installed { "installed" } else { "available" };
+                format!("* Default version is the latest {installed_or_available}")
+            }
+        }
+    }
+}
+
+/// The names of the files that can pin a Ruby, in the order [`find_directory_ruby`] reads them
+/// by default.
+pub const VERSION_FILES: [&str; 4] = [".ruby-version", ".tool-versions", "Gemfile.lock", "Gemfile"];
+
+/// A file that can pin a Ruby.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VersionFile {
+    /// `.ruby-version`, as read by rbenv, chruby and most other version managers.
+    RubyVersion,
+    /// The `ruby` line of asdf's and mise's `.tool-versions`.
+    ToolVersions,
+    /// The ruby locked in `Gemfile.lock`.
+    GemfileLock,
+    /// The Gemfile's `ruby` directive.
+    Gemfile,
+}
+
+impl VersionFile {
+    /// The order version files are read in, unless configured otherwise.
+    pub const DEFAULT_ORDER: [Self; 4] = [
+        Self::RubyVersion,
+        Self::ToolVersions,
+        Self::GemfileLock,
+        Self::Gemfile,
+    ];
+
+    /// The name of the file.
+    pub fn file_name(self) -> &'static str {
+        match self {
+            Self::RubyVersion => ".ruby-version",
+            Self::ToolVersions => ".tool-versions",
+            Self::GemfileLock => "Gemfile.lock",
+            Self::Gemfile => "Gemfile",
+        }
+    }
+
+    /// The version file called `name`, if there's one.
+    pub fn from_file_name(name: &str) -> Option<Self> {
+        Self::DEFAULT_ORDER
+            .into_iter()
+            .find(|file| file.file_name() == name)
+    }
+}
+
+/// Every file that could decide which Ruby to use in `current_dir`, whether it exists or not, so
+/// that tools can watch them for changes. `max_depth` limits the search like it does for
+/// [`RequestedRuby::new`].
+pub fn version_files(
+    home_dir: &Utf8Path,
+    current_dir: &Utf8Path,
+    root: &Utf8Path,
+    max_depth: Option<usize>,
+) -> Vec<Utf8PathBuf> {
+    project_search_dirs(current_dir, home_dir, root, max_depth)
+        .iter()
+        .map(Utf8PathBuf::as_path)
+        .chain([home_dir])
+        .flat_map(|dir| VERSION_FILES.map(|file| dir.join(file)))
+        .collect()
+}
+
+/// The directories where a version file can pin the project's Ruby, nearest first: `start` and
+/// its ancestors, like rbenv. The search stops at the repository root (a directory containing
+/// `.git`) or the project root (a directory containing `Gemfile.lock`), before the home directory
+/// (whose version file is the user's default instead), at `root`, or after `max_depth`
+/// directories.
+pub fn project_search_dirs(
+    start: &Utf8Path,
+    home_dir: &Utf8Path,
+    root: &Utf8Path,
+    max_depth: Option<usize>,
+) -> Vec<Utf8PathBuf> {
+    let mut dirs = vec![];
+    for dir in start.ancestors() {
+        if dir == home_dir || Some(dir) == root.parent() || max_depth == Some(dirs.len()) {
+            break;
+        }
+        dirs.push(dir.to_path_buf());
+        if dir.join(".git").exists() || dir.join("Gemfile.lock").is_file() {
+            break;
+        }
+    }
+    dirs
+}
+
+/// The Ruby pinned by the first of `version_files` in `dir` that pins one. By default, that's
+/// `.ruby-version`, `.tool-versions`, the ruby locked in `Gemfile.lock`, or the Gemfile's `ruby`
+/// directive, in that order.
+pub fn find_directory_ruby(
+    dir: &Utf8Path,
+    version_files: &[VersionFile],
+) -> Result<Option<(RubyRequest, Source)>, Error> {
+    for &version_file in version_files {
+        if let Some(found) = read_version_file(dir, version_file)? {
+            return Ok(Some(found));
+        }
+    }
+
+    Ok(None)
+}
+
+/// The Ruby pinned by `version_file` in `dir`, if it exists and pins one.
+fn read_version_file(
+    dir: &Utf8Path,
+    version_file: VersionFile,
+) -> Result<Option<(RubyRequest, Source)>, Error> {
+    let path = dir.join(version_file.file_name());
+    if !path.exists() {
+        return Ok(None);
+    }
+    let contents = std::fs::read_to_string(&path)?;
+
+    match version_file {
+        VersionFile::RubyVersion => Ok(Some((contents.parse()?, Source::DotRubyVersion(path)))),
+        VersionFile::ToolVersions => match tool_versions_ruby(&contents) {
+            Some(version) => Ok(Some((version.parse()?, Source::DotToolVersions(path)))),
+            None => Ok(None),
+        },
+        VersionFile::GemfileLock => {
+            // Normalize Windows line endings (CRLF) to Unix (LF) for the parser
+            let lockfile_contents = rv_lockfile::normalize_line_endings(&contents);
+
+            let Ok(parsed_lockfile) = rv_lockfile::parse(&lockfile_contents) else {
+                debug!(
+                    "Ignoring {} while discovering ruby version to use because it could not be parsed",
+                    path
+                );
+                return Ok(None);
+            };
+            let Some(lockfile_ruby) = parsed_lockfile.ruby_version else {
+                return Ok(None);
+            };
+
+            // Rubies other than CRuby are locked as e.g. `ruby 3.1.4p0 (jruby 9.4.5.0)`,
+            // and it's the engine's own version that picks the ruby to use.
+            let version = match lockfile_ruby.engine_version {
+                Some(engine_version) if engine_version.engine != RubyEngine::Ruby => engine_version,
+                _ => lockfile_ruby.cruby_version,
+            };
+            Ok(Some((version.into(), Source::GemfileLock(path))))
+        }
+        VersionFile::Gemfile => match crate::gemfile::ruby_request(&contents) {
+            Some(Ok(request)) => Ok(Some((request, Source::Gemfile(path)))),
+            Some(Err(err)) => {
+                warn!(
+                    "Ignoring the ruby directive in {path}, because it could not be parsed: {err}"
+                );
+                Ok(None)
+            }
+            None => Ok(None),
+        },
+    }
+}
+
+/// The version on the `ruby` line of a `.tool-versions` file. asdf lists fallbacks after the
+/// preferred version, and `system`, `ref:` and `path:` versions aren't ones rv can pick, so this
+/// is the first version that isn't one of those.
+fn tool_versions_ruby(contents: &str) -> Option<&str> {
+    contents.lines().find_map(|line| {
+        let line = line.split('#').next().unwrap_or_default();
+        let mut words = line.split_whitespace();
+        if words.next() != Some("ruby") {
+            return None;
+        }
+        words.find(|version| {
+            *version != "system" && !version.starts_with("ref:") && !version.starts_with("path:")
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::TempDir;
+
+    #[test]
+    fn test_project_search_dirs_stops_at_project_root() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = Utf8Path::from_path(temp_dir.path()).unwrap();
+        let project = root.join("home/me/app");
+        let nested = project.join("lib/app");
+        fs_err::create_dir_all(&nested).unwrap();
+        fs_err::write(project.join("Gemfile.lock"), "").unwrap();
+
+        let home = root.join("home/me");
+        let dirs = project_search_dirs(&nested, &home, root, None);
+        assert_eq!(
+            dirs,
+            vec![nested.clone(), project.join("lib"), project.clone()]
+        );
+
+        let dirs = project_search_dirs(&nested, &home, root, Some(1));
+        assert_eq!(dirs, vec![nested]);
+
+        let files = version_files(&home, &project, root, None);
+        assert_eq!(files.len(), 2 * VERSION_FILES.len());
+        assert_eq!(files[0], project.join(".ruby-version"));
+        assert_eq!(files.last(), Some(&home.join("Gemfile")));
+    }
+
+    #[test]
+    fn test_find_directory_ruby_prefers_ruby_version() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = Utf8Path::from_path(temp_dir.path()).unwrap();
+        let order = VersionFile::DEFAULT_ORDER;
+        assert!(find_directory_ruby(dir, &order).unwrap().is_none());
+
+        fs_err::write(dir.join("Gemfile"), "ruby \"3.3.0\"\n").unwrap();
+        let (request, source) = find_directory_ruby(dir, &order).unwrap().unwrap();
+        assert_eq!(request.to_string(), "ruby-3.3.0");
+        assert!(matches!(source, Source::Gemfile(_)));
+
+        fs_err::write(dir.join(".ruby-version"), "3.4.1\n").unwrap();
+        let (request, source) = find_directory_ruby(dir, &order).unwrap().unwrap();
+        assert_eq!(request.to_string(), "ruby-3.4.1");
+        assert!(matches!(source, Source::DotRubyVersion(_)));
+    }
+
+    #[test]
+    fn test_find_directory_ruby_ignores_unparseable_gemfile_directive() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = Utf8Path::from_path(temp_dir.path()).unwrap();
+        fs_err::write(dir.join("Gemfile"), "ruby \">= three\"\n").unwrap();
+
+        let order = VersionFile::DEFAULT_ORDER;
+        assert!(find_directory_ruby(dir, &order).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_find_directory_ruby_in_configured_order() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = Utf8Path::from_path(temp_dir.path()).unwrap();
+        fs_err::write(dir.join(".ruby-version"), "3.4.1\n").unwrap();
+        fs_err::write(dir.join(".tool-versions"), "nodejs 22.1.0\nruby 3.2.4\n").unwrap();
+        fs_err::write(dir.join("Gemfile"), "ruby \"3.3.0\"\n").unwrap();
+
+        let order = [VersionFile::ToolVersions, VersionFile::RubyVersion];
+        let (request, source) = find_directory_ruby(dir, &order).unwrap().unwrap();
+        assert_eq!(request.to_string(), "ruby-3.2.4");
+        assert!(matches!(source, Source::DotToolVersions(_)));
+
+        // Files left out of the order aren't read at all
+        let (request, source) = find_directory_ruby(dir, &[VersionFile::Gemfile])
+            .unwrap()
+            .unwrap();
+        assert_eq!(request.to_string(), "ruby-3.3.0");
+        assert!(matches!(source, Source::Gemfile(_)));
+        assert!(find_directory_ruby(dir, &[]).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_tool_versions_ruby() {
+        assert_eq!(tool_versions_ruby("ruby 3.2.4\n"), Some("3.2.4"));
+        assert_eq!(
+            tool_versions_ruby("nodejs 22.1.0\n  ruby\t3.2.4 3.1.6 # fallback\n"),
+            Some("3.2.4")
+        );
+        assert_eq!(tool_versions_ruby("ruby system 3.3.0\n"), Some("3.3.0"));
+        assert_eq!(tool_versions_ruby("# ruby 3.2.4\nnodejs 22.1.0\n"), None);
+        assert_eq!(tool_versions_ruby("rubyx 3.2.4\nruby system\n"), None);
+    }
+}
@@ -0,0 +1,778 @@
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+
+/// Windows error code for "A required privilege is not held by the client."
+/// Symlink creation requires either Developer Mode or admin privileges.
+#[cfg(windows)]
+const ERROR_PRIVILEGE_NOT_HELD: i32 = 1314;
+
+/// Unpack a tar archive to `dst`, falling back to file copies when symlink
+/// creation fails on Windows (requires Developer Mode or admin privileges).
+///
+/// Archives come from gem servers and mirrors, so nothing in them is trusted: see
+/// [`unpack_tar_stripped`] for what's refused.
+pub fn unpack_tar<R: Read>(archive: &mut tar::Archive<R>, dst: &Path) -> io::Result<()> {
+    unpack_tar_stripped(archive, dst, 0)
+}
+
+/// Unpack a tar archive to `dst`, dropping the first `strip_components` directories of every
+/// path like `tar --strip-components` does. Entries are written as they're read from the
+/// archive, so it's never buffered in memory.
+///
+/// Anything that would write outside of `dst` is an error: absolute paths, paths with `..`,
+/// symlinks pointing outside of it, hard links to files outside of it, and paths going through
+/// symlinks. So are device nodes and FIFOs. Files are owned by the current user, whatever the
+/// archive says, and get `0644` or `0755` permissions, without setuid, setgid or sticky bits.
+pub fn unpack_tar_stripped<R: Read>(
+    archive: &mut tar::Archive<R>,
+    dst: &Path,
+    strip_components: usize,
+) -> io::Result<()> {
+    // Symlinks are made once everything else is unpacked, so that no entry is written through
+    // one, and so that their targets exist on Windows, where they may need copying instead.
+    let mut deferred_symlinks: Vec<(PathBuf, PathBuf)> = Vec::new();
+
+    for entry_result in archive.entries()? {
+        unpack_entry_in(
+            &mut entry_result?,
+            dst,
+            strip_components,
+            &mut deferred_symlinks,
+        )?;
+    }
+    create_symlinks(deferred_symlinks)
+}
+
+/// Unpack a single tar entry into the directory `dst`, with the same checks as [`unpack_tar`].
+/// Its symlink target, if it's a symlink, must already be unpacked for the fallback to copying it
+/// on Windows.
+pub fn unpack_entry<R: Read>(entry: &mut tar::Entry<'_, R>, dst: &Path) -> io::Result<()> {
+    let mut symlinks = Vec::new();
+    unpack_entry_in(entry, dst, 0, &mut symlinks)?;
+    create_symlinks(symlinks)
+}
+
+/// Unpack `entry` into `dst`, except for symlinks, which are added to `deferred_symlinks` as
+/// where they go and what they point to.
+fn unpack_entry_in<R: Read>(
+    entry: &mut tar::Entry<'_, R>,
+    dst: &Path,
+    strip_components: usize,
+    deferred_symlinks: &mut Vec<(PathBuf, PathBuf)>,
+) -> io::Result<()> {
+    let entry_path = entry.path()?.into_owned();
+    let Some(relative) = sanitized_path(&entry_path, strip_components)? else {
+        return Ok(());
+    };
+    let dest_path = dst.join(&relative);
+    create_parent_dirs(dst, &relative)?;
+
+    let entry_type = entry.header().entry_type();
+    match entry_type {
+        tar::EntryType::Directory => {
+            if fs_err::symlink_metadata(&dest_path).is_ok_and(|m| !m.is_dir()) {
+                fs_err::remove_file(&dest_path)?;
+            }
+            fs_err::create_dir_all(&dest_path)?;
+            set_mode(&dest_path, 0o755)?;
+        }
+        tar::EntryType::Regular | tar::EntryType::Continuous | tar::EntryType::GNUSparse => {
+            let mode = normalized_mode(entry.header().mode().unwrap_or(0o644));
+            let mtime = entry.header().mtime().ok();
+            remove_existing(&dest_path)?;
+            let mut file = fs_err::File::create(&dest_path)?;
+            io::copy(entry, &mut file)?;
+            if let Some(mtime) = mtime {
+                let mtime = std::time::UNIX_EPOCH + std::time::Duration::from_secs(mtime);
+                file.file().set_modified(mtime)?;
+            }
+            drop(file);
+            set_mode(&dest_path, mode)?;
+        }
+        tar::EntryType::Symlink => {
+            let link_target = link_name(entry, &entry_path)?;
+            check_symlink_target(&relative, &link_target)?;
+            deferred_symlinks.push((dest_path, link_target));
+        }
+        tar::EntryType::Link => {
+            let link_target = link_name(entry, &entry_path)?;
+            let Some(source) = sanitized_path(&link_target, strip_components)? else {
+                return Err(invalid_entry(&entry_path, "is a hard link to nothing"));
+            };
+            check_no_symlinks(dst, &source)?;
+            let source_path = dst.join(&source);
+            if !fs_err::symlink_metadata(&source_path).is_ok_and(|m| m.is_file()) {
+                return Err(invalid_entry(
+                    &entry_path,
+                    "is a hard link to something that isn't a file unpacked before it",
+                ));
+            }
+            remove_existing(&dest_path)?;
+            if std::fs::hard_link(&source_path, &dest_path).is_err() {
+                fs_err::copy(&source_path, &dest_path)?;
+            }
+        }
+        tar::EntryType::Char | tar::EntryType::Block | tar::EntryType::Fifo => {
+            return Err(invalid_entry(&entry_path, "is a device node or FIFO"));
+        }
+        other => {
+            tracing::debug!(
+                "Skipping {} in archive, an entry of type {other:?}",
+                entry_path.display()
+            );
+        }
+    }
+    Ok(())
+}
+
+fn create_symlinks(symlinks: Vec<(PathBuf, PathBuf)>) -> io::Result<()> {
+    for (dest_path, link_target) in symlinks {
+        remove_existing(&dest_path)?;
+        create_symlink(&link_target, &dest_path)?;
+    }
+    Ok(())
+}
+
+/// `path` from an archive, without its first `strip_components` directories, or `None` if
+/// nothing's left of it. Absolute paths and paths with `..` are errors.
+fn sanitized_path(path: &Path, strip_components: usize) -> io::Result<Option<PathBuf>> {
+    let mut sanitized = PathBuf::new();
+    let mut stripped = 0;
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::Prefix(_) | Component::RootDir => {
+                return Err(invalid_entry(path, "is an absolute path"));
+            }
+            Component::ParentDir => {
+                return Err(invalid_entry(path, "has `..` in it"));
+            }
+            Component::Normal(_) if stripped < strip_components => stripped += 1,
+            Component::Normal(part) => sanitized.push(part),
+        }
+    }
+    Ok((!sanitized.as_os_str().is_empty()).then_some(sanitized))
+}
+
+/// Check that the symlink at `link`, relative to the destination, points somewhere inside of
+/// it. `..` may only start the target, since after another component it could be going back up
+/// from a symlink, rather than from the directory it's in.
+fn check_symlink_target(link: &Path, target: &Path) -> io::Result<()> {
+    let escapes = || {
+        invalid_entry(
+            link,
+            &format!(
+                "is a symlink to {}, outside of the archive",
+                target.display()
+            ),
+        )
+    };
+    let mut depth = link.components().count().saturating_sub(1);
+    let mut past_parents = false;
+    for component in target.components() {
+        match component {
+            Component::CurDir => {}
+            Component::Prefix(_) | Component::RootDir => return Err(escapes()),
+            Component::ParentDir if past_parents || depth == 0 => return Err(escapes()),
+            Component::ParentDir => depth -= 1,
+            Component::Normal(_) => past_parents = true,
+        }
+    }
+    Ok(())
+}
+
+/// Check that none of the directories `relative` goes through inside `dst` is a symlink.
+fn check_no_symlinks(dst: &Path, relative: &Path) -> io::Result<()> {
+    let mut path = dst.to_path_buf();
+    let mut components = relative.components().peekable();
+    while let Some(component) = components.next() {
+        if components.peek().is_none() {
+            break;
+        }
+        path.push(component);
+        if fs_err::symlink_metadata(&path).is_ok_and(|m| m.file_type().is_symlink()) {
+            return Err(invalid_entry(relative, "goes through a symlink"));
+        }
+    }
+    Ok(())
+}
+
+/// Create the directories `relative` is in inside `dst`, refusing to go through symlinks.
+fn create_parent_dirs(dst: &Path, relative: &Path) -> io::Result<()> {
+    check_no_symlinks(dst, relative)?;
+    if let Some(parent) = dst.join(relative).parent() {
+        fs_err::create_dir_all(parent)?;
+    }
+    Ok(())
+}
+
+/// Remove whatever is at `path` that isn't a directory, like a file or symlink from an earlier
+/// install, so that it's replaced rather than written through.
+fn remove_existing(path: &Path) -> io::Result<()> {
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if !metadata.is_dir() => fs_err::remove_file(path),
+        _ => Ok(()),
+    }
+}
+
+fn link_name<R: Read>(entry: &tar::Entry<'_, R>, entry_path: &Path) -> io::Result<PathBuf> {
+    entry
+        .link_name()?
+        .map(|link| link.into_owned())
+        .ok_or_else(|| invalid_entry(entry_path, "is a link without a target"))
+}
+
+fn invalid_entry(path: &Path, problem: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("{} in the archive {problem}", path.display()),
+    )
+}
+
+/// `0755` for executables, and `0644` for everything else.
+fn normalized_mode(mode: u32) -> u32 {
+    if mode & 0o111 != 0 { 0o755 } else { 0o644 }
+}
+
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs_err::set_permissions(path, std::fs::Permissions::from_mode(mode))
+}
+
+#[cfg(not(unix))]
+fn set_mode(_path: &Path, _mode: u32) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(unix)]
+fn create_symlink(link_target: &Path, dst: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(link_target, dst)
+}
+
+#[cfg(windows)]
+fn create_symlink(link_target: &Path, dst: &Path) -> io::Result<()> {
+    let parent = dst.parent().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("path {} has no parent directory", dst.display()),
+        )
+    })?;
+    // Resolve the symlink target relative to the symlink's parent directory.
+    let resolved_target = parent.join(link_target);
+    create_symlink_or_copy(link_target, dst, &resolved_target)
+}
+
+/// Try to create a symlink; if it fails on Windows, copy the target instead.
+#[cfg(windows)]
+fn create_symlink_or_copy(
+    link_target: &Path,
+    dst: &Path,
+    resolved_target: &Path,
+) -> io::Result<()> {
+    // Tar archives store paths with forward slashes, but Windows symlinks
+    // need backslashes to resolve targets correctly. Rebuilding from
+    // components uses the platform's native separator automatically.
+    // See: https://github.com/dotnet/runtime/issues/79031
+    let native_target: std::path::PathBuf = link_target.components().collect();
+
+    // Try symlink_file first. On Windows, both symlink_file and symlink_dir
+    // require Developer Mode or admin privileges.
+    let symlink_err = match std::os::windows::fs::symlink_file(&native_target, dst) {
+        Ok(()) => return Ok(()),
+        Err(e) => e,
+    };
+
+    // If the error isn't a privilege issue, try symlink_dir in case the
+    // target is a directory (symlink_file won't work for directories).
+    if symlink_err.raw_os_error() != Some(ERROR_PRIVILEGE_NOT_HELD)
+        && std::os::windows::fs::symlink_dir(&native_target, dst).is_ok()
+    {
+        return Ok(());
+    }
+
+    tracing::debug!(
+        "Symlink creation failed for {} -> {}, falling back to copy: {}",
+        dst.display(),
+        link_target.display(),
+        symlink_err
+    );
+
+    if !resolved_target.exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!(
+                "symlink target {} does not exist (resolved to {})",
+                link_target.display(),
+                resolved_target.display()
+            ),
+        ));
+    }
+
+    if let Some(parent) = dst.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    if resolved_target.is_dir() {
+        copy_dir_recursive(resolved_target, dst)
+    } else {
+        std::fs::copy(resolved_target, dst)?;
+        Ok(())
+    }
+}
+
+#[cfg(windows)]
+fn copy_dir_recursive(src: &Path, dst: &Path) -> io::Result<()> {
+    std::fs::create_dir_all(dst)?;
+    for entry in std::fs::read_dir(src)? {
+        let entry = entry?;
+        let entry_dst = dst.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir_recursive(&entry.path(), &entry_dst)?;
+        } else {
+            std::fs::copy(entry.path(), &entry_dst)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use tempfile::TempDir;
+
+    /// Helper to build tar archives in memory for testing.
+    struct TarBuilder {
+        builder: tar::Builder<Vec<u8>>,
+    }
+
+    impl TarBuilder {
+        fn new() -> Self {
+            Self {
+                builder: tar::Builder::new(Vec::new()),
+            }
+        }
+
+        fn add_file(mut self, path: &str, content: &[u8]) -> Self {
+            let mut header = tar::Header::new_gnu();
+            header.set_path(path).unwrap();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_entry_type(tar::EntryType::Regular);
+            header.set_cksum();
+            self.builder.append(&header, content).unwrap();
+            self
+        }
+
+        fn add_dir(mut self, path: &str) -> Self {
+            let mut header = tar::Header::new_gnu();
+            header.set_path(path).unwrap();
+            header.set_size(0);
+            header.set_mode(0o755);
+            header.set_entry_type(tar::EntryType::Directory);
+            header.set_cksum();
+            self.builder.append(&header, &[] as &[u8]).unwrap();
+            self
+        }
+
+        fn add_symlink(mut self, path: &str, target: &str) -> Self {
+            let mut header = tar::Header::new_gnu();
+            header.set_path(path).unwrap();
+            header.set_size(0);
+            header.set_mode(0o777);
+            header.set_entry_type(tar::EntryType::Symlink);
+            header.set_link_name(target).unwrap();
+            header.set_cksum();
+            self.builder.append(&header, &[] as &[u8]).unwrap();
+            self
+        }
+
+        /// Add an entry without the checks `tar` makes on paths, like malicious archives have.
+        fn add_raw(
+            mut self,
+            path: &str,
+            entry_type: tar::EntryType,
+            mode: u32,
+            link_name: Option<&str>,
+            content: &[u8],
+        ) -> Self {
+            let mut header = tar::Header::new_gnu();
+            let gnu = header.as_gnu_mut().unwrap();
+            gnu.name[..path.len()].copy_from_slice(path.as_bytes());
+            if let Some(link_name) = link_name {
+                gnu.linkname[..link_name.len()].copy_from_slice(link_name.as_bytes());
+            }
+            header.set_size(content.len() as u64);
+            header.set_mode(mode);
+            header.set_entry_type(entry_type);
+            header.set_cksum();
+            self.builder.append(&header, content).unwrap();
+            self
+        }
+
+        fn build(mut self) -> Vec<u8> {
+            self.builder.finish().unwrap();
+            self.builder.into_inner().unwrap()
+        }
+    }
+
+    // -- unpack_tar tests --
+    // These verify the public API contract: after unpacking, files (including
+    // symlink targets) are readable with the expected content. On Windows
+    // without Developer Mode, symlinks are transparently replaced by copies.
+
+    #[test]
+    fn test_unpack_tar_regular_files_and_nested_dirs() {
+        let temp_dir = TempDir::new().unwrap();
+        let data = TarBuilder::new()
+            .add_dir("subdir/")
+            .add_file("hello.txt", b"hello world")
+            .add_file("subdir/nested.txt", b"nested content")
+            .build();
+
+        let mut archive = tar::Archive::new(Cursor::new(data));
+        unpack_tar(&mut archive, temp_dir.path()).unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(temp_dir.path().join("hello.txt")).unwrap(),
+            "hello world"
+        );
+        assert_eq!(
+            std::fs::read_to_string(temp_dir.path().join("subdir/nested.txt")).unwrap(),
+            "nested content"
+        );
+    }
+
+    #[test]
+    fn test_unpack_tar_symlink_before_target() {
+        // Symlinks can appear in tar archives before their targets.
+        // The fallback copy must still work (deferred to a second pass).
+        let temp_dir = TempDir::new().unwrap();
+        let data = TarBuilder::new()
+            .add_symlink("link.txt", "real.txt")
+            .add_file("real.txt", b"forward ref")
+            .build();
+
+        let mut archive = tar::Archive::new(Cursor::new(data));
+        unpack_tar(&mut archive, temp_dir.path()).unwrap();
+
+        let content = std::fs::read_to_string(temp_dir.path().join("link.txt")).unwrap();
+        assert_eq!(content, "forward ref");
+    }
+
+    #[test]
+    fn test_unpack_tar_file_symlink_is_readable() {
+        let temp_dir = TempDir::new().unwrap();
+        let data = TarBuilder::new()
+            .add_file("real.txt", b"real content")
+            .add_symlink("link.txt", "real.txt")
+            .build();
+
+        let mut archive = tar::Archive::new(Cursor::new(data));
+        unpack_tar(&mut archive, temp_dir.path()).unwrap();
+
+        // Whether a real symlink or a copy, the content must be readable.
+        let content = std::fs::read_to_string(temp_dir.path().join("link.txt")).unwrap();
+        assert_eq!(content, "real content");
+    }
+
+    #[test]
+    fn test_unpack_tar_directory_symlink_is_readable() {
+        let temp_dir = TempDir::new().unwrap();
+        let data = TarBuilder::new()
+            .add_dir("real_dir/")
+            .add_file("real_dir/file.txt", b"dir file content")
+            .add_symlink("link_dir", "real_dir")
+            .build();
+
+        let mut archive = tar::Archive::new(Cursor::new(data));
+        unpack_tar(&mut archive, temp_dir.path()).unwrap();
+
+        let content =
+            std::fs::read_to_string(temp_dir.path().join("link_dir").join("file.txt")).unwrap();
+        assert_eq!(content, "dir file content");
+    }
+
+    // -- malicious archives --
+
+    fn unpack_err(data: Vec<u8>, dst: &Path) -> io::Error {
+        let mut archive = tar::Archive::new(Cursor::new(data));
+        unpack_tar(&mut archive, dst).unwrap_err()
+    }
+
+    #[test]
+    fn test_unpack_tar_rejects_parent_dirs() {
+        let temp_dir = TempDir::new().unwrap();
+        let dst = temp_dir.path().join("dst");
+        let data = TarBuilder::new()
+            .add_raw("../evil.txt", tar::EntryType::Regular, 0o644, None, b"evil")
+            .build();
+
+        let err = unpack_err(data, &dst);
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(!temp_dir.path().join("evil.txt").exists());
+    }
+
+    #[test]
+    fn test_unpack_tar_rejects_absolute_paths() {
+        let temp_dir = TempDir::new().unwrap();
+        let target = temp_dir.path().join("evil.txt");
+        let data = TarBuilder::new()
+            .add_raw(
+                target.to_str().unwrap(),
+                tar::EntryType::Regular,
+                0o644,
+                None,
+                b"evil",
+            )
+            .build();
+
+        let err = unpack_err(data, &temp_dir.path().join("dst"));
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(!target.exists());
+    }
+
+    #[test]
+    fn test_unpack_tar_rejects_escaping_symlinks() {
+        let temp_dir = TempDir::new().unwrap();
+        for target in ["../../outside", "/etc", "lib/../../outside"] {
+            let data = TarBuilder::new()
+                .add_raw(
+                    "lib/link",
+                    tar::EntryType::Symlink,
+                    0o777,
+                    Some(target),
+                    b"",
+                )
+                .build();
+            let err = unpack_err(data, temp_dir.path());
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData, "{target}");
+        }
+
+        // Symlinks that stay inside are fine.
+        let data = TarBuilder::new()
+            .add_file("lib/real.rb", b"real")
+            .add_raw(
+                "bin/link",
+                tar::EntryType::Symlink,
+                0o777,
+                Some("../lib/real.rb"),
+                b"",
+            )
+            .build();
+        let mut archive = tar::Archive::new(Cursor::new(data));
+        unpack_tar(&mut archive, temp_dir.path()).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(temp_dir.path().join("bin/link")).unwrap(),
+            "real"
+        );
+    }
+
+    #[test]
+    fn test_unpack_tar_rejects_escaping_hardlinks() {
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::write(temp_dir.path().join("secret"), b"secret").unwrap();
+        let data = TarBuilder::new()
+            .add_raw("hard", tar::EntryType::Link, 0o644, Some("../secret"), b"")
+            .build();
+
+        let err = unpack_err(data, &temp_dir.path().join("dst"));
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(!temp_dir.path().join("dst/hard").exists());
+    }
+
+    #[test]
+    fn test_unpack_tar_rejects_device_nodes() {
+        let temp_dir = TempDir::new().unwrap();
+        let data = TarBuilder::new()
+            .add_raw("dev/null", tar::EntryType::Char, 0o666, None, b"")
+            .build();
+
+        let err = unpack_err(data, temp_dir.path());
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_unpack_tar_refuses_to_write_through_symlinks() {
+        let temp_dir = TempDir::new().unwrap();
+        let outside = temp_dir.path().join("outside");
+        let dst = temp_dir.path().join("dst");
+        std::fs::create_dir_all(&outside).unwrap();
+        std::fs::create_dir_all(&dst).unwrap();
+        std::os::unix::fs::symlink(&outside, dst.join("lib")).unwrap();
+
+        let data = TarBuilder::new().add_file("lib/evil.rb", b"evil").build();
+        let err = unpack_err(data, &dst);
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(!outside.join("evil.rb").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_unpack_tar_normalizes_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = TempDir::new().unwrap();
+        let data = TarBuilder::new()
+            .add_raw("bin/setuid", tar::EntryType::Regular, 0o4777, None, b"")
+            .add_raw("lib/writable.rb", tar::EntryType::Regular, 0o666, None, b"")
+            .build();
+        let mut archive = tar::Archive::new(Cursor::new(data));
+        unpack_tar(&mut archive, temp_dir.path()).unwrap();
+
+        let mode = |path: &str| {
+            std::fs::metadata(temp_dir.path().join(path))
+                .unwrap()
+                .permissions()
+                .mode()
+                & 0o7777
+        };
+        assert_eq!(mode("bin/setuid"), 0o755);
+        assert_eq!(mode("lib/writable.rb"), 0o644);
+    }
+
+    #[test]
+    fn test_unpack_tar_stripped() {
+        let temp_dir = TempDir::new().unwrap();
+        let data = TarBuilder::new()
+            .add_dir("rv-ruby@3.4.1/")
+            .add_dir("rv-ruby@3.4.1/3.4.1/")
+            .add_file("rv-ruby@3.4.1/3.4.1/bin/ruby", b"ruby")
+            .add_raw(
+                "rv-ruby@3.4.1/3.4.1/bin/ruby3.4",
+                tar::EntryType::Link,
+                0o755,
+                Some("rv-ruby@3.4.1/3.4.1/bin/ruby"),
+                b"",
+            )
+            .build();
+        let mut archive = tar::Archive::new(Cursor::new(data));
+        unpack_tar_stripped(&mut archive, temp_dir.path(), 2).unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(temp_dir.path().join("bin/ruby")).unwrap(),
+            "ruby"
+        );
+        assert_eq!(
+            std::fs::read_to_string(temp_dir.path().join("bin/ruby3.4")).unwrap(),
+            "ruby"
+        );
+        assert!(!temp_dir.path().join("rv-ruby@3.4.1").exists());
+    }
+
+    // -- unpack_entry tests --
+
+    #[test]
+    fn test_unpack_entry_symlink_is_readable() {
+        let temp_dir = TempDir::new().unwrap();
+
+        // The symlink target must already exist on disk for unpack_entry,
+        // since it operates on a single entry without context of the archive.
+        std::fs::write(temp_dir.path().join("target.txt"), b"target content").unwrap();
+
+        let data = TarBuilder::new()
+            .add_symlink("link.txt", "target.txt")
+            .build();
+
+        let mut archive = tar::Archive::new(Cursor::new(data));
+        let mut entries = archive.entries().unwrap();
+        let mut entry = entries.next().unwrap().unwrap();
+
+        unpack_entry(&mut entry, temp_dir.path()).unwrap();
+
+        let link = temp_dir.path().join("link.txt");
+        assert_eq!(std::fs::read_to_string(&link).unwrap(), "target content");
+    }
+
+    #[test]
+    fn test_unpack_entry_rejects_escaping_symlink() {
+        let temp_dir = TempDir::new().unwrap();
+        let data = TarBuilder::new()
+            .add_raw(
+                "link",
+                tar::EntryType::Symlink,
+                0o777,
+                Some("../../etc/passwd"),
+                b"",
+            )
+            .build();
+
+        let mut archive = tar::Archive::new(Cursor::new(data));
+        let mut entry = archive.entries().unwrap().next().unwrap().unwrap();
+        let err = unpack_entry(&mut entry, temp_dir.path()).unwrap_err();
+
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(std::fs::symlink_metadata(temp_dir.path().join("link")).is_err());
+    }
+
+    // -- Windows-only tests --
+    // These test internal helpers that are only compiled on Windows.
+
+    #[cfg(windows)]
+    mod windows_tests {
+        use super::*;
+
+        #[test]
+        fn test_copy_dir_recursive_copies_nested_structure() {
+            let temp_dir = TempDir::new().unwrap();
+            let src = temp_dir.path().join("src");
+            let dst = temp_dir.path().join("dst");
+
+            std::fs::create_dir_all(src.join("sub")).unwrap();
+            std::fs::write(src.join("a.txt"), b"aaa").unwrap();
+            std::fs::write(src.join("sub").join("b.txt"), b"bbb").unwrap();
+
+            copy_dir_recursive(&src, &dst).unwrap();
+
+            assert_eq!(std::fs::read_to_string(dst.join("a.txt")).unwrap(), "aaa");
+            assert_eq!(
+                std::fs::read_to_string(dst.join("sub").join("b.txt")).unwrap(),
+                "bbb"
+            );
+        }
+
+        #[test]
+        fn test_create_symlink_or_copy_normalizes_forward_slashes() {
+            // Tar archives use forward slashes, but Windows symlinks need
+            // backslashes to resolve targets. This test verifies the fix for
+            // https://github.com/dotnet/runtime/issues/79031
+            let temp_dir = TempDir::new().unwrap();
+            let base = temp_dir.path();
+
+            // Create a nested target file
+            std::fs::create_dir_all(base.join("sub")).unwrap();
+            std::fs::write(base.join("sub").join("target.txt"), b"found it").unwrap();
+
+            // Symlink with forward-slash target (as tar archives produce)
+            let link_target = Path::new("sub/target.txt");
+            let dst = base.join("link.txt");
+            let resolved = base.join("sub").join("target.txt");
+
+            create_symlink_or_copy(link_target, &dst, &resolved).unwrap();
+
+            // Whether via symlink or copy, the content must be readable.
+            let content = std::fs::read_to_string(&dst).unwrap();
+            assert_eq!(content, "found it");
+        }
+
+        #[test]
+        fn test_create_symlink_or_copy_returns_not_found_for_missing_target() {
+            let temp_dir = TempDir::new().unwrap();
+            let dst = temp_dir.path().join("link.txt");
+            let link_target = Path::new("nonexistent.txt");
+            let resolved = temp_dir.path().join("nonexistent.txt");
+
+            let result = create_symlink_or_copy(link_target, &dst, &resolved);
+
+            // On Developer Mode machines, symlink_file succeeds with a dangling
+            // symlink. On non-Developer-Mode machines, the fallback checks
+            // resolved_target.exists() and returns NotFound. Either outcome is
+            // acceptable — the important thing is it doesn't panic.
+            if let Err(err) = result {
+                assert_eq!(err.kind(), io::ErrorKind::NotFound);
+            }
+        }
+    }
+}
@@ -58,7 +58,7 @@ pub fn default_ruby_dirs(root: &Utf8Path) -> Vec<Utf8PathBuf> {
         (true, xdg_data_path()),
         (false, legacy_default_data_path()),
         (false, legacy_default_path()),
-        (false, "/opt/rubies".into()),
+        (false, system_ruby_dir("/".into())),
         (false, "/usr/local/rubies".into()),
         (false, "/opt/homebrew/Cellar/ruby".into()),
     ];
@@ -74,6 +74,32 @@ pub fn default_ruby_dirs(root: &Utf8Path) -> Vec<Utf8PathBuf> {
         .collect()
 }
 
+/// System-wide Ruby installation directory, shared by every user on the machine.
+///
+/// On Windows this is `%SYSTEMDRIVE%\ProgramData\rv\rubies`, elsewhere `/opt/rubies`.
+pub fn system_ruby_dir(root: &Utf8Path) -> Utf8PathBuf {
+    #[cfg(windows)]
+    {
+        let system_drive = env::var("SYSTEMDRIVE").unwrap_or_else(|_| "C:".to_owned());
+        root.join(format!("{system_drive}\\"))
+            .join("ProgramData")
+            .join("rv")
+            .join("rubies")
+    }
+
+    #[cfg(not(windows))]
+    {
+        root.join("opt/rubies")
+    }
+}
+
+/// Whether the given path lives inside the current user's home directory.
+///
+/// Ruby installations outside of it are considered system-wide installations.
+pub fn is_user_path(path: &Utf8Path) -> bool {
+    path.starts_with(home_dir())
+}
+
 fn xdg_data_path() -> Utf8PathBuf {
     user_data_dir("/".into()).join("rubies")
 }
@@ -102,12 +128,16 @@ pub fn project_root(root: &Utf8PathBuf) -> io::Result<Utf8PathBuf> {
     let current_dir = Utf8PathBuf::try_from(std::env::current_dir()?)
         .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
 
-    Ok(current_dir
-        .ancestors()
+    Ok(project_root_of(&current_dir, root))
+}
+
+/// The nearest directory at or above `dir` with a `Gemfile.lock`, or `dir` itself if there's none.
+pub fn project_root_of(dir: &Utf8Path, root: &Utf8Path) -> Utf8PathBuf {
+    dir.ancestors()
         .take_while(|d| Some(*d) != root.parent())
         .find(|d| d.join("Gemfile.lock").is_file())
-        .map(|p| p.to_path_buf())
-        .unwrap_or(current_dir))
+        .unwrap_or(dir)
+        .to_path_buf()
 }
 
 pub fn root_dir() -> Utf8PathBuf {
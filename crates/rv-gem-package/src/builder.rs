@@ -0,0 +1,206 @@
+//! Write .gem files that come out the same, byte for byte, every time they're built from the same
+//! spec and files: entries are sorted, and every timestamp, owner and gzip header is fixed.
+
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::Path;
+
+use flate2::{Compression, GzBuilder};
+use rv_gem_types::Specification;
+use tar::{Builder, EntryType, Header};
+
+use crate::{ChecksumAlgorithm, Error, Result};
+
+/// The time RubyGems stamps on everything in a gem unless `SOURCE_DATE_EPOCH` says otherwise,
+/// like `Gem::DEFAULT_SOURCE_DATE_EPOCH`: 1980-01-02 00:00:00 UTC.
+pub const DEFAULT_SOURCE_DATE_EPOCH: u64 = 315_619_200;
+
+#[derive(Debug, Clone)]
+enum Contents {
+    File { data: Vec<u8>, mode: u32 },
+    Symlink { target: String },
+}
+
+/// Builds a .gem from a spec and the files that go in it.
+#[derive(Debug, Clone)]
+pub struct PackageBuilder {
+    spec: Specification,
+    files: BTreeMap<String, Contents>,
+    mtime: u64,
+}
+
+impl PackageBuilder {
+    /// A gem of `spec`, with no files yet.
+    pub fn new(spec: Specification) -> Self {
+        Self {
+            spec,
+            files: BTreeMap::new(),
+            mtime: DEFAULT_SOURCE_DATE_EPOCH,
+        }
+    }
+
+    /// A gem of `spec`, with every file its `files` lists, read from `root`. Files keep whether
+    /// they're executable, and symlinks stay symlinks.
+    pub fn from_dir(spec: Specification, root: impl AsRef<Path>) -> Result<Self> {
+        let root = root.as_ref();
+        let mut builder = Self::new(spec);
+        for file in builder.spec.files.clone() {
+            let path = root.join(&file);
+            let metadata = std::fs::symlink_metadata(&path)?;
+            builder = if metadata.is_symlink() {
+                let target = std::fs::read_link(&path)?;
+                builder.with_symlink(file, target.to_string_lossy())
+            } else if is_executable(&metadata) {
+                builder.with_executable(file, std::fs::read(&path)?)
+            } else {
+                builder.with_file(file, std::fs::read(&path)?)
+            };
+        }
+        Ok(builder)
+    }
+
+    /// Add a file, readable by everyone, to the gem's data.
+    pub fn with_file(self, path: impl Into<String>, data: impl Into<Vec<u8>>) -> Self {
+        self.with_contents(
+            path,
+            Contents::File {
+                data: data.into(),
+                mode: 0o644,
+            },
+        )
+    }
+
+    /// Add a file, which everyone can run, to the gem's data.
+    pub fn with_executable(self, path: impl Into<String>, data: impl Into<Vec<u8>>) -> Self {
+        self.with_contents(
+            path,
+            Contents::File {
+                data: data.into(),
+                mode: 0o755,
+            },
+        )
+    }
+
+    /// Add a symlink to the gem's data. `target` is relative to the symlink's directory.
+    pub fn with_symlink(self, path: impl Into<String>, target: impl Into<String>) -> Self {
+        self.with_contents(
+            path,
+            Contents::Symlink {
+                target: target.into(),
+            },
+        )
+    }
+
+    /// Stamp everything in the gem with `mtime`, in seconds since the Unix epoch, instead of
+    /// [`DEFAULT_SOURCE_DATE_EPOCH`].
+    pub fn with_mtime(mut self, mtime: u64) -> Self {
+        self.mtime = mtime;
+        self
+    }
+
+    fn with_contents(mut self, path: impl Into<String>, contents: Contents) -> Self {
+        self.files.insert(path.into(), contents);
+        self
+    }
+
+    /// Write the .gem to `writer`.
+    pub fn write(&self, writer: impl Write) -> Result<()> {
+        let yaml = rv_gem_specification_yaml::serialize_specification_to_yaml(&self.spec)
+            .map_err(Error::YamlSerialization)?;
+        let metadata = self.gzip(yaml.as_bytes())?;
+        let data = self.gzip(&self.data_tar()?)?;
+        let checksums =
+            checksums_yaml(&[("metadata.gz", &metadata[..]), ("data.tar.gz", &data[..])]);
+        let checksums = self.gzip(checksums.as_bytes())?;
+
+        // The order RubyGems writes them in.
+        let mut gem = Builder::new(writer);
+        for (name, contents) in [
+            ("metadata.gz", metadata),
+            ("data.tar.gz", data),
+            ("checksums.yaml.gz", checksums),
+        ] {
+            let mut header = self.header(EntryType::Regular, contents.len() as u64, 0o444);
+            gem.append_data(&mut header, name, &contents[..])?;
+        }
+        gem.into_inner()?.flush()?;
+        Ok(())
+    }
+
+    /// The .gem's bytes.
+    pub fn build(&self) -> Result<Vec<u8>> {
+        let mut gem = Vec::new();
+        self.write(&mut gem)?;
+        Ok(gem)
+    }
+
+    /// The uncompressed data.tar.gz, with the files in order of their paths.
+    fn data_tar(&self) -> Result<Vec<u8>> {
+        let mut tar = Builder::new(Vec::new());
+        for (path, contents) in &self.files {
+            if !crate::verify::stays_inside(Path::new(""), Path::new(path)) {
+                return Err(Error::unsafe_path(path));
+            }
+            match contents {
+                Contents::File { data, mode } => {
+                    let mut header = self.header(EntryType::Regular, data.len() as u64, *mode);
+                    tar.append_data(&mut header, path, &data[..])?;
+                }
+                Contents::Symlink { target } => {
+                    let dir = Path::new(path).parent().unwrap_or(Path::new(""));
+                    if !crate::verify::stays_inside(dir, Path::new(target)) {
+                        return Err(Error::unsafe_path(path));
+                    }
+                    let mut header = self.header(EntryType::Symlink, 0, 0o777);
+                    tar.append_link(&mut header, path, target)?;
+                }
+            }
+        }
+        Ok(tar.into_inner()?)
+    }
+
+    /// A header owned by root, stamped with the builder's time.
+    fn header(&self, entry_type: EntryType, size: u64, mode: u32) -> Header {
+        let mut header = Header::new_ustar();
+        header.set_entry_type(entry_type);
+        header.set_size(size);
+        header.set_mode(mode);
+        header.set_mtime(self.mtime);
+        header.set_uid(0);
+        header.set_gid(0);
+        header
+    }
+
+    /// Compress `data` the way RubyGems does, with the builder's time in the gzip header.
+    fn gzip(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let mtime = u32::try_from(self.mtime).unwrap_or(u32::MAX);
+        let mut encoder = GzBuilder::new()
+            .mtime(mtime)
+            .write(Vec::new(), Compression::best());
+        encoder.write_all(data)?;
+        Ok(encoder.finish()?)
+    }
+}
+
+/// The contents of checksums.yaml.gz for `files`, as RubyGems writes it.
+fn checksums_yaml(files: &[(&str, &[u8])]) -> String {
+    let mut yaml = String::from("---\n");
+    for algorithm in [ChecksumAlgorithm::Sha256, ChecksumAlgorithm::Sha512] {
+        yaml += &format!("{}:\n", algorithm.name());
+        for (name, contents) in files {
+            yaml += &format!("  {name}: {}\n", algorithm.calculate(contents));
+        }
+    }
+    yaml
+}
+
+#[cfg(unix)]
+fn is_executable(metadata: &std::fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o111 != 0
+}
+
+#[cfg(not(unix))]
+fn is_executable(_metadata: &std::fs::Metadata) -> bool {
+    false
+}
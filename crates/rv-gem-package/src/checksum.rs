@@ -1,7 +1,9 @@
+use saphyr::{LoadableYamlNode, Yaml};
 use sha1::{Digest as _, Sha1};
 use sha2::{Digest as _, Sha256, Sha512};
 use std::collections::HashMap;
 
+use crate::Error;
 use crate::error::ChecksumErrorKind;
 
 /// Checksums for files in a gem package
@@ -19,6 +21,40 @@ impl Checksums {
         Self::default()
     }
 
+    /// Parse the contents of a gem's checksums.yaml.gz
+    pub fn from_yaml(yaml_str: &str) -> crate::Result<Self> {
+        // Use saphyr for parsing the checksums structure
+        let docs = Yaml::load_from_str(yaml_str)
+            .map_err(|e| Error::invalid_yaml("checksums.yaml.gz", e))?;
+
+        let doc = docs
+            .first()
+            .ok_or_else(|| Error::empty_yaml("checksums.yaml.gz"))?;
+
+        let mut checksums = Self::new();
+
+        // Iterate over the top-level mapping (algorithm -> files)
+        if let Some(top_mapping) = doc.as_mapping() {
+            for (algorithm_key, files_value) in top_mapping {
+                if let (Some(algorithm), Some(files_mapping)) =
+                    (algorithm_key.as_str(), files_value.as_mapping())
+                {
+                    let algorithm = algorithm.parse()?;
+                    // Iterate over files for this algorithm
+                    for (file_key, checksum_value) in files_mapping {
+                        if let (Some(file), Some(checksum)) =
+                            (file_key.as_str(), checksum_value.as_str())
+                        {
+                            checksums.add_checksum(algorithm, file, checksum);
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(checksums)
+    }
+
     /// Add a checksum for a file
     pub fn add_checksum(&mut self, algorithm: ChecksumAlgorithm, file_path: &str, checksum: &str) {
         self.algorithms
@@ -34,6 +34,10 @@ pub enum Error {
     #[error("YAML parsing error")]
     #[diagnostic(transparent)]
     YamlParsing(#[diagnostic_source] miette::Report),
+
+    #[error("YAML serialization error")]
+    #[diagnostic(transparent)]
+    YamlSerialization(#[diagnostic_source] miette::Report),
 }
 
 #[derive(Error, Debug, Diagnostic)]
@@ -61,6 +65,12 @@ pub enum FormatErrorKind {
     #[error("empty YAML document in '{file_name}'")]
     #[diagnostic(help("YAML file exists but contains no valid documents"))]
     EmptyYaml { file_name: String },
+
+    #[error("'{path}' in data.tar.gz would be unpacked outside of the gem's directory")]
+    #[diagnostic(help(
+        "Gems must only contain relative paths, and symlinks that stay inside the gem"
+    ))]
+    UnsafePath { path: String },
 }
 
 #[derive(Error, Debug, Diagnostic)]
@@ -130,6 +140,10 @@ impl Error {
         .into()
     }
 
+    pub fn unsafe_path(path: impl Into<String>) -> Self {
+        FormatErrorKind::UnsafePath { path: path.into() }.into()
+    }
+
     // Checksum error constructors
     pub fn unsupported_algorithm(algorithm: impl Into<String>) -> Self {
         ChecksumErrorKind::UnsupportedAlgorithm {
@@ -1,11 +1,15 @@
+pub mod builder;
 pub mod checksum;
 pub mod entry;
 pub mod error;
 pub mod package;
 pub mod source;
+pub mod verify;
 
+pub use builder::{DEFAULT_SOURCE_DATE_EPOCH, PackageBuilder};
 pub use checksum::{ChecksumAlgorithm, ChecksumCalculator, Checksums};
 pub use entry::{DataReader, Entry, EntryType, FileReader};
 pub use error::{Error, Result};
 pub use package::Package;
 pub use source::PackageSource;
+pub use verify::{Verified, verify};
@@ -1,7 +1,6 @@
 use crate::{Error, Result, checksum::Checksums, entry::DataReader, source::PackageSource};
 use flate2::read::GzDecoder;
 use rv_gem_types::Specification;
-use saphyr::{LoadableYamlNode, Yaml};
 use std::io::{Read, SeekFrom};
 use std::path::Path;
 use tar::Archive;
@@ -198,7 +197,7 @@ impl<S: PackageSource> Package<S> {
                     .map_err(|e| Error::invalid_utf8("checksums.yaml.gz", e))?;
 
                 // Parse the YAML manually since it's a simple structure
-                self.checksums = Some(self.parse_checksums_yaml(&yaml_str)?);
+                self.checksums = Some(Checksums::from_yaml(&yaml_str)?);
                 return Ok(());
             }
         }
@@ -207,38 +206,4 @@ impl<S: PackageSource> Package<S> {
         self.checksums = Some(Checksums::new());
         Ok(())
     }
-
-    /// Parse checksums YAML format
-    fn parse_checksums_yaml(&self, yaml_str: &str) -> Result<Checksums> {
-        // Use saphyr for parsing the checksums structure
-        let docs = Yaml::load_from_str(yaml_str)
-            .map_err(|e| Error::invalid_yaml("checksums.yaml.gz", e))?;
-
-        let doc = docs
-            .first()
-            .ok_or_else(|| Error::empty_yaml("checksums.yaml.gz"))?;
-
-        let mut checksums = Checksums::new();
-
-        // Iterate over the top-level mapping (algorithm -> files)
-        if let Some(top_mapping) = doc.as_mapping() {
-            for (algorithm_key, files_value) in top_mapping {
-                if let (Some(algorithm), Some(files_mapping)) =
-                    (algorithm_key.as_str(), files_value.as_mapping())
-                {
-                    let algorithm = algorithm.parse()?;
-                    // Iterate over files for this algorithm
-                    for (file_key, checksum_value) in files_mapping {
-                        if let (Some(file), Some(checksum)) =
-                            (file_key.as_str(), checksum_value.as_str())
-                        {
-                            checksums.add_checksum(algorithm, file, checksum);
-                        }
-                    }
-                }
-            }
-        }
-
-        Ok(checksums)
-    }
 }
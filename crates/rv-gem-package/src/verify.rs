@@ -0,0 +1,207 @@
+//! Verify a .gem in a single pass over its bytes, without unpacking it anywhere.
+//!
+//! Every top-level file is hashed as it streams past and compared against `checksums.yaml.gz`,
+//! the spec in `metadata.gz` is parsed, and every file in `data.tar.gz` is read to its end, which
+//! checks the gzip stream's CRC, and has its path checked to stay inside the gem's directory.
+
+use std::collections::HashMap;
+use std::io::{self, Read};
+use std::path::{Component, Path};
+
+use flate2::read::GzDecoder;
+use rv_gem_types::Specification;
+use tar::Archive;
+
+use crate::{ChecksumAlgorithm, ChecksumCalculator, Checksums, Error, Result};
+
+/// What verifying a .gem found.
+#[derive(Debug)]
+pub struct Verified {
+    /// The gem's specification, from `metadata.gz`.
+    pub spec: Specification,
+    /// How many files, directories and links `data.tar.gz` holds.
+    pub data_entries: usize,
+    /// The top-level files whose checksums matched, sorted. Empty for gems built before
+    /// RubyGems wrote `checksums.yaml.gz`.
+    pub checksummed: Vec<String>,
+}
+
+/// Verify the .gem read from `reader`: its checksums, its spec, and the structure of its data.
+pub fn verify(reader: impl Read) -> Result<Verified> {
+    let mut archive = Archive::new(reader);
+    let mut digests: HashMap<String, HashMap<ChecksumAlgorithm, String>> = HashMap::new();
+    let mut spec = None;
+    let mut data_entries = None;
+    let mut checksums = Checksums::new();
+
+    for entry in archive.entries()? {
+        let entry = entry?;
+        let path = entry.path()?.to_string_lossy().into_owned();
+        let mut hashing = HashingReader::new(entry);
+        match path.as_str() {
+            "metadata.gz" => spec = Some(read_spec(GzDecoder::new(&mut hashing), &path)?),
+            "metadata" => spec = Some(read_spec(&mut hashing, &path)?),
+            "data.tar.gz" => data_entries = Some(check_data(GzDecoder::new(&mut hashing))?),
+            "checksums.yaml.gz" => {
+                let yaml = read_utf8(GzDecoder::new(&mut hashing), &path)?;
+                checksums = Checksums::from_yaml(&yaml)?;
+            }
+            _ => {}
+        }
+        // Whatever a reader above left unread is still part of the file's checksum.
+        io::copy(&mut hashing, &mut io::sink())?;
+        digests.insert(path, hashing.finalize());
+    }
+
+    let spec = spec.ok_or_else(|| Error::missing_file("metadata.gz"))?;
+    let data_entries = data_entries.ok_or_else(|| Error::missing_file("data.tar.gz"))?;
+
+    let mut checksummed = Vec::new();
+    for algorithm in checksums.algorithms() {
+        for file_path in checksums
+            .files_for_algorithm(algorithm)
+            .into_iter()
+            .flatten()
+        {
+            let calculated = digests
+                .get(file_path)
+                .and_then(|digests| digests.get(&algorithm))
+                .ok_or_else(|| Error::checksum_missing_file(file_path))?;
+            if let Some(expected) = checksums.get_checksum(algorithm, file_path)
+                && calculated != expected
+            {
+                return Err(Error::checksum_mismatch(
+                    file_path, algorithm, expected, calculated,
+                ));
+            }
+            checksummed.push(file_path.to_string());
+        }
+    }
+    checksummed.sort();
+    checksummed.dedup();
+
+    Ok(Verified {
+        spec,
+        data_entries,
+        checksummed,
+    })
+}
+
+/// Hashes everything read through it with every supported algorithm.
+struct HashingReader<R> {
+    inner: R,
+    calculator: ChecksumCalculator,
+}
+
+impl<R: Read> HashingReader<R> {
+    fn new(inner: R) -> Self {
+        Self {
+            inner,
+            calculator: ChecksumCalculator::new(ChecksumAlgorithm::all()),
+        }
+    }
+
+    fn finalize(self) -> HashMap<ChecksumAlgorithm, String> {
+        self.calculator.finalize()
+    }
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.calculator.update(&buf[..read]);
+        Ok(read)
+    }
+}
+
+fn read_utf8(mut reader: impl Read, file_name: &str) -> Result<String> {
+    let mut content = Vec::new();
+    reader.read_to_end(&mut content)?;
+    String::from_utf8(content).map_err(|e| Error::invalid_utf8(file_name, e))
+}
+
+fn read_spec(reader: impl Read, file_name: &str) -> Result<Specification> {
+    let yaml = read_utf8(reader, file_name)?;
+    rv_gem_specification_yaml::parse(&yaml).map_err(Error::YamlParsing)
+}
+
+/// Read every entry of `data.tar.gz` to its end, checking that unpacking it would only write
+/// inside the gem's directory. Returns how many entries it has.
+fn check_data(reader: impl Read) -> Result<usize> {
+    let mut archive = Archive::new(reader);
+    let mut count = 0;
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        if !stays_inside(Path::new(""), &path) {
+            return Err(Error::unsafe_path(path.to_string_lossy()));
+        }
+
+        match entry.header().entry_type() {
+            tar::EntryType::Regular | tar::EntryType::Directory => {}
+            tar::EntryType::Symlink | tar::EntryType::Link => {
+                let target = entry
+                    .link_name()?
+                    .ok_or_else(Error::tar_missing_symlink_target)?;
+                // A symlink's target is relative to its own directory, a hard link's to the root
+                // of the archive.
+                let base = if entry.header().entry_type() == tar::EntryType::Symlink {
+                    path.parent().unwrap_or(Path::new(""))
+                } else {
+                    Path::new("")
+                };
+                if !stays_inside(base, &target) {
+                    return Err(Error::unsafe_path(path.to_string_lossy()));
+                }
+            }
+            tar::EntryType::XGlobalHeader => continue,
+            other => return Err(Error::tar_unsupported_entry_type(format!("{other:?}"))),
+        }
+
+        io::copy(&mut entry, &mut io::sink())?;
+        count += 1;
+    }
+
+    // The archive ends before the gzip stream does, and gzip only checks its CRC at the very end.
+    io::copy(&mut archive.into_inner(), &mut io::sink())?;
+    Ok(count)
+}
+
+/// Does `path`, relative to the directory `base` inside the gem, stay inside the gem?
+pub(crate) fn stays_inside(base: &Path, path: &Path) -> bool {
+    let mut depth = base
+        .components()
+        .filter(|component| matches!(component, Component::Normal(_)))
+        .count();
+    for component in path.components() {
+        match component {
+            Component::Normal(_) => depth += 1,
+            Component::CurDir => {}
+            Component::ParentDir => match depth.checked_sub(1) {
+                Some(parent) => depth = parent,
+                None => return false,
+            },
+            Component::RootDir | Component::Prefix(_) => return false,
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stays_inside() {
+        assert!(stays_inside(Path::new(""), Path::new("lib/foo.rb")));
+        assert!(stays_inside(Path::new("lib"), Path::new("../README.md")));
+        assert!(stays_inside(Path::new(""), Path::new("lib/../ext/./foo.c")));
+        assert!(!stays_inside(Path::new(""), Path::new("../foo.rb")));
+        assert!(!stays_inside(
+            Path::new("lib"),
+            Path::new("../../etc/passwd")
+        ));
+        assert!(!stays_inside(Path::new(""), Path::new("/etc/passwd")));
+    }
+}
@@ -0,0 +1,221 @@
+use rv_gem_package::{DEFAULT_SOURCE_DATE_EPOCH, Error, Package, PackageBuilder, verify};
+use rv_gem_types::{Specification, Version};
+use std::io::{Cursor, Read};
+use std::path::PathBuf;
+use std::process::Command;
+use tar::Archive;
+
+fn spec() -> Specification {
+    let mut spec =
+        Specification::new("built-gem".to_string(), Version::new("1.2.3").unwrap()).unwrap();
+    spec.summary = "A gem built by rv-gem-package".to_string();
+    spec.authors = vec![Some("Test Author".to_string())];
+    spec.files = vec![
+        "bin/built".to_string(),
+        "lib/built_gem.rb".to_string(),
+        "lib/built_gem/version.rb".to_string(),
+    ];
+    spec.executables = vec!["built".to_string()];
+    spec
+}
+
+fn builder() -> PackageBuilder {
+    PackageBuilder::new(spec())
+        .with_file(
+            "lib/built_gem/version.rb",
+            "module BuiltGem\n  VERSION = \"1.2.3\"\nend\n",
+        )
+        .with_file(
+            "lib/built_gem.rb",
+            "require_relative \"built_gem/version\"\n",
+        )
+        .with_executable("bin/built", "#!/usr/bin/env ruby\nputs BuiltGem::VERSION\n")
+}
+
+/// A directory of its own for each test, which is empty to begin with
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("rv-gem-package-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Test that building the same gem twice gives the same bytes, whatever order files were added in
+#[test]
+fn test_build_is_reproducible() {
+    let first = builder().build().unwrap();
+    let second = builder().build().unwrap();
+    assert_eq!(first, second);
+
+    let reordered = PackageBuilder::new(spec())
+        .with_executable("bin/built", "#!/usr/bin/env ruby\nputs BuiltGem::VERSION\n")
+        .with_file(
+            "lib/built_gem.rb",
+            "require_relative \"built_gem/version\"\n",
+        )
+        .with_file(
+            "lib/built_gem/version.rb",
+            "module BuiltGem\n  VERSION = \"1.2.3\"\nend\n",
+        )
+        .build()
+        .unwrap();
+    assert_eq!(first, reordered);
+
+    let later = builder()
+        .with_mtime(DEFAULT_SOURCE_DATE_EPOCH + 1)
+        .build()
+        .unwrap();
+    assert_ne!(first, later);
+}
+
+/// Test that a built gem verifies, and reads back with the spec and files it was built from
+#[test]
+fn test_build_round_trips() {
+    let gem = builder().build().unwrap();
+
+    let verified = verify(Cursor::new(&gem)).unwrap();
+    assert_eq!(verified.spec.name, "built-gem");
+    assert_eq!(verified.spec.version, spec().version);
+    assert_eq!(verified.spec.summary, spec().summary);
+    assert_eq!(verified.spec.files, spec().files);
+    assert_eq!(verified.spec.executables, spec().executables);
+    assert_eq!(verified.data_entries, 3);
+    assert_eq!(verified.checksummed, vec!["data.tar.gz", "metadata.gz"]);
+
+    let mut package = Package::from_source(Cursor::new(gem.clone())).unwrap();
+    package.verify().unwrap();
+    let entries = package.data().unwrap().collect_entries().unwrap();
+    let paths: Vec<_> = entries.iter().map(|entry| entry.path.as_str()).collect();
+    assert_eq!(
+        paths,
+        vec!["bin/built", "lib/built_gem.rb", "lib/built_gem/version.rb"]
+    );
+    let modes: Vec<_> = entries.iter().map(|entry| entry.mode).collect();
+    assert_eq!(modes, vec![0o755, 0o644, 0o644]);
+
+    let mut archive = Archive::new(Cursor::new(gem));
+    for entry in archive.entries().unwrap() {
+        let entry = entry.unwrap();
+        let header = entry.header();
+        assert_eq!(header.mtime().unwrap(), DEFAULT_SOURCE_DATE_EPOCH);
+        assert_eq!(header.uid().unwrap(), 0);
+        assert_eq!(header.gid().unwrap(), 0);
+    }
+}
+
+/// Test building a gem from the files its spec lists
+#[test]
+fn test_build_from_dir() {
+    let root = scratch_dir("from-dir");
+    std::fs::create_dir_all(root.join("bin")).unwrap();
+    std::fs::create_dir_all(root.join("lib/built_gem")).unwrap();
+    std::fs::write(
+        root.join("lib/built_gem/version.rb"),
+        "module BuiltGem\n  VERSION = \"1.2.3\"\nend\n",
+    )
+    .unwrap();
+    std::fs::write(
+        root.join("lib/built_gem.rb"),
+        "require_relative \"built_gem/version\"\n",
+    )
+    .unwrap();
+    std::fs::write(
+        root.join("bin/built"),
+        "#!/usr/bin/env ruby\nputs BuiltGem::VERSION\n",
+    )
+    .unwrap();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(
+            root.join("bin/built"),
+            std::fs::Permissions::from_mode(0o755),
+        )
+        .unwrap();
+    }
+    // Files the spec doesn't list stay out of the gem.
+    std::fs::write(root.join("notes.txt"), "not packaged\n").unwrap();
+
+    let built = PackageBuilder::from_dir(spec(), &root)
+        .unwrap()
+        .build()
+        .unwrap();
+    #[cfg(unix)]
+    assert_eq!(built, builder().build().unwrap());
+    assert_eq!(verify(Cursor::new(built)).unwrap().data_entries, 3);
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+/// Test that files outside the gem's directory can't be added
+#[test]
+fn test_build_rejects_unsafe_paths() {
+    for builder in [
+        builder().with_file("../evil.rb", "pwned"),
+        builder().with_symlink("lib/passwd", "/etc/passwd"),
+        builder().with_symlink("lib/up", "../.."),
+    ] {
+        match builder.build() {
+            Err(Error::FormatError(_)) => {}
+            other => panic!("Expected unsafe path error, got: {other:?}"),
+        }
+    }
+}
+
+/// Test that RubyGems unpacks a built gem to the files it was built from
+#[test]
+fn test_gem_unpack_round_trip() {
+    if Command::new("gem").arg("--version").output().is_err() {
+        eprintln!("Skipping: RubyGems' `gem` command is not installed");
+        return;
+    }
+
+    let dir = scratch_dir("gem-unpack");
+    let gem_path = dir.join("built-gem-1.2.3.gem");
+    std::fs::write(&gem_path, builder().build().unwrap()).unwrap();
+
+    let output = Command::new("gem")
+        .arg("unpack")
+        .arg(&gem_path)
+        .arg("--target")
+        .arg(&dir)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "gem unpack failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let unpacked = dir.join("built-gem-1.2.3");
+    let mut contents = String::new();
+    std::fs::File::open(unpacked.join("lib/built_gem/version.rb"))
+        .unwrap()
+        .read_to_string(&mut contents)
+        .unwrap();
+    assert_eq!(contents, "module BuiltGem\n  VERSION = \"1.2.3\"\nend\n");
+    assert!(unpacked.join("lib/built_gem.rb").is_file());
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = std::fs::metadata(unpacked.join("bin/built"))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o111, 0o111);
+    }
+
+    let output = Command::new("gem")
+        .args(["specification", "--yaml"])
+        .arg(&gem_path)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let yaml = String::from_utf8(output.stdout).unwrap();
+    let spec_from_rubygems = rv_gem_specification_yaml::parse(&yaml).unwrap();
+    assert_eq!(spec_from_rubygems.name, "built-gem");
+    assert_eq!(spec_from_rubygems.version, spec().version);
+    assert_eq!(spec_from_rubygems.files, spec().files);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
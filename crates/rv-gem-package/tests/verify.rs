@@ -0,0 +1,157 @@
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use rv_gem_package::error::FormatErrorKind;
+use rv_gem_package::{Error, verify};
+use std::io::{Cursor, Read, Write};
+use std::path::Path;
+use tar::{Archive, Builder, EntryType, Header};
+
+/// The top-level files of a fixture gem, in order
+fn top_level_files(gem: &str) -> Vec<(String, Vec<u8>)> {
+    let gem_data = std::fs::read(Path::new("tests/fixtures").join(gem)).unwrap();
+    let mut archive = Archive::new(Cursor::new(gem_data));
+    archive
+        .entries()
+        .unwrap()
+        .map(|entry| {
+            let mut entry = entry.unwrap();
+            let path = entry.path().unwrap().to_string_lossy().into_owned();
+            let mut content = Vec::new();
+            entry.read_to_end(&mut content).unwrap();
+            (path, content)
+        })
+        .collect()
+}
+
+fn build_gem(files: &[(String, Vec<u8>)]) -> Vec<u8> {
+    let mut builder = Builder::new(Vec::new());
+    for (path, content) in files {
+        let mut header = Header::new_gnu();
+        header.set_path(path).unwrap();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o444);
+        header.set_cksum();
+        builder.append(&header, &content[..]).unwrap();
+    }
+    builder.into_inner().unwrap()
+}
+
+fn gzip(data: &[u8]) -> Vec<u8> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
+
+/// Test verifying every well-formed fixture gem
+#[test]
+fn test_verify_fixture_gems() {
+    for (gem, name, data_entries) in [
+        ("test-gem-1.0.0.gem", "test-gem", 2),
+        ("checksums-gem-1.0.0.gem", "checksums-gem", 2),
+        ("symlink-test-1.0.0.gem", "symlink-test", 6),
+        ("racc-1.8.1.gem", "racc", 33),
+        ("ffi-1.17.2-x86_64-linux-gnu.gem", "ffi", 133),
+    ] {
+        let file = std::fs::File::open(Path::new("tests/fixtures").join(gem)).unwrap();
+        let verified = verify(file).unwrap_or_else(|e| panic!("{gem} should verify: {e:?}"));
+        assert_eq!(verified.spec.name, name);
+        assert_eq!(verified.data_entries, data_entries, "{gem}");
+        assert_eq!(
+            verified.checksummed,
+            vec!["data.tar.gz", "metadata.gz"],
+            "{gem}"
+        );
+    }
+}
+
+/// Test that a corrupted data.tar.gz doesn't verify
+#[test]
+fn test_verify_corrupted_data() {
+    let mut files = top_level_files("test-gem-1.0.0.gem");
+    let (_, data) = files
+        .iter_mut()
+        .find(|(path, _)| path == "data.tar.gz")
+        .unwrap();
+    let middle = data.len() / 2;
+    data[middle] ^= 0xff;
+
+    let gem = build_gem(&files);
+    assert!(verify(Cursor::new(gem)).is_err());
+}
+
+/// Test that a data.tar.gz that doesn't match checksums.yaml.gz doesn't verify
+#[test]
+fn test_verify_checksum_mismatch() {
+    let mut files = top_level_files("test-gem-1.0.0.gem");
+    let racc_data = top_level_files("racc-1.8.1.gem")
+        .into_iter()
+        .find(|(path, _)| path == "data.tar.gz")
+        .unwrap();
+    for file in &mut files {
+        if file.0 == "data.tar.gz" {
+            *file = racc_data.clone();
+        }
+    }
+
+    let gem = build_gem(&files);
+    match verify(Cursor::new(gem)) {
+        Err(Error::ChecksumError(_)) => {}
+        other => panic!("Expected checksum error, got: {other:?}"),
+    }
+}
+
+/// Test that a gem whose data would be unpacked outside its directory doesn't verify
+#[test]
+fn test_verify_unsafe_paths() {
+    let metadata = top_level_files("test-gem-1.0.0.gem")
+        .into_iter()
+        .find(|(path, _)| path == "metadata.gz")
+        .unwrap();
+
+    let unsafe_data = |name: &[u8], entry_type: EntryType, link: Option<&str>| {
+        let mut header = Header::new_gnu();
+        // `set_path` refuses paths with `..`, so write the name into the header directly
+        header.as_old_mut().name[..name.len()].copy_from_slice(name);
+        header.set_entry_type(entry_type);
+        header.set_mode(0o644);
+        if let Some(link) = link {
+            header.set_link_name(link).unwrap();
+        }
+        let content: &[u8] = if link.is_some() { b"" } else { b"pwned\n" };
+        header.set_size(content.len() as u64);
+        header.set_cksum();
+
+        let mut builder = Builder::new(Vec::new());
+        builder.append(&header, content).unwrap();
+        let data = gzip(&builder.into_inner().unwrap());
+        build_gem(&[metadata.clone(), ("data.tar.gz".to_string(), data)])
+    };
+
+    for gem in [
+        unsafe_data(b"../evil.rb", EntryType::Regular, None),
+        unsafe_data(b"lib/../../evil.rb", EntryType::Regular, None),
+        unsafe_data(b"lib/passwd", EntryType::Symlink, Some("/etc/passwd")),
+        unsafe_data(b"lib/up", EntryType::Symlink, Some("../../..")),
+    ] {
+        match verify(Cursor::new(gem)) {
+            Err(Error::FormatError(FormatErrorKind::UnsafePath { .. })) => {}
+            other => panic!("Expected unsafe path error, got: {other:?}"),
+        }
+    }
+}
+
+/// Test that a gem without data.tar.gz doesn't verify
+#[test]
+fn test_verify_missing_data() {
+    let files: Vec<_> = top_level_files("test-gem-1.0.0.gem")
+        .into_iter()
+        .filter(|(path, _)| path == "metadata.gz")
+        .collect();
+
+    match verify(Cursor::new(build_gem(&files))) {
+        Err(Error::FormatError(FormatErrorKind::MissingFile { expected_file })) => {
+            assert_eq!(expected_file, "data.tar.gz");
+        }
+        other => panic!("Expected missing file error, got: {other:?}"),
+    }
+}
@@ -55,17 +55,6 @@
 //!       version: 0.0.0
 //! ```
 //! **Example**: `terminal-table-1.4.5.gem` - Different class hierarchy than standard `Gem::Requirement`
-//!
-//! ### YAML Anchors and References
-//! ```yaml
-//! dependencies:
-//! - !ruby/object:Gem::Dependency
-//!   requirement: &id001 !ruby/object:Gem::Requirement
-//!     # ... requirement definition
-//!   version_requirements: *id001
-//!   prerelease: false
-//! ```
-//! **Example**: `mocha-on-bacon-0.2.2.gem` - YAML anchors/references and dependency `prerelease` field not implemented
 
 use miette::Diagnostic;
 use rv_gem_types::requirement::RequirementError;
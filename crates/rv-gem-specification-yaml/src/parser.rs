@@ -14,7 +14,17 @@ use rv_gem_types::{Dependency, DependencyType, Platform, Requirement, Specificat
 pub use error::DeserializationError;
 
 mod error;
-type AnchorMap = HashMap<usize, String>;
+
+/// What an anchor like `&id001` names, so that aliases like `*id001` can stand in for it later.
+#[derive(Debug, Clone)]
+enum Anchored {
+    /// A constraint pair, like `[">=", Gem::Version]`, kept as `>= 1.0`.
+    Constraint(String),
+    Requirement(Requirement),
+    Version(Version),
+}
+
+type AnchorMap = HashMap<usize, Anchored>;
 
 // Helper function to parse YAML into events
 fn parse_yaml_events<'a>(source: &'a str) -> Result<Vec<(Event<'a>, Span)>> {
@@ -151,14 +161,28 @@ fn tagged_mapping_start<'a>(
     }
 }
 
-fn parse_version<'a>(input: &mut &'a [(Event<'a>, Span)]) -> ModalResult<Version, ContextError> {
-    delimited(
-        tagged_mapping_start("ruby/object:Gem::Version"),
-        parse_version_fields,
+fn parse_version<'a>(
+    anchors: &mut AnchorMap,
+    input: &mut &'a [(Event<'a>, Span)],
+) -> ModalResult<Version, ContextError> {
+    if let Ok((Event::Alias(_), _)) = peek(any::<_, ContextError>).parse_next(input) {
+        return resolve_alias(anchors, input, |anchored| match anchored {
+            Anchored::Version(version) => Some(version.clone()),
+            _ => None,
+        });
+    }
+
+    let (anchor_id, version) = terminated(
+        (
+            tagged_mapping_start("ruby/object:Gem::Version"),
+            parse_version_fields,
+        ),
         mapping_end,
     )
     .context(StrContext::Label("Gem::Version object"))
-    .parse_next(input)
+    .parse_next(input)?;
+    anchors.insert(anchor_id, Anchored::Version(version.clone()));
+    Ok(version)
 }
 
 fn parse_version_fields<'a>(
@@ -248,6 +272,22 @@ fn skip_alias<'a>(input: &mut &'a [(Event<'a>, Span)]) -> ModalResult<(), Contex
     .parse_next(input)
 }
 
+// Resolve an alias to the object its anchor names, if `resolve` accepts that kind of object
+fn resolve_alias<'a, T>(
+    anchors: &AnchorMap,
+    input: &mut &'a [(Event<'a>, Span)],
+    resolve: impl Fn(&Anchored) -> Option<T>,
+) -> ModalResult<T, ContextError> {
+    any.verify_map(|(event, _span)| match event {
+        Event::Alias(anchor_id) => anchors.get(&anchor_id).and_then(&resolve),
+        _ => None,
+    })
+    .context(StrContext::Expected(StrContextValue::Description(
+        "alias of an earlier anchor",
+    )))
+    .parse_next(input)
+}
+
 // String array parsing for regular string arrays
 fn parse_string_array<'a>(
     input: &mut &'a [(Event<'a>, Span)],
@@ -308,14 +348,22 @@ fn parse_requirement<'a>(
     anchors: &mut AnchorMap,
     input: &mut &'a [(Event<'a>, Span)],
 ) -> ModalResult<Requirement, ContextError> {
-    alt([
+    if let Ok((Event::Alias(_), _)) = peek(any::<_, ContextError>).parse_next(input) {
+        return resolve_alias(anchors, input, |anchored| match anchored {
+            Anchored::Requirement(requirement) => Some(requirement.clone()),
+            _ => None,
+        });
+    }
+
+    let anchor_id = alt([
         tagged_mapping_start("ruby/object:Gem::Requirement"),
         tagged_mapping_start("ruby/object:Gem::Version::Requirement"),
     ])
     .parse_next(input)?;
-    let fields = parse_requirement_fields(anchors, input)?;
+    let requirement = parse_requirement_fields(anchors, input)?;
     mapping_end.parse_next(input)?;
-    Ok(fields)
+    anchors.insert(anchor_id, Anchored::Requirement(requirement.clone()));
+    Ok(requirement)
 }
 
 fn parse_requirement_fields<'a>(
@@ -390,21 +438,17 @@ fn parse_constraint_pair<'a>(
         .context(context)
         .parse_next(input)
     {
-        Ok((Event::Alias(anchor_id), _)) => {
-            // Consume the alias event
-            let _ = any::<_, ErrMode<ContextError>>.parse_next(input)?;
-            match anchors.get(&anchor_id) {
-                Some(source) => Ok(source.to_string()),
-                _ => Err(ErrMode::Backtrack(ContextError::new())),
-            }
-        }
+        Ok((Event::Alias(_), _)) => resolve_alias(anchors, input, |anchored| match anchored {
+            Anchored::Constraint(constraint) => Some(constraint.clone()),
+            _ => None,
+        }),
         Ok((Event::SequenceStart(_, _), _)) => {
             // Parse a sequence like [">=", "2.0"]
             let anchor_id = sequence_start.parse_next(input)?;
-            let constraint = (string, parse_version)
-                .map(|(op, version)| format!("{op} {version}"))
-                .parse_next(input)?;
-            anchors.insert(anchor_id, constraint.to_string());
+            let op = string.parse_next(input)?;
+            let version = parse_version(anchors, input)?;
+            let constraint = format!("{op} {version}");
+            anchors.insert(anchor_id, Anchored::Constraint(constraint.clone()));
             sequence_end.parse_next(input)?;
             Ok(constraint)
         }
@@ -578,7 +622,7 @@ fn parse_gem_specification_winnow<'a>(
             }
             "version" => {
                 version = Some(
-                    parse_version
+                    (|input: &mut &'a [(Event<'a>, Span)]| parse_version(anchors, input))
                         .context(StrContext::Expected(StrContextValue::Description(
                             "Gem::Version object",
                         )))
@@ -492,6 +492,30 @@ fn test_yaml_with_aliased_dependencies() {
     }
 }
 
+#[test]
+fn test_yaml_with_aliased_requirements_and_versions() {
+    let yaml_content = load_fixture("yaml_anchors_shared_objects");
+    let spec = parse(&yaml_content).expect("aliases of whole objects should resolve");
+
+    let requirements: Vec<(&str, String)> = spec
+        .dependencies
+        .iter()
+        .map(|dep| (dep.name.as_str(), dep.requirement.to_string()))
+        .collect();
+    assert_eq!(
+        requirements,
+        vec![
+            ("mocha", ">= 0.13.0".to_string()),
+            // `requirement: *id001` is the whole Gem::Requirement of mocha
+            ("bacon", ">= 0.13.0".to_string()),
+            // `- *id002` is the Gem::Version inside mocha's constraint
+            ("rake", "~> 0.13.0".to_string()),
+        ]
+    );
+    assert_eq!(spec.dependencies[1].dep_type, DependencyType::Development);
+    assert_eq!(spec.required_rubygems_version.to_string(), ">= 0");
+}
+
 #[test]
 fn test_version_requirement_class() {
     let yaml_content = load_fixture("version_requirement_class");
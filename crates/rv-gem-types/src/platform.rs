@@ -74,7 +74,35 @@ impl Platform {
             .replace("x86_64-windows-msvc", "x64-mingw-ucrt")
             .replace("aarch64-windows-msvc", "aarch64-mingw-ucrt");
 
-        Self::new(rubygems_platform).expect("Could not parse current platform")
+        // rv may run on a host with a different libc than it was built for, like a glibc build
+        // on Alpine, and precompiled gems only work with the host's libc.
+        Self::new(rubygems_platform)
+            .expect("Could not parse current platform")
+            .with_libc(rv_platform::Libc::current())
+    }
+
+    /// The same platform linked against `libc`, like `x86_64-linux-musl` for `x86_64-linux-gnu`.
+    /// Platforms other than Linux don't change.
+    pub fn with_libc(self, libc: rv_platform::Libc) -> Self {
+        match self {
+            Platform::Specific { cpu, os, version } if os == "linux" => {
+                let version = version.unwrap_or_default();
+                let abi = version
+                    .strip_prefix("gnu")
+                    .or_else(|| version.strip_prefix("musl"))
+                    .unwrap_or(&version);
+                let libc = match libc {
+                    rv_platform::Libc::Gnu => "gnu",
+                    rv_platform::Libc::Musl => "musl",
+                };
+                Platform::Specific {
+                    cpu,
+                    os,
+                    version: Some(format!("{libc}{abi}")),
+                }
+            }
+            other => other,
+        }
     }
 
     pub fn local_precompiled_ruby_arch() -> Result<String, PlatformError> {
@@ -185,9 +213,16 @@ impl Platform {
                     // For non-Linux platforms, nil version matches any version
                     version1.is_none() || version2.is_none() || version1 == version2
                 } else {
-                    // For Linux platforms, nil version matches any version (unversioned matches versioned libc)
-                    version1.is_none() || version2.is_none() || version1 == version2
-                    // TODO: Add musl variant matching if needed
+                    // For Linux platforms, the version is the libc: gnu is the default, so
+                    // `x86_64-linux` is `x86_64-linux-gnu`, and musl gems also match a Linux
+                    // platform whose libc isn't known
+                    let other_version = version2.as_deref().unwrap_or_default();
+                    normalized_linux_version(version1.as_deref())
+                        == normalized_linux_version(version2.as_deref())
+                        || version1
+                            .as_deref()
+                            .and_then(|version| version.strip_suffix(other_version))
+                            .is_some_and(|libc| matches!(libc, "musl" | "musleabi" | "musleabihf"))
                 };
 
                 cpu_compatible && os_compatible && version_compatible
@@ -212,6 +247,18 @@ impl Platform {
     }
 }
 
+/// A Linux platform's version without the default gnu libc or an `eabi` suffix, like RubyGems'
+/// `normalized_linux_version`: `gnueabihf` is `None`, and `musleabi` is `musl`.
+fn normalized_linux_version(version: Option<&str>) -> Option<&str> {
+    let version = version?;
+    let version = version.strip_prefix("gnu").unwrap_or(version);
+    let version = version
+        .strip_suffix("eabihf")
+        .or_else(|| version.strip_suffix("eabi"))
+        .unwrap_or(version);
+    (!version.is_empty()).then_some(version)
+}
+
 impl Ord for Platform {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
         if self == other {
@@ -721,6 +768,66 @@ mod tests {
         assert!(!linux_musl.matches(&linux_gnu));
     }
 
+    #[test]
+    fn test_linux_libc_matching() {
+        // Test cases from RubyGems test_equals3_version and test_normalized_linux_version
+        let gnu = Platform::new("x86_64-linux-gnu").unwrap();
+        let musl = Platform::new("x86_64-linux-musl").unwrap();
+        let unversioned = Platform::new("x86_64-linux").unwrap();
+
+        // gnu is the default libc
+        assert!(unversioned.matches(&gnu));
+        assert!(gnu.matches(&unversioned));
+
+        // gems for an unknown libc are gnu gems, which don't run with musl
+        assert!(!unversioned.matches(&musl));
+        // musl gems run where the libc isn't known
+        assert!(musl.matches(&unversioned));
+
+        let gnueabihf = Platform::new("arm-linux-gnueabihf").unwrap();
+        let eabihf = Platform::new("arm-linux-eabihf").unwrap();
+        let musleabihf = Platform::new("arm-linux-musleabihf").unwrap();
+        let arm_linux = Platform::new("arm-linux").unwrap();
+
+        assert!(gnueabihf.matches(&eabihf));
+        assert!(eabihf.matches(&gnueabihf));
+        assert!(gnueabihf.matches(&arm_linux));
+        assert!(musleabihf.matches(&arm_linux));
+        assert!(!musleabihf.matches(&gnueabihf));
+        assert!(!gnueabihf.matches(&musleabihf));
+
+        assert_eq!(normalized_linux_version(Some("gnu")), None);
+        assert_eq!(normalized_linux_version(Some("gnueabihf")), None);
+        assert_eq!(normalized_linux_version(Some("musleabi")), Some("musl"));
+        assert_eq!(normalized_linux_version(Some("uclibceabi")), Some("uclibc"));
+        assert_eq!(normalized_linux_version(None), None);
+    }
+
+    #[test]
+    fn test_with_libc() {
+        use rv_platform::Libc;
+
+        let musl = Platform::new("x86_64-linux-gnu")
+            .unwrap()
+            .with_libc(Libc::Musl);
+        assert_eq!(musl.to_string(), "x86_64-linux-musl");
+        let gnu = Platform::new("x86_64-linux-musl")
+            .unwrap()
+            .with_libc(Libc::Gnu);
+        assert_eq!(gnu.to_string(), "x86_64-linux-gnu");
+        let musleabihf = Platform::new("arm-linux-gnueabihf")
+            .unwrap()
+            .with_libc(Libc::Musl);
+        assert_eq!(musleabihf.to_string(), "arm-linux-musleabihf");
+        let unversioned = Platform::new("aarch64-linux")
+            .unwrap()
+            .with_libc(Libc::Musl);
+        assert_eq!(unversioned.to_string(), "aarch64-linux-musl");
+
+        let darwin = Platform::new("arm64-darwin").unwrap();
+        assert_eq!(darwin.clone().with_libc(Libc::Musl), darwin);
+    }
+
     #[test]
     fn test_universal_platform_matching() {
         // Universal platforms should match specific architectures
@@ -5,6 +5,8 @@ use serde::{Deserialize, Serialize};
 use std::hash::{Hash, Hasher};
 use std::str::FromStr;
 
+pub use rv_version::ComparisonOperator;
+
 #[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
 pub enum RequirementError {
     #[error("Empty requirement string")]
@@ -102,7 +104,7 @@ impl TryFrom<&str> for VersionConstraint {
         }
 
         // Try to match operator and version
-        let operator = ComparisonOperator::try_from(str)?;
+        let operator = parse_operator(str)?;
         let version = VersionConstraint::version_from(str, operator.as_ref())?;
 
         Ok(Self { operator, version })
@@ -152,66 +154,14 @@ impl From<VersionConstraint> for Ranges<VersionPlatform> {
     }
 }
 
-#[derive(Default, Debug, Clone, PartialEq, Eq, Ord, PartialOrd, Hash, Serialize, Deserialize)]
-pub enum ComparisonOperator {
-    Equal,
-    NotEqual,
-    GreaterThan,
-    #[default]
-    GreaterThanOrEqual,
-    LessThan,
-    LessThanOrEqual,
-    Pessimistic,
-}
-
-impl TryFrom<&str> for ComparisonOperator {
-    type Error = RequirementError;
-
-    fn try_from(str: &str) -> Result<Self, RequirementError> {
-        match str {
-            s if s.starts_with(">=") => Ok(Self::GreaterThanOrEqual),
-            s if s.starts_with("<=") => Ok(Self::LessThanOrEqual),
-            s if s.starts_with("!=") => Ok(Self::NotEqual),
-            s if s.starts_with("~>") => Ok(Self::Pessimistic),
-            s if s.starts_with(">") => Ok(Self::GreaterThan),
-            s if s.starts_with("<") => Ok(Self::LessThan),
-            s if s.starts_with("!") => Err(RequirementError::InvalidOperator {
-                operator: str.chars().take(2).collect(),
-            }),
-            _ => Ok(Self::Equal), // Default to "=" if no operator specified
-        }
-    }
-}
-
-impl FromStr for ComparisonOperator {
-    type Err = String;
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            "!=" => Ok(ComparisonOperator::NotEqual),
-            ">=" => Ok(ComparisonOperator::GreaterThanOrEqual),
-            "<=" => Ok(ComparisonOperator::LessThanOrEqual),
-            ">" => Ok(ComparisonOperator::GreaterThan),
-            "<" => Ok(ComparisonOperator::LessThan),
-            "~>" => Ok(ComparisonOperator::Pessimistic),
-            "=" => Ok(ComparisonOperator::Equal),
-            other => Err(other.to_owned()),
-        }
+fn parse_operator(str: &str) -> Result<ComparisonOperator, RequirementError> {
+    if str.starts_with('!') && !str.starts_with("!=") {
+        return Err(RequirementError::InvalidOperator {
+            operator: str.chars().take(2).collect(),
+        });
     }
-}
 
-impl AsRef<str> for ComparisonOperator {
-    fn as_ref(&self) -> &str {
-        match self {
-            Self::GreaterThanOrEqual => ">=",
-            Self::LessThanOrEqual => "<=",
-            Self::NotEqual => "!=",
-            Self::Pessimistic => "~>",
-            Self::GreaterThan => ">",
-            Self::LessThan => "<",
-            Self::Equal => "=",
-        }
-    }
+    Ok(ComparisonOperator::split_prefix(str).0)
 }
 
 impl Requirement {
@@ -248,18 +198,11 @@ impl Requirement {
     }
 
     pub fn satisfied_by(&self, version: &Version) -> bool {
-        self.constraints
-            .iter()
-            .all(|constraint| constraint.matches(version))
+        rv_version::req::satisfies_all(&self.constraints, version)
     }
 
     pub fn matches(&self, version: &Version, allow_prerelease: bool) -> bool {
-        // Check prerelease logic
-        if version.is_prerelease() && !allow_prerelease && !self.is_prerelease() {
-            return false;
-        }
-
-        self.satisfied_by(version)
+        rv_version::req::matches_all(&self.constraints, version, allow_prerelease)
     }
 
     pub fn is_latest_version(&self) -> bool {
@@ -269,9 +212,7 @@ impl Requirement {
 
     pub fn is_prerelease(&self) -> bool {
         // A requirement is prerelease if any of its constraint versions are prerelease
-        self.constraints
-            .iter()
-            .any(|constraint| constraint.version.is_prerelease())
+        rv_version::req::mentions_prerelease(&self.constraints)
     }
 
     pub fn to_ruby(&self) -> String {
@@ -317,23 +258,29 @@ impl VersionConstraint {
     }
 
     pub fn matches(&self, version: &Version) -> bool {
-        match self.operator {
-            ComparisonOperator::Equal => version == &self.version,
-            ComparisonOperator::NotEqual => version != &self.version,
-            ComparisonOperator::GreaterThan => version > &self.version,
-            ComparisonOperator::GreaterThanOrEqual => version >= &self.version,
-            ComparisonOperator::LessThan => version < &self.version,
-            ComparisonOperator::LessThanOrEqual => version <= &self.version,
-            ComparisonOperator::Pessimistic => {
-                version >= &self.version && version < &self.version.bump()
-            }
-        }
+        rv_version::Constraint::matches(self, version)
     }
 }
 
-impl std::fmt::Display for ComparisonOperator {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.as_ref())
+impl rv_version::Constraint for VersionConstraint {
+    fn operator(&self) -> &ComparisonOperator {
+        &self.operator
+    }
+
+    fn target(&self) -> &Version {
+        &self.version
+    }
+}
+
+impl From<&Requirement> for rv_version::VersionReq {
+    fn from(requirement: &Requirement) -> Self {
+        Self::new(
+            requirement
+                .constraints
+                .iter()
+                .map(|constraint| (constraint.operator.clone(), constraint.version.clone()))
+                .collect(),
+        )
     }
 }
 
@@ -461,6 +408,15 @@ mod tests {
         assert!(!req.satisfied_by(&v("1.3")));
         assert!(!req.satisfied_by(&v("1.5")));
         assert!(!req.satisfied_by(&v("1.7")));
+
+        let version_req = rv_version::VersionReq::from(&req);
+        assert_eq!(version_req.to_string(), ">= 1.4, <= 1.6, != 1.5");
+        for version in ["1.3", "1.4", "1.5", "1.6", "1.7"] {
+            assert_eq!(
+                version_req.satisfied_by(&v(version)),
+                req.satisfied_by(&v(version))
+            );
+        }
     }
 
     #[test]
@@ -44,6 +44,105 @@ impl GemfileDotLock<'_> {
             + self.git.iter().map(|s| s.specs.len()).sum::<usize>()
             + self.path.iter().map(|s| s.specs.len()).sum::<usize>()
     }
+
+    /// Whether the lockfile was resolved for a specific (non-"ruby") platform matching
+    /// `platform`, and so can include precompiled gems for it.
+    pub fn has_native_platform(&self, platform: &Platform) -> bool {
+        self.platforms
+            .iter()
+            .any(|locked| !locked.is_ruby() && locked.matches(platform))
+    }
+
+    /// Add `platform` to the PLATFORMS section, which Bundler keeps sorted.
+    /// Returns false if it was already listed.
+    ///
+    /// This doesn't add any gems for the new platform: Bundler resolves those the next time it
+    /// runs against the lockfile.
+    pub fn add_platform(&mut self, platform: Platform) -> bool {
+        if self.platforms.contains(&platform) {
+            return false;
+        }
+
+        self.platforms.push(platform);
+        self.platforms.sort_by_key(|platform| platform.to_string());
+        true
+    }
+
+    /// Put every section in the order Bundler writes it, and drop duplicate entries, so the same
+    /// resolution always serializes to the same lockfile. Specs, their dependencies, DEPENDENCIES,
+    /// PLATFORMS and CHECKSUMS are sorted by how they're written, like Bundler does, and sources
+    /// of the same kind are sorted by remote.
+    pub fn normalize(&mut self) {
+        self.git
+            .sort_by(|a, b| (a.remote, a.revision).cmp(&(b.remote, b.revision)));
+        self.gem.sort_by_key(|section| section.remote);
+        // Bundler sorts path sources by "source at `<path>`", so the closing backtick decides
+        // between paths where one is a prefix of the other.
+        self.path
+            .sort_by_cached_key(|section| format!("{}`", section.remote));
+        for specs in self
+            .git
+            .iter_mut()
+            .map(|section| &mut section.specs)
+            .chain(self.gem.iter_mut().map(|section| &mut section.specs))
+            .chain(self.path.iter_mut().map(|section| &mut section.specs))
+        {
+            for spec in specs.iter_mut() {
+                spec.deps.sort_by_cached_key(|dep| dep.to_gemfile_lock());
+                spec.deps.dedup();
+            }
+            sort_and_dedup_by_display(specs);
+        }
+
+        self.platforms
+            .sort_by_cached_key(|platform| platform.to_string());
+        self.platforms.dedup();
+
+        sort_and_dedup_by_display(&mut self.dependencies);
+        // A gem can only be a dependency once, even with different requirements.
+        self.dependencies.dedup_by(|a, b| a.name == b.name);
+
+        if let Some(checksums) = &mut self.checksums {
+            sort_and_dedup_by_display(checksums);
+            checksums.dedup_by(|a, b| a.release_tuple == b.release_tuple);
+        }
+    }
+
+    /// Platform-specific gems that have no generic "ruby" platform version to fall back to.
+    ///
+    /// When PLATFORMS includes "ruby", Bundler expects every gem to have a version that installs
+    /// anywhere, so a precompiled gem without one breaks installs on platforms that aren't listed.
+    /// Lockfiles that don't list "ruby" only install on their listed platforms, so they never need
+    /// fallbacks and this is always empty.
+    pub fn missing_ruby_fallbacks(&self) -> Vec<&ReleaseTuple> {
+        if !self.platforms.iter().any(Platform::is_ruby) {
+            return vec![];
+        }
+
+        let specs: Vec<&ReleaseTuple> = self
+            .gem
+            .iter()
+            .flat_map(|section| section.specs.iter().map(|spec| &spec.release_tuple))
+            .collect();
+        specs
+            .iter()
+            .filter(|tuple| !tuple.platform.is_ruby())
+            .filter(|tuple| {
+                !specs.iter().any(|other| {
+                    other.platform.is_ruby()
+                        && other.name == tuple.name
+                        && other.version == tuple.version
+                })
+            })
+            .copied()
+            .collect()
+    }
+}
+
+/// Sort items by how they're written to the lockfile, and drop exact duplicates.
+fn sort_and_dedup_by_display<T: std::fmt::Display + PartialEq>(items: &mut Vec<T>) {
+    items.sort_by_cached_key(|item| item.to_string());
+    items.dedup();
 }
 
 impl std::fmt::Display for GemfileDotLock<'_> {
@@ -298,39 +397,79 @@ impl std::fmt::Display for Spec {
 #[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
 pub struct Checksum<'i> {
     pub release_tuple: ReleaseTuple,
-    pub algorithm: ChecksumAlgorithm<'i>,
-    pub value: Vec<u8>,
+    /// The gem's digests, in the order the lockfile lists them. Empty for gems from sources that
+    /// don't publish checksums.
+    pub digests: Vec<Digest<'i>>,
+}
+
+impl Checksum<'_> {
+    /// The checksum of a gem with the sha256 digest `value`, like gem servers publish. A gem
+    /// server that publishes no digest gives an empty `value`, which locks the gem unchecked.
+    pub fn sha256(release_tuple: ReleaseTuple, value: Vec<u8>) -> Self {
+        let digests = if value.is_empty() {
+            Vec::new()
+        } else {
+            vec![Digest {
+                algorithm: ChecksumAlgorithm::SHA256,
+                value,
+            }]
+        };
+        Self {
+            release_tuple,
+            digests,
+        }
+    }
 }
 
 impl std::fmt::Display for Checksum<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match &self.algorithm {
-            ChecksumAlgorithm::None => write!(f, "  {}", self.release_tuple.to_gemfile_lock()),
-            other => write!(
-                f,
-                "  {} {}={}",
-                self.release_tuple.to_gemfile_lock(),
-                other,
-                hex::encode(&self.value)
-            ),
+        write!(f, "  {}", self.release_tuple.to_gemfile_lock())?;
+        for (i, digest) in self.digests.iter().enumerate() {
+            let separator = if i == 0 { ' ' } else { ',' };
+            write!(f, "{separator}{digest}")?;
         }
+        Ok(())
+    }
+}
+
+/// One digest of a gem, like `sha256=51f4…`.
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct Digest<'i> {
+    pub algorithm: ChecksumAlgorithm<'i>,
+    pub value: Vec<u8>,
+}
+
+impl std::fmt::Display for Digest<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}={}", self.algorithm, hex::encode(&self.value))
     }
 }
 
 #[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Default)]
 pub enum ChecksumAlgorithm<'i> {
-    None,
     Unknown(&'i str),
     #[default]
     SHA256,
+    SHA512,
+}
+
+impl<'i> ChecksumAlgorithm<'i> {
+    /// The algorithm named `name` in a lockfile, like `sha256`.
+    pub fn from_name(name: &'i str) -> Self {
+        match name {
+            "sha256" => Self::SHA256,
+            "sha512" => Self::SHA512,
+            other => Self::Unknown(other),
+        }
+    }
 }
 
 impl std::fmt::Display for ChecksumAlgorithm<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Self::None => write!(f, ""),
             Self::Unknown(algo) => write!(f, "{algo}"),
             Self::SHA256 => write!(f, "sha256"),
+            Self::SHA512 => write!(f, "sha512"),
         }
     }
 }
@@ -0,0 +1,356 @@
+//! The gems a lockfile locks, as a graph of which gems depend on which.
+//!
+//! Each gem is one node, whatever versions and platform variants of it the lockfile has, because
+//! that's how Bundler treats a lockfile's dependencies: every variant of a gem may have its own
+//! dependencies, and any of them may be the one that gets installed. Use
+//! [`DependencyGraph::for_platform`] for the graph of the variants one platform installs.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use rv_gem_types::Platform;
+
+use crate::datatypes::{GemfileDotLock, Spec};
+
+/// One gem in a [`DependencyGraph`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Node<'a> {
+    /// Every spec the lockfile has for the gem, across sources, versions and platforms.
+    pub specs: Vec<&'a Spec>,
+    /// The locked gems any of those specs depend on.
+    pub dependencies: BTreeSet<&'a str>,
+    /// The locked gems that depend on this one.
+    pub dependents: BTreeSet<&'a str>,
+}
+
+/// Which locked gems depend on which, and which ones the Gemfile asks for.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DependencyGraph<'a> {
+    nodes: BTreeMap<&'a str, Node<'a>>,
+    /// The gems from DEPENDENCIES that the lockfile locks, i.e. the ones the Gemfile asks for.
+    roots: BTreeSet<&'a str>,
+}
+
+impl<'a> DependencyGraph<'a> {
+    /// The graph of every gem in `lockfile`.
+    pub fn new(lockfile: &'a GemfileDotLock) -> Self {
+        Self::with_specs(lockfile, |_| true)
+    }
+
+    /// The graph of the gems `platform` installs from `lockfile`: gem variants for other
+    /// platforms, and the dependencies only they have, are left out.
+    pub fn for_platform(lockfile: &'a GemfileDotLock, platform: &Platform) -> Self {
+        Self::with_specs(lockfile, |spec| {
+            spec.release_tuple.platform.matches(platform)
+        })
+    }
+
+    fn with_specs(lockfile: &'a GemfileDotLock, keep: impl Fn(&Spec) -> bool) -> Self {
+        let mut nodes: BTreeMap<&'a str, Node<'a>> = BTreeMap::new();
+        let all_specs = lockfile
+            .gem
+            .iter()
+            .flat_map(|section| &section.specs)
+            .chain(lockfile.git.iter().flat_map(|section| &section.specs))
+            .chain(lockfile.path.iter().flat_map(|section| &section.specs));
+        for spec in all_specs.filter(|spec| keep(spec)) {
+            nodes
+                .entry(spec.release_tuple.name.as_str())
+                .or_default()
+                .specs
+                .push(spec);
+        }
+
+        // Dependencies the lockfile doesn't lock, like gems only some platforms need, aren't
+        // part of the graph.
+        let mut edges = Vec::new();
+        for (name, node) in &nodes {
+            for dep in node.specs.iter().flat_map(|spec| &spec.deps) {
+                if let Some((dep_name, _)) = nodes.get_key_value(dep.name.as_str()) {
+                    edges.push((*name, *dep_name));
+                }
+            }
+        }
+        for (from, to) in edges {
+            nodes.get_mut(from).unwrap().dependencies.insert(to);
+            nodes.get_mut(to).unwrap().dependents.insert(from);
+        }
+
+        let roots = lockfile
+            .dependencies
+            .iter()
+            .filter_map(|dep| nodes.get_key_value(dep.name).map(|(name, _)| *name))
+            .collect();
+        Self { nodes, roots }
+    }
+
+    /// The gems the Gemfile asks for, in name order.
+    pub fn roots(&self) -> impl Iterator<Item = &'a str> + '_ {
+        self.roots.iter().copied()
+    }
+
+    /// Every gem in the graph, in name order.
+    pub fn gems(&self) -> impl Iterator<Item = &'a str> + '_ {
+        self.nodes.keys().copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.nodes.contains_key(name)
+    }
+
+    pub fn node(&self, name: &str) -> Option<&Node<'a>> {
+        self.nodes.get(name)
+    }
+
+    /// The gems `name` depends on directly.
+    pub fn dependencies(&self, name: &str) -> impl Iterator<Item = &'a str> + '_ {
+        self.nodes
+            .get(name)
+            .into_iter()
+            .flat_map(|node| node.dependencies.iter().copied())
+    }
+
+    /// The gems that depend on `name` directly.
+    pub fn dependents(&self, name: &str) -> impl Iterator<Item = &'a str> + '_ {
+        self.nodes
+            .get(name)
+            .into_iter()
+            .flat_map(|node| node.dependents.iter().copied())
+    }
+
+    /// `gems` and every gem they depend on, directly or not.
+    pub fn reachable_from<'n>(&self, gems: impl IntoIterator<Item = &'n str>) -> BTreeSet<&'a str> {
+        let mut reachable = BTreeSet::new();
+        let mut to_visit: Vec<&'a str> = gems
+            .into_iter()
+            .filter_map(|name| self.nodes.get_key_value(name).map(|(name, _)| *name))
+            .collect();
+        while let Some(name) = to_visit.pop() {
+            if reachable.insert(name) {
+                to_visit.extend(self.dependencies(name));
+            }
+        }
+        reachable
+    }
+
+    /// Every chain of dependencies from a gem the Gemfile asks for down to `name`, each starting
+    /// with that gem and ending with `name`, in name order. A chain never visits a gem twice, so
+    /// gems that depend on each other don't make chains endless.
+    pub fn paths_to(&self, name: &str) -> Vec<Vec<&'a str>> {
+        let Some((name, _)) = self.nodes.get_key_value(name) else {
+            return Vec::new();
+        };
+        let mut paths = Vec::new();
+        // Walk up from `name` through the gems that depend on it, so every chain ends there.
+        let mut chain = vec![*name];
+        self.collect_paths_up(&mut chain, &mut paths);
+        paths.sort();
+        paths
+    }
+
+    fn collect_paths_up(&self, chain: &mut Vec<&'a str>, paths: &mut Vec<Vec<&'a str>>) {
+        let top = *chain.last().expect("chains are never empty");
+        if self.roots.contains(top) {
+            paths.push(chain.iter().rev().copied().collect());
+        }
+        for dependent in self.dependents(top) {
+            if !chain.contains(&dependent) {
+                chain.push(dependent);
+                self.collect_paths_up(chain, paths);
+                chain.pop();
+            }
+        }
+    }
+
+    /// The graph of the gems the Gemfile asks for that are `wanted`, like the gems of some of
+    /// the Gemfile's groups, and every gem those depend on.
+    pub fn subgraph(&self, wanted: impl Fn(&str) -> bool) -> Self {
+        let roots: BTreeSet<&'a str> = self.roots().filter(|name| wanted(name)).collect();
+        let keep = self.reachable_from(roots.iter().copied());
+        let nodes = self
+            .nodes
+            .iter()
+            .filter(|(name, _)| keep.contains(*name))
+            .map(|(name, node)| {
+                let node = Node {
+                    specs: node.specs.clone(),
+                    dependencies: node.dependencies.clone(),
+                    dependents: node
+                        .dependents
+                        .iter()
+                        .copied()
+                        .filter(|dependent| keep.contains(dependent))
+                        .collect(),
+                };
+                (*name, node)
+            })
+            .collect();
+        Self { nodes, roots }
+    }
+
+    /// Every gem, after all the gems it depends on, so that installing gems in this order
+    /// installs each one's dependencies first. Gems that depend on each other, which RubyGems
+    /// allows, can't all come after each other, so each such cycle is broken at one of its gems.
+    pub fn topological_order(&self) -> Vec<&'a str> {
+        let mut waiting_on: BTreeMap<&'a str, usize> = self
+            .nodes
+            .iter()
+            .map(|(name, node)| (*name, node.dependencies.len()))
+            .collect();
+        let mut ready: BTreeSet<&'a str> = waiting_on
+            .iter()
+            .filter(|(_, count)| **count == 0)
+            .map(|(name, _)| *name)
+            .collect();
+
+        let mut order = Vec::with_capacity(self.nodes.len());
+        while !waiting_on.is_empty() {
+            let name = match ready.pop_first() {
+                Some(name) => name,
+                // Everything left is in a cycle, or waits on one. Follow what the first gem waits
+                // on until it comes back around, and break the cycle there.
+                None => {
+                    let mut name = *waiting_on.keys().next().expect("waiting_on isn't empty");
+                    let mut seen = BTreeSet::new();
+                    while seen.insert(name) {
+                        name = self
+                            .dependencies(name)
+                            .find(|dep| waiting_on.contains_key(dep))
+                            .expect("gems only wait on gems that are still waiting");
+                    }
+                    name
+                }
+            };
+            waiting_on.remove(name);
+            order.push(name);
+            for dependent in self.dependents(name) {
+                if let Some(count) = waiting_on.get_mut(dependent) {
+                    *count = count.saturating_sub(1);
+                    if *count == 0 {
+                        ready.insert(dependent);
+                    }
+                }
+            }
+        }
+        order
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOCKFILE: &str = "\
+GEM
+  remote: https://rubygems.org/
+  specs:
+    minitest (5.25.5)
+    nokogiri (1.18.8)
+      mini_portile2 (~> 2.8.2)
+      racc (~> 1.4)
+    nokogiri (1.18.8-x86_64-linux-gnu)
+      racc (~> 1.4)
+    mini_portile2 (2.8.9)
+    racc (1.8.1)
+    rails (8.0.2)
+      railties (= 8.0.2)
+    railties (8.0.2)
+      rails (= 8.0.2)
+      thor (~> 1.0)
+    thor (1.3.2)
+
+PLATFORMS
+  ruby
+  x86_64-linux-gnu
+
+DEPENDENCIES
+  minitest
+  nokogiri
+  rails
+  windows-only
+
+BUNDLED WITH
+   2.6.9
+";
+
+    #[test]
+    fn test_graph() {
+        let lockfile = crate::parse(LOCKFILE).unwrap();
+        let graph = DependencyGraph::new(&lockfile);
+
+        assert_eq!(graph.len(), 7);
+        assert_eq!(
+            graph.roots().collect::<Vec<_>>(),
+            ["minitest", "nokogiri", "rails"]
+        );
+        assert_eq!(graph.node("nokogiri").unwrap().specs.len(), 2);
+        assert_eq!(
+            graph.dependencies("nokogiri").collect::<Vec<_>>(),
+            ["mini_portile2", "racc"]
+        );
+        assert_eq!(graph.dependents("racc").collect::<Vec<_>>(), ["nokogiri"]);
+        assert_eq!(
+            graph.reachable_from(["rails"]),
+            BTreeSet::from(["rails", "railties", "thor"])
+        );
+    }
+
+    #[test]
+    fn test_graph_for_platform() {
+        let lockfile = crate::parse(LOCKFILE).unwrap();
+        let linux = Platform::new("x86_64-linux-gnu").unwrap();
+        let graph = DependencyGraph::for_platform(&lockfile, &linux);
+        assert_eq!(graph.node("nokogiri").unwrap().specs.len(), 2);
+
+        let graph = DependencyGraph::for_platform(&lockfile, &Platform::new("java").unwrap());
+        assert_eq!(graph.node("nokogiri").unwrap().specs.len(), 1);
+        assert_eq!(
+            graph.dependencies("nokogiri").collect::<Vec<_>>(),
+            ["mini_portile2", "racc"]
+        );
+    }
+
+    #[test]
+    fn test_paths_to() {
+        let lockfile = crate::parse(LOCKFILE).unwrap();
+        let graph = DependencyGraph::new(&lockfile);
+        assert_eq!(graph.paths_to("racc"), [["nokogiri", "racc"]]);
+        assert_eq!(graph.paths_to("minitest"), [["minitest"]]);
+        // railties depends on rails too, but a chain only goes through rails once.
+        assert_eq!(graph.paths_to("thor"), [vec!["rails", "railties", "thor"]]);
+        assert!(graph.paths_to("windows-only").is_empty());
+    }
+
+    #[test]
+    fn test_subgraph() {
+        let lockfile = crate::parse(LOCKFILE).unwrap();
+        let graph = DependencyGraph::new(&lockfile).subgraph(|name| name != "rails");
+        assert_eq!(
+            graph.gems().collect::<Vec<_>>(),
+            ["mini_portile2", "minitest", "nokogiri", "racc"]
+        );
+        assert_eq!(graph.roots().collect::<Vec<_>>(), ["minitest", "nokogiri"]);
+    }
+
+    #[test]
+    fn test_topological_order() {
+        let lockfile = crate::parse(LOCKFILE).unwrap();
+        let graph = DependencyGraph::new(&lockfile);
+        let order = graph.topological_order();
+        assert_eq!(order.len(), graph.len());
+
+        let position = |name| order.iter().position(|gem| *gem == name).unwrap();
+        assert!(position("racc") < position("nokogiri"));
+        assert!(position("mini_portile2") < position("nokogiri"));
+        // rails and railties depend on each other, so the cycle is broken at rails.
+        assert!(position("thor") < position("railties"));
+        assert!(position("rails") < position("railties"));
+    }
+}
@@ -1,12 +1,14 @@
 pub mod datatypes;
+pub mod graph;
 mod parser;
 #[cfg(test)]
 mod tests;
 
 use std::borrow::Cow;
 
+use camino::Utf8Path;
 use miette::{Diagnostic, SourceSpan};
-pub use parser::parse;
+pub use parser::{parse, parse_lenient};
 
 /// Normalize line endings in a lockfile string.
 ///
@@ -24,6 +26,24 @@ pub fn normalize_line_endings(contents: &str) -> Cow<'_, str> {
     }
 }
 
+/// Atomically write the lockfile `contents` to `path`, with Windows line endings if `original`,
+/// the lockfile's contents before, had them.
+///
+/// `contents` is normalized first, so that line endings that are already Windows-style don't
+/// end up as `\r\r\n`.
+pub fn write_preserving_line_endings(
+    path: &Utf8Path,
+    contents: &str,
+    original: &str,
+) -> std::io::Result<()> {
+    let contents = normalize_line_endings(contents);
+    if original.contains("\r\n") {
+        rv_cache::write_atomic(path, contents.replace('\n', "\r\n"))
+    } else {
+        rv_cache::write_atomic(path, contents.as_bytes())
+    }
+}
+
 #[derive(Debug, thiserror::Error, Diagnostic)]
 #[error("Could not parse")]
 #[diagnostic()]
@@ -45,6 +65,20 @@ pub struct ParseError {
     #[label("Parsing failed here")]
     char_offset: SourceSpan,
 
+    /// The 1-based line parsing failed on.
+    line: usize,
+
     /// Error message
     msg: String,
 }
+
+impl ParseError {
+    /// The 1-based line parsing failed on.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    pub fn message(&self) -> &str {
+        &self.msg
+    }
+}
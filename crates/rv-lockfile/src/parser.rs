@@ -55,18 +55,35 @@ fn parse_section<'i>(i: &mut Input<'i>) -> Res<Section<'i>> {
 }
 
 pub fn parse<'i>(file: &'i str) -> Result<GemfileDotLock<'i>, ParseErrors> {
+    match parse_lenient(file) {
+        (parsed, None) => Ok(parsed),
+        (_, Some(errors)) => Err(errors),
+    }
+}
+
+/// Parse as much of `file` as possible, returning the lockfile without what couldn't be parsed,
+/// and every problem found, if any.
+///
+/// A line that doesn't parse inside a section is skipped, along with anything indented under it
+/// (like the dependencies of a spec), and the rest of the section is still parsed. When a section
+/// can't be parsed at all, everything up to the next section is skipped.
+pub fn parse_lenient<'i>(file: &'i str) -> (GemfileDotLock<'i>, Option<ParseErrors>) {
     let mut input = LocatingSlice::new(file);
     let i = &mut input;
     let mut parsed = GemfileDotLock::default();
-    let mut error: Option<ParseErrors> = None;
+    let mut errors = Vec::new();
+    // The header of the last section parsed, to carry on with after a line that doesn't parse.
+    let mut current: Option<&'static str> = None;
 
     while !i.is_empty() {
+        let attempt = i.checkpoint();
+        let attempt_offset = i.previous_token_end();
         let section = match parse_section.parse_next(i) {
             Ok(sec) => sec,
             Err(e) => {
                 // OK, there was an error. Let's figure out where, to highlight it.
-                let byte_offset = i.previous_token_end();
-                let char_offset = file[..byte_offset.min(file.len())].chars().count();
+                let byte_offset = i.previous_token_end().min(file.len());
+                let failed_line = line_start(file, byte_offset);
 
                 // Then find the error message.
                 let msg = match &e {
@@ -74,20 +91,32 @@ pub fn parse<'i>(file: &'i str) -> Result<GemfileDotLock<'i>, ParseErrors> {
                     ErrMode::Backtrack(err) | ErrMode::Cut(err) => err.to_string(),
                 };
 
-                // Now we can add the error to the list.
-                let parse_err = ParseError {
-                    char_offset: SourceSpan::new(char_offset.into(), 1),
-                    msg,
-                };
-                if let Some(err) = error.as_mut() {
-                    err.others.push(parse_err);
-                } else {
-                    error = Some(ParseErrors {
-                        lockfile_contents: file.to_owned(),
-                        others: vec![parse_err],
-                    })
+                // An indented line that fails right where the section before it stopped is a bad
+                // line inside that section: skip it, and whatever is indented under it, then
+                // carry on with the rest of the section.
+                if let Some(header) = current
+                    && failed_line == line_start(file, attempt_offset)
+                    && file[failed_line..].starts_with(' ')
+                {
+                    let skip_to = end_of_indented_block(file, failed_line);
+                    errors.push(line_error(
+                        file,
+                        failed_line,
+                        byte_offset,
+                        msg,
+                        Some(header),
+                    ));
+                    i.reset(&attempt);
+                    i.next_slice(skip_to - attempt_offset);
+                    if parse_rest_of_section(i, &mut parsed, header).is_err() {
+                        current = None;
+                    }
+                    continue;
                 }
 
+                errors.push(line_error(file, failed_line, byte_offset, msg, None));
+                current = None;
+
                 // Consume input until the next new line which starts with a non-whitespace character.
                 // If we reach the end of input, stop parsing.
                 let remainder = *i.as_ref();
@@ -128,35 +157,140 @@ pub fn parse<'i>(file: &'i str) -> Result<GemfileDotLock<'i>, ParseErrors> {
         match section {
             Section::Git(section) => {
                 parsed.git.push(section);
+                current = Some(GIT);
             }
             Section::Gem(section) => {
                 parsed.gem.push(section);
+                current = Some(GEM);
             }
             Section::Path(section) => {
                 parsed.path.push(section);
+                current = Some(PATH);
             }
             Section::Platforms(section) => {
                 parsed.platforms = section;
+                current = Some(PLATFORMS);
             }
             Section::Dependencies(section) => {
                 parsed.dependencies = section;
+                current = Some(DEPENDENCIES);
             }
             Section::RubyVersion(section) => {
                 parsed.ruby_version = Some(section);
+                current = Some(RUBY_VERSION);
             }
             Section::BundledWith(section) => {
                 parsed.bundled_with = Some(section);
+                current = Some(BUNDLED_WITH);
             }
             Section::Checksums(section) => {
                 parsed.checksums = Some(section);
+                current = Some(CHECKSUMS);
             }
         }
     }
 
-    match error {
-        None => Ok(parsed),
-        Some(error) => Err(error),
+    let errors = (!errors.is_empty()).then(|| ParseErrors {
+        lockfile_contents: file.to_owned(),
+        others: errors,
+    });
+    (parsed, errors)
+}
+
+/// Parse the lines of a section after one that was skipped, adding them to the section parsed
+/// last, which has the header `header`.
+fn parse_rest_of_section<'i>(
+    i: &mut Input<'i>,
+    parsed: &mut GemfileDotLock<'i>,
+    header: &str,
+) -> Res<()> {
+    let specs = match header {
+        GIT => parsed.git.last_mut().map(|section| &mut section.specs),
+        GEM => parsed.gem.last_mut().map(|section| &mut section.specs),
+        PATH => parsed.path.last_mut().map(|section| &mut section.specs),
+        _ => None,
+    };
+    if let Some(specs) = specs {
+        // The skipped line may have been one of the last spec's dependencies.
+        let deps: Vec<_> = repeat(0.., parse_spec_dep).parse_next(i)?;
+        if let Some(spec) = specs.last_mut() {
+            spec.deps.extend(deps);
+        }
+        let more: Vec<_> = repeat(0.., parse_spec).parse_next(i)?;
+        specs.extend(more);
+    } else if header == PLATFORMS {
+        let more: Vec<_> =
+            repeat(0.., delimited(space1, parse_platform, line_ending)).parse_next(i)?;
+        parsed.platforms.extend(more);
+    } else if header == DEPENDENCIES {
+        let more: Vec<_> =
+            repeat(0.., delimited(space1, parse_dependency, line_ending)).parse_next(i)?;
+        parsed.dependencies.extend(more);
+    } else if header == CHECKSUMS {
+        let more: Vec<_> =
+            repeat(0.., delimited(space1, parse_checksum, line_ending)).parse_next(i)?;
+        parsed.checksums.get_or_insert_default().extend(more);
+    }
+    parse_empty_lines.parse_next(i)
+}
+
+/// A line that couldn't be parsed, from its first non-space character to its end, inside the
+/// section with the header `section` if it's known. `byte_offset` is where parsing failed,
+/// somewhere on the line.
+fn line_error(
+    file: &str,
+    line_start: usize,
+    byte_offset: usize,
+    msg: String,
+    section: Option<&str>,
+) -> ParseError {
+    let line = &file[line_start..];
+    let line = &line[..line.find('\n').unwrap_or(line.len())];
+    let content = line.trim_start_matches(' ');
+    let start = line_start + (line.len() - content.len());
+    let char_offset = file[..start].chars().count();
+    let msg = match (msg.is_empty(), section) {
+        (true, Some(section)) => format!("invalid line in {section}: {content}"),
+        (true, None) => format!("invalid line: {content}"),
+        (false, Some(section)) => format!("{msg}, in {section}: {content}"),
+        (false, None) => format!("{msg}: {content}"),
+    };
+    ParseError {
+        char_offset: SourceSpan::new(char_offset.into(), content.chars().count().max(1)),
+        line: line_number(file, byte_offset),
+        msg,
+    }
+}
+
+/// The byte offset of the start of the line that `byte_offset` is on.
+fn line_start(file: &str, byte_offset: usize) -> usize {
+    file[..byte_offset]
+        .rfind('\n')
+        .map_or(0, |newline| newline + 1)
+}
+
+/// The 1-based number of the line that `byte_offset` is on.
+fn line_number(file: &str, byte_offset: usize) -> usize {
+    file[..byte_offset].matches('\n').count() + 1
+}
+
+/// The byte offset just past the line starting at `line_start` and every line after it that's
+/// indented further.
+fn end_of_indented_block(file: &str, line_start: usize) -> usize {
+    let indent = |line: &str| line.len() - line.trim_start_matches(' ').len();
+    let mut lines = file[line_start..].split_inclusive('\n');
+    let Some(first) = lines.next() else {
+        return file.len();
+    };
+    let block_indent = indent(first);
+    let mut end = line_start + first.len();
+    for line in lines {
+        if line.trim().is_empty() || indent(line) <= block_indent {
+            break;
+        }
+        end += line.len();
     }
+    end
 }
 
 /// Parse a paragraph, i.e. something ending in a new line.
@@ -365,23 +499,23 @@ fn parse_bool<'i>(i: &mut Input<'i>) -> Res<bool> {
 
 fn parse_checksum<'i>(i: &mut Input<'i>) -> Res<Checksum<'i>> {
     // nokogiri (1.18.10-arm-linux-gnu) sha256=51f4f25ab5d5ba1012d6b16aad96b840a10b067b93f35af6a55a2c104a7ee322
+    // rack (3.2.3) sha256=3ab2…,sha512=9f1c…
     // rack (3.2.3)
     let release_tuple = parse_release_tuple.parse_next(i)?;
-    let value = opt((space1, "sha256=")).parse_next(i)?;
-    if value.is_some() {
-        let sha256 = parse_hex_string.try_map(hex::decode).parse_next(i)?;
-        Ok(Checksum {
-            release_tuple,
-            value: sha256,
-            algorithm: ChecksumAlgorithm::SHA256,
-        })
-    } else {
-        Ok(Checksum {
-            release_tuple,
-            value: vec![],
-            algorithm: ChecksumAlgorithm::None,
-        })
-    }
+    let digests: Option<Vec<_>> =
+        opt(preceded(space1, separated(1.., parse_digest, ','))).parse_next(i)?;
+    Ok(Checksum {
+        release_tuple,
+        digests: digests.unwrap_or_default(),
+    })
+}
+
+fn parse_digest<'i>(i: &mut Input<'i>) -> Res<Digest<'i>> {
+    let algorithm = terminated(parse_alphanum, '=')
+        .map(ChecksumAlgorithm::from_name)
+        .parse_next(i)?;
+    let value = parse_hex_string.try_map(hex::decode).parse_next(i)?;
+    Ok(Digest { algorithm, value })
 }
 
 fn parse_num(i: &mut Input<'_>) -> Res<u32> {
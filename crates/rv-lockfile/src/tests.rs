@@ -234,3 +234,298 @@ fn test_spec_count_multiple_platforms() {
     assert_eq!(lockfile.spec_count(), 7);
     assert_eq!(lockfile.gem_spec_count(), 7);
 }
+
+#[test]
+fn test_add_platform() {
+    use rv_gem_types::Platform;
+
+    let input = include_str!("../tests/inputs/Gemfile.one-for-multiple-platforms.lock");
+    let mut lockfile = must_parse(input);
+
+    let windows = Platform::new("x64-mingw-ucrt").unwrap();
+    assert!(!lockfile.has_native_platform(&windows));
+    assert!(lockfile.add_platform(windows.clone()));
+    assert!(lockfile.has_native_platform(&windows));
+
+    // Already listed platforms aren't added again
+    assert!(!lockfile.add_platform(windows));
+    assert!(!lockfile.add_platform(Platform::new("arm64-darwin").unwrap()));
+
+    let platforms: Vec<_> = lockfile.platforms.iter().map(|p| p.to_string()).collect();
+    assert_eq!(
+        platforms,
+        [
+            "aarch64-linux",
+            "aarch64-linux-musl",
+            "arm64-darwin",
+            "ruby",
+            "x64-mingw-ucrt",
+            "x86_64-darwin",
+            "x86_64-linux",
+            "x86_64-linux-musl",
+        ]
+    );
+}
+
+#[test]
+fn test_parse_sha512_and_unknown_checksums() {
+    use crate::datatypes::ChecksumAlgorithm;
+
+    let input = "\
+GEM
+  remote: https://rubygems.org/
+  specs:
+    minitest (5.25.5)
+    rack (3.2.3)
+    rake (13.3.0)
+
+PLATFORMS
+  ruby
+
+DEPENDENCIES
+  minitest
+  rack
+  rake
+
+CHECKSUMS
+  minitest (5.25.5) sha512=cdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcd
+  rack (3.2.3) sha256=abababababababababababababababababababababababababababababababab,sha512=cdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcd
+  rake (13.3.0) blake3=abababababababababababababababababababababababababababababababab
+
+BUNDLED WITH
+   2.6.9
+";
+    let lockfile = must_parse(input);
+    let algorithms: Vec<Vec<_>> = lockfile
+        .checksums
+        .unwrap()
+        .iter()
+        .map(|checksum| {
+            checksum
+                .digests
+                .iter()
+                .map(|digest| digest.algorithm.clone())
+                .collect()
+        })
+        .collect();
+    assert_eq!(
+        algorithms,
+        [
+            vec![ChecksumAlgorithm::SHA512],
+            vec![ChecksumAlgorithm::SHA256, ChecksumAlgorithm::SHA512],
+            vec![ChecksumAlgorithm::Unknown("blake3")],
+        ]
+    );
+}
+
+#[test]
+fn test_normalize_is_a_no_op_for_bundler_lockfiles() {
+    for input in [
+        include_str!("../tests/inputs/Gemfile.faker.lock"),
+        include_str!("../tests/inputs/Gemfile.withchecksums.lock"),
+        include_str!("../tests/inputs/Gemfile.one-for-multiple-platforms.lock"),
+        include_str!("../tests/inputs/Gemfile.gitlab.lock"),
+    ] {
+        let mut lockfile = must_parse(input);
+        lockfile.normalize();
+        assert_eq!(input, lockfile.to_string());
+    }
+}
+
+#[test]
+fn test_normalize_sorts_and_dedupes() {
+    let input = "\
+GEM
+  remote: https://rubygems.org/
+  specs:
+    rake (13.3.0)
+    minitest (5.25.5)
+    rake (13.3.0)
+    actionpack (8.0.2)
+      rack (>= 2.2.4)
+      activesupport (= 8.0.2)
+
+PLATFORMS
+  x86_64-linux
+  ruby
+  x86_64-linux
+
+DEPENDENCIES
+  rake
+  minitest
+  rake
+
+BUNDLED WITH
+   2.6.9
+";
+    let mut lockfile = crate::parse(input).unwrap();
+    lockfile.normalize();
+    assert_eq!(
+        lockfile.to_string(),
+        "\
+GEM
+  remote: https://rubygems.org/
+  specs:
+    actionpack (8.0.2)
+      activesupport (= 8.0.2)
+      rack (>= 2.2.4)
+    minitest (5.25.5)
+    rake (13.3.0)
+
+PLATFORMS
+  ruby
+  x86_64-linux
+
+DEPENDENCIES
+  minitest
+  rake
+
+BUNDLED WITH
+   2.6.9
+"
+    );
+}
+
+#[test]
+fn test_missing_ruby_fallbacks() {
+    let input = "\
+GEM
+  remote: https://rubygems.org/
+  specs:
+    nokogiri (1.18.8)
+    nokogiri (1.18.8-x86_64-linux-gnu)
+    sqlite3 (2.7.0-arm64-darwin)
+
+PLATFORMS
+  arm64-darwin
+  ruby
+  x86_64-linux-gnu
+
+DEPENDENCIES
+  nokogiri
+  sqlite3
+
+BUNDLED WITH
+   2.6.9
+";
+    let lockfile = crate::parse(input).unwrap();
+    let missing: Vec<_> = lockfile
+        .missing_ruby_fallbacks()
+        .into_iter()
+        .map(|tuple| tuple.full_name())
+        .collect();
+    assert_eq!(missing, ["sqlite3-2.7.0-arm64-darwin"]);
+
+    // Without the ruby platform, precompiled gems don't need a fallback.
+    let without_ruby = input.replace("  ruby\n", "");
+    let lockfile = crate::parse(&without_ruby).unwrap();
+    assert!(lockfile.missing_ruby_fallbacks().is_empty());
+}
+
+#[test]
+fn test_parse_lenient_skips_bad_lines() {
+    let input = "\
+GEM
+  remote: https://rubygems.org/
+  specs:
+    actionpack (8.0.2)
+      rack (>= 2.2.4)
+      activesupport (= 8.0.2
+      rack-test (>= 0.6.3)
+    minitest 5.25.5
+      ruby2_keywords
+    rake (13.3.0)
+
+PLATFORMS
+  ruby
+  x86_64-linux!
+  arm64-darwin
+
+DEPENDENCIES
+  actionpack
+  rake
+
+BUNDLED WITH
+   2.6.9
+";
+    let (lockfile, errors) = crate::parse_lenient(input);
+    let errors = errors.expect("the bad lines should be reported");
+    let lines: Vec<_> = errors.others.iter().map(|e| e.line()).collect();
+    assert_eq!(lines, [6, 8, 14]);
+    assert!(errors.others[0].message().contains("activesupport (= 8.0.2"));
+    assert!(errors.others[1].message().contains("minitest 5.25.5"));
+
+    // Everything around the bad lines is still there.
+    let specs: Vec<_> = lockfile.gem[0]
+        .specs
+        .iter()
+        .map(|spec| spec.release_tuple.full_name())
+        .collect();
+    assert_eq!(specs, ["actionpack-8.0.2", "rake-13.3.0"]);
+    let deps: Vec<_> = lockfile.gem[0].specs[0]
+        .deps
+        .iter()
+        .map(|dep| dep.name.as_str())
+        .collect();
+    assert_eq!(deps, ["rack", "rack-test"]);
+    let platforms: Vec<_> = lockfile.platforms.iter().map(|p| p.to_string()).collect();
+    assert_eq!(platforms, ["ruby", "arm64-darwin"]);
+    assert_eq!(lockfile.dependencies.len(), 2);
+    assert!(lockfile.bundled_with.is_some());
+
+    // The strict parser reports the same problems.
+    let strict = crate::parse(input).unwrap_err();
+    assert_eq!(strict.others.len(), 3);
+}
+
+#[test]
+fn test_parse_lenient_skips_bad_sections() {
+    let input = "\
+GIT
+  remote: https://github.com/rails/rails.git
+  revision: not-a-sha
+  specs:
+    rails (8.1.0.alpha)
+
+GEM
+  remote: https://rubygems.org/
+  specs:
+    rake (13.3.0)
+
+DEPENDENCIES
+  rake
+";
+    let (lockfile, errors) = crate::parse_lenient(input);
+    let errors = errors.unwrap();
+    assert_eq!(errors.others.len(), 1);
+    assert_eq!(errors.others[0].line(), 3);
+    assert!(lockfile.git.is_empty());
+    assert_eq!(lockfile.gem_spec_count(), 1);
+    assert_eq!(lockfile.dependencies.len(), 1);
+}
+
+#[test]
+fn test_parse_lenient_without_errors() {
+    let input = include_str!("../tests/inputs/Gemfile.discourse.lock");
+    let (lockfile, errors) = crate::parse_lenient(input);
+    assert!(errors.is_none());
+    assert_eq!(input, lockfile.to_string());
+}
+
+#[test]
+fn test_write_preserving_line_endings() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = camino::Utf8PathBuf::from_path_buf(dir.path().join("Gemfile.lock")).unwrap();
+    let lf = "GEM\n  specs:\n\nBUNDLED WITH\n   2.6.2\n";
+    let crlf = lf.replace('\n', "\r\n");
+
+    crate::write_preserving_line_endings(&path, lf, lf).unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), lf);
+
+    crate::write_preserving_line_endings(&path, lf, &crlf).unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), crlf);
+
+    // Contents that already have Windows line endings keep them as they are.
+    crate::write_preserving_line_endings(&path, &crlf, &crlf).unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), crlf);
+}
@@ -1,3 +1,6 @@
+use std::str::FromStr;
+use std::sync::OnceLock;
+
 use current_platform::CURRENT_PLATFORM;
 #[cfg(test)]
 use proptest::prelude::*;
@@ -11,6 +14,233 @@ pub struct UnsupportedPlatformError {
     pub platform: String,
 }
 
+/// Error returned when a libc name can't be parsed.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("unknown libc {libc:?}, expected one of: gnu, musl")]
+pub struct UnknownLibcError {
+    pub libc: String,
+}
+
+/// Error returned when the headers of an ELF binary point outside of it.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("malformed ELF binary: {0}")]
+pub struct MalformedElfError(String);
+
+/// The C library a Linux host links against.
+///
+/// Prebuilt rubies are linked against a specific libc, so a glibc build won't
+/// run on Alpine (and vice versa), regardless of which libc rv itself was
+/// compiled for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(test, derive(Arbitrary))]
+pub enum Libc {
+    Gnu,
+    Musl,
+}
+
+static LIBC_OVERRIDE: OnceLock<Libc> = OnceLock::new();
+static DETECTED_LIBC: OnceLock<Libc> = OnceLock::new();
+
+impl Libc {
+    /// Force the libc used by [`HostPlatform::current`], skipping detection.
+    ///
+    /// Only the first override for the process takes effect.
+    pub fn set_override(libc: Self) {
+        let _ = LIBC_OVERRIDE.set(libc);
+    }
+
+    /// The libc of the running host.
+    ///
+    /// Uses the override if one was set, otherwise detects it once per process.
+    pub fn current() -> Self {
+        if let Some(libc) = LIBC_OVERRIDE.get() {
+            return *libc;
+        }
+
+        *DETECTED_LIBC.get_or_init(Self::detect)
+    }
+
+    /// Detect the host libc by inspecting the system.
+    ///
+    /// In order, checks the ELF interpreter of `/bin/sh`, the output of
+    /// `ldd --version`, and finally `/etc/os-release`. Falls back to the libc
+    /// rv was compiled against if none of them are conclusive.
+    pub fn detect() -> Self {
+        if !cfg!(target_os = "linux") {
+            return Self::compiled();
+        }
+
+        std::fs::read("/bin/sh")
+            .ok()
+            .and_then(|bytes| match Self::from_elf_interpreter(&bytes) {
+                Ok(libc) => libc,
+                // A malformed binary is as inconclusive as a missing one.
+                Err(err) => {
+                    tracing::warn!("Could not read the libc of /bin/sh: {err}");
+                    None
+                }
+            })
+            .or_else(|| {
+                std::process::Command::new("ldd")
+                    .arg("--version")
+                    .output()
+                    .ok()
+                    .and_then(|output| {
+                        // musl's ldd prints its version banner to stderr.
+                        let mut text = String::from_utf8_lossy(&output.stdout).into_owned();
+                        text.push_str(&String::from_utf8_lossy(&output.stderr));
+                        Self::from_ldd_output(&text)
+                    })
+            })
+            .or_else(|| {
+                std::fs::read_to_string("/etc/os-release")
+                    .ok()
+                    .and_then(|text| Self::from_os_release(&text))
+            })
+            .unwrap_or_else(Self::compiled)
+    }
+
+    /// The libc of the target rv was compiled for.
+    fn compiled() -> Self {
+        if CURRENT_PLATFORM.ends_with("-musl") {
+            Self::Musl
+        } else {
+            Self::Gnu
+        }
+    }
+
+    /// Determine the libc from the `PT_INTERP` program header of an ELF binary. `None` if
+    /// `bytes` isn't an ELF binary, or its interpreter doesn't tell.
+    fn from_elf_interpreter(bytes: &[u8]) -> Result<Option<Self>, MalformedElfError> {
+        const PT_INTERP: u32 = 3;
+
+        if !bytes.starts_with(b"\x7fELF") {
+            return Ok(None);
+        }
+
+        let is_64 = match bytes.get(4) {
+            Some(1) => false,
+            Some(2) => true,
+            class => return Err(MalformedElfError(format!("unknown class {class:?}"))),
+        };
+        let little_endian = match bytes.get(5) {
+            Some(1) => true,
+            Some(2) => false,
+            encoding => {
+                return Err(MalformedElfError(format!(
+                    "unknown data encoding {encoding:?}"
+                )));
+            }
+        };
+
+        let read = |offset: usize, len: usize| -> Result<u64, MalformedElfError> {
+            let slice = offset
+                .checked_add(len)
+                .and_then(|end| bytes.get(offset..end))
+                .ok_or_else(|| {
+                    MalformedElfError(format!(
+                        "{len} bytes at offset {offset} are past its end, at {}",
+                        bytes.len()
+                    ))
+                })?;
+            let mut buf = [0u8; 8];
+            if little_endian {
+                buf[..len].copy_from_slice(slice);
+                Ok(u64::from_le_bytes(buf))
+            } else {
+                buf[8 - len..].copy_from_slice(slice);
+                Ok(u64::from_be_bytes(buf))
+            }
+        };
+
+        let (phoff, phentsize, phnum) = if is_64 {
+            (read(0x20, 8)?, read(0x36, 2)?, read(0x38, 2)?)
+        } else {
+            (read(0x1c, 4)?, read(0x2a, 2)?, read(0x2c, 2)?)
+        };
+
+        for index in 0..phnum {
+            let header = index
+                .checked_mul(phentsize)
+                .and_then(|offset| offset.checked_add(phoff))
+                .and_then(|offset| usize::try_from(offset).ok())
+                .ok_or_else(|| {
+                    MalformedElfError(format!(
+                        "program header {index} is past the end of the address space"
+                    ))
+                })?;
+            if read(header, 4)? as u32 != PT_INTERP {
+                continue;
+            }
+
+            let (offset, size) = if is_64 {
+                (read(header + 0x08, 8)?, read(header + 0x20, 8)?)
+            } else {
+                (read(header + 0x04, 4)?, read(header + 0x10, 4)?)
+            };
+            let interpreter = usize::try_from(offset)
+                .ok()
+                .zip(usize::try_from(size).ok())
+                .and_then(|(start, size)| bytes.get(start..start.checked_add(size)?))
+                .ok_or_else(|| {
+                    MalformedElfError(format!(
+                        "the interpreter, {size} bytes at offset {offset}, is past its end, at {}",
+                        bytes.len()
+                    ))
+                })?;
+            let interpreter = String::from_utf8_lossy(interpreter);
+
+            if interpreter.contains("musl") {
+                return Ok(Some(Self::Musl));
+            } else if interpreter.contains("ld-linux") {
+                return Ok(Some(Self::Gnu));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Determine the libc from the output of `ldd --version`.
+    fn from_ldd_output(output: &str) -> Option<Self> {
+        let output = output.to_lowercase();
+
+        if output.contains("musl") {
+            Some(Self::Musl)
+        } else if output.contains("glibc") || output.contains("gnu libc") {
+            Some(Self::Gnu)
+        } else {
+            None
+        }
+    }
+
+    /// Determine the libc from the contents of `/etc/os-release`.
+    ///
+    /// Only recognizes distributions that are known to ship musl.
+    fn from_os_release(contents: &str) -> Option<Self> {
+        const MUSL_DISTROS: &[&str] = &["alpine", "chimera", "postmarketos", "void-musl"];
+
+        contents
+            .lines()
+            .filter_map(|line| line.strip_prefix("ID="))
+            .map(|id| id.trim().trim_matches('"'))
+            .find(|id| MUSL_DISTROS.contains(id))
+            .map(|_| Self::Musl)
+    }
+}
+
+impl FromStr for Libc {
+    type Err = UnknownLibcError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "gnu" | "glibc" => Ok(Self::Gnu),
+            "musl" => Ok(Self::Musl),
+            other => Err(UnknownLibcError {
+                libc: other.to_string(),
+            }),
+        }
+    }
+}
+
 /// Represents the host platforms that rv supports.
 ///
 /// Using an enum with no wildcard fallback ensures the compiler enforces
@@ -33,12 +263,40 @@ impl HostPlatform {
     /// Detect the current host platform.
     ///
     /// Checks the `RV_TEST_PLATFORM` env var first (for testing), then falls
-    /// back to the compile-time `CURRENT_PLATFORM`.
+    /// back to the compile-time `CURRENT_PLATFORM`, with the libc adjusted to
+    /// what the host actually uses (see [`Libc::current`]).
     pub fn current() -> Result<Self, UnsupportedPlatformError> {
         if let Ok(platform) = std::env::var("RV_TEST_PLATFORM") {
             Self::from_target_triple(&platform)
         } else {
-            Self::from_target_triple(CURRENT_PLATFORM)
+            Self::from_target_triple(CURRENT_PLATFORM).map(|host| host.with_libc(Libc::current()))
+        }
+    }
+
+    /// The same OS and architecture, but linked against the given libc.
+    ///
+    /// Platforms without a libc choice (macOS, Windows) are returned unchanged.
+    pub fn with_libc(self, libc: Libc) -> Self {
+        match (self, libc) {
+            (Self::LinuxX86_64 | Self::LinuxMuslX86_64, Libc::Gnu) => Self::LinuxX86_64,
+            (Self::LinuxX86_64 | Self::LinuxMuslX86_64, Libc::Musl) => Self::LinuxMuslX86_64,
+            (Self::LinuxAarch64 | Self::LinuxMuslAarch64, Libc::Gnu) => Self::LinuxAarch64,
+            (Self::LinuxAarch64 | Self::LinuxMuslAarch64, Libc::Musl) => Self::LinuxMuslAarch64,
+            (
+                Self::MacosAarch64 | Self::MacosX86_64 | Self::WindowsX86_64 | Self::WindowsAarch64,
+                _,
+            ) => self,
+        }
+    }
+
+    /// The libc this platform links against, if it's a Linux platform.
+    pub fn libc(&self) -> Option<Libc> {
+        match self {
+            Self::LinuxX86_64 | Self::LinuxAarch64 => Some(Libc::Gnu),
+            Self::LinuxMuslX86_64 | Self::LinuxMuslAarch64 => Some(Libc::Musl),
+            Self::MacosAarch64 | Self::MacosX86_64 | Self::WindowsX86_64 | Self::WindowsAarch64 => {
+                None
+            }
         }
     }
 
@@ -345,6 +603,111 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_with_libc() {
+        assert_eq!(
+            HostPlatform::LinuxX86_64.with_libc(Libc::Musl),
+            HostPlatform::LinuxMuslX86_64
+        );
+        assert_eq!(
+            HostPlatform::LinuxMuslAarch64.with_libc(Libc::Gnu),
+            HostPlatform::LinuxAarch64
+        );
+        assert_eq!(
+            HostPlatform::MacosAarch64.with_libc(Libc::Musl),
+            HostPlatform::MacosAarch64
+        );
+        assert_eq!(
+            HostPlatform::WindowsX86_64.with_libc(Libc::Musl),
+            HostPlatform::WindowsX86_64
+        );
+    }
+
+    #[test]
+    fn test_libc_from_str() {
+        assert_eq!("gnu".parse::<Libc>().unwrap(), Libc::Gnu);
+        assert_eq!("glibc".parse::<Libc>().unwrap(), Libc::Gnu);
+        assert_eq!("musl".parse::<Libc>().unwrap(), Libc::Musl);
+        assert_eq!("uclibc".parse::<Libc>().unwrap_err().libc, "uclibc");
+    }
+
+    /// Build a minimal 64-bit little-endian ELF image with a single `PT_INTERP` header.
+    fn elf_with_interpreter(interpreter: &str) -> Vec<u8> {
+        let mut bytes = vec![0u8; 0x40 + 0x38];
+        bytes[..4].copy_from_slice(b"\x7fELF");
+        bytes[4] = 2; // ELFCLASS64
+        bytes[5] = 1; // ELFDATA2LSB
+        bytes[0x20..0x28].copy_from_slice(&0x40u64.to_le_bytes()); // e_phoff
+        bytes[0x36..0x38].copy_from_slice(&0x38u16.to_le_bytes()); // e_phentsize
+        bytes[0x38..0x3a].copy_from_slice(&1u16.to_le_bytes()); // e_phnum
+
+        let interp_offset = bytes.len() as u64;
+        bytes[0x40..0x44].copy_from_slice(&3u32.to_le_bytes()); // p_type = PT_INTERP
+        bytes[0x48..0x50].copy_from_slice(&interp_offset.to_le_bytes()); // p_offset
+        bytes[0x60..0x68].copy_from_slice(&(interpreter.len() as u64).to_le_bytes()); // p_filesz
+        bytes.extend_from_slice(interpreter.as_bytes());
+        bytes
+    }
+
+    #[test]
+    fn test_libc_from_elf_interpreter() {
+        let musl = elf_with_interpreter("/lib/ld-musl-x86_64.so.1");
+        assert_eq!(Libc::from_elf_interpreter(&musl), Ok(Some(Libc::Musl)));
+
+        let gnu = elf_with_interpreter("/lib64/ld-linux-x86-64.so.2");
+        assert_eq!(Libc::from_elf_interpreter(&gnu), Ok(Some(Libc::Gnu)));
+
+        let unknown = elf_with_interpreter("/system/bin/linker64");
+        assert_eq!(Libc::from_elf_interpreter(&unknown), Ok(None));
+
+        assert_eq!(Libc::from_elf_interpreter(b"#!/bin/sh\n"), Ok(None));
+        assert!(Libc::from_elf_interpreter(&musl[..0x50]).is_err());
+    }
+
+    #[test]
+    fn test_libc_from_malformed_elf_interpreter() {
+        let musl = elf_with_interpreter("/lib/ld-musl-x86_64.so.1");
+
+        // A program header table that wraps around the address space.
+        let mut wrapping = musl.clone();
+        wrapping[0x20..0x28].copy_from_slice(&u64::MAX.to_le_bytes());
+        wrapping[0x38..0x3a].copy_from_slice(&2u16.to_le_bytes());
+        assert!(Libc::from_elf_interpreter(&wrapping).is_err());
+
+        // A program header table past the end of the file.
+        let mut past_end = musl.clone();
+        past_end[0x20..0x28].copy_from_slice(&0x10000u64.to_le_bytes());
+        assert!(Libc::from_elf_interpreter(&past_end).is_err());
+
+        // An interpreter that runs past the end of the file.
+        let mut long_interpreter = musl.clone();
+        long_interpreter[0x60..0x68].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(Libc::from_elf_interpreter(&long_interpreter).is_err());
+    }
+
+    #[test]
+    fn test_libc_from_ldd_output() {
+        let musl = "musl libc (x86_64)\nVersion 1.2.5\nDynamic Program Loader\n";
+        assert_eq!(Libc::from_ldd_output(musl), Some(Libc::Musl));
+
+        let gnu = "ldd (Ubuntu GLIBC 2.39-0ubuntu8) 2.39\nCopyright (C) 2024 Free Software Foundation, Inc.\n";
+        assert_eq!(Libc::from_ldd_output(gnu), Some(Libc::Gnu));
+
+        assert_eq!(Libc::from_ldd_output(""), None);
+    }
+
+    #[test]
+    fn test_libc_from_os_release() {
+        let alpine = "NAME=\"Alpine Linux\"\nID=alpine\nVERSION_ID=3.20.0\n";
+        assert_eq!(Libc::from_os_release(alpine), Some(Libc::Musl));
+
+        let quoted = "ID=\"alpine\"\n";
+        assert_eq!(Libc::from_os_release(quoted), Some(Libc::Musl));
+
+        let debian = "ID=debian\nID_LIKE=\"ubuntu\"\n";
+        assert_eq!(Libc::from_os_release(debian), None);
+    }
+
     proptest! {
         /// If this test fails, you forgot to add your new variant of `HostPlatform`
         /// to `HostPlatform::all`
@@ -352,5 +715,15 @@ mod tests {
         fn platform_in_list_of_all_platforms(host_platform: HostPlatform) {
             assert!(HostPlatform::all().contains(&host_platform))
         }
+
+        #[test]
+        fn with_libc_round_trips(host_platform: HostPlatform, libc: Libc) {
+            let switched = host_platform.with_libc(libc);
+            assert_eq!(switched.os() == "macos" || switched.is_windows(), switched.libc().is_none());
+            if let Some(original) = host_platform.libc() {
+                assert_eq!(switched.libc(), Some(libc));
+                assert_eq!(switched.with_libc(original), host_platform);
+            }
+        }
     }
 }
@@ -0,0 +1,98 @@
+//! Where a bundle's gems live inside its install path, laid out like RubyGems and Bundler do, so
+//! every command that installs, runs or removes gems finds them in the same places.
+//!
+//! ```text
+//! <install_path>/bin/<executable>
+//! <install_path>/gems/<full_name>
+//! <install_path>/specifications/<full_name>.gemspec
+//! <install_path>/extensions/<platform>/<extension api version>/<full_name>
+//! <install_path>/bundler/gems/<repo name>-<revision>
+//! ```
+//!
+//! A gem's full name includes its platform when it isn't `ruby`, like
+//! `nokogiri-1.18.9-x86_64-linux`.
+
+use camino::Utf8PathBuf;
+
+use crate::Ruby;
+
+/// The directories of a bundle installed in `install_path` for one Ruby.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BundleLayout {
+    pub install_path: Utf8PathBuf,
+    /// Where compiled extensions go for the Ruby, from [`Ruby::extensions_scope`].
+    pub extensions_scope: String,
+}
+
+impl BundleLayout {
+    /// The layout of a bundle installed in `install_path` for `ruby`.
+    pub fn new(install_path: impl Into<Utf8PathBuf>, ruby: &Ruby) -> Self {
+        Self {
+            install_path: install_path.into(),
+            extensions_scope: ruby.extensions_scope(),
+        }
+    }
+
+    pub fn binstub_dir(&self) -> Utf8PathBuf {
+        self.install_path.join("bin")
+    }
+
+    pub fn gems_dir(&self) -> Utf8PathBuf {
+        self.install_path.join("gems")
+    }
+
+    pub fn gem_path(&self, full_name: &str) -> Utf8PathBuf {
+        self.gems_dir().join(full_name)
+    }
+
+    pub fn specifications_dir(&self) -> Utf8PathBuf {
+        self.install_path.join("specifications")
+    }
+
+    pub fn spec_path(&self, full_name: &str) -> Utf8PathBuf {
+        self.specifications_dir()
+            .join(format!("{full_name}.gemspec"))
+    }
+
+    /// Where a gem from a git source is checked out, by the directory name Bundler gives it.
+    pub fn git_gem_path(&self, dir_name: &str) -> Utf8PathBuf {
+        self.install_path.join("bundler/gems").join(dir_name)
+    }
+
+    pub fn extensions_dir(&self, full_name: &str) -> Utf8PathBuf {
+        self.install_path
+            .join("extensions")
+            .join(&self.extensions_scope)
+            .join(full_name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_paths() {
+        let layout = BundleLayout {
+            install_path: "/app/vendor/bundle/ruby/3.4.0".into(),
+            extensions_scope: "x86_64-linux/3.4.0".to_string(),
+        };
+        assert_eq!(
+            layout.gem_path("nokogiri-1.18.9-x86_64-linux"),
+            "/app/vendor/bundle/ruby/3.4.0/gems/nokogiri-1.18.9-x86_64-linux"
+        );
+        assert_eq!(
+            layout.spec_path("rake-13.3.0"),
+            "/app/vendor/bundle/ruby/3.4.0/specifications/rake-13.3.0.gemspec"
+        );
+        assert_eq!(
+            layout.extensions_dir("json-2.9.1"),
+            "/app/vendor/bundle/ruby/3.4.0/extensions/x86_64-linux/3.4.0/json-2.9.1"
+        );
+        assert_eq!(
+            layout.git_gem_path("rails-0123456789ab"),
+            "/app/vendor/bundle/ruby/3.4.0/bundler/gems/rails-0123456789ab"
+        );
+        assert_eq!(layout.binstub_dir(), "/app/vendor/bundle/ruby/3.4.0/bin");
+    }
+}
@@ -3,6 +3,7 @@ use std::{
     str::FromStr,
 };
 
+use camino::{Utf8Path, Utf8PathBuf};
 use rv_cache::{CacheKey, CacheKeyHasher};
 use serde::{Deserialize, Serialize};
 
@@ -42,6 +43,121 @@ impl RubyEngine {
     }
 }
 
+/// What's different about each Ruby implementation, for the code that finds, activates and runs
+/// rubies. The defaults describe CRuby; other engines override what they do differently. Get an
+/// engine's quirks with [`RubyEngine::quirks`].
+pub trait EngineQuirks: Sync {
+    /// Names of the executables in `bin/` that start the engine, in order of preference.
+    fn executable_names(&self) -> &'static [&'static str] {
+        if cfg!(windows) {
+            &["ruby.exe", "ruby.cmd"]
+        } else {
+            &["ruby"]
+        }
+    }
+
+    /// Where gems are installed by default, relative to the installation, for a Ruby whose ABI
+    /// version is `abi`.
+    fn gem_dir(&self, abi: &str) -> Utf8PathBuf {
+        Utf8PathBuf::from(format!("lib/ruby/gems/{abi}"))
+    }
+
+    /// The environment variable the engine reads extra command-line options from.
+    fn options_var(&self) -> &'static str {
+        "RUBYOPT"
+    }
+
+    /// Environment variables to set, besides the ones every Ruby gets, to activate the
+    /// installation in `dir`.
+    fn env(&self, dir: &Utf8Path) -> Vec<(&'static str, String)> {
+        let _ = dir;
+        vec![]
+    }
+
+    /// Environment variables the engine can't run without, which only the user can set.
+    fn required_env(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    /// How to show a version of the engine called `name`, e.g. `ruby-3.4.1`.
+    fn display_version(&self, name: &str, number: &str) -> String {
+        format!("{name}-{number}")
+    }
+}
+
+struct CRubyQuirks;
+
+impl EngineQuirks for CRubyQuirks {}
+
+struct JRubyQuirks;
+
+impl EngineQuirks for JRubyQuirks {
+    fn executable_names(&self) -> &'static [&'static str] {
+        if cfg!(windows) {
+            &["jruby.exe", "jruby.bat", "ruby.exe"]
+        } else {
+            &["jruby", "ruby"]
+        }
+    }
+
+    /// JRuby shares one gem directory between all of its versions.
+    fn gem_dir(&self, _abi: &str) -> Utf8PathBuf {
+        Utf8PathBuf::from("lib/ruby/gems/shared")
+    }
+
+    fn options_var(&self) -> &'static str {
+        "JRUBY_OPTS"
+    }
+
+    fn env(&self, dir: &Utf8Path) -> Vec<(&'static str, String)> {
+        vec![("JRUBY_HOME", dir.to_string())]
+    }
+
+    fn required_env(&self) -> &'static [&'static str] {
+        &["JAVA_HOME"]
+    }
+}
+
+struct TruffleRubyQuirks;
+
+impl EngineQuirks for TruffleRubyQuirks {
+    fn executable_names(&self) -> &'static [&'static str] {
+        &["truffleruby", "ruby"]
+    }
+
+    fn gem_dir(&self, _abi: &str) -> Utf8PathBuf {
+        Utf8PathBuf::from("lib/gems")
+    }
+
+    /// Where polyglot flags like `--polyglot` and `--jvm` go.
+    fn options_var(&self) -> &'static str {
+        "TRUFFLERUBYOPT"
+    }
+}
+
+/// Every executable name a Ruby installation could have, before it's known which engine it is.
+pub(crate) fn all_executable_names() -> impl Iterator<Item = &'static str> {
+    [
+        &CRubyQuirks as &dyn EngineQuirks,
+        &JRubyQuirks,
+        &TruffleRubyQuirks,
+    ]
+    .into_iter()
+    .flat_map(|quirks| quirks.executable_names().iter().copied())
+}
+
+impl RubyEngine {
+    /// What's different about this engine. Engines rv doesn't know about are assumed to work
+    /// like CRuby.
+    pub fn quirks(&self) -> &'static dyn EngineQuirks {
+        match self {
+            Self::JRuby => &JRubyQuirks,
+            Self::TruffleRuby => &TruffleRubyQuirks,
+            Self::Ruby | Self::MRuby | Self::Artichoke | Self::Unknown(_) => &CRubyQuirks,
+        }
+    }
+}
+
 impl Display for RubyEngine {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}", self.name())
@@ -144,6 +260,37 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_engine_quirks() {
+        let ruby = RubyEngine::Ruby.quirks();
+        let jruby = RubyEngine::JRuby.quirks();
+        let truffleruby = RubyEngine::TruffleRuby.quirks();
+        let unknown = RubyEngine::Unknown("custom-ruby".to_string()).quirks();
+
+        assert_eq!(ruby.gem_dir("3.4.0"), "lib/ruby/gems/3.4.0");
+        assert_eq!(jruby.gem_dir("9.4.0"), "lib/ruby/gems/shared");
+        assert_eq!(truffleruby.gem_dir("24.1.0"), "lib/gems");
+        assert_eq!(unknown.gem_dir("1.0.0"), "lib/ruby/gems/1.0.0");
+
+        assert_eq!(ruby.options_var(), "RUBYOPT");
+        assert_eq!(jruby.options_var(), "JRUBY_OPTS");
+        assert_eq!(truffleruby.options_var(), "TRUFFLERUBYOPT");
+
+        let dir = Utf8Path::new("/rubies/jruby-9.4.8.0");
+        assert_eq!(
+            jruby.env(dir),
+            vec![("JRUBY_HOME", "/rubies/jruby-9.4.8.0".to_string())]
+        );
+        assert!(ruby.env(dir).is_empty());
+        assert_eq!(jruby.required_env(), ["JAVA_HOME"]);
+
+        assert_eq!(ruby.display_version("ruby", "3.4.1"), "ruby-3.4.1");
+
+        let names: Vec<_> = all_executable_names().collect();
+        assert_eq!(names[0], ruby.executable_names()[0]);
+        assert!(names.contains(&truffleruby.executable_names()[0]));
+    }
+
     #[test]
     fn test_engine_ordering() {
         let ruby = RubyEngine::Ruby;
@@ -0,0 +1,139 @@
+//! Where the gems installed with `gem install --user-install` go, which is the first entry on
+//! `GEM_PATH` when rv activates a Ruby.
+//!
+//! rv keeps them in `~/.local/share/rv/gems/<engine>/<abi>`, but RubyGems' own default is
+//! `~/.gem/<engine>/<abi>`. Until a layout is chosen with `rv migrate gem-home`, rv uses the
+//! legacy directory for a Ruby if it's there, so gems installed before rv are still found.
+
+use std::io;
+use std::str::FromStr;
+
+use camino::{Utf8Path, Utf8PathBuf};
+
+use crate::engine::RubyEngine;
+use crate::user_gems_dir;
+
+/// The name of the file that records the chosen layout, next to rv's gem directory.
+const LAYOUT_FILE: &str = "gem-home-layout";
+
+/// Which directory the gems installed with `gem install --user-install` go in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GemHomeLayout {
+    /// `~/.local/share/rv/gems/<engine>/<abi>`.
+    Rv,
+    /// `~/.gem/<engine>/<abi>`.
+    Legacy,
+}
+
+impl GemHomeLayout {
+    /// The layout chosen with `rv migrate gem-home`, if one was.
+    pub fn chosen() -> Option<Self> {
+        let layout = std::fs::read_to_string(layout_file()).ok()?;
+        layout.trim().parse().ok()
+    }
+
+    /// Remember `self` as the chosen layout.
+    pub fn choose(self) -> io::Result<()> {
+        rv_cache::write_atomic(layout_file(), format!("{}\n", self.name()))
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Rv => "rv",
+            Self::Legacy => "legacy",
+        }
+    }
+
+    /// The directory with a gem home for each engine and ABI version.
+    pub fn gems_dir(self) -> Utf8PathBuf {
+        match self {
+            Self::Rv => user_gems_dir(),
+            Self::Legacy => legacy_gems_dir(),
+        }
+    }
+}
+
+impl FromStr for GemHomeLayout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rv" => Ok(Self::Rv),
+            "legacy" => Ok(Self::Legacy),
+            other => Err(format!("unknown gem home layout {other}")),
+        }
+    }
+}
+
+fn layout_file() -> Utf8PathBuf {
+    user_gems_dir().with_file_name(LAYOUT_FILE)
+}
+
+/// Where RubyGems puts the gems installed with `gem install --user-install` by default.
+pub fn legacy_gems_dir() -> Utf8PathBuf {
+    rv_dirs::home_dir().join(".gem")
+}
+
+/// The gem homes in `gems_dir`, like `ruby/3.3.0`, as their scope and their path. Only the
+/// directories of known engines count, which leaves out RubyGems' `specs` cache.
+pub fn gem_homes_in(gems_dir: &Utf8Path) -> Vec<(String, Utf8PathBuf)> {
+    let Ok(engines) = gems_dir.read_dir_utf8() else {
+        return Vec::new();
+    };
+    let mut gem_homes: Vec<(String, Utf8PathBuf)> = engines
+        .flatten()
+        .filter(|engine| !matches!(RubyEngine::from(engine.file_name()), RubyEngine::Unknown(_)))
+        .filter_map(|engine| {
+            let versions = engine.path().read_dir_utf8().ok()?;
+            let engine_name = engine.file_name().to_string();
+            Some(versions.flatten().filter_map(move |version| {
+                version.path().is_dir().then(|| {
+                    (
+                        format!("{engine_name}/{}", version.file_name()),
+                        version.into_path(),
+                    )
+                })
+            }))
+        })
+        .flatten()
+        .collect();
+    gem_homes.sort();
+    gem_homes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_layout_names_round_trip() {
+        for layout in [GemHomeLayout::Rv, GemHomeLayout::Legacy] {
+            assert_eq!(layout.name().parse(), Ok(layout));
+        }
+        assert!("xdg".parse::<GemHomeLayout>().is_err());
+    }
+
+    #[test]
+    fn test_gem_homes_in() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let dir = Utf8Path::from_path(temp_dir.path()).unwrap();
+        for path in [
+            "ruby/3.3.0",
+            "ruby/3.4.0",
+            "jruby/3.1.0",
+            "specs/index.rubygems.org%443",
+        ] {
+            std::fs::create_dir_all(dir.join(path)).unwrap();
+        }
+        std::fs::write(dir.join("credentials"), "").unwrap();
+
+        let scopes: Vec<String> = gem_homes_in(dir)
+            .into_iter()
+            .map(|(scope, path)| {
+                assert_eq!(path, dir.join(&scope));
+                scope
+            })
+            .collect();
+        assert_eq!(scopes, vec!["jruby/3.1.0", "ruby/3.3.0", "ruby/3.4.0"]);
+    }
+}
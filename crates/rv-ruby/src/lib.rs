@@ -1,5 +1,7 @@
+pub mod bundle_layout;
 pub mod canonical_name;
 pub mod engine;
+pub mod gem_home;
 pub mod request;
 pub mod version;
 
@@ -13,32 +15,36 @@ use std::env::{
     consts::{ARCH, OS},
 };
 use std::process::Command;
-use tracing::instrument;
+use tracing::{debug, instrument};
 
+use crate::gem_home::GemHomeLayout;
 use crate::version::RubyVersion;
 
-/// Returns the possible Ruby executable names for the current platform, in priority order.
-/// On Windows, checks `ruby.exe` (standard RubyInstaller2) then `ruby.cmd` (batch wrapper).
-/// On Unix systems (macOS, Linux), it's just `ruby`.
-fn ruby_executable_names() -> &'static [&'static str] {
-    if cfg!(windows) {
-        &["ruby.exe", "ruby.cmd"]
-    } else {
-        &["ruby"]
-    }
+/// Find the Ruby executable in a directory's `bin/` subdirectory, trying the name of every engine's
+/// executable (see [`EngineQuirks::executable_names`](engine::EngineQuirks::executable_names)).
+pub fn find_ruby_executable(dir: &Utf8Path) -> Option<Utf8PathBuf> {
+    find_executable(dir, engine::all_executable_names())
 }
 
-/// Find the Ruby executable in a directory's `bin/` subdirectory.
-/// Returns the first matching executable name from [`ruby_executable_names`].
-pub fn find_ruby_executable(dir: &Utf8Path) -> Option<Utf8PathBuf> {
+fn find_executable<'a>(
+    dir: &Utf8Path,
+    names: impl IntoIterator<Item = &'a str>,
+) -> Option<Utf8PathBuf> {
     let bin_dir = dir.join("bin");
-    for name in ruby_executable_names() {
-        let path = bin_dir.join(name);
-        if path.exists() {
-            return Some(path);
-        }
-    }
-    None
+    names
+        .into_iter()
+        .map(|name| bin_dir.join(name))
+        .find(|path| path.exists())
+}
+
+/// Where rv keeps the gems each user installs with `gem install --user-install`, in a directory
+/// per [`Ruby::gem_scope`].
+pub fn user_gems_dir() -> Utf8PathBuf {
+    rv_dirs::home_dir()
+        .join(".local")
+        .join("share")
+        .join("rv")
+        .join("gems")
 }
 
 static RUBY_DESCRIPTION_REGEX: Lazy<Regex> = Lazy::new(|| {
@@ -55,6 +61,9 @@ pub struct Release {
 pub struct Asset {
     pub name: String,
     pub browser_download_url: String,
+    /// The archive's digest, like `sha256:4f2a…`, if its source publishes one. GitHub's API does.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub digest: Option<String>,
 }
 
 pub trait Versioned {
@@ -166,11 +175,13 @@ impl Ruby {
         find_ruby_executable(&self.path).is_some()
     }
 
-    /// Get the path to the Ruby executable.
-    /// Checks for the first matching name from [`ruby_executable_names`].
+    /// Get the path to the Ruby executable: the first of its engine's executable names that
+    /// exists, or any Ruby executable.
     pub fn executable_path(&self) -> Utf8PathBuf {
-        find_ruby_executable(&self.path)
-            .unwrap_or_else(|| self.bin_path().join(ruby_executable_names()[0]))
+        let names = self.version.engine.quirks().executable_names();
+        find_executable(&self.path, names.iter().copied())
+            .or_else(|| find_ruby_executable(&self.path))
+            .unwrap_or_else(|| self.bin_path().join(names[0]))
     }
 
     pub fn bin_path(&self) -> Utf8PathBuf {
@@ -178,31 +189,30 @@ impl Ruby {
     }
 
     pub fn gem_home(&self) -> Utf8PathBuf {
-        self.gem_root().unwrap_or(
-            self.path
-                .join("lib")
-                .join("ruby")
-                .join("gems")
-                .join(self.version.abi()),
-        )
+        self.gem_root().unwrap_or_else(|| {
+            let quirks = self.version.engine.quirks();
+            self.path.join(quirks.gem_dir(&self.version.abi()))
+        })
     }
 
     pub fn gem_root(&self) -> Option<Utf8PathBuf> {
         self.gem_root.clone()
     }
 
+    /// Where `gem install --user-install` puts gems for this Ruby: the directory for its
+    /// [`Ruby::gem_scope`] in the layout chosen with `rv migrate gem-home`. Without a choice, the
+    /// legacy `~/.gem` directory is used if it exists.
     pub fn user_home(&self) -> Utf8PathBuf {
-        let home = rv_dirs::home_dir();
-        let legacy_path = home.join(".gem").join(self.gem_scope());
-        if legacy_path.exists() {
-            legacy_path
-        } else {
-            home.join(".local")
-                .join("share")
-                .join("rv")
-                .join("gems")
-                .join(self.gem_scope())
-        }
+        let layout = GemHomeLayout::chosen().unwrap_or_else(|| {
+            let legacy_path = GemHomeLayout::Legacy.gems_dir().join(self.gem_scope());
+            if legacy_path.exists() {
+                debug!("Using the legacy gem home {legacy_path}");
+                GemHomeLayout::Legacy
+            } else {
+                GemHomeLayout::Rv
+            }
+        });
+        layout.gems_dir().join(self.gem_scope())
     }
 
     pub fn man_path(&self) -> Option<Utf8PathBuf> {
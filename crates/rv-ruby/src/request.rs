@@ -2,7 +2,8 @@ use camino::{Utf8Path, Utf8PathBuf};
 use rv_cache::{CacheKey, CacheKeyHasher};
 use std::{fmt::Display, str::FromStr};
 
-use crate::{Versioned, engine::RubyEngine};
+use crate::{Versioned, engine::RubyEngine, version::RubyVersion};
+use rv_version::{Version, VersionReq};
 use serde_with::{DeserializeFromStr, SerializeDisplay};
 
 pub type VersionPart = u32;
@@ -13,6 +14,7 @@ pub type VersionPart = u32;
 pub enum RubyRequest {
     Dev,
     Released(ReleasedRubyRequest),
+    Range(RubyRange),
 }
 
 impl FromStr for RubyRequest {
@@ -22,6 +24,9 @@ impl FromStr for RubyRequest {
         if s.trim() == "dev" {
             return Ok(RubyRequest::Dev);
         }
+        if RubyRange::is_range(s) {
+            return RubyRange::from_str(s).map(Self::Range);
+        }
         ReleasedRubyRequest::from_str(s).map(Self::Released)
     }
 }
@@ -31,7 +36,68 @@ impl std::fmt::Display for RubyRequest {
         match self {
             RubyRequest::Dev => "dev".fmt(f),
             RubyRequest::Released(req) => req.fmt(f),
+            RubyRequest::Range(range) => range.fmt(f),
+        }
+    }
+}
+
+/// A range of Ruby versions written with RubyGems-style constraints, e.g. `>= 3.2, < 3.4` or
+/// `~> 3.3`. An engine name may come first, as in `jruby ~> 9.4`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RubyRange {
+    pub engine: RubyEngine,
+    pub requirement: VersionReq,
+}
+
+impl RubyRange {
+    /// Does this input use constraint operators, rather than naming a version?
+    fn is_range(input: &str) -> bool {
+        let (_, constraints) = Self::split_engine(input.trim());
+        constraints.starts_with(['<', '>', '=', '~', '!'])
+    }
+
+    fn split_engine(input: &str) -> (&str, &str) {
+        match input.split_once(char::is_whitespace) {
+            Some((engine, rest)) if input.starts_with(char::is_alphabetic) => {
+                (engine, rest.trim_start())
+            }
+            _ => ("ruby", input),
+        }
+    }
+
+    /// Does the given version fall within this range? Like RubyGems, prereleases only match if
+    /// one of the constraints mentions a prerelease.
+    pub fn matches(&self, version: &RubyVersion) -> bool {
+        self.engine == version.engine && self.requirement.matches(&Version::from(version))
+    }
+}
+
+impl FromStr for RubyRange {
+    type Err = RequestError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let input = input.trim();
+        if input.is_empty() {
+            return Err(RequestError::EmptyInput);
+        }
+
+        let (engine, constraints) = Self::split_engine(input);
+        let requirement = VersionReq::parse(constraints)
+            .map_err(|_| RequestError::InvalidVersion(input.to_string()))?;
+
+        Ok(Self {
+            engine: engine.into(),
+            requirement,
+        })
+    }
+}
+
+impl Display for RubyRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.engine != RubyEngine::Ruby {
+            write!(f, "{} ", self.engine)?;
         }
+        write!(f, "{}", self.requirement)
     }
 }
 
@@ -50,6 +116,7 @@ pub enum Source {
     DotToolVersions(Utf8PathBuf),
     DotRubyVersion(Utf8PathBuf),
     GemfileLock(Utf8PathBuf),
+    Gemfile(Utf8PathBuf),
 }
 
 impl std::fmt::Debug for Source {
@@ -58,6 +125,7 @@ impl std::fmt::Debug for Source {
             Self::DotToolVersions(arg0) => f.debug_tuple("DotToolVersions").field(arg0).finish(),
             Self::DotRubyVersion(arg0) => f.debug_tuple("DotRubyVersion").field(arg0).finish(),
             Self::GemfileLock(arg0) => f.debug_tuple("GemfileLock").field(arg0).finish(),
+            Self::Gemfile(arg0) => f.debug_tuple("Gemfile").field(arg0).finish(),
         }
     }
 }
@@ -68,6 +136,7 @@ impl Source {
             Self::DotToolVersions(arg0) => arg0,
             Self::DotRubyVersion(arg0) => arg0,
             Self::GemfileLock(arg0) => arg0,
+            Self::Gemfile(arg0) => arg0,
         }
     }
 }
@@ -118,6 +187,7 @@ impl RubyRequest {
         match self {
             Self::Dev => true,
             Self::Released(req) => req.prerelease == Some("dev".to_string()),
+            Self::Range(_) => false,
         }
     }
 }
@@ -280,6 +350,7 @@ impl CacheKey for RubyRequest {
             // i.e. there's only one "dev" version, no separate dev versions for each day.
             RubyRequest::Dev => "dev".cache_key(state),
             RubyRequest::Released(req) => req.cache_key(state),
+            RubyRequest::Range(range) => range.to_string().cache_key(state),
         }
     }
 }
@@ -559,4 +630,63 @@ mod tests {
         assert!(v("3.3.9") < v("3.3.10"));
         assert!(v("4.0.0-preview3") < v("4.0.0"));
     }
+
+    #[test]
+    fn test_parsing_ranges() {
+        let inputs = [
+            (">= 3.2, < 3.4", ">= 3.2, < 3.4"),
+            ("~> 3.3", "~> 3.3"),
+            ("~>3.3.1", "~> 3.3.1"),
+            (">=3.2,<3.4\n", ">= 3.2, < 3.4"),
+            ("jruby ~> 9.4", "jruby ~> 9.4"),
+            ("ruby >= 3.3", ">= 3.3"),
+        ];
+        for (input, expected) in inputs {
+            let request = RubyRequest::from_str(input).unwrap();
+            assert!(
+                matches!(request, RubyRequest::Range(_)),
+                "{input} should be a range"
+            );
+            assert_eq!(request.to_string(), expected);
+        }
+    }
+
+    #[test]
+    fn test_parsing_invalid_range() {
+        let err = RubyRequest::from_str(">= three").unwrap_err();
+        assert_eq!(err, RequestError::InvalidVersion(">= three".into()));
+    }
+
+    #[test]
+    fn test_range_matches() {
+        let range = RubyRange::from_str(">= 3.2, < 3.4").unwrap();
+        assert!(!range.matches(&v("3.1.6")));
+        assert!(range.matches(&v("3.2.0")));
+        assert!(range.matches(&v("3.3.9")));
+        assert!(!range.matches(&v("3.4.0")));
+        assert!(!range.matches(&v("jruby-3.3.0")));
+
+        let range = RubyRange::from_str("~> 3.3.1").unwrap();
+        assert!(!range.matches(&v("3.3.0")));
+        assert!(range.matches(&v("3.3.7")));
+        assert!(!range.matches(&v("3.4.0")));
+    }
+
+    #[test]
+    fn test_range_skips_prereleases_unless_requested() {
+        let range = RubyRange::from_str(">= 3.4").unwrap();
+        assert!(!range.matches(&v("4.0.0-preview2")));
+        assert!(range.matches(&v("4.0.0")));
+
+        let range = RubyRange::from_str(">= 4.0.0.preview1").unwrap();
+        assert!(range.matches(&v("4.0.0-preview2")));
+    }
+
+    #[test]
+    fn test_range_satisfied_by_version() {
+        let request = RubyRequest::from_str("~> 3.3").unwrap();
+        assert!(v("3.3.0").satisfies(&request));
+        assert!(v("3.9.1").satisfies(&request));
+        assert!(!v("4.0.0").satisfies(&request));
+    }
 }
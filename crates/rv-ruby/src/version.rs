@@ -63,6 +63,8 @@ pub enum ParseVersionError {
     MissingPatch,
     #[error("Cannot use the dev version of Ruby here")]
     CannotUseDev,
+    #[error("Cannot use a range of Ruby versions here")]
+    CannotUseRange,
 }
 
 impl FromStr for RubyVersion {
@@ -81,6 +83,7 @@ impl TryFrom<RubyRequest> for RubyVersion {
         match request {
             RubyRequest::Dev => Err(ParseVersionError::CannotUseDev),
             RubyRequest::Released(request) => Self::try_from(request),
+            RubyRequest::Range(_) => Err(ParseVersionError::CannotUseRange),
         }
     }
 }
@@ -148,6 +151,7 @@ impl RubyVersion {
         let request = match request {
             RubyRequest::Dev => return false,
             RubyRequest::Released(request) => request,
+            RubyRequest::Range(range) => return range.matches(self),
         };
         if self.engine != request.engine {
             return false;
@@ -206,7 +210,8 @@ impl RubyVersion {
 
 impl std::fmt::Display for RubyVersion {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}-{}", self.engine, self.number())
+        let quirks = self.engine.quirks();
+        f.write_str(&quirks.display_version(self.engine.name(), &self.number()))
     }
 }
 
@@ -1,5 +1,13 @@
 use serde::{Deserialize, Serialize};
 
+pub use crate::operator::ComparisonOperator;
+pub use crate::req::{Constraint, VersionReq, VersionReqError};
+pub use crate::semver_compat::SemverError;
+
+mod operator;
+pub mod req;
+mod semver_compat;
+
 const ZERO: VersionSegment = VersionSegment::Number(0);
 
 #[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
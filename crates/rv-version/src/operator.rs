@@ -0,0 +1,130 @@
+use serde::{Deserialize, Serialize};
+
+use crate::Version;
+
+/// An operator in a RubyGems-style version constraint, like the `~>` in `~> 3.3`.
+#[derive(Default, Debug, Clone, PartialEq, Eq, Ord, PartialOrd, Hash, Serialize, Deserialize)]
+pub enum ComparisonOperator {
+    Equal,
+    NotEqual,
+    GreaterThan,
+    #[default]
+    GreaterThanOrEqual,
+    LessThan,
+    LessThanOrEqual,
+    Pessimistic,
+}
+
+impl ComparisonOperator {
+    /// Split a leading operator off `constraint`, returning it and the rest of the string.
+    /// Constraints without an operator mean `=`, like in RubyGems.
+    pub fn split_prefix(constraint: &str) -> (Self, &str) {
+        // Two-character operators must be checked before their one-character prefixes.
+        let operators = [
+            Self::GreaterThanOrEqual,
+            Self::LessThanOrEqual,
+            Self::NotEqual,
+            Self::Pessimistic,
+            Self::GreaterThan,
+            Self::LessThan,
+            Self::Equal,
+        ];
+
+        operators
+            .into_iter()
+            .find_map(|op| {
+                let rest = constraint.strip_prefix(op.as_ref())?;
+                Some((op, rest))
+            })
+            .unwrap_or((Self::Equal, constraint))
+    }
+
+    /// Does `version` satisfy this operator applied to `target`?
+    pub fn matches(&self, version: &Version, target: &Version) -> bool {
+        match self {
+            Self::Equal => version == target,
+            Self::NotEqual => version != target,
+            Self::GreaterThan => version > target,
+            Self::GreaterThanOrEqual => version >= target,
+            Self::LessThan => version < target,
+            Self::LessThanOrEqual => version <= target,
+            Self::Pessimistic => version >= target && version < &target.bump(),
+        }
+    }
+}
+
+impl std::str::FromStr for ComparisonOperator {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "!=" => Ok(Self::NotEqual),
+            ">=" => Ok(Self::GreaterThanOrEqual),
+            "<=" => Ok(Self::LessThanOrEqual),
+            ">" => Ok(Self::GreaterThan),
+            "<" => Ok(Self::LessThan),
+            "~>" => Ok(Self::Pessimistic),
+            "=" => Ok(Self::Equal),
+            other => Err(other.to_owned()),
+        }
+    }
+}
+
+impl AsRef<str> for ComparisonOperator {
+    fn as_ref(&self) -> &str {
+        match self {
+            Self::GreaterThanOrEqual => ">=",
+            Self::LessThanOrEqual => "<=",
+            Self::NotEqual => "!=",
+            Self::Pessimistic => "~>",
+            Self::GreaterThan => ">",
+            Self::LessThan => "<",
+            Self::Equal => "=",
+        }
+    }
+}
+
+impl std::fmt::Display for ComparisonOperator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_ref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v(s: &str) -> Version {
+        Version::new(s).unwrap()
+    }
+
+    #[test]
+    fn test_split_prefix() {
+        assert_eq!(
+            ComparisonOperator::split_prefix(">= 3.2"),
+            (ComparisonOperator::GreaterThanOrEqual, " 3.2")
+        );
+        assert_eq!(
+            ComparisonOperator::split_prefix("~>3.3"),
+            (ComparisonOperator::Pessimistic, "3.3")
+        );
+        assert_eq!(
+            ComparisonOperator::split_prefix("<3.4"),
+            (ComparisonOperator::LessThan, "3.4")
+        );
+        assert_eq!(
+            ComparisonOperator::split_prefix("3.4.1"),
+            (ComparisonOperator::Equal, "3.4.1")
+        );
+    }
+
+    #[test]
+    fn test_pessimistic_matches() {
+        let op = ComparisonOperator::Pessimistic;
+        assert!(op.matches(&v("3.3.0"), &v("3.3")));
+        assert!(op.matches(&v("3.9.1"), &v("3.3")));
+        assert!(!op.matches(&v("4.0.0"), &v("3.3")));
+        assert!(op.matches(&v("3.3.9"), &v("3.3.0")));
+        assert!(!op.matches(&v("3.4.0"), &v("3.3.0")));
+    }
+}
@@ -0,0 +1,202 @@
+//! Requirements on versions, written the way RubyGems writes them: `~> 3.3`, `>= 3.2, < 3.4`.
+//!
+//! The matching rules live here, so every type with constraints of its own, like a gem's
+//! `Requirement` or a range of Ruby versions, matches versions the same way.
+
+use crate::{ComparisonOperator, Version, VersionError};
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum VersionReqError {
+    #[error("Empty version requirement")]
+    Empty,
+    #[error("Invalid version in the constraint {constraint}")]
+    InvalidVersion {
+        constraint: String,
+        source: VersionError,
+    },
+}
+
+/// One constraint of a requirement, like `>= 3.2`.
+pub trait Constraint {
+    fn operator(&self) -> &ComparisonOperator;
+
+    /// The version the operator compares against.
+    fn target(&self) -> &Version;
+
+    /// Does `version` satisfy this constraint?
+    fn matches(&self, version: &Version) -> bool {
+        self.operator().matches(version, self.target())
+    }
+}
+
+impl Constraint for (ComparisonOperator, Version) {
+    fn operator(&self) -> &ComparisonOperator {
+        &self.0
+    }
+
+    fn target(&self) -> &Version {
+        &self.1
+    }
+}
+
+/// Does `version` satisfy every one of `constraints`?
+pub fn satisfies_all<C: Constraint>(constraints: &[C], version: &Version) -> bool {
+    constraints
+        .iter()
+        .all(|constraint| constraint.matches(version))
+}
+
+/// Does any of `constraints` compare against a prerelease?
+pub fn mentions_prerelease<C: Constraint>(constraints: &[C]) -> bool {
+    constraints
+        .iter()
+        .any(|constraint| constraint.target().is_prerelease())
+}
+
+/// Does `version` satisfy every one of `constraints`? Like RubyGems, a prerelease only does when
+/// `allow_prerelease` is set or one of the constraints mentions a prerelease.
+pub fn matches_all<C: Constraint>(
+    constraints: &[C],
+    version: &Version,
+    allow_prerelease: bool,
+) -> bool {
+    if version.is_prerelease() && !allow_prerelease && !mentions_prerelease(constraints) {
+        return false;
+    }
+    satisfies_all(constraints, version)
+}
+
+/// A requirement on a version: any number of constraints, which a version has to satisfy all of.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct VersionReq {
+    pub constraints: Vec<(ComparisonOperator, Version)>,
+}
+
+impl VersionReq {
+    pub fn new(constraints: Vec<(ComparisonOperator, Version)>) -> Self {
+        Self { constraints }
+    }
+
+    /// Parses comma-separated constraints, like `>= 3.2, < 3.4`. A constraint without an
+    /// operator means `=`, like in RubyGems.
+    pub fn parse(input: &str) -> Result<Self, VersionReqError> {
+        let input = input.trim();
+        if input.is_empty() {
+            return Err(VersionReqError::Empty);
+        }
+
+        let constraints = input
+            .split(',')
+            .map(|constraint| {
+                let constraint = constraint.trim();
+                let (operator, version) = ComparisonOperator::split_prefix(constraint);
+                let version = version.trim();
+                if version.is_empty() {
+                    return Err(VersionReqError::Empty);
+                }
+                let version =
+                    Version::new(version).map_err(|source| VersionReqError::InvalidVersion {
+                        constraint: constraint.to_string(),
+                        source,
+                    })?;
+                Ok((operator, version))
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self { constraints })
+    }
+
+    /// Does `version` satisfy every constraint, prerelease or not?
+    pub fn satisfied_by(&self, version: &Version) -> bool {
+        satisfies_all(&self.constraints, version)
+    }
+
+    /// Does `version` satisfy every constraint? Prereleases only do when a constraint mentions a
+    /// prerelease.
+    pub fn matches(&self, version: &Version) -> bool {
+        matches_all(&self.constraints, version, false)
+    }
+
+    pub fn is_prerelease(&self) -> bool {
+        mentions_prerelease(&self.constraints)
+    }
+}
+
+impl std::str::FromStr for VersionReq {
+    type Err = VersionReqError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+impl std::fmt::Display for VersionReq {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let constraints: Vec<String> = self
+            .constraints
+            .iter()
+            .map(|(operator, version)| format!("{operator} {version}"))
+            .collect();
+        write!(f, "{}", constraints.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[track_caller]
+    fn v(version: &str) -> Version {
+        Version::new(version).unwrap()
+    }
+
+    #[track_caller]
+    fn req(requirement: &str) -> VersionReq {
+        VersionReq::parse(requirement).unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(req(">= 3.2,< 3.4").to_string(), ">= 3.2, < 3.4");
+        assert_eq!(req("~>3.3").to_string(), "~> 3.3");
+        assert_eq!(req("3.4.1").to_string(), "= 3.4.1");
+        assert_eq!(req("!= 3.3.0").to_string(), "!= 3.3.0");
+        assert_eq!(VersionReq::parse(" "), Err(VersionReqError::Empty));
+        assert_eq!(VersionReq::parse(">= 3.2, <"), Err(VersionReqError::Empty));
+        assert!(matches!(
+            VersionReq::parse(">= three"),
+            Err(VersionReqError::InvalidVersion { .. })
+        ));
+    }
+
+    #[test]
+    fn test_matches() {
+        let range = req(">= 3.2, < 3.4, != 3.3.0");
+        assert!(!range.matches(&v("3.1.6")));
+        assert!(range.matches(&v("3.2.0")));
+        assert!(!range.matches(&v("3.3.0")));
+        assert!(range.matches(&v("3.3.9")));
+        assert!(!range.matches(&v("3.4.0")));
+
+        let pessimistic = req("~> 3.3.1");
+        assert!(!pessimistic.matches(&v("3.3.0")));
+        assert!(pessimistic.matches(&v("3.3.7")));
+        assert!(!pessimistic.matches(&v("3.4.0")));
+    }
+
+    #[test]
+    fn test_prereleases() {
+        let released = req(">= 3.4");
+        assert!(!released.matches(&v("4.0.0.preview2")));
+        assert!(released.satisfied_by(&v("4.0.0.preview2")));
+        assert!(matches_all(
+            &released.constraints,
+            &v("4.0.0.preview2"),
+            true
+        ));
+
+        let prerelease = req(">= 4.0.0.preview1");
+        assert!(prerelease.is_prerelease());
+        assert!(prerelease.matches(&v("4.0.0.preview2")));
+    }
+}
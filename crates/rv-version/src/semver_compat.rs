@@ -0,0 +1,201 @@
+//! Conversions between RubyGems versions and [semver](https://semver.org) versions.
+//!
+//! RubyGems versions can have any number of release numbers, and mark prereleases with a letter
+//! anywhere, like `1.0.0.rc1`. Semver has exactly three release numbers, and a prerelease after a
+//! dash, like `1.0.0-rc.1`. Versions convert so that they sort the same way either side, as long
+//! as they have at most three release numbers that aren't trailing zeros.
+
+use crate::{Version, VersionError, VersionSegment};
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum SemverError {
+    #[error("{version} has more than three release numbers, which semver can't express")]
+    TooManyReleaseNumbers { version: String },
+    #[error("The prerelease of {version} can't be expressed in semver")]
+    InvalidPrerelease { version: String },
+}
+
+impl TryFrom<&Version> for semver::Version {
+    type Error = SemverError;
+
+    fn try_from(version: &Version) -> Result<Self, Self::Error> {
+        let segments = version.canonical_segments();
+        let release_len = segments
+            .iter()
+            .position(|segment| segment.is_string())
+            .unwrap_or(segments.len());
+        let (release, prerelease) = segments.split_at(release_len);
+
+        let mut numbers = [0u64; 3];
+        for (i, segment) in release.iter().enumerate() {
+            let VersionSegment::Number(number) = segment else {
+                unreachable!("release segments are numbers");
+            };
+            let Some(slot) = numbers.get_mut(i) else {
+                return Err(SemverError::TooManyReleaseNumbers {
+                    version: version.to_string(),
+                });
+            };
+            *slot = u64::from(*number);
+        }
+
+        // Semver compares letters and numbers within a prerelease separately, so `rc10` becomes
+        // `rc.10`, which sorts after `rc.9` as it does in RubyGems.
+        let identifiers: Vec<String> = prerelease
+            .iter()
+            .flat_map(|segment| match segment {
+                VersionSegment::Number(number) => vec![number.to_string()],
+                VersionSegment::String(string) => Version::split_alphanumeric(string)
+                    .into_iter()
+                    .map(|part| match part.parse::<u64>() {
+                        Ok(number) => number.to_string(),
+                        Err(_) => part.to_string(),
+                    })
+                    .collect(),
+            })
+            .collect();
+        let pre = semver::Prerelease::new(&identifiers.join(".")).map_err(|_| {
+            SemverError::InvalidPrerelease {
+                version: version.to_string(),
+            }
+        })?;
+
+        let [major, minor, patch] = numbers;
+        Ok(semver::Version {
+            major,
+            minor,
+            patch,
+            pre,
+            build: semver::BuildMetadata::EMPTY,
+        })
+    }
+}
+
+impl TryFrom<&semver::Version> for Version {
+    type Error = VersionError;
+
+    /// The prerelease follows the release numbers after a dot, like RubyGems writes them, and
+    /// build metadata is dropped, since it doesn't take part in comparisons.
+    fn try_from(version: &semver::Version) -> Result<Self, Self::Error> {
+        let mut rubygems = format!("{}.{}.{}", version.major, version.minor, version.patch);
+        if !version.pre.is_empty() {
+            rubygems.push('.');
+            rubygems.push_str(version.pre.as_str());
+        }
+        Self::new(rubygems)
+    }
+}
+
+impl Version {
+    /// Like [`Version::new`], but also accepts versions as other ecosystems write them: with a
+    /// leading `v`, or with semver's `+build` metadata, which is dropped.
+    pub fn parse_lenient(version: impl AsRef<str>) -> Result<Self, VersionError> {
+        let version = version.as_ref().trim();
+        let version = version.strip_prefix(['v', 'V']).unwrap_or(version);
+        let version = version
+            .split_once('+')
+            .map_or(version, |(version, _build)| version);
+        Self::new(version)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    #[track_caller]
+    fn v(version: &str) -> Version {
+        Version::new(version).unwrap()
+    }
+
+    #[track_caller]
+    fn to_semver(version: &str) -> String {
+        semver::Version::try_from(&v(version)).unwrap().to_string()
+    }
+
+    #[test]
+    fn test_to_semver() {
+        assert_eq!(to_semver("1"), "1.0.0");
+        assert_eq!(to_semver("1.2"), "1.2.0");
+        assert_eq!(to_semver("1.2.3"), "1.2.3");
+        assert_eq!(to_semver("1.2.3.0.0"), "1.2.3");
+        assert_eq!(to_semver("0"), "0.0.0");
+        assert_eq!(to_semver("8.0.0.rc1"), "8.0.0-rc.1");
+        assert_eq!(to_semver("2.0.0.pre.3"), "2.0.0-pre.3");
+        assert_eq!(to_semver("1.0.0-beta"), "1.0.0-pre.beta");
+        assert_eq!(to_semver("1.1.rc10"), "1.1.0-rc.10");
+        assert_eq!(
+            semver::Version::try_from(&v("7.1.3.4")),
+            Err(SemverError::TooManyReleaseNumbers {
+                version: "7.1.3.4".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_from_semver() {
+        let version = semver::Version::parse("1.0.0-beta.2+exp.sha.5114f85").unwrap();
+        assert_eq!(
+            Version::try_from(&version).unwrap().to_string(),
+            "1.0.0.beta.2"
+        );
+        let version = semver::Version::parse("3.4.1").unwrap();
+        assert_eq!(Version::try_from(&version).unwrap(), v("3.4.1"));
+    }
+
+    #[test]
+    fn test_parse_lenient() {
+        assert_eq!(Version::parse_lenient("v1.2.3").unwrap(), v("1.2.3"));
+        assert_eq!(Version::parse_lenient(" V2.0 ").unwrap(), v("2.0"));
+        assert_eq!(
+            Version::parse_lenient("1.0.0-rc.1+build.5").unwrap(),
+            v("1.0.0-rc.1")
+        );
+        assert!(Version::new("v1.2.3").is_err());
+        assert!(Version::parse_lenient("1.2.3+").is_ok());
+        assert!(Version::parse_lenient("v1.2.3-").is_err());
+    }
+
+    /// Versions with at most three release numbers and, maybe, a prerelease of letters with a
+    /// number after them, like `1.2.rc3`. A trailing zero would be dropped by RubyGems, but kept
+    /// by semver, so the number is never zero.
+    fn semver_compatible() -> impl Strategy<Value = Version> {
+        (
+            prop::collection::vec(0u32..20, 1..=3),
+            prop::option::of(("[a-z]{1,5}", prop::option::of(1u32..50))),
+        )
+            .prop_map(|(release, prerelease)| {
+                let mut version = release
+                    .iter()
+                    .map(u32::to_string)
+                    .collect::<Vec<_>>()
+                    .join(".");
+                if let Some((letters, number)) = prerelease {
+                    version.push('.');
+                    version.push_str(&letters);
+                    if let Some(number) = number {
+                        version.push_str(&number.to_string());
+                    }
+                }
+                Version::new(version).unwrap()
+            })
+    }
+
+    proptest! {
+        #[test]
+        fn conversion_keeps_the_order(a in semver_compatible(), b in semver_compatible()) {
+            let semver_a = semver::Version::try_from(&a).unwrap();
+            let semver_b = semver::Version::try_from(&b).unwrap();
+            prop_assert_eq!(a.cmp(&b), semver_a.cmp(&semver_b), "{} vs {}", a, b);
+        }
+
+        #[test]
+        fn semver_round_trips(version in semver_compatible()) {
+            let semver = semver::Version::try_from(&version).unwrap();
+            let back = Version::try_from(&semver).unwrap();
+            prop_assert_eq!(semver::Version::try_from(&back).unwrap(), semver);
+        }
+    }
+}
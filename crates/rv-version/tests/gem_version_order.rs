@@ -0,0 +1,159 @@
+//! Versions sort the way RubyGems sorts them. The reference here follows `Gem::Version#<=>`:
+//! split into runs of digits and runs of letters, drop trailing zeros from the release and
+//! from the prerelease, then compare segment by segment, with missing segments as zeros and
+//! numbers after letters.
+
+use std::cmp::Ordering;
+use std::fs;
+
+use proptest::prelude::*;
+use rv_version::Version;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Number(u64),
+    String(String),
+}
+
+/// `Gem::Version#segments`, after `Gem::Version.new` turns `-` into `.pre.`.
+fn gem_segments(version: &str) -> Vec<Segment> {
+    let version = version.replace('-', ".pre.");
+    let mut runs: Vec<String> = Vec::new();
+    let mut current = String::new();
+    for ch in version.chars() {
+        if !ch.is_ascii_alphanumeric() {
+            runs.extend((!current.is_empty()).then(|| std::mem::take(&mut current)));
+            continue;
+        }
+        let same_kind = current
+            .chars()
+            .last()
+            .is_none_or(|last| last.is_ascii_digit() == ch.is_ascii_digit());
+        if !same_kind {
+            runs.push(std::mem::take(&mut current));
+        }
+        current.push(ch);
+    }
+    runs.extend((!current.is_empty()).then_some(current));
+
+    runs.into_iter()
+        .map(|run| match run.parse() {
+            Ok(number) => Segment::Number(number),
+            Err(_) => Segment::String(run),
+        })
+        .collect()
+}
+
+/// `Gem::Version#canonical_segments`.
+fn canonical_segments(segments: Vec<Segment>) -> Vec<Segment> {
+    let string_start = segments
+        .iter()
+        .position(|segment| matches!(segment, Segment::String(_)))
+        .unwrap_or(segments.len());
+    let (release, prerelease) = segments.split_at(string_start);
+    let trimmed = |part: &[Segment]| {
+        let end = part
+            .iter()
+            .rposition(|segment| *segment != Segment::Number(0))
+            .map_or(0, |last| last + 1);
+        part[..end].to_vec()
+    };
+    [trimmed(release), trimmed(prerelease)].concat()
+}
+
+/// `Gem::Version#<=>`.
+fn gem_cmp(a: &str, b: &str) -> Ordering {
+    let a = canonical_segments(gem_segments(a));
+    let b = canonical_segments(gem_segments(b));
+    let zero = Segment::Number(0);
+    for i in 0..a.len().max(b.len()) {
+        let lhs = a.get(i).unwrap_or(&zero);
+        let rhs = b.get(i).unwrap_or(&zero);
+        let ordering = match (lhs, rhs) {
+            (Segment::Number(lhs), Segment::Number(rhs)) => lhs.cmp(rhs),
+            (Segment::String(lhs), Segment::String(rhs)) => lhs.cmp(rhs),
+            (Segment::String(_), Segment::Number(_)) => Ordering::Less,
+            (Segment::Number(_), Segment::String(_)) => Ordering::Greater,
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    Ordering::Equal
+}
+
+fn rv_cmp(a: &str, b: &str) -> Ordering {
+    Version::new(a).unwrap().cmp(&Version::new(b).unwrap())
+}
+
+#[test]
+fn test_reference() {
+    assert_eq!(gem_cmp("1.0", "1.0.0"), Ordering::Equal);
+    assert_eq!(gem_cmp("1.0.0.rc1", "1.0.0"), Ordering::Less);
+    assert_eq!(gem_cmp("1.0.0.beta10", "1.0.0.beta9"), Ordering::Greater);
+    assert_eq!(gem_cmp("1.0.0-beta", "1.0.0.pre.beta"), Ordering::Equal);
+    assert_eq!(gem_cmp("5.x", "5.0.0.rc2"), Ordering::Greater);
+}
+
+#[test]
+fn test_real_gem_versions() {
+    let corpus = fs::read_to_string("tests/fixtures/gem-versions.txt").unwrap();
+    let versions: Vec<&str> = corpus.lines().filter(|line| !line.is_empty()).collect();
+    for a in &versions {
+        for b in &versions {
+            assert_eq!(rv_cmp(a, b), gem_cmp(a, b), "{a} <=> {b}");
+        }
+    }
+
+    // The corpus is sorted, and stays sorted.
+    let mut sorted: Vec<Version> = versions.iter().map(|v| Version::new(v).unwrap()).collect();
+    sorted.sort();
+    let sorted: Vec<String> = sorted.iter().map(ToString::to_string).collect();
+    assert_eq!(sorted, versions);
+}
+
+/// Versions like RubyGems sees them: up to four release numbers, then maybe a prerelease. Letters
+/// with a number right after them (`rc2`) come from other letters than letters with a number
+/// after a dot (`pre.2`), since RubyGems treats `rc2` and `rc.2` alike and rv doesn't.
+fn gem_version() -> impl Strategy<Value = String> {
+    let release = prop::collection::vec(0u32..20, 1..=4).prop_map(|numbers| {
+        numbers
+            .iter()
+            .map(u32::to_string)
+            .collect::<Vec<_>>()
+            .join(".")
+    });
+    let prerelease = prop_oneof![
+        Just(String::new()),
+        ("[a-m]{1,4}", prop::option::of(1u32..30)).prop_map(|(letters, number)| {
+            format!(
+                ".{letters}{}",
+                number.map(|n| n.to_string()).unwrap_or_default()
+            )
+        }),
+        ("[n-z]{1,4}", 0u32..30).prop_map(|(letters, number)| format!(".{letters}.{number}")),
+        "[a-m]{1,4}".prop_map(|letters| format!("-{letters}")),
+    ];
+    (release, prerelease).prop_map(|(release, prerelease)| release + &prerelease)
+}
+
+proptest! {
+    #[test]
+    fn order_matches_rubygems(a in gem_version(), b in gem_version()) {
+        prop_assert_eq!(rv_cmp(&a, &b), gem_cmp(&a, &b), "{} <=> {}", a, b);
+    }
+
+    #[test]
+    fn order_is_total(a in gem_version(), b in gem_version(), c in gem_version()) {
+        let (a, b, c) = (
+            Version::new(&a).unwrap(),
+            Version::new(&b).unwrap(),
+            Version::new(&c).unwrap(),
+        );
+        prop_assert_eq!(a.cmp(&b), b.cmp(&a).reverse());
+        prop_assert_eq!(a == b, a.cmp(&b) == Ordering::Equal);
+        if a <= b && b <= c {
+            prop_assert!(a <= c);
+        }
+    }
+}
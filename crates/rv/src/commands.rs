@@ -1,7 +1,22 @@
 pub mod cache;
+pub mod clean;
 pub mod clean_install;
+pub mod complete;
+pub mod doctor;
+pub mod gc;
+pub mod gem;
+pub mod gemfile;
+pub mod generate;
+pub mod lock;
+pub mod matrix;
+pub mod migrate;
+pub mod prune;
+pub mod rpc;
 pub mod ruby;
 pub mod run;
 pub mod self_cmd;
 pub mod shell;
 pub mod tool;
+pub mod trust;
+pub mod update;
+pub mod why;
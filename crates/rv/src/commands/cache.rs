@@ -1,11 +1,28 @@
+use std::collections::HashMap;
+use std::io::Read;
+use std::process::Command;
+
 use anstream::println;
 use bytesize::ByteSize;
+use camino::{Utf8Path, Utf8PathBuf};
 use clap::{Args, Subcommand};
 use owo_colors::OwoColorize;
-use rv_cache::CleanReporter;
+use rv_cache::{CacheBucket, CleanReporter};
+use rv_client::http_client::{Client, rv_http_client};
+use rv_core::provenance::Provenance;
+use rv_platform::HostPlatform;
+use tracing::debug;
+use url::Url;
 
+use crate::commands::clean_install::{find_lockfile_path, gem_cache_path, url_for_spec};
+use crate::commands::ruby::install::archive_cache_path;
+use crate::config::github::{github_token, is_github_url};
 use crate::{GlobalArgs, config::Config};
 
+mod transfer;
+
+use transfer::{ExportArgs, ImportArgs, cache_export, cache_import};
+
 #[derive(Args)]
 pub struct CacheCommandArgs {
     #[command(subcommand)]
@@ -20,24 +37,102 @@ pub enum CacheCommand {
     Prune,
     #[command(about = "Show the cache directory")]
     Dir,
+    #[command(about = "Check cached gems, git clones and ruby archives for corruption")]
+    Verify(VerifyArgs),
+    #[command(
+        about = "Export the cached gems, git repos and ruby a Gemfile.lock needs into an archive"
+    )]
+    Export(ExportArgs),
+    #[command(about = "Import an archive written by `rv cache export` into the cache")]
+    Import(ImportArgs),
+}
+
+#[derive(Args)]
+pub struct VerifyArgs {
+    /// Download corrupt gems and ruby archives again, and delete the corrupt entries rv can't
+    /// tell the source of, so they are downloaded again the next time they're needed
+    #[arg(long)]
+    fix: bool,
 }
+
 #[derive(Debug, thiserror::Error, miette::Diagnostic)]
 pub enum Error {
     #[error(transparent)]
+    #[diagnostic(code(RV4001))]
     IoError(#[from] std::io::Error),
     #[error(transparent)]
+    #[diagnostic(transparent)]
     Config(#[from] crate::config::Error),
+    #[error("Found {count} corrupt cache entries")]
+    #[diagnostic(
+        code(RV4002),
+        help(
+            "Run `rv cache verify --fix` to download them again, or remove the ones that can't be"
+        )
+    )]
+    CorruptEntries { count: usize },
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    LockfileNotFound(#[from] crate::commands::clean_install::Error),
+    #[error(transparent)]
+    #[diagnostic(code(RV4007))]
+    Http(#[from] reqwest::Error),
+    #[error("Could not parse {lockfile}")]
+    #[diagnostic(code(RV4003))]
+    Parse {
+        lockfile: Utf8PathBuf,
+        #[diagnostic_source]
+        source: rv_lockfile::ParseErrors,
+    },
+    #[error("Some of what {lockfile} needs isn't cached: {entries}")]
+    #[diagnostic(
+        code(RV4004),
+        help("Run `rv ci` to download everything the lockfile needs, then export again")
+    )]
+    NotCached {
+        lockfile: Utf8PathBuf,
+        entries: String,
+    },
+    #[error("{archive} is not an export of rv's cache: {reason}")]
+    #[diagnostic(code(RV4005))]
+    InvalidExport {
+        archive: Utf8PathBuf,
+        reason: String,
+    },
+    #[error(
+        "{path} in {archive} is corrupt: its sha256 is {actual}, but the export's manifest says {expected}"
+    )]
+    #[diagnostic(
+        code(RV4006),
+        help(
+            "Nothing was imported. Export the cache again, and check the archive isn't changed on its way"
+        )
+    )]
+    DigestMismatch {
+        archive: Utf8PathBuf,
+        path: String,
+        expected: String,
+        actual: String,
+    },
 }
 
 type Result<T> = miette::Result<T, Error>;
 
-pub(crate) fn cache(global_args: &GlobalArgs, args: CacheCommandArgs) -> Result<()> {
-    let config = &Config::new(global_args, None)?;
+pub(crate) async fn cache(global_args: &GlobalArgs, args: CacheCommandArgs) -> Result<()> {
+    let config = &match args.command {
+        // Exports and fixes need Bundler's settings, to find gems downloaded through a mirror.
+        CacheCommand::Export(_) => Config::with_settings(global_args, None)?,
+        CacheCommand::Verify(VerifyArgs { fix: true }) => Config::with_settings(global_args, None)?,
+        _ => Config::new(global_args, None)?,
+    };
 
     match args.command {
         CacheCommand::Dir => cache_dir(config)?,
         CacheCommand::Clean => cache_clean(config)?,
         CacheCommand::Prune => cache_prune(config)?,
+        CacheCommand::Verify(args) => cache_verify(config, args).await?,
+        CacheCommand::Export(args) => cache_export(config, args).await?,
+        CacheCommand::Import(args) => cache_import(config, args)?,
     };
 
     Ok(())
@@ -73,3 +168,351 @@ fn cache_prune(config: &Config) -> Result<()> {
     );
     Ok(())
 }
+
+/// The kinds of cache entries that `rv cache verify` knows how to check.
+#[derive(Debug, Clone, Copy)]
+enum EntryKind {
+    Gem,
+    GitClone,
+    RubyArchive,
+}
+
+impl EntryKind {
+    fn describe(self) -> &'static str {
+        match self {
+            Self::Gem => "gem",
+            Self::GitClone => "git clone",
+            Self::RubyArchive => "ruby archive",
+        }
+    }
+
+    /// The cache shard holding this kind of entry.
+    fn shard(self, cache: &rv_cache::Cache) -> rv_cache::CacheShard {
+        match self {
+            Self::Gem => cache.shard(CacheBucket::Gem, "gems"),
+            Self::GitClone => cache.shard(CacheBucket::Git, "gits"),
+            Self::RubyArchive => cache.shard(CacheBucket::Ruby, "tarballs"),
+        }
+    }
+
+    /// Should this path inside the shard be checked as an entry of this kind?
+    fn matches(self, path: &Utf8Path) -> bool {
+        match self {
+            Self::Gem => path.extension() == Some("gem"),
+            Self::GitClone => path.is_dir(),
+            // Not the lock files of archives, or archives that are still downloading.
+            Self::RubyArchive => {
+                path.is_file() && !matches!(path.extension(), Some("lock" | "tmp"))
+            }
+        }
+    }
+
+    /// Returns why the entry at `path` is corrupt, or `None` if it looks fine.
+    fn check(self, path: &Utf8Path) -> Option<String> {
+        let result = match self {
+            Self::Gem => verify_gem(path),
+            Self::GitClone => verify_git_clone(path),
+            Self::RubyArchive => verify_ruby_archive(path),
+        };
+        result.err()
+    }
+}
+
+async fn cache_verify(config: &Config, args: VerifyArgs) -> Result<()> {
+    let mut checked = 0;
+    let mut corrupt = 0;
+    let mut refetched = 0;
+    let (sources, client) = if args.fix {
+        (known_sources(config), Some(rv_http_client("cache_verify")?))
+    } else {
+        (HashMap::new(), None)
+    };
+
+    for kind in [EntryKind::Gem, EntryKind::GitClone, EntryKind::RubyArchive] {
+        let shard = kind.shard(&config.cache);
+        if !shard.is_dir() {
+            continue;
+        }
+        let lock = config.cache.lock_shard_async(&shard).await?;
+
+        let mut corrupt_paths = vec![];
+        for entry in shard.read_dir_utf8()? {
+            let path = entry?.into_path();
+            if !kind.matches(&path) {
+                continue;
+            }
+
+            checked += 1;
+            debug!("Verifying {} {path}", kind.describe());
+            let Some(reason) = kind.check(&path) else {
+                continue;
+            };
+            println!("{} {} {path}: {reason}", "corrupt".red(), kind.describe());
+            corrupt += 1;
+            corrupt_paths.push(path);
+        }
+
+        // Downloads can take a while, and other rv processes shouldn't wait for them.
+        drop(lock);
+
+        let Some(client) = &client else {
+            continue;
+        };
+        for path in corrupt_paths {
+            if let Some(url) = sources.get(&path) {
+                match refetch(client, kind, url, &path).await {
+                    Ok(()) => {
+                        println!(
+                            "{} {} {path} from {url}",
+                            "refetched".green(),
+                            kind.describe()
+                        );
+                        refetched += 1;
+                        continue;
+                    }
+                    Err(reason) => debug!("Could not download {url} again: {reason}"),
+                }
+            }
+            rv_cache::rm_rf(&path)?;
+        }
+    }
+
+    println!(
+        "Verified {} cache entries, {} corrupt",
+        checked.cyan(),
+        corrupt.cyan()
+    );
+
+    if corrupt == 0 {
+        Ok(())
+    } else if args.fix {
+        if refetched > 0 {
+            println!("Downloaded {} corrupt entries again", refetched.cyan());
+        }
+        if corrupt > refetched {
+            println!("Removed {} corrupt entries", (corrupt - refetched).cyan());
+        }
+        Ok(())
+    } else {
+        Err(Error::CorruptEntries { count: corrupt })
+    }
+}
+
+/// The URLs that cache entries were downloaded from, by the entry's path, as far as rv can tell:
+/// the gems the project's lockfile needs, and the archives the installed rubies came from. Entries
+/// are named after a digest of their URL, so the URL of any other entry is lost.
+fn known_sources(config: &Config) -> HashMap<Utf8PathBuf, Url> {
+    let mut sources = HashMap::new();
+
+    let lockfile = find_lockfile_path(&None)
+        .ok()
+        .and_then(|path| fs_err::read_to_string(path).ok());
+    if let Some(raw_contents) = lockfile {
+        let contents = rv_lockfile::normalize_line_endings(&raw_contents);
+        match rv_lockfile::parse(&contents) {
+            Ok(lockfile) => {
+                for gem_source in &lockfile.gem {
+                    let Some(remote) = gem_source.remote else {
+                        continue;
+                    };
+                    let mirror = config.bundler_settings.mirror_for(remote);
+                    let remote = mirror.as_deref().unwrap_or(remote);
+                    for spec in &gem_source.specs {
+                        if let Ok(url) = url_for_spec(remote, spec) {
+                            let (path, _) = gem_cache_path(&config.cache, &url);
+                            sources.insert(path, url);
+                        }
+                    }
+                }
+            }
+            Err(err) => {
+                debug!("Not downloading corrupt gems again, the lockfile is invalid: {err}")
+            }
+        }
+    }
+
+    if let Ok(host) = HostPlatform::current() {
+        for ruby in config.rubies() {
+            let Some(provenance) = Provenance::read(&ruby.path) else {
+                continue;
+            };
+            // Rubies installed from a local archive or built from source have no URL.
+            let Ok(url) = Url::parse(&provenance.source) else {
+                continue;
+            };
+            if matches!(url.scheme(), "http" | "https") {
+                sources.insert(archive_cache_path(config, &provenance.source, &host), url);
+            }
+        }
+    }
+
+    sources
+}
+
+/// Download the cache entry at `path` from `url` again, replacing the corrupt copy, and check the
+/// new copy like the old one.
+async fn refetch(
+    client: &Client,
+    kind: EntryKind,
+    url: &Url,
+    path: &Utf8Path,
+) -> std::result::Result<(), String> {
+    let mut request = client.get(url.clone());
+    if is_github_url(url.as_str())
+        && let Some(token) = github_token()
+    {
+        request = request.header("Authorization", format!("Bearer {token}"));
+    }
+    let body = async { request.send().await?.error_for_status()?.bytes().await }
+        .await
+        .map_err(|err| err.to_string())?;
+    rv_cache::write_atomic(path, &body).map_err(|err| err.to_string())?;
+    kind.check(path).map_or(Ok(()), Err)
+}
+
+/// Re-hash a cached .gem against the checksums recorded inside it, and read all of its contents.
+fn verify_gem(path: &Utf8Path) -> std::result::Result<(), String> {
+    std::fs::File::open(path)
+        .map_err(rv_gem_package::Error::from)
+        .and_then(|file| rv_gem_package::verify(std::io::BufReader::new(file)))
+        .map(|_| ())
+        .map_err(|err| err.to_string())
+}
+
+/// Check the object database of a cached git clone for missing or broken objects.
+fn verify_git_clone(path: &Utf8Path) -> std::result::Result<(), String> {
+    let output = Command::new("git")
+        .current_dir(path)
+        .args(["fsck", "--no-full", "--no-progress"])
+        .output()
+        .map_err(|err| format!("could not run git: {err}"))?;
+
+    if output.status.success() {
+        Ok(())
+    } else {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        Err(stderr
+            .lines()
+            .next()
+            .unwrap_or("git fsck failed")
+            .to_string())
+    }
+}
+
+/// Read a cached ruby archive all the way through, which catches truncated downloads.
+fn verify_ruby_archive(path: &Utf8Path) -> std::result::Result<(), String> {
+    let describe = |err: std::io::Error| err.to_string();
+
+    if path.extension() == Some("tmp") {
+        return Err("incomplete download".to_string());
+    }
+    if fs_err::metadata(path).map_err(describe)?.len() == 0 {
+        return Err("empty archive".to_string());
+    }
+
+    match path.extension() {
+        Some("zip") => {
+            let file = fs_err::File::open(path).map_err(describe)?;
+            let mut archive = zip::ZipArchive::new(file).map_err(|err| err.to_string())?;
+            for i in 0..archive.len() {
+                let mut entry = archive.by_index(i).map_err(|err| err.to_string())?;
+                // Reading to the end checks the entry's CRC.
+                std::io::copy(&mut entry, &mut std::io::sink()).map_err(describe)?;
+            }
+            Ok(())
+        }
+        Some("gz") => {
+            let file = fs_err::File::open(path).map_err(describe)?;
+            let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(file));
+            for entry in archive.entries().map_err(describe)? {
+                let mut entry = entry.map_err(describe)?;
+                std::io::copy(&mut entry, &mut std::io::sink()).map_err(describe)?;
+            }
+            // Make sure there's no trailing garbage or missing gzip footer.
+            let mut rest = Vec::new();
+            archive
+                .into_inner()
+                .read_to_end(&mut rest)
+                .map_err(describe)?;
+            Ok(())
+        }
+        // Other formats (like RubyInstaller's 7z archives) are only checked for being non-empty.
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_fs::TempDir;
+    use assert_fs::prelude::*;
+    use camino::Utf8PathBuf;
+
+    fn tarball(contents: &[u8]) -> Vec<u8> {
+        let mut tar_data = Vec::new();
+        {
+            let mut builder = tar::Builder::new(&mut tar_data);
+            let mut header = tar::Header::new_gnu();
+            header.set_path("ruby/bin/ruby").unwrap();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o755);
+            header.set_cksum();
+            builder.append(&header, contents).unwrap();
+            builder.finish().unwrap();
+        }
+
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        std::io::Write::write_all(&mut encoder, &tar_data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn utf8(path: &std::path::Path) -> Utf8PathBuf {
+        Utf8PathBuf::from_path_buf(path.to_path_buf()).unwrap()
+    }
+
+    #[test]
+    fn test_verify_ruby_archive_accepts_complete_tarball() {
+        let temp_dir = TempDir::new().unwrap();
+        let archive = temp_dir.child("ruby.tar.gz");
+        archive.write_binary(&tarball(b"#!/bin/sh")).unwrap();
+
+        assert_eq!(verify_ruby_archive(&utf8(archive.path())), Ok(()));
+    }
+
+    #[test]
+    fn test_verify_ruby_archive_rejects_truncated_tarball() {
+        let temp_dir = TempDir::new().unwrap();
+        let archive = temp_dir.child("ruby.tar.gz");
+        let data = tarball(&[b'x'; 4096]);
+        archive.write_binary(&data[..data.len() / 2]).unwrap();
+
+        assert!(verify_ruby_archive(&utf8(archive.path())).is_err());
+    }
+
+    #[test]
+    fn test_verify_ruby_archive_rejects_empty_and_partial_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let empty = temp_dir.child("ruby.tar.gz");
+        empty.touch().unwrap();
+        let partial = temp_dir.child("ruby.tar.gz.tmp");
+        partial.write_binary(&tarball(b"#!/bin/sh")).unwrap();
+
+        assert_eq!(
+            verify_ruby_archive(&utf8(empty.path())),
+            Err("empty archive".to_string())
+        );
+        assert_eq!(
+            verify_ruby_archive(&utf8(partial.path())),
+            Err("incomplete download".to_string())
+        );
+    }
+
+    #[test]
+    fn test_verify_gem_rejects_garbage() {
+        let temp_dir = TempDir::new().unwrap();
+        let gem = temp_dir.child("broken.gem");
+        gem.write_binary(&[0u8; 1024]).unwrap();
+
+        assert!(verify_gem(&utf8(gem.path())).is_err());
+    }
+}
@@ -0,0 +1,452 @@
+//! `rv cache export` and `rv cache import`, which carry the part of the cache a project needs to
+//! a machine that can't download it, like one in an air-gapped network.
+//!
+//! An export is a gzipped tarball of cache entries, at the paths they have in the cache, after a
+//! manifest with the sha256 of every file in it. An import checks every file against the manifest
+//! before it moves anything into the cache, so a corrupt or tampered export imports nothing.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Read;
+use std::path::Component;
+
+use anstream::println;
+use camino::{Utf8Path, Utf8PathBuf};
+use clap::Args;
+use owo_colors::OwoColorize;
+use rv_cache::{Cache, CacheBucket};
+use rv_platform::HostPlatform;
+use rv_ruby::request::RubyRequest;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::{debug, warn};
+
+use super::{Error, Result};
+use crate::commands::clean_install::{
+    find_lockfile_path, gem_cache_path, git_cache_key, url_for_spec,
+};
+use crate::commands::ruby::install::archive_cache_path;
+use crate::config::Config;
+use crate::config::release_source::ArchiveLocation;
+
+/// The manifest, which is the first file of an export.
+const MANIFEST_NAME: &str = "rv-cache-export.json";
+const MANIFEST_VERSION: u32 = 1;
+
+#[derive(Args)]
+pub struct ExportArgs {
+    /// Where to write the archive, e.g. `rv-cache.tar.gz`
+    output: Utf8PathBuf,
+
+    /// Export what the lockfile of this Gemfile needs, instead of what ./Gemfile.lock needs
+    #[arg(long)]
+    gemfile: Option<Utf8PathBuf>,
+}
+
+#[derive(Args)]
+pub struct ImportArgs {
+    /// An archive written by `rv cache export`
+    archive: Utf8PathBuf,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Manifest {
+    version: u32,
+    /// The sha256 of every file in the export, by its path in the cache, joined with `/`.
+    files: BTreeMap<String, String>,
+}
+
+/// The shards of the cache an export has entries from, relative to the cache root.
+fn exported_shards(cache: &Cache) -> Vec<String> {
+    [
+        cache.shard(CacheBucket::Gem, "gems"),
+        cache.shard(CacheBucket::Git, "gits"),
+        cache.shard(CacheBucket::Ruby, "tarballs"),
+        cache.shard(CacheBucket::Ruby, "releases"),
+    ]
+    .iter()
+    .map(|shard| relative_name(cache.root(), shard))
+    .collect()
+}
+
+/// `path`, relative to `root`, joined with `/` on every platform.
+fn relative_name(root: &Utf8Path, path: &Utf8Path) -> String {
+    let relative = path
+        .strip_prefix(root)
+        .expect("cache entries are in the cache");
+    relative
+        .components()
+        .map(|component| component.as_str())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// The cache entry the file `name` of an export belongs to: the file itself for gems, Ruby
+/// archives and release lists, or the directory of a git clone. `None` if it isn't in one of the
+/// `shards` an export has.
+fn entry_of<'a>(name: &'a str, shards: &[String]) -> Option<&'a str> {
+    shards.iter().find_map(|shard| {
+        let rest = name.strip_prefix(shard.as_str())?.strip_prefix('/')?;
+        let entry = rest.split('/').next().filter(|entry| !entry.is_empty())?;
+        Some(&name[..shard.len() + 1 + entry.len()])
+    })
+}
+
+pub(super) async fn cache_export(config: &Config, args: ExportArgs) -> Result<()> {
+    let cache = &config.cache;
+    let lockfile_path = find_lockfile_path(&args.gemfile)?;
+    let raw_contents = fs_err::read_to_string(&lockfile_path)?;
+    let contents = rv_lockfile::normalize_line_endings(&raw_contents);
+    let lockfile = rv_lockfile::parse(&contents).map_err(|source| Error::Parse {
+        lockfile: lockfile_path.clone(),
+        source,
+    })?;
+
+    let mut entries = BTreeSet::new();
+    let mut missing = Vec::new();
+
+    for gem_source in &lockfile.gem {
+        let Some(remote) = gem_source.remote else {
+            continue;
+        };
+        let mirror = config.bundler_settings.mirror_for(remote);
+        let remote = mirror.as_deref().unwrap_or(remote);
+
+        // A lockfile can list a gem for several platforms, and `rv ci` only downloads the ones for
+        // this machine, so a gem is only missing if none of them are cached.
+        let mut cached_versions: BTreeMap<String, bool> = BTreeMap::new();
+        for spec in &gem_source.specs {
+            let (path, _) = gem_cache_path(cache, &url_for_spec(remote, spec)?);
+            let cached = path.is_file();
+            if cached {
+                entries.insert(path);
+            }
+            let name = format!("{}-{}", spec.release_tuple.name, spec.release_tuple.version);
+            *cached_versions.entry(name).or_default() |= cached;
+        }
+        missing.extend(
+            cached_versions
+                .into_iter()
+                .filter(|(_, cached)| !cached)
+                .map(|(name, _)| name),
+        );
+    }
+
+    let git_clone_dir = cache.shard(CacheBucket::Git, "gits");
+    for git_source in &lockfile.git {
+        let path = git_clone_dir.join(git_cache_key(git_source));
+        if path.is_dir() {
+            entries.insert(path);
+        } else {
+            missing.push(git_source.remote.to_owned());
+        }
+    }
+
+    if !missing.is_empty() {
+        return Err(Error::NotCached {
+            lockfile: lockfile_path,
+            entries: missing.join(", "),
+        });
+    }
+
+    entries.extend(ruby_entries(config).await);
+
+    let files = entry_files(cache.root(), &entries)?;
+    write_export(&files, &args.output)?;
+    println!(
+        "Exported {} cache entries ({} files) to {}",
+        entries.len().cyan(),
+        files.len().cyan(),
+        args.output.cyan()
+    );
+
+    Ok(())
+}
+
+/// The cached archive of the project's Ruby for this machine, and the cached lists of Ruby
+/// releases, which `rv ruby install` needs to resolve a version range offline.
+async fn ruby_entries(config: &Config) -> Vec<Utf8PathBuf> {
+    let mut entries = Vec::new();
+    let releases = config.cache.shard(CacheBucket::Ruby, "releases");
+    if releases.is_dir() {
+        entries.push(releases.into_path_buf());
+    }
+
+    let request = config.ruby_request();
+    // Development builds change every day, so there's no one archive to export.
+    if matches!(request, RubyRequest::Dev) {
+        return entries;
+    }
+    let (Ok(host), Ok(version)) = (
+        HostPlatform::current(),
+        config.find_matching_remote_ruby().await,
+    ) else {
+        warn!("Could not tell which Ruby {request} is, so the export has no Ruby archive");
+        return entries;
+    };
+
+    match config
+        .release_source(&host)
+        .archive(&version.number(), &host)
+    {
+        ArchiveLocation::Url(url) => {
+            let path = archive_cache_path(config, url, &host);
+            if path.is_file() {
+                entries.push(path);
+            } else {
+                warn!(
+                    "Ruby {version} isn't cached, so the export has no Ruby archive; run `rv ruby install {version}` to download it"
+                );
+            }
+        }
+        // A local mirror's archives are on the mirror, not in the cache.
+        ArchiveLocation::Path(path) => debug!("Not exporting {path}, which is on a local mirror"),
+        ArchiveLocation::LatestRedirect(_) => {}
+    }
+    entries
+}
+
+/// Every file in `entries`, by its name in an export.
+fn entry_files(
+    root: &Utf8Path,
+    entries: &BTreeSet<Utf8PathBuf>,
+) -> Result<BTreeMap<String, Utf8PathBuf>> {
+    let mut files = BTreeMap::new();
+    let mut pending: Vec<_> = entries.iter().cloned().collect();
+    while let Some(path) = pending.pop() {
+        let file_type = fs_err::symlink_metadata(&path)?.file_type();
+        if file_type.is_dir() {
+            for entry in path.read_dir_utf8()? {
+                pending.push(entry?.into_path());
+            }
+        } else if file_type.is_file() {
+            files.insert(relative_name(root, &path), path);
+        } else {
+            debug!("Not exporting {path}, which isn't a file or a directory");
+        }
+    }
+    Ok(files)
+}
+
+fn write_export(files: &BTreeMap<String, Utf8PathBuf>, output: &Utf8Path) -> Result<()> {
+    let mut manifest = Manifest {
+        version: MANIFEST_VERSION,
+        files: BTreeMap::new(),
+    };
+    for (name, path) in files {
+        let sha256 = hex::encode(Sha256::digest(fs_err::read(path)?));
+        manifest.files.insert(name.clone(), sha256);
+    }
+    let manifest = serde_json::to_vec_pretty(&manifest).expect("the manifest is serializable");
+
+    // Write next to the output and rename it at the end, so a failed export leaves no archive.
+    let temp_output = output.with_added_extension("tmp");
+    let encoder = flate2::write::GzEncoder::new(
+        fs_err::File::create(&temp_output)?,
+        flate2::Compression::default(),
+    );
+    let mut builder = tar::Builder::new(encoder);
+    let mut header = tar::Header::new_gnu();
+    header.set_size(manifest.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    builder.append_data(&mut header, MANIFEST_NAME, manifest.as_slice())?;
+    for (name, path) in files {
+        builder.append_path_with_name(path, name)?;
+    }
+    builder.into_inner()?.finish()?;
+    fs_err::rename(&temp_output, output)?;
+
+    Ok(())
+}
+
+/// The path of an archive entry, joined with `/`, if it's a relative path that stays in the
+/// directory it's unpacked into.
+fn entry_name<R: Read>(entry: &tar::Entry<R>) -> Option<String> {
+    let path = entry.path().ok()?;
+    let parts = path
+        .components()
+        .map(|component| match component {
+            Component::Normal(part) => part.to_str(),
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>()?;
+    (!parts.is_empty()).then(|| parts.join("/"))
+}
+
+pub(super) fn cache_import(config: &Config, args: ImportArgs) -> Result<()> {
+    let cache = &config.cache;
+    let shards = exported_shards(cache);
+    let invalid = |reason: String| Error::InvalidExport {
+        archive: args.archive.clone(),
+        reason,
+    };
+
+    let file = fs_err::File::open(&args.archive)?;
+    let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(file));
+    let mut archive_entries = archive.entries()?;
+
+    let manifest: Manifest = match archive_entries.next() {
+        Some(entry) => {
+            let entry = entry?;
+            if entry_name(&entry).as_deref() != Some(MANIFEST_NAME) {
+                return Err(invalid(format!("it doesn't start with {MANIFEST_NAME}")));
+            }
+            serde_json::from_reader(entry)
+                .map_err(|err| invalid(format!("its manifest is invalid: {err}")))?
+        }
+        None => return Err(invalid("it's empty".to_owned())),
+    };
+    if manifest.version != MANIFEST_VERSION {
+        return Err(invalid(format!(
+            "it's version {} of the format, but this rv reads version {MANIFEST_VERSION}",
+            manifest.version
+        )));
+    }
+
+    // Unpack into the cache directory, so that checked entries can be moved into the cache
+    // without copying them.
+    fs_err::create_dir_all(cache.root())?;
+    let staging = camino_tempfile::tempdir_in(cache.root())?;
+    let mut unpacked = BTreeSet::new();
+
+    for entry in archive_entries {
+        let mut entry = entry?;
+        let name = entry_name(&entry)
+            .ok_or_else(|| invalid("it has a file outside of the cache".to_owned()))?;
+        match entry.header().entry_type() {
+            tar::EntryType::Regular => {}
+            tar::EntryType::Directory => continue,
+            _ => return Err(invalid(format!("{name} isn't a regular file"))),
+        }
+        let Some(expected) = manifest.files.get(&name) else {
+            return Err(invalid(format!("{name} isn't in its manifest")));
+        };
+        if entry_of(&name, &shards).is_none() {
+            return Err(invalid(format!("{name} isn't a cache entry rv exports")));
+        }
+
+        let mut contents = Vec::new();
+        entry.read_to_end(&mut contents)?;
+        let actual = hex::encode(Sha256::digest(&contents));
+        if actual != *expected {
+            return Err(Error::DigestMismatch {
+                archive: args.archive.clone(),
+                path: name,
+                expected: expected.clone(),
+                actual,
+            });
+        }
+
+        let path = staging.path().join(&name);
+        fs_err::create_dir_all(path.parent().expect("entries are in a shard"))?;
+        fs_err::write(&path, contents)?;
+        unpacked.insert(name);
+    }
+
+    if let Some(name) = manifest.files.keys().find(|name| !unpacked.contains(*name)) {
+        return Err(invalid(format!(
+            "{name} is in its manifest, but not in the archive"
+        )));
+    }
+
+    let mut imported = 0;
+    let mut skipped = 0;
+    let entries: BTreeSet<_> = unpacked
+        .iter()
+        .filter_map(|name| entry_of(name, &shards))
+        .collect();
+    for entry in entries {
+        let target = cache.root().join(entry);
+        if target.exists() {
+            debug!("Not importing {entry}, which is cached already");
+            skipped += 1;
+            continue;
+        }
+        fs_err::create_dir_all(target.parent().expect("entries are in a shard"))?;
+        fs_err::rename(staging.path().join(entry), &target)?;
+        imported += 1;
+    }
+
+    println!(
+        "Imported {} cache entries from {}, skipped {} that were already cached",
+        imported.cyan(),
+        args.archive.cyan(),
+        skipped.cyan()
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entry_of() {
+        let shards = vec!["gem-v0/gems".to_owned(), "git-v0/gits".to_owned()];
+        assert_eq!(
+            entry_of("gem-v0/gems/abc.gem", &shards),
+            Some("gem-v0/gems/abc.gem")
+        );
+        assert_eq!(
+            entry_of("git-v0/gits/abc/objects/pack/1.pack", &shards),
+            Some("git-v0/gits/abc")
+        );
+        assert_eq!(entry_of("gem-v0/gemspecs/abc.gemspec", &shards), None);
+        assert_eq!(entry_of("gem-v0/gems", &shards), None);
+        assert_eq!(entry_of("gem-v0/gems/", &shards), None);
+    }
+
+    fn export(files: &[(&str, &[u8])], manifest: &Manifest) -> Vec<u8> {
+        let mut builder = tar::Builder::new(flate2::write::GzEncoder::new(
+            Vec::new(),
+            flate2::Compression::default(),
+        ));
+        let manifest = serde_json::to_vec(manifest).unwrap();
+        let mut entries = vec![(MANIFEST_NAME, manifest.as_slice())];
+        entries.extend_from_slice(files);
+        for (name, contents) in entries {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, name, contents).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    fn import(archive: &[u8]) -> (Cache, Result<()>) {
+        let cache = Cache::temp().unwrap();
+        let dir = camino_tempfile::tempdir().unwrap();
+        let path = dir.path().join("export.tar.gz");
+        fs_err::write(&path, archive).unwrap();
+        let mut config = Config::new_dummy();
+        config.cache = cache.clone();
+        let result = cache_import(&config, ImportArgs { archive: path });
+        (cache, result)
+    }
+
+    #[test]
+    fn test_import_checks_digests() {
+        let cache = Cache::temp().unwrap();
+        let gem = relative_name(
+            cache.root(),
+            &cache.shard(CacheBucket::Gem, "gems").join("abc.gem"),
+        );
+        let manifest = Manifest {
+            version: MANIFEST_VERSION,
+            files: BTreeMap::from([(gem.clone(), hex::encode(Sha256::digest(b"gem")))]),
+        };
+
+        let (cache, result) = import(&export(&[(gem.as_str(), b"gem")], &manifest));
+        result.unwrap();
+        assert_eq!(fs_err::read(cache.root().join(&gem)).unwrap(), b"gem");
+
+        let (cache, result) = import(&export(&[(gem.as_str(), b"tampered")], &manifest));
+        assert!(matches!(result, Err(Error::DigestMismatch { path, .. }) if path == gem));
+        assert!(!cache.root().join(&gem).exists());
+
+        let (_, result) = import(&export(&[], &manifest));
+        assert!(matches!(result, Err(Error::InvalidExport { .. })));
+    }
+}
@@ -0,0 +1,165 @@
+use anstream::println;
+use bytesize::ByteSize;
+use camino::{Utf8Path, Utf8PathBuf};
+use clap::Args;
+use owo_colors::OwoColorize;
+use tracing::debug;
+
+use crate::GlobalArgs;
+use crate::config::Config;
+
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+pub enum Error {
+    #[error(transparent)]
+    #[diagnostic(code(RV7201))]
+    IoError(#[from] std::io::Error),
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    ConfigError(#[from] crate::config::Error),
+}
+
+type Result<T> = miette::Result<T, Error>;
+
+#[derive(Args)]
+pub struct CleanArgs {
+    /// Show what would be removed, and how much space it takes, without removing anything
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+/// Something rv installed for the project, which `rv ci` can recreate.
+#[derive(Debug, PartialEq, Eq)]
+struct Artifact {
+    path: Utf8PathBuf,
+    description: &'static str,
+}
+
+pub(crate) fn clean(global_args: &GlobalArgs, args: CleanArgs) -> Result<()> {
+    let config = Config::with_settings(global_args, None)?;
+    let project_root = rv_dirs::canonicalize_utf8(&config.project_root)?;
+    let artifacts = project_artifacts(&config, &project_root);
+
+    if artifacts.is_empty() {
+        println!("Nothing to clean in {}", project_root.cyan());
+        return Ok(());
+    }
+
+    let mut total = 0;
+    for artifact in &artifacts {
+        let bytes = if args.dry_run {
+            disk_usage(&artifact.path)?
+        } else {
+            rv_cache::rm_rf(&artifact.path)?.bytes
+        };
+        total += bytes;
+
+        let path = artifact
+            .path
+            .strip_prefix(&project_root)
+            .unwrap_or(&artifact.path);
+        println!(
+            "{} {} ({}, {})",
+            if args.dry_run {
+                "Would remove"
+            } else {
+                "Removed"
+            },
+            path.cyan(),
+            artifact.description,
+            ByteSize::b(bytes).display().iec_short()
+        );
+    }
+
+    let total = ByteSize::b(total).display().iec_short();
+    if args.dry_run {
+        println!("Would free {}", total.cyan());
+    } else {
+        println!("Freed {}", total.cyan());
+    }
+
+    Ok(())
+}
+
+/// The directories rv installs gems into that belong to this project. Gem homes shared with other
+/// projects, and the global cache, are never included. Binstubs and extension build logs live
+/// inside these directories, so they go with them.
+fn project_artifacts(config: &Config, project_root: &Utf8Path) -> Vec<Artifact> {
+    let candidates = [
+        (
+            Some(project_root.join(".rv")),
+            "project gems from `isolation \"project\"`",
+        ),
+        (config.bundler_settings.path(), "Bundler's `path`"),
+        (
+            config.rv_settings.install_path_as_utf8pathbuf(),
+            "rv's `install-path`",
+        ),
+    ];
+
+    let mut artifacts: Vec<Artifact> = Vec::new();
+    for (path, description) in candidates {
+        let Some(path) = path.and_then(|path| rv_dirs::canonicalize_utf8(path).ok()) else {
+            continue;
+        };
+        if !is_inside(&path, project_root) {
+            debug!("Not cleaning {path}, which is outside of {project_root}");
+            continue;
+        }
+        if artifacts
+            .iter()
+            .any(|artifact| path.starts_with(&artifact.path))
+        {
+            continue;
+        }
+        artifacts.retain(|artifact| !artifact.path.starts_with(&path));
+        artifacts.push(Artifact { path, description });
+    }
+    artifacts
+}
+
+/// Whether `path` is inside `dir`. `dir` itself doesn't count, so a misconfigured
+/// `BUNDLE_PATH=.` can't remove the whole project.
+fn is_inside(path: &Utf8Path, dir: &Utf8Path) -> bool {
+    path != dir && path.starts_with(dir)
+}
+
+/// The size of the files under `path`, without following symlinks.
+pub(crate) fn disk_usage(path: &Utf8Path) -> std::io::Result<u64> {
+    let metadata = fs_err::symlink_metadata(path)?;
+    if !metadata.is_dir() {
+        return Ok(metadata.len());
+    }
+
+    let mut bytes = 0;
+    for entry in fs_err::read_dir(path)? {
+        let entry = entry?;
+        let entry_path = Utf8PathBuf::try_from(entry.path()).map_err(|err| {
+            std::io::Error::new(std::io::ErrorKind::InvalidData, err.into_io_error())
+        })?;
+        bytes += disk_usage(&entry_path)?;
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_inside() {
+        let project = Utf8Path::new("/work/app");
+        assert!(is_inside(Utf8Path::new("/work/app/vendor/bundle"), project));
+        assert!(!is_inside(Utf8Path::new("/work/app"), project));
+        assert!(!is_inside(Utf8Path::new("/work/application"), project));
+        assert!(!is_inside(Utf8Path::new("/home/me/.gem"), project));
+    }
+
+    #[test]
+    fn test_disk_usage() {
+        let dir = camino_tempfile::tempdir().unwrap();
+        fs_err::create_dir_all(dir.path().join("gems/rake")).unwrap();
+        fs_err::write(dir.path().join("gems/rake/rake.rb"), "x".repeat(10)).unwrap();
+        fs_err::write(dir.path().join("build.log"), "x".repeat(5)).unwrap();
+        assert_eq!(disk_usage(dir.path()).unwrap(), 15);
+    }
+}
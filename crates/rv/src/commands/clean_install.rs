@@ -7,12 +7,13 @@ use futures_util::StreamExt;
 use futures_util::TryStreamExt;
 use glob::glob;
 use indicatif::ProgressStyle;
-use once_cell::sync::Lazy;
+use once_cell::sync::{Lazy, OnceCell};
 use owo_colors::OwoColorize;
 use rayon::ThreadPoolBuildError;
 use regex::Regex;
-use reqwest::Client;
-use rv_client::http_client::rv_http_client;
+use rv_client::http_client::{Client, rv_http_client};
+use rv_client::retry::RetryPolicy;
+use rv_gem_types::Platform;
 use rv_gem_types::ReleaseTuple;
 use rv_gem_types::Specification as GemSpecification;
 use rv_lockfile::datatypes::ChecksumAlgorithm;
@@ -21,19 +22,39 @@ use rv_lockfile::datatypes::GemfileDotLock;
 use rv_lockfile::datatypes::GitSection;
 use rv_lockfile::datatypes::PathSection;
 use rv_lockfile::datatypes::Spec;
+use rv_lockfile::graph::DependencyGraph;
+use rv_ruby::Ruby;
+use rv_ruby::bundle_layout::BundleLayout;
 use rv_ruby::request::RubyRequest;
 use sha2::Digest;
+use tracing::Instrument;
 use tracing::debug;
 use tracing::info;
 use tracing::info_span;
+use tracing::warn;
 use tracing_indicatif::span_ext::IndicatifSpanExt;
 use url::Url;
 
+use crate::commands::clean_install::bundler_compat::BundlerCompat;
 use crate::commands::clean_install::checksums::ArchiveChecksums;
 use crate::commands::clean_install::checksums::HashReader;
 use crate::commands::clean_install::checksums::Hashed;
-use crate::commands::ruby::install::install as ruby_install;
+use crate::commands::clean_install::hooks::Hooks;
+use crate::commands::clean_install::manifest::InstallManifest;
+use crate::commands::clean_install::remote_cache::RemoteCache;
+pub use crate::commands::clean_install::report::OutputMode;
+use crate::commands::clean_install::report::{GemStatus, InstallReport};
+use crate::commands::clean_install::vendor::{AppCache, VendorMode};
+use crate::commands::clean_install::watch::Watcher;
+use crate::commands::ruby::install::{
+    InstallDir, InstallLayout as RubyLayout, install as ruby_install,
+};
 use crate::commands::run::Invocation;
+use crate::commands::trust::TrustStore;
+use crate::config::credentials::Userinfo;
+use crate::config::gemfile::{self, DEFAULT_GROUP};
+use crate::config::rv_settings::HookEvent;
+use crate::failure_report::{Failure, Phase, ReportArgs};
 use crate::progress::WorkProgress;
 use crate::{GlobalArgs, config::Config};
 use std::collections::HashMap;
@@ -43,12 +64,24 @@ use std::io::Write;
 use std::ops::Not;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::sync::Arc;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::Duration;
 use std::time::Instant;
 use std::vec;
 
+mod bundler_compat;
 mod checksums;
+mod hooks;
+mod manifest;
+mod prune;
+mod remote_cache;
+mod report;
+mod reproducible;
+mod standalone;
+mod static_gemspec;
+mod vendor;
+mod watch;
 
 #[derive(Debug, clap_derive::Args)]
 pub struct CleanInstallArgs {
@@ -62,17 +95,137 @@ pub struct CleanInstallArgs {
 
     /// Maximum number of gem installations that can be in flight at once.
     /// This reduces concurrently-open files on your filesystem,
-    /// and concurrent disk operations.
-    #[arg(long, hide = true, default_value = "20")]
-    pub max_concurrent_installs: usize,
+    /// and concurrent disk operations. Defaults to Bundler's `BUNDLE_JOBS` setting, or 20.
+    #[arg(long, hide = true)]
+    pub max_concurrent_installs: Option<usize>,
 
     /// Validate the checksums from the gem server and gem itself.
     #[arg(long, hide = true, default_value = "true")]
     pub validate_checksums: bool,
 
+    /// What to do with a gem whose lockfile checksums all use algorithms rv can't check, i.e.
+    /// neither sha256 nor sha512.
+    #[arg(long, env = "RV_UNSUPPORTED_CHECKSUMS", value_enum, default_value_t)]
+    pub unsupported_checksums: UnsupportedChecksums,
+
     /// Force installation of gems, whatever is installed or not.
     #[arg(long, default_value = "false")]
     pub force: bool,
+
+    /// Only install the gems that changed since the last `rv ci --only-changed`, going by the
+    /// checksums in the lockfile, and remove installed gems the lockfile no longer has.
+    #[arg(long, conflicts_with = "force")]
+    pub only_changed: bool,
+
+    /// After installing, remove the installed gems that the lockfile doesn't lock any more,
+    /// like `rv prune` does.
+    #[arg(long)]
+    pub prune: bool,
+
+    /// Also write a `bundler/setup.rb` into the install path that puts every gem on the load
+    /// path, so that the app can `require_relative` it and run without Bundler or rv.
+    #[arg(long)]
+    pub standalone: bool,
+
+    /// Install into this directory as if it were the root of the filesystem, like `make install
+    /// DESTDIR=...`, e.g. to copy the installed gems into a container image afterwards.
+    #[arg(long, value_name = "DIR")]
+    pub destdir: Option<Utf8PathBuf>,
+
+    /// Give every installed file this modification time, in seconds since the Unix epoch, so that
+    /// installing the same lockfile again produces identical files.
+    #[arg(long, env = "SOURCE_DATE_EPOCH", value_name = "SECONDS")]
+    pub source_date_epoch: Option<u64>,
+
+    /// Install precompiled, platform-specific variants of gems whenever the lockfile has them,
+    /// even if Bundler's `force_ruby_platform` setting asks to compile gems from source.
+    #[arg(long)]
+    pub prefer_native_platform: bool,
+
+    /// Build native extensions without network access, so that builds can't download sources or
+    /// binaries. Done on a best-effort basis, by pointing proxy settings at an unreachable address
+    /// and telling Cargo to stay offline.
+    #[arg(long)]
+    pub no_network_during_build: bool,
+
+    /// Print the output of native extension builds as they run, each line prefixed with the
+    /// gem's name, instead of only showing it when a build fails.
+    #[arg(long, env = "RV_VERBOSE_BUILD")]
+    pub verbose_build: bool,
+
+    /// How many times to retry a gem download that failed for a transient reason,
+    /// like a timeout or a 5xx response from the gem server.
+    #[arg(long, env = "RV_HTTP_RETRIES", default_value = "3")]
+    pub retries: u32,
+
+    /// Keep installing the other gems when some fail to download, instead of stopping before
+    /// installing anything. rv still exits with an error, listing the gems that failed.
+    #[arg(long)]
+    pub keep_going: bool,
+
+    /// Print how long each phase of the install took, and how gems were downloaded.
+    #[arg(long)]
+    pub timings: bool,
+
+    /// Also copy every gem package and git repository the project needs into `vendor/cache`
+    /// (or Bundler's `BUNDLE_CACHE_PATH`), laid out like `bundle cache` does, so that later
+    /// installs can run with `--local`.
+    #[arg(long, conflicts_with = "local")]
+    pub vendor: bool,
+
+    /// Install exclusively from the gems vendored in `vendor/cache`, never downloading any.
+    #[arg(long)]
+    pub local: bool,
+
+    /// Instead of progress bars and a summary, print one tab-separated
+    /// `<status> <gem> <milliseconds>` line per gem, for scripts and CI logs.
+    #[arg(long)]
+    pub porcelain: bool,
+
+    #[command(flatten)]
+    pub report: ReportArgs,
+
+    /// After installing, keep watching the Gemfile, the lockfile and the project's gemspecs, and
+    /// install again whenever they change.
+    #[arg(long)]
+    pub watch: bool,
+
+    /// How long the watched files must stay unchanged before installing again, in milliseconds.
+    #[arg(long, requires = "watch", default_value = "500")]
+    pub debounce: u64,
+
+    /// Show a desktop notification after each install triggered by a change.
+    #[arg(long, requires = "watch")]
+    pub notify: bool,
+
+    /// A shell command to run after each install triggered by a change. Its `RV_CI_EXIT_STATUS`
+    /// environment variable is 0 if the install succeeded, and 1 if it failed.
+    #[arg(long, requires = "watch", value_name = "COMMAND")]
+    pub on_install: Option<String>,
+}
+
+/// What `rv ci` does with a gem whose lockfile checksums it can't check.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UnsupportedChecksums {
+    /// Install it, with a warning.
+    #[default]
+    Warn,
+    /// Install it silently.
+    Ignore,
+    /// Stop the install.
+    Error,
+}
+
+impl CleanInstallArgs {
+    pub fn output_mode(&self, quiet: bool) -> OutputMode {
+        if self.porcelain {
+            OutputMode::Porcelain
+        } else if quiet {
+            OutputMode::Quiet
+        } else {
+            OutputMode::Human
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -80,79 +233,73 @@ struct CiInnerArgs {
     pub max_concurrent_requests: usize,
     pub max_concurrent_installs: usize,
     pub validate_checksums: bool,
-    pub install_layout: InstallLayout,
+    pub unsupported_checksums: UnsupportedChecksums,
+    pub install_layout: BundleLayout,
     /// Full path to the Ruby executable, used for Windows .bat binstub wrappers
     pub ruby_executable_path: Utf8PathBuf,
     /// Will install already installed gems
     pub force: bool,
+    /// Install the generic "ruby" platform variant of gems, compiling them from source,
+    /// instead of precompiled platform-specific variants
+    pub force_ruby_platform: bool,
+    /// Deny network access to native extension builds
+    pub no_network_during_build: bool,
+    /// Stream the output of native extension builds while they run
+    pub verbose_build: bool,
+    /// How to report progress and results
+    pub output: OutputMode,
+    /// How to retry failed gem downloads
+    pub retry_policy: RetryPolicy,
+    /// Install what can be installed, even if some gems failed to download
+    pub keep_going: bool,
+    /// Print a breakdown of how long each phase took
+    pub timings: bool,
+    /// The project's vendored gems, and whether to read or write them
+    pub app_cache: AppCache,
+    /// Commands to run at points of each gem's installation
+    pub hooks: Hooks,
+    /// A bucket shared with other machines to get gems and builds from, and maybe put them in
+    pub remote_cache: Option<RemoteCache>,
 }
 
-#[derive(Debug)]
-struct InstallLayout {
-    install_path: Utf8PathBuf,
+/// The directory name Bundler gives a git source, when installing or vendoring it: the repo's
+/// name and the start of the locked revision.
+fn git_dir_name(git_section: &GitSection) -> String {
+    let repo_path = Path::new(&git_section.remote);
+    let repo_name = repo_path
+        .file_stem()
+        .expect("repo has no filename?")
+        .to_string_lossy();
 
-    extensions_scope: String,
-}
-
-impl InstallLayout {
-    pub fn binstub_dir(&self) -> Utf8PathBuf {
-        self.install_path.join("bin")
-    }
-
-    pub fn gem_path(&self, full_version: &str) -> Utf8PathBuf {
-        self.install_path.join(format!("gems/{}", full_version))
-    }
-
-    pub fn specifications_dir(&self) -> Utf8PathBuf {
-        self.install_path.join("specifications")
-    }
-
-    pub fn spec_path(&self, full_version: &str) -> Utf8PathBuf {
-        self.specifications_dir()
-            .join(format!("{}.gemspec", full_version))
-    }
-
-    pub fn git_gem_path(&self, git_section: &GitSection) -> Utf8PathBuf {
-        use std::path::Path;
-
-        let repo_path = Path::new(&git_section.remote);
-        let repo_name = repo_path
-            .file_stem()
-            .expect("repo has no filename?")
-            .to_string_lossy();
-
-        let install_dir_name = format!("{}-{:.12}", repo_name, git_section.revision);
-        self.install_path
-            .join(format!("bundler/gems/{install_dir_name}"))
-    }
-
-    pub fn extensions_dir(&self, full_version: &str) -> Utf8PathBuf {
-        self.install_path
-            .join("extensions")
-            .join(self.extensions_scope.clone())
-            .join(full_version)
-    }
+    format!("{}-{:.12}", repo_name, git_section.revision)
 }
 
 #[derive(Debug, thiserror::Error, miette::Diagnostic)]
 pub enum UnpackError {
     #[error("No gemspec found for downloaded gem {0}")]
+    #[diagnostic(code(RV2101))]
     MissingGemspec(String),
     #[error(transparent)]
+    #[diagnostic(code(RV2102))]
     Io(#[from] io::Error),
     #[error("File {filename} did not match {algo} checksum in gem {gem_name} archive")]
+    #[diagnostic(code(RV2103))]
     ArchiveChecksumFail {
         filename: String,
         gem_name: String,
         algo: &'static str,
     },
     #[error("Checksum for {0} was not valid YAML")]
+    #[diagnostic(code(RV2104))]
     InvalidChecksum(String),
     #[error("Gem {gem_name} archive did not include metadata.gz")]
+    #[diagnostic(code(RV2105))]
     NoMetadata { gem_name: String },
     #[error("Gem archive did not include data.tar.gz")]
+    #[diagnostic(code(RV2106))]
     NoDataTar,
     #[error("Invalid gem archive: {0}")]
+    #[diagnostic(code(RV2107))]
     InvalidGemArchive(String),
     #[error("Could not parse YAML metadata inside gem package")]
     #[diagnostic(transparent)]
@@ -162,72 +309,234 @@ pub enum UnpackError {
 #[derive(Debug, thiserror::Error, miette::Diagnostic)]
 pub enum Error {
     #[error(transparent)]
+    #[diagnostic(code(RV2001))]
     Infallible(#[from] std::convert::Infallible),
     #[error("Needed to install Ruby but couldn't: {0}")]
+    #[diagnostic(code(RV2002))]
     Install(#[from] crate::commands::ruby::install::Error),
     #[error("Cannot build unknown native extension {filename} from gem {gemname}")]
+    #[diagnostic(code(RV2003))]
     UnknownExtension { filename: String, gemname: String },
     #[error("Error evaluating gemspec: {0}")]
+    #[diagnostic(code(RV2004))]
     GemspecError(String),
     #[error("Gemfile \"{0}\" does not exist")]
+    #[diagnostic(code(RV2005))]
     MissingGemfile(String),
     #[error("A Gemfile.lock file was not found")]
+    #[diagnostic(code(RV2006))]
     MissingImplicitLockfile,
     #[error("A {lockfile_name} file was not found in {lockfile_dir}")]
+    #[diagnostic(code(RV2007))]
     MissingLockfile {
         lockfile_name: String,
         lockfile_dir: String,
     },
     #[error("Gem {gem} could not compile extensions")]
-    CompileFailures { gem: String },
+    #[diagnostic(code(RV2008))]
+    CompileFailures {
+        gem: String,
+        /// The absolute paths of the build's logs, `build_ext.log` and `mkmf.log` if there is one.
+        logs: Vec<Utf8PathBuf>,
+        /// The last lines the failed build commands printed to stderr.
+        stderr_tail: Vec<String>,
+        #[help]
+        help: String,
+    },
+    #[error("{} gems could not be downloaded: {}", gems.len(), gems.join(", "))]
+    #[diagnostic(code(RV2009))]
+    DownloadFailures { gems: Vec<String> },
+    #[error("The Gemfile changed since the lockfile was generated, and the lockfile is frozen. Gems missing from the lockfile: {}", gems.join(", "))]
+    #[diagnostic(
+        code(RV2010),
+        help(
+            "The lockfile is frozen by Bundler's BUNDLE_FROZEN or BUNDLE_DEPLOYMENT setting. Run `bundle lock` and commit the updated lockfile."
+        )
+    )]
+    FrozenLockfileOutdated { gems: Vec<String> },
     #[error(transparent)]
+    #[diagnostic(transparent)]
     Config(#[from] crate::config::Error),
     #[error(transparent)]
+    #[diagnostic(transparent)]
     Run(#[from] crate::commands::run::Error),
     #[error(transparent)]
-    Parse(#[from] rv_lockfile::ParseErrors),
+    #[diagnostic(code(RV2011))]
+    Parse(
+        #[from]
+        #[diagnostic_source]
+        rv_lockfile::ParseErrors,
+    ),
     #[error(transparent)]
+    #[diagnostic(code(RV2012))]
     Io(#[from] io::Error),
     #[error(transparent)]
-    Reqwest(#[from] reqwest::Error),
+    #[diagnostic(code(RV2013))]
+    Reqwest(reqwest::Error),
     #[error("Invalid remote URL")]
+    #[diagnostic(code(RV2014))]
     BadRemote {
         remote: String,
         err: url::ParseError,
     },
     #[error(transparent)]
+    #[diagnostic(code(RV2015))]
     UrlError(#[from] url::ParseError),
     #[error("File {filename} did not match {algo} locked checksum in gem {gem_name}")]
+    #[diagnostic(code(RV2016))]
     LockfileChecksumFail {
         filename: String,
         gem_name: String,
         algo: &'static str,
     },
     #[error("Could not write binstub for {dep_name}/{exe_name}: {error}")]
+    #[diagnostic(code(RV2017))]
     CouldNotWriteBinstub {
         dep_name: String,
         exe_name: String,
         error: io::Error,
     },
     #[error("Could not download a git dependency: {error}")]
+    #[diagnostic(code(RV2018))]
     Git { error: String },
     #[error(
         "The gemfile path must be inside a directory with a parent, but it wasn't. Path was {0}"
     )]
+    #[diagnostic(code(RV2019))]
     InvalidGemfilePath(String),
     #[error(transparent)]
+    #[diagnostic(transparent)]
     UnpackError(#[from] UnpackError),
     #[error("macOS Command Line Tools are not installed")]
-    #[diagnostic(help(
-        "Native gem extensions require a C compiler to build.\nInstall them by running:\n\n  xcode-select --install"
-    ))]
+    #[diagnostic(
+        code(RV2020),
+        help(
+            "Native gem extensions require a C compiler to build.\nInstall them by running:\n\n  xcode-select --install"
+        )
+    )]
     MissingMacosDevTools,
+    #[error(
+        "{gem_name} from {source_url} changed since rv first downloaded it: its sha256 is now {actual}, but it was {trusted} when downloaded from {trusted_source}"
+    )]
+    #[diagnostic(
+        code(RV2021),
+        help(
+            "A published gem release never changes, so this can mean the gem server or a mirror was tampered with. If you're sure the new package is legitimate, trust it with:\n\n  rv trust allow {gem_name} {actual}"
+        )
+    )]
+    TrustedDigestChanged {
+        gem_name: String,
+        source_url: String,
+        actual: String,
+        trusted: String,
+        trusted_source: String,
+    },
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    Trust(#[from] crate::commands::trust::Error),
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    Credentials(#[from] crate::config::credentials::Error),
+    #[error("{name} is not vendored in {dir}")]
+    #[diagnostic(
+        code(RV2022),
+        help("Run `rv ci --vendor` with network access to vendor every gem the project needs")
+    )]
+    NotVendored { name: String, dir: String },
+    #[error("The {event} hook `{command}` failed for {gem}: {reason}")]
+    #[diagnostic(
+        code(RV2023),
+        help(
+            "Hooks are configured with `hook` in rv.kdl, and must exit with 0 for the install to go on"
+        )
+    )]
+    HookFailed {
+        event: HookEvent,
+        command: String,
+        gem: String,
+        reason: String,
+    },
+    #[error("The remote cache {url} is invalid: {reason}")]
+    #[diagnostic(
+        code(RV2024),
+        help(
+            "Set `remote-cache` in rv.kdl to a bucket like s3://my-bucket/rv or gs://my-bucket/rv"
+        )
+    )]
+    InvalidRemoteCache { url: String, reason: String },
+    #[error("The lockfile's checksums for {gem_name} use {algorithms}, which rv can't check")]
+    #[diagnostic(
+        code(RV2025),
+        help(
+            "Add a sha256 or sha512 checksum for it to the lockfile, or pass `--unsupported-checksums warn` to install it unchecked"
+        )
+    )]
+    UnsupportedChecksum {
+        gem_name: String,
+        algorithms: String,
+    },
+    #[error("The certificate of {host} doesn't match its pins in rv.kdl")]
+    #[diagnostic(
+        code(RV2026),
+        help(
+            "The server presented a certificate with the public key {actual}. This can mean the connection is being intercepted. If the server's certificate was replaced on purpose, update its `certificate-pin` in rv.kdl"
+        )
+    )]
+    CertificatePinMismatch { host: String, actual: String },
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(err: reqwest::Error) -> Self {
+        match rv_client::pin::pin_mismatch(&err) {
+            Some(mismatch) => Self::CertificatePinMismatch {
+                host: mismatch.host,
+                actual: mismatch.actual,
+            },
+            None => Self::Reqwest(err),
+        }
+    }
 }
 
 type Result<T> = std::result::Result<T, Error>;
 type UnpackResult<T> = std::result::Result<T, UnpackError>;
 
 pub(crate) async fn ci(global_args: &GlobalArgs, args: CleanInstallArgs) -> Result<()> {
+    if !args.watch {
+        return ci_once(global_args, &args).await;
+    }
+
+    let config = Config::with_settings(global_args, None)?;
+    let lockfile_path = find_lockfile_path(&gemfile_path(&config, args.gemfile.as_deref()))?;
+    let mut watcher = Watcher::new(&lockfile_path);
+    let debounce = Duration::from_millis(args.debounce);
+    let mut result = ci_once(global_args, &args).await;
+    loop {
+        // Keep watching after a failure: the next change is likely to be the fix.
+        if let Err(err) = result {
+            eprintln!("{:?}", miette::Report::new(err));
+        }
+
+        info!(
+            "Watching {} for changes",
+            gemfile_for_lockfile(&lockfile_path)
+        );
+        watcher.wait_for_change(debounce).await;
+        result = ci_once(global_args, &args).await;
+        watch::after_install(args.on_install.as_deref(), args.notify, result.is_ok());
+    }
+}
+
+/// The Gemfile `--gemfile` or Bundler's `BUNDLE_GEMFILE` setting points at, if any.
+pub(crate) fn gemfile_path(config: &Config, gemfile: Option<&Utf8Path>) -> Option<Utf8PathBuf> {
+    gemfile.map(Utf8Path::to_owned).or_else(|| {
+        config
+            .bundler_settings
+            .get_string("BUNDLE_GEMFILE")
+            .map(Utf8PathBuf::from)
+    })
+}
+
+async fn ci_once(global_args: &GlobalArgs, args: &CleanInstallArgs) -> Result<()> {
     let config = &Config::with_settings(global_args, None)?;
 
     config.self_update_if_needed().await;
@@ -235,7 +544,17 @@ pub(crate) async fn ci(global_args: &GlobalArgs, args: CleanInstallArgs) -> Resu
     // We need some Ruby installed, because we need to run Ruby code when installing
     // gems. Ensure Ruby is installed here so we can use it later.
     if config.current_ruby().is_none() {
-        ruby_install(global_args, None, None, None, false).await?;
+        ruby_install(
+            global_args,
+            InstallDir::Default,
+            None,
+            None,
+            false,
+            false,
+            RubyLayout::Rv,
+            Default::default(),
+        )
+        .await?;
     }
 
     // Now that it's installed, we can use Ruby to query various directories
@@ -243,19 +562,50 @@ pub(crate) async fn ci(global_args: &GlobalArgs, args: CleanInstallArgs) -> Resu
     let ruby = config
         .current_ruby()
         .expect("Ruby should be installed after the check above");
-    let extensions_scope = ruby.extensions_scope();
-    let lockfile_path = find_lockfile_path(&args.gemfile)?;
-    let install_path = config.gem_home(&ruby);
+    let bundler_compat = BundlerCompat::from_settings(&config.bundler_settings);
+    let lockfile_path = find_lockfile_path(&gemfile_path(config, args.gemfile.as_deref()))?;
+    let mut install_path = config.gem_home(&ruby);
+    if let Some(destdir) = &args.destdir {
+        install_path = reproducible::with_destdir(destdir, &install_path);
+    }
     let inner_args = CiInnerArgs {
         max_concurrent_requests: args.max_concurrent_requests,
-        max_concurrent_installs: args.max_concurrent_installs,
+        max_concurrent_installs: args
+            .max_concurrent_installs
+            .or(bundler_compat.jobs)
+            .unwrap_or(20),
         validate_checksums: args.validate_checksums,
-        install_layout: InstallLayout {
-            install_path,
-            extensions_scope,
-        },
+        unsupported_checksums: args.unsupported_checksums,
+        install_layout: BundleLayout::new(install_path, &ruby),
         ruby_executable_path: ruby.executable_path(),
         force: args.force,
+        force_ruby_platform: !args.prefer_native_platform
+            && config
+                .bundler_settings
+                .get_bool("BUNDLE_FORCE_RUBY_PLATFORM")
+                .unwrap_or(false),
+        no_network_during_build: args.no_network_during_build,
+        verbose_build: args.verbose_build,
+        output: args.output_mode(global_args.quiet),
+        retry_policy: RetryPolicy::default().with_max_retries(args.retries),
+        keep_going: args.keep_going,
+        timings: args.timings,
+        app_cache: AppCache::new(
+            lockfile_path.parent().unwrap_or(Utf8Path::new(".")),
+            bundler_compat.cache_path.as_deref(),
+            if args.vendor {
+                VendorMode::Vendor
+            } else if args.local {
+                VendorMode::Local
+            } else {
+                VendorMode::Prefer
+            },
+        ),
+        hooks: Hooks::new(
+            config.rv_settings.hooks.clone(),
+            config.project_root.clone(),
+        ),
+        remote_cache: RemoteCache::from_settings(&config.rv_settings)?,
     };
 
     // Terminal progress indicator (OSC 9;4) for supported terminals
@@ -271,19 +621,156 @@ pub(crate) async fn ci(global_args: &GlobalArgs, args: CleanInstallArgs) -> Resu
         // Normalize Windows line endings (CRLF) to Unix (LF) for the parser
         rv_lockfile::normalize_line_endings(&raw_contents).into_owned()
     };
-    let lockfile = rv_lockfile::parse(&lockfile_contents)?;
+    let mut lockfile = rv_lockfile::parse(&lockfile_contents)?;
 
     drop(span);
 
-    ci_inner_work(config, &inner_args, &progress, lockfile)
-        .await
-        .map(|_| ())
+    let report = InstallReport::default();
+    let mut manifest = if args.only_changed {
+        let install_layout = &inner_args.install_layout;
+        let mut manifest = InstallManifest::load(&install_layout.install_path)?;
+        for full_name in manifest.remove_unlocked_gems(&lockfile, install_layout)? {
+            report.record(full_name, GemStatus::Removed, None);
+        }
+        Some(manifest)
+    } else {
+        None
+    };
+    // Gems outside of the groups to install are still locked, so they're never pruned.
+    let locked_gems = args.prune.then(|| prune::locked_gems(&lockfile));
+    apply_bundler_groups(&mut lockfile, &lockfile_path, &bundler_compat, &ruby)?;
+    let standalone_lockfile = args.standalone.then(|| lockfile.clone());
+
+    let result = ci_inner_work(
+        config,
+        &inner_args,
+        &progress,
+        &report,
+        lockfile,
+        manifest.as_mut(),
+    )
+    .await;
+    if result.is_ok()
+        && let Some(manifest) = &manifest
+    {
+        manifest.save(&inner_args.install_layout.install_path)?;
+    }
+    if result.is_ok()
+        && let Some(locked_gems) = &locked_gems
+    {
+        let stale = prune::stale_gems(locked_gems, &inner_args.install_layout)?;
+        prune::remove_gems(&stale, &inner_args.install_layout)?;
+        for full_name in stale {
+            report.record(full_name, GemStatus::Removed, None);
+        }
+    }
+    if result.is_ok()
+        && let Some(mut lockfile) = standalone_lockfile
+    {
+        retain_gems_to_be_installed(
+            &mut lockfile,
+            &Platform::local(),
+            inner_args.force_ruby_platform,
+        );
+        let project_dir = lockfile_path.parent().unwrap_or(Utf8Path::new("."));
+        let setup_path =
+            standalone::write_setup(&lockfile, &inner_args.install_layout, project_dir)?;
+        if inner_args.output == OutputMode::Human {
+            println!("Wrote {setup_path}, which loads the bundle's gems without Bundler");
+        }
+    }
+    if result.is_ok()
+        && let Some(epoch) = args.source_date_epoch
+    {
+        let install_path = &inner_args.install_layout.install_path;
+        debug!("Setting the modification time of everything in {install_path} to {epoch}");
+        reproducible::normalize_timestamps(install_path, reproducible::source_date(epoch))?;
+    }
+
+    // Report on every gem, including after a failure, so it's clear which gems were affected.
+    match inner_args.output {
+        OutputMode::Human => report.print_table(),
+        OutputMode::Porcelain => report.print_porcelain(),
+        OutputMode::Quiet => {}
+    }
+    args.report.emit("rv ci", &report.test_cases())?;
+
+    result.map(|_| ())
+}
+
+/// Find the gems installed for `ruby` that `lockfile` doesn't lock, and remove them unless
+/// `dry_run` is set, returning their full names.
+pub(crate) fn prune_stale_gems(
+    config: &Config,
+    ruby: &Ruby,
+    lockfile: &GemfileDotLock,
+    dry_run: bool,
+) -> Result<Vec<String>> {
+    let install_layout = BundleLayout::new(config.gem_home(ruby), ruby);
+    let stale = prune::stale_gems(&prune::locked_gems(lockfile), &install_layout)?;
+    if !dry_run {
+        prune::remove_gems(&stale, &install_layout)?;
+    }
+    Ok(stale)
 }
 
 pub struct InstallStats {
     pub executables_installed: Vec<String>,
 }
 
+/// Honor Bundler's `BUNDLE_FROZEN`, `BUNDLE_WITHOUT`, `BUNDLE_WITH` and `BUNDLE_ONLY` settings,
+/// using the groups declared in the Gemfile next to the lockfile, and skip gems the Gemfile
+/// restricts to platforms other than `ruby`'s.
+fn apply_bundler_groups(
+    lockfile: &mut GemfileDotLock,
+    lockfile_path: &Utf8Path,
+    compat: &BundlerCompat,
+    ruby: &Ruby,
+) -> Result<()> {
+    let gemfile_path = gemfile_for_lockfile(lockfile_path);
+    let gemfile = match fs_err::read_to_string(&gemfile_path) {
+        Ok(gemfile) => gemfile,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            debug!("No Gemfile at {gemfile_path}, so all gems are in the default group");
+            String::new()
+        }
+        Err(err) => return Err(err.into()),
+    };
+    let declarations =
+        gemfile::read_gem_declarations(&gemfile_path, &gemfile, Some(&ruby.executable_path()));
+    let gem_groups = declarations
+        .iter()
+        .map(|(name, declaration)| (name.clone(), declaration.groups.clone()))
+        .collect();
+
+    if compat.frozen {
+        let missing = bundler_compat::gems_missing_from_lockfile(lockfile, &gem_groups);
+        if !missing.is_empty() {
+            return Err(Error::FrozenLockfileOutdated { gems: missing });
+        }
+    }
+
+    let default_groups = vec![DEFAULT_GROUP.to_string()];
+    let wanted = |name: &str| match declarations.get(name) {
+        Some(declaration) => compat.includes(&declaration.groups) && declaration.installs_on(ruby),
+        None => compat.includes(&default_groups),
+    };
+    if lockfile.dependencies.iter().all(|dep| wanted(dep.name)) {
+        return Ok(());
+    }
+
+    let skipped = bundler_compat::retain_gems(lockfile, wanted);
+    if !skipped.is_empty() {
+        info!(
+            "Skipping {} gems outside of the groups or platforms to install: {}",
+            skipped.len(),
+            skipped.join(", ")
+        );
+    }
+
+    Ok(())
+}
+
 pub(crate) async fn install_tool_lockfile(
     global_args: &GlobalArgs,
     request: Option<RubyRequest>,
@@ -295,37 +782,60 @@ pub(crate) async fn install_tool_lockfile(
     // We need some Ruby installed, because we need to run Ruby code when installing
     // gems. Ensure Ruby is installed here so we can use it later.
     if config.current_ruby().is_none() {
-        ruby_install(global_args, None, request, None, false).await?;
+        ruby_install(
+            global_args,
+            InstallDir::Default,
+            request,
+            None,
+            false,
+            false,
+            RubyLayout::Rv,
+            Default::default(),
+        )
+        .await?;
     }
 
     let ruby = config
         .current_ruby()
         .expect("Ruby should be installed after the check above");
-    let extensions_scope = ruby.extensions_scope();
     let inner_args = CiInnerArgs {
         max_concurrent_requests: 10,
         max_concurrent_installs: 20,
         validate_checksums: true,
-        install_layout: InstallLayout {
-            install_path: install_path.clone(),
-            extensions_scope,
-        },
+        unsupported_checksums: UnsupportedChecksums::default(),
+        install_layout: BundleLayout::new(install_path.clone(), &ruby),
         ruby_executable_path: ruby.executable_path(),
         force: true,
+        force_ruby_platform: false,
+        no_network_during_build: false,
+        verbose_build: false,
+        output: OutputMode::Human,
+        retry_policy: RetryPolicy::default(),
+        keep_going: false,
+        timings: false,
+        app_cache: AppCache::new(&install_path, None, VendorMode::Ignore),
+        hooks: Hooks::new(
+            config.rv_settings.hooks.clone(),
+            config.project_root.clone(),
+        ),
+        remote_cache: RemoteCache::from_settings(&config.rv_settings)?,
     };
 
     // Terminal progress indicator (OSC 9;4) for supported terminals
     let progress = WorkProgress::new();
 
     // Do the work.
-    ci_inner_work(config, &inner_args, &progress, lockfile).await
+    let report = InstallReport::default();
+    ci_inner_work(config, &inner_args, &progress, &report, lockfile, None).await
 }
 
 async fn ci_inner_work(
     config: &Config,
     args: &CiInnerArgs,
     progress: &WorkProgress,
+    report: &InstallReport,
     mut lockfile: GemfileDotLock<'_>,
+    mut manifest: Option<&mut InstallManifest>,
 ) -> Result<InstallStats> {
     let install_layout = &args.install_layout;
     let install_path = &install_layout.install_path;
@@ -335,16 +845,25 @@ async fn ci_inner_work(
     // over generic "ruby" platform gems. This ensures we use prebuilt binaries
     // (like libv8-node-24.1.0.0-x86_64-linux.gem) instead of compiling from
     // source (libv8-node-24.1.0.0.gem).
-    retain_gems_to_be_installed(&mut lockfile);
+    retain_gems_to_be_installed(&mut lockfile, &Platform::local(), args.force_ruby_platform);
+
+    // Gems whose locked checksum changed since they were installed are removed, so that
+    // they're installed again like any missing gem.
+    let locked_digests = manifest::locked_digests(&lockfile);
+    if let Some(manifest) = manifest.as_deref_mut() {
+        manifest.remove_changed_gems(&locked_digests, install_layout)?;
+    }
 
     if !args.force {
         let original_count = lockfile.spec_count();
-        discard_installed_gems(&mut lockfile, install_layout);
+        for full_name in discard_installed_gems(&mut lockfile, install_layout, &args.app_cache) {
+            report.record(full_name, GemStatus::Skipped, None);
+        }
         let filtered_count = lockfile.spec_count();
 
         let already_installed = original_count.saturating_sub(filtered_count);
 
-        if already_installed > 0 {
+        if already_installed > 0 && args.output == OutputMode::Human {
             let n_gems = if already_installed == 1 {
                 "1 gem".to_string()
             } else {
@@ -357,6 +876,9 @@ async fn ci_inner_work(
         }
 
         if filtered_count == 0 {
+            if let Some(manifest) = manifest {
+                manifest.record_all(locked_digests, HashMap::new());
+            }
             return Ok(InstallStats {
                 executables_installed: vec![],
             });
@@ -371,73 +893,178 @@ async fn ci_inner_work(
     let path_specs = install_paths(config, &lockfile.path, args)?;
     let path_count = path_specs.len();
     let path_fetch_elapsed = path_fetch_start.elapsed();
+    crate::stats::record_phase("path gems", path_fetch_elapsed);
 
     let git_fetch_start = Instant::now();
     let git_specs = install_git_repos(config, &lockfile.git, args)?;
     let git_count = git_specs.len();
     let git_fetch_elapsed = git_fetch_start.elapsed();
+    crate::stats::record_phase("git gems", git_fetch_elapsed);
+
+    for spec in path_specs.iter().chain(&git_specs) {
+        report.record(spec.full_name(), GemStatus::Installed, None);
+    }
+
+    for spec in lockfile.gem.iter().flat_map(|section| &section.specs) {
+        args.hooks
+            .run(HookEvent::PreDownload, &spec.release_tuple, install_layout)?;
+    }
 
     let gem_fetch_start = Instant::now();
     let stats = DownloadStats::default();
-    let downloaded = download_gems(config, &lockfile, args, progress, &stats).await?;
+    let trust = TrustStore::load(TrustStore::default_path())?;
+    let downloaded = download_gems(config, &lockfile, args, &trust, progress, report, &stats).await;
+    // Remember the digests of new releases even if other gems failed to download.
+    trust.save()?;
+    let (downloaded, failed_downloads) = downloaded?;
     let downloaded_count = downloaded.len();
     let gem_fetch_elapsed = gem_fetch_start.elapsed();
+    crate::stats::record_phase("gem downloads", gem_fetch_elapsed);
 
     let fetch_elapsed = path_fetch_elapsed + git_fetch_elapsed + gem_fetch_elapsed;
 
+    if !failed_downloads.is_empty() && !args.keep_going {
+        return Err(Error::DownloadFailures {
+            gems: failed_downloads,
+        });
+    }
+
     // Phase 2: Installs (40-80%)
     progress.start_phase(downloaded_count as u64, 40);
 
+    // Unpack and compile each gem after the gems it depends on, so that gems needed to build
+    // native extensions (like mini_portile2 or rake-compiler) are ready first.
+    let locked = DependencyGraph::new(&lockfile);
+
+    let downloaded_digests = downloaded
+        .iter()
+        .map(|download| {
+            let full_name = download.spec.release_tuple.full_name();
+            (full_name, download.sha256.clone())
+        })
+        .collect();
+
     let install_start = Instant::now();
-    let specs = install_gems(downloaded, args, progress)?;
+    let specs = install_gems(downloaded, &locked, args, progress, report)?;
     let gem_count = specs.len();
     let executables_installed = specs
         .iter()
         .flat_map(|spec| spec.executables.clone())
         .collect();
     let install_elapsed = install_start.elapsed();
+    crate::stats::record_phase("unpacking", install_elapsed);
 
     // Phase 3 (Compiles, 80-100%) - start_phase called inside compile_gems after filtering
     let compile_start = Instant::now();
-    let gems_compiled = compile_gems(config, specs, args, progress)?;
+    let gems_compiled = compile_gems(config, &specs, &locked, args, progress, report)?;
     let compile_elapsed = compile_start.elapsed();
+    crate::stats::record_phase("compiling", compile_elapsed);
+
+    for spec in &specs {
+        args.hooks
+            .run(HookEvent::PostInstall, &release_tuple(spec), install_layout)?;
+    }
+
+    if let Some(manifest) = manifest {
+        manifest.record_all(locked_digests, downloaded_digests);
+    }
 
     let total_elapsed = fetch_elapsed + install_elapsed + compile_elapsed;
     let total_gems = gem_count + git_count + path_count;
 
+    let local_platform = Platform::local();
+    if gems_compiled.total > gems_compiled.cached
+        && !args.force_ruby_platform
+        && !lockfile.has_native_platform(&local_platform)
+    {
+        warn!(
+            "Compiled {} from source, because Gemfile.lock doesn't include your platform. Precompiled versions may be available: run `bundle lock --add-platform {local_platform}` to use them.",
+            gems_compiled.names.join(", "),
+        );
+    }
+
     let (cached_count, network_count) = stats.counts();
 
-    println!("{} gems installed to {}:", total_gems, install_path);
-    println!(
-        " - {} fetching {} gems from gem servers ({} cached, {} downloaded), {} from git repos, {} from local paths",
-        format_duration(fetch_elapsed),
-        gem_count,
-        cached_count,
-        network_count,
-        git_count,
-        path_count,
-    );
-    println!(
-        " - {} unpacking {} gems from gem servers",
-        format_duration(install_elapsed),
-        gem_count,
-    );
-    if gems_compiled.total > 0 {
+    if args.output == OutputMode::Human {
+        println!("{} gems installed to {}:", total_gems, install_path);
         println!(
-            " - {} compiling {} native extensions ({} cached)",
+            " - {} fetching {} gems from gem servers ({} cached, {} downloaded), {} from git repos, {} from local paths",
+            format_duration(fetch_elapsed),
+            gem_count,
+            cached_count,
+            network_count,
+            git_count,
+            path_count,
+        );
+        println!(
+            " - {} unpacking {} gems from gem servers",
+            format_duration(install_elapsed),
+            gem_count,
+        );
+        if gems_compiled.total > 0 {
+            println!(
+                " - {} compiling {} native extensions ({} cached)",
+                format_duration(compile_elapsed),
+                gems_compiled.total,
+                gems_compiled.cached,
+            );
+        }
+        println!(" - {} total", format_duration(total_elapsed));
+        if args.app_cache.mode == VendorMode::Vendor {
+            println!("Vendored gems into {}", args.app_cache.dir());
+        }
+    }
+
+    if args.timings {
+        println!("Timings:");
+        println!(
+            "  {:<16}{:>8}  ({path_count} gems)",
+            "path gems",
+            format_duration(path_fetch_elapsed)
+        );
+        println!(
+            "  {:<16}{:>8}  ({git_count} gems)",
+            "git gems",
+            format_duration(git_fetch_elapsed)
+        );
+        println!(
+            "  {:<16}{:>8}  ({})",
+            "gem downloads",
+            format_duration(gem_fetch_elapsed),
+            stats.describe()
+        );
+        println!(
+            "  {:<16}{:>8}  ({gem_count} gems)",
+            "unpacking",
+            format_duration(install_elapsed)
+        );
+        println!(
+            "  {:<16}{:>8}  ({} extensions, {} cached)",
+            "compiling",
             format_duration(compile_elapsed),
             gems_compiled.total,
             gems_compiled.cached,
         );
+        println!("  {:<16}{:>8}", "total", format_duration(total_elapsed));
+    }
+
+    if !failed_downloads.is_empty() {
+        return Err(Error::DownloadFailures {
+            gems: failed_downloads,
+        });
     }
-    println!(" - {} total", format_duration(total_elapsed));
 
     Ok(InstallStats {
         executables_installed,
     })
 }
 
-fn retain_gems_to_be_installed(lockfile: &mut GemfileDotLock) {
+/// Keep only the variant of each gem to install on `local_platform`.
+fn retain_gems_to_be_installed(
+    lockfile: &mut GemfileDotLock,
+    local_platform: &Platform,
+    force_ruby_platform: bool,
+) {
     lockfile.gem.iter_mut().for_each(|gem_section| {
         use std::collections::HashMap;
 
@@ -445,14 +1072,14 @@ fn retain_gems_to_be_installed(lockfile: &mut GemfileDotLock) {
         for spec in &gem_section.specs {
             let release_tuple = &spec.release_tuple;
 
-            if !release_tuple.platform.is_local() {
+            if !release_tuple.platform.matches(local_platform) {
                 continue;
             }
 
             if let Some(other_spec) = by_name.get_mut(&release_tuple.name) {
                 let other_release_tuple = &other_spec.release_tuple;
 
-                if *release_tuple > *other_release_tuple {
+                if is_preferred_variant(release_tuple, other_release_tuple, force_ruby_platform) {
                     *other_spec = spec.clone();
                 }
             } else {
@@ -466,20 +1093,50 @@ fn retain_gems_to_be_installed(lockfile: &mut GemfileDotLock) {
     })
 }
 
-fn discard_installed_gems(lockfile: &mut GemfileDotLock, install_layout: &InstallLayout) {
+/// Whether `candidate` should be installed rather than `current`, another variant of the same gem.
+/// Newer versions win. Between variants of the same version, platform-specific (precompiled)
+/// variants win over the generic "ruby" platform, unless `force_ruby_platform` is set.
+fn is_preferred_variant(
+    candidate: &ReleaseTuple,
+    current: &ReleaseTuple,
+    force_ruby_platform: bool,
+) -> bool {
+    if force_ruby_platform
+        && candidate.version == current.version
+        && candidate.platform.is_ruby() != current.platform.is_ruby()
+    {
+        return candidate.platform.is_ruby();
+    }
+
+    candidate > current
+}
+
+/// Remove gems which are already installed from the lockfile, returning their full names.
+fn discard_installed_gems(
+    lockfile: &mut GemfileDotLock,
+    install_layout: &BundleLayout,
+    app_cache: &AppCache,
+) -> Vec<String> {
+    let mut discarded = Vec::new();
+
     lockfile.gem.iter_mut().for_each(|gem_section| {
         use std::path::Path;
 
         gem_section.specs.retain(|spec| {
-            let full_name = &spec.release_tuple.full_name();
-            let gem_path = install_layout.gem_path(full_name);
-            let spec_path = install_layout.spec_path(full_name);
-            let extensions_dir = install_layout.extensions_dir(full_name);
+            let full_name = spec.release_tuple.full_name();
+            let gem_path = install_layout.gem_path(&full_name);
+            let spec_path = install_layout.spec_path(&full_name);
+            let extensions_dir = install_layout.extensions_dir(&full_name);
             let ext_path = cached_compile_path(&extensions_dir);
 
-            !Path::new(&gem_path).exists()
+            let keep = !Path::new(&gem_path).exists()
                 || !Path::new(&spec_path).exists()
                 || (Path::new(&extensions_dir).exists() && !Path::new(&ext_path).exists())
+                || app_cache.needs_gem(&full_name);
+            if !keep {
+                discarded.push(full_name);
+            }
+            keep
         })
     });
 
@@ -488,14 +1145,21 @@ fn discard_installed_gems(lockfile: &mut GemfileDotLock, install_layout: &Instal
     lockfile.git.iter_mut().for_each(|git_section| {
         use std::path::Path;
 
-        let git_gem_path = install_layout.git_gem_path(git_section);
+        let git_gem_path = install_layout.git_gem_path(&git_dir_name(git_section));
 
-        git_section
-            .specs
-            .retain(|_| !Path::new(&git_gem_path).exists())
+        if Path::new(&git_gem_path).exists() && !app_cache.needs_git_repo(git_section) {
+            discarded.extend(
+                git_section
+                    .specs
+                    .drain(..)
+                    .map(|spec| spec.release_tuple.full_name()),
+            );
+        }
     });
 
     lockfile.git.retain(|section| !section.specs.is_empty());
+
+    discarded
 }
 
 fn install_paths<'i>(
@@ -559,6 +1223,7 @@ fn install_path(
                 let cache_modified = std::fs::metadata(&cached_gemspec_path)?.modified()?;
                 gemspec_modified < cache_modified
             };
+            rv_cache::record_lookup(rv_cache::CacheBucket::Gemspec, cached);
             // parse the YAML gemspec to get the executable names
             let dep_gemspec = if cached {
                 let yaml_contents = std::fs::read_to_string(&cached_gemspec_path)?;
@@ -616,9 +1281,9 @@ fn install_git_repo(
 ) -> Result<Vec<GemSpecification>> {
     debug!("Installing git repo {:?}", repo);
     let install_layout = &args.install_layout;
-    let repo_path = &repo.remote();
+    let repo_path = &repo.clone_source();
     let repo_sha = repo.sha();
-    let dest_dir = install_layout.git_gem_path(&repo.source);
+    let dest_dir = install_layout.git_gem_path(&git_dir_name(&repo.source));
     let mut just_cloned = false;
 
     if std::fs::exists(&dest_dir)?.not() {
@@ -722,6 +1387,7 @@ fn install_git_repo(
             let cache_key = format!("{gitsha}-{full_name}.gemspec");
             let cached_gemspec_path = cached_gemspecs_dir.join(&cache_key);
             let cached = std::fs::exists(&cached_gemspec_path).is_ok_and(|exists| exists);
+            rv_cache::record_lookup(rv_cache::CacheBucket::Gemspec, cached);
             let dep_gemspec = if cached {
                 let yaml_contents = std::fs::read_to_string(&cached_gemspec_path)?;
 
@@ -761,7 +1427,7 @@ fn download_git_repos<'i>(
     let downloads = pool.install(|| {
         git_sources
             .par_iter()
-            .map(|git_source| download_git_repo(&git_clone_dir, git_source))
+            .map(|git_source| download_git_repo(&git_clone_dir, git_source, &args.app_cache))
             .collect::<Result<Vec<_>>>()
     })?;
     Ok(downloads)
@@ -771,9 +1437,29 @@ fn download_git_repos<'i>(
 fn download_git_repo<'i>(
     git_clone_dir: &Utf8Path,
     git_source: &GitSection<'i>,
+    app_cache: &AppCache,
 ) -> Result<DownloadedGitRepo<'i>> {
+    if app_cache.has_git_repo(git_source) {
+        debug!(
+            "Using git repo {} vendored in {}",
+            git_source.remote,
+            app_cache.dir()
+        );
+        return Ok(DownloadedGitRepo {
+            source: git_source.clone(),
+            path: app_cache.git_path(git_source),
+            vendored: true,
+        });
+    }
+    if app_cache.mode == VendorMode::Local {
+        return Err(Error::NotVendored {
+            name: git_source.remote.to_string(),
+            dir: app_cache.dir().to_string(),
+        });
+    }
+
     // This will be the subdir within `git_clone_dir` that the git cloned repos are written to.
-    let cache_key = rv_cache::cache_digest((git_source.remote, git_source.revision));
+    let cache_key = git_cache_key(git_source);
     let git_repo_dir = git_clone_dir.join(&cache_key);
 
     // Check if it's already in the cache.
@@ -790,6 +1476,7 @@ fn download_git_repo<'i>(
             ])
             .spawn()?
             .wait()?;
+        rv_cache::record_lookup(rv_cache::CacheBucket::Git, sha_check.success());
         if !sha_check.success() {
             tracing::event!(tracing::Level::DEBUG, %git_repo_dir, %git_source.remote, %git_source.revision, "updating repo");
             let git_fetch = std::process::Command::new("git")
@@ -813,6 +1500,7 @@ fn download_git_repo<'i>(
         }
     } else {
         // It wasn't cached, so clone it.
+        rv_cache::record_miss(rv_cache::CacheBucket::Git);
         tracing::event!(tracing::Level::DEBUG, %git_clone_dir, %git_source.remote, %git_source.revision, "Cloning repo");
         let git_cloned = std::process::Command::new("git")
             .current_dir(git_clone_dir)
@@ -823,7 +1511,7 @@ fn download_git_repo<'i>(
                 "--no-hardlinks",
                 "--",
                 git_source.remote,
-                cache_key.as_ref(),
+                &cache_key,
             ])
             .spawn()?
             .wait()?;
@@ -834,10 +1522,13 @@ fn download_git_repo<'i>(
         }
     }
 
+    app_cache.vendor_git_repo(&git_repo_dir, git_source)?;
+
     // Success! Save the paths of all the repos we just cloned.
     Ok(DownloadedGitRepo {
         source: git_source.clone(),
         path: git_repo_dir,
+        vendored: false,
     })
 }
 
@@ -847,8 +1538,21 @@ fn cache_gemspec_path(
     path: PathBuf,
     cached_path: Utf8PathBuf,
 ) -> Result<GemSpecification> {
-    let gemspec_path = Utf8PathBuf::try_from(path)
-        .expect("gemspec path not valid UTF-8")
+    let gemspec_path = Utf8PathBuf::try_from(path).expect("gemspec path not valid UTF-8");
+
+    // Most gemspecs only assign literals, which rv can read without starting Ruby.
+    if let Some(dep_gemspec) = static_gemspec::evaluate(&gemspec_path) {
+        match rv_gem_specification_yaml::serialize_specification_to_yaml(&dep_gemspec) {
+            Ok(yaml_contents) => {
+                debug!("writing YAML gemspec to {}", &cached_path);
+                rv_cache::write_atomic(&cached_path, &yaml_contents)?;
+                return Ok(dep_gemspec);
+            }
+            Err(err) => debug!("Could not write {gemspec_path} as YAML: {err}"),
+        }
+    }
+
+    let gemspec_path = gemspec_path
         .as_str()
         .replace('\\', "\\\\")
         .replace('\'', "\\'");
@@ -879,12 +1583,12 @@ fn cache_gemspec_path(
         .expect("Failed to parse the result of RubyGems YAML serialization");
 
     debug!("writing YAML gemspec to {}", &cached_path);
-    fs_err::write(&cached_path, &yaml_contents)?;
+    rv_cache::write_atomic(&cached_path, &yaml_contents)?;
 
     Ok(dep_gemspec)
 }
 
-fn find_lockfile_path(gemfile: &Option<Utf8PathBuf>) -> Result<Utf8PathBuf> {
+pub(crate) fn find_lockfile_path(gemfile: &Option<Utf8PathBuf>) -> Result<Utf8PathBuf> {
     let Some(gemfile) = gemfile else {
         let lockfile_path = rv_dirs::canonicalize_utf8(Utf8Path::new("Gemfile.lock"))
             .map_err(|_| Error::MissingImplicitLockfile)?;
@@ -913,6 +1617,16 @@ fn find_lockfile_path(gemfile: &Option<Utf8PathBuf>) -> Result<Utf8PathBuf> {
     Ok(lockfile_path)
 }
 
+/// The Gemfile the lockfile at `lockfile_path` locks: `Gemfile` for `Gemfile.lock`, and `gems.rb`
+/// for `gems.locked`.
+pub(crate) fn gemfile_for_lockfile(lockfile_path: &Utf8Path) -> Utf8PathBuf {
+    if lockfile_path.file_name() == Some("gems.locked") {
+        lockfile_path.with_file_name("gems.rb")
+    } else {
+        lockfile_path.with_extension("")
+    }
+}
+
 pub fn create_rayon_pool(
     num_threads: usize,
 ) -> std::result::Result<rayon::ThreadPool, ThreadPoolBuildError> {
@@ -922,12 +1636,24 @@ pub fn create_rayon_pool(
 }
 
 fn install_gems(
-    downloaded: Vec<DownloadedRubygems>,
+    mut downloaded: Vec<DownloadedRubygems>,
+    locked: &DependencyGraph,
     args: &CiInnerArgs,
     progress: &WorkProgress,
+    report: &InstallReport,
 ) -> Result<Vec<GemSpecification>> {
     use rayon::prelude::*;
 
+    // Start unpacking the gems others depend on first. Gems are still unpacked in parallel,
+    // so this doesn't make a gem wait for its dependencies.
+    let order = install_order(locked);
+    downloaded.sort_by_key(|download| {
+        order
+            .get(download.spec.release_tuple.name.as_str())
+            .copied()
+            .unwrap_or(usize::MAX)
+    });
+
     debug!("Installing gem packages");
     let span = info_span!("Installing gem packages");
     span.pb_set_style(
@@ -942,7 +1668,14 @@ fn install_gems(
             .into_iter()
             .par_bridge()
             .map(|download| {
+                let full_name = download.spec.release_tuple.full_name();
+                let started = Instant::now();
                 let result = install_single_gem(download, args);
+                let status = match result {
+                    Ok(_) => GemStatus::Installed,
+                    Err(_) => GemStatus::Failed,
+                };
+                report.record(full_name, status, Some(started.elapsed()));
                 span.pb_inc(1);
                 progress.complete_one();
                 result
@@ -962,16 +1695,42 @@ fn install_single_gem(
     let dep_gemspec_res = download.unpack_tarball(args)?;
     debug!("Unpacked tarball {full_name}");
     let dep_gemspec = dep_gemspec_res.ok_or(UnpackError::MissingGemspec(full_name.clone()))?;
+    args.hooks.run(
+        HookEvent::PostUnpack,
+        &release_tuple(&dep_gemspec),
+        &args.install_layout,
+    )?;
     debug!("Installing binstubs for {full_name}");
     install_binstub(&dep_gemspec, args)?;
     debug!("Installed {full_name}");
     Ok(dep_gemspec)
 }
 
+/// Where each locked gem comes in an order that puts every gem after the gems it depends on.
+fn install_order<'a>(locked: &DependencyGraph<'a>) -> HashMap<&'a str, usize> {
+    locked
+        .topological_order()
+        .into_iter()
+        .enumerate()
+        .map(|(position, name)| (name, position))
+        .collect()
+}
+
+/// The name, version and platform of the gem `spec` describes.
+fn release_tuple(spec: &GemSpecification) -> ReleaseTuple {
+    ReleaseTuple::new(
+        spec.name.clone(),
+        spec.version.clone(),
+        Some(spec.platform.clone()),
+    )
+}
+
 #[derive(Default)]
 struct GemsCompiled {
     total: usize,
     cached: usize,
+    /// Names of the gems with native extensions.
+    names: Vec<String>,
 }
 
 #[derive(Default)]
@@ -990,7 +1749,7 @@ impl<'a> CompileNativeExtInfo<'a> {
         self.nodes.insert(name, spec);
     }
 
-    pub fn contains_key(&self, name: &'a String) -> bool {
+    pub fn contains_key(&self, name: &str) -> bool {
         self.nodes.contains_key(name)
     }
 
@@ -1021,16 +1780,18 @@ fn check_macos_dev_tools() -> Result<()> {
 
 fn compile_gems(
     config: &Config,
-    specs: Vec<GemSpecification>,
+    specs: &[GemSpecification],
+    locked: &DependencyGraph,
     args: &CiInnerArgs,
     progress: &WorkProgress,
+    report: &InstallReport,
 ) -> Result<GemsCompiled> {
     use dep_graph::DepGraph;
     use rayon::prelude::*;
 
     let install_layout = &args.install_layout;
 
-    let (info, deps) = make_dep_graph(&specs, install_layout)?;
+    let (info, deps) = make_dep_graph(specs, locked, install_layout)?;
     let deps_count = info.count;
 
     if deps_count == 0 {
@@ -1063,15 +1824,36 @@ fn compile_gems(
             |mut count, node| {
                 if let Some(spec) = info.get_if_has_extension(&node) {
                     span.pb_set_message(&spec.name);
-                    let compile_stats = compile_gem(config, args, spec)?;
+                    let gem_span = info_span!(parent: &span, "Compiling", gem = %spec.full_name());
+                    gem_span.pb_set_style(&gem_progress_style());
+                    let started = Instant::now();
+                    let compile_stats = gem_span.in_scope(|| compile_gem(config, args, spec));
+                    let elapsed = Some(started.elapsed());
+                    match &compile_stats {
+                        Ok(stats) if stats.ok => {
+                            report.record(spec.full_name(), GemStatus::Compiled, elapsed)
+                        }
+                        Ok(stats) => report.record_failure(
+                            spec.full_name(),
+                            Failure::new(Phase::Compile, "Could not compile native extensions")
+                                .with_log(&stats.failure_log),
+                            elapsed,
+                        ),
+                        Err(err) => report.record_failure(
+                            spec.full_name(),
+                            Failure::new(Phase::Compile, err.to_string()),
+                            elapsed,
+                        ),
+                    }
+                    let compile_stats = compile_stats?;
                     let compiled_ok = compile_stats.ok;
                     span.pb_inc(1);
                     progress.complete_one();
                     if !compiled_ok {
-                        return Err(Error::CompileFailures {
-                            gem: spec.full_name(),
-                        });
+                        return Err(compile_failure(spec.full_name(), compile_stats));
                     }
+                    args.hooks
+                        .run(HookEvent::PostCompile, &release_tuple(spec), install_layout)?;
                     if compile_stats.is_cached {
                         count += 1;
                     }
@@ -1081,17 +1863,29 @@ fn compile_gems(
         )
         .try_reduce(|| 0, |a, b| Ok(a + b))?;
 
+    let mut names: Vec<_> = specs
+        .iter()
+        .filter(|spec| !spec.extensions.is_empty())
+        .map(|spec| spec.name.clone())
+        .collect();
+    names.sort();
+
     Ok(GemsCompiled {
         total: deps_count,
         cached: total_cached_deps,
+        names,
     })
 }
 
 /// Build a dependency graph of all the gems which need compiling,
-/// and dependencies (i.e. which gems must be compiled before other gems)
+/// and dependencies (i.e. which gems must be compiled before other gems).
+/// Dependencies come from the gemspecs and from `locked`, the lockfile's graph. Gems in the
+/// lockfile that depend on each other only wait on the ones that come first in its install
+/// order, so that a dependency cycle can't leave them all waiting.
 fn make_dep_graph<'a>(
     specs: &'a [GemSpecification],
-    install_layout: &'a InstallLayout,
+    locked: &DependencyGraph,
+    install_layout: &'a BundleLayout,
 ) -> Result<(CompileNativeExtInfo<'a>, Vec<dep_graph::Node<String>>)> {
     use dep_graph::Node;
     let mut info = CompileNativeExtInfo::default();
@@ -1110,15 +1904,26 @@ fn make_dep_graph<'a>(
         Ok::<(), Error>(())
     })?;
 
+    let order = install_order(locked);
     let deps: Vec<_> = specs
         .iter()
         .map(|spec| {
             let name = spec.name.clone();
+            let position = order.get(name.as_str());
             let mut node = Node::new(name);
 
-            for dep in &spec.dependencies {
-                if dep.is_runtime() && info.contains_key(&dep.name) {
-                    node.add_dep(dep.name.clone());
+            let runtime_deps = spec
+                .dependencies
+                .iter()
+                .filter(|dep| dep.is_runtime())
+                .map(|dep| dep.name.as_str());
+            for dep in runtime_deps.chain(locked.dependencies(&spec.name)) {
+                let comes_later = match (position, order.get(dep)) {
+                    (Some(position), Some(dep_position)) => dep_position > position,
+                    _ => false,
+                };
+                if !comes_later && info.contains_key(dep) {
+                    node.add_dep(dep.to_string());
                 }
             }
 
@@ -1155,6 +1960,7 @@ fn install_binstub(gemspec: &GemSpecification, args: &CiInnerArgs) -> Result<()>
 
 enum KnownChecksumAlgos {
     Sha256,
+    Sha512,
 }
 
 struct HowToChecksum {
@@ -1162,11 +1968,19 @@ struct HowToChecksum {
     value: Vec<u8>,
 }
 
-/// Tracks how many gems were served from cache vs downloaded from the network.
+/// Tracks how many gems were served from cache vs downloaded from the network,
+/// and what it took to download them.
 #[derive(Default)]
 struct DownloadStats {
     cached: AtomicU64,
     downloaded: AtomicU64,
+    /// HTTP requests made, including retries.
+    requests: AtomicU64,
+    /// HTTP requests which were answered over HTTP/2.
+    http2_requests: AtomicU64,
+    /// Downloads which reused an identical request from another gem source.
+    coalesced: AtomicU64,
+    bytes: AtomicU64,
 }
 
 impl DownloadStats {
@@ -1178,6 +1992,33 @@ impl DownloadStats {
         self.downloaded.fetch_add(1, Ordering::Relaxed);
     }
 
+    fn coalesced_one(&self) {
+        self.coalesced.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn response(&self, version: reqwest::Version) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        if version == reqwest::Version::HTTP_2 {
+            self.http2_requests.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn received(&self, bytes: usize) {
+        self.bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    /// A one-line summary of how gems were fetched, for `rv ci --timings`.
+    fn describe(&self) -> String {
+        let (cached, downloaded) = self.counts();
+        format!(
+            "{cached} cached, {downloaded} downloaded in {} requests, {} over HTTP/2, {} coalesced, {}",
+            self.requests.load(Ordering::Relaxed),
+            self.http2_requests.load(Ordering::Relaxed),
+            self.coalesced.load(Ordering::Relaxed),
+            indicatif::HumanBytes(self.bytes.load(Ordering::Relaxed)),
+        )
+    }
+
     fn counts(&self) -> (u64, u64) {
         (
             self.cached.load(Ordering::Relaxed),
@@ -1186,15 +2027,82 @@ impl DownloadStats {
     }
 }
 
+/// Downloads gem packages through a single HTTP client, so connections (and HTTP/2 streams, for
+/// servers like rubygems.org that support them) are reused across every gem source. Requests for
+/// the same URL are coalesced, so a gem listed by several sources is only fetched once.
+struct GemDownloader {
+    client: Client,
+    retry_policy: RetryPolicy,
+    /// The requests that haven't resolved yet, by URL. Resolved requests are taken out, so that
+    /// the bodies of gems aren't all kept in memory until the install is done.
+    in_flight: std::sync::Mutex<HashMap<Url, Arc<tokio::sync::OnceCell<Bytes>>>>,
+}
+
+impl GemDownloader {
+    fn new(retry_policy: RetryPolicy) -> Result<Self> {
+        Ok(Self {
+            client: rv_http_client("ci")?,
+            retry_policy,
+            in_flight: Default::default(),
+        })
+    }
+
+    /// Fetch `url`, or wait for the result of an identical request that's already in flight.
+    /// `what` describes the request for log messages.
+    async fn fetch(&self, url: &Url, what: &str, stats: &DownloadStats) -> Result<Bytes> {
+        let cell = {
+            let mut in_flight = self.in_flight.lock().unwrap();
+            let cell = in_flight.entry(url.clone()).or_default();
+            if cell.initialized() || Arc::strong_count(cell) > 1 {
+                stats.coalesced_one();
+            }
+            Arc::clone(cell)
+        };
+
+        let client = &self.client;
+        let retry_policy = &self.retry_policy;
+        let contents = cell
+            .get_or_try_init(|| async move {
+                retry_policy
+                    .run(what, || async move {
+                        let response = client.get(url.clone()).send().await?;
+                        stats.response(response.version());
+                        let contents = response.error_for_status()?.bytes().await?;
+                        stats.received(contents.len());
+                        Ok(contents)
+                    })
+                    .await
+            })
+            .await
+            .cloned();
+
+        // Everyone waiting on the request holds the cell already, so it can be taken out.
+        {
+            let mut in_flight = self.in_flight.lock().unwrap();
+            if in_flight
+                .get(url)
+                .is_some_and(|entry| Arc::ptr_eq(entry, &cell))
+            {
+                in_flight.remove(url);
+            }
+        }
+
+        Ok(contents?)
+    }
+}
+
 /// Downloads all Rubygem server gems from a Gemfile.lock
 async fn download_gems<'i>(
     config: &Config,
     lockfile: &'i GemfileDotLock<'i>,
     args: &CiInnerArgs,
+    trust: &TrustStore,
     progress: &WorkProgress,
+    report: &InstallReport,
     stats: &DownloadStats,
-) -> Result<Vec<DownloadedRubygems<'i>>> {
+) -> Result<(Vec<DownloadedRubygems<'i>>, Vec<String>)> {
     debug!("Downloading gem packages");
+    let downloader = GemDownloader::new(args.retry_policy)?;
     let span = info_span!("Downloading gem packages");
     span.pb_set_style(
         &ProgressStyle::with_template("{spinner:.green} {span_name} {pos}/{len} - {msg}").unwrap(),
@@ -1209,48 +2117,82 @@ async fn download_gems<'i>(
     {
         let mut hm = HashMap::new();
         for checksum in checks {
-            hm.insert(
-                checksum.release_tuple.clone(),
-                HowToChecksum {
-                    algorithm: match checksum.algorithm {
-                        ChecksumAlgorithm::None => continue,
-                        ChecksumAlgorithm::Unknown(other) => {
-                            eprintln!("Unknown checksum algorithm {}", other.yellow());
-                            continue;
-                        }
-                        ChecksumAlgorithm::SHA256 => KnownChecksumAlgos::Sha256,
-                    },
-                    value: checksum.value.clone(),
-                },
-            );
+            let mut known = Vec::new();
+            let mut unsupported = Vec::new();
+            for digest in &checksum.digests {
+                let algorithm = match digest.algorithm {
+                    ChecksumAlgorithm::Unknown(other) => {
+                        unsupported.push(other);
+                        continue;
+                    }
+                    ChecksumAlgorithm::SHA256 => KnownChecksumAlgos::Sha256,
+                    ChecksumAlgorithm::SHA512 => KnownChecksumAlgos::Sha512,
+                };
+                known.push(HowToChecksum {
+                    algorithm,
+                    value: digest.value.clone(),
+                });
+            }
+            if known.is_empty() && !unsupported.is_empty() {
+                let gem_name = checksum.release_tuple.full_name();
+                let algorithms = unsupported.join(", ");
+                match args.unsupported_checksums {
+                    UnsupportedChecksums::Ignore => {
+                        debug!("Not checking {gem_name}, whose checksums are {algorithms}");
+                    }
+                    UnsupportedChecksums::Warn => eprintln!(
+                        "Unknown checksum algorithm {} for {gem_name}, installing it without checking it",
+                        algorithms.yellow()
+                    ),
+                    UnsupportedChecksums::Error => {
+                        return Err(Error::UnsupportedChecksum {
+                            gem_name,
+                            algorithms,
+                        });
+                    }
+                }
+            }
+            if !known.is_empty() {
+                hm.insert(checksum.release_tuple.clone(), known);
+            }
         }
         hm
     } else {
         HashMap::default()
     };
-    let downloaded: Vec<_> = all_sources
+    let per_source: Vec<_> = all_sources
         .map(|gem_source| {
             let checksums = &checksums;
+            let downloader = &downloader;
             let span = &span;
             async move {
-                download_gem_source(config, gem_source, checksums, args, progress, stats, span)
-                    .await
+                download_gem_source(
+                    config, gem_source, checksums, downloader, args, trust, progress, report,
+                    stats, span,
+                )
+                .await
             }
         })
         .buffered(args.max_concurrent_requests)
-        .try_collect::<Vec<_>>()
-        .await?
-        .into_iter()
-        .flatten()
-        .collect();
-    debug!("Downloaded all gems");
-    Ok(downloaded)
+        .try_collect()
+        .await?;
+
+    let mut downloaded = Vec::new();
+    let mut failed = Vec::new();
+    for (source_downloaded, source_failed) in per_source {
+        downloaded.extend(source_downloaded);
+        failed.extend(source_failed);
+    }
+    debug!("Downloaded all gems, {} failed", failed.len());
+    Ok((downloaded, failed))
 }
 
 /// A gem downloaded from a RubyGems source.
 struct DownloadedRubygems<'i> {
     contents: Bytes,
     spec: &'i Spec,
+    /// The package's SHA256, as hex.
+    sha256: String,
 }
 
 /// A gem downloaded from a git source.
@@ -1258,6 +2200,8 @@ struct DownloadedRubygems<'i> {
 struct DownloadedGitRepo<'i> {
     source: GitSection<'i>,
     path: Utf8PathBuf,
+    /// Whether `path` is a copy in the project's app cache, to be used instead of the remote.
+    vendored: bool,
 }
 
 impl<'i> DownloadedGitRepo<'i> {
@@ -1265,6 +2209,15 @@ impl<'i> DownloadedGitRepo<'i> {
         self.source.remote.to_string()
     }
 
+    /// Where to clone the repo from.
+    pub fn clone_source(&self) -> String {
+        if self.vendored {
+            self.path.to_string()
+        } else {
+            self.remote()
+        }
+    }
+
     pub fn specs(&self) -> Vec<Spec> {
         self.source.specs.clone()
     }
@@ -1496,9 +2449,50 @@ impl CompileNativeExtResult {
 static EXTCONF_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?i)extconf").unwrap());
 static RAKE_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?i)rakefile|mkrf_conf").unwrap());
 
+#[derive(Default)]
 struct CompileStats {
     ok: bool,
     is_cached: bool,
+    /// Output of the build commands that failed.
+    failure_log: String,
+    /// The absolute paths of the logs of a build that failed.
+    log_paths: Vec<Utf8PathBuf>,
+    /// The last lines of what the build commands that failed printed to stderr.
+    stderr_tail: Vec<String>,
+}
+
+/// How many lines of a failed build's stderr to show in its error.
+const COMPILE_FAILURE_STDERR_LINES: usize = 30;
+
+/// The error for a gem whose extensions could not be compiled, with where its logs are and how
+/// its build ended.
+fn compile_failure(gem: String, stats: CompileStats) -> Error {
+    let mut help = String::new();
+    for log in &stats.log_paths {
+        help.push_str(&format!("The build log is in {log}\n"));
+    }
+    if !stats.stderr_tail.is_empty() {
+        help.push_str(&format!(
+            "The build ended with:\n{}",
+            stats.stderr_tail.join("\n")
+        ));
+    }
+
+    Error::CompileFailures {
+        gem,
+        logs: stats.log_paths,
+        stderr_tail: stats.stderr_tail,
+        help: help.trim_end().to_owned(),
+    }
+}
+
+/// The last `count` lines of `output`.
+fn last_lines(output: &str, count: usize) -> Vec<String> {
+    let lines: Vec<_> = output.lines().collect();
+    lines[lines.len().saturating_sub(count)..]
+        .iter()
+        .map(|line| line.to_string())
+        .collect()
 }
 
 /// Write a file to signal there's no need to compile the gem again
@@ -1511,6 +2505,62 @@ fn cached_compile_path(ext_dest: &Utf8Path) -> Utf8PathBuf {
     ext_dest.join("gem.build_complete")
 }
 
+/// Where the compiled extensions of a gem are kept in the cache. Builds are only shared between
+/// installs of the same gem, for the same platform and Ruby ABI, built with the same configuration.
+fn compiled_extensions_cache_dir(
+    config: &Config,
+    install_layout: &BundleLayout,
+    full_name: &str,
+    build: &BuildConfig,
+) -> Utf8PathBuf {
+    let scope = rv_cache::cache_digest(&install_layout.extensions_scope);
+    let key = rv_cache::cache_digest((full_name, &build.flags, &build.env));
+    config
+        .cache
+        .shard(rv_cache::CacheBucket::Extensions, scope)
+        .into_path_buf()
+        .join(key)
+}
+
+/// Files rv writes into a gem's extensions dir itself, rather than the build, which don't belong
+/// in the gem's `lib`.
+const BUILD_BOOKKEEPING_FILES: [&str; 3] = ["gem.build_complete", "build_ext.log", "mkmf.log"];
+
+/// Copy a cached build of a gem's extensions into `ext_dest`, and its compiled files into the
+/// gem's `lib_dest`, like a fresh build does, if there is one. Returns whether the build was
+/// restored.
+fn restore_compiled_extensions(
+    cached_build: &Utf8Path,
+    ext_dest: &Utf8Path,
+    lib_dest: &Utf8Path,
+) -> Result<bool> {
+    if !cached_compile_path(cached_build).exists() {
+        return Ok(false);
+    }
+    copy_dir(cached_build, ext_dest)?;
+    let mut copy = dircpy::CopyBuilder::new(cached_build, lib_dest).overwrite(true);
+    for file in BUILD_BOOKKEEPING_FILES {
+        copy = copy.with_exclude_filter(file);
+    }
+    copy.run()?;
+    Ok(true)
+}
+
+/// Save a successful build of a gem's extensions to the cache. The build is copied to a
+/// temporary directory first and then moved into place, so other processes never see half of it.
+fn store_compiled_extensions(ext_dest: &Utf8Path, cached_build: &Utf8Path) -> Result<()> {
+    if cached_build.exists() {
+        return Ok(());
+    }
+    let shard = cached_build.parent().expect("cache entries have a parent");
+    fs_err::create_dir_all(shard)?;
+    let tmp_dir = camino_tempfile::tempdir_in(shard)?;
+    copy_dir(ext_dest, tmp_dir.path())?;
+    // Another process may have cached the same build in the meantime, which is fine.
+    let _ = fs_err::rename(tmp_dir.path(), cached_build);
+    Ok(())
+}
+
 fn compile_gem(
     config: &Config,
     args: &CiInnerArgs,
@@ -1533,15 +2583,45 @@ fn compile_gem(
         return Ok(CompileStats {
             ok: true,
             is_cached: true,
+            ..Default::default()
         });
     }
     debug!("compiling native extensions for {}", full_name);
 
+    let build = BuildConfig::new(config, args, &spec.name);
+
+    let cached_build = compiled_extensions_cache_dir(config, install_layout, &full_name, &build);
+    if restore_compiled_extensions(&cached_build, &ext_dest, &lib_dest)? {
+        debug!("restored native extensions for {} from cache", full_name);
+        rv_cache::record_hit(rv_cache::CacheBucket::Extensions);
+        return Ok(CompileStats {
+            ok: true,
+            is_cached: true,
+            ..Default::default()
+        });
+    }
+    rv_cache::record_miss(rv_cache::CacheBucket::Extensions);
+    if let Some(remote_cache) = &args.remote_cache
+        && remote_cache.fetch_extensions(&cached_build)
+        && restore_compiled_extensions(&cached_build, &ext_dest, &lib_dest)?
+    {
+        debug!(
+            "restored native extensions for {} from the remote cache",
+            full_name
+        );
+        return Ok(CompileStats {
+            ok: true,
+            is_cached: true,
+            ..Default::default()
+        });
+    }
+
     for extstr in spec.extensions.clone() {
         let extension = extstr.as_ref();
         if EXTCONF_REGEX.is_match(extension) {
-            let outputs =
-                build_extconf(config, extension, gem_home, &gem_path, &ext_dest, &lib_dest)?;
+            let outputs = build_extconf(
+                config, &build, extension, gem_home, &gem_path, &ext_dest, &lib_dest,
+            )?;
 
             compile_results.push(CompileNativeExtResult {
                 extension: extension.to_string(),
@@ -1549,8 +2629,9 @@ fn compile_gem(
             });
         } else if RAKE_REGEX.is_match(extension) {
             if !ran_rake {
-                let outputs =
-                    build_rakefile(config, extension, gem_home, &gem_path, &ext_dest, &lib_dest)?;
+                let outputs = build_rakefile(
+                    config, &build, extension, gem_home, &gem_path, &ext_dest, &lib_dest,
+                )?;
 
                 compile_results.push(CompileNativeExtResult {
                     extension: extension.to_string(),
@@ -1567,7 +2648,10 @@ fn compile_gem(
         }
     }
 
-    let mut log = fs_err::File::create(ext_dest.join("build_ext.log"))?;
+    let build_log = ext_dest.join("build_ext.log");
+    let mut log = fs_err::File::create(&build_log)?;
+    let mut failure_log = String::new();
+    let mut failure_stderr = String::new();
     for res in compile_results.iter() {
         for out in res.outputs.iter() {
             log.write_all(&out.stdout)?;
@@ -1589,12 +2673,18 @@ fn compile_gem(
                     .map(|c| c.to_string())
                     .unwrap_or("<unknown>".to_owned()),
             );
-            if !out.stdout.is_empty() {
-                eprintln!("stdout was:\n{}", String::from_utf8_lossy(&out.stdout));
-            }
-            if !out.stderr.is_empty() {
-                eprintln!("stderr was:\n{}", String::from_utf8_lossy(&out.stderr));
+            // Streamed output was printed already.
+            if build.stream_prefix.is_none() {
+                if !out.stdout.is_empty() {
+                    eprintln!("stdout was:\n{}", String::from_utf8_lossy(&out.stdout));
+                }
+                if !out.stderr.is_empty() {
+                    eprintln!("stderr was:\n{}", String::from_utf8_lossy(&out.stderr));
+                }
             }
+            failure_log.push_str(&String::from_utf8_lossy(&out.stdout));
+            failure_log.push_str(&String::from_utf8_lossy(&out.stderr));
+            failure_stderr.push_str(&String::from_utf8_lossy(&out.stderr));
         }
     }
 
@@ -1602,16 +2692,133 @@ fn compile_gem(
 
     if all_ok {
         mark_as_built(&ext_dest)?;
+        match store_compiled_extensions(&ext_dest, &cached_build) {
+            Ok(()) => {
+                if let Some(remote_cache) = &args.remote_cache {
+                    remote_cache.upload_extensions(&cached_build);
+                }
+            }
+            Err(err) => debug!(
+                "could not cache native extensions for {}: {}",
+                full_name, err
+            ),
+        }
+        return Ok(CompileStats {
+            ok: true,
+            ..Default::default()
+        });
     }
 
+    let log_paths = [build_log, ext_dest.join("mkmf.log")]
+        .into_iter()
+        .filter(|path| path.exists())
+        .map(|path| rv_dirs::canonicalize_utf8(&path).unwrap_or(path))
+        .collect();
+    // extconf.rb reports why it failed on stdout, so that's the next best thing.
+    let stderr_tail = if failure_stderr.trim().is_empty() {
+        last_lines(&failure_log, COMPILE_FAILURE_STDERR_LINES)
+    } else {
+        last_lines(&failure_stderr, COMPILE_FAILURE_STDERR_LINES)
+    };
+
     Ok(CompileStats {
-        ok: all_ok,
+        ok: false,
         is_cached: false,
+        failure_log,
+        log_paths,
+        stderr_tail,
     })
 }
 
+/// Extra configuration for building one gem's native extensions.
+#[derive(Debug, Default, PartialEq)]
+struct BuildConfig {
+    /// Arguments for the extension's `extconf.rb` or `mkrf_conf.rb`
+    flags: Vec<String>,
+    /// Environment variables for every command in the build
+    env: Vec<(String, String)>,
+    /// When set, the output of every command in the build is printed while it runs, each line
+    /// after this prefix.
+    stream_prefix: Option<String>,
+}
+
+/// Proxy settings used to deny network access to builds, pointing at the discard port.
+const NO_NETWORK_PROXY: &str = "http://127.0.0.1:9";
+
+impl BuildConfig {
+    fn new(config: &Config, args: &CiInnerArgs, gem_name: &str) -> Self {
+        let mut build = Self {
+            flags: config.bundler_settings.build_flags(gem_name),
+            env: std::env::var(build_env_var(gem_name))
+                .map(|env| parse_build_env(&env))
+                .unwrap_or_default(),
+            stream_prefix: args.verbose_build.then(|| format!("{gem_name} | ")),
+        };
+        if args.no_network_during_build {
+            build.deny_network();
+        }
+        build
+    }
+
+    /// Run one command of the build, capturing its output, and printing it too if the build's
+    /// output is streamed.
+    fn run(
+        &self,
+        invocation: Invocation,
+        config: &Config,
+        args: Vec<String>,
+        cwd: Option<&Utf8Path>,
+    ) -> Result<std::process::Output> {
+        let output = match &self.stream_prefix {
+            Some(prefix) => {
+                crate::commands::run::stream_run_no_install(invocation, config, args, cwd, prefix)?
+            }
+            None => crate::commands::run::capture_run_no_install(invocation, config, args, cwd)?,
+        };
+        Ok(output)
+    }
+
+    fn deny_network(&mut self) {
+        for var in [
+            "http_proxy",
+            "https_proxy",
+            "all_proxy",
+            "HTTP_PROXY",
+            "HTTPS_PROXY",
+            "ALL_PROXY",
+        ] {
+            self.env
+                .push((var.to_string(), NO_NETWORK_PROXY.to_string()));
+        }
+        for var in ["no_proxy", "NO_PROXY"] {
+            self.env.push((var.to_string(), String::new()));
+        }
+        self.env
+            .push(("CARGO_NET_OFFLINE".to_string(), "true".to_string()));
+    }
+}
+
+/// The variable holding extra environment for building a gem, e.g. `RV_BUILD_ENV__NOKOGIRI` for
+/// nokogiri. Dashes and dots in gem names are written like in Bundler's `BUNDLE_BUILD__<GEM>` keys.
+fn build_env_var(gem_name: &str) -> String {
+    format!(
+        "RV_BUILD_ENV__{}",
+        crate::config::bundler_settings::key_part(gem_name)
+    )
+}
+
+/// Parse whitespace-separated `NAME=value` pairs, ignoring anything else.
+fn parse_build_env(env: &str) -> Vec<(String, String)> {
+    env.split_whitespace()
+        .filter_map(|pair| pair.split_once('='))
+        .filter(|(name, _)| !name.is_empty())
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect()
+}
+
 fn build_rakefile(
     config: &Config,
+    build: &BuildConfig,
     extension: &str,
     gem_home: &Utf8PathBuf,
     gem_path: &Utf8PathBuf,
@@ -1626,10 +2833,10 @@ fn build_rakefile(
 
     // 1. Run mkrf if needed to create the Rakefile
     if ext_file.to_lowercase().contains("mkrf_conf") {
-        output = crate::commands::run::capture_run_no_install(
-            Invocation::ruby(vec![]),
+        output = build.run(
+            Invocation::ruby(vec![]).with_env(build.env.clone()),
             config,
-            vec![ext_file.to_string()],
+            [vec![ext_file.to_string()], build.flags.clone()].concat(),
             Some(&ext_dir),
         )?;
         outputs.push(output);
@@ -1641,9 +2848,10 @@ fn build_rakefile(
     let sitelibdir = format!("RUBYLIBDIR={}", tmp_dir.path());
     let args = vec![sitearchdir, sitelibdir];
 
-    let rake = Invocation::tool("rake", vec![("GEM_HOME", gem_home.to_string())]);
+    let rake = Invocation::tool("rake", vec![("GEM_HOME", gem_home.to_string())])
+        .with_env(build.env.clone());
 
-    output = crate::commands::run::capture_run_no_install(rake, config, args, Some(&ext_dir))?;
+    output = build.run(rake, config, args, Some(&ext_dir))?;
     outputs.push(output);
 
     // 3. Copy the resulting files to ext and lib dirs
@@ -1655,6 +2863,7 @@ fn build_rakefile(
 
 fn build_extconf(
     config: &Config,
+    build: &BuildConfig,
     extension: &str,
     gem_home: &Utf8PathBuf,
     gem_path: &Utf8PathBuf,
@@ -1668,10 +2877,10 @@ fn build_extconf(
     let mut outputs = vec![];
 
     // 1. Run the extconf.rb file with the current ruby
-    output = crate::commands::run::capture_run_no_install(
-        Invocation::ruby(vec![("GEM_HOME", gem_home.to_string())]),
+    output = build.run(
+        Invocation::ruby(vec![("GEM_HOME", gem_home.to_string())]).with_env(build.env.clone()),
         config,
-        vec![ext_file.to_string()],
+        [vec![ext_file.to_string()], build.flags.clone()].concat(),
         Some(&ext_dir),
     )?;
     outputs.push(output);
@@ -1701,16 +2910,16 @@ fn build_extconf(
     let make_env = vec![("GEM_HOME", gem_home.to_string())];
 
     // make clean (ignore failures)
-    let _ = crate::commands::run::capture_run_no_install(
-        Invocation::tool("make", make_env.clone()),
+    let _ = build.run(
+        Invocation::tool("make", make_env.clone()).with_env(build.env.clone()),
         config,
         [vec!["clean".to_string()], base_args.clone()].concat(),
         Some(&ext_dir),
     );
 
     // make
-    output = crate::commands::run::capture_run_no_install(
-        Invocation::tool("make", make_env.clone()),
+    output = build.run(
+        Invocation::tool("make", make_env.clone()).with_env(build.env.clone()),
         config,
         base_args.clone(),
         Some(&ext_dir),
@@ -1722,8 +2931,8 @@ fn build_extconf(
     }
 
     // make install
-    output = crate::commands::run::capture_run_no_install(
-        Invocation::tool("make", make_env.clone()),
+    output = build.run(
+        Invocation::tool("make", make_env.clone()).with_env(build.env.clone()),
         config,
         [vec!["install".to_string()], base_args.clone()].concat(),
         Some(&ext_dir),
@@ -1731,8 +2940,8 @@ fn build_extconf(
     outputs.push(output);
 
     // make clean (ignore failures)
-    let _ = crate::commands::run::capture_run_no_install(
-        Invocation::tool("make", make_env),
+    let _ = build.run(
+        Invocation::tool("make", make_env).with_env(build.env.clone()),
         config,
         [vec!["clean".to_string()], base_args].concat(),
         Some(&ext_dir),
@@ -1758,7 +2967,7 @@ where
 {
     // Unpack it (with symlink fallback on Windows):
     let mut gem_data_archive = tar::Archive::new(GzDecoder::new(data_tar_gz));
-    crate::tar_utils::unpack_tar(&mut gem_data_archive, data_dir)?;
+    rv_core::tar_utils::unpack_tar(&mut gem_data_archive, data_dir)?;
     // Get the HashReader back, so we can tell what the hash is for the contents of this tar.
     let mut gz_archive = gem_data_archive.into_inner();
     gz_archive.read_to_end(&mut Vec::new())?;
@@ -1775,7 +2984,7 @@ struct UnpackedMetadata {
 /// Given the metadata.gz from a gem, write it to the filesystem under
 /// BUNDLEPATH/specifications/name-version.gemspec
 fn unpack_metadata<R>(
-    install_layout: &InstallLayout,
+    install_layout: &BundleLayout,
     nameversion: &str,
     metadata_gz: HashReader<R>,
 ) -> UnpackResult<UnpackedMetadata>
@@ -1804,7 +3013,52 @@ where
     })
 }
 
-fn url_for_spec(remote: &str, spec: &Spec) -> Result<Url> {
+/// The name of the directory in the cache's git shard that `git_source` is cloned into.
+pub(crate) fn git_cache_key(git_source: &GitSection) -> String {
+    rv_cache::cache_digest((git_source.remote, git_source.revision))
+}
+
+/// Where the gem downloaded from `url` is cached, and the key it's cached under.
+pub(crate) fn gem_cache_path(cache: &rv_cache::Cache, url: &Url) -> (Utf8PathBuf, String) {
+    let cache_key = rv_cache::cache_digest(url.as_ref());
+    let cache_path = cache
+        .shard(rv_cache::CacheBucket::Gem, "gems")
+        .into_path_buf()
+        .join(format!("{cache_key}.gem"));
+    (cache_path, cache_key)
+}
+
+/// The gem cached at `cache_path`, unless it's corrupt. A corrupt gem is moved into the cache's
+/// quarantine, where it can be inspected, and None is returned so the gem is downloaded again.
+async fn read_cached_gem(cache: &rv_cache::Cache, cache_path: &Utf8Path) -> Result<Option<Bytes>> {
+    let contents = Bytes::from(tokio::fs::read(cache_path).await?);
+    let reader = io::Cursor::new(contents.clone());
+    let verified = tokio::task::spawn_blocking(move || rv_gem_package::verify(reader).map(|_| ()))
+        .await
+        .expect("verifying a gem should not panic");
+    let Err(err) = verified else {
+        return Ok(Some(contents));
+    };
+
+    let quarantine = cache
+        .shard(rv_cache::CacheBucket::Gem, "quarantine")
+        .into_path_buf();
+    tokio::fs::create_dir_all(&quarantine).await?;
+    let quarantined = quarantine.join(cache_path.file_name().unwrap_or("corrupt.gem"));
+    match tokio::fs::rename(cache_path, &quarantined).await {
+        // Another rv, installing at the same time, moved it first.
+        Err(rename_err) if rename_err.kind() != io::ErrorKind::NotFound => {
+            return Err(rename_err.into());
+        }
+        _ => {}
+    }
+    warn!(
+        "The cached gem {cache_path} is corrupt ({err}), so it was moved to {quarantined} and will be downloaded again"
+    );
+    Ok(None)
+}
+
+pub(crate) fn url_for_spec(remote: &str, spec: &Spec) -> Result<Url> {
     let package_name = spec.release_tuple.package_name();
     let path = format!("gems/{package_name}");
     let url = url::Url::parse(remote)
@@ -1818,81 +3072,164 @@ fn url_for_spec(remote: &str, spec: &Spec) -> Result<Url> {
 
 /// Downloads all gems from a particular gem source,
 /// e.g. from gems.coop or rubygems or something.
+#[allow(clippy::too_many_arguments)]
 async fn download_gem_source<'i>(
     config: &Config,
     gem_source: &'i GemSection<'i>,
-    checksums: &HashMap<ReleaseTuple, HowToChecksum>,
+    checksums: &HashMap<ReleaseTuple, Vec<HowToChecksum>>,
+    downloader: &GemDownloader,
     args: &CiInnerArgs,
+    trust: &TrustStore,
     progress: &WorkProgress,
+    report: &InstallReport,
     stats: &DownloadStats,
     span: &tracing::Span,
-) -> Result<Vec<DownloadedRubygems<'i>>> {
-    let client = rv_http_client("ci")?;
+) -> Result<(Vec<DownloadedRubygems<'i>>, Vec<String>)> {
     let Some(remote) = gem_source.remote else {
         debug!("Skipping download of gems attached to the global source, because it has no remote");
-        return Ok(vec![]);
+        return Ok(Default::default());
     };
+    let mirror = config.bundler_settings.mirror_for(remote);
+    if let Some(mirror) = &mirror {
+        debug!("Downloading gems from {remote} through mirror {mirror}");
+    }
+    let remote = mirror.as_deref().unwrap_or(remote);
+    // Looked up the first time a gem is actually downloaded, so that a credential helper doesn't
+    // run, or prompt, when every gem is cached.
+    let credentials = OnceCell::new();
 
-    // Download them all, concurrently.
+    // Download them all, concurrently. A gem that fails to download doesn't stop the others, so
+    // that every failure can be reported at the end.
     let spec_stream = futures_util::stream::iter(&gem_source.specs);
     let downloaded_gems: Vec<_> = spec_stream
         .map(|spec| {
-            let client = &client;
+            let full_name = spec.release_tuple.full_name();
+            let gem_span = info_span!(parent: span, "Downloading", gem = %full_name);
+            gem_span.pb_set_style(&gem_progress_style());
             async move {
-                let result =
-                    download_gem(config, remote, spec, client, checksums, stats, span).await;
+                let started = Instant::now();
+                let result = download_gem(
+                    config,
+                    remote,
+                    &credentials,
+                    spec,
+                    downloader,
+                    checksums,
+                    trust,
+                    &args.app_cache,
+                    args.remote_cache.as_ref(),
+                    stats,
+                    span,
+                )
+                .instrument(gem_span)
+                .await;
+                match &result {
+                    Ok(_) => report.record(
+                        full_name.clone(),
+                        GemStatus::Installed,
+                        Some(started.elapsed()),
+                    ),
+                    Err(err) => report.record_failure(
+                        full_name.clone(),
+                        Failure::new(Phase::Download, err.to_string()),
+                        Some(started.elapsed()),
+                    ),
+                }
                 span.pb_inc(1);
                 progress.complete_one();
-                result
+                result.map_err(|err| (full_name, err))
             }
         })
         .buffered(args.max_concurrent_requests)
-        .try_collect()
-        .await?;
+        .collect::<Vec<_>>()
+        .await;
+
+    let mut downloaded = Vec::with_capacity(downloaded_gems.len());
+    let mut failed = Vec::new();
+    for result in downloaded_gems {
+        match result {
+            Ok(gem) => downloaded.push(gem),
+            // A gem that changed under us is never skipped, not even with --keep-going, and neither
+            // is a gem missing from the app cache with --local, or a server failing its pins.
+            Err((
+                _,
+                err @ (Error::TrustedDigestChanged { .. }
+                | Error::NotVendored { .. }
+                | Error::CertificatePinMismatch { .. }),
+            )) => {
+                return Err(err);
+            }
+            Err((full_name, err)) => {
+                eprintln!("{} {full_name}: {err}", "Could not download".red());
+                failed.push(full_name);
+            }
+        }
+    }
     debug!("Finished downloading gems from source {}", remote);
-    Ok(downloaded_gems)
+    Ok((downloaded, failed))
 }
 
-/// Download a single gem, from the given URL, using the given client.
+/// Download a single gem, from the given URL, using the given downloader.
+/// Transient failures are retried according to the downloader's retry policy.
+#[allow(clippy::too_many_arguments)]
 async fn download_gem<'i>(
     config: &Config,
     remote: &str,
+    credentials: &OnceCell<Option<Userinfo>>,
     spec: &'i Spec,
-    client: &Client,
-    checksums: &HashMap<ReleaseTuple, HowToChecksum>,
+    downloader: &GemDownloader,
+    checksums: &HashMap<ReleaseTuple, Vec<HowToChecksum>>,
+    trust: &TrustStore,
+    app_cache: &AppCache,
+    remote_cache: Option<&RemoteCache>,
     stats: &DownloadStats,
     span: &tracing::Span,
 ) -> Result<DownloadedRubygems<'i>> {
     let mut url = url_for_spec(remote, spec)?;
-    let cache_key = rv_cache::cache_digest(url.as_ref());
-    let cache_path = config
-        .cache
-        .shard(rv_cache::CacheBucket::Gem, "gems")
-        .into_path_buf()
-        .join(format!("{cache_key}.gem"));
+    let vendored_path = app_cache.vendored_gem(&spec.release_tuple.full_name());
+    let (cache_path, cache_key) = gem_cache_path(&config.cache, &url);
+    let remote_key = format!("gems/{cache_key}.gem");
+    let mut downloaded_from_source = false;
 
-    let contents = if cache_path.exists() {
+    let contents = if let Some(vendored_path) = vendored_path {
+        debug!("Using gem vendored at {vendored_path}");
+        stats.cached_one();
+        Bytes::from(tokio::fs::read(&vendored_path).await?)
+    } else if app_cache.mode == VendorMode::Local {
+        return Err(Error::NotVendored {
+            name: spec.release_tuple.full_name(),
+            dir: app_cache.dir().to_string(),
+        });
+    } else if cache_path.exists()
+        && let Some(data) = read_cached_gem(&config.cache, &cache_path).await?
+    {
         debug!("Reusing gem from {url} in cache");
+        rv_cache::record_hit(rv_cache::CacheBucket::Gem);
+        stats.cached_one();
+        data
+    } else if let Some(remote_cache) = remote_cache
+        && let Some(contents) = remote_cache.get(&remote_key).await
+    {
+        debug!("Reusing gem from {url} in the remote cache");
+        rv_cache::record_miss(rv_cache::CacheBucket::Gem);
         stats.cached_one();
-        let data = tokio::fs::read(&cache_path).await?;
-        Bytes::from(data)
+        contents
     } else {
         debug!("Downloading gem from {url}");
+        rv_cache::record_miss(rv_cache::CacheBucket::Gem);
         stats.downloaded_one();
 
         if let Some(host) = url.host_str()
-            && let Some((user, password)) = config.bundler_settings.userinfo_for_host(host)
+            && let Some((user, password)) =
+                credentials.get_or_try_init(|| config.credentials_for_host(host))?
         {
-            let _ = url.set_username(&user);
+            let _ = url.set_username(user);
             let _ = url.set_password(password.as_deref());
         }
 
-        client
-            .get(url.clone())
-            .send()
-            .await?
-            .error_for_status()?
-            .bytes()
+        downloaded_from_source = true;
+        downloader
+            .fetch(&url, &spec.release_tuple.full_name(), stats)
             .await?
     };
 
@@ -1904,35 +3241,59 @@ async fn download_gem<'i>(
     let full_name = release_tuple.full_name();
 
     // Validate the checksums.
-    if let Some(checksum) = checksums.get(release_tuple) {
-        match checksum.algorithm {
-            KnownChecksumAlgos::Sha256 => {
-                let actual = sha2::Sha256::digest(&contents);
-                if actual[..] != checksum.value {
-                    return Err(Error::LockfileChecksumFail {
-                        filename: url.to_string(),
-                        gem_name: full_name,
-                        algo: "sha256",
-                    });
-                }
-            }
+    let sha256 = sha2::Sha256::digest(&contents);
+    for checksum in checksums.get(release_tuple).into_iter().flatten() {
+        let (matches, algo) = match checksum.algorithm {
+            KnownChecksumAlgos::Sha256 => (sha256[..] == checksum.value, "sha256"),
+            KnownChecksumAlgos::Sha512 => (
+                sha2::Sha512::digest(&contents)[..] == checksum.value,
+                "sha512",
+            ),
+        };
+        if !matches {
+            return Err(Error::LockfileChecksumFail {
+                filename: url.to_string(),
+                gem_name: full_name,
+                algo,
+            });
         }
     }
+    let sha256 = hex::encode(sha256);
+    if let Err(mismatch) = trust.check(&full_name, remote, &sha256) {
+        return Err(Error::TrustedDigestChanged {
+            gem_name: full_name,
+            source_url: remote.to_string(),
+            actual: mismatch.actual,
+            trusted: mismatch.trusted.sha256,
+            trusted_source: mismatch.trusted.source,
+        });
+    }
     debug!("Validated {}", full_name);
 
     if !cache_path.exists() {
-        if let Some(parent) = cache_path.parent() {
-            tokio::fs::create_dir_all(parent).await?;
-        }
-        tokio::fs::write(&cache_path, &contents).await?;
+        // Written atomically, since another rv process may be reading the same cache entry.
+        rv_cache::write_atomic(&cache_path, &contents)?;
         debug!("Cached {}", full_name);
     }
+    if downloaded_from_source && let Some(remote_cache) = remote_cache {
+        remote_cache.put(&remote_key, contents.clone()).await;
+    }
+    app_cache.vendor_gem(&full_name, &contents)?;
 
-    Ok(DownloadedRubygems { contents, spec })
+    Ok(DownloadedRubygems {
+        contents,
+        spec,
+        sha256,
+    })
+}
+
+/// Style for the progress bar of a single gem, nested under the bar of the phase it's in.
+fn gem_progress_style() -> ProgressStyle {
+    ProgressStyle::with_template("{spinner:.green} {span_name} {span_fields}").unwrap()
 }
 
 /// Format a duration in a human-readable way (e.g., "16s" or "1m16s").
-fn format_duration(duration: Duration) -> String {
+pub(crate) fn format_duration(duration: Duration) -> String {
     let secs = duration.as_secs();
     if secs >= 60 {
         let mins = secs / 60;
@@ -1946,14 +3307,29 @@ fn format_duration(duration: Duration) -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use rv_gem_types::Platform;
+
+    #[test]
+    fn test_gemfile_for_lockfile() {
+        assert_eq!(
+            gemfile_for_lockfile(Utf8Path::new("/app/Gemfile.lock")),
+            "/app/Gemfile"
+        );
+        assert_eq!(
+            gemfile_for_lockfile(Utf8Path::new("/app/gems.locked")),
+            "/app/gems.rb"
+        );
+        assert_eq!(
+            gemfile_for_lockfile(Utf8Path::new("/app/Gemfile.next.lock")),
+            "/app/Gemfile.next"
+        );
+    }
 
     #[test]
     fn test_dep_graph() {
         use tempfile::TempDir;
 
         let temp_dir = TempDir::new().unwrap();
-        let install_layout = InstallLayout {
+        let install_layout = BundleLayout {
             install_path: Utf8PathBuf::from_path_buf(temp_dir.path().to_path_buf()).unwrap(),
             extensions_scope: "arm64-darwin-23/3.4.0-static".to_string(),
         };
@@ -1979,7 +3355,8 @@ mod tests {
             .into_iter()
             .map(|s| rv_gem_specification_yaml::parse(s).unwrap())
             .collect();
-        let (info, deps) = make_dep_graph(&specs, &install_layout).unwrap();
+        let (info, deps) =
+            make_dep_graph(&specs, &DependencyGraph::default(), &install_layout).unwrap();
         assert_eq!(2, info.count);
         let dot = depgraph_to_graphviz(deps);
         insta::assert_snapshot!(dot);
@@ -1990,7 +3367,7 @@ mod tests {
         use tempfile::TempDir;
 
         let temp_dir = TempDir::new().unwrap();
-        let install_layout = InstallLayout {
+        let install_layout = BundleLayout {
             install_path: Utf8PathBuf::from_path_buf(temp_dir.path().to_path_buf()).unwrap(),
             extensions_scope: "arm64-darwin-23/3.4.0-static".to_string(),
         };
@@ -2002,12 +3379,85 @@ mod tests {
             .into_iter()
             .map(|s| rv_gem_specification_yaml::parse(s).unwrap())
             .collect();
-        let (info, deps) = make_dep_graph(&specs, &install_layout).unwrap();
+        let (info, deps) =
+            make_dep_graph(&specs, &DependencyGraph::default(), &install_layout).unwrap();
         assert_eq!(1, info.count);
         let dot = depgraph_to_graphviz(deps);
         insta::assert_snapshot!(dot);
     }
 
+    #[test]
+    fn test_dep_graph_breaks_lockfile_cycles() {
+        use tempfile::TempDir;
+
+        let temp_dir = TempDir::new().unwrap();
+        let install_layout = BundleLayout {
+            install_path: Utf8PathBuf::from_path_buf(temp_dir.path().to_path_buf()).unwrap(),
+            extensions_scope: "arm64-darwin-23/3.4.0-static".to_string(),
+        };
+
+        // In this lockfile, rake and llhttp-ffi depend on each other.
+        let lockfile = rv_lockfile::parse(
+            "\
+GEM
+  remote: https://rubygems.org/
+  specs:
+    ffi (1.17.3)
+    ffi-compiler (1.3.2)
+      ffi (>= 1.15.5)
+      rake
+    llhttp-ffi (0.4.0)
+      ffi-compiler (~> 1.0)
+      rake (~> 13.0)
+    rake (13.3.1)
+      llhttp-ffi
+
+PLATFORMS
+  ruby
+
+DEPENDENCIES
+  llhttp-ffi
+
+BUNDLED WITH
+   2.6.9
+",
+        )
+        .unwrap();
+        let locked = DependencyGraph::new(&lockfile);
+
+        let specs = [
+            include_str!(
+                "../../../rv-gem-specification-yaml/tests/fixtures/ffi-compiler-1.3.2.gemspec.yaml"
+            ),
+            include_str!(
+                "../../../rv-gem-specification-yaml/tests/fixtures/llhttp-ffi-0.4.0.gemspec.yaml"
+            ),
+            include_str!(
+                "../../../rv-gem-specification-yaml/tests/fixtures/rake-13.3.1.gemspec.yaml"
+            ),
+            include_str!(
+                "../../../rv-gem-specification-yaml/tests/fixtures/ffi-1.17.3.gemspec.yaml"
+            ),
+        ];
+        let specs: Vec<GemSpecification> = specs
+            .into_iter()
+            .map(|s| rv_gem_specification_yaml::parse(s).unwrap())
+            .collect();
+        let (info, deps) = make_dep_graph(&specs, &locked, &install_layout).unwrap();
+        assert_eq!(2, info.count);
+        // Each gem only waits on gems that come before it in the lockfile's install order.
+        assert_eq!(
+            depgraph_to_graphviz(deps),
+            "\
+digraph G {
+  ffi_compiler -> ffi;
+  llhttp_ffi -> ffi_compiler;
+  rake -> llhttp_ffi;
+  ffi;
+}"
+        );
+    }
+
     /// View the dependency graph as a GraphViz file.
     fn depgraph_to_graphviz(deps: Vec<dep_graph::Node<String>>) -> String {
         let mut dot = "digraph G {\n".to_owned();
@@ -2191,7 +3641,7 @@ SHA512:
         let input = include_str!("../../../rv-lockfile/tests/inputs/Gemfile.twosources.lock");
 
         let mut lockfile = rv_lockfile::parse(input).unwrap();
-        let install_layout = InstallLayout {
+        let install_layout = BundleLayout {
             install_path: install_path.clone(),
             extensions_scope: "arm64-darwin-23/3.4.0-static".to_string(),
         };
@@ -2200,7 +3650,8 @@ SHA512:
         let installed_gem_dir = install_path.join("gems").join("rake-13.3.0");
         fs_err::create_dir_all(&installed_gem_dir).unwrap();
 
-        discard_installed_gems(&mut lockfile, &install_layout);
+        let app_cache = AppCache::new(&install_path, None, VendorMode::Prefer);
+        discard_installed_gems(&mut lockfile, &install_layout, &app_cache);
 
         assert_eq!(lockfile.gem_spec_count(), 2);
         assert_eq!(lockfile.gem[0].specs[0].release_tuple.name, "rake");
@@ -2214,10 +3665,21 @@ SHA512:
         let installed_specification = specifications_dir.join("rake-13.3.0.gemspec");
         fs_err::write(&installed_specification, "").unwrap();
 
-        discard_installed_gems(&mut lockfile, &install_layout);
+        discard_installed_gems(&mut lockfile, &install_layout, &app_cache);
 
         assert_eq!(lockfile.gem_spec_count(), 1);
         assert_eq!(lockfile.gem[0].specs[0].release_tuple.name, "rack");
+
+        // When vendoring, installed gems are kept until they're in the app cache too.
+        let vendoring = AppCache::new(&install_path, None, VendorMode::Vendor);
+        let mut lockfile = rv_lockfile::parse(input).unwrap();
+        discard_installed_gems(&mut lockfile, &install_layout, &vendoring);
+        assert_eq!(lockfile.gem_spec_count(), 2);
+
+        vendoring.vendor_gem("rake-13.3.0", b"package").unwrap();
+        let mut lockfile = rv_lockfile::parse(input).unwrap();
+        discard_installed_gems(&mut lockfile, &install_layout, &vendoring);
+        assert_eq!(lockfile.gem_spec_count(), 1);
     }
 
     #[test]
@@ -2246,7 +3708,7 @@ SHA512:
             libv8_before.len()
         );
 
-        retain_gems_to_be_installed(&mut lockfile);
+        retain_gems_to_be_installed(&mut lockfile, &Platform::local(), false);
 
         // Get all specs filtered by platform specific
         let filtered_specs: Vec<_> = lockfile
@@ -2290,4 +3752,178 @@ SHA512:
             "should select platform-specific version for current platform"
         );
     }
+
+    #[test]
+    fn test_platform_specific_gems_by_libc() {
+        // The Discourse lockfile has libv8-node for x86_64-linux (glibc) and x86_64-linux-musl,
+        // but only glibc for aarch64-linux.
+        let input = include_str!("../../../rv-lockfile/tests/inputs/Gemfile.discourse.lock");
+        let selected = |local: &str| {
+            let mut lockfile = rv_lockfile::parse(input).unwrap();
+            retain_gems_to_be_installed(&mut lockfile, &Platform::new(local).unwrap(), false);
+            let libv8: Vec<_> = lockfile
+                .gem
+                .iter()
+                .flat_map(|section| &section.specs)
+                .filter(|s| s.release_tuple.name == "libv8-node")
+                .map(|s| s.release_tuple.full_version())
+                .collect();
+            assert_eq!(libv8.len(), 1, "{local}: {libv8:?}");
+            libv8[0].clone()
+        };
+
+        assert_eq!(selected("x86_64-linux-gnu"), "24.1.0.0-x86_64-linux");
+        assert_eq!(selected("x86_64-linux-musl"), "24.1.0.0-x86_64-linux-musl");
+        assert_eq!(selected("aarch64-linux-gnu"), "24.1.0.0-aarch64-linux");
+        // Unversioned Linux gems are built against glibc, so musl hosts compile from source.
+        assert_eq!(selected("aarch64-linux-musl"), "24.1.0.0");
+        assert_eq!(selected("arm64-darwin"), "24.1.0.0-arm64-darwin");
+        assert_eq!(selected("x64-mingw-ucrt"), "24.1.0.0");
+    }
+
+    #[test]
+    fn test_compiled_extensions_cache_roundtrip() {
+        let temp_dir = camino_tempfile::tempdir().unwrap();
+        let ext_dest = temp_dir.path().join("ext");
+        let lib_dest = temp_dir.path().join("gem/lib");
+        let cached_build = temp_dir.path().join("cache/scope/key");
+        fs_err::create_dir_all(ext_dest.join("nokogiri")).unwrap();
+        fs_err::write(ext_dest.join("nokogiri/nokogiri.so"), "binary").unwrap();
+        fs_err::write(ext_dest.join("build_ext.log"), "log").unwrap();
+
+        // Nothing cached yet
+        assert!(!restore_compiled_extensions(&cached_build, &ext_dest, &lib_dest).unwrap());
+
+        // Incomplete builds are not restored
+        store_compiled_extensions(&ext_dest, &cached_build).unwrap();
+        assert!(!restore_compiled_extensions(&cached_build, &ext_dest, &lib_dest).unwrap());
+
+        fs_err::remove_dir_all(&cached_build).unwrap();
+        mark_as_built(&ext_dest).unwrap();
+        store_compiled_extensions(&ext_dest, &cached_build).unwrap();
+
+        let restored = temp_dir.path().join("restored");
+        fs_err::create_dir_all(&lib_dest).unwrap();
+        fs_err::write(lib_dest.join("nokogiri.rb"), "require 'nokogiri/nokogiri'").unwrap();
+        assert!(restore_compiled_extensions(&cached_build, &restored, &lib_dest).unwrap());
+        assert_eq!(
+            fs_err::read_to_string(restored.join("nokogiri/nokogiri.so")).unwrap(),
+            "binary"
+        );
+        assert!(cached_compile_path(&restored).exists());
+
+        // The gem's lib gets the compiled files, but not rv's own bookkeeping, and keeps its own.
+        assert_eq!(
+            fs_err::read_to_string(lib_dest.join("nokogiri/nokogiri.so")).unwrap(),
+            "binary"
+        );
+        assert!(lib_dest.join("nokogiri.rb").exists());
+        assert!(!cached_compile_path(&lib_dest).exists());
+        assert!(!lib_dest.join("build_ext.log").exists());
+    }
+
+    #[test]
+    fn test_parse_build_env() {
+        assert_eq!(
+            parse_build_env("NOKOGIRI_USE_SYSTEM_LIBRARIES=1  CFLAGS=-O2=x junk =nope"),
+            vec![
+                ("NOKOGIRI_USE_SYSTEM_LIBRARIES".to_string(), "1".to_string()),
+                ("CFLAGS".to_string(), "-O2=x".to_string()),
+            ]
+        );
+        assert_eq!(build_env_var("libv8-node"), "RV_BUILD_ENV__LIBV8___NODE");
+    }
+
+    #[test]
+    fn test_compile_failure_shows_logs_and_stderr_tail() {
+        let stderr: String = (1..=40).map(|n| format!("error {n}\n")).collect();
+        let stats = CompileStats {
+            log_paths: vec![Utf8PathBuf::from(
+                "/gems/extensions/json-2.9.1/build_ext.log",
+            )],
+            stderr_tail: last_lines(&stderr, COMPILE_FAILURE_STDERR_LINES),
+            ..Default::default()
+        };
+        let Error::CompileFailures {
+            gem,
+            logs,
+            stderr_tail,
+            help,
+        } = compile_failure("json-2.9.1".to_string(), stats)
+        else {
+            panic!("Expected CompileFailures");
+        };
+
+        assert_eq!(gem, "json-2.9.1");
+        assert_eq!(logs.len(), 1);
+        assert_eq!(stderr_tail.len(), COMPILE_FAILURE_STDERR_LINES);
+        assert_eq!(stderr_tail.first().unwrap(), "error 11");
+        assert_eq!(stderr_tail.last().unwrap(), "error 40");
+        assert!(help.starts_with(
+            "The build log is in /gems/extensions/json-2.9.1/build_ext.log\nThe build ended with:\nerror 11\n"
+        ));
+        assert!(help.ends_with("error 40"));
+    }
+
+    #[test]
+    fn test_deny_network_during_build() {
+        let mut build = BuildConfig::default();
+        build.deny_network();
+        assert!(
+            build
+                .env
+                .contains(&("HTTPS_PROXY".to_string(), NO_NETWORK_PROXY.to_string()))
+        );
+        assert!(
+            build
+                .env
+                .contains(&("CARGO_NET_OFFLINE".to_string(), "true".to_string()))
+        );
+        assert!(build.flags.is_empty());
+    }
+
+    #[test]
+    fn test_force_ruby_platform_selects_generic_gems() {
+        let input = include_str!("../../../rv-lockfile/tests/inputs/Gemfile.discourse.lock");
+        let mut lockfile = rv_lockfile::parse(input).unwrap();
+
+        retain_gems_to_be_installed(&mut lockfile, &Platform::local(), true);
+
+        let libv8: Vec<_> = lockfile
+            .gem
+            .iter()
+            .flat_map(|section| &section.specs)
+            .filter(|s| s.release_tuple.name == "libv8-node")
+            .collect();
+        assert_eq!(libv8.len(), 1);
+        assert_eq!(libv8[0].release_tuple.full_version(), "24.1.0.0");
+    }
+
+    #[tokio::test]
+    async fn test_downloader_coalesces_identical_requests() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("GET", "/gems/test-gem-1.0.0.gem")
+            .with_body("contents")
+            .expect(1)
+            .create_async()
+            .await;
+
+        let downloader = GemDownloader::new(RetryPolicy::none()).unwrap();
+        let stats = DownloadStats::default();
+        let url = Url::parse(&format!("{}/gems/test-gem-1.0.0.gem", server.url())).unwrap();
+
+        let (first, second) = tokio::join!(
+            downloader.fetch(&url, "test-gem-1.0.0", &stats),
+            downloader.fetch(&url, "test-gem-1.0.0", &stats),
+        );
+        assert_eq!(first.unwrap(), Bytes::from_static(b"contents"));
+        assert_eq!(second.unwrap(), Bytes::from_static(b"contents"));
+
+        mock.assert_async().await;
+        assert_eq!(stats.requests.load(Ordering::Relaxed), 1);
+        assert_eq!(stats.coalesced.load(Ordering::Relaxed), 1);
+        assert_eq!(stats.bytes.load(Ordering::Relaxed), 8);
+        assert!(downloader.in_flight.lock().unwrap().is_empty());
+    }
 }
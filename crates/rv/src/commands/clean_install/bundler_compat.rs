@@ -0,0 +1,204 @@
+//! Bundler settings that `rv ci` honors, so that CI pipelines configured through `BUNDLE_*`
+//! variables can swap `bundle install` for `rv ci` without changes.
+//!
+//! `BUNDLE_PATH` and `BUNDLE_DEPLOYMENT` pick the install path (see [`Config::gem_home`]), and
+//! `BUNDLE_GEMFILE` picks the Gemfile. The rest are read here.
+//!
+//! [`Config::gem_home`]: crate::config::Config::gem_home
+
+use std::collections::{HashMap, HashSet};
+
+use rv_lockfile::datatypes::GemfileDotLock;
+use rv_lockfile::graph::DependencyGraph;
+
+use crate::config::bundler_settings::BundlerSettings;
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct BundlerCompat {
+    /// `BUNDLE_JOBS`: how many gems to install at once.
+    pub jobs: Option<usize>,
+    /// `BUNDLE_FROZEN` or `BUNDLE_DEPLOYMENT`: fail if the Gemfile and the lockfile disagree.
+    pub frozen: bool,
+    /// `BUNDLE_WITHOUT`: groups of gems not to install.
+    pub without: Vec<String>,
+    /// `BUNDLE_WITH`: groups of gems to install, even if they're also listed in `BUNDLE_WITHOUT`.
+    pub with: Vec<String>,
+    /// `BUNDLE_ONLY`: if set, only install gems in these groups.
+    pub only: Vec<String>,
+    /// `BUNDLE_CACHE_PATH`: where vendored gems live, relative to the Gemfile.
+    pub cache_path: Option<String>,
+}
+
+impl BundlerCompat {
+    pub fn from_settings(settings: &BundlerSettings) -> Self {
+        let groups = |key| {
+            settings
+                .get_string(key)
+                .map(|groups| parse_group_list(&groups))
+                .unwrap_or_default()
+        };
+
+        Self {
+            jobs: settings
+                .get_string("BUNDLE_JOBS")
+                .and_then(|jobs| jobs.parse().ok())
+                .filter(|jobs| *jobs > 0),
+            frozen: settings.get_bool("BUNDLE_FROZEN").unwrap_or(false)
+                || settings.get_bool("BUNDLE_DEPLOYMENT").unwrap_or(false),
+            without: groups("BUNDLE_WITHOUT"),
+            with: groups("BUNDLE_WITH"),
+            only: groups("BUNDLE_ONLY"),
+            cache_path: settings.get_string("BUNDLE_CACHE_PATH"),
+        }
+    }
+
+    /// Whether a gem in the given groups should be installed.
+    pub fn includes(&self, groups: &[String]) -> bool {
+        if !self.only.is_empty() {
+            return groups.iter().any(|group| self.only.contains(group));
+        }
+
+        groups
+            .iter()
+            .any(|group| !self.without.contains(group) || self.with.contains(group))
+    }
+}
+
+/// Bundler accepts group lists separated by colons or spaces, e.g. `development:test`.
+fn parse_group_list(groups: &str) -> Vec<String> {
+    groups
+        .split([':', ' ', ','])
+        .filter(|group| !group.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Gems declared in the Gemfile which the lockfile doesn't know about, i.e. gems added to the
+/// Gemfile since the lockfile was generated.
+pub fn gems_missing_from_lockfile(
+    lockfile: &GemfileDotLock,
+    gem_groups: &HashMap<String, Vec<String>>,
+) -> Vec<String> {
+    let mut missing: Vec<_> = gem_groups
+        .keys()
+        .filter(|name| !lockfile.dependencies.iter().any(|dep| dep.name == *name))
+        .cloned()
+        .collect();
+    missing.sort();
+    missing
+}
+
+/// Remove gems from the lockfile which are only needed by the Gemfile dependencies that won't
+/// be installed, because `wanted` returns false for them. Returns the names of the removed gems.
+pub fn retain_gems(lockfile: &mut GemfileDotLock, wanted: impl Fn(&str) -> bool) -> Vec<String> {
+    // Every platform variant of a gem may have its own dependencies, so use all of them.
+    let graph = DependencyGraph::new(lockfile);
+    let needed: HashSet<String> = graph.subgraph(wanted).gems().map(str::to_owned).collect();
+    let removed: Vec<String> = graph
+        .gems()
+        .filter(|name| !needed.contains(*name))
+        .map(str::to_owned)
+        .collect();
+
+    let keep = |specs: &mut Vec<rv_lockfile::datatypes::Spec>| {
+        specs.retain(|spec| needed.contains(&spec.release_tuple.name));
+    };
+    lockfile
+        .gem
+        .iter_mut()
+        .for_each(|section| keep(&mut section.specs));
+    lockfile.gem.retain(|section| !section.specs.is_empty());
+    lockfile
+        .git
+        .iter_mut()
+        .for_each(|section| keep(&mut section.specs));
+    lockfile.git.retain(|section| !section.specs.is_empty());
+    lockfile
+        .path
+        .iter_mut()
+        .for_each(|section| keep(&mut section.specs));
+    lockfile.path.retain(|section| !section.specs.is_empty());
+
+    removed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::gemfile::DEFAULT_GROUP;
+
+    fn groups(groups: &[&str]) -> Vec<String> {
+        groups.iter().map(|group| group.to_string()).collect()
+    }
+
+    #[test]
+    fn test_includes() {
+        let compat = BundlerCompat {
+            without: parse_group_list("development:test"),
+            with: groups(&["test"]),
+            ..Default::default()
+        };
+        assert!(compat.includes(&groups(&["default"])));
+        assert!(compat.includes(&groups(&["test"])));
+        assert!(compat.includes(&groups(&["development", "test"])));
+        assert!(!compat.includes(&groups(&["development"])));
+
+        let compat = BundlerCompat {
+            only: groups(&["lint"]),
+            ..Default::default()
+        };
+        assert!(compat.includes(&groups(&["development", "lint"])));
+        assert!(!compat.includes(&groups(&["default"])));
+    }
+
+    #[test]
+    fn test_retain_gems() {
+        let input =
+            include_str!("../../../../rv-lockfile/tests/inputs/Gemfile.minimal-ruby-project.lock");
+        let mut lockfile = rv_lockfile::parse(input).unwrap();
+        let gem_groups = HashMap::from([
+            ("rake".to_string(), groups(&["default"])),
+            ("rspec".to_string(), groups(&["development", "test"])),
+        ]);
+        let compat = BundlerCompat {
+            without: groups(&["development", "test"]),
+            ..Default::default()
+        };
+
+        assert!(gems_missing_from_lockfile(&lockfile, &gem_groups).is_empty());
+
+        let default_groups = groups(&[DEFAULT_GROUP]);
+        let removed = retain_gems(&mut lockfile, |name| {
+            compat.includes(gem_groups.get(name).unwrap_or(&default_groups))
+        });
+
+        assert_eq!(
+            removed,
+            vec![
+                "diff-lcs",
+                "rspec",
+                "rspec-core",
+                "rspec-expectations",
+                "rspec-mocks",
+                "rspec-support"
+            ]
+        );
+        assert_eq!(lockfile.spec_count(), 1);
+        assert_eq!(lockfile.gem[0].specs[0].release_tuple.name, "rake");
+    }
+
+    #[test]
+    fn test_gems_missing_from_lockfile() {
+        let input = include_str!("../../../../rv-lockfile/tests/inputs/Gemfile.testsource.lock");
+        let lockfile = rv_lockfile::parse(input).unwrap();
+        let gem_groups = HashMap::from([
+            ("test-gem".to_string(), groups(&["default"])),
+            ("new-gem".to_string(), groups(&["default"])),
+        ]);
+
+        assert_eq!(
+            gems_missing_from_lockfile(&lockfile, &gem_groups),
+            vec!["new-gem"]
+        );
+    }
+}
@@ -0,0 +1,142 @@
+//! The `hook` setting of `rv.kdl`, which runs the user's commands at points of each gem's
+//! installation, e.g. to scan gems with a policy scanner or to copy them into an internal cache.
+//!
+//! Each command runs in a shell, in the project's directory, and learns which gem it's called for
+//! from environment variables:
+//!
+//! - `RV_HOOK`: the event, like `post-install`
+//! - `RV_GEM_NAME`, `RV_GEM_VERSION` and `RV_GEM_PLATFORM`: the gem, e.g. `nokogiri`, `1.18.8`
+//!   and `x86_64-linux`
+//! - `RV_GEM_FULL_NAME`: the gem's full name, e.g. `nokogiri-1.18.8-x86_64-linux`
+//! - `RV_GEM_HOME`: the gem home the gem is installed into
+//! - `RV_GEM_DIR`: the gem's directory in the gem home
+//! - `RV_GEM_EXTENSIONS_DIR`: where the gem's native extensions are built into
+//!
+//! Gems are installed in parallel, so hooks for different gems can run at the same time.
+
+use std::process::Command;
+
+use camino::Utf8PathBuf;
+use rv_gem_types::ReleaseTuple;
+use rv_ruby::bundle_layout::BundleLayout;
+use tracing::debug;
+
+use super::{Error, Result};
+use crate::config::rv_settings::{HookEvent, HookSetting};
+
+/// A command that runs `command` with the platform's shell.
+pub fn shell_command(command: &str) -> Command {
+    let mut shell = if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C");
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.arg("-c");
+        shell
+    };
+    shell.arg(command);
+    shell
+}
+
+/// The hooks configured for an install.
+#[derive(Debug, Default)]
+pub struct Hooks {
+    hooks: Vec<HookSetting>,
+    /// The directory hooks run in.
+    dir: Utf8PathBuf,
+}
+
+impl Hooks {
+    pub fn new(hooks: Vec<HookSetting>, dir: Utf8PathBuf) -> Self {
+        Self { hooks, dir }
+    }
+
+    /// Whether any hook runs at `event`, so callers can skip work that's only needed for hooks.
+    pub fn has(&self, event: HookEvent) -> bool {
+        self.hooks.iter().any(|hook| hook.event == event)
+    }
+
+    /// Run the hooks for `event`, in the order they're configured, for `gem`. A hook that fails
+    /// stops the others, and the install.
+    pub fn run(&self, event: HookEvent, gem: &ReleaseTuple, layout: &BundleLayout) -> Result<()> {
+        for hook in self.hooks.iter().filter(|hook| hook.event == event) {
+            let full_name = gem.full_name();
+            debug!("Running {event} hook for {full_name}: {}", hook.command);
+            let status = shell_command(&hook.command)
+                .current_dir(&self.dir)
+                .env("RV_HOOK", event.as_str())
+                .env("RV_GEM_NAME", &gem.name)
+                .env("RV_GEM_VERSION", gem.version.to_string())
+                .env("RV_GEM_PLATFORM", gem.platform.to_string())
+                .env("RV_GEM_FULL_NAME", &full_name)
+                .env("RV_GEM_HOME", &layout.install_path)
+                .env("RV_GEM_DIR", layout.gem_path(&full_name))
+                .env("RV_GEM_EXTENSIONS_DIR", layout.extensions_dir(&full_name))
+                .status();
+
+            let reason = match status {
+                Ok(status) if status.success() => continue,
+                Ok(status) => format!("it exited with {status}"),
+                Err(err) => format!("it could not be run: {err}"),
+            };
+            return Err(Error::HookFailed {
+                event,
+                command: hook.command.clone(),
+                gem: full_name,
+                reason,
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use rv_gem_types::Version;
+
+    #[test]
+    fn test_hooks_receive_gem_and_can_fail() {
+        let temp_dir = camino_tempfile::tempdir().unwrap();
+        let layout = BundleLayout {
+            install_path: temp_dir.path().join("gems"),
+            extensions_scope: "x86_64-linux/3.4.0".to_owned(),
+        };
+        let gem = ReleaseTuple::new("rack".to_owned(), Version::new("3.1.8").unwrap(), None);
+        let hooks = Hooks::new(
+            vec![
+                HookSetting {
+                    event: HookEvent::PostUnpack,
+                    command: "echo \"$RV_HOOK $RV_GEM_FULL_NAME $RV_GEM_DIR\" >> hooks.log"
+                        .to_owned(),
+                },
+                HookSetting {
+                    event: HookEvent::PostInstall,
+                    command: "exit 3".to_owned(),
+                },
+            ],
+            temp_dir.path().to_owned(),
+        );
+
+        assert!(hooks.has(HookEvent::PostUnpack));
+        assert!(!hooks.has(HookEvent::PreDownload));
+        hooks.run(HookEvent::PreDownload, &gem, &layout).unwrap();
+        hooks.run(HookEvent::PostUnpack, &gem, &layout).unwrap();
+        assert_eq!(
+            fs_err::read_to_string(temp_dir.path().join("hooks.log")).unwrap(),
+            format!(
+                "post-unpack rack-3.1.8 {}\n",
+                temp_dir.path().join("gems/gems/rack-3.1.8")
+            )
+        );
+
+        let err = hooks
+            .run(HookEvent::PostInstall, &gem, &layout)
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            Error::HookFailed { event: HookEvent::PostInstall, ref gem, .. } if gem == "rack-3.1.8"
+        ));
+    }
+}
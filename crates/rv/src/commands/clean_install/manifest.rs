@@ -0,0 +1,219 @@
+//! A record of the gems `rv ci` installed into a bundle path, and the SHA256 of each one's package.
+//!
+//! With `--only-changed`, gems whose locked checksum still matches the recorded digest are left
+//! alone, gems whose locked checksum changed are installed again, and gems the lockfile no longer
+//! has are removed, so that installing an unchanged lockfile again is close to a no-op.
+
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use rv_lockfile::datatypes::{ChecksumAlgorithm, GemfileDotLock};
+use rv_ruby::bundle_layout::BundleLayout;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+use super::prune::{locked_gems, remove_installed_gem};
+
+/// Name of the manifest file, kept at the root of the bundle path.
+const MANIFEST_FILE: &str = ".rv-manifest.json";
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct InstallManifest {
+    /// The full name of each installed gem, like `rack-3.1.7`, and the SHA256 of its package,
+    /// if rv knows it.
+    #[serde(default)]
+    gems: BTreeMap<String, Option<String>>,
+}
+
+impl InstallManifest {
+    fn path(install_path: &Utf8Path) -> Utf8PathBuf {
+        install_path.join(MANIFEST_FILE)
+    }
+
+    /// The manifest of the gems installed in `install_path`. A missing or unreadable manifest
+    /// is empty, which makes rv fall back to checking which gems are on disk.
+    pub fn load(install_path: &Utf8Path) -> io::Result<Self> {
+        let path = Self::path(install_path);
+        let contents = match fs_err::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => return Err(err),
+        };
+        Ok(serde_json::from_str(&contents).unwrap_or_else(|err| {
+            warn!("Ignoring the install manifest at {path}, which could not be read: {err}");
+            Self::default()
+        }))
+    }
+
+    pub fn save(&self, install_path: &Utf8Path) -> io::Result<()> {
+        let json = serde_json::to_string_pretty(self).expect("the manifest serializes to JSON");
+        rv_cache::write_atomic(&Self::path(install_path), json + "\n")
+    }
+
+    /// Remember that the gem `full_name` is installed, from a package with this SHA256. A digest
+    /// that's already recorded is kept if `sha256` is unknown.
+    pub fn record(&mut self, full_name: String, sha256: Option<String>) {
+        let recorded = self.gems.entry(full_name).or_default();
+        if sha256.is_some() {
+            *recorded = sha256;
+        }
+    }
+
+    /// Record every gem in `gems`, with the SHA256 of its package if it was just downloaded, or
+    /// else its locked SHA256.
+    pub fn record_all(
+        &mut self,
+        gems: Vec<(String, Option<String>)>,
+        mut downloaded: HashMap<String, String>,
+    ) {
+        for (full_name, locked) in gems {
+            let sha256 = downloaded.remove(&full_name).or(locked);
+            self.record(full_name, sha256);
+        }
+    }
+
+    /// Uninstall the recorded gems that `lockfile` doesn't lock any more, in any of its sources
+    /// or platforms, returning their full names.
+    pub fn remove_unlocked_gems(
+        &mut self,
+        lockfile: &GemfileDotLock,
+        install_layout: &BundleLayout,
+    ) -> io::Result<Vec<String>> {
+        let locked = locked_gems(lockfile);
+        let unlocked: Vec<String> = self
+            .gems
+            .keys()
+            .filter(|full_name| !locked.contains(*full_name))
+            .cloned()
+            .collect();
+        for full_name in &unlocked {
+            debug!("Removing {full_name}, which isn't in the lockfile any more");
+            remove_installed_gem(full_name, install_layout)?;
+            self.gems.remove(full_name);
+        }
+        Ok(unlocked)
+    }
+
+    /// Uninstall the gems in `gems` whose locked SHA256 isn't the one they were installed from,
+    /// so that they're installed again. Returns their full names.
+    pub fn remove_changed_gems(
+        &mut self,
+        gems: &[(String, Option<String>)],
+        install_layout: &BundleLayout,
+    ) -> io::Result<Vec<String>> {
+        let mut changed = Vec::new();
+        for (full_name, locked) in gems {
+            let Some(locked) = locked else { continue };
+            if let Some(Some(recorded)) = self.gems.get(full_name)
+                && recorded != locked
+            {
+                debug!("Installing {full_name} again, since its locked checksum changed");
+                remove_installed_gem(full_name, install_layout)?;
+                self.gems.remove(full_name);
+                changed.push(full_name.clone());
+            }
+        }
+        Ok(changed)
+    }
+}
+
+/// The full name of every gem from a gem server in `lockfile`, and its locked SHA256 as hex, if
+/// the lockfile has one.
+pub fn locked_digests(lockfile: &GemfileDotLock) -> Vec<(String, Option<String>)> {
+    let checksums = lockfile.checksums.as_deref().unwrap_or_default();
+    lockfile
+        .gem
+        .iter()
+        .flat_map(|section| &section.specs)
+        .map(|spec| {
+            let sha256 = checksums
+                .iter()
+                .filter(|checksum| checksum.release_tuple == spec.release_tuple)
+                .flat_map(|checksum| &checksum.digests)
+                .find(|digest| digest.algorithm == ChecksumAlgorithm::SHA256)
+                .map(|digest| hex::encode(&digest.value));
+            (spec.release_tuple.full_name(), sha256)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOCKFILE: &str = "\
+GEM
+  remote: https://rubygems.org/
+  specs:
+    rack (3.1.7)
+    rake (13.3.1)
+
+PLATFORMS
+  ruby
+
+DEPENDENCIES
+  rack
+  rake
+
+CHECKSUMS
+  rack (3.1.7) sha256=b4e2c0c2ec2ae4f4e4f1b4f6a3c42d85e4a9bd9f19c8ae8cfe0e2c42e8d9f1a2
+  rake (13.3.1)
+
+BUNDLED WITH
+   2.6.9
+";
+
+    fn install(install_layout: &BundleLayout, full_name: &str) {
+        fs_err::create_dir_all(install_layout.gem_path(full_name)).unwrap();
+        fs_err::create_dir_all(install_layout.specifications_dir()).unwrap();
+        fs_err::write(install_layout.spec_path(full_name), "").unwrap();
+    }
+
+    #[test]
+    fn test_only_changed_gems_are_removed() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let install_layout = BundleLayout {
+            install_path: Utf8PathBuf::from_path_buf(temp_dir.path().to_path_buf()).unwrap(),
+            extensions_scope: "x86_64-linux/3.4.0".to_string(),
+        };
+        let lockfile = rv_lockfile::parse(LOCKFILE).unwrap();
+        let locked = locked_digests(&lockfile);
+        assert_eq!(locked[1], ("rake-13.3.1".to_string(), None));
+
+        let mut manifest = InstallManifest::default();
+        for full_name in ["rack-3.1.7", "rake-13.3.1", "rails-8.0.2"] {
+            install(&install_layout, full_name);
+            manifest.record(full_name.to_string(), Some("0".repeat(64)));
+        }
+
+        let removed = manifest
+            .remove_unlocked_gems(&lockfile, &install_layout)
+            .unwrap();
+        assert_eq!(removed, ["rails-8.0.2"]);
+        assert!(!install_layout.gem_path("rails-8.0.2").exists());
+        assert!(!install_layout.spec_path("rails-8.0.2").exists());
+
+        // Only rack has a locked checksum to compare against.
+        let changed = manifest
+            .remove_changed_gems(&locked, &install_layout)
+            .unwrap();
+        assert_eq!(changed, ["rack-3.1.7"]);
+        assert!(!install_layout.gem_path("rack-3.1.7").exists());
+        assert!(install_layout.gem_path("rake-13.3.1").exists());
+
+        manifest.record_all(
+            locked,
+            HashMap::from([("rack-3.1.7".to_string(), "1".repeat(64))]),
+        );
+        manifest.save(&install_layout.install_path).unwrap();
+        let manifest = InstallManifest::load(&install_layout.install_path).unwrap();
+        assert_eq!(
+            manifest.gems,
+            BTreeMap::from([
+                ("rack-3.1.7".to_string(), Some("1".repeat(64))),
+                ("rake-13.3.1".to_string(), Some("0".repeat(64))),
+            ])
+        );
+    }
+}
@@ -0,0 +1,203 @@
+//! Removing gems from a bundle path that the lockfile doesn't lock any more, like versions left
+//! behind by past updates, along with their specifications, compiled extensions and binstubs.
+
+use std::collections::BTreeSet;
+use std::io;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use rv_lockfile::datatypes::GemfileDotLock;
+use rv_ruby::bundle_layout::BundleLayout;
+use tracing::debug;
+
+/// How the binstubs rv writes start their second line, which names the gem each one runs.
+const BINSTUB_MARKER: &str = "# This executable comes from the '";
+
+/// The full name of every gem `lockfile` locks from a gem server, on any platform.
+pub fn locked_gems(lockfile: &GemfileDotLock) -> BTreeSet<String> {
+    lockfile
+        .gem
+        .iter()
+        .flat_map(|section| &section.specs)
+        .map(|spec| spec.release_tuple.full_name())
+        .collect()
+}
+
+/// The full names of the gems installed in `install_layout`, going by their specifications.
+pub fn installed_gems(install_layout: &BundleLayout) -> io::Result<Vec<String>> {
+    let specifications_dir = install_layout.specifications_dir();
+    let entries = match fs_err::read_dir(&specifications_dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+
+    let mut installed = Vec::new();
+    for entry in entries {
+        let file_name = entry?.file_name();
+        if let Some(full_name) = file_name
+            .to_str()
+            .and_then(|name| name.strip_suffix(".gemspec"))
+        {
+            installed.push(full_name.to_string());
+        }
+    }
+    installed.sort();
+    Ok(installed)
+}
+
+/// The installed gems that aren't in `locked`. Bundler is never stale, since it's installed
+/// next to the gems without being locked like them.
+pub fn stale_gems(
+    locked: &BTreeSet<String>,
+    install_layout: &BundleLayout,
+) -> io::Result<Vec<String>> {
+    Ok(installed_gems(install_layout)?
+        .into_iter()
+        .filter(|full_name| !locked.contains(full_name) && !is_release_of(full_name, "bundler"))
+        .collect())
+}
+
+/// Remove the installed gems in `stale`, and the binstubs of gems that no longer have any version
+/// installed.
+pub fn remove_gems(stale: &[String], install_layout: &BundleLayout) -> io::Result<()> {
+    for full_name in stale {
+        debug!("Removing {full_name}, which isn't in the lockfile");
+        remove_installed_gem(full_name, install_layout)?;
+    }
+
+    let installed = installed_gems(install_layout)?;
+    for (path, gem_name) in binstubs(&install_layout.binstub_dir())? {
+        if installed
+            .iter()
+            .any(|full_name| is_release_of(full_name, &gem_name))
+        {
+            continue;
+        }
+        debug!("Removing binstub {path} of {gem_name}, which isn't installed any more");
+        fs_err::remove_file(&path)?;
+        let bat_path = path.with_extension("bat");
+        if bat_path.exists() {
+            fs_err::remove_file(bat_path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Delete the files of the installed gem `full_name`: its unpacked contents, its specification
+/// and its compiled extensions.
+pub fn remove_installed_gem(full_name: &str, install_layout: &BundleLayout) -> io::Result<()> {
+    for dir in [
+        install_layout.gem_path(full_name),
+        install_layout.extensions_dir(full_name),
+    ] {
+        match fs_err::remove_dir_all(&dir) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+            _ => {}
+        }
+    }
+    match fs_err::remove_file(install_layout.spec_path(full_name)) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+        _ => Ok(()),
+    }
+}
+
+/// The binstubs rv wrote in `binstub_dir`, and the name of the gem each one runs.
+fn binstubs(binstub_dir: &Utf8Path) -> io::Result<Vec<(Utf8PathBuf, String)>> {
+    let entries = match fs_err::read_dir(binstub_dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+
+    let mut binstubs = Vec::new();
+    for entry in entries {
+        let Ok(path) = Utf8PathBuf::try_from(entry?.path()) else {
+            continue;
+        };
+        if path.extension() == Some("bat") || !path.is_file() {
+            continue;
+        }
+        // Binstubs are small, but other files in the directory may not be.
+        let Ok(contents) = fs_err::read_to_string(&path) else {
+            continue;
+        };
+        let gem_name = contents
+            .lines()
+            .nth(1)
+            .and_then(|line| line.strip_prefix(BINSTUB_MARKER))
+            .and_then(|rest| rest.split_once("' gem"))
+            .map(|(gem_name, _)| gem_name.replace("\\'", "'").replace("\\\\", "\\"));
+        if let Some(gem_name) = gem_name {
+            binstubs.push((path, gem_name));
+        }
+    }
+    Ok(binstubs)
+}
+
+/// Whether `full_name`, like `rack-3.1.7`, is a release of the gem `gem_name`. Versions start with
+/// a digit, so this tells `rack-3.1.7` apart from releases of `rack-test`.
+fn is_release_of(full_name: &str, gem_name: &str) -> bool {
+    full_name
+        .strip_prefix(gem_name)
+        .and_then(|rest| rest.strip_prefix('-'))
+        .is_some_and(|version| version.starts_with(|c: char| c.is_ascii_digit()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::generate_binstub_contents;
+    use super::*;
+
+    fn install(install_layout: &BundleLayout, full_name: &str, gem_name: &str, exe: &str) {
+        fs_err::create_dir_all(install_layout.gem_path(full_name)).unwrap();
+        fs_err::create_dir_all(install_layout.extensions_dir(full_name)).unwrap();
+        fs_err::create_dir_all(install_layout.specifications_dir()).unwrap();
+        fs_err::write(install_layout.spec_path(full_name), "").unwrap();
+        fs_err::create_dir_all(install_layout.binstub_dir()).unwrap();
+        fs_err::write(
+            install_layout.binstub_dir().join(exe),
+            generate_binstub_contents(gem_name, exe),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_is_release_of() {
+        assert!(is_release_of("rack-3.1.7", "rack"));
+        assert!(is_release_of(
+            "nokogiri-1.18.8-x86_64-linux-gnu",
+            "nokogiri"
+        ));
+        assert!(!is_release_of("rack-test-2.2.0", "rack"));
+        assert!(!is_release_of("rack", "rack"));
+    }
+
+    #[test]
+    fn test_prune_stale_gems() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let install_layout = BundleLayout {
+            install_path: Utf8PathBuf::from_path_buf(temp_dir.path().to_path_buf()).unwrap(),
+            extensions_scope: "x86_64-linux/3.4.0".to_string(),
+        };
+        install(&install_layout, "rake-13.2.1", "rake", "rake");
+        install(&install_layout, "rake-13.3.1", "rake", "rake");
+        install(&install_layout, "rackup-2.2.1", "rackup", "rackup");
+        install(&install_layout, "bundler-2.6.9", "bundler", "bundle");
+
+        let locked = BTreeSet::from(["rake-13.3.1".to_string()]);
+        let stale = stale_gems(&locked, &install_layout).unwrap();
+        assert_eq!(stale, ["rackup-2.2.1", "rake-13.2.1"]);
+
+        remove_gems(&stale, &install_layout).unwrap();
+        assert_eq!(
+            installed_gems(&install_layout).unwrap(),
+            ["bundler-2.6.9", "rake-13.3.1"]
+        );
+        assert!(!install_layout.gem_path("rake-13.2.1").exists());
+        assert!(!install_layout.extensions_dir("rake-13.2.1").exists());
+        // rake is still installed, so its binstub stays.
+        assert!(install_layout.binstub_dir().join("rake").exists());
+        assert!(!install_layout.binstub_dir().join("rackup").exists());
+        assert!(install_layout.binstub_dir().join("bundle").exists());
+    }
+}
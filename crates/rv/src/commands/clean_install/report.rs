@@ -0,0 +1,219 @@
+use std::borrow::Cow;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use anstream::println;
+use tabled::{Table, settings::Style};
+
+use super::format_duration;
+use crate::failure_report::{Failure, TestCase};
+
+/// How `rv ci` reports what it's doing.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputMode {
+    /// Progress bars while working, then a summary and a table of every gem.
+    #[default]
+    Human,
+    /// Print nothing but errors.
+    Quiet,
+    /// No progress bars, then one tab-separated line per gem, for scripts and CI logs.
+    Porcelain,
+}
+
+/// What happened to a single gem during `rv ci`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum GemStatus {
+    Installed,
+    Compiled,
+    Skipped,
+    /// Uninstalled, because the lockfile doesn't have it any more.
+    Removed,
+    Failed,
+}
+
+impl GemStatus {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Installed => "installed",
+            Self::Compiled => "compiled",
+            Self::Skipped => "skipped",
+            Self::Removed => "removed",
+            Self::Failed => "failed",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct GemReport {
+    full_name: String,
+    status: GemStatus,
+    duration: Option<Duration>,
+    failure: Option<Failure>,
+}
+
+impl GemReport {
+    fn duration(&self) -> String {
+        self.duration.map(format_duration).unwrap_or_default()
+    }
+}
+
+impl tabled::Tabled for GemReport {
+    const LENGTH: usize = 3;
+
+    fn fields(&self) -> Vec<Cow<'_, str>> {
+        vec![
+            Cow::Borrowed(&self.full_name),
+            Cow::Borrowed(self.status.as_str()),
+            Cow::Owned(self.duration()),
+        ]
+    }
+
+    fn headers() -> Vec<Cow<'static, str>> {
+        vec!["Gem".into(), "Status".into(), "Time".into()]
+    }
+}
+
+/// Collects the outcome of every gem `rv ci` handles, from whichever thread handled it.
+#[derive(Debug, Default)]
+pub struct InstallReport {
+    gems: Mutex<Vec<GemReport>>,
+}
+
+impl InstallReport {
+    pub fn record(&self, full_name: String, status: GemStatus, duration: Option<Duration>) {
+        self.record_with_failure(full_name, status, duration, None);
+    }
+
+    /// Record that a gem failed, and why.
+    pub fn record_failure(&self, full_name: String, failure: Failure, duration: Option<Duration>) {
+        self.record_with_failure(full_name, GemStatus::Failed, duration, Some(failure));
+    }
+
+    fn record_with_failure(
+        &self,
+        full_name: String,
+        status: GemStatus,
+        duration: Option<Duration>,
+        failure: Option<Failure>,
+    ) {
+        let mut gems = self.gems.lock().unwrap();
+
+        // A gem that gets compiled after it's installed should only be listed once.
+        if let Some(existing) = gems.iter_mut().find(|gem| gem.full_name == full_name) {
+            existing.status = existing.status.max(status);
+            existing.duration = match (existing.duration, duration) {
+                (Some(a), Some(b)) => Some(a + b),
+                (a, b) => a.or(b),
+            };
+            if failure.is_some() {
+                existing.failure = failure;
+            }
+        } else {
+            gems.push(GemReport {
+                full_name,
+                status,
+                duration,
+                failure,
+            });
+        }
+    }
+
+    fn sorted(&self) -> Vec<GemReport> {
+        let mut gems = self.gems.lock().unwrap().clone();
+        gems.sort_by(|a, b| a.full_name.cmp(&b.full_name));
+        gems
+    }
+
+    /// Print a table of every gem, its status and how long it took.
+    pub fn print_table(&self) {
+        let gems = self.sorted();
+        if gems.is_empty() {
+            return;
+        }
+
+        let mut table = Table::new(gems);
+        table.with(Style::sharp());
+        println!("{table}");
+    }
+
+    /// Print one `<status>\t<gem>\t<milliseconds>` line per gem.
+    pub fn print_porcelain(&self) {
+        for gem in self.sorted() {
+            let millis = gem
+                .duration
+                .map(|duration| duration.as_millis().to_string())
+                .unwrap_or_default();
+            println!("{}\t{}\t{millis}", gem.status.as_str(), gem.full_name);
+        }
+    }
+
+    /// Every gem as a test case for `--report` and `--github-annotations`.
+    pub fn test_cases(&self) -> Vec<TestCase> {
+        self.sorted()
+            .into_iter()
+            .map(|gem| TestCase {
+                name: gem.full_name,
+                duration: gem.duration,
+                failure: gem.failure,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::failure_report::Phase;
+
+    #[test]
+    fn test_record_merges_install_and_compile() {
+        let report = InstallReport::default();
+        report.record(
+            "nokogiri-1.18.0".into(),
+            GemStatus::Installed,
+            Some(Duration::from_millis(200)),
+        );
+        report.record("rake-13.3.0".into(), GemStatus::Skipped, None);
+        report.record(
+            "nokogiri-1.18.0".into(),
+            GemStatus::Compiled,
+            Some(Duration::from_millis(300)),
+        );
+
+        let gems = report.sorted();
+        assert_eq!(gems.len(), 2);
+        assert_eq!(gems[0].full_name, "nokogiri-1.18.0");
+        assert_eq!(gems[0].status, GemStatus::Compiled);
+        assert_eq!(gems[0].duration, Some(Duration::from_millis(500)));
+        assert_eq!(gems[1].full_name, "rake-13.3.0");
+        assert_eq!(gems[1].status, GemStatus::Skipped);
+    }
+
+    #[test]
+    fn test_record_failure() {
+        let report = InstallReport::default();
+        report.record("rake-13.3.0".into(), GemStatus::Installed, None);
+        report.record_failure(
+            "rack-3.1.0".into(),
+            Failure::new(Phase::Download, "HTTP status server error (503)"),
+            Some(Duration::from_millis(100)),
+        );
+
+        let cases = report.test_cases();
+        assert_eq!(cases.len(), 2);
+        assert_eq!(cases[0].name, "rack-3.1.0");
+        assert_eq!(cases[0].failure.as_ref().unwrap().phase, Phase::Download);
+        assert!(cases[1].failure.is_none());
+    }
+
+    #[test]
+    fn test_failure_wins_over_success() {
+        let report = InstallReport::default();
+        report.record("pg-1.5.9".into(), GemStatus::Installed, None);
+        report.record("pg-1.5.9".into(), GemStatus::Failed, None);
+
+        let gems = report.sorted();
+        assert_eq!(gems.len(), 1);
+        assert_eq!(gems[0].status, GemStatus::Failed);
+    }
+}
@@ -0,0 +1,97 @@
+//! Installing gems for container images: `--destdir` stages the install in another directory, to be
+//! copied into the image's root, and `SOURCE_DATE_EPOCH` gives every installed file the same
+//! timestamp, so that installing the same lockfile again builds an identical layer.
+
+use std::io;
+use std::time::{Duration, SystemTime};
+
+use camino::{Utf8Component, Utf8Path, Utf8PathBuf};
+
+/// `path` moved under `destdir`, like `make install DESTDIR=…` does, so that `/usr/local/bundle`
+/// becomes `<destdir>/usr/local/bundle`.
+pub fn with_destdir(destdir: &Utf8Path, path: &Utf8Path) -> Utf8PathBuf {
+    let relative: Utf8PathBuf = path
+        .components()
+        .filter(|component| !matches!(component, Utf8Component::Prefix(_) | Utf8Component::RootDir))
+        .collect();
+    destdir.join(relative)
+}
+
+/// The time `SOURCE_DATE_EPOCH`, in seconds since the Unix epoch, stands for.
+pub fn source_date(epoch: u64) -> SystemTime {
+    SystemTime::UNIX_EPOCH + Duration::from_secs(epoch)
+}
+
+/// Set the modification time of `dir` and everything in it to `time`, going through entries in
+/// name order. Symlinks are left alone, since their own times can't be set portably.
+pub fn normalize_timestamps(dir: &Utf8Path, time: SystemTime) -> io::Result<()> {
+    let mut entries = fs_err::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<Vec<_>>>()?;
+    entries.sort();
+
+    for path in entries {
+        let file_type = fs_err::symlink_metadata(&path)?.file_type();
+        if file_type.is_dir() {
+            let path = Utf8PathBuf::try_from(path).map_err(|err| err.into_io_error())?;
+            normalize_timestamps(&path, time)?;
+        } else if file_type.is_file() {
+            set_modified(&path, time)?;
+        }
+    }
+
+    // Windows can't open directories like files, so only their contents get normalized there.
+    if cfg!(windows) {
+        return Ok(());
+    }
+    set_modified(dir.as_std_path(), time)
+}
+
+fn set_modified(path: &std::path::Path, time: SystemTime) -> io::Result<()> {
+    // Windows only lets files opened for writing change their times. Elsewhere, opening them for
+    // reading is enough, and works for read-only files too.
+    let file = std::fs::OpenOptions::new()
+        .read(true)
+        .write(cfg!(windows))
+        .open(path)?;
+    file.set_modified(time)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_with_destdir() {
+        let destdir = Utf8Path::new("/staging");
+        assert_eq!(
+            with_destdir(destdir, Utf8Path::new("/app/vendor/bundle/ruby/3.4.0")),
+            Utf8Path::new("/staging/app/vendor/bundle/ruby/3.4.0")
+        );
+        assert_eq!(
+            with_destdir(destdir, Utf8Path::new("vendor/bundle")),
+            Utf8Path::new("/staging/vendor/bundle")
+        );
+    }
+
+    #[test]
+    fn test_normalize_timestamps() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let dir = Utf8Path::from_path(temp_dir.path()).unwrap();
+        fs_err::create_dir_all(dir.join("gems/rake-13.3.1/lib")).unwrap();
+        fs_err::write(dir.join("gems/rake-13.3.1/lib/rake.rb"), "").unwrap();
+
+        let time = source_date(315_532_800);
+        normalize_timestamps(dir, time).unwrap();
+        let modified = |path: &str| {
+            fs_err::metadata(dir.join(path))
+                .unwrap()
+                .modified()
+                .unwrap()
+        };
+        assert_eq!(modified("gems/rake-13.3.1/lib/rake.rb"), time);
+        if !cfg!(windows) {
+            assert_eq!(modified("gems/rake-13.3.1/lib"), time);
+        }
+    }
+}
@@ -0,0 +1,234 @@
+//! The `bundler/setup.rb` that `rv ci --standalone` writes, which puts every gem in the bundle on
+//! the load path like `bundle install --standalone` does. Apps can `require_relative` it, and run
+//! without Bundler or rv being installed.
+
+use std::io;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use glob::glob;
+use rv_lockfile::datatypes::GemfileDotLock;
+use rv_ruby::bundle_layout::BundleLayout;
+use tracing::debug;
+
+/// Where the setup script goes, next to git gems in the bundle path.
+pub fn setup_path(install_layout: &BundleLayout) -> Utf8PathBuf {
+    install_layout.install_path.join("bundler/setup.rb")
+}
+
+/// Write the setup script for the gems of `lockfile` installed in `install_layout`, returning its
+/// path. Path gems are relative to `project_dir`, the directory of the lockfile.
+pub fn write_setup(
+    lockfile: &GemfileDotLock,
+    install_layout: &BundleLayout,
+    project_dir: &Utf8Path,
+) -> io::Result<Utf8PathBuf> {
+    let mut load_paths = Vec::new();
+
+    for spec in lockfile.gem.iter().flat_map(|section| &section.specs) {
+        let full_name = spec.release_tuple.full_name();
+        let spec_path = install_layout.spec_path(&full_name);
+        let Ok(installed_spec) = fs_err::read_to_string(&spec_path) else {
+            debug!("Leaving {full_name} out of the setup script, since it isn't installed");
+            continue;
+        };
+        let stub = Stub::parse(&installed_spec);
+        for require_path in &stub.require_paths {
+            load_paths.push(LoadPath::Bundled(format!(
+                "gems/{full_name}/{require_path}"
+            )));
+        }
+        if stub.has_extensions {
+            let extensions_dir = install_layout.extensions_dir(&full_name);
+            let relative = extensions_dir
+                .strip_prefix(&install_layout.install_path)
+                .expect("extensions are installed in the bundle path");
+            load_paths.push(LoadPath::Bundled(relative.as_str().replace('\\', "/")));
+        }
+    }
+
+    for section in &lockfile.git {
+        let repo_dir = install_layout.git_gem_path(&super::git_dir_name(section));
+        let relative = repo_dir
+            .strip_prefix(&install_layout.install_path)
+            .expect("git gems are installed in the bundle path")
+            .as_str()
+            .replace('\\', "/");
+        for spec in &section.specs {
+            // Git gems are used in place, with their gemspec's directory as the gem's root.
+            let name = &spec.release_tuple.name;
+            if let Some(gem_dir) = gemspec_dir(&repo_dir, name) {
+                let gem_dir = gem_dir.strip_prefix(&repo_dir).unwrap_or(&gem_dir);
+                let gem_dir = Utf8Path::new(&relative).join(gem_dir).join("lib");
+                load_paths.push(LoadPath::Bundled(gem_dir.as_str().replace('\\', "/")));
+            }
+        }
+    }
+
+    for section in &lockfile.path {
+        let path_dir = project_dir.join(section.remote);
+        let path_dir = rv_dirs::canonicalize_utf8(&path_dir).unwrap_or(path_dir);
+        for spec in &section.specs {
+            if let Some(gem_dir) = gemspec_dir(&path_dir, &spec.release_tuple.name) {
+                load_paths.push(LoadPath::Absolute(gem_dir.join("lib")));
+            }
+        }
+    }
+
+    let path = setup_path(install_layout);
+    fs_err::create_dir_all(path.parent().expect("the setup script is in a directory"))?;
+    rv_cache::write_atomic(&path, setup_contents(&load_paths))?;
+    Ok(path)
+}
+
+/// A directory to put on the load path.
+#[derive(Debug, PartialEq, Eq)]
+enum LoadPath {
+    /// Relative to the bundle path, so that it can be moved along with the gems.
+    Bundled(String),
+    /// A path gem, which stays where it is.
+    Absolute(Utf8PathBuf),
+}
+
+fn setup_contents(load_paths: &[LoadPath]) -> String {
+    let mut contents = "\
+# This file was generated by `rv ci --standalone` (https://rv.dev).
+# Require it instead of bundler/setup to load the bundle's gems without Bundler.
+
+path = File.expand_path('..', __dir__)
+"
+    .to_string();
+    for load_path in load_paths {
+        let line = match load_path {
+            LoadPath::Bundled(relative) => {
+                format!(
+                    "$:.unshift File.expand_path('{}', path)\n",
+                    escape(relative)
+                )
+            }
+            LoadPath::Absolute(absolute) => format!("$:.unshift '{}'\n", escape(absolute.as_str())),
+        };
+        contents.push_str(&line);
+    }
+    contents
+}
+
+/// Escape `s` to go in a single-quoted Ruby string.
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('\'', "\\'")
+}
+
+/// The directory of the gemspec of the gem `name`, somewhere in `dir`.
+fn gemspec_dir(dir: &Utf8Path, name: &str) -> Option<Utf8PathBuf> {
+    let pattern = dir.join(format!("**/{name}.gemspec"));
+    let gemspec = glob(pattern.as_str()).ok()?.flatten().next()?;
+    let gemspec = Utf8PathBuf::from_path_buf(gemspec).ok()?;
+    gemspec.parent().map(Utf8Path::to_owned)
+}
+
+/// What the stub lines at the top of an installed specification say about the gem, which RubyGems
+/// reads to load gems without evaluating their whole specification.
+#[derive(Debug, PartialEq, Eq)]
+struct Stub {
+    require_paths: Vec<String>,
+    has_extensions: bool,
+}
+
+impl Stub {
+    /// Parse lines like `# stub: rake 13.3.1 ruby lib`, where the require paths are separated by
+    /// null bytes, and an optional second one listing extensions.
+    fn parse(spec: &str) -> Self {
+        let mut stubs = spec
+            .lines()
+            .filter_map(|line| line.strip_prefix("# stub: "));
+        let require_paths = stubs
+            .next()
+            .and_then(|stub| stub.splitn(4, ' ').nth(3))
+            .map(|paths| paths.split('\0').map(str::to_string).collect())
+            .unwrap_or_else(|| vec!["lib".to_string()]);
+        Self {
+            require_paths,
+            has_extensions: stubs.next().is_some(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_stub() {
+        let spec = "# -*- encoding: utf-8 -*-\n# stub: json 2.9.1 ruby lib\0ext\n# stub: ext/json/extconf.rb\n\nGem::Specification.new do |s|\n";
+        assert_eq!(
+            Stub::parse(spec),
+            Stub {
+                require_paths: vec!["lib".to_string(), "ext".to_string()],
+                has_extensions: true,
+            }
+        );
+
+        let spec = "# -*- encoding: utf-8 -*-\n# stub: rake 13.3.1 ruby lib\n\nGem::Specification.new do |s|\n";
+        assert_eq!(
+            Stub::parse(spec),
+            Stub {
+                require_paths: vec!["lib".to_string()],
+                has_extensions: false,
+            }
+        );
+    }
+
+    #[test]
+    fn test_write_setup() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let install_layout = BundleLayout {
+            install_path: Utf8PathBuf::from_path_buf(temp_dir.path().to_path_buf()).unwrap(),
+            extensions_scope: "x86_64-linux/3.4.0".to_string(),
+        };
+        fs_err::create_dir_all(install_layout.specifications_dir()).unwrap();
+        fs_err::write(
+            install_layout.spec_path("json-2.9.1"),
+            "# stub: json 2.9.1 ruby lib\n# stub: ext/json/extconf.rb\n",
+        )
+        .unwrap();
+        fs_err::write(
+            install_layout.spec_path("rake-13.3.1"),
+            "# stub: rake 13.3.1 ruby lib\n",
+        )
+        .unwrap();
+
+        let lockfile = rv_lockfile::parse(
+            "\
+GEM
+  remote: https://rubygems.org/
+  specs:
+    json (2.9.1)
+    rake (13.3.1)
+
+PLATFORMS
+  ruby
+
+DEPENDENCIES
+  json
+  rake
+
+BUNDLED WITH
+   2.6.9
+",
+        )
+        .unwrap();
+        let path = write_setup(&lockfile, &install_layout, Utf8Path::new(".")).unwrap();
+        assert_eq!(path, install_layout.install_path.join("bundler/setup.rb"));
+        assert_eq!(
+            fs_err::read_to_string(path).unwrap(),
+            "\
+# This file was generated by `rv ci --standalone` (https://rv.dev).
+# Require it instead of bundler/setup to load the bundle's gems without Bundler.
+
+path = File.expand_path('..', __dir__)
+$:.unshift File.expand_path('gems/json-2.9.1/lib', path)
+$:.unshift File.expand_path('extensions/x86_64-linux/3.4.0/json-2.9.1', path)
+$:.unshift File.expand_path('gems/rake-13.3.1/lib', path)
+"
+        );
+    }
+}
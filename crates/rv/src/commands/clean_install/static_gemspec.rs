@@ -0,0 +1,965 @@
+//! Reads `.gemspec` files without running Ruby, for the many gemspecs that only assign literals:
+//! strings, arrays and hashes, `Dir` globs of the gem's files, and `VERSION` constants from the
+//! files they require.
+//!
+//! This isn't a Ruby interpreter. Anything it doesn't understand, like shelling out to
+//! `git ls-files`, string interpolation or conditionals, makes it give up, so the gemspec is
+//! evaluated by Ruby instead.
+
+use std::collections::HashMap;
+
+use camino::Utf8Path;
+use glob::{MatchOptions, Pattern};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use rv_gem_types::{Dependency, DependencyType, Platform, Requirement, Specification, Version};
+use tracing::debug;
+
+/// Matches the block that builds the specification, capturing its block variable.
+static SPEC_BLOCK_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"^Gem::Specification\.new\s+do\s*\|\s*(\w+)\s*\|$"#).expect("valid regex")
+});
+
+/// Matches `require_relative "lib/foo/version"` or `require "foo/version"`.
+static REQUIRE_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"^(require_relative|require)\s*\(?\s*["']([^"']+)["']\s*\)?$"#)
+        .expect("valid regex")
+});
+
+/// Matches the lines that put `lib` on the load path, so the gemspec can require its version.
+static LOAD_PATH_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"^(?:lib\s*=\s*File\.expand_path\(|\$LOAD_PATH\b|\$:)"#).expect("valid regex")
+});
+
+/// Matches a string constant in a required file, like `VERSION = "1.2.3".freeze`.
+static CONSTANT_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"(?m)^\s*([A-Z]\w*)\s*=\s*["']([^"'#]*)["'](?:\.freeze)?\s*$"#)
+        .expect("valid regex")
+});
+
+/// Matches an assignment to an attribute, like `spec.files = ...` or `spec.metadata["x"] = ...`.
+static ASSIGNMENT_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"(?s)^(\w+)\.(\w+)\s*(?:\[\s*["']([^"']+)["']\s*\])?\s*(=|\+=|<<)\s*([^=].*)$"#)
+        .expect("valid regex")
+});
+
+/// Matches a dependency declaration, like `spec.add_dependency "rack", "~> 3.0"`.
+static DEPENDENCY_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r#"(?s)^(\w+)\.add_(dependency|runtime_dependency|development_dependency)\b\s*(.*)$"#,
+    )
+    .expect("valid regex")
+});
+
+/// Matches the guard RubyGems puts after attributes that old versions don't have, like
+/// `if s.respond_to? :metadata=`.
+static RESPOND_TO_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"\s+if\s+\w+\.respond_to\?\s*\(?\s*:\w+=?\s*\)?$"#).expect("valid regex")
+});
+
+/// Matches a block that only takes the basename of each file, like `{ |f| File.basename(f) }`.
+static BASENAME_BLOCK_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"^\{\s*\|\s*(\w+)\s*\|\s*File\.basename\(\s*(\w+)\s*\)\s*\}"#)
+        .expect("valid regex")
+});
+
+/// The specification in the gemspec at `gemspec_path`, if it can be read without Ruby. Relative
+/// globs are relative to the gemspec's directory, like when Bundler loads it.
+pub fn evaluate(gemspec_path: &Utf8Path) -> Option<Specification> {
+    let contents = fs_err::read_to_string(gemspec_path).ok()?;
+    let spec = evaluate_str(&contents, gemspec_path.parent()?);
+    match spec {
+        Some(_) => debug!("Read {gemspec_path} without Ruby"),
+        None => debug!("{gemspec_path} needs Ruby to be evaluated"),
+    }
+    spec
+}
+
+fn evaluate_str(contents: &str, gem_dir: &Utf8Path) -> Option<Specification> {
+    let mut statements = statements(contents).into_iter();
+    let mut constants = Constants::default();
+
+    let var = loop {
+        let statement = statements.next()?;
+        if let Some(captures) = SPEC_BLOCK_REGEX.captures(&statement) {
+            break captures[1].to_string();
+        } else if let Some(captures) = REQUIRE_REGEX.captures(&statement) {
+            constants.require(gem_dir, &captures[1], &captures[2])?;
+        } else if !LOAD_PATH_REGEX.is_match(&statement) {
+            return None;
+        }
+    };
+
+    let context = Context {
+        gem_dir,
+        constants,
+        var,
+    };
+    let mut spec = Specification::new("unnamed".to_string(), Version::new("0").ok()?).ok()?;
+    let (mut name, mut version) = (false, false);
+    loop {
+        let statement = statements.next()?;
+        if statement == "end" {
+            break;
+        }
+        let statement = RESPOND_TO_REGEX.replace(&statement, "");
+        match context.run(&statement, &mut spec)? {
+            "name" => name = true,
+            "version" => version = true,
+            _ => {}
+        }
+    }
+
+    // Nothing may follow the specification, since it's the value of the gemspec.
+    (name && version && statements.next().is_none()).then_some(spec)
+}
+
+/// The statements of a gemspec, without comments. A statement that spans several lines, like an
+/// array with an element per line, is joined into one.
+fn statements(contents: &str) -> Vec<String> {
+    let mut statements: Vec<String> = Vec::new();
+    let mut current = String::new();
+    let mut depth = 0;
+
+    for line in contents.lines() {
+        if line == "__END__" {
+            break;
+        }
+        let (code, delta) = strip_comment(line);
+        let code = code.trim();
+        if code.is_empty() {
+            continue;
+        }
+        // A method chain may continue on the next line, like `.reject { ... }`.
+        if current.is_empty()
+            && code.starts_with('.')
+            && let Some(previous) = statements.pop()
+        {
+            current = previous;
+        }
+        if !current.is_empty() {
+            current.push('\n');
+        }
+        current.push_str(code);
+        depth += delta;
+
+        let continues = depth > 0 || code.ends_with([',', '+', '=', '\\', '.']);
+        if !continues {
+            statements.push(std::mem::take(&mut current));
+            depth = 0;
+        }
+    }
+    if !current.is_empty() {
+        statements.push(current);
+    }
+    statements
+}
+
+/// The code of `line` before any comment, and how many more brackets it opens than it closes.
+fn strip_comment(line: &str) -> (&str, i32) {
+    let mut quote = None;
+    let mut escaped = false;
+    let mut delta = 0;
+    for (i, c) in line.char_indices() {
+        match quote {
+            Some(_) if escaped => escaped = false,
+            Some(_) if c == '\\' => escaped = true,
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None => match c {
+                '"' | '\'' => quote = Some(c),
+                '#' => return (&line[..i], delta),
+                '(' | '[' | '{' => delta += 1,
+                ')' | ']' | '}' => delta -= 1,
+                _ => {}
+            },
+        }
+    }
+    (line, delta)
+}
+
+/// String constants defined by the files the gemspec requires, by their unqualified name.
+#[derive(Debug, Default)]
+struct Constants(HashMap<String, Option<String>>);
+
+impl Constants {
+    /// Read the constants of a required file. Files outside the gem, like `rubygems`, define
+    /// nothing the gemspec needs, but a missing `require_relative` would make Ruby fail.
+    fn require(&mut self, gem_dir: &Utf8Path, method: &str, feature: &str) -> Option<()> {
+        let feature = if feature.ends_with(".rb") {
+            feature.to_string()
+        } else {
+            format!("{feature}.rb")
+        };
+        let path = if method == "require_relative" {
+            gem_dir.join(&feature)
+        } else {
+            gem_dir.join("lib").join(&feature)
+        };
+        let contents = match fs_err::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(_) if method == "require" => return Some(()),
+            Err(_) => return None,
+        };
+        for captures in CONSTANT_REGEX.captures_iter(&contents) {
+            let value = captures[2].to_string();
+            self.0
+                .entry(captures[1].to_string())
+                // Constants of the same name in different modules can't be told apart.
+                .and_modify(|existing| {
+                    if existing.as_ref() != Some(&value) {
+                        *existing = None;
+                    }
+                })
+                .or_insert(Some(value));
+        }
+        Some(())
+    }
+
+    /// The value of a constant like `Foo::VERSION`.
+    fn get(&self, path: &str) -> Option<&str> {
+        let name = path.rsplit("::").next()?;
+        self.0.get(name)?.as_deref()
+    }
+}
+
+/// A value the gemspec can compute without Ruby.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Value {
+    Nil,
+    /// `true` or `false`, which only attributes rv doesn't keep are set to.
+    Bool,
+    Int(i32),
+    Str(String),
+    List(Vec<String>),
+    Hash(Vec<(String, String)>),
+}
+
+impl Value {
+    fn string(self) -> Option<String> {
+        match self {
+            Value::Str(string) => Some(string),
+            _ => None,
+        }
+    }
+
+    fn optional_string(self) -> Option<Option<String>> {
+        match self {
+            Value::Nil => Some(None),
+            Value::Str(string) => Some(Some(string)),
+            _ => None,
+        }
+    }
+
+    /// A string or a list of them, which RubyGems accepts wherever it expects a list.
+    fn strings(self) -> Option<Vec<String>> {
+        match self {
+            Value::Str(string) => Some(vec![string]),
+            Value::List(list) => Some(list),
+            _ => None,
+        }
+    }
+
+    fn plus(self, other: Value) -> Option<Value> {
+        match (self, other) {
+            (Value::Str(a), Value::Str(b)) => Some(Value::Str(a + &b)),
+            (Value::List(mut a), Value::List(b)) => {
+                a.extend(b);
+                Some(Value::List(a))
+            }
+            _ => None,
+        }
+    }
+}
+
+struct Context<'a> {
+    gem_dir: &'a Utf8Path,
+    constants: Constants,
+    /// The block variable of `Gem::Specification.new`, like `spec`.
+    var: String,
+}
+
+impl Context<'_> {
+    /// Run one statement of the specification block, returning the attribute it sets.
+    fn run<'s>(&self, statement: &'s str, spec: &mut Specification) -> Option<&'s str> {
+        if let Some(captures) = DEPENDENCY_REGEX.captures(statement) {
+            let var = captures.get(1)?.as_str();
+            let method = captures.get(2)?.as_str();
+            let args = captures.get(3)?.as_str();
+            if var != self.var {
+                return None;
+            }
+            let mut args = Expr::new(args, self, spec).call_args()?.into_iter();
+            let name = args.next()?.string()?;
+            let mut requirements = Vec::new();
+            for arg in args {
+                requirements.extend(arg.strings()?);
+            }
+            let dep_type = if method == "development_dependency" {
+                DependencyType::Development
+            } else {
+                DependencyType::Runtime
+            };
+            spec.dependencies
+                .push(Dependency::new(name, requirements, Some(dep_type)).ok()?);
+            return Some("dependencies");
+        }
+
+        let captures = ASSIGNMENT_REGEX.captures(statement)?;
+        let var = captures.get(1)?.as_str();
+        let attribute = captures.get(2)?.as_str();
+        let key = captures.get(3).map(|key| key.as_str());
+        let operator = captures.get(4)?.as_str();
+        if var != self.var {
+            return None;
+        }
+        let mut expr = Expr::new(captures.get(5)?.as_str(), self, spec);
+        let value = expr.expr()?;
+        expr.finish()?;
+
+        match (key, operator) {
+            (None, "=") => assign(spec, attribute, value)?,
+            (None, _) => list_mut(spec, attribute)?.extend(value.strings()?),
+            (Some(key), "=") if attribute == "metadata" => {
+                spec.metadata.insert(key.to_string(), value.string()?);
+            }
+            (Some(_), _) => return None,
+        }
+        Some(attribute)
+    }
+}
+
+/// Set `attribute` of `spec` to `value`, if `spec` has such an attribute and the value fits.
+fn assign(spec: &mut Specification, attribute: &str, value: Value) -> Option<()> {
+    match attribute {
+        "name" => spec.name = value.string()?,
+        "version" => spec.version = Version::new(value.string()?).ok()?,
+        "summary" => spec.summary = value.string()?,
+        "description" => spec.description = value.optional_string()?,
+        "homepage" => spec.homepage = value.optional_string()?,
+        "bindir" => spec.bindir = value.string()?,
+        "date" => spec.date = value.string()?,
+        "rubygems_version" => spec.rubygems_version = value.string()?,
+        "post_install_message" => spec.post_install_message = value.optional_string()?,
+        "signing_key" => spec.signing_key = value.optional_string()?,
+        "autorequire" => spec.autorequire = value.optional_string()?,
+        "platform" => spec.platform = value.string()?.parse::<Platform>().ok()?,
+        "author" | "authors" => spec.authors = value.strings()?.into_iter().map(Some).collect(),
+        "email" => spec.email = value.strings()?.into_iter().map(Some).collect(),
+        "license" => spec.licenses = value.strings()?,
+        "executable" => spec.executables = value.strings()?,
+        "require_path" => spec.require_paths = value.strings()?,
+        "test_file" => spec.test_files = value.strings()?,
+        "required_ruby_version" => {
+            spec.required_ruby_version = Requirement::new(value.strings()?).ok()?;
+        }
+        "required_rubygems_version" => {
+            spec.required_rubygems_version = Requirement::new(value.strings()?).ok()?;
+        }
+        "specification_version" => match value {
+            Value::Int(version) => spec.specification_version = version,
+            _ => return None,
+        },
+        "metadata" => match value {
+            Value::Hash(pairs) => spec.metadata = pairs.into_iter().collect(),
+            _ => return None,
+        },
+        // Attributes RubyGems ignores, or that rv doesn't keep.
+        "installed_by_version" | "rubyforge_project" | "has_rdoc" | "default_executable" => {}
+        _ => *list_mut(spec, attribute)? = value.strings()?,
+    }
+    Some(())
+}
+
+/// The attributes of `spec` that are lists of strings.
+fn list_mut<'a>(spec: &'a mut Specification, attribute: &str) -> Option<&'a mut Vec<String>> {
+    Some(match attribute {
+        "files" => &mut spec.files,
+        "executables" => &mut spec.executables,
+        "extensions" => &mut spec.extensions,
+        "licenses" => &mut spec.licenses,
+        "require_paths" => &mut spec.require_paths,
+        "test_files" => &mut spec.test_files,
+        "extra_rdoc_files" => &mut spec.extra_rdoc_files,
+        "rdoc_options" => &mut spec.rdoc_options,
+        "cert_chain" => &mut spec.cert_chain,
+        "requirements" => &mut spec.requirements,
+        _ => return None,
+    })
+}
+
+/// An attribute the gemspec has already set, read back, like `spec.files`.
+fn read(spec: &Specification, attribute: &str) -> Option<Value> {
+    Some(match attribute {
+        "name" => Value::Str(spec.name.clone()),
+        "version" => Value::Str(spec.version.to_string()),
+        "bindir" => Value::Str(spec.bindir.clone()),
+        "files" => Value::List(spec.files.clone()),
+        "executables" => Value::List(spec.executables.clone()),
+        "require_paths" => Value::List(spec.require_paths.clone()),
+        "test_files" => Value::List(spec.test_files.clone()),
+        _ => return None,
+    })
+}
+
+/// Evaluates one expression, like `Dir["lib/**/*.rb"] + %w[README.md]`.
+struct Expr<'a> {
+    rest: &'a str,
+    context: &'a Context<'a>,
+    spec: &'a Specification,
+}
+
+impl<'a> Expr<'a> {
+    fn new(input: &'a str, context: &'a Context<'a>, spec: &'a Specification) -> Self {
+        Self {
+            rest: input,
+            context,
+            spec,
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        self.rest = self.rest.trim_start();
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_whitespace();
+        match self.rest.strip_prefix(token) {
+            Some(rest) => {
+                self.rest = rest;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn expect(&mut self, token: &str) -> Option<()> {
+        self.eat(token).then_some(())
+    }
+
+    /// Only whitespace may be left.
+    fn finish(&mut self) -> Option<()> {
+        self.skip_whitespace();
+        self.rest.is_empty().then_some(())
+    }
+
+    fn expr(&mut self) -> Option<Value> {
+        let mut value = self.postfix()?;
+        while self.eat("+") {
+            value = value.plus(self.postfix()?)?;
+        }
+        Some(value)
+    }
+
+    /// The arguments of a method call, with or without parentheses.
+    fn call_args(&mut self) -> Option<Vec<Value>> {
+        if self.eat("(") {
+            let args = self.list(")")?;
+            self.finish()?;
+            return Some(args);
+        }
+        let mut args = vec![self.expr()?];
+        while self.eat(",") {
+            args.push(self.expr()?);
+        }
+        self.finish()?;
+        Some(args)
+    }
+
+    /// Comma-separated values up to `close`, which may follow a trailing comma.
+    fn list(&mut self, close: &str) -> Option<Vec<Value>> {
+        let mut values = Vec::new();
+        loop {
+            if self.eat(close) {
+                return Some(values);
+            }
+            values.push(self.expr()?);
+            if !self.eat(",") {
+                self.expect(close)?;
+                return Some(values);
+            }
+        }
+    }
+
+    fn postfix(&mut self) -> Option<Value> {
+        let mut value = self.primary()?;
+        while self.eat(".") {
+            let method = self.ident()?;
+            value = self.method(value, method)?;
+        }
+        Some(value)
+    }
+
+    fn method(&mut self, value: Value, method: &str) -> Option<Value> {
+        Some(match (method, value) {
+            ("freeze" | "dup" | "to_s" | "to_a", value) => value,
+            ("strip", Value::Str(string)) => Value::Str(string.trim().to_string()),
+            ("sort", Value::List(mut list)) => {
+                list.sort();
+                Value::List(list)
+            }
+            ("uniq", Value::List(list)) => {
+                let mut unique = Vec::new();
+                for item in list {
+                    if !unique.contains(&item) {
+                        unique.push(item);
+                    }
+                }
+                Value::List(unique)
+            }
+            ("grep", Value::List(list)) => {
+                self.expect("(")?;
+                let prefix = self.regex_prefix()?;
+                self.expect(")")?;
+                let matching = list
+                    .into_iter()
+                    .filter(|item| item.starts_with(&prefix))
+                    .collect();
+                self.skip_whitespace();
+                if self.rest.starts_with('{') {
+                    self.basenames(matching)?
+                } else {
+                    Value::List(matching)
+                }
+            }
+            ("map", Value::List(list)) => {
+                self.skip_whitespace();
+                self.basenames(list)?
+            }
+            _ => return None,
+        })
+    }
+
+    /// Apply a block like `{ |f| File.basename(f) }` to every item of `list`.
+    fn basenames(&mut self, list: Vec<String>) -> Option<Value> {
+        let captures = BASENAME_BLOCK_REGEX.captures(self.rest)?;
+        if captures[1] != captures[2] {
+            return None;
+        }
+        self.rest = &self.rest[captures.get(0)?.end()..];
+        Some(Value::List(
+            list.iter()
+                .map(|item| item.rsplit('/').next().unwrap_or(item).to_string())
+                .collect(),
+        ))
+    }
+
+    fn ident(&mut self) -> Option<&'a str> {
+        self.skip_whitespace();
+        let end = self
+            .rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(self.rest.len());
+        if end == 0 {
+            return None;
+        }
+        let (ident, rest) = self.rest.split_at(end);
+        self.rest = rest;
+        Some(ident)
+    }
+
+    /// A constant path like `Gem::Platform::RUBY`, or an identifier.
+    fn path(&mut self) -> Option<&'a str> {
+        let start = self.rest;
+        self.ident()?;
+        while let Some(rest) = self.rest.strip_prefix("::") {
+            self.rest = rest;
+            self.ident()?;
+        }
+        Some(&start[..start.len() - self.rest.len()])
+    }
+
+    fn primary(&mut self) -> Option<Value> {
+        self.skip_whitespace();
+        let first = self.rest.chars().next()?;
+        match first {
+            '"' | '\'' => {
+                self.rest = &self.rest[1..];
+                self.string(first, first == '"').map(Value::Str)
+            }
+            '[' => {
+                self.rest = &self.rest[1..];
+                let mut list = Vec::new();
+                for value in self.list("]")? {
+                    list.extend(value.strings()?);
+                }
+                Some(Value::List(list))
+            }
+            '{' => {
+                self.rest = &self.rest[1..];
+                self.hash().map(Value::Hash)
+            }
+            '%' => self.percent_literal(),
+            c if c.is_ascii_digit() => self.ident()?.parse().ok().map(Value::Int),
+            _ => {
+                let path = self.path()?;
+                match path {
+                    "nil" => Some(Value::Nil),
+                    "true" | "false" => Some(Value::Bool),
+                    "Dir" => self.glob(),
+                    "Gem::Requirement" | "Gem::Version" => {
+                        self.expect(".")?;
+                        (self.ident()? == "new").then_some(())?;
+                        self.expect("(")?;
+                        let mut strings = Vec::new();
+                        for value in self.list(")")? {
+                            strings.extend(value.strings()?);
+                        }
+                        if path == "Gem::Version" {
+                            let [version] = <[String; 1]>::try_from(strings).ok()?;
+                            Some(Value::Str(version))
+                        } else {
+                            Some(Value::List(strings))
+                        }
+                    }
+                    "Gem::Platform::RUBY" => Some(Value::Str("ruby".to_string())),
+                    var if var == self.context.var => {
+                        self.expect(".")?;
+                        read(self.spec, self.ident()?)
+                    }
+                    constant if constant.starts_with(|c: char| c.is_ascii_uppercase()) => self
+                        .context
+                        .constants
+                        .get(constant)
+                        .map(|value| Value::Str(value.to_string())),
+                    _ => None,
+                }
+            }
+        }
+    }
+
+    /// The rest of a string literal opened by `quote`. Interpolation needs Ruby.
+    fn string(&mut self, quote: char, double_quoted: bool) -> Option<String> {
+        let mut string = String::new();
+        let mut chars = self.rest.char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                c if c == quote => {
+                    self.rest = &self.rest[i + c.len_utf8()..];
+                    return Some(string);
+                }
+                '#' if double_quoted && self.rest[i..].starts_with("#{") => return None,
+                '\\' => {
+                    let (_, escaped) = chars.next()?;
+                    match escaped {
+                        '\\' | '\'' | '"' | '#' => string.push(escaped),
+                        'n' if double_quoted => string.push('\n'),
+                        't' if double_quoted => string.push('\t'),
+                        _ if double_quoted => return None,
+                        _ => {
+                            string.push('\\');
+                            string.push(escaped);
+                        }
+                    }
+                }
+                _ => string.push(c),
+            }
+        }
+        None
+    }
+
+    /// The pairs of a hash literal, with string or symbol keys and string values.
+    fn hash(&mut self) -> Option<Vec<(String, String)>> {
+        let mut pairs = Vec::new();
+        loop {
+            if self.eat("}") {
+                return Some(pairs);
+            }
+            self.skip_whitespace();
+            let key = if self.rest.starts_with(['"', '\'']) {
+                let key = self.primary()?.string()?;
+                self.expect("=>")?;
+                key
+            } else {
+                let key = self.ident()?.to_string();
+                self.rest = self.rest.strip_prefix(':')?;
+                key
+            };
+            pairs.push((key, self.expr()?.string()?));
+            if !self.eat(",") {
+                self.expect("}")?;
+                return Some(pairs);
+            }
+        }
+    }
+
+    /// `%w[...]` and `%i[...]` word lists, and `%q(...)` strings.
+    fn percent_literal(&mut self) -> Option<Value> {
+        let mut chars = self.rest.chars();
+        chars.next();
+        let kind = chars.next()?;
+        let open = chars.next()?;
+        let close = match open {
+            '(' => ')',
+            '[' => ']',
+            '{' => '}',
+            '<' => '>',
+            c if c.is_ascii_punctuation() => c,
+            _ => return None,
+        };
+        let body_start = 3;
+        let body_len = self.rest[body_start..].find(close)?;
+        let body = &self.rest[body_start..body_start + body_len];
+        if body.contains(open) && open != close {
+            return None;
+        }
+        let value = match kind {
+            'w' | 'i' => Value::List(body.split_whitespace().map(str::to_string).collect()),
+            'q' => Value::Str(body.to_string()),
+            _ => return None,
+        };
+        self.rest = &self.rest[body_start + body_len + close.len_utf8()..];
+        Some(value)
+    }
+
+    /// The literal prefix a regex like `%r{\Aexe/}` or `/^bin\//` anchors to.
+    fn regex_prefix(&mut self) -> Option<String> {
+        self.skip_whitespace();
+        let (body, rest) = if let Some(rest) = self.rest.strip_prefix("%r{") {
+            rest.split_once('}')?
+        } else {
+            let rest = self.rest.strip_prefix('/')?;
+            let end = rest
+                .char_indices()
+                .find(|&(i, c)| c == '/' && !rest[..i].ends_with('\\'))?
+                .0;
+            (&rest[..end], &rest[end + 1..])
+        };
+        self.rest = rest;
+
+        let literal = body
+            .strip_prefix('^')
+            .or_else(|| body.strip_prefix("\\A"))?;
+        let mut prefix = String::new();
+        let mut chars = literal.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => match chars.next()? {
+                    escaped @ ('/' | '.' | '-') => prefix.push(escaped),
+                    _ => return None,
+                },
+                '.' | '*' | '+' | '?' | '[' | ']' | '(' | ')' | '{' | '}' | '|' | '$' | '^' => {
+                    return None;
+                }
+                _ => prefix.push(c),
+            }
+        }
+        Some(prefix)
+    }
+
+    /// `Dir[...]` or `Dir.glob(...)`: the gem's files matching the patterns, like Ruby lists them.
+    fn glob(&mut self) -> Option<Value> {
+        let args = if self.eat("[") {
+            self.list("]")?
+        } else {
+            self.expect(".")?;
+            (self.ident()? == "glob").then_some(())?;
+            self.expect("(")?;
+            self.list(")")?
+        };
+        let mut patterns = Vec::new();
+        for arg in args {
+            patterns.extend(arg.strings()?);
+        }
+
+        // Like Ruby, `*` doesn't match dotfiles.
+        let options = MatchOptions {
+            require_literal_leading_dot: true,
+            ..MatchOptions::new()
+        };
+        let root = Pattern::escape(self.context.gem_dir.as_str());
+        let mut files = Vec::new();
+        for pattern in patterns.iter().flat_map(|pattern| expand_braces(pattern)) {
+            let mut matches: Vec<String> = glob::glob_with(&format!("{root}/{pattern}"), options)
+                .ok()?
+                .flatten()
+                .filter_map(|path| {
+                    let path = camino::Utf8PathBuf::try_from(path).ok()?;
+                    let relative = path.strip_prefix(self.context.gem_dir).ok()?;
+                    Some(relative.as_str().replace('\\', "/"))
+                })
+                .collect();
+            matches.sort();
+            files.extend(matches);
+        }
+        Some(Value::List(files))
+    }
+}
+
+/// The patterns a glob with braces stands for, like `{lib,exe}/*` for `lib/*` and `exe/*`.
+fn expand_braces(pattern: &str) -> Vec<String> {
+    let Some(open) = pattern.find('{') else {
+        return vec![pattern.to_string()];
+    };
+    let Some(close) = pattern[open..].find('}').map(|close| open + close) else {
+        return vec![pattern.to_string()];
+    };
+    let (before, after) = (&pattern[..open], &pattern[close + 1..]);
+    pattern[open + 1..close]
+        .split(',')
+        .flat_map(|choice| expand_braces(&format!("{before}{choice}{after}")))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use camino_tempfile::Utf8TempDir;
+
+    use super::*;
+
+    fn write(dir: &Utf8Path, path: &str, contents: &str) {
+        let path = dir.join(path);
+        fs_err::create_dir_all(path.parent().unwrap()).unwrap();
+        fs_err::write(path, contents).unwrap();
+    }
+
+    #[test]
+    fn test_evaluate_rubygems_stub() {
+        // The format RubyGems writes installed gemspecs in.
+        let gemspec = r#"# -*- encoding: utf-8 -*-
+# stub: bootsnap 1.18.6 ruby lib
+# stub: ext/bootsnap/extconf.rb
+
+Gem::Specification.new do |s|
+  s.name = "bootsnap".freeze
+  s.version = "1.18.6".freeze
+
+  s.required_rubygems_version = Gem::Requirement.new(">= 0".freeze) if s.respond_to? :required_rubygems_version=
+  s.metadata = { "bug_tracker_uri" => "https://github.com/Shopify/bootsnap/issues", "source_code_uri" => "https://github.com/Shopify/bootsnap" } if s.respond_to? :metadata=
+  s.require_paths = ["lib".freeze]
+  s.authors = ["Burke Libbey".freeze]
+  s.bindir = "exe".freeze
+  s.executables = ["bootsnap".freeze]
+  s.extensions = ["ext/bootsnap/extconf.rb".freeze]
+  s.licenses = ["MIT".freeze]
+  s.required_ruby_version = Gem::Requirement.new(">= 2.6.0".freeze)
+  s.summary = "Boot large ruby/rails apps faster".freeze
+
+  s.installed_by_version = "4.0.3".freeze
+
+  s.specification_version = 4
+
+  s.add_runtime_dependency(%q<msgpack>.freeze, ["~> 1.2".freeze])
+end
+"#;
+        let dir = Utf8TempDir::new().unwrap();
+        let spec = evaluate_str(gemspec, dir.path()).unwrap();
+        assert_eq!(spec.full_name(), "bootsnap-1.18.6");
+        assert_eq!(spec.bindir, "exe");
+        assert_eq!(spec.executables, vec!["bootsnap"]);
+        assert_eq!(spec.extensions, vec!["ext/bootsnap/extconf.rb"]);
+        assert_eq!(spec.required_ruby_version.to_string(), ">= 2.6.0");
+        assert_eq!(spec.metadata.len(), 2);
+        assert_eq!(spec.dependencies.len(), 1);
+        assert_eq!(spec.dependencies[0].name, "msgpack");
+        assert_eq!(spec.dependencies[0].requirement.to_string(), "~> 1.2");
+    }
+
+    #[test]
+    fn test_evaluate_bundler_template() {
+        let dir = Utf8TempDir::new().unwrap();
+        write(
+            dir.path(),
+            "lib/widget/version.rb",
+            "module Widget\n  VERSION = \"0.3.1\"\nend\n",
+        );
+        write(dir.path(), "lib/widget.rb", "");
+        write(dir.path(), "exe/widget", "");
+        write(dir.path(), "README.md", "");
+        // Like in Ruby, `*` doesn't match dotfiles.
+        write(dir.path(), ".rubocop.yml", "");
+
+        let gemspec = r#"# frozen_string_literal: true
+
+require_relative "lib/widget/version"
+
+Gem::Specification.new do |spec|
+  spec.name = "widget"
+  spec.version = Widget::VERSION
+  spec.authors = ["Jane Doe"]
+  spec.summary = "Makes widgets" # and nothing else
+  spec.required_ruby_version = ">= 3.1"
+  spec.metadata["rubygems_mfa_required"] = "true"
+
+  spec.files = Dir["{exe,lib}/**/*", "*.{md,yml}"]
+  spec.bindir = "exe"
+  spec.executables = spec.files.grep(%r{\Aexe/}) { |f| File.basename(f) }
+  spec.require_paths = ["lib"]
+
+  spec.add_dependency "rack", ">= 2.2", "< 4"
+  spec.add_development_dependency "rake", "~> 13.0"
+end
+"#;
+        let spec = evaluate_str(gemspec, dir.path()).unwrap();
+        assert_eq!(spec.full_name(), "widget-0.3.1");
+        assert_eq!(
+            spec.files,
+            vec![
+                "exe/widget",
+                "lib/widget",
+                "lib/widget.rb",
+                "lib/widget/version.rb",
+                "README.md"
+            ]
+        );
+        assert_eq!(spec.executables, vec!["widget"]);
+        assert_eq!(spec.summary, "Makes widgets");
+        assert_eq!(spec.metadata["rubygems_mfa_required"], "true");
+        let deps: Vec<String> = spec
+            .dependencies
+            .iter()
+            .map(|dep| format!("{} ({}) {:?}", dep.name, dep.requirement, dep.dep_type))
+            .collect();
+        assert_eq!(
+            deps,
+            vec!["rack (>= 2.2, < 4) Runtime", "rake (~> 13.0) Development"]
+        );
+    }
+
+    #[test]
+    fn test_needs_ruby() {
+        let dir = Utf8TempDir::new().unwrap();
+        let needs_ruby = [
+            // Shelling out
+            "spec.files = `git ls-files -z`.split(\"\\x0\")",
+            // Interpolation
+            "spec.summary = \"Version #{spec.version}\"",
+            // Conditionals
+            "spec.add_dependency \"ffi\" if RUBY_PLATFORM =~ /mingw/",
+            // Attributes rv doesn't know
+            "spec.unknown_attribute = \"x\"",
+            // Constants from files it didn't read
+            "spec.summary = Widget::SUMMARY",
+        ];
+        for statement in needs_ruby {
+            let gemspec = format!(
+                "Gem::Specification.new do |spec|\n  spec.name = \"widget\"\n  spec.version = \"1.0\"\n  {statement}\nend\n"
+            );
+            assert_eq!(evaluate_str(&gemspec, dir.path()), None, "{statement}");
+        }
+
+        // Without a name, it isn't a valid specification.
+        let gemspec = "Gem::Specification.new do |spec|\n  spec.version = \"1.0\"\nend\n";
+        assert_eq!(evaluate_str(gemspec, dir.path()), None);
+    }
+
+    #[test]
+    fn test_statements() {
+        let gemspec = "spec.files = [\n  \"a\", # first\n  \"b\",\n]\nspec.executables = spec.files\n  .grep(/^b/)\n";
+        assert_eq!(
+            statements(gemspec),
+            vec![
+                "spec.files = [\n\"a\",\n\"b\",\n]",
+                "spec.executables = spec.files\n.grep(/^b/)"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_expand_braces() {
+        assert_eq!(
+            expand_braces("{lib,exe}/**/*.{rb,so}"),
+            vec!["lib/**/*.rb", "lib/**/*.so", "exe/**/*.rb", "exe/**/*.so"]
+        );
+    }
+}
@@ -0,0 +1,155 @@
+//! Bundler's app cache, `vendor/cache` by default, which holds a copy of every gem a project
+//! needs so that it can be installed without network access.
+//!
+//! Gems are stored as `<full name>.gem`, and git sources as a bare repository in
+//! `<repo name>-<revision>`, marked with an empty `.bundlecache` file, just like `bundle cache`
+//! lays them out.
+
+use std::io;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use dircpy::copy_dir;
+use rv_lockfile::datatypes::GitSection;
+use tracing::debug;
+
+/// The directory Bundler vendors gems into, unless `BUNDLE_CACHE_PATH` says otherwise.
+const DEFAULT_CACHE_PATH: &str = "vendor/cache";
+
+/// Name of the marker file Bundler leaves in vendored git repositories.
+const GIT_MARKER: &str = ".bundlecache";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VendorMode {
+    /// Don't look for vendored gems, like when installing a tool outside of any project.
+    Ignore,
+    /// Use vendored gems when they're there, and download the rest.
+    Prefer,
+    /// Copy every gem into the app cache, as well as installing it.
+    Vendor,
+    /// Only install vendored gems, never downloading anything.
+    Local,
+}
+
+#[derive(Debug)]
+pub struct AppCache {
+    dir: Utf8PathBuf,
+    pub mode: VendorMode,
+}
+
+impl AppCache {
+    /// The app cache of the project whose Gemfile is in `project_dir`.
+    pub fn new(project_dir: &Utf8Path, cache_path: Option<&str>, mode: VendorMode) -> Self {
+        let dir = project_dir.join(cache_path.unwrap_or(DEFAULT_CACHE_PATH));
+        Self { dir, mode }
+    }
+
+    pub fn dir(&self) -> &Utf8Path {
+        &self.dir
+    }
+
+    /// Where the package of the gem with this full name, like `rack-3.1.7`, is vendored.
+    pub fn gem_path(&self, full_name: &str) -> Utf8PathBuf {
+        self.dir.join(format!("{full_name}.gem"))
+    }
+
+    /// Where a git source is vendored, if it is.
+    pub fn git_path(&self, git_section: &GitSection) -> Utf8PathBuf {
+        self.dir.join(super::git_dir_name(git_section))
+    }
+
+    /// The vendored package of the gem with this full name, if there is one.
+    pub fn vendored_gem(&self, full_name: &str) -> Option<Utf8PathBuf> {
+        let path = self.gem_path(full_name);
+        (self.mode != VendorMode::Ignore && path.exists()).then_some(path)
+    }
+
+    pub fn has_git_repo(&self, git_section: &GitSection) -> bool {
+        self.mode != VendorMode::Ignore && self.git_path(git_section).join(GIT_MARKER).exists()
+    }
+
+    /// Whether gems that are already installed still need to be fetched, to be vendored.
+    pub fn needs_gem(&self, full_name: &str) -> bool {
+        self.mode == VendorMode::Vendor && !self.gem_path(full_name).exists()
+    }
+
+    pub fn needs_git_repo(&self, git_section: &GitSection) -> bool {
+        self.mode == VendorMode::Vendor && !self.has_git_repo(git_section)
+    }
+
+    /// Copy a downloaded gem package into the app cache, if vendoring.
+    pub fn vendor_gem(&self, full_name: &str, contents: &[u8]) -> io::Result<()> {
+        if self.mode != VendorMode::Vendor {
+            return Ok(());
+        }
+        let path = self.gem_path(full_name);
+        if !path.exists() {
+            rv_cache::write_atomic(&path, contents)?;
+            debug!("Vendored {full_name} into {}", self.dir);
+        }
+        Ok(())
+    }
+
+    /// Copy a bare clone of a git source into the app cache, if vendoring.
+    pub fn vendor_git_repo(
+        &self,
+        bare_repo: &Utf8Path,
+        git_section: &GitSection,
+    ) -> io::Result<()> {
+        if self.mode != VendorMode::Vendor || self.has_git_repo(git_section) {
+            return Ok(());
+        }
+        let path = self.git_path(git_section);
+        if path.exists() {
+            fs_err::remove_dir_all(&path)?;
+        }
+        copy_dir(bare_repo, &path)?;
+        fs_err::write(path.join(GIT_MARKER), "")?;
+        debug!("Vendored {} into {}", git_section.remote, self.dir);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_app_cache_layout() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let project_dir = Utf8Path::from_path(temp_dir.path()).unwrap();
+        let lockfile = include_str!("../../../../rv-lockfile/tests/inputs/Gemfile.git.lock");
+        let lockfile = rv_lockfile::parse(lockfile).unwrap();
+        let git_section = &lockfile.git[0];
+
+        let app_cache = AppCache::new(project_dir, None, VendorMode::Vendor);
+        assert_eq!(
+            app_cache.gem_path("rack-3.1.7"),
+            project_dir.join("vendor/cache/rack-3.1.7.gem")
+        );
+        assert!(app_cache.needs_gem("rack-3.1.7"));
+        app_cache.vendor_gem("rack-3.1.7", b"package").unwrap();
+        assert!(!app_cache.needs_gem("rack-3.1.7"));
+
+        let bare_repo = project_dir.join("bare.git");
+        fs_err::create_dir_all(bare_repo.join("refs")).unwrap();
+        fs_err::write(bare_repo.join("HEAD"), "ref: refs/heads/main\n").unwrap();
+        assert!(app_cache.needs_git_repo(git_section));
+        app_cache.vendor_git_repo(&bare_repo, git_section).unwrap();
+        assert!(!app_cache.needs_git_repo(git_section));
+        assert!(app_cache.git_path(git_section).join("HEAD").exists());
+
+        let custom = AppCache::new(project_dir, Some("gems"), VendorMode::Prefer);
+        assert_eq!(custom.dir(), project_dir.join("gems"));
+        assert!(!custom.needs_gem("rack-3.1.7"));
+        custom.vendor_gem("rack-3.1.7", b"package").unwrap();
+        assert!(!custom.gem_path("rack-3.1.7").exists());
+        assert_eq!(
+            app_cache.vendored_gem("rack-3.1.7"),
+            Some(app_cache.gem_path("rack-3.1.7"))
+        );
+
+        let ignored = AppCache::new(project_dir, None, VendorMode::Ignore);
+        assert_eq!(ignored.vendored_gem("rack-3.1.7"), None);
+        assert!(!ignored.has_git_repo(git_section));
+    }
+}
@@ -0,0 +1,175 @@
+//! `rv ci --watch`, which installs the project's gems again whenever its Gemfile, lockfile or
+//! gemspecs change, e.g. after pulling or switching branches.
+//!
+//! Files are polled rather than watched with OS notifications, so that it works the same on
+//! every platform and on network filesystems.
+
+use std::collections::BTreeMap;
+use std::process::Command;
+use std::time::{Duration, SystemTime};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use glob::glob;
+use tracing::debug;
+
+use super::gemfile_for_lockfile;
+
+/// How often to check the watched files.
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// When each watched file was last modified and how big it is, or `None` if it's gone.
+type Fingerprint = BTreeMap<Utf8PathBuf, Option<(SystemTime, u64)>>;
+
+/// The files of a project that decide which gems `rv ci` installs.
+pub struct Watcher {
+    project_dir: Utf8PathBuf,
+    gemfile: Utf8PathBuf,
+    lockfile: Utf8PathBuf,
+    fingerprint: Fingerprint,
+}
+
+impl Watcher {
+    pub fn new(lockfile: &Utf8Path) -> Self {
+        let mut watcher = Self {
+            project_dir: lockfile
+                .parent()
+                .unwrap_or(Utf8Path::new("."))
+                .to_path_buf(),
+            gemfile: gemfile_for_lockfile(lockfile),
+            lockfile: lockfile.to_path_buf(),
+            fingerprint: Fingerprint::new(),
+        };
+        watcher.fingerprint = watcher.fingerprint();
+        watcher
+    }
+
+    /// The Gemfile, the lockfile, and the gemspecs next to them, which can appear or disappear.
+    fn files(&self) -> Vec<Utf8PathBuf> {
+        let mut files = vec![self.gemfile.clone(), self.lockfile.clone()];
+        let pattern = self.project_dir.join("*.gemspec");
+        if let Ok(gemspecs) = glob(pattern.as_str()) {
+            files.extend(
+                gemspecs
+                    .flatten()
+                    .filter_map(|path| Utf8PathBuf::from_path_buf(path).ok()),
+            );
+        }
+        files
+    }
+
+    fn fingerprint(&self) -> Fingerprint {
+        self.files()
+            .into_iter()
+            .map(|file| {
+                let stamp = fs_err::metadata(&file)
+                    .and_then(|metadata| Ok((metadata.modified()?, metadata.len())))
+                    .ok();
+                (file, stamp)
+            })
+            .collect()
+    }
+
+    /// Whether any of the files changed since the last time they were looked at.
+    fn changed(&mut self) -> bool {
+        let fingerprint = self.fingerprint();
+        if fingerprint == self.fingerprint {
+            return false;
+        }
+        self.fingerprint = fingerprint;
+        true
+    }
+
+    /// Wait until the files change, and then until they've stopped changing for `debounce`, so
+    /// that a `git pull` or an editor saving several files triggers a single install.
+    pub async fn wait_for_change(&mut self, debounce: Duration) {
+        while !self.changed() {
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+        debug!("Noticed a change in {}", self.project_dir);
+
+        loop {
+            tokio::time::sleep(debounce).await;
+            if !self.changed() {
+                return;
+            }
+        }
+    }
+}
+
+/// Tell the user how an install triggered by a change went: with a desktop notification if they
+/// asked for one, and by running their hook with the install's exit status in
+/// `RV_CI_EXIT_STATUS`.
+pub fn after_install(hook: Option<&str>, notify: bool, succeeded: bool) {
+    if notify {
+        let message = if succeeded {
+            "Installed the project's gems"
+        } else {
+            "Could not install the project's gems"
+        };
+        desktop_notification("rv ci", message);
+    }
+
+    let Some(hook) = hook else {
+        return;
+    };
+    let status = super::hooks::shell_command(hook)
+        .env("RV_CI_EXIT_STATUS", if succeeded { "0" } else { "1" })
+        .status();
+    match status {
+        Ok(status) if !status.success() => debug!("The install hook exited with {status}"),
+        Ok(_) => {}
+        Err(err) => debug!("Could not run the install hook: {err}"),
+    }
+}
+
+/// Show a notification with the desktop's own tools, if there are any. Failing to is not worth
+/// interrupting the user's work for.
+fn desktop_notification(title: &str, message: &str) {
+    let mut command = if cfg!(target_os = "macos") {
+        let mut command = Command::new("osascript");
+        command.arg("-e").arg(format!(
+            "display notification {message:?} with title {title:?}"
+        ));
+        command
+    } else if cfg!(windows) {
+        debug!("Desktop notifications are not supported on Windows");
+        return;
+    } else {
+        let mut command = Command::new("notify-send");
+        command.arg(title).arg(message);
+        command
+    };
+
+    if let Err(err) = command.status() {
+        debug!("Could not show a desktop notification: {err}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_watcher_notices_changes() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let project_dir = Utf8Path::from_path(temp_dir.path()).unwrap();
+        let lockfile = project_dir.join("Gemfile.lock");
+        fs_err::write(project_dir.join("Gemfile"), "gem \"rack\"\n").unwrap();
+        fs_err::write(&lockfile, "").unwrap();
+
+        let mut watcher = Watcher::new(&lockfile);
+        assert!(!watcher.changed());
+        assert_eq!(watcher.files().len(), 2);
+
+        fs_err::write(project_dir.join("app.gemspec"), "").unwrap();
+        assert!(watcher.changed());
+        assert!(!watcher.changed());
+
+        fs_err::write(project_dir.join("Gemfile"), "gem \"rack\"\ngem \"rake\"\n").unwrap();
+        assert!(watcher.changed());
+
+        fs_err::remove_file(&lockfile).unwrap();
+        assert!(watcher.changed());
+        assert_eq!(watcher.fingerprint[&lockfile], None);
+    }
+}
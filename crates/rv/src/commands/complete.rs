@@ -0,0 +1,127 @@
+use std::collections::BTreeSet;
+
+use anstream::println;
+use clap::{Args, ValueEnum};
+use rv_ruby::engine::RubyEngine;
+use rv_ruby::version::RubyVersion;
+
+use crate::GlobalArgs;
+use crate::config::Config;
+
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+pub enum Error {
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    ConfigError(#[from] crate::config::Error),
+}
+
+type Result<T> = miette::Result<T, Error>;
+
+/// Print completion candidates, one per line. The shell completion scripts from
+/// `rv shell completions` call this for arguments that clap can't complete statically.
+#[derive(Args)]
+pub struct CompleteArgs {
+    /// What kind of value is being completed
+    #[arg(value_enum)]
+    pub kind: CompletionKind,
+
+    /// Only print candidates that start with this
+    #[arg(default_value = "")]
+    pub prefix: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum CompletionKind {
+    /// Ruby versions that can be installed
+    InstallableRubies,
+    /// Ruby versions that are already installed
+    InstalledRubies,
+    /// Gems in rv's compact index cache
+    Gems,
+}
+
+pub(crate) async fn complete(global_args: &GlobalArgs, args: CompleteArgs) -> Result<()> {
+    let config = Config::new(global_args, None)?;
+
+    let candidates = match args.kind {
+        CompletionKind::InstallableRubies => config
+            .discover_remote_rubies(false)
+            .await
+            .iter()
+            .map(|ruby| completion_for(&ruby.version))
+            .collect(),
+        CompletionKind::InstalledRubies => config
+            .rubies()
+            .iter()
+            .map(|ruby| completion_for(&ruby.version))
+            .collect(),
+        CompletionKind::Gems => cached_gem_names(&config),
+    };
+
+    for candidate in filter_candidates(candidates, &args.prefix) {
+        println!("{candidate}");
+    }
+
+    Ok(())
+}
+
+/// How a Ruby version is typed on the command line: just the number for CRuby,
+/// and the full name for other engines.
+fn completion_for(version: &RubyVersion) -> String {
+    if version.engine == RubyEngine::Ruby {
+        version.number()
+    } else {
+        version.to_string()
+    }
+}
+
+/// Names of the gems whose compact index info rv has cached while resolving dependencies.
+fn cached_gem_names(config: &Config) -> Vec<String> {
+    let info_dir = config
+        .cache
+        .shard(rv_cache::CacheBucket::GemDeps, "compact_index")
+        .into_path_buf()
+        .join("info");
+
+    let Ok(entries) = fs_err::read_dir(info_dir) else {
+        return vec![];
+    };
+
+    entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_ok_and(|file_type| file_type.is_file()))
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .collect()
+}
+
+/// Drop candidates that don't match the prefix, and duplicates, keeping the original order.
+fn filter_candidates(candidates: Vec<String>, prefix: &str) -> Vec<String> {
+    let mut seen = BTreeSet::new();
+    candidates
+        .into_iter()
+        .filter(|candidate| candidate.starts_with(prefix))
+        .filter(|candidate| seen.insert(candidate.clone()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_completion_for() {
+        let cruby: RubyVersion = "ruby-3.4.1".parse().unwrap();
+        assert_eq!(completion_for(&cruby), "3.4.1");
+
+        let jruby: RubyVersion = "jruby-9.4.12.0".parse().unwrap();
+        assert_eq!(completion_for(&jruby), "jruby-9.4.12.0");
+    }
+
+    #[test]
+    fn test_filter_candidates() {
+        let candidates = ["3.4.1", "3.3.5", "3.4.1", "jruby-9.4.12.0"]
+            .map(String::from)
+            .to_vec();
+        assert_eq!(filter_candidates(candidates, "3.4"), vec!["3.4.1"]);
+    }
+}
@@ -0,0 +1,358 @@
+//! `rv doctor`: looks for things that stop rv's rubies from being the ones that run, like the
+//! system's Ruby or Homebrew's coming first on `PATH`, and for lines in the project's lockfile
+//! that rv can't parse.
+
+use std::env;
+use std::ffi::OsStr;
+
+use anstream::println;
+use camino::{Utf8Path, Utf8PathBuf};
+use owo_colors::OwoColorize;
+use rv_core::external::ExternalKind;
+use rv_ruby::Ruby;
+use rv_ruby::gem_home::{GemHomeLayout, gem_homes_in};
+
+use crate::GlobalArgs;
+use crate::config::Config;
+use crate::config::inferred_ruby::LOCKFILE_NAMES;
+
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+pub enum Error {
+    #[error(transparent)]
+    #[diagnostic(code(RV8001))]
+    IoError(#[from] std::io::Error),
+    #[error("rv doctor found {0} problem(s)")]
+    #[diagnostic(code(RV8002))]
+    ProblemsFound(usize),
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    ConfigError(#[from] crate::config::Error),
+}
+
+type Result<T> = miette::Result<T, Error>;
+
+#[cfg(windows)]
+const RUBY_EXECUTABLES: [&str; 2] = ["ruby.exe", "ruby.cmd"];
+#[cfg(not(windows))]
+const RUBY_EXECUTABLES: [&str; 1] = ["ruby"];
+
+/// The `ruby` a shell would run.
+#[derive(Debug)]
+struct PathRuby {
+    executable: Utf8PathBuf,
+    /// Who installed it, if it's the system's or Homebrew's.
+    external: Option<ExternalKind>,
+    /// The installation it's in, if it's a Ruby rv can make sense of.
+    ruby: Option<Ruby>,
+    /// Whether it's one of the rubies rv finds.
+    from_rv: bool,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Finding {
+    Ok(String),
+    Warning { message: String, hint: String },
+}
+
+pub(crate) fn doctor(global_args: &GlobalArgs) -> Result<()> {
+    let config = Config::new(global_args, None)?;
+    let root = rv_dirs::root_dir();
+    let rubies = config.rubies();
+
+    let path_ruby = env::var_os("PATH")
+        .and_then(|path| first_on_path(&path))
+        .map(|executable| {
+            let canonical = rv_dirs::canonicalize_utf8(&executable).ok();
+            let external = ExternalKind::of(&executable, &root)
+                .or_else(|| ExternalKind::of(canonical.as_deref()?, &root));
+            let from_rv = canonical.as_ref().is_some_and(|canonical| {
+                rubies.iter().any(|ruby| {
+                    rv_dirs::canonicalize_utf8(ruby.executable_path())
+                        .ok()
+                        .as_ref()
+                        == Some(canonical)
+                })
+            });
+            let ruby = executable
+                .parent()
+                .and_then(Utf8Path::parent)
+                .and_then(|dir| config.installed_ruby_dirs().ruby_in(dir, false));
+            PathRuby {
+                executable,
+                external,
+                ruby,
+                from_rv,
+            }
+        });
+
+    let mut findings = path_findings(path_ruby.as_ref(), &rubies);
+    findings.extend(external_findings(&config.external_rubies(), &root));
+    findings.extend(gem_home_findings(
+        GemHomeLayout::chosen(),
+        &gem_homes_in(&GemHomeLayout::Legacy.gems_dir()),
+    ));
+    if let Some(lockfile) = LOCKFILE_NAMES
+        .iter()
+        .map(|name| config.project_root.join(name))
+        .find(|path| path.is_file())
+    {
+        let contents = fs_err::read_to_string(&lockfile)?;
+        let contents = rv_lockfile::normalize_line_endings(&contents);
+        findings.extend(lockfile_findings(&lockfile, &contents));
+    }
+
+    let mut problems = 0;
+    for finding in &findings {
+        match finding {
+            Finding::Ok(message) => println!("{} {message}", "✓".green()),
+            Finding::Warning { message, hint } => {
+                problems += 1;
+                println!("{} {message}", "warning:".yellow().bold());
+                println!("  {} {hint}", "hint:".cyan());
+            }
+        }
+    }
+
+    if problems > 0 {
+        return Err(Error::ProblemsFound(problems));
+    }
+    Ok(())
+}
+
+/// The first Ruby executable in the directories of `path`.
+fn first_on_path(path: &OsStr) -> Option<Utf8PathBuf> {
+    env::split_paths(path)
+        .filter_map(|dir| Utf8PathBuf::try_from(dir).ok())
+        .flat_map(|dir| RUBY_EXECUTABLES.map(|name| dir.join(name)))
+        .find(|executable| executable.is_file())
+}
+
+/// What's worth knowing about the `ruby` on `PATH`, given the `rubies` rv finds.
+fn path_findings(path_ruby: Option<&PathRuby>, rubies: &[Ruby]) -> Vec<Finding> {
+    let Some(path_ruby) = path_ruby else {
+        return vec![Finding::Warning {
+            message: "There's no `ruby` on PATH".to_string(),
+            hint:
+                "Set up rv's shell integration with `rv shell <shell>`, or run Ruby with `rv run`"
+                    .to_string(),
+        }];
+    };
+    let executable = rv_dirs::unexpand(&path_ruby.executable);
+
+    if path_ruby.from_rv {
+        return vec![Finding::Ok(format!(
+            "The `ruby` on PATH, {executable}, is one rv found"
+        ))];
+    }
+
+    let mut findings = Vec::new();
+    match path_ruby.external {
+        Some(kind) if !rubies.is_empty() => findings.push(Finding::Warning {
+            message: format!(
+                "The `ruby` on PATH is {}, {executable}, which comes before the rubies rv installed",
+                describe(kind)
+            ),
+            hint: "Set up rv's shell integration with `rv shell <shell>` so rv's rubies come \
+                   first, or run Ruby with `rv run`"
+                .to_string(),
+        }),
+        Some(kind) => findings.push(Finding::Warning {
+            message: format!(
+                "The `ruby` on PATH is {}, {executable}, and rv hasn't installed any rubies",
+                describe(kind)
+            ),
+            hint: "Install one with `rv ruby install`".to_string(),
+        }),
+        None => findings.push(Finding::Ok(format!(
+            "The `ruby` on PATH, {executable}, wasn't installed by the system or Homebrew"
+        ))),
+    }
+
+    let is_macos_system_ruby = path_ruby.external == Some(ExternalKind::System)
+        && path_ruby
+            .ruby
+            .as_ref()
+            .is_some_and(|ruby| ruby.os == "macos");
+    if is_macos_system_ruby {
+        findings.push(Finding::Warning {
+            message: "macOS's own Ruby is protected by System Integrity Protection, so `gem \
+                      install` needs `sudo` and can't update the gems it came with, and Apple \
+                      no longer updates it"
+                .to_string(),
+            hint: "Use a Ruby from `rv ruby install` instead".to_string(),
+        });
+    }
+
+    findings
+}
+
+/// The external rubies there are, which rv lists but never uses.
+fn external_findings(external_rubies: &[Ruby], root: &Utf8Path) -> Vec<Finding> {
+    external_rubies
+        .iter()
+        .map(|ruby| {
+            let kind = ExternalKind::of(&ruby.path, root).map_or("an external Ruby", describe);
+            Finding::Ok(format!(
+                "Found {}, {} {}, which rv lists but doesn't use",
+                kind,
+                ruby.version,
+                rv_dirs::unexpand(&ruby.path)
+            ))
+        })
+        .collect()
+}
+
+/// Whether rv quietly uses the legacy gem homes in `~/.gem`, because no layout was chosen.
+fn gem_home_findings(
+    chosen: Option<GemHomeLayout>,
+    legacy_homes: &[(String, Utf8PathBuf)],
+) -> Vec<Finding> {
+    if chosen.is_some() || legacy_homes.is_empty() {
+        return Vec::new();
+    }
+    let homes: Vec<String> = legacy_homes
+        .iter()
+        .map(|(_, path)| rv_dirs::unexpand(path))
+        .collect();
+    vec![Finding::Warning {
+        message: format!(
+            "`gem install --user-install` puts gems in {}, rather than rv's gem directory",
+            homes.join(", ")
+        ),
+        hint: "Run `rv migrate gem-home` to move them, or `rv migrate gem-home --keep-legacy` to \
+               keep using them"
+            .to_string(),
+    }]
+}
+
+/// Whether rv can parse every line of `lockfile`.
+fn lockfile_findings(lockfile: &Utf8Path, contents: &str) -> Vec<Finding> {
+    let lockfile = rv_dirs::unexpand(lockfile);
+    let (_, errors) = rv_lockfile::parse_lenient(contents);
+    let Some(errors) = errors else {
+        return vec![Finding::Ok(format!("rv can parse {lockfile}"))];
+    };
+    errors
+        .others
+        .iter()
+        .map(|error| Finding::Warning {
+            message: format!(
+                "rv can't parse line {} of {lockfile}: {}",
+                error.line(),
+                error.message()
+            ),
+            hint: "Fix the line, or run `bundle lock` to write the lockfile again".to_string(),
+        })
+        .collect()
+}
+
+fn describe(kind: ExternalKind) -> &'static str {
+    match kind {
+        ExternalKind::System => "the system's Ruby",
+        ExternalKind::Homebrew => "Homebrew's Ruby",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use rv_ruby::version::RubyVersion;
+
+    use super::*;
+
+    fn ruby(path: &str, os: &str) -> Ruby {
+        Ruby {
+            key: String::new(),
+            version: RubyVersion::from_str("ruby-2.6.10").unwrap(),
+            path: path.into(),
+            managed: false,
+            enable_shared: false,
+            symlink: None,
+            arch: "aarch64".into(),
+            os: os.into(),
+            gem_root: None,
+            rubygems_platform: "universal-darwin23".into(),
+        }
+    }
+
+    fn path_ruby(executable: &str, external: Option<ExternalKind>, os: &str) -> PathRuby {
+        PathRuby {
+            executable: executable.into(),
+            external,
+            ruby: Some(ruby(executable.trim_end_matches("/bin/ruby"), os)),
+            from_rv: false,
+        }
+    }
+
+    fn is_warning(finding: &Finding) -> bool {
+        matches!(finding, Finding::Warning { .. })
+    }
+
+    #[test]
+    fn test_gem_home_findings() {
+        let legacy = [("ruby/3.3.0".to_string(), "/home/me/.gem/ruby/3.3.0".into())];
+        assert!(is_warning(&gem_home_findings(None, &legacy)[0]));
+        assert!(gem_home_findings(Some(GemHomeLayout::Legacy), &legacy).is_empty());
+        assert!(gem_home_findings(None, &[]).is_empty());
+    }
+
+    #[test]
+    fn test_lockfile_findings() {
+        let lockfile = Utf8Path::new("/app/Gemfile.lock");
+        let valid = "GEM\n  remote: https://rubygems.org/\n  specs:\n    rake (13.3.0)\n";
+        let findings = lockfile_findings(lockfile, valid);
+        assert_eq!(findings.len(), 1);
+        assert!(!is_warning(&findings[0]));
+
+        let invalid = "GEM\n  remote: https://rubygems.org/\n  specs:\n    rake 13.3.0\n    \
+                       rack (3.2.3)\n\nPLATFORMS\n  ruby!\n";
+        let findings = lockfile_findings(lockfile, invalid);
+        assert_eq!(findings.len(), 2);
+        assert!(findings.iter().all(is_warning));
+        let Finding::Warning { message, .. } = &findings[0] else {
+            unreachable!()
+        };
+        assert!(message.starts_with("rv can't parse line 4 of /app/Gemfile.lock"));
+    }
+
+    #[test]
+    fn test_path_findings() {
+        let installed = [ruby("/rubies/ruby-3.4.1", "macos")];
+
+        let findings = path_findings(None, &installed);
+        assert!(is_warning(&findings[0]));
+
+        let mut from_rv = path_ruby("/rubies/ruby-3.4.1/bin/ruby", None, "macos");
+        from_rv.from_rv = true;
+        let findings = path_findings(Some(&from_rv), &installed);
+        assert_eq!(findings.len(), 1);
+        assert!(!is_warning(&findings[0]));
+
+        // Homebrew's Ruby shadows rv's.
+        let homebrew = path_ruby(
+            "/opt/homebrew/opt/ruby/bin/ruby",
+            Some(ExternalKind::Homebrew),
+            "macos",
+        );
+        let findings = path_findings(Some(&homebrew), &installed);
+        assert_eq!(findings.len(), 1);
+        assert!(is_warning(&findings[0]));
+
+        // macOS's system Ruby shadows rv's, and comes with caveats of its own.
+        let system = path_ruby("/usr/bin/ruby", Some(ExternalKind::System), "macos");
+        let findings = path_findings(Some(&system), &installed);
+        assert_eq!(findings.len(), 2);
+        assert!(findings.iter().all(is_warning));
+
+        // Linux's doesn't have those caveats.
+        let system = path_ruby("/usr/bin/ruby", Some(ExternalKind::System), "linux");
+        assert_eq!(path_findings(Some(&system), &installed).len(), 1);
+
+        // Nor does a Ruby that's neither rv's nor external.
+        let other = path_ruby("/home/me/.rbenv/shims/ruby", None, "linux");
+        let findings = path_findings(Some(&other), &installed);
+        assert_eq!(findings.len(), 1);
+        assert!(!is_warning(&findings[0]));
+    }
+}
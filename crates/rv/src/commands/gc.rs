@@ -0,0 +1,208 @@
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+
+use anstream::println;
+use bytesize::ByteSize;
+use camino::{Utf8Path, Utf8PathBuf};
+use clap::Args;
+use owo_colors::OwoColorize;
+use rv_ruby::Ruby;
+use rv_ruby::request::RubyRequest;
+use tracing::debug;
+
+use crate::GlobalArgs;
+use crate::commands::clean::disk_usage;
+use crate::config::Config;
+
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+pub enum Error {
+    #[error(transparent)]
+    #[diagnostic(code(RV7701))]
+    IoError(#[from] std::io::Error),
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    ConfigError(#[from] crate::config::Error),
+}
+
+type Result<T> = miette::Result<T, Error>;
+
+#[derive(Args)]
+pub struct GcArgs {
+    /// Show what would be removed, and how much space it takes, without removing anything
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+/// `rv run --isolated` removes its gem home when the command finishes, so one that's still around
+/// after this long belongs to a run that was killed.
+const ISOLATED_GEM_HOME_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Something rv created for a Ruby that's gone.
+#[derive(Debug, PartialEq, Eq)]
+struct Orphan {
+    path: Utf8PathBuf,
+    reason: String,
+}
+
+pub(crate) fn gc(global_args: &GlobalArgs, args: GcArgs) -> Result<()> {
+    let config = Config::with_settings(global_args, None)?;
+    let rubies = config.rubies();
+
+    let mut orphans = orphaned_gem_homes(&rv_ruby::user_gems_dir(), &rubies)?;
+    orphans.extend(orphaned_tools(&crate::commands::tool::tool_dir(), &rubies)?);
+    orphans.extend(orphaned_isolated_gem_homes(
+        &Utf8PathBuf::try_from(std::env::temp_dir()).map_err(|err| err.into_io_error())?,
+        SystemTime::now(),
+    )?);
+
+    if orphans.is_empty() {
+        println!("Nothing to collect");
+        return Ok(());
+    }
+
+    let mut total = 0;
+    for orphan in &orphans {
+        let bytes = if args.dry_run {
+            disk_usage(&orphan.path)?
+        } else {
+            rv_cache::rm_rf(&orphan.path)?.bytes
+        };
+        total += bytes;
+
+        println!(
+            "{} {} ({}, {})",
+            if args.dry_run {
+                "Would remove"
+            } else {
+                "Removed"
+            },
+            orphan.path.cyan(),
+            orphan.reason,
+            ByteSize::b(bytes).display().iec_short()
+        );
+    }
+
+    let total = ByteSize::b(total).display().iec_short();
+    if args.dry_run {
+        println!("Would free {}", total.cyan());
+    } else {
+        println!("Freed {}", total.cyan());
+    }
+
+    Ok(())
+}
+
+/// The gem homes under `gems_dir`, laid out as `<engine>/<abi>`, that no installed Ruby uses.
+fn orphaned_gem_homes(gems_dir: &Utf8Path, rubies: &[Ruby]) -> Result<Vec<Orphan>> {
+    let mut orphans = Vec::new();
+    for engine_dir in subdirs(gems_dir)? {
+        for gem_home in subdirs(&engine_dir)? {
+            let scope = gem_home.strip_prefix(gems_dir).unwrap_or(&gem_home);
+            let scope = scope.as_str().replace('\\', "/");
+            if rubies.iter().any(|ruby| ruby.gem_scope() == scope) {
+                continue;
+            }
+            orphans.push(Orphan {
+                reason: format!("gems for {scope}, which is not installed"),
+                path: gem_home,
+            });
+        }
+    }
+    Ok(orphans)
+}
+
+/// The tool environments under `tool_dir` whose pinned Ruby is no longer installed, or which
+/// don't pin one because their installation failed.
+fn orphaned_tools(tool_dir: &Utf8Path, rubies: &[Ruby]) -> Result<Vec<Orphan>> {
+    let mut orphans = Vec::new();
+    for tool in subdirs(tool_dir)? {
+        let pin = fs_err::read_to_string(tool.join(".ruby-version")).ok();
+        let request = pin.as_deref().map(str::trim).map(RubyRequest::from_str);
+        let reason = match request {
+            Some(Ok(request)) if request.find_match_in(rubies).is_some() => continue,
+            Some(Ok(request)) => format!("tool for Ruby {request}, which is not installed"),
+            Some(Err(_)) | None => "tool without a Ruby".to_owned(),
+        };
+        orphans.push(Orphan { path: tool, reason });
+    }
+    Ok(orphans)
+}
+
+/// The gem homes `rv run --isolated` left behind in `temp_dir` when it was killed.
+fn orphaned_isolated_gem_homes(temp_dir: &Utf8Path, now: SystemTime) -> Result<Vec<Orphan>> {
+    let mut orphans = Vec::new();
+    for dir in subdirs(temp_dir)? {
+        if !dir
+            .file_name()
+            .is_some_and(|name| name.starts_with("rv-isolated-"))
+        {
+            continue;
+        }
+        let modified = fs_err::metadata(&dir)?.modified()?;
+        if now
+            .duration_since(modified)
+            .is_ok_and(|age| age > ISOLATED_GEM_HOME_MAX_AGE)
+        {
+            orphans.push(Orphan {
+                path: dir,
+                reason: "gems from an interrupted `rv run --isolated`".to_owned(),
+            });
+        }
+    }
+    Ok(orphans)
+}
+
+/// The directories directly inside `dir`, sorted, or none if `dir` doesn't exist.
+fn subdirs(dir: &Utf8Path) -> Result<Vec<Utf8PathBuf>> {
+    if !dir.is_dir() {
+        debug!("Skipping {dir}, which doesn't exist");
+        return Ok(Vec::new());
+    }
+    let mut dirs = Vec::new();
+    for entry in dir.read_dir_utf8()? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            dirs.push(entry.into_path());
+        }
+    }
+    dirs.sort();
+    Ok(dirs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_orphaned_isolated_gem_homes() {
+        let temp_dir = camino_tempfile::tempdir().unwrap();
+        fs_err::create_dir(temp_dir.path().join("rv-isolated-abc")).unwrap();
+        fs_err::create_dir(temp_dir.path().join("something-else")).unwrap();
+
+        let now = SystemTime::now();
+        assert!(
+            orphaned_isolated_gem_homes(temp_dir.path(), now)
+                .unwrap()
+                .is_empty()
+        );
+
+        let tomorrow = now + ISOLATED_GEM_HOME_MAX_AGE + Duration::from_secs(60);
+        let orphans = orphaned_isolated_gem_homes(temp_dir.path(), tomorrow).unwrap();
+        assert_eq!(orphans.len(), 1);
+        assert_eq!(orphans[0].path, temp_dir.path().join("rv-isolated-abc"));
+    }
+
+    #[test]
+    fn test_orphaned_tools_without_ruby() {
+        let tool_dir = camino_tempfile::tempdir().unwrap();
+        fs_err::create_dir(tool_dir.path().join("rake@13.0.0")).unwrap();
+        let orphans = orphaned_tools(tool_dir.path(), &[]).unwrap();
+        assert_eq!(
+            orphans,
+            vec![Orphan {
+                path: tool_dir.path().join("rake@13.0.0"),
+                reason: "tool without a Ruby".to_owned(),
+            }]
+        );
+    }
+}
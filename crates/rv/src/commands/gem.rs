@@ -0,0 +1,92 @@
+//! `rv gem`: work with .gem files directly, without installing them.
+
+use std::io::BufReader;
+
+use anstream::println;
+use camino::Utf8PathBuf;
+use clap::{Args, Subcommand};
+use owo_colors::OwoColorize;
+
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+pub enum Error {
+    #[error("{count} of {total} gems failed to verify")]
+    #[diagnostic(code(RV8301))]
+    VerificationFailed { count: usize, total: usize },
+}
+
+type Result<T> = miette::Result<T, Error>;
+
+#[derive(Args)]
+pub struct GemArgs {
+    #[command(subcommand)]
+    pub command: GemCommand,
+}
+
+#[derive(Subcommand)]
+pub enum GemCommand {
+    #[command(
+        about = "Check the checksums, spec and contents of .gem files without unpacking them"
+    )]
+    Verify(VerifyArgs),
+}
+
+#[derive(Args)]
+pub struct VerifyArgs {
+    /// The .gem files to verify
+    #[arg(required = true)]
+    gems: Vec<Utf8PathBuf>,
+}
+
+pub(crate) fn gem(args: GemArgs) -> Result<()> {
+    match args.command {
+        GemCommand::Verify(verify_args) => gem_verify(verify_args),
+    }
+}
+
+fn gem_verify(args: VerifyArgs) -> Result<()> {
+    let mut failed = 0;
+    for path in &args.gems {
+        let verified = fs_err::File::open(path)
+            .map_err(rv_gem_package::Error::from)
+            .and_then(|file| rv_gem_package::verify(BufReader::new(file)));
+        match verified {
+            Ok(verified) => {
+                let checksums = if verified.checksummed.is_empty() {
+                    "no checksums".to_string()
+                } else {
+                    format!("checksums of {} match", verified.checksummed.join(", "))
+                };
+                println!(
+                    "{} {path}: {} {}, {} files, {checksums}",
+                    "ok".green(),
+                    verified.spec.name,
+                    verified.spec.version,
+                    verified.data_entries,
+                );
+            }
+            Err(err) => {
+                failed += 1;
+                println!("{} {path}: {}", "corrupt".red(), describe(&err));
+            }
+        }
+    }
+
+    if failed > 0 {
+        return Err(Error::VerificationFailed {
+            count: failed,
+            total: args.gems.len(),
+        });
+    }
+    Ok(())
+}
+
+/// The error and the errors that caused it, like `checksum error: checksum mismatch for ...`.
+fn describe(err: &dyn std::error::Error) -> String {
+    let mut message = err.to_string();
+    let mut source = err.source();
+    while let Some(cause) = source {
+        message += &format!(": {cause}");
+        source = cause.source();
+    }
+    message
+}
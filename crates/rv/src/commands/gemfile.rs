@@ -0,0 +1,159 @@
+//! `rv gemfile`: check a Gemfile, or print what rv reads from it, without running it.
+
+use std::collections::BTreeMap;
+
+use anstream::println;
+use camino::{Utf8Path, Utf8PathBuf};
+use clap::{Args, Subcommand};
+use owo_colors::OwoColorize;
+use serde::Serialize;
+
+use crate::GlobalArgs;
+use crate::commands::clean_install::gemfile_path;
+use crate::config::Config;
+use crate::config::gemfile::lint::{self, Problem};
+use crate::config::gemfile::{self, GemDeclaration};
+
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+pub enum Error {
+    #[error(transparent)]
+    #[diagnostic(code(RV8401))]
+    IoError(#[from] std::io::Error),
+    #[error("{gemfile} has {} problem(s)", problems.len())]
+    #[diagnostic(code(RV8402))]
+    ProblemsFound {
+        gemfile: Utf8PathBuf,
+        #[related]
+        problems: Vec<Problem>,
+    },
+    #[error("No Gemfile in {dir}")]
+    #[diagnostic(code(RV8403), help("Pass the Gemfile to check with `--gemfile`"))]
+    MissingGemfile { dir: Utf8PathBuf },
+    #[error(transparent)]
+    #[diagnostic(code(RV8409))]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    ConfigError(#[from] crate::config::Error),
+}
+
+type Result<T> = miette::Result<T, Error>;
+
+/// The names Bundler looks for a Gemfile under, in order.
+const GEMFILE_NAMES: [&str; 2] = ["Gemfile", "gems.rb"];
+
+#[derive(Args)]
+pub struct GemfileArgs {
+    #[command(subcommand)]
+    pub command: GemfileCommand,
+}
+
+#[derive(Subcommand)]
+pub enum GemfileCommand {
+    #[command(
+        about = "Report mistakes in a Gemfile, like missing commas and unknown options, with fixes"
+    )]
+    Lint(LintArgs),
+    #[command(
+        about = "Print what rv reads from a Gemfile as JSON",
+        long_about = "Print the Gemfile's Ruby, gem sources, and every gem it declares with its groups, platforms, source and version requirements as JSON, for tools that want to read Gemfiles the way rv does"
+    )]
+    Parse(ParseArgs),
+}
+
+#[derive(Args)]
+pub struct LintArgs {
+    /// Path to Gemfile
+    #[arg(long, env = "BUNDLE_GEMFILE")]
+    gemfile: Option<Utf8PathBuf>,
+}
+
+#[derive(Args)]
+pub struct ParseArgs {
+    /// Path to Gemfile
+    #[arg(long, env = "BUNDLE_GEMFILE")]
+    gemfile: Option<Utf8PathBuf>,
+
+    /// Print JSON, which is the only format
+    #[arg(long, required = true)]
+    json: bool,
+}
+
+/// Everything rv reads from a Gemfile. Gems are sorted by name, so the same Gemfile always
+/// prints the same JSON.
+#[derive(Debug, Serialize)]
+struct GemfileSnapshot {
+    /// The Ruby the `ruby` directive asks for, like `ruby-3.4.1`.
+    ruby: Option<String>,
+    sources: Vec<String>,
+    gems: Vec<GemSnapshot>,
+}
+
+#[derive(Debug, Serialize)]
+struct GemSnapshot {
+    name: String,
+    #[serde(flatten)]
+    declaration: GemDeclaration,
+}
+
+pub(crate) fn gemfile(global_args: &GlobalArgs, args: GemfileArgs) -> Result<()> {
+    match args.command {
+        GemfileCommand::Lint(lint_args) => gemfile_lint(global_args, lint_args),
+        GemfileCommand::Parse(parse_args) => gemfile_parse(global_args, parse_args),
+    }
+}
+
+fn gemfile_lint(global_args: &GlobalArgs, args: LintArgs) -> Result<()> {
+    let config = Config::with_settings(global_args, None)?;
+    let gemfile = the_gemfile(&config, args.gemfile.as_deref())?;
+    let contents = fs_err::read_to_string(&gemfile)?;
+
+    let problems = lint::lint(&contents);
+    if !problems.is_empty() {
+        return Err(Error::ProblemsFound { gemfile, problems });
+    }
+    println!("{} {gemfile} has no problems", "✓".green());
+    Ok(())
+}
+
+fn gemfile_parse(global_args: &GlobalArgs, args: ParseArgs) -> Result<()> {
+    let config = Config::with_settings(global_args, None)?;
+    let gemfile_path = the_gemfile(&config, args.gemfile.as_deref())?;
+    let contents = fs_err::read_to_string(&gemfile_path)?;
+
+    let ruby = config.current_ruby().map(|ruby| ruby.executable_path());
+    let declarations = gemfile::read_gem_declarations(&gemfile_path, &contents, ruby.as_deref());
+    let snapshot = GemfileSnapshot {
+        ruby: rv_core::gemfile::ruby_request(&contents)
+            .and_then(|request| request.ok())
+            .map(|request| request.to_string()),
+        sources: gemfile::sources(&contents),
+        gems: declarations
+            .into_iter()
+            .collect::<BTreeMap<_, _>>()
+            .into_iter()
+            .map(|(name, declaration)| GemSnapshot { name, declaration })
+            .collect(),
+    };
+    println!("{}", serde_json::to_string_pretty(&snapshot)?);
+    Ok(())
+}
+
+/// The Gemfile `--gemfile` or `BUNDLE_GEMFILE` points at, or else the one in the project.
+fn the_gemfile(config: &Config, gemfile: Option<&Utf8Path>) -> Result<Utf8PathBuf> {
+    match gemfile_path(config, gemfile) {
+        Some(gemfile) => Ok(gemfile),
+        None => find_gemfile(&config.project_root),
+    }
+}
+
+/// The Gemfile in `dir`, under either name Bundler accepts.
+pub(crate) fn find_gemfile(dir: &Utf8Path) -> Result<Utf8PathBuf> {
+    GEMFILE_NAMES
+        .iter()
+        .map(|name| dir.join(name))
+        .find(|path| path.is_file())
+        .ok_or_else(|| Error::MissingGemfile {
+            dir: dir.to_path_buf(),
+        })
+}
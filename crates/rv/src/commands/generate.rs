@@ -0,0 +1,348 @@
+use anstream::{eprintln, print};
+use camino::Utf8PathBuf;
+use clap::{Args, Subcommand};
+use owo_colors::OwoColorize;
+use rv_ruby::request::RubyRequest;
+
+use crate::GlobalArgs;
+use crate::config::{Config, RequestedRuby};
+
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+pub enum Error {
+    #[error(transparent)]
+    #[diagnostic(code(RV7101))]
+    IoError(#[from] std::io::Error),
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    ConfigError(#[from] crate::config::Error),
+    #[error("{0} already exists")]
+    #[diagnostic(
+        code(RV7102),
+        help("Use `--force` to overwrite it, or `--stdout` to print it instead")
+    )]
+    AlreadyExists(Utf8PathBuf),
+}
+
+type Result<T> = miette::Result<T, Error>;
+
+#[derive(Args)]
+pub struct GenerateArgs {
+    #[command(subcommand)]
+    pub command: GenerateCommand,
+}
+
+#[derive(Subcommand)]
+pub enum GenerateCommand {
+    #[command(about = "Generate a GitHub Actions workflow that tests the project with rv")]
+    GithubActions(GithubActionsArgs),
+    #[command(
+        about = "Generate a Dockerfile that installs the project's Ruby and gems with rv, using build cache mounts"
+    )]
+    Dockerfile(DockerfileArgs),
+}
+
+#[derive(Args)]
+pub struct GithubActionsArgs {
+    /// Rubies to test with, separated by commas. Defaults to the project's pinned Ruby.
+    #[arg(long, value_delimiter = ',')]
+    pub ruby: Vec<RubyRequest>,
+
+    /// GitHub Actions runners to test on, separated by commas
+    #[arg(long, value_delimiter = ',', default_value = "ubuntu-latest")]
+    pub os: Vec<String>,
+
+    /// The command that runs the project's tests
+    #[arg(long, default_value = "bundle exec rake")]
+    pub test_command: String,
+
+    /// Where to write the workflow, relative to the project directory
+    #[arg(long, default_value = ".github/workflows/rv.yml")]
+    pub output: Utf8PathBuf,
+
+    /// Print the workflow instead of writing it to a file
+    #[arg(long, conflicts_with = "output")]
+    pub stdout: bool,
+
+    /// Overwrite the workflow file if it already exists
+    #[arg(long)]
+    pub force: bool,
+}
+
+#[derive(Args)]
+pub struct DockerfileArgs {
+    /// The image to build from, and run the project in
+    #[arg(long, default_value = "debian:bookworm-slim")]
+    pub base: String,
+
+    /// The command that starts the project, if the image should have one
+    #[arg(long)]
+    pub command: Option<String>,
+
+    /// Where to write the Dockerfile, relative to the project directory
+    #[arg(long, default_value = "Dockerfile")]
+    pub output: Utf8PathBuf,
+
+    /// Print the Dockerfile instead of writing it to a file
+    #[arg(long, conflicts_with = "output")]
+    pub stdout: bool,
+
+    /// Overwrite the Dockerfile if it already exists
+    #[arg(long)]
+    pub force: bool,
+}
+
+pub(crate) fn generate(global_args: &GlobalArgs, args: GenerateArgs) -> Result<()> {
+    match args.command {
+        GenerateCommand::GithubActions(args) => github_actions(global_args, args),
+        GenerateCommand::Dockerfile(args) => dockerfile(global_args, args),
+    }
+}
+
+fn github_actions(global_args: &GlobalArgs, args: GithubActionsArgs) -> Result<()> {
+    let config = Config::new(global_args, None)?;
+
+    let rubies = if args.ruby.is_empty() {
+        match &config.requested_ruby {
+            RequestedRuby::Project((request, _)) => vec![request.to_string()],
+            _ => vec![],
+        }
+    } else {
+        args.ruby.iter().map(ToString::to_string).collect()
+    };
+    let workflow = github_actions_workflow(&rubies, &args.os, &args.test_command);
+
+    if args.stdout {
+        print!("{workflow}");
+        return Ok(());
+    }
+
+    let path = config.project_root.join(&args.output);
+    if path.exists() && !args.force {
+        return Err(Error::AlreadyExists(path));
+    }
+    if let Some(parent) = path.parent() {
+        fs_err::create_dir_all(parent)?;
+    }
+    fs_err::write(&path, workflow)?;
+    eprintln!("Wrote GitHub Actions workflow to {}", path.cyan());
+
+    Ok(())
+}
+
+/// A workflow that installs rv, restores rv's cache, installs gems with `rv ci` and runs the tests,
+/// for every combination of runner and Ruby.
+///
+/// The cache key covers everything that decides what ends up in the cache: the runner's OS and
+/// architecture, the Ruby, the lockfile and the project's Ruby pin.
+fn github_actions_workflow(rubies: &[String], os: &[String], test_command: &str) -> String {
+    let test_ruby = !rubies.is_empty();
+    let list = |values: &[String]| {
+        let quoted: Vec<_> = values
+            .iter()
+            .map(|value| serde_json::to_string(value).unwrap())
+            .collect();
+        format!("[{}]", quoted.join(", "))
+    };
+
+    let mut workflow = String::from(
+        "# Generated by `rv generate github-actions`.
+name: CI
+
+on:
+  push:
+    branches: [main]
+  pull_request:
+
+jobs:
+  test:
+",
+    );
+    if test_ruby {
+        workflow.push_str("    name: Test (${{ matrix.os }}, Ruby ${{ matrix.ruby }})\n");
+    } else {
+        workflow.push_str("    name: Test (${{ matrix.os }})\n");
+    }
+    workflow.push_str(&format!(
+        "    runs-on: ${{{{ matrix.os }}}}
+    strategy:
+      fail-fast: false
+      matrix:
+        os: {}
+",
+        list(os)
+    ));
+    if test_ruby {
+        workflow.push_str(&format!(
+            "        ruby: {}
+    env:
+      RV_RUBY_VERSION: ${{{{ matrix.ruby }}}}
+",
+            list(rubies)
+        ));
+    }
+
+    let cache_key = if test_ruby {
+        "rv-${{ runner.os }}-${{ runner.arch }}-ruby-${{ matrix.ruby }}-"
+    } else {
+        "rv-${{ runner.os }}-${{ runner.arch }}-"
+    };
+    workflow.push_str(&format!(
+        "    steps:
+      - uses: actions/checkout@v6
+      - name: Install rv
+        shell: bash
+        run: |
+          curl -LsSf https://rv.dev/install | sh
+          echo \"$HOME/.cargo/bin\" >> \"$GITHUB_PATH\"
+          echo \"RV_CACHE_DIR=$RUNNER_TEMP/rv-cache\" >> \"$GITHUB_ENV\"
+      - name: Cache rubies and gems
+        uses: actions/cache@v4
+        with:
+          path: ${{{{ runner.temp }}}}/rv-cache
+          key: {cache_key}${{{{ hashFiles('**/Gemfile.lock', '.ruby-version', '.tool-versions') }}}}
+          restore-keys: |
+            {cache_key}
+      - name: Install gems
+        run: rv ci
+      - name: Run tests
+        run: |
+          rv run -- {test_command}
+"
+    ));
+
+    workflow
+}
+
+fn dockerfile(global_args: &GlobalArgs, args: DockerfileArgs) -> Result<()> {
+    let config = Config::new(global_args, None)?;
+
+    let pin_files: Vec<&str> = RUBY_PIN_FILES
+        .into_iter()
+        .filter(|file| config.project_root.join(file).exists())
+        .collect();
+    let dockerfile = dockerfile_contents(&args.base, &pin_files, args.command.as_deref());
+
+    if args.stdout {
+        print!("{dockerfile}");
+        return Ok(());
+    }
+
+    let path = config.project_root.join(&args.output);
+    if path.exists() && !args.force {
+        return Err(Error::AlreadyExists(path));
+    }
+    fs_err::write(&path, dockerfile)?;
+    eprintln!("Wrote Dockerfile to {}", path.cyan());
+
+    Ok(())
+}
+
+/// Files that pin the project's Ruby, which the Dockerfile copies in before installing it.
+const RUBY_PIN_FILES: [&str; 2] = [".ruby-version", ".tool-versions"];
+
+/// A multi-stage Dockerfile. The build stage installs rv, the project's Ruby and its gems, and the
+/// final stage copies in only what they installed.
+///
+/// Downloads go to rv's cache, which is a cache mount, so they're reused across builds without
+/// ending up in any layer. Gems are installed with `--destdir` into a staging directory that's
+/// copied into the final image in one layer, and `SOURCE_DATE_EPOCH` gives their files fixed
+/// timestamps, so that an unchanged lockfile builds an identical layer.
+fn dockerfile_contents(base: &str, pin_files: &[&str], command: Option<&str>) -> String {
+    let mut project_files = vec!["Gemfile", "Gemfile.lock"];
+    project_files.extend(pin_files);
+    let project_files = project_files.join(" ");
+
+    let mut dockerfile = format!(
+        "# syntax=docker/dockerfile:1
+# Generated by `rv generate dockerfile`.
+
+FROM {base} AS build
+RUN apt-get update \\
+    && apt-get install --yes --no-install-recommends build-essential ca-certificates curl git libyaml-dev \\
+    && rm -rf /var/lib/apt/lists/*
+RUN curl -LsSf https://rv.dev/install | sh
+ENV PATH=\"/root/.cargo/bin:$PATH\" \\
+    RV_CACHE_DIR=/var/cache/rv \\
+    BUNDLE_PATH=/usr/local/bundle \\
+    SOURCE_DATE_EPOCH=0
+WORKDIR /app
+
+# Only the files that decide which Ruby and gems to install, so that this layer stays cached
+# until one of them changes.
+COPY {project_files} ./
+RUN --mount=type=cache,target=/var/cache/rv,sharing=locked \\
+    rv ruby install --system \\
+    && rv ci --destdir /staging
+
+FROM {base}
+RUN apt-get update \\
+    && apt-get install --yes --no-install-recommends ca-certificates libyaml-0-2 \\
+    && rm -rf /var/lib/apt/lists/*
+ENV BUNDLE_PATH=/usr/local/bundle
+COPY --from=build /root/.cargo/bin/rv /usr/local/bin/rv
+COPY --from=build /opt/rubies /opt/rubies
+COPY --from=build /staging/ /
+WORKDIR /app
+COPY . .
+"
+    );
+    if let Some(command) = command {
+        let words: Vec<_> = ["rv", "run", "--"]
+            .into_iter()
+            .chain(command.split_whitespace())
+            .map(|word| serde_json::to_string(word).unwrap())
+            .collect();
+        dockerfile.push_str(&format!("CMD [{}]\n", words.join(", ")));
+    }
+
+    dockerfile
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_github_actions_workflow() {
+        let workflow = github_actions_workflow(
+            &["3.3".to_string(), "3.4".to_string()],
+            &["ubuntu-latest".to_string(), "macos-latest".to_string()],
+            "bundle exec rake test",
+        );
+        insta::assert_snapshot!(workflow);
+    }
+
+    #[test]
+    fn test_github_actions_workflow_without_rubies() {
+        let workflow =
+            github_actions_workflow(&[], &["ubuntu-latest".to_string()], "bundle exec rspec");
+        assert!(workflow.contains("name: Test (${{ matrix.os }})\n"));
+        assert!(!workflow.contains("matrix.ruby"));
+        assert!(workflow.contains("key: rv-${{ runner.os }}-${{ runner.arch }}-${{ hashFiles("));
+        assert!(workflow.contains("rv run -- bundle exec rspec\n"));
+    }
+
+    #[test]
+    fn test_dockerfile_contents() {
+        let dockerfile = dockerfile_contents(
+            "debian:bookworm-slim",
+            &[".ruby-version"],
+            Some("bin/rails server -b 0.0.0.0"),
+        );
+        assert!(dockerfile.starts_with("# syntax=docker/dockerfile:1\n"));
+        assert!(dockerfile.contains("FROM debian:bookworm-slim AS build\n"));
+        assert!(dockerfile.contains("COPY Gemfile Gemfile.lock .ruby-version ./\n"));
+        assert!(
+            dockerfile.contains("RUN --mount=type=cache,target=/var/cache/rv,sharing=locked \\\n")
+        );
+        assert!(dockerfile.contains("    && rv ci --destdir /staging\n"));
+        assert!(dockerfile.contains("COPY --from=build /staging/ /\n"));
+        assert!(dockerfile.ends_with(
+            "CMD [\"rv\", \"run\", \"--\", \"bin/rails\", \"server\", \"-b\", \"0.0.0.0\"]\n"
+        ));
+
+        let dockerfile = dockerfile_contents("ruby:slim", &[], None);
+        assert!(dockerfile.contains("COPY Gemfile Gemfile.lock ./\n"));
+        assert!(dockerfile.ends_with("COPY . .\n"));
+    }
+}
@@ -0,0 +1,400 @@
+use std::collections::{HashMap, HashSet};
+
+use anstream::println;
+use camino::{Utf8Path, Utf8PathBuf};
+use clap::{Args, Subcommand};
+use owo_colors::OwoColorize;
+use rv_gem_types::ProjectDependency;
+use rv_gem_types::requirement::Requirement;
+use rv_lockfile::datatypes::{GemfileDotLock, Spec};
+
+use crate::GlobalArgs;
+use crate::commands::clean_install::{find_lockfile_path, gemfile_for_lockfile};
+use crate::commands::update;
+use crate::config::Config;
+use crate::config::gemfile::{self, GemDeclaration};
+
+mod diff;
+mod merge;
+mod snapshot;
+
+use diff::DiffFormat;
+
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+pub enum Error {
+    #[error(transparent)]
+    #[diagnostic(code(RV7301))]
+    IoError(#[from] std::io::Error),
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    LockfileNotFound(#[from] crate::commands::clean_install::Error),
+    #[error("Could not parse {lockfile}")]
+    #[diagnostic(code(RV7302))]
+    Parse {
+        lockfile: Utf8PathBuf,
+        #[diagnostic_source]
+        source: rv_lockfile::ParseErrors,
+    },
+    #[error("{count} platform-specific gems have no \"ruby\" platform version to fall back to")]
+    #[diagnostic(
+        code(RV7303),
+        help(
+            "Run `bundle lock --add-platform ruby` to add them, or `bundle lock --remove-platform ruby` if the project only installs on the platforms it lists"
+        )
+    )]
+    MissingRubyFallbacks { count: usize },
+    #[error("{lockfile} is out of date with the Gemfile")]
+    #[diagnostic(
+        code(RV7304),
+        help("Run `bundle lock` to update it, and commit the result")
+    )]
+    Outdated { lockfile: Utf8PathBuf },
+    #[error("`--merge` takes no files, or the base, ours and theirs files of a merge")]
+    #[diagnostic(
+        code(RV7305),
+        help(
+            "Run `rv lock --merge` to resolve the conflicts in the lockfile, or use `rv lock --merge %O %A %B` as a git merge driver"
+        )
+    )]
+    MergeArgs,
+    #[error("Both sides of the merge changed {what}")]
+    #[diagnostic(
+        code(RV7306),
+        help("Resolve the conflict in the Gemfile, then run `bundle lock` to update the lockfile")
+    )]
+    MergeConflict { what: String },
+    #[error("The versions locked on either side of the merge don't work together:\n{0}")]
+    #[diagnostic(code(RV7307), help("Run `bundle lock` to resolve the Gemfile again"))]
+    CouldNotMerge(String),
+    #[error(transparent)]
+    #[diagnostic(code(RV7308))]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    ConfigError(#[from] crate::config::Error),
+}
+
+type Result<T> = miette::Result<T, Error>;
+
+#[derive(Args)]
+#[command(arg_required_else_help = true)]
+pub struct LockArgs {
+    #[command(subcommand)]
+    pub command: Option<LockCommand>,
+
+    /// Path to Gemfile
+    #[arg(long, env = "BUNDLE_GEMFILE")]
+    pub gemfile: Option<Utf8PathBuf>,
+
+    /// Rewrite the lockfile with every section sorted the way Bundler sorts it and duplicate
+    /// entries removed, then check that platform-specific gems have a "ruby" platform fallback
+    #[arg(long)]
+    pub normalize: bool,
+
+    /// Check that the lockfile still satisfies the Gemfile, without changing it or using the
+    /// network, and fail with a diff of what's out of date if it doesn't
+    #[arg(long)]
+    pub check: bool,
+
+    /// Resolve git merge conflicts in the lockfile, locking gems the two sides locked at
+    /// different versions at the higher versions that work together. Given the base, ours and
+    /// theirs files of a merge, writes the result to ours, so that it can be used as a git merge
+    /// driver: `rv lock --merge %O %A %B`
+    #[arg(
+        long,
+        num_args = 0..=3,
+        value_names = ["BASE", "OURS", "THEIRS"],
+        conflicts_with_all = ["normalize", "check"]
+    )]
+    pub merge: Option<Vec<Utf8PathBuf>>,
+}
+
+#[derive(Subcommand)]
+pub enum LockCommand {
+    #[command(
+        about = "Show which gems and platforms changed between two lockfiles",
+        long_about = "Show which gems were added, removed, upgraded or downgraded between two lockfiles, which gems moved to another source or git commit, and which platforms changed"
+    )]
+    Diff {
+        /// The lockfile before the change
+        old: Utf8PathBuf,
+
+        /// The lockfile after the change
+        new: Utf8PathBuf,
+
+        /// Output format for the diff
+        #[arg(long, value_enum, default_value = "text")]
+        format: DiffFormat,
+    },
+    #[command(
+        about = "Print everything rv reads from the lockfile as JSON",
+        long_about = "Print the lockfile's sources, locked gems and their dependencies, platforms, DEPENDENCIES, Ruby and Bundler versions and checksums as JSON, for tools that want to read lockfiles the way rv does"
+    )]
+    Parse {
+        /// Print JSON, which is the only format
+        #[arg(long, required = true)]
+        json: bool,
+    },
+}
+
+/// A line of the diff between the lockfile and the Gemfile.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum DiffLine {
+    /// In the lockfile, but not what the Gemfile asks for.
+    Removed(String),
+    /// What the Gemfile asks for, but not in the lockfile.
+    Added(String),
+    /// Why the locked versions don't work for the Gemfile.
+    Note(String),
+}
+
+pub(crate) fn lock(global_args: &GlobalArgs, args: LockArgs) -> Result<()> {
+    if let Some(LockCommand::Diff { old, new, format }) = &args.command {
+        return diff::diff(old, new, *format);
+    }
+    if let Some(LockCommand::Parse { .. }) = &args.command {
+        return snapshot::print_snapshot(&args.gemfile);
+    }
+    // A lockfile with conflicts in it doesn't parse, so merges don't start from the lockfile.
+    if let Some(files) = &args.merge {
+        return merge::merge(&args.gemfile, files);
+    }
+
+    let lockfile_path = find_lockfile_path(&args.gemfile)?;
+    let raw_contents = fs_err::read_to_string(&lockfile_path)?;
+    let contents = rv_lockfile::normalize_line_endings(&raw_contents);
+    let mut lockfile = parse(&lockfile_path, &contents)?;
+
+    if args.normalize {
+        lockfile.normalize();
+        let normalized = lockfile.to_string();
+        if normalized == contents {
+            println!("{} is already normalized", lockfile_path.cyan());
+        } else {
+            rv_lockfile::write_preserving_line_endings(&lockfile_path, &normalized, &raw_contents)?;
+            println!("Normalized {}", lockfile_path.cyan());
+        }
+    }
+
+    if args.check {
+        let config = Config::with_settings(global_args, None)?;
+        let ruby = config.current_ruby().map(|ruby| ruby.executable_path());
+        let gemfile_path = gemfile_for_lockfile(&lockfile_path);
+        let gemfile = fs_err::read_to_string(&gemfile_path)?;
+        let declarations = gemfile::read_gem_declarations(&gemfile_path, &gemfile, ruby.as_deref());
+
+        let diff = drift(&lockfile, &declarations);
+        if !diff.is_empty() {
+            for line in &diff {
+                match line {
+                    DiffLine::Removed(text) => println!("{}", format!("- {text}").red()),
+                    DiffLine::Added(text) => println!("{}", format!("+ {text}").green()),
+                    DiffLine::Note(text) => println!("{text}"),
+                }
+            }
+            return Err(Error::Outdated {
+                lockfile: lockfile_path,
+            });
+        }
+        println!("{} is up to date with the Gemfile", lockfile_path.cyan());
+    }
+
+    let missing = lockfile.missing_ruby_fallbacks();
+    if !missing.is_empty() {
+        for tuple in &missing {
+            println!(
+                "{} has no {} platform version",
+                tuple.full_name().yellow(),
+                "ruby".cyan()
+            );
+        }
+        return Err(Error::MissingRubyFallbacks {
+            count: missing.len(),
+        });
+    }
+
+    Ok(())
+}
+
+fn parse<'i>(lockfile: &Utf8Path, contents: &'i str) -> Result<GemfileDotLock<'i>> {
+    rv_lockfile::parse(contents).map_err(|source| Error::Parse {
+        lockfile: lockfile.to_owned(),
+        source,
+    })
+}
+
+/// How the lockfile differs from what the Gemfile declares: its DEPENDENCIES, and whether the
+/// locked versions still resolve for the Gemfile's requirements without any gems left over.
+pub(crate) fn drift(
+    lockfile: &GemfileDotLock,
+    declarations: &HashMap<String, GemDeclaration>,
+) -> Vec<DiffLine> {
+    let mut diff = vec![];
+
+    let mut names: Vec<&String> = declarations.keys().collect();
+    names.sort();
+    for name in &names {
+        let declaration = &declarations[*name];
+        let locked = lockfile.dependencies.iter().find(|dep| dep.name == **name);
+        let wanted = declaration
+            .requirements
+            .as_ref()
+            .and_then(|requirements| Requirement::new(requirements.clone()).ok());
+        match (locked, wanted) {
+            (None, wanted) => diff.push(DiffLine::Added(dependency(name, wanted.as_ref()))),
+            (Some(locked), Some(wanted)) if !same_requirement(&locked.requirement, &wanted) => {
+                diff.push(DiffLine::Removed(locked.to_string()));
+                diff.push(DiffLine::Added(dependency(name, Some(&wanted))));
+            }
+            (Some(_), _) => {}
+        }
+    }
+    for dep in &lockfile.dependencies {
+        if !declarations.contains_key(dep.name) {
+            diff.push(DiffLine::Removed(dep.to_string()));
+        }
+    }
+    if !diff.is_empty() {
+        return diff;
+    }
+
+    let specs: Vec<&Spec> = lockfile
+        .gem
+        .iter()
+        .flat_map(|section| &section.specs)
+        .chain(lockfile.git.iter().flat_map(|section| &section.specs))
+        .chain(lockfile.path.iter().flat_map(|section| &section.specs))
+        .collect();
+    let requirements = lockfile
+        .dependencies
+        .iter()
+        // Bundler doesn't lock gems that are only for platforms the lockfile isn't for.
+        .filter(|dep| {
+            declarations[dep.name].platforms.is_empty()
+                || specs.iter().any(|spec| spec.release_tuple.name == dep.name)
+        })
+        .map(|dep| ProjectDependency {
+            name: dep.name.to_string(),
+            requirement: dep.requirement.clone(),
+        })
+        .collect();
+    let locked = update::locked_releases(lockfile, &HashSet::new());
+    match crate::resolver::solve_project(requirements, locked) {
+        Ok(resolved) => {
+            let resolved: HashSet<&str> = resolved
+                .iter()
+                .map(|(tuple, _)| tuple.name.as_str())
+                .collect();
+            for spec in specs {
+                if !resolved.contains(spec.release_tuple.name.as_str()) {
+                    let tuple = &spec.release_tuple;
+                    diff.push(DiffLine::Removed(format!(
+                        "{} ({}), which is no longer needed",
+                        tuple.name,
+                        tuple.full_version()
+                    )));
+                }
+            }
+        }
+        Err(err) => diff.push(DiffLine::Note(format!(
+            "The locked versions don't satisfy the Gemfile:\n{}",
+            crate::resolver::explain(&err)
+        ))),
+    }
+
+    diff
+}
+
+/// A DEPENDENCIES entry, written the way Bundler writes it.
+fn dependency(name: &str, requirement: Option<&Requirement>) -> String {
+    match requirement {
+        Some(requirement) if !requirement.is_latest_version() => format!("{name} ({requirement})"),
+        _ => name.to_string(),
+    }
+}
+
+/// Whether two requirements allow the same versions, going by how they're written.
+fn same_requirement(a: &Requirement, b: &Requirement) -> bool {
+    let constraints = |requirement: &Requirement| {
+        let mut constraints: Vec<String> = requirement
+            .constraints
+            .iter()
+            .filter(|constraint| !constraint.is_latest())
+            .map(ToString::to_string)
+            .collect();
+        constraints.sort();
+        constraints
+    };
+    constraints(a) == constraints(b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOCKFILE: &str = "\
+GEM
+  remote: https://rubygems.org/
+  specs:
+    rack (3.1.7)
+    rails (7.1.0)
+      rack (>= 2.2)
+    tzinfo-data (1.2024.1)
+
+PLATFORMS
+  ruby
+
+DEPENDENCIES
+  rails (~> 7.1)
+  tzinfo-data
+
+BUNDLED WITH
+   2.6.9
+";
+
+    fn declarations(gemfile: &str) -> HashMap<String, GemDeclaration> {
+        gemfile::gem_declarations(gemfile, camino::Utf8Path::new("."))
+    }
+
+    #[test]
+    fn test_drift_up_to_date() {
+        let lockfile = rv_lockfile::parse(LOCKFILE).unwrap();
+        let gemfile =
+            "gem \"rails\", \"~> 7.1\"\ngem \"tzinfo-data\", platforms: %i[windows jruby]\n";
+        assert_eq!(drift(&lockfile, &declarations(gemfile)), vec![]);
+    }
+
+    #[test]
+    fn test_drift_in_dependencies() {
+        let lockfile = rv_lockfile::parse(LOCKFILE).unwrap();
+        let gemfile = "gem \"rails\", \"~> 7.2\"\ngem \"pg\"\n";
+        assert_eq!(
+            drift(&lockfile, &declarations(gemfile)),
+            vec![
+                DiffLine::Added("pg".to_string()),
+                DiffLine::Removed("rails (~> 7.1)".to_string()),
+                DiffLine::Added("rails (~> 7.2)".to_string()),
+                DiffLine::Removed("tzinfo-data".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_drift_in_locked_versions() {
+        let contents = LOCKFILE.replace("rack (>= 2.2)", "rack (>= 4.0)");
+        let lockfile = rv_lockfile::parse(&contents).unwrap();
+        let gemfile = "gem \"rails\", \"~> 7.1\"\ngem \"tzinfo-data\"\n";
+        let diff = drift(&lockfile, &declarations(gemfile));
+        assert_eq!(diff.len(), 1);
+        assert!(matches!(&diff[0], DiffLine::Note(note) if note.contains("rack")));
+
+        let contents = LOCKFILE.replace("      rack (>= 2.2)\n", "");
+        let lockfile = rv_lockfile::parse(&contents).unwrap();
+        assert_eq!(
+            drift(&lockfile, &declarations(gemfile)),
+            vec![DiffLine::Removed(
+                "rack (3.1.7), which is no longer needed".to_string()
+            )]
+        );
+    }
+}
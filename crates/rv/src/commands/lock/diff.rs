@@ -0,0 +1,373 @@
+//! `rv lock diff`, which compares two lockfiles by what they lock rather than line by line.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use anstream::{print, println};
+use camino::Utf8PathBuf;
+use owo_colors::OwoColorize;
+use rv_gem_types::Platform;
+use rv_lockfile::datatypes::GemfileDotLock;
+use rv_version::Version;
+use serde::Serialize;
+
+use super::{Result, parse};
+
+/// How to print the diff.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DiffFormat {
+    /// One line per change, for people
+    #[default]
+    Text,
+    /// An object with the changed gems and platforms, for scripts
+    Json,
+    /// Tables, for bots to post as pull request comments
+    Markdown,
+}
+
+/// Where a lockfile locks a gem from.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Source {
+    Gem(Option<String>),
+    Git { remote: String, revision: String },
+    Path(String),
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Gem(Some(remote)) => write!(f, "{remote}"),
+            Self::Gem(None) => write!(f, "installed gems"),
+            Self::Git { remote, revision } => {
+                let short = revision.get(..7).unwrap_or(revision);
+                write!(f, "{remote}@{short}")
+            }
+            Self::Path(path) => write!(f, "{path}"),
+        }
+    }
+}
+
+/// What changed about a gem.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Change {
+    Added,
+    Removed,
+    Upgraded,
+    Downgraded,
+    /// Locked from a different source, or a different commit of the same git repo.
+    SourceChanged,
+}
+
+/// A change to one gem. `from` and `to` are versions, or sources for [`Change::SourceChanged`].
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub(crate) struct GemChange {
+    pub name: String,
+    pub change: Change,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub to: Option<String>,
+}
+
+/// Platforms added to or removed from the PLATFORMS section.
+#[derive(Debug, Default, PartialEq, Eq, Serialize)]
+pub(crate) struct PlatformChanges {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Serialize)]
+pub(crate) struct LockfileDiff {
+    pub gems: Vec<GemChange>,
+    pub platforms: PlatformChanges,
+}
+
+impl LockfileDiff {
+    pub fn is_empty(&self) -> bool {
+        self.gems.is_empty() && self.platforms.added.is_empty() && self.platforms.removed.is_empty()
+    }
+}
+
+pub(super) fn diff(old: &Utf8PathBuf, new: &Utf8PathBuf, format: DiffFormat) -> Result<()> {
+    let old_contents = fs_err::read_to_string(old)?;
+    let old_contents = rv_lockfile::normalize_line_endings(&old_contents);
+    let new_contents = fs_err::read_to_string(new)?;
+    let new_contents = rv_lockfile::normalize_line_endings(&new_contents);
+    let diff = diff_lockfiles(&parse(old, &old_contents)?, &parse(new, &new_contents)?);
+
+    match format {
+        DiffFormat::Text => print_text(&diff),
+        DiffFormat::Json => println!("{}", serde_json::to_string_pretty(&diff)?),
+        DiffFormat::Markdown => print!("{}", markdown(&diff)),
+    }
+    Ok(())
+}
+
+/// The highest version and the source of each gem `lockfile` locks.
+fn locked_gems(lockfile: &GemfileDotLock) -> BTreeMap<String, (Version, Source)> {
+    let gem = lockfile.gem.iter().flat_map(|section| {
+        let source = Source::Gem(section.remote.map(str::to_owned));
+        section.specs.iter().map(move |spec| (spec, source.clone()))
+    });
+    let git = lockfile.git.iter().flat_map(|section| {
+        let source = Source::Git {
+            remote: section.remote.to_owned(),
+            revision: section.revision.to_owned(),
+        };
+        section.specs.iter().map(move |spec| (spec, source.clone()))
+    });
+    let path = lockfile.path.iter().flat_map(|section| {
+        let source = Source::Path(section.remote.to_owned());
+        section.specs.iter().map(move |spec| (spec, source.clone()))
+    });
+
+    let mut gems: BTreeMap<String, (Version, Source)> = BTreeMap::new();
+    for (spec, source) in gem.chain(git).chain(path) {
+        let tuple = &spec.release_tuple;
+        match gems.get_mut(&tuple.name) {
+            Some((version, _)) if *version >= tuple.version => {}
+            Some(locked) => *locked = (tuple.version.clone(), source),
+            None => {
+                gems.insert(tuple.name.clone(), (tuple.version.clone(), source));
+            }
+        }
+    }
+    gems
+}
+
+/// What changed between the `old` and `new` lockfiles: which gems were added, removed, locked at
+/// other versions or from other sources, and which platforms were added or removed.
+pub(crate) fn diff_lockfiles(old: &GemfileDotLock, new: &GemfileDotLock) -> LockfileDiff {
+    let old_gems = locked_gems(old);
+    let new_gems = locked_gems(new);
+    let names: BTreeSet<&String> = old_gems.keys().chain(new_gems.keys()).collect();
+
+    let mut gems = Vec::new();
+    for name in names {
+        let change = |change, from: Option<String>, to: Option<String>| GemChange {
+            name: name.clone(),
+            change,
+            from,
+            to,
+        };
+        match (old_gems.get(name), new_gems.get(name)) {
+            (None, Some((version, _))) => {
+                gems.push(change(Change::Added, None, Some(version.to_string())))
+            }
+            (Some((version, _)), None) => {
+                gems.push(change(Change::Removed, Some(version.to_string()), None))
+            }
+            (Some((old_version, old_source)), Some((new_version, new_source))) => {
+                let versions = (Some(old_version.to_string()), Some(new_version.to_string()));
+                if new_version > old_version {
+                    gems.push(change(Change::Upgraded, versions.0, versions.1));
+                } else if new_version < old_version {
+                    gems.push(change(Change::Downgraded, versions.0, versions.1));
+                }
+                if old_source != new_source {
+                    gems.push(change(
+                        Change::SourceChanged,
+                        Some(old_source.to_string()),
+                        Some(new_source.to_string()),
+                    ));
+                }
+            }
+            (None, None) => unreachable!("names come from one of the lockfiles"),
+        }
+    }
+
+    let platforms = |lockfile: &GemfileDotLock| -> BTreeSet<String> {
+        lockfile.platforms.iter().map(Platform::to_string).collect()
+    };
+    let (old_platforms, new_platforms) = (platforms(old), platforms(new));
+    LockfileDiff {
+        gems,
+        platforms: PlatformChanges {
+            added: new_platforms.difference(&old_platforms).cloned().collect(),
+            removed: old_platforms.difference(&new_platforms).cloned().collect(),
+        },
+    }
+}
+
+fn print_text(diff: &LockfileDiff) {
+    if diff.is_empty() {
+        println!("The lockfiles lock the same gems");
+        return;
+    }
+    for gem in &diff.gems {
+        let from = gem.from.as_deref().unwrap_or_default();
+        let to = gem.to.as_deref().unwrap_or_default();
+        match gem.change {
+            Change::Added => println!("{}", format!("+ {} {to}", gem.name).green()),
+            Change::Removed => println!("{}", format!("- {} {from}", gem.name).red()),
+            Change::Upgraded => println!("{} {} {from} -> {to}", "↑".green(), gem.name),
+            Change::Downgraded => println!("{} {} {from} -> {to}", "↓".yellow(), gem.name),
+            Change::SourceChanged => println!("{} {} {from} -> {to}", "~".cyan(), gem.name),
+        }
+    }
+    for platform in &diff.platforms.added {
+        println!("{}", format!("+ platform {platform}").green());
+    }
+    for platform in &diff.platforms.removed {
+        println!("{}", format!("- platform {platform}").red());
+    }
+}
+
+/// The diff as GitHub-flavored Markdown.
+pub(crate) fn markdown(diff: &LockfileDiff) -> String {
+    if diff.is_empty() {
+        return "No changes to the locked gems.\n".to_owned();
+    }
+
+    let mut out = String::new();
+    if !diff.gems.is_empty() {
+        out.push_str("| Gem | Change | From | To |\n| --- | --- | --- | --- |\n");
+        for gem in &diff.gems {
+            let change = match gem.change {
+                Change::Added => "added",
+                Change::Removed => "removed",
+                Change::Upgraded => "upgraded",
+                Change::Downgraded => "downgraded",
+                Change::SourceChanged => "source changed",
+            };
+            let cell = |value: &Option<String>| {
+                value
+                    .as_deref()
+                    .map(|value| format!("`{value}`"))
+                    .unwrap_or_default()
+            };
+            out.push_str(&format!(
+                "| {} | {change} | {} | {} |\n",
+                gem.name,
+                cell(&gem.from),
+                cell(&gem.to)
+            ));
+        }
+    }
+    let platforms = |platforms: &[String]| {
+        platforms
+            .iter()
+            .map(|platform| format!("`{platform}`"))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    if !diff.platforms.added.is_empty() {
+        out.push_str(&format!(
+            "\n**Added platforms:** {}\n",
+            platforms(&diff.platforms.added)
+        ));
+    }
+    if !diff.platforms.removed.is_empty() {
+        out.push_str(&format!(
+            "\n**Removed platforms:** {}\n",
+            platforms(&diff.platforms.removed)
+        ));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OLD: &str = "\
+GIT
+  remote: https://github.com/rails/rails.git
+  revision: 0123456789abcdef0123456789abcdef01234567
+  specs:
+    rails (8.1.0.alpha)
+
+GEM
+  remote: https://rubygems.org/
+  specs:
+    minitest (5.25.5)
+    rack (3.1.7)
+    rake (13.3.0)
+
+PLATFORMS
+  ruby
+
+DEPENDENCIES
+  minitest
+  rack
+  rails!
+  rake
+
+BUNDLED WITH
+   2.6.9
+";
+
+    #[test]
+    fn test_diff_lockfiles() {
+        let new = OLD
+            .replace("0123456789abcdef", "fedcba9876543210")
+            .replace("    minitest (5.25.5)\n", "")
+            .replace("rack (3.1.7)", "rack (3.1.8)")
+            .replace("rake (13.3.0)", "rake (13.2.1)\n    zeitwerk (2.7.2)")
+            .replace("  ruby\n", "  arm64-darwin\n");
+        let old = rv_lockfile::parse(OLD).unwrap();
+        let new = rv_lockfile::parse(&new).unwrap();
+        let diff = diff_lockfiles(&old, &new);
+
+        let changes: Vec<_> = diff
+            .gems
+            .iter()
+            .map(|gem| {
+                (
+                    gem.name.as_str(),
+                    gem.change,
+                    gem.from.as_deref(),
+                    gem.to.as_deref(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            changes,
+            [
+                ("minitest", Change::Removed, Some("5.25.5"), None),
+                ("rack", Change::Upgraded, Some("3.1.7"), Some("3.1.8")),
+                (
+                    "rails",
+                    Change::SourceChanged,
+                    Some("https://github.com/rails/rails.git@0123456"),
+                    Some("https://github.com/rails/rails.git@fedcba9")
+                ),
+                ("rake", Change::Downgraded, Some("13.3.0"), Some("13.2.1")),
+                ("zeitwerk", Change::Added, None, Some("2.7.2")),
+            ]
+        );
+        assert_eq!(
+            diff.platforms,
+            PlatformChanges {
+                added: vec!["arm64-darwin".to_owned()],
+                removed: vec!["ruby".to_owned()],
+            }
+        );
+
+        assert_eq!(
+            markdown(&diff),
+            "\
+| Gem | Change | From | To |
+| --- | --- | --- | --- |
+| minitest | removed | `5.25.5` |  |
+| rack | upgraded | `3.1.7` | `3.1.8` |
+| rails | source changed | `https://github.com/rails/rails.git@0123456` | `https://github.com/rails/rails.git@fedcba9` |
+| rake | downgraded | `13.3.0` | `13.2.1` |
+| zeitwerk | added |  | `2.7.2` |
+
+**Added platforms:** `arm64-darwin`
+
+**Removed platforms:** `ruby`
+"
+        );
+    }
+
+    #[test]
+    fn test_diff_identical_lockfiles() {
+        let lockfile = rv_lockfile::parse(OLD).unwrap();
+        let diff = diff_lockfiles(&lockfile, &lockfile);
+        assert!(diff.is_empty());
+        assert_eq!(markdown(&diff), "No changes to the locked gems.\n");
+    }
+}
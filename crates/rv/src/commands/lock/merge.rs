@@ -0,0 +1,556 @@
+//! `rv lock --merge`, which resolves git merge conflicts in a lockfile.
+//!
+//! Both sides are merged section by section, the way git merges lines: whatever both sides agree
+//! on stays, and a change only one side made wins. Gems the two sides locked at different
+//! versions are resolved again, from the versions either side locked, preferring the higher
+//! versions that work together. Gems that nothing needs anymore are dropped.
+//!
+//! It works on a lockfile with conflict markers in it, or as a git merge driver:
+//!
+//! ```text
+//! # .gitattributes
+//! Gemfile.lock merge=rv-lock
+//!
+//! # .git/config
+//! [merge "rv-lock"]
+//!     name = rv lockfile merge
+//!     driver = rv lock --merge %O %A %B
+//! ```
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+use anstream::println;
+use camino::{Utf8Path, Utf8PathBuf};
+use owo_colors::OwoColorize;
+use rv_gem_types::ProjectDependency;
+use rv_lockfile::datatypes::{GemSection, GemfileDotLock};
+use rv_version::Version;
+
+use super::{Error, Result, parse};
+use crate::commands::clean_install::find_lockfile_path;
+use crate::commands::update;
+
+/// How the two sides locked a gem they disagreed on, and the version it was merged at.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct MergedGem {
+    pub name: String,
+    pub ours: BTreeSet<Version>,
+    pub theirs: BTreeSet<Version>,
+    pub merged: Option<Version>,
+}
+
+/// Merge the lockfile: the conflict markers in the project's lockfile when `files` is empty, or
+/// the base, ours and theirs files of a merge driver, writing the result to ours.
+pub(super) fn merge(gemfile: &Option<Utf8PathBuf>, files: &[Utf8PathBuf]) -> Result<()> {
+    let (lockfile_path, base, ours, theirs) = match files {
+        [] => {
+            let lockfile_path = find_lockfile_path(gemfile)?;
+            let raw_contents = fs_err::read_to_string(&lockfile_path)?;
+            let contents = rv_lockfile::normalize_line_endings(&raw_contents);
+            let Some((ours, theirs, base)) = split_conflicts(&contents) else {
+                println!("{} has no merge conflicts", lockfile_path.cyan());
+                return Ok(());
+            };
+            (lockfile_path, base, ours, theirs)
+        }
+        [base, ours, theirs] => {
+            let read = |path: &Utf8Path| -> Result<String> {
+                let raw_contents = fs_err::read_to_string(path)?;
+                Ok(rv_lockfile::normalize_line_endings(&raw_contents).into_owned())
+            };
+            // Git passes an empty base when both sides added the file.
+            let base = Some(read(base)?).filter(|base| !base.trim().is_empty());
+            (ours.clone(), base, read(ours)?, read(theirs)?)
+        }
+        _ => return Err(Error::MergeArgs),
+    };
+
+    let base = base
+        .as_deref()
+        .map(|base| parse(&lockfile_path, base))
+        .transpose()?;
+    let ours = parse(&lockfile_path, &ours)?;
+    let theirs = parse(&lockfile_path, &theirs)?;
+    let (mut merged, gems) = merge_lockfiles(base.as_ref(), &ours, &theirs)?;
+    merged.normalize();
+    let merged = merged.to_string();
+    let raw_contents = fs_err::read_to_string(&lockfile_path)?;
+    rv_lockfile::write_preserving_line_endings(&lockfile_path, &merged, &raw_contents)?;
+
+    let versions = |versions: &BTreeSet<Version>| {
+        versions
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    for gem in &gems {
+        let was = format!(
+            "ours {}, theirs {}",
+            versions(&gem.ours),
+            versions(&gem.theirs)
+        );
+        match &gem.merged {
+            Some(version) => println!("Merged {} at {version} ({was})", gem.name.cyan()),
+            None => println!(
+                "Removed {}, which is no longer needed ({was})",
+                gem.name.cyan()
+            ),
+        }
+    }
+    println!("Wrote {}", lockfile_path.cyan());
+
+    Ok(())
+}
+
+/// The sides of a file with git's conflict markers in it: ours, theirs, and the base, if the
+/// conflicts were written in the diff3 style. `None` if there are no conflicts.
+pub(crate) fn split_conflicts(contents: &str) -> Option<(String, String, Option<String>)> {
+    #[derive(PartialEq)]
+    enum Side {
+        Both,
+        Ours,
+        Base,
+        Theirs,
+    }
+
+    let mut side = Side::Both;
+    let (mut ours, mut theirs, mut base) = (String::new(), String::new(), String::new());
+    let mut conflicts = false;
+    let mut diff3 = false;
+    for line in contents.split_inclusive('\n') {
+        let marker = |marker: &str| {
+            line.strip_prefix(marker)
+                .is_some_and(|rest| rest.trim().is_empty() || rest.starts_with(' '))
+        };
+        if side == Side::Both && marker("<<<<<<<") {
+            side = Side::Ours;
+            conflicts = true;
+        } else if side == Side::Ours && marker("|||||||") {
+            side = Side::Base;
+            diff3 = true;
+        } else if (side == Side::Ours || side == Side::Base) && marker("=======") {
+            side = Side::Theirs;
+        } else if side == Side::Theirs && marker(">>>>>>>") {
+            side = Side::Both;
+        } else {
+            match side {
+                Side::Both => {
+                    ours.push_str(line);
+                    theirs.push_str(line);
+                    base.push_str(line);
+                }
+                Side::Ours => ours.push_str(line),
+                Side::Base => base.push_str(line),
+                Side::Theirs => theirs.push_str(line),
+            }
+        }
+    }
+    conflicts.then(|| (ours, theirs, diff3.then_some(base)))
+}
+
+/// Merge one value the way git merges a line: keep what both sides agree on, or the side that
+/// changed it from `base`. Without a base, a value only one side has is kept. `None` when both
+/// sides changed it, differently.
+fn merge_value<T: PartialEq + Clone>(
+    base: Option<Option<&T>>,
+    ours: Option<&T>,
+    theirs: Option<&T>,
+) -> Option<Option<T>> {
+    if ours == theirs {
+        return Some(ours.cloned());
+    }
+    match base {
+        Some(base) if base == ours => Some(theirs.cloned()),
+        Some(base) if base == theirs => Some(ours.cloned()),
+        Some(_) => None,
+        None => match (ours, theirs) {
+            (Some(value), None) | (None, Some(value)) => Some(Some(value.clone())),
+            _ => None,
+        },
+    }
+}
+
+/// Merge every value `key` picks out of the sides, in the order ours lists them, then theirs.
+fn merge_by_key<'a, T: PartialEq + Clone + 'a, K: PartialEq>(
+    base: Option<&'a [T]>,
+    ours: &'a [T],
+    theirs: &'a [T],
+    key: impl Fn(&T) -> K,
+    what: impl Fn(&T) -> String,
+) -> Result<Vec<T>> {
+    let mut keys: Vec<K> = Vec::new();
+    for value in ours.iter().chain(theirs) {
+        let value_key = key(value);
+        if !keys.contains(&value_key) {
+            keys.push(value_key);
+        }
+    }
+
+    let find = |values: &'a [T], wanted: &K| values.iter().find(|value| key(value) == *wanted);
+    let mut merged = Vec::new();
+    for wanted in &keys {
+        let ours = find(ours, wanted);
+        let theirs = find(theirs, wanted);
+        let base = base.map(|base| find(base, wanted));
+        match merge_value(base, ours, theirs) {
+            Some(value) => merged.extend(value),
+            None => {
+                let value = ours.or(theirs).expect("keys come from one of the sides");
+                return Err(Error::MergeConflict { what: what(value) });
+            }
+        }
+    }
+    Ok(merged)
+}
+
+/// The versions of each gem from a gem server that `lockfile` locks.
+fn gem_versions(lockfile: &GemfileDotLock) -> BTreeMap<String, BTreeSet<Version>> {
+    let mut versions: BTreeMap<String, BTreeSet<Version>> = BTreeMap::new();
+    for spec in lockfile.gem.iter().flat_map(|section| &section.specs) {
+        let tuple = &spec.release_tuple;
+        versions
+            .entry(tuple.name.clone())
+            .or_default()
+            .insert(tuple.version.clone());
+    }
+    versions
+}
+
+/// Merge two sides of a lockfile, given the lockfile they both started from, if there is one.
+/// Returns the merged lockfile and the gems the sides locked at different versions.
+pub(crate) fn merge_lockfiles<'i>(
+    base: Option<&GemfileDotLock<'i>>,
+    ours: &GemfileDotLock<'i>,
+    theirs: &GemfileDotLock<'i>,
+) -> Result<(GemfileDotLock<'i>, Vec<MergedGem>)> {
+    let mut merged = GemfileDotLock {
+        git: merge_by_key(
+            base.map(|base| base.git.as_slice()),
+            &ours.git,
+            &theirs.git,
+            |section| section.remote,
+            |section| format!("the git source {}", section.remote),
+        )?,
+        path: merge_by_key(
+            base.map(|base| base.path.as_slice()),
+            &ours.path,
+            &theirs.path,
+            |section| section.remote,
+            |section| format!("the path source {}", section.remote),
+        )?,
+        platforms: merge_by_key(
+            base.map(|base| base.platforms.as_slice()),
+            &ours.platforms,
+            &theirs.platforms,
+            Clone::clone,
+            |platform| format!("the platform {platform}"),
+        )?,
+        dependencies: merge_by_key(
+            base.map(|base| base.dependencies.as_slice()),
+            &ours.dependencies,
+            &theirs.dependencies,
+            |dep| dep.name,
+            |dep| format!("the Gemfile's requirement for {}", dep.name),
+        )?,
+        // Sides that both changed these are merged at the newer version.
+        ruby_version: merge_value(
+            base.map(|base| base.ruby_version.as_ref()),
+            ours.ruby_version.as_ref(),
+            theirs.ruby_version.as_ref(),
+        )
+        .unwrap_or_else(|| {
+            [&ours.ruby_version, &theirs.ruby_version]
+                .into_iter()
+                .flatten()
+                .max_by(|a, b| a.cruby_version.cmp(&b.cruby_version))
+                .cloned()
+        }),
+        bundled_with: merge_value(
+            base.map(|base| base.bundled_with.as_ref()),
+            ours.bundled_with.as_ref(),
+            theirs.bundled_with.as_ref(),
+        )
+        .unwrap_or_else(|| {
+            [&ours.bundled_with, &theirs.bundled_with]
+                .into_iter()
+                .flatten()
+                .max_by(|a, b| a.bundler_version.cmp(&b.bundler_version))
+                .cloned()
+        }),
+        checksums: match (&ours.checksums, &theirs.checksums) {
+            (None, None) => None,
+            (ours, theirs) => Some(
+                ours.iter()
+                    .chain(theirs)
+                    .flatten()
+                    .cloned()
+                    .collect::<Vec<_>>(),
+            ),
+        },
+        gem: Vec::new(),
+    };
+
+    // Every gem either side locked from a gem server is a candidate, in the section it was
+    // locked in.
+    for section in ours.gem.iter().chain(&theirs.gem) {
+        let index = match merged
+            .gem
+            .iter()
+            .position(|merged| merged.remote == section.remote)
+        {
+            Some(index) => index,
+            None => {
+                merged.gem.push(GemSection {
+                    remote: section.remote,
+                    specs: Vec::new(),
+                });
+                merged.gem.len() - 1
+            }
+        };
+        for spec in &section.specs {
+            let locked = merged
+                .gem
+                .iter()
+                .flat_map(|section| &section.specs)
+                .any(|locked| locked.release_tuple == spec.release_tuple);
+            if !locked {
+                merged.gem[index].specs.push(spec.clone());
+            }
+        }
+    }
+
+    let chosen = resolve(&merged)?;
+    for section in &mut merged.gem {
+        section.specs.retain(|spec| {
+            let tuple = &spec.release_tuple;
+            chosen.get(&tuple.name) == Some(&tuple.version)
+        });
+    }
+    merged.gem.retain(|section| !section.specs.is_empty());
+    if let Some(checksums) = &mut merged.checksums {
+        let locked: HashSet<_> = merged
+            .gem
+            .iter()
+            .flat_map(|section| &section.specs)
+            .chain(merged.git.iter().flat_map(|section| &section.specs))
+            .chain(merged.path.iter().flat_map(|section| &section.specs))
+            .map(|spec| &spec.release_tuple)
+            .collect();
+        checksums.retain(|checksum| locked.contains(&checksum.release_tuple));
+    }
+
+    let ours_versions = gem_versions(ours);
+    let theirs_versions = gem_versions(theirs);
+    let gems = ours_versions
+        .into_iter()
+        .filter_map(|(name, ours)| {
+            let theirs = theirs_versions.get(&name)?;
+            (ours != *theirs).then(|| MergedGem {
+                merged: chosen.get(&name).cloned(),
+                theirs: theirs.clone(),
+                name,
+                ours,
+            })
+        })
+        .collect();
+
+    Ok((merged, gems))
+}
+
+/// The version of each gem the merged lockfile locks, out of the versions either side locked.
+fn resolve(merged: &GemfileDotLock) -> Result<HashMap<String, Version>> {
+    let specs: Vec<_> = merged
+        .gem
+        .iter()
+        .flat_map(|section| &section.specs)
+        .chain(merged.git.iter().flat_map(|section| &section.specs))
+        .chain(merged.path.iter().flat_map(|section| &section.specs))
+        .collect();
+    let dependencies = merged
+        .dependencies
+        .iter()
+        // Bundler doesn't lock gems that are only for platforms the lockfile isn't for.
+        .filter(|dep| specs.iter().any(|spec| spec.release_tuple.name == dep.name))
+        .map(|dep| ProjectDependency {
+            name: dep.name.to_string(),
+            requirement: dep.requirement.clone(),
+        })
+        .collect();
+    let locked = update::locked_releases(merged, &HashSet::new());
+    let resolved = crate::resolver::solve_project(dependencies, locked)
+        .map_err(|err| Error::CouldNotMerge(crate::resolver::explain(&err)))?;
+    let mut chosen: HashMap<String, Version> = resolved
+        .into_iter()
+        .map(|(tuple, _)| (tuple.name, tuple.version))
+        .collect();
+
+    // The resolver picks one platform of each gem, but the lockfile keeps every platform of the
+    // version, and some platforms depend on gems others don't, like the generic platform of a
+    // precompiled gem on the gems it compiles with. Lock those at the newest version that fits.
+    loop {
+        let mut needed = Vec::new();
+        for spec in &specs {
+            if chosen.get(&spec.release_tuple.name) != Some(&spec.release_tuple.version) {
+                continue;
+            }
+            for dep in spec
+                .deps
+                .iter()
+                .filter(|dep| !chosen.contains_key(&dep.name))
+            {
+                let version = specs
+                    .iter()
+                    .map(|spec| &spec.release_tuple)
+                    .filter(|tuple| {
+                        tuple.name == dep.name && dep.requirement.satisfied_by(&tuple.version)
+                    })
+                    .map(|tuple| tuple.version.clone())
+                    .max();
+                if let Some(version) = version {
+                    needed.push((dep.name.clone(), version));
+                }
+            }
+        }
+        if needed.is_empty() {
+            break;
+        }
+        for (name, version) in needed {
+            chosen
+                .entry(name)
+                .and_modify(|chosen| *chosen = version.clone().max(chosen.clone()))
+                .or_insert(version);
+        }
+    }
+
+    Ok(chosen)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: &str = "\
+GEM
+  remote: https://rubygems.org/
+  specs:
+    rack (3.1.7)
+    rails (7.1.0)
+      rack (>= 2.2)
+    rake (13.2.0)
+
+PLATFORMS
+  ruby
+
+DEPENDENCIES
+  rails (~> 7.1)
+  rake
+
+BUNDLED WITH
+   2.6.9
+";
+
+    fn parse(contents: &str) -> GemfileDotLock<'_> {
+        rv_lockfile::parse(contents).unwrap()
+    }
+
+    #[test]
+    fn test_merge_value() {
+        let (a, b, c) = (1, 2, 3);
+        assert_eq!(merge_value(None, Some(&a), Some(&a)), Some(Some(a)));
+        assert_eq!(
+            merge_value(Some(Some(&a)), Some(&a), Some(&b)),
+            Some(Some(b))
+        );
+        assert_eq!(merge_value(Some(Some(&a)), None, Some(&a)), Some(None));
+        assert_eq!(merge_value(None, None, Some(&b)), Some(Some(b)));
+        assert_eq!(merge_value(Some(Some(&a)), Some(&b), Some(&c)), None);
+        assert_eq!(merge_value(None, Some(&b), Some(&c)), None);
+    }
+
+    #[test]
+    fn test_split_conflicts() {
+        let contents = "\
+a
+<<<<<<< HEAD
+ours
+||||||| base
+base
+=======
+theirs
+>>>>>>> feature
+b
+";
+        assert_eq!(
+            split_conflicts(contents),
+            Some((
+                "a\nours\nb\n".to_string(),
+                "a\ntheirs\nb\n".to_string(),
+                Some("a\nbase\nb\n".to_string())
+            ))
+        );
+        assert_eq!(split_conflicts("a\nb\n"), None);
+    }
+
+    #[test]
+    fn test_merge_lockfiles_prefers_higher_versions() {
+        let ours = BASE.replace("rack (3.1.7)", "rack (3.1.8)");
+        let theirs = BASE
+            .replace("rack (3.1.7)", "rack (3.1.9)")
+            .replace("rake (13.2.0)", "rake (13.3.0)")
+            .replace("  ruby\n", "  ruby\n  x86_64-linux\n");
+        let base = parse(BASE);
+        let (mut merged, gems) =
+            merge_lockfiles(Some(&base), &parse(&ours), &parse(&theirs)).unwrap();
+        merged.normalize();
+
+        let merged = merged.to_string();
+        assert!(merged.contains("    rack (3.1.9)\n"));
+        assert!(!merged.contains("rack (3.1.8)"));
+        assert!(merged.contains("    rake (13.3.0)\n"));
+        assert!(merged.contains("  x86_64-linux\n"));
+        assert_eq!(
+            gems.iter()
+                .map(|gem| (
+                    gem.name.as_str(),
+                    gem.merged.as_ref().map(ToString::to_string)
+                ))
+                .collect::<Vec<_>>(),
+            [
+                ("rack", Some("3.1.9".to_string())),
+                ("rake", Some("13.3.0".to_string()))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_merge_lockfiles_keeps_compatible_versions_and_drops_unneeded_gems() {
+        // Theirs needs a rails that pins rack below what ours locked, so ours' newer rack can't
+        // be used.
+        let ours = BASE.replace("rack (3.1.7)", "rack (3.1.8)");
+        let theirs = BASE
+            .replace("      rack (>= 2.2)\n", "      rack (< 3.1.8)\n")
+            .replace("  rails (~> 7.1)\n", "  rails (~> 7.1.1)\n")
+            .replace("rails (7.1.0)", "rails (7.1.1)")
+            .replace("    rake (13.2.0)\n", "")
+            .replace("  rake\n", "");
+        let base = parse(BASE);
+        let (merged, _) = merge_lockfiles(Some(&base), &parse(&ours), &parse(&theirs)).unwrap();
+
+        let merged = merged.to_string();
+        assert!(merged.contains("    rack (3.1.7)\n"));
+        assert!(merged.contains("    rails (7.1.1)\n"));
+        assert!(!merged.contains("rake"));
+    }
+
+    #[test]
+    fn test_merge_lockfiles_reports_conflicting_requirements() {
+        let ours = BASE.replace("  rake\n", "  rake (~> 13.2)\n");
+        let theirs = BASE.replace("  rake\n", "  rake (>= 13)\n");
+        let base = parse(BASE);
+        let err = merge_lockfiles(Some(&base), &parse(&ours), &parse(&theirs)).unwrap_err();
+        assert!(
+            matches!(err, Error::MergeConflict { what } if what == "the Gemfile's requirement for rake")
+        );
+    }
+}
@@ -0,0 +1,287 @@
+//! `rv lock parse --json`, which prints everything rv reads from a lockfile, so that other tools
+//! can use rv's parser without linking it.
+//!
+//! Versions, requirements and platforms are strings, written the way the lockfile writes them,
+//! and everything is in the order the lockfile lists it, so the same lockfile always prints the
+//! same JSON.
+
+use std::collections::BTreeMap;
+
+use anstream::println;
+use camino::Utf8PathBuf;
+use rv_gem_types::ReleaseTuple;
+use rv_gem_types::requirement::Requirement;
+use rv_lockfile::datatypes::{GemfileDotLock, Spec};
+use serde::Serialize;
+
+use super::{Result, parse};
+use crate::commands::clean_install::find_lockfile_path;
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub(crate) struct LockfileSnapshot {
+    /// The GIT, PATH and GEM sections, in the order Bundler writes them.
+    pub sources: Vec<SourceSnapshot>,
+    pub platforms: Vec<String>,
+    pub dependencies: Vec<DependencySnapshot>,
+    /// Like `ruby 3.4.1p0`.
+    pub ruby_version: Option<String>,
+    pub bundled_with: Option<String>,
+    /// `None` if the lockfile has no CHECKSUMS section.
+    pub checksums: Option<Vec<ChecksumSnapshot>>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub(crate) enum SourceSnapshot {
+    Git {
+        remote: String,
+        revision: String,
+        branch: Option<String>,
+        #[serde(rename = "ref")]
+        git_ref: Option<String>,
+        tag: Option<String>,
+        submodules: Option<bool>,
+        glob: Option<String>,
+        specs: Vec<SpecSnapshot>,
+    },
+    Path {
+        remote: String,
+        specs: Vec<SpecSnapshot>,
+    },
+    Gem {
+        remote: Option<String>,
+        specs: Vec<SpecSnapshot>,
+    },
+}
+
+/// A locked gem and the gems it depends on.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub(crate) struct SpecSnapshot {
+    pub name: String,
+    pub version: String,
+    pub platform: String,
+    pub dependencies: Vec<RequirementSnapshot>,
+}
+
+/// A gem that a locked gem depends on.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub(crate) struct RequirementSnapshot {
+    pub name: String,
+    /// Empty when any version will do.
+    pub requirements: Vec<String>,
+}
+
+/// A gem from the DEPENDENCIES section.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub(crate) struct DependencySnapshot {
+    pub name: String,
+    /// Empty when any version will do.
+    pub requirements: Vec<String>,
+    /// Whether it comes from a source other than a gem server, written with a `!`.
+    pub pinned: bool,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub(crate) struct ChecksumSnapshot {
+    pub name: String,
+    pub version: String,
+    pub platform: String,
+    /// Hex digests by algorithm, like `sha256`. Empty for gems locked without a checksum.
+    pub digests: BTreeMap<String, String>,
+}
+
+pub(super) fn print_snapshot(gemfile: &Option<Utf8PathBuf>) -> Result<()> {
+    let lockfile_path = find_lockfile_path(gemfile)?;
+    let contents = fs_err::read_to_string(&lockfile_path)?;
+    let contents = rv_lockfile::normalize_line_endings(&contents);
+    let lockfile = parse(&lockfile_path, &contents)?;
+    println!("{}", serde_json::to_string_pretty(&snapshot(&lockfile))?);
+    Ok(())
+}
+
+pub(crate) fn snapshot(lockfile: &GemfileDotLock) -> LockfileSnapshot {
+    let git = lockfile.git.iter().map(|section| SourceSnapshot::Git {
+        remote: section.remote.to_string(),
+        revision: section.revision.to_string(),
+        branch: section.branch.map(str::to_string),
+        git_ref: section.git_ref.map(str::to_string),
+        tag: section.tag.map(str::to_string),
+        submodules: section.submodules,
+        glob: section.glob.map(str::to_string),
+        specs: specs(&section.specs),
+    });
+    let path = lockfile.path.iter().map(|section| SourceSnapshot::Path {
+        remote: section.remote.to_string(),
+        specs: specs(&section.specs),
+    });
+    let gem = lockfile.gem.iter().map(|section| SourceSnapshot::Gem {
+        remote: section.remote.map(str::to_string),
+        specs: specs(&section.specs),
+    });
+
+    LockfileSnapshot {
+        sources: git.chain(path).chain(gem).collect(),
+        platforms: lockfile.platforms.iter().map(ToString::to_string).collect(),
+        dependencies: lockfile
+            .dependencies
+            .iter()
+            .map(|dependency| DependencySnapshot {
+                name: dependency.name.to_string(),
+                requirements: requirements(&dependency.requirement),
+                pinned: dependency.nonstandard,
+            })
+            .collect(),
+        ruby_version: lockfile
+            .ruby_version
+            .as_ref()
+            .map(|section| section.to_string().trim().to_string()),
+        bundled_with: lockfile
+            .bundled_with
+            .as_ref()
+            .map(|section| section.bundler_version.to_string()),
+        checksums: lockfile.checksums.as_ref().map(|checksums| {
+            checksums
+                .iter()
+                .map(|checksum| {
+                    let (name, version, platform) = tuple_parts(&checksum.release_tuple);
+                    ChecksumSnapshot {
+                        name,
+                        version,
+                        platform,
+                        digests: checksum
+                            .digests
+                            .iter()
+                            .map(|digest| {
+                                (digest.algorithm.to_string(), hex::encode(&digest.value))
+                            })
+                            .collect(),
+                    }
+                })
+                .collect()
+        }),
+    }
+}
+
+fn specs(specs: &[Spec]) -> Vec<SpecSnapshot> {
+    specs
+        .iter()
+        .map(|spec| {
+            let (name, version, platform) = tuple_parts(&spec.release_tuple);
+            SpecSnapshot {
+                name,
+                version,
+                platform,
+                dependencies: spec
+                    .deps
+                    .iter()
+                    .map(|dep| RequirementSnapshot {
+                        name: dep.name.clone(),
+                        requirements: requirements(&dep.requirement),
+                    })
+                    .collect(),
+            }
+        })
+        .collect()
+}
+
+fn tuple_parts(tuple: &ReleaseTuple) -> (String, String, String) {
+    (
+        tuple.name.clone(),
+        tuple.version.to_string(),
+        tuple.platform.to_string(),
+    )
+}
+
+fn requirements(requirement: &Requirement) -> Vec<String> {
+    if requirement.is_latest_version() {
+        return vec![];
+    }
+    requirement
+        .constraints
+        .iter()
+        .map(ToString::to_string)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot() {
+        let lockfile = "\
+GIT
+  remote: https://github.com/rails/rails.git
+  revision: 0123456789abcdef0123456789abcdef01234567
+  branch: main
+  specs:
+    rails (8.1.0.alpha)
+      rack (>= 2.2.4, < 4)
+
+GEM
+  remote: https://rubygems.org/
+  specs:
+    nokogiri (1.18.9-x86_64-linux-gnu)
+    rack (3.1.7)
+
+PLATFORMS
+  ruby
+  x86_64-linux-gnu
+
+DEPENDENCIES
+  nokogiri (~> 1.18)
+  rails!
+
+CHECKSUMS
+  nokogiri (1.18.9-x86_64-linux-gnu) sha256=2a
+  rack (3.1.7)
+
+BUNDLED WITH
+   2.6.9
+";
+        let lockfile = rv_lockfile::parse(lockfile).unwrap();
+        let json = serde_json::to_value(snapshot(&lockfile)).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "sources": [
+                    {
+                        "type": "git",
+                        "remote": "https://github.com/rails/rails.git",
+                        "revision": "0123456789abcdef0123456789abcdef01234567",
+                        "branch": "main",
+                        "ref": null,
+                        "tag": null,
+                        "submodules": null,
+                        "glob": null,
+                        "specs": [{
+                            "name": "rails",
+                            "version": "8.1.0.alpha",
+                            "platform": "ruby",
+                            "dependencies": [{"name": "rack", "requirements": [">= 2.2.4", "< 4"]}],
+                        }],
+                    },
+                    {
+                        "type": "gem",
+                        "remote": "https://rubygems.org/",
+                        "specs": [
+                            {"name": "nokogiri", "version": "1.18.9", "platform": "x86_64-linux-gnu", "dependencies": []},
+                            {"name": "rack", "version": "3.1.7", "platform": "ruby", "dependencies": []},
+                        ],
+                    },
+                ],
+                "platforms": ["ruby", "x86_64-linux-gnu"],
+                "dependencies": [
+                    {"name": "nokogiri", "requirements": ["~> 1.18"], "pinned": false},
+                    {"name": "rails", "requirements": [], "pinned": true},
+                ],
+                "ruby_version": null,
+                "bundled_with": "2.6.9",
+                "checksums": [
+                    {"name": "nokogiri", "version": "1.18.9", "platform": "x86_64-linux-gnu", "digests": {"sha256": "2a"}},
+                    {"name": "rack", "version": "3.1.7", "platform": "ruby", "digests": {}},
+                ],
+            })
+        );
+    }
+}
@@ -0,0 +1,275 @@
+use std::borrow::Cow;
+use std::process::{Command, ExitStatus, Output, Stdio};
+use std::time::{Duration, Instant};
+
+use anstream::{eprint, eprintln, print, println};
+use camino::Utf8PathBuf;
+use clap::Args;
+use owo_colors::OwoColorize;
+use rv_ruby::request::RubyRequest;
+use tabled::{Table, settings::Style};
+use tracing::debug;
+
+use crate::GlobalArgs;
+use crate::commands::clean_install::format_duration;
+use crate::commands::ruby::install::{InstallDir, InstallLayout, install};
+use crate::commands::run::{Invocation, Program, prepare_command};
+use crate::config::Config;
+use crate::failure_report::{Failure, Phase, ReportArgs, TestCase};
+
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+pub enum Error {
+    #[error(transparent)]
+    #[diagnostic(code(RV7001))]
+    IoError(#[from] std::io::Error),
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    ConfigError(#[from] crate::config::Error),
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    InstallError(#[from] crate::commands::ruby::install::Error),
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    RunError(#[from] crate::commands::run::Error),
+    #[error("No installed Ruby matches {0}")]
+    #[diagnostic(code(RV7002))]
+    NoMatchingRuby(RubyRequest),
+    #[error("The command failed with {} of {total} rubies: {}", rubies.len(), rubies.join(", "))]
+    #[diagnostic(code(RV7003))]
+    Failures { rubies: Vec<String>, total: usize },
+}
+
+type Result<T> = miette::Result<T, Error>;
+
+#[derive(Args)]
+pub struct MatrixArgs {
+    /// Rubies to run the command with, separated by commas, e.g. `3.2,3.3,3.4`
+    #[arg(long, value_delimiter = ',', required = true)]
+    pub ruby: Vec<RubyRequest>,
+
+    /// Run the command with every Ruby at the same time. Each run's output is printed when it
+    /// finishes, so that outputs don't get mixed up.
+    #[arg(long)]
+    pub parallel: bool,
+
+    /// By default, rv will install any Ruby that is needed.
+    /// If this flag is given, rv will exit with an error instead of installing.
+    #[arg(long)]
+    pub no_install: bool,
+
+    #[command(flatten)]
+    pub report: ReportArgs,
+
+    /// What to run with each Ruby, e.g. `rake test`
+    #[arg(trailing_var_arg = true, allow_hyphen_values = true, required = true, value_names = ["COMMAND", "ARGS"])]
+    pub args: Vec<String>,
+}
+
+/// The outcome of running the command with one Ruby.
+struct MatrixResult {
+    ruby: String,
+    status: ExitStatus,
+    duration: Duration,
+    /// The command's output, if it was captured rather than printed as it ran.
+    output: Option<String>,
+}
+
+impl MatrixResult {
+    fn result(&self) -> Cow<'_, str> {
+        if self.status.success() {
+            return "pass".into();
+        }
+        match self.status.code() {
+            Some(code) => format!("fail (exit code {code})").into(),
+            None => "fail (killed)".into(),
+        }
+    }
+
+    fn test_case(&self) -> TestCase {
+        let failure = (!self.status.success()).then(|| {
+            let message = match self.status.code() {
+                Some(code) => format!("The command exited with code {code}"),
+                None => "The command was killed".to_string(),
+            };
+            let failure = Failure::new(Phase::Run, message);
+            match &self.output {
+                Some(output) => failure.with_log(output),
+                None => failure,
+            }
+        });
+        TestCase {
+            name: self.ruby.clone(),
+            duration: Some(self.duration),
+            failure,
+        }
+    }
+}
+
+impl tabled::Tabled for MatrixResult {
+    const LENGTH: usize = 3;
+
+    fn fields(&self) -> Vec<Cow<'_, str>> {
+        vec![
+            Cow::Borrowed(&self.ruby),
+            self.result(),
+            Cow::Owned(format_duration(self.duration)),
+        ]
+    }
+
+    fn headers() -> Vec<Cow<'static, str>> {
+        vec!["Ruby".into(), "Result".into(), "Time".into()]
+    }
+}
+
+pub(crate) async fn matrix(global_args: &GlobalArgs, args: MatrixArgs) -> Result<()> {
+    let (program, cmd_args) = args.args.split_first().unwrap();
+
+    // Make sure every Ruby is installed before running anything, so that runs don't wait on
+    // each other's downloads.
+    let mut commands = Vec::with_capacity(args.ruby.len());
+    for request in &args.ruby {
+        let (name, cmd) =
+            prepare_for_ruby(global_args, request, args.no_install, program, cmd_args).await?;
+        commands.push((name, cmd));
+    }
+
+    let results = if args.parallel {
+        run_parallel(commands)?
+    } else {
+        run_sequential(commands)?
+    };
+
+    let failed: Vec<_> = results
+        .iter()
+        .filter(|result| !result.status.success())
+        .map(|result| result.ruby.clone())
+        .collect();
+    let total = results.len();
+    let cases: Vec<_> = results.iter().map(MatrixResult::test_case).collect();
+
+    let mut table = Table::new(results);
+    table.with(Style::sharp());
+    println!("{table}");
+    args.report.emit("rv matrix", &cases)?;
+
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::Failures {
+            rubies: failed,
+            total,
+        })
+    }
+}
+
+/// Find (or install) the Ruby matching `request`, and prepare the command to run with it.
+/// Every Ruby gets its own GEM_HOME, so gems built for one never leak into another.
+async fn prepare_for_ruby(
+    global_args: &GlobalArgs,
+    request: &RubyRequest,
+    no_install: bool,
+    program: &str,
+    args: &[String],
+) -> Result<(String, Command)> {
+    let mut config = Config::with_settings(global_args, Some(request.clone()))?;
+
+    if config.current_ruby().is_none() {
+        if no_install {
+            return Err(Error::NoMatchingRuby(request.clone()));
+        }
+        debug!("Ruby not found, so installing {request}");
+        install(
+            global_args,
+            InstallDir::Default,
+            Some(request.clone()),
+            None,
+            false,
+            false,
+            InstallLayout::Rv,
+        )
+        .await?;
+        config = Config::with_settings(global_args, Some(request.clone()))?;
+    }
+
+    let ruby = config
+        .current_ruby()
+        .ok_or_else(|| Error::NoMatchingRuby(request.clone()))?;
+    let gem_home = config.gem_home(&ruby);
+
+    let invocation = Invocation {
+        program: Program::Tool {
+            executable_path: Utf8PathBuf::from(program),
+            extra_paths: vec![gem_home.join("bin").into()],
+        },
+        env: vec![
+            ("GEM_HOME".to_string(), gem_home.to_string()),
+            ("GEM_PATH".to_string(), gem_home.to_string()),
+        ],
+        isolated: false,
+    };
+    let cmd = prepare_command(invocation, &config, args.to_vec(), None)?;
+
+    Ok((ruby.version.to_string(), cmd))
+}
+
+fn run_sequential(commands: Vec<(String, Command)>) -> Result<Vec<MatrixResult>> {
+    let mut results = Vec::with_capacity(commands.len());
+    for (ruby, mut cmd) in commands {
+        eprintln!("{} {}", "==>".cyan(), ruby.cyan());
+        debug!("Running command: {:?}", cmd);
+
+        let started = Instant::now();
+        let status = cmd.status()?;
+        results.push(MatrixResult {
+            ruby,
+            status,
+            duration: started.elapsed(),
+            output: None,
+        });
+    }
+    Ok(results)
+}
+
+fn run_parallel(commands: Vec<(String, Command)>) -> Result<Vec<MatrixResult>> {
+    std::thread::scope(|scope| {
+        let runs: Vec<_> = commands
+            .into_iter()
+            .map(|(ruby, mut cmd)| {
+                debug!("Running command: {:?}", cmd);
+                scope.spawn(move || {
+                    let started = Instant::now();
+                    let output = cmd
+                        .stdin(Stdio::null())
+                        .stdout(Stdio::piped())
+                        .stderr(Stdio::piped())
+                        .output();
+                    (ruby, output, started.elapsed())
+                })
+            })
+            .collect();
+
+        let mut results = Vec::with_capacity(runs.len());
+        for run in runs {
+            let (ruby, output, duration) = run.join().expect("matrix run panicked");
+            let Output {
+                status,
+                stdout,
+                stderr,
+            } = output?;
+
+            let stdout = String::from_utf8_lossy(&stdout);
+            let stderr = String::from_utf8_lossy(&stderr);
+            eprintln!("{} {}", "==>".cyan(), ruby.cyan());
+            print!("{stdout}");
+            eprint!("{stderr}");
+
+            results.push(MatrixResult {
+                ruby,
+                status,
+                duration,
+                output: Some(format!("{stdout}{stderr}")),
+            });
+        }
+        Ok(results)
+    })
+}
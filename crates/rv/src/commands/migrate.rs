@@ -0,0 +1,190 @@
+//! `rv migrate`: moves what older setups left behind into the places rv uses now.
+
+use anstream::println;
+use camino::{Utf8Path, Utf8PathBuf};
+use clap::{Args, Subcommand};
+use owo_colors::OwoColorize;
+use rv_ruby::gem_home::{GemHomeLayout, gem_homes_in};
+
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+pub enum Error {
+    #[error(transparent)]
+    #[diagnostic(code(RV8101))]
+    IoError(#[from] std::io::Error),
+}
+
+type Result<T> = miette::Result<T, Error>;
+
+#[derive(Args)]
+pub struct MigrateArgs {
+    #[command(subcommand)]
+    pub command: MigrateCommand,
+}
+
+#[derive(Subcommand)]
+pub enum MigrateCommand {
+    #[command(
+        about = "Move gems installed with `gem install --user-install` from ~/.gem into rv's gem directory"
+    )]
+    GemHome(GemHomeArgs),
+}
+
+#[derive(Args)]
+pub struct GemHomeArgs {
+    /// Keep using the gems in ~/.gem, and remember that, instead of moving them
+    #[arg(long, conflicts_with = "dry_run")]
+    keep_legacy: bool,
+
+    /// Show what would be moved, without moving anything
+    #[arg(long)]
+    dry_run: bool,
+}
+
+pub(crate) fn migrate(args: MigrateArgs) -> Result<()> {
+    match args.command {
+        MigrateCommand::GemHome(args) => gem_home(args),
+    }
+}
+
+fn gem_home(args: GemHomeArgs) -> Result<()> {
+    let legacy_dir = GemHomeLayout::Legacy.gems_dir();
+    let rv_dir = GemHomeLayout::Rv.gems_dir();
+
+    if args.keep_legacy {
+        GemHomeLayout::Legacy.choose()?;
+        println!(
+            "rv will keep installing user gems into {}",
+            rv_dirs::unexpand(&legacy_dir).cyan()
+        );
+        return Ok(());
+    }
+
+    let gem_homes = gem_homes_in(&legacy_dir);
+    if gem_homes.is_empty() {
+        println!(
+            "There are no gems in {} to move",
+            rv_dirs::unexpand(&legacy_dir)
+        );
+    }
+
+    for (scope, legacy_home) in &gem_homes {
+        let rv_home = rv_dir.join(scope);
+        let mut left = Vec::new();
+        move_merged(legacy_home, &rv_home, args.dry_run, &mut left)?;
+        println!(
+            "{} {} to {}",
+            if args.dry_run { "Would move" } else { "Moved" },
+            rv_dirs::unexpand(legacy_home).cyan(),
+            rv_dirs::unexpand(&rv_home).cyan()
+        );
+        for path in left {
+            println!(
+                "  {} {}, since rv's gem directory has it already",
+                "Left".yellow(),
+                rv_dirs::unexpand(&path)
+            );
+        }
+    }
+
+    if !args.dry_run {
+        GemHomeLayout::Rv.choose()?;
+        println!(
+            "rv will install user gems into {} from now on",
+            rv_dirs::unexpand(&rv_dir).cyan()
+        );
+    }
+    Ok(())
+}
+
+/// Move `src` to `dest`. If both are directories, move everything in `src` that isn't in `dest`
+/// already, the same way, and add what couldn't be moved to `left`.
+fn move_merged(
+    src: &Utf8Path,
+    dest: &Utf8Path,
+    dry_run: bool,
+    left: &mut Vec<Utf8PathBuf>,
+) -> Result<()> {
+    if dest.symlink_metadata().is_err() {
+        if !dry_run {
+            if let Some(parent) = dest.parent() {
+                fs_err::create_dir_all(parent)?;
+            }
+            fs_err::rename(src, dest)?;
+        }
+        return Ok(());
+    }
+
+    if !(src.is_dir() && dest.is_dir()) {
+        left.push(src.to_owned());
+        return Ok(());
+    }
+
+    let mut entries: Vec<Utf8PathBuf> = src
+        .read_dir_utf8()?
+        .map(|entry| entry.map(|entry| entry.into_path()))
+        .collect::<std::io::Result<_>>()?;
+    entries.sort();
+    for entry in entries {
+        let Some(name) = entry.file_name() else {
+            continue;
+        };
+        move_merged(&entry, &dest.join(name), dry_run, left)?;
+    }
+
+    // Anything left behind keeps the directory from being removed.
+    if !dry_run && left.iter().all(|path| !path.starts_with(src)) {
+        fs_err::remove_dir(src)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(path: &Utf8Path, contents: &str) {
+        fs_err::create_dir_all(path.parent().unwrap()).unwrap();
+        fs_err::write(path, contents).unwrap();
+    }
+
+    #[test]
+    fn test_move_merged() {
+        let temp_dir = camino_tempfile::Utf8TempDir::new().unwrap();
+        let src = temp_dir.path().join(".gem/ruby/3.3.0");
+        let dest = temp_dir.path().join("rv/gems/ruby/3.3.0");
+        write(&src.join("gems/rake-13.3.0/README"), "legacy");
+        write(&src.join("specifications/rake-13.3.0.gemspec"), "legacy");
+        write(&src.join("bin/rake"), "legacy");
+        write(&dest.join("bin/rake"), "rv");
+        write(&dest.join("gems/rspec-3.13.0/README"), "rv");
+
+        let mut left = Vec::new();
+        move_merged(&src, &dest, true, &mut left).unwrap();
+        assert_eq!(left, vec![src.join("bin/rake")]);
+        assert!(src.join("gems/rake-13.3.0/README").exists());
+
+        let mut left = Vec::new();
+        move_merged(&src, &dest, false, &mut left).unwrap();
+        assert_eq!(left, vec![src.join("bin/rake")]);
+        assert_eq!(
+            fs_err::read_to_string(dest.join("gems/rake-13.3.0/README")).unwrap(),
+            "legacy"
+        );
+        assert!(dest.join("specifications/rake-13.3.0.gemspec").exists());
+        assert!(dest.join("gems/rspec-3.13.0/README").exists());
+        assert_eq!(fs_err::read_to_string(dest.join("bin/rake")).unwrap(), "rv");
+        assert!(!src.join("gems").exists());
+        assert!(src.join("bin/rake").exists());
+
+        // Once nothing conflicts, the legacy directory is gone entirely.
+        fs_err::remove_file(dest.join("bin/rake")).unwrap();
+        let mut left = Vec::new();
+        move_merged(&src, &dest, false, &mut left).unwrap();
+        assert!(left.is_empty());
+        assert!(!src.exists());
+        assert_eq!(
+            fs_err::read_to_string(dest.join("bin/rake")).unwrap(),
+            "legacy"
+        );
+    }
+}
@@ -0,0 +1,74 @@
+use anstream::println;
+use camino::Utf8PathBuf;
+use clap::Args;
+use owo_colors::OwoColorize;
+
+use crate::GlobalArgs;
+use crate::commands::clean_install::{find_lockfile_path, gemfile_path, prune_stale_gems};
+use crate::config::Config;
+
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+pub enum Error {
+    #[error(transparent)]
+    #[diagnostic(code(RV7801))]
+    IoError(#[from] std::io::Error),
+    #[error(transparent)]
+    #[diagnostic(code(RV7802))]
+    Parse(
+        #[from]
+        #[diagnostic_source]
+        rv_lockfile::ParseErrors,
+    ),
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    ConfigError(#[from] crate::config::Error),
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    CiError(#[from] crate::commands::clean_install::Error),
+}
+
+type Result<T> = miette::Result<T, Error>;
+
+#[derive(Args)]
+pub struct PruneArgs {
+    /// Path to Gemfile
+    #[arg(long, env = "BUNDLE_GEMFILE")]
+    gemfile: Option<Utf8PathBuf>,
+
+    /// Show which gems would be removed, without removing anything
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+pub(crate) fn prune(global_args: &GlobalArgs, args: PruneArgs) -> Result<()> {
+    let config = Config::with_settings(global_args, None)?;
+    let Some(ruby) = config.current_ruby() else {
+        println!("No Ruby is installed, so there are no gems to prune");
+        return Ok(());
+    };
+
+    let lockfile_path = find_lockfile_path(&gemfile_path(&config, args.gemfile.as_deref()))?;
+    let contents = fs_err::read_to_string(&lockfile_path)?;
+    let contents = rv_lockfile::normalize_line_endings(&contents);
+    let lockfile = rv_lockfile::parse(&contents)?;
+
+    let stale = prune_stale_gems(&config, &ruby, &lockfile, args.dry_run)?;
+    if stale.is_empty() {
+        println!("Every installed gem is in {}", lockfile_path.cyan());
+        return Ok(());
+    }
+
+    for full_name in &stale {
+        println!(
+            "{} {}",
+            if args.dry_run {
+                "Would remove"
+            } else {
+                "Removed"
+            },
+            full_name.cyan()
+        );
+    }
+
+    Ok(())
+}
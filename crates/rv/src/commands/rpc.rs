@@ -0,0 +1,512 @@
+//! A JSON-RPC 2.0 server for editors and other tools that need to know which Ruby applies to a
+//! file, without starting rv for every question.
+//!
+//! Messages are JSON objects, one per line, on stdin and stdout. Every directory asked about with
+//! `resolveRuby` or `envForDir` is watched: when one of the files that pin its Ruby changes, or a
+//! Ruby is installed or removed, the server re-resolves it and sends a `rubyChanged` notification
+//! if the answer is different.
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, BufRead, Write};
+use std::sync::Mutex;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::{Duration, SystemTime};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use clap::Args;
+use rv_ruby::Ruby;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use serde_json::Value;
+use tracing::debug;
+
+use crate::GlobalArgs;
+use crate::commands;
+use crate::commands::clean_install::gemfile_for_lockfile;
+use crate::commands::lock::{self, DiffLine};
+use crate::config::{Config, gemfile};
+use crate::error_format::JsonError;
+
+#[derive(Args)]
+pub struct RpcArgs {
+    /// Serve requests on stdin, answering on stdout, one JSON-RPC message per line
+    #[arg(long, required = true)]
+    pub stdio: bool,
+
+    /// How often to check the files that pin watched directories' rubies, in milliseconds
+    #[arg(long, default_value_t = 500)]
+    pub poll_interval: u64,
+}
+
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+pub enum Error {
+    #[error(transparent)]
+    #[diagnostic(code(RV7601))]
+    Io(#[from] io::Error),
+}
+
+type Result<T> = miette::Result<T, Error>;
+
+// The error codes JSON-RPC 2.0 reserves.
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+/// For errors rv reports, whose code and details are in the error's data.
+const SERVER_ERROR: i64 = -32000;
+
+#[derive(Debug, Deserialize)]
+struct Request {
+    /// Absent for notifications, which get no response.
+    #[serde(default)]
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Debug, Serialize)]
+struct Response {
+    jsonrpc: &'static str,
+    id: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<ResponseError>,
+}
+
+#[derive(Debug, Serialize)]
+struct ResponseError {
+    code: i64,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<JsonError>,
+}
+
+impl ResponseError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            data: None,
+        }
+    }
+}
+
+impl<E: miette::Diagnostic> From<E> for ResponseError {
+    fn from(err: E) -> Self {
+        Self {
+            code: SERVER_ERROR,
+            message: err.to_string(),
+            data: Some(JsonError::new(&err)),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct Notification<T> {
+    jsonrpc: &'static str,
+    method: &'static str,
+    params: T,
+}
+
+#[derive(Debug, Deserialize)]
+struct DirParams {
+    /// A directory, or a file whose directory to use.
+    dir: Utf8PathBuf,
+}
+
+/// Which Ruby applies in a directory, and why.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Resolution {
+    dir: Utf8PathBuf,
+    /// The requested Ruby, e.g. `ruby-3.4` or `ruby` for the latest.
+    request: String,
+    /// The file that pinned the request, if any.
+    source: Option<Utf8PathBuf>,
+    /// The installed Ruby that satisfies the request, if there is one.
+    ruby: Option<Ruby>,
+    executable: Option<Utf8PathBuf>,
+    gem_home: Option<Utf8PathBuf>,
+}
+
+#[derive(Debug, Serialize)]
+struct EnvResult {
+    /// The Ruby the environment activates, which is the latest one installed if none satisfies
+    /// the request, like `rv shell env`.
+    ruby: Option<Ruby>,
+    set: BTreeMap<&'static str, String>,
+    unset: Vec<&'static str>,
+}
+
+#[derive(Debug, Serialize)]
+struct Diagnostic {
+    #[serde(flatten)]
+    error: JsonError,
+    /// More about the problem, like the diff between an outdated lockfile and the Gemfile.
+    details: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct DiagnosticsResult {
+    lockfile: Utf8PathBuf,
+    diagnostics: Vec<Diagnostic>,
+}
+
+#[derive(Debug, Serialize)]
+struct GemfileDiagnosticsResult {
+    gemfile: Utf8PathBuf,
+    diagnostics: Vec<Diagnostic>,
+}
+
+/// A directory the client asked about, and what was last said about it.
+struct Watched {
+    files: Vec<Utf8PathBuf>,
+    fingerprint: Vec<Option<SystemTime>>,
+    resolution: Resolution,
+}
+
+struct Server<'a> {
+    global_args: &'a GlobalArgs,
+    output: Mutex<Box<dyn Write + Send + 'a>>,
+    watched: Mutex<HashMap<Utf8PathBuf, Watched>>,
+}
+
+pub(crate) fn rpc(global_args: &GlobalArgs, args: RpcArgs) -> Result<()> {
+    let server = &Server::new(global_args, io::stdout());
+    let interval = Duration::from_millis(args.poll_interval);
+
+    std::thread::scope(|scope| {
+        let (stop, stopped) = mpsc::channel::<()>();
+        scope.spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                if let Err(err) = server.poll() {
+                    debug!("Stopped watching for changes: {err}");
+                    break;
+                }
+            }
+        });
+        let served = server.serve(io::stdin().lock());
+        drop(stop);
+        served
+    })?;
+
+    Ok(())
+}
+
+impl<'a> Server<'a> {
+    fn new(global_args: &'a GlobalArgs, output: impl Write + Send + 'a) -> Self {
+        Self {
+            global_args,
+            output: Mutex::new(Box::new(output)),
+            watched: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Answer requests until the input ends or the client asks to shut down.
+    fn serve(&self, input: impl BufRead) -> io::Result<()> {
+        for line in input.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let request = match serde_json::from_str::<Value>(&line) {
+                Ok(value) => serde_json::from_value::<Request>(value)
+                    .map_err(|err| ResponseError::new(INVALID_REQUEST, err.to_string())),
+                Err(err) => Err(ResponseError::new(PARSE_ERROR, err.to_string())),
+            };
+            // Requests that can't be read can't be answered by id.
+            let request = match request {
+                Ok(request) => request,
+                Err(error) => {
+                    self.respond(Value::Null, Err(error))?;
+                    continue;
+                }
+            };
+
+            debug!("Handling {}", request.method);
+            let shutdown = matches!(request.method.as_str(), "shutdown" | "exit");
+            let result = self.handle(&request.method, request.params);
+            if let Some(id) = request.id {
+                self.respond(id, result)?;
+            }
+            if shutdown {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn handle(&self, method: &str, params: Value) -> std::result::Result<Value, ResponseError> {
+        let result = match method {
+            "resolveRuby" => {
+                let dir = self.dir(params)?;
+                serde_json::to_value(self.watch(&dir)?)
+            }
+            "envForDir" => {
+                let dir = self.dir(params)?;
+                self.watch(&dir)?;
+                serde_json::to_value(self.env(&dir)?)
+            }
+            "listRubies" => {
+                let config = Config::new(self.global_args, None)?;
+                serde_json::to_value(config.rubies())
+            }
+            "lockfileDiagnostics" => {
+                let dir = self.dir(params)?;
+                serde_json::to_value(self.lockfile_diagnostics(&dir)?)
+            }
+            "gemfileDiagnostics" => {
+                let dir = self.dir(params)?;
+                serde_json::to_value(gemfile_diagnostics(&dir)?)
+            }
+            "shutdown" | "exit" => Ok(Value::Null),
+            _ => {
+                return Err(ResponseError::new(
+                    METHOD_NOT_FOUND,
+                    format!("Unknown method {method}"),
+                ));
+            }
+        };
+
+        result.map_err(|err| ResponseError::new(SERVER_ERROR, err.to_string()))
+    }
+
+    fn dir(&self, params: Value) -> std::result::Result<Utf8PathBuf, ResponseError> {
+        let DirParams { dir } = params_of(params)?;
+        let path = rv_dirs::canonicalize_utf8(&dir)
+            .map_err(|err| ResponseError::new(INVALID_PARAMS, format!("{dir}: {err}")))?;
+        if path.is_dir() {
+            Ok(path)
+        } else {
+            Ok(path.parent().map_or(path.clone(), Utf8Path::to_path_buf))
+        }
+    }
+
+    /// Resolve the Ruby for `dir`, and keep an eye on it from now on.
+    fn watch(&self, dir: &Utf8Path) -> std::result::Result<Resolution, ResponseError> {
+        let (resolution, files) = self.resolve(dir)?;
+        let fingerprint = fingerprint(&files);
+        self.watched.lock().unwrap().insert(
+            dir.to_path_buf(),
+            Watched {
+                files,
+                fingerprint,
+                resolution: resolution.clone(),
+            },
+        );
+        Ok(resolution)
+    }
+
+    /// Which Ruby applies in `dir`, and every file whose changes could change that.
+    fn resolve(
+        &self,
+        dir: &Utf8Path,
+    ) -> std::result::Result<(Resolution, Vec<Utf8PathBuf>), crate::config::Error> {
+        let config = Config::with_settings_in(self.global_args, None, dir)?;
+        let ruby = config.current_ruby();
+        let source = match &config.requested_ruby {
+            crate::config::RequestedRuby::Project((_, source))
+            | crate::config::RequestedRuby::User((_, source)) => Some(source.path().to_path_buf()),
+            _ => None,
+        };
+
+        let mut files = rv_core::request::version_files(
+            &rv_dirs::home_dir(),
+            dir,
+            &rv_dirs::root_dir(),
+            config.dir_depth,
+        );
+        files.extend(config.ruby_dirs.iter().cloned());
+
+        let resolution = Resolution {
+            dir: dir.to_path_buf(),
+            request: config.ruby_request().to_string(),
+            source,
+            executable: ruby.as_ref().map(Ruby::executable_path),
+            gem_home: ruby.as_ref().map(|ruby| config.gem_home(ruby)),
+            ruby,
+        };
+        Ok((resolution, files))
+    }
+
+    fn env(&self, dir: &Utf8Path) -> std::result::Result<EnvResult, crate::config::Error> {
+        let config = Config::with_settings_in(self.global_args, None, dir)?;
+        let ruby = config.best_ruby();
+        let (unset, set) = config.env_for(ruby.as_ref())?.split();
+        Ok(EnvResult {
+            ruby,
+            set: set.into_iter().collect(),
+            unset,
+        })
+    }
+
+    /// Problems with the lockfile of the project `dir` is in: whether it parses, whether it's
+    /// still up to date with the Gemfile, and whether its platform-specific gems can fall back
+    /// to a "ruby" platform version.
+    fn lockfile_diagnostics(
+        &self,
+        dir: &Utf8Path,
+    ) -> std::result::Result<DiagnosticsResult, ResponseError> {
+        let project_root = rv_dirs::project_root_of(dir, &rv_dirs::root_dir());
+        let lockfile_path = project_root.join("Gemfile.lock");
+        let raw_contents = fs_err::read_to_string(&lockfile_path).map_err(lock::Error::IoError)?;
+        let contents = rv_lockfile::normalize_line_endings(&raw_contents);
+
+        let mut diagnostics = vec![];
+        let lockfile = match rv_lockfile::parse(&contents) {
+            Ok(lockfile) => lockfile,
+            Err(source) => {
+                let error = lock::Error::Parse {
+                    lockfile: lockfile_path.clone(),
+                    source,
+                };
+                diagnostics.push(Diagnostic::new(&error, vec![]));
+                return Ok(DiagnosticsResult {
+                    lockfile: lockfile_path,
+                    diagnostics,
+                });
+            }
+        };
+
+        let gemfile_path = gemfile_for_lockfile(&lockfile_path);
+        if let Ok(gemfile) = fs_err::read_to_string(&gemfile_path) {
+            let config = Config::with_settings_in(self.global_args, None, dir)?;
+            let ruby = config.current_ruby().map(|ruby| ruby.executable_path());
+            let declarations =
+                gemfile::read_gem_declarations(&gemfile_path, &gemfile, ruby.as_deref());
+            let diff = lock::drift(&lockfile, &declarations);
+            if !diff.is_empty() {
+                let details = diff
+                    .into_iter()
+                    .map(|line| match line {
+                        DiffLine::Removed(text) => format!("- {text}"),
+                        DiffLine::Added(text) => format!("+ {text}"),
+                        DiffLine::Note(text) => text,
+                    })
+                    .collect();
+                let error = lock::Error::Outdated {
+                    lockfile: lockfile_path.clone(),
+                };
+                diagnostics.push(Diagnostic::new(&error, details));
+            }
+        }
+
+        let missing = lockfile.missing_ruby_fallbacks();
+        if !missing.is_empty() {
+            let details = missing
+                .iter()
+                .map(|tuple| format!("{} has no ruby platform version", tuple.full_name()))
+                .collect();
+            let error = lock::Error::MissingRubyFallbacks {
+                count: missing.len(),
+            };
+            diagnostics.push(Diagnostic::new(&error, details));
+        }
+
+        Ok(DiagnosticsResult {
+            lockfile: lockfile_path,
+            diagnostics,
+        })
+    }
+
+    /// Re-resolve every watched directory whose files changed, and tell the client about the
+    /// ones whose Ruby changed.
+    fn poll(&self) -> io::Result<()> {
+        let mut watched = self.watched.lock().unwrap();
+        for (dir, watch) in watched.iter_mut() {
+            let current = fingerprint(&watch.files);
+            if current == watch.fingerprint {
+                continue;
+            }
+
+            let (resolution, files) = match self.resolve(dir) {
+                Ok(resolved) => resolved,
+                Err(err) => {
+                    debug!("Could not re-resolve the ruby for {dir}: {err}");
+                    watch.fingerprint = current;
+                    continue;
+                }
+            };
+            watch.fingerprint = fingerprint(&files);
+            watch.files = files;
+            if resolution != watch.resolution {
+                debug!("The ruby for {dir} changed to {}", resolution.request);
+                watch.resolution = resolution.clone();
+                self.send(&Notification {
+                    jsonrpc: "2.0",
+                    method: "rubyChanged",
+                    params: resolution,
+                })?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn respond(
+        &self,
+        id: Value,
+        result: std::result::Result<Value, ResponseError>,
+    ) -> io::Result<()> {
+        let (result, error) = match result {
+            Ok(result) => (Some(result), None),
+            Err(error) => (None, Some(error)),
+        };
+        self.send(&Response {
+            jsonrpc: "2.0",
+            id,
+            result,
+            error,
+        })
+    }
+
+    fn send(&self, message: &impl Serialize) -> io::Result<()> {
+        let mut output = self.output.lock().unwrap();
+        serde_json::to_writer(&mut *output, message)?;
+        writeln!(output)?;
+        output.flush()
+    }
+}
+
+impl Diagnostic {
+    fn new(error: &dyn miette::Diagnostic, details: Vec<String>) -> Self {
+        Self {
+            error: JsonError::new(error),
+            details,
+        }
+    }
+}
+
+/// The mistakes `rv gemfile lint` finds in the Gemfile of the project `dir` is in, each with
+/// the corrected line as its hint.
+fn gemfile_diagnostics(
+    dir: &Utf8Path,
+) -> std::result::Result<GemfileDiagnosticsResult, ResponseError> {
+    let project_root = rv_dirs::project_root_of(dir, &rv_dirs::root_dir());
+    let gemfile_path = commands::gemfile::find_gemfile(&project_root)?;
+    let contents = fs_err::read_to_string(&gemfile_path).map_err(commands::gemfile::Error::from)?;
+    let diagnostics = gemfile::lint::lint(&contents)
+        .iter()
+        .map(|problem| Diagnostic::new(problem, vec![]))
+        .collect();
+    Ok(GemfileDiagnosticsResult {
+        gemfile: gemfile_path,
+        diagnostics,
+    })
+}
+
+fn params_of<T: DeserializeOwned>(params: Value) -> std::result::Result<T, ResponseError> {
+    serde_json::from_value(params)
+        .map_err(|err| ResponseError::new(INVALID_PARAMS, err.to_string()))
+}
+
+/// When each file was last modified, or `None` if it doesn't exist.
+fn fingerprint(files: &[Utf8PathBuf]) -> Vec<Option<SystemTime>> {
+    files
+        .iter()
+        .map(|file| fs_err::metadata(file).and_then(|m| m.modified()).ok())
+        .collect()
+}
@@ -5,16 +5,21 @@ use owo_colors::OwoColorize;
 
 use crate::output_format::OutputFormat;
 use rv_ruby::request::RubyRequest;
+use url::Url;
 
 use crate::GlobalArgs;
 
 pub mod dir;
 pub mod find;
 pub mod install;
+pub mod link;
 pub mod list;
 pub mod pin;
+pub mod repair;
+pub mod resolve;
 pub mod run;
 pub mod uninstall;
+pub mod verify;
 
 #[derive(Args)]
 pub struct RubyArgs {
@@ -33,10 +38,25 @@ pub enum RubyCommand {
         #[command(flatten)]
         version_filter: list::VersionFilter,
 
+        #[command(flatten)]
+        link_options: list::LinkOptions,
+
+        /// Show which platforms have a prebuilt Ruby of each available version, rather than the
+        /// installed and available rubies for this one
+        #[arg(long, conflicts_with_all = ["installed_only", "expand_links"])]
+        platforms: bool,
+
         /// By default, the table view is colored.
         /// Set this to skip coloring.
         #[arg(long)]
         no_color: bool,
+
+        /// Fetch the list of available rubies again, even if the cached list is recent
+        #[arg(long)]
+        refresh: bool,
+
+        #[command(flatten)]
+        script_options: list::ScriptOptions,
     },
 
     #[command(about = "Show or set the Ruby version for the current project")]
@@ -44,14 +64,52 @@ pub enum RubyCommand {
         /// The Ruby version to pin
         version: Option<String>,
 
-        /// Write the resolved Ruby version instead of the request
-        #[arg(long)]
+        /// Write the resolved Ruby version instead of the request, e.g. `3.3.9` for `3.3`.
+        /// Versions are resolved against available releases, or installed rubies when offline.
+        #[arg(long, visible_alias = "resolve")]
         resolved: bool,
+
+        /// Pin the newest stable Ruby release
+        #[arg(long, conflicts_with = "version")]
+        latest: bool,
+
+        /// Install the pinned version if it isn't installed yet, without asking
+        #[arg(long)]
+        install: bool,
+
+        /// Also write an `rv.lock` next to the version file, with the exact version the pin
+        /// resolves to and the digests of its archives for every platform, which
+        /// `rv ruby install` checks the archive it installs against. An existing `rv.lock` is
+        /// always kept up to date
+        #[arg(long)]
+        lock: bool,
     },
 
     #[command(about = "Show the directory where all Ruby versions are installed")]
     Dir,
 
+    #[command(
+        about = "Show the exact Ruby version that the pinned version or a specific version resolves to"
+    )]
+    Resolve {
+        /// Ruby version to resolve, e.g. `3.4`
+        version: Option<RubyRequest>,
+
+        /// Show every Ruby version that can be installed instead
+        #[arg(long, conflicts_with = "version")]
+        all: bool,
+
+        /// Name versions like asdf and mise do, e.g. `3.4.1` instead of `ruby-3.4.1`, and show
+        /// `--all` on one line, like an asdf plugin's `list-all`
+        #[arg(long)]
+        asdf_format: bool,
+
+        /// Explain how the Ruby was chosen: where the request came from, what it asks for, and
+        /// why each installed Ruby was or wasn't picked
+        #[arg(long, conflicts_with_all = ["all", "asdf_format"])]
+        explain: bool,
+    },
+
     #[command(
         about = "Show the path to the Ruby executable for the pinned version or a specific version"
     )]
@@ -77,7 +135,7 @@ pub enum RubyCommand {
                       {:width$}  # Install the latest Ruby release that matches a version
                       {:width$}  # Install a specific Ruby release
                       {:width$}  # Install the latest development version of Ruby
-                      {:width$}  # Discover version to install from tool files (.ruby-version, .tool-versions, or Gemfile.lock)
+                      {:width$}  # Discover version to install from tool files (.ruby-version, .tool-versions, or Gemfile.lock), or pick one from a list
                 "#,
                 header.green().bold(),
                 install_latest.cyan(),
@@ -94,24 +152,118 @@ pub enum RubyCommand {
         #[arg(short, long, value_name = "DIR")]
         install_dir: Option<String>,
 
-        /// Ruby version to install
-        version: Option<RubyRequest>,
+        /// Install into the system-wide Ruby directory, shared by all users
+        #[arg(long, conflicts_with = "install_dir")]
+        system: bool,
+
+        /// Ruby version to install, or the name of a build definition to build from source
+        version: Option<String>,
 
         /// Path to a local ruby tarball
         #[arg(long, value_name = "TARBALL_PATH")]
         tarball_path: Option<Utf8PathBuf>,
 
+        /// Install a Ruby from a local archive instead of one of rv's builds, as `--name`
+        #[arg(
+            long,
+            group = "custom",
+            requires = "name",
+            conflicts_with_all = ["version", "tarball_path"]
+        )]
+        archive: Option<Utf8PathBuf>,
+
+        /// Download and install a Ruby archive from this URL instead of one of rv's builds,
+        /// as `--name`
+        #[arg(
+            long,
+            group = "custom",
+            requires = "name",
+            conflicts_with_all = ["version", "tarball_path"]
+        )]
+        url: Option<Url>,
+
+        /// The name to install a Ruby from `--archive` or `--url` as, e.g. `ruby-3.3.9-internal`
+        #[arg(long, requires = "custom")]
+        name: Option<String>,
+
         /// Overwrite an existing installed version.
         #[arg(long)]
         force: bool,
+
+        /// Install the latest Ruby without asking when no version is given or pinned,
+        /// instead of showing a list to pick from.
+        #[arg(short, long)]
+        yes: bool,
+
+        /// Check that the new Ruby works once it's installed, like `rv ruby verify`
+        #[arg(long)]
+        verify: bool,
+
+        /// How to name the new Ruby's directory. `asdf` names it like asdf and mise do, e.g.
+        /// `3.4.1`, so their shims find it when `--install-dir` is their installs directory
+        #[arg(long, value_enum, default_value = "rv", conflicts_with = "custom")]
+        layout: install::InstallLayout,
+
+        /// Update RubyGems in the new Ruby, to VERSION or else the latest. Overrides the
+        /// `update-rubygems` setting
+        #[arg(
+            long,
+            value_name = "VERSION",
+            num_args = 0..=1,
+            default_missing_value = install::LATEST
+        )]
+        update_rubygems: Option<String>,
+
+        /// Install this version of Bundler, or `latest`, into the new Ruby. Overrides the
+        /// `bundler-version` setting
+        #[arg(long, value_name = "VERSION")]
+        bundler: Option<String>,
     },
 
     #[command(about = "Uninstall a specific Ruby version")]
     Uninstall {
         /// Ruby version to uninstall
         version: RubyRequest,
+
+        /// Only uninstall a Ruby that rv installed, never one installed by another tool
+        #[arg(long)]
+        only_rv_managed: bool,
+    },
+
+    #[command(about = "Make rv find a Ruby that was built or installed somewhere else")]
+    Link {
+        /// The directory the Ruby is installed in, e.g. `/opt/custom/ruby-3.3`
+        dir: Utf8PathBuf,
+    },
+
+    #[command(about = "Stop finding a Ruby that was added with `rv ruby link`")]
+    Unlink {
+        /// The directory the linked Ruby is installed in
+        dir: Utf8PathBuf,
+    },
+
+    #[command(
+        about = "Check that an installed Ruby works: that it runs, loads openssl, zlib and psych, and verifies certificates"
+    )]
+    Verify {
+        /// Ruby version to verify
+        version: Option<RubyRequest>,
     },
 
+    #[command(
+        about = "Fix an installed Ruby that stopped working, like after a Homebrew upgrade moved a library it links to",
+        long_about = "Fix an installed Ruby that stopped working. References to shared libraries that moved, like ones from Homebrew after `brew upgrade`, are pointed at where the libraries are now, with `install_name_tool` on macOS and `patchelf` on Linux. If the Ruby still fails the checks of `rv ruby verify`, it's reinstalled from the archive it was installed from, if rv still has it"
+    )]
+    Repair {
+        /// Ruby version to repair
+        version: Option<RubyRequest>,
+    },
+
+    #[command(
+        about = "Run a command with Ruby available, like `rv run`, e.g. `rv ruby exec --isolated -- rake test`"
+    )]
+    Exec(crate::commands::run::RunArgs),
+
     #[command(
         about = "Run Ruby with arguments, using the pinned version or a specific version",
         hide = true,
@@ -124,6 +276,10 @@ pub enum RubyCommand {
         #[arg(long)]
         no_install: bool,
 
+        /// Run in a scrubbed environment, like `rv run --isolated`.
+        #[arg(long)]
+        isolated: bool,
+
         /// Ruby version to run
         version: Option<RubyRequest>,
 
@@ -136,19 +292,41 @@ pub enum RubyCommand {
 #[derive(Debug, thiserror::Error, miette::Diagnostic)]
 pub enum Error {
     #[error(transparent)]
+    #[diagnostic(transparent)]
     FindError(#[from] find::Error),
     #[error(transparent)]
+    #[diagnostic(transparent)]
     ListError(#[from] crate::commands::ruby::list::Error),
     #[error(transparent)]
+    #[diagnostic(transparent)]
     PinError(#[from] crate::commands::ruby::pin::Error),
     #[error(transparent)]
+    #[diagnostic(transparent)]
     DirError(#[from] crate::commands::ruby::dir::Error),
     #[error(transparent)]
+    #[diagnostic(transparent)]
     InstallError(#[from] crate::commands::ruby::install::Error),
     #[error(transparent)]
+    #[diagnostic(transparent)]
     UninstallError(#[from] crate::commands::ruby::uninstall::Error),
     #[error(transparent)]
+    #[diagnostic(transparent)]
     RunError(#[from] crate::commands::ruby::run::Error),
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    ExecError(#[from] crate::commands::run::Error),
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    VerifyError(#[from] crate::commands::ruby::verify::Error),
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    RepairError(#[from] crate::commands::ruby::repair::Error),
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    LinkError(#[from] crate::commands::ruby::link::Error),
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    ResolveError(#[from] crate::commands::ruby::resolve::Error),
 }
 
 type Result<T> = miette::Result<T, Error>;
@@ -159,27 +337,117 @@ pub(crate) async fn ruby(global_args: &GlobalArgs, args: RubyArgs) -> Result<()>
         RubyCommand::List {
             format,
             version_filter,
+            link_options,
+            platforms,
             no_color,
-        } => list::list(global_args, format, version_filter, no_color).await?,
-        RubyCommand::Pin { version, resolved } => pin::pin(global_args, version, resolved).await?,
+            refresh,
+            script_options,
+        } => {
+            list::list(
+                global_args,
+                format,
+                version_filter,
+                link_options,
+                script_options,
+                platforms,
+                no_color,
+                refresh,
+            )
+            .await?
+        }
+        RubyCommand::Pin {
+            version,
+            resolved,
+            latest,
+            install,
+            lock,
+        } => {
+            let version = if latest {
+                Some("latest".to_string())
+            } else {
+                version
+            };
+            pin::pin(global_args, version, resolved, install, lock).await?
+        }
         RubyCommand::Dir => dir::dir(global_args)?,
+        RubyCommand::Resolve {
+            version,
+            all,
+            asdf_format,
+            explain,
+        } => resolve::resolve(global_args, version, all, asdf_format, explain).await?,
         RubyCommand::Install {
             version,
             install_dir,
+            system,
             tarball_path,
+            archive,
+            url,
+            name,
             force,
-        } => install::install(global_args, install_dir, version, tarball_path, force).await?,
-        RubyCommand::Uninstall { version } => uninstall::uninstall(global_args, version).await?,
+            yes,
+            verify,
+            layout,
+            update_rubygems,
+            bundler,
+        } => {
+            let install_dir = install::InstallDir::new(install_dir, system);
+            let default_gem_updates = install::DefaultGemUpdates {
+                rubygems: update_rubygems,
+                bundler,
+            };
+            let custom = archive
+                .map(install::CustomArchive::Path)
+                .or(url.map(install::CustomArchive::Url));
+            match (custom, name) {
+                (Some(archive), Some(name)) => {
+                    install::install_custom(
+                        global_args,
+                        install_dir,
+                        archive,
+                        &name,
+                        force,
+                        verify,
+                        default_gem_updates,
+                    )
+                    .await?
+                }
+                _ => {
+                    install::install_named(
+                        global_args,
+                        install_dir,
+                        version,
+                        tarball_path,
+                        force,
+                        !yes,
+                        verify,
+                        layout,
+                        default_gem_updates,
+                    )
+                    .await?
+                }
+            }
+        }
+        RubyCommand::Uninstall {
+            version,
+            only_rv_managed,
+        } => uninstall::uninstall(global_args, version, only_rv_managed).await?,
+        RubyCommand::Link { dir } => link::link(global_args, &dir)?,
+        RubyCommand::Unlink { dir } => link::unlink(global_args, &dir)?,
+        RubyCommand::Verify { version } => verify::verify(global_args, version)?,
+        RubyCommand::Repair { version } => repair::repair(global_args, version)?,
+        RubyCommand::Exec(args) => crate::commands::run::run(global_args, args).await?,
         RubyCommand::Run {
             version,
             no_install,
+            isolated,
             args,
         } => {
             if env!("CARGO_PKG_VERSION_MINOR").parse::<u8>().unwrap() >= 7 {
                 panic!("Remove this subcommand before releasing 0.7.0");
             };
 
-            run::run(global_args, version, no_install, args)
+            run::run(global_args, version, no_install, isolated, args)
                 .await
                 .map(|_| ())?
         }
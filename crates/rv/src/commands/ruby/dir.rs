@@ -6,6 +6,7 @@ use crate::{GlobalArgs, config::Config};
 #[derive(Debug, thiserror::Error, miette::Diagnostic)]
 pub enum Error {
     #[error(transparent)]
+    #[diagnostic(transparent)]
     ConfigError(#[from] crate::config::Error),
 }
 
@@ -7,8 +7,10 @@ use crate::{GlobalArgs, config::Config};
 #[derive(Debug, thiserror::Error, miette::Diagnostic)]
 pub enum Error {
     #[error("no matching ruby version found")]
+    #[diagnostic(code(RV1001))]
     NoMatchingRuby,
     #[error(transparent)]
+    #[diagnostic(transparent)]
     ConfigError(#[from] crate::config::Error),
 }
 
@@ -6,90 +6,429 @@ use futures_util::StreamExt;
 use indicatif::ProgressStyle;
 use owo_colors::OwoColorize;
 use reqwest::StatusCode;
-use std::path::{Component, PathBuf};
+use sha2::{Digest, Sha256};
+use std::io::IsTerminal;
+use std::time::Instant;
 use tokio::io::AsyncWriteExt;
-use tracing::{debug, info_span};
+use tracing::{debug, info_span, warn};
 use tracing_indicatif::span_ext::IndicatifSpanExt;
 
+use rv_client::http_client::{rv_http_client, rv_http_client_without_redirects};
+use rv_core::provenance::Provenance;
 use rv_platform::HostPlatform;
+use rv_ruby::Ruby;
 use rv_ruby::request::RubyRequest;
+use url::Url;
 
+use crate::GlobalArgs;
+use crate::config::release_source::ArchiveLocation;
+use crate::config::ruby_lock::{self, RubyLock};
+use crate::config::{Config, RequestedRuby};
 use crate::progress::WorkProgress;
-use crate::{GlobalArgs, config::Config};
+
+mod build;
+mod default_gems;
+mod delta;
+mod macos;
+mod picker;
+
+pub(crate) use default_gems::{DefaultGemUpdates, LATEST};
+pub(crate) use macos::BINARY_PATTERNS as MACHO_BINARY_PATTERNS;
 
 #[derive(Debug, thiserror::Error, miette::Diagnostic)]
 pub enum Error {
     #[error(transparent)]
+    #[diagnostic(transparent)]
     ConfigError(#[from] crate::config::Error),
     #[error(transparent)]
+    #[diagnostic(code(RV1301))]
     ReqwestError(#[from] reqwest::Error),
     #[error(transparent)]
+    #[diagnostic(code(RV1302))]
     IoError(#[from] std::io::Error),
     #[error(transparent)]
+    #[diagnostic(code(RV1303))]
     StripPrefixError(#[from] std::path::StripPrefixError),
     #[error(transparent)]
+    #[diagnostic(code(RV1304))]
     ZipError(#[from] zip::result::ZipError),
     #[error(transparent)]
+    #[diagnostic(code(RV1305))]
     SevenZipError(#[from] sevenz_rust2::Error),
     #[error("no matching ruby version found")]
+    #[diagnostic(code(RV1306))]
     NoMatchingRuby,
     #[error("Download from URL {url} failed with status code {status}. Response body was {body}")]
+    #[diagnostic(code(RV1307))]
     DownloadFailed {
         url: String,
         status: reqwest::StatusCode,
         body: String,
     },
     #[error("Could not get latest ruby-dev release")]
+    #[diagnostic(code(RV1308))]
     GetLatestDevReleaseFailed,
-    #[error("Paths including .. are not allowed inside archives, but found {0}")]
+    #[error("Paths that are absolute or include .. are not allowed inside archives, but found {0}")]
+    #[diagnostic(code(RV1309))]
     DirectoryTraversalError(String),
     #[error(transparent)]
+    #[diagnostic(code(RV1310))]
     UnsupportedPlatform(#[from] rv_platform::UnsupportedPlatformError),
+    #[error("You don't have permission to install rubies into {dir}")]
+    #[diagnostic(
+        code(RV1311),
+        help(
+            "Re-run with elevated permissions (e.g. `sudo`), or choose another directory with `--install-dir`"
+        )
+    )]
+    InstallDirNotWritable { dir: Utf8PathBuf },
+    #[error(transparent)]
+    #[diagnostic(
+        code(RV1312),
+        help(
+            "Another rv process is installing into the same place. Wait for it to finish, or raise `--lock-timeout`"
+        )
+    )]
+    LockFailed(std::io::Error),
+    #[error("No Ruby version was chosen")]
+    #[diagnostic(
+        code(RV1313),
+        help("Give a version, e.g. `rv ruby install 3.4`, or use `--yes` to install the latest")
+    )]
+    NoVersionChosen,
+    #[error("{name:?} can't be used as the name of a Ruby installation")]
+    #[diagnostic(
+        code(RV1314),
+        help("Use a name like `ruby-3.3.9-internal`, without slashes")
+    )]
+    InvalidName { name: String },
+    #[error("{archive} does not contain a Ruby that rv can use")]
+    #[diagnostic(
+        code(RV1315),
+        help(
+            "rv expects archives laid out like its own builds, with the Ruby two directories deep, e.g. `rv-ruby@3.3.9/3.3.9/bin/ruby`"
+        )
+    )]
+    InvalidArchive {
+        archive: String,
+        #[source]
+        source: rv_ruby::RubyError,
+    },
+    #[error(transparent)]
+    #[diagnostic(code(RV1316))]
+    InvalidRequest(#[from] rv_ruby::request::RequestError),
+    #[error("The build definition {path} is invalid: {reason}")]
+    #[diagnostic(code(RV1317))]
+    InvalidDefinition { path: Utf8PathBuf, reason: String },
+    #[error(
+        "The source tarball from {url} has SHA256 digest {actual}, but its definition expects {expected}"
+    )]
+    #[diagnostic(code(RV1318))]
+    SourceChecksumMismatch {
+        url: String,
+        expected: String,
+        actual: String,
+    },
+    #[error("Building {name} failed while running `{step}`")]
+    #[diagnostic(code(RV1319), help("The build's output is in {log}"))]
+    BuildFailed {
+        name: String,
+        step: String,
+        log: Utf8PathBuf,
+    },
+    #[error("rv can't build rubies from source on Windows")]
+    #[diagnostic(code(RV1320))]
+    SourceBuildUnsupported,
+    #[error("Updating the default gems of Ruby {ruby} failed while running `{command}`")]
+    #[diagnostic(code(RV1321), help("{output}"))]
+    DefaultGemUpdateFailed {
+        ruby: String,
+        command: String,
+        output: String,
+    },
+    #[error("Signing the binaries of {ruby_dir} failed")]
+    #[diagnostic(code(RV1322), help("{output}"))]
+    SigningFailed {
+        ruby_dir: Utf8PathBuf,
+        output: String,
+    },
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    VerifyError(#[from] crate::commands::ruby::verify::Error),
+    #[error("The archive {archive} has SHA256 digest {actual}, but {lockfile} expects {expected}")]
+    #[diagnostic(
+        code(RV1323),
+        help(
+            "If the Ruby's archive was rebuilt on purpose, lock the project to it again with `rv ruby pin --lock`"
+        )
+    )]
+    LockedDigestMismatch {
+        archive: Utf8PathBuf,
+        lockfile: Utf8PathBuf,
+        expected: String,
+        actual: String,
+    },
 }
 
 type Result<T> = miette::Result<T, Error>;
 
+impl From<rv_core::install::Error> for Error {
+    fn from(err: rv_core::install::Error) -> Self {
+        use rv_core::install::Error as InstallError;
+        match err {
+            InstallError::Io(err) => Self::IoError(err),
+            InstallError::Zip(err) => Self::ZipError(err),
+            InstallError::SevenZip(err) => Self::SevenZipError(err),
+            InstallError::DirectoryTraversal(path) => Self::DirectoryTraversalError(path),
+            InstallError::UnsupportedPlatform(err) => Self::UnsupportedPlatform(err),
+        }
+    }
+}
+
+/// Where `rv ruby install` should put the new ruby.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum InstallDir {
+    /// The first configured ruby directory (usually the user's data dir).
+    Default,
+    /// The system-wide ruby directory, shared by all users.
+    System,
+    /// A directory given explicitly with `--install-dir`.
+    Explicit(Utf8PathBuf),
+}
+
+impl InstallDir {
+    pub(crate) fn new(install_dir: Option<String>, system: bool) -> Self {
+        match install_dir {
+            Some(dir) => Self::Explicit(dir.into()),
+            None if system => Self::System,
+            None => Self::Default,
+        }
+    }
+
+    fn resolve(self, config: &Config) -> Utf8PathBuf {
+        match self {
+            Self::Explicit(dir) => dir,
+            Self::System => rv_dirs::system_ruby_dir(&rv_dirs::root_dir()),
+            Self::Default => match config.ruby_dirs.first() {
+                Some(dir) => dir.clone(),
+                None => panic!("No Ruby directories to install into"),
+            },
+        }
+    }
+}
+
+/// How the directory of a newly installed Ruby is named.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum InstallLayout {
+    /// `ruby-3.4.1`, like every Ruby rv installs.
+    #[default]
+    Rv,
+    /// `3.4.1`, like asdf and mise name the rubies in their installs directories.
+    Asdf,
+}
+
+impl InstallLayout {
+    /// The name of the directory `version` is installed into.
+    fn dir_name(self, version: &str) -> String {
+        match self {
+            Self::Rv => format!("ruby-{version}"),
+            Self::Asdf => version.to_owned(),
+        }
+    }
+}
+
+/// Install the Ruby named on the command line: the one a build definition with that name builds
+/// if there is one, or else the release that matches it. With `verify`, check that it works
+/// afterwards.
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn install_named(
+    global_args: &GlobalArgs,
+    install_dir: InstallDir,
+    name: Option<String>,
+    tarball_path: Option<Utf8PathBuf>,
+    force: bool,
+    interactive: bool,
+    verify: bool,
+    layout: InstallLayout,
+    default_gem_updates: DefaultGemUpdates,
+) -> Result<()> {
+    if let Some(name) = &name
+        && tarball_path.is_none()
+    {
+        let config = Config::with_settings(global_args, None)?;
+        let definitions_dir = config
+            .rv_settings
+            .build_definitions_dir(&rv_dirs::root_dir());
+        if let Some(definition) = build::Definition::find(&definitions_dir, name)? {
+            let ruby_dir = build::build(&config, install_dir, &definition, force).await?;
+            default_gems::update(&config, &ruby_dir, default_gem_updates)?;
+            if verify {
+                verify_dir(&config, ruby_dir, name)?;
+            }
+            return Ok(());
+        }
+    }
+
+    let request = name.as_deref().map(str::parse::<RubyRequest>).transpose()?;
+    install(
+        global_args,
+        install_dir,
+        request.clone(),
+        tarball_path,
+        force,
+        interactive,
+        layout,
+        default_gem_updates,
+    )
+    .await?;
+    if verify {
+        super::verify::verify(global_args, request)?;
+    }
+    Ok(())
+}
+
+/// Check that the Ruby just installed into `ruby_dir` works.
+fn verify_dir(config: &Config, ruby_dir: Utf8PathBuf, name: &str) -> Result<()> {
+    let ruby = Ruby::from_dir(ruby_dir, true).map_err(|source| Error::InvalidArchive {
+        archive: name.to_owned(),
+        source,
+    })?;
+    super::verify::verify_ruby(config, &ruby)?;
+    Ok(())
+}
+
+/// Install the release of Ruby that `request` asks for, or the one in `tarball_path`, and then
+/// update its default gems as `default_gem_updates` and the settings say.
+#[allow(clippy::too_many_arguments)]
 pub(crate) async fn install(
     global_args: &GlobalArgs,
-    install_dir: Option<String>,
+    install_dir: InstallDir,
     request: Option<RubyRequest>,
     tarball_path: Option<Utf8PathBuf>,
     force: bool,
+    interactive: bool,
+    layout: InstallLayout,
+    default_gem_updates: DefaultGemUpdates,
 ) -> Result<()> {
-    let config = &Config::with_settings(global_args, request)?;
+    let mut config = Config::with_settings(global_args, request)?;
 
     config.self_update_if_needed().await;
 
+    // Nothing says which Ruby to install, so let the user pick one.
+    if interactive
+        && !global_args.no_input
+        && tarball_path.is_none()
+        && matches!(config.requested_ruby, RequestedRuby::Global)
+        && std::io::stdin().is_terminal()
+        && std::io::stdout().is_terminal()
+    {
+        let versions: Vec<_> = config
+            .remote_rubies()
+            .await
+            .into_iter()
+            .map(|ruby| ruby.version)
+            .collect();
+        if !versions.is_empty() {
+            let version = picker::pick(versions)?.ok_or(Error::NoVersionChosen)?;
+            config.requested_ruby = RequestedRuby::Explicit(version.into());
+        }
+    }
+    let config = &config;
+
     let progress = WorkProgress::new();
 
     let request = config.ruby_request();
 
-    let version = match request {
-        RubyRequest::Dev => "dev".to_string(),
-        RubyRequest::Released(_) => config.find_matching_remote_ruby().await?.number(),
+    // The project's lock applies to its locked version, whatever asked for it to be installed.
+    let ruby_lock = Config::new(global_args, None)?.ruby_lock()?;
+    // The project's own Ruby is the one it's locked to, as long as that still satisfies its pin.
+    let locked_version = ruby_lock
+        .as_ref()
+        .filter(|(_, lock)| {
+            matches!(config.requested_ruby, RequestedRuby::Project(_))
+                && lock.ruby.satisfies(&request)
+        })
+        .map(|(_, lock)| lock.ruby.number());
+
+    let version = match (request, locked_version.clone()) {
+        (RubyRequest::Dev, _) => "dev".to_string(),
+        (_, Some(version)) => version,
+        (RubyRequest::Released(_) | RubyRequest::Range(_), None) => {
+            config.find_matching_remote_ruby().await?.number()
+        }
     };
 
-    let install_dir = match install_dir {
-        Some(dir) => Utf8PathBuf::from(dir),
-        None => match config.ruby_dirs.first() {
-            Some(dir) => dir.clone(),
-            None => panic!("No Ruby directories to install into"),
-        },
+    let install_dir = install_dir.resolve(config);
+    let ruby_dir = install_dir.join(layout.dir_name(&version));
+    let is_installed = || match layout {
+        InstallLayout::Rv if locked_version.is_none() => {
+            config.is_requested_ruby_installed_in_dir(&install_dir)
+        }
+        InstallLayout::Rv | InstallLayout::Asdf => config
+            .installed_ruby_dirs()
+            .ruby_in(&ruby_dir, false)
+            .is_some(),
     };
 
-    if config.is_requested_ruby_installed_in_dir(&install_dir) && !force {
+    if is_installed() && !force {
         println!("Version already installed. If you want to overwrite it, use '--force'.");
 
         return Ok(());
     }
 
-    let archive_path = if let Some(path) = tarball_path {
-        path
+    ensure_writable(&install_dir)?;
+
+    let _lock = rv_cache::LockedFile::acquire_async(
+        ruby_dir_lock_path(&ruby_dir),
+        &format!("ruby dir {ruby_dir}"),
+        config.cache.lock_wait(),
+    )
+    .await
+    .map_err(Error::LockFailed)?;
+
+    // Another rv process may have finished installing this ruby while we waited for the lock.
+    if !force && is_installed() {
+        println!("Version already installed. If you want to overwrite it, use '--force'.");
+
+        return Ok(());
+    }
+
+    let (archive_path, source) = if let Some(path) = tarball_path {
+        let source = path.to_string();
+        (path, source)
     } else {
-        download_tarball(config, &version, &progress).await?
+        let download_start = Instant::now();
+        let downloaded = download_tarball(config, &version, &progress).await?;
+        crate::stats::record_phase("download", download_start.elapsed());
+        downloaded
     };
 
-    extract_ruby_archive(&archive_path, &install_dir, &version)?;
+    if let Some((lock_dir, lock)) = &ruby_lock
+        && lock.ruby.number() == version
+    {
+        verify_locked_archive(config, lock_dir, lock, &archive_path)?;
+    }
+
+    {
+        let span = info_span!("Installing Ruby", version);
+        span.pb_set_style(&ProgressStyle::with_template("{spinner:.green} {span_name}").unwrap());
+        let _guard = span.enter();
+        let extract_start = Instant::now();
+        rv_core::install::extract_ruby_archive(&archive_path, &install_dir, &version)?;
+        crate::stats::record_phase("extract", extract_start.elapsed());
+    }
+
+    // Archives are always extracted into `ruby-{version}`, so move the Ruby where the layout
+    // wants it.
+    let extracted_dir = install_dir.join(InstallLayout::Rv.dir_name(&version));
+    if extracted_dir != ruby_dir {
+        if ruby_dir.exists() {
+            fs_err::remove_dir_all(&ruby_dir)?;
+        }
+        fs_err::rename(&extracted_dir, &ruby_dir)?;
+    }
+    macos::prepare(&ruby_dir)?;
+    record_provenance(&ruby_dir, source, &archive_path)?;
 
     let installed_version = if version == "dev" {
         "ruby-dev".cyan().to_string()
@@ -99,21 +438,306 @@ pub(crate) async fn install(
 
     println!("Installed {installed_version} to {}", install_dir.cyan());
 
+    default_gems::update(config, &ruby_dir, default_gem_updates)?;
+
+    Ok(())
+}
+
+/// Where to get a Ruby that isn't one of rv's builds.
+#[derive(Debug, Clone)]
+pub(crate) enum CustomArchive {
+    /// An archive on disk.
+    Path(Utf8PathBuf),
+    /// An archive to download.
+    Url(Url),
+}
+
+impl std::fmt::Display for CustomArchive {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Path(path) => path.fmt(f),
+            Self::Url(url) => url.fmt(f),
+        }
+    }
+}
+
+/// Install a Ruby from an archive that isn't one of rv's builds, like one an organization builds
+/// with its own patches, as `name`. Nothing is looked up about releases: the archive is checked by
+/// running the Ruby in it once it's extracted.
+pub(crate) async fn install_custom(
+    global_args: &GlobalArgs,
+    install_dir: InstallDir,
+    archive: CustomArchive,
+    name: &str,
+    force: bool,
+    verify: bool,
+    default_gem_updates: DefaultGemUpdates,
+) -> Result<()> {
+    let config = &Config::with_settings(global_args, None)?;
+
+    // Archives are extracted into `ruby-{version}`, so the name is the version for them.
+    let version = name.strip_prefix("ruby-").unwrap_or(name);
+    if version.is_empty() || version.contains(['/', '\\']) || version == ".." {
+        return Err(Error::InvalidName {
+            name: name.to_owned(),
+        });
+    }
+
+    let install_dir = install_dir.resolve(config);
+    let ruby_dir = install_dir.join(format!("ruby-{version}"));
+    if ruby_dir.exists() && !force {
+        println!("{name} is already installed. If you want to overwrite it, use '--force'.");
+
+        return Ok(());
+    }
+
+    ensure_writable(&install_dir)?;
+
+    let _lock = rv_cache::LockedFile::acquire_async(
+        ruby_dir_lock_path(&ruby_dir),
+        &format!("ruby dir {ruby_dir}"),
+        config.cache.lock_wait(),
+    )
+    .await
+    .map_err(Error::LockFailed)?;
+
+    let archive_path = match &archive {
+        CustomArchive::Path(path) => path.clone(),
+        CustomArchive::Url(url) => download_custom_archive(config, url, name).await?,
+    };
+
+    {
+        let span = info_span!("Installing Ruby", version);
+        span.pb_set_style(&ProgressStyle::with_template("{spinner:.green} {span_name}").unwrap());
+        let _guard = span.enter();
+        rv_core::install::extract_ruby_archive(&archive_path, &install_dir, version)?;
+    }
+
+    macos::prepare(&ruby_dir)?;
+
+    // Don't leave something that looks like a Ruby installation, but isn't one, behind.
+    let ruby = match Ruby::from_dir(ruby_dir.clone(), true) {
+        Ok(ruby) => ruby,
+        Err(source) => {
+            fs_err::remove_dir_all(&ruby_dir)?;
+            return Err(Error::InvalidArchive {
+                archive: archive.to_string(),
+                source,
+            });
+        }
+    };
+
+    record_provenance(&ruby_dir, archive.to_string(), &archive_path)?;
+
+    println!("Installed {} to {}", name.cyan(), install_dir.cyan());
+
+    default_gems::update(config, &ruby_dir, default_gem_updates)?;
+
+    if verify {
+        super::verify::verify_ruby(config, &ruby)?;
+    }
+
+    Ok(())
+}
+
+/// The archive a Ruby was installed from, if it's still around: the local archive it was
+/// installed from, or the one rv downloaded from `source` into its cache.
+pub(super) fn cached_archive(config: &Config, source: &str) -> Option<Utf8PathBuf> {
+    let local = Utf8Path::new(source);
+    if valid_archive_exists(local) {
+        return Some(local.to_path_buf());
+    }
+    let shard = config.cache.shard(rv_cache::CacheBucket::Ruby, "tarballs");
+    let digest = rv_cache::cache_digest(source);
+    ["tar.gz", "zip", "7z"]
+        .into_iter()
+        .map(|ext| shard.join(format!("{digest}.{ext}")))
+        .find(|path| valid_archive_exists(path))
+}
+
+/// The lock file that keeps other rv processes from installing into `ruby_dir` at the same time.
+/// It sits next to the Ruby rather than inside it, so that replacing the Ruby leaves it alone.
+pub(super) fn ruby_dir_lock_path(ruby_dir: &Utf8Path) -> Utf8PathBuf {
+    let name = ruby_dir.file_name().unwrap_or_default();
+    ruby_dir.with_file_name(format!(".{name}.lock"))
+}
+
+/// Replace the Ruby in `ruby_dir` with a fresh copy from `archive`, the archive it was installed
+/// from. The broken Ruby is only removed once the new copy is known to be a Ruby.
+pub(super) fn reinstall(
+    config: &Config,
+    ruby_dir: &Utf8Path,
+    archive: &Utf8Path,
+    source: String,
+) -> Result<Ruby> {
+    let install_dir = ruby_dir.parent().unwrap_or(ruby_dir);
+    ensure_writable(install_dir)?;
+
+    let _lock = rv_cache::LockedFile::acquire(
+        ruby_dir_lock_path(ruby_dir),
+        &format!("ruby dir {ruby_dir}"),
+        config.cache.lock_wait(),
+    )
+    .map_err(Error::LockFailed)?;
+
+    // Archives are extracted into `ruby-{version}`, whatever the layout the Ruby is in.
+    let dir_name = ruby_dir.file_name().unwrap_or_default();
+    let version = dir_name.strip_prefix("ruby-").unwrap_or(dir_name);
+    let staging = camino_tempfile::Builder::new()
+        .prefix(".rv-reinstall-")
+        .tempdir_in(install_dir)?;
+    {
+        let span = info_span!("Reinstalling Ruby", version);
+        span.pb_set_style(&ProgressStyle::with_template("{spinner:.green} {span_name}").unwrap());
+        let _guard = span.enter();
+        rv_core::install::extract_ruby_archive(archive, staging.path(), version)?;
+    }
+    let extracted_dir = staging.path().join(InstallLayout::Rv.dir_name(version));
+    macos::prepare(&extracted_dir)?;
+    Ruby::from_dir(extracted_dir.clone(), true).map_err(|source| Error::InvalidArchive {
+        archive: archive.to_string(),
+        source,
+    })?;
+
+    fs_err::remove_dir_all(ruby_dir)?;
+    fs_err::rename(&extracted_dir, ruby_dir)?;
+    record_provenance(ruby_dir, source, archive)?;
+    default_gems::update(config, ruby_dir, DefaultGemUpdates::default())?;
+
+    Ruby::from_dir(ruby_dir.to_path_buf(), true).map_err(|source| Error::InvalidArchive {
+        archive: archive.to_string(),
+        source,
+    })
+}
+
+/// Write down where the Ruby just installed into `ruby_dir` came from, in its `.rv-meta.json`.
+fn record_provenance(ruby_dir: &Utf8Path, source: String, archive_path: &Utf8Path) -> Result<()> {
+    let sha256 = sha256_file(archive_path)?;
+    let provenance = Provenance::new(source, Some(sha256), env!("CARGO_PKG_VERSION"));
+    provenance.write(ruby_dir)?;
+    Ok(())
+}
+
+/// Check the archive of the Ruby that `lock` locks the project to against the digest it records
+/// for this platform.
+fn verify_locked_archive(
+    config: &Config,
+    lock_dir: &Utf8Path,
+    lock: &RubyLock,
+    archive_path: &Utf8Path,
+) -> Result<()> {
+    let host = HostPlatform::current()?;
+    let lockfile = lock_dir.join(ruby_lock::FILE_NAME);
+    let Some(expected) = lock.sha256_for(&host) else {
+        warn!(
+            "{lockfile} has no digest for {}, so the archive can't be checked against it",
+            ruby_lock::platform_key(&host)
+        );
+        return Ok(());
+    };
+
+    let actual = sha256_file(archive_path)?;
+    if actual == expected {
+        debug!("{archive_path} matches the digest in {lockfile}");
+        return Ok(());
+    }
+
+    // Don't keep a download that doesn't match, so that installing again downloads it again.
+    let shard = config.cache.shard(rv_cache::CacheBucket::Ruby, "tarballs");
+    if archive_path.starts_with(&*shard) {
+        fs_err::remove_file(archive_path)?;
+    }
+    Err(Error::LockedDigestMismatch {
+        archive: archive_path.to_path_buf(),
+        lockfile,
+        expected: expected.to_owned(),
+        actual,
+    })
+}
+
+/// The SHA256 digest of a file, as lowercase hex.
+fn sha256_file(path: &Utf8Path) -> Result<String> {
+    Ok(hex::encode(Sha256::digest(fs_err::read(path)?)))
+}
+
+/// Download an archive from a URL, unless it's in the cache already.
+async fn download_custom_archive(config: &Config, url: &Url, name: &str) -> Result<Utf8PathBuf> {
+    let host = HostPlatform::current()?;
+    let ext = match url.path() {
+        path if path.ends_with(".zip") => "zip",
+        path if path.ends_with(".7z") => "7z",
+        _ => "tar.gz",
+    };
+    let shard = config.cache.shard(rv_cache::CacheBucket::Ruby, "tarballs");
+    let archive_path = shard.join(format!("{}.{ext}", rv_cache::cache_digest(url.as_str())));
+    fs_err::create_dir_all(&*shard)?;
+    let _lock = config
+        .cache
+        .lock_entry_async(&archive_path)
+        .await
+        .map_err(Error::LockFailed)?;
+
+    if valid_archive_exists(&archive_path) {
+        debug!("Using cached archive {archive_path} for {url}");
+        rv_cache::record_hit(rv_cache::CacheBucket::Ruby);
+        return Ok(archive_path);
+    }
+    rv_cache::record_miss(rv_cache::CacheBucket::Ruby);
+
+    let progress = WorkProgress::new();
+    download_ruby_archive(config, url.as_str(), &archive_path, name, &progress, &host)
+        .await
+        .map_err(|err| match err {
+            // It's not a release that's missing, but the archive at this exact URL.
+            Error::NoMatchingRuby => Error::DownloadFailed {
+                url: url.to_string(),
+                status: StatusCode::NOT_FOUND,
+                body: String::new(),
+            },
+            err => err,
+        })?;
+
+    Ok(archive_path)
+}
+
+/// Check up front that we can write into the install directory, so that we don't
+/// download a whole archive only to fail extracting it.
+fn ensure_writable(dir: &Utf8Path) -> Result<()> {
+    let not_writable = |err: std::io::Error| {
+        if err.kind() == std::io::ErrorKind::PermissionDenied {
+            Error::InstallDirNotWritable {
+                dir: dir.to_path_buf(),
+            }
+        } else {
+            Error::IoError(err)
+        }
+    };
+
+    fs_err::create_dir_all(dir).map_err(not_writable)?;
+    camino_tempfile::NamedUtf8TempFile::new_in(dir).map_err(not_writable)?;
+
     Ok(())
 }
 
-// downloads a remote ruby archive (tarball or zip)
+// downloads a remote ruby archive (tarball or zip), returning where it is and the URL it came from
 async fn download_tarball(
     config: &Config,
     version: &str,
     progress: &WorkProgress,
-) -> Result<Utf8PathBuf> {
+) -> Result<(Utf8PathBuf, String)> {
     let host = HostPlatform::current()?;
-    let mut url = ruby_url(version, &host);
-
-    if version == "dev" && !host.is_windows() {
-        url = find_latest_ruby_dev_url(&url).await?;
-    }
+    let source = config.release_source(&host);
+    let url = match source.archive(version, &host) {
+        ArchiveLocation::Url(url) => url,
+        ArchiveLocation::LatestRedirect(url) => find_latest_ruby_dev_url(&url).await?,
+        // A local mirror's archives don't need to be copied into the cache.
+        ArchiveLocation::Path(path) if path.is_file() => {
+            debug!("Using archive {path} from the local mirror");
+            let source = path.to_string();
+            return Ok((path, source));
+        }
+        ArchiveLocation::Path(_) => return Err(Error::NoMatchingRuby),
+    };
     let archive_path = archive_cache_path(config, &url, &host);
 
     let cache_dir = archive_path.parent().unwrap();
@@ -121,16 +745,26 @@ async fn download_tarball(
         fs_err::create_dir_all(cache_dir)?;
     }
 
+    let _lock = config
+        .cache
+        .lock_entry_async(&archive_path)
+        .await
+        .map_err(Error::LockFailed)?;
+
     if valid_archive_exists(&archive_path) {
+        rv_cache::record_hit(rv_cache::CacheBucket::Ruby);
         println!(
             "Archive {} already exists, skipping download.",
             archive_path.cyan()
         );
     } else {
-        download_ruby_archive(config, &url, &archive_path, version, progress, &host).await?;
+        rv_cache::record_miss(rv_cache::CacheBucket::Ruby);
+        if !delta::download(config, source.as_ref(), version, &url, &archive_path, &host).await {
+            download_ruby_archive(config, &url, &archive_path, version, progress, &host).await?;
+        }
     }
 
-    Ok(archive_path)
+    Ok((archive_path, url))
 }
 
 /// Does a usable archive already exist at this path?
@@ -138,40 +772,6 @@ fn valid_archive_exists(path: &Utf8Path) -> bool {
     fs_err::metadata(path).is_ok_and(|m| m.is_file() && m.len() > 0)
 }
 
-fn ruby_url(version: &str, host: &HostPlatform) -> String {
-    let download_base =
-        std::env::var("RV_INSTALL_URL").unwrap_or_else(|_| download_base_for(version, host));
-    let download_path = download_path_for(version, host);
-
-    format!("{download_base}/{download_path}")
-}
-
-fn download_base_for(version: &str, host: &HostPlatform) -> String {
-    if host.is_windows() {
-        "https://github.com/oneclick/rubyinstaller2/releases/download".to_owned()
-    } else if version == "dev" {
-        "https://github.com/spinel-coop/rv-ruby-dev/releases/latest/download".to_owned()
-    } else {
-        "https://github.com/spinel-coop/rv-ruby/releases/latest/download".to_owned()
-    }
-}
-
-fn download_path_for(version: &str, host: &HostPlatform) -> String {
-    let arch = host.ruby_arch_str();
-    let ext = host.archive_ext();
-
-    if host.is_windows() {
-        if version == "dev" {
-            // Dev builds use the rubyinstaller-head release (no revision number)
-            format!("rubyinstaller-head/rubyinstaller-head-{arch}.{ext}")
-        } else {
-            format!("RubyInstaller-{version}-1/rubyinstaller-{version}-1-{arch}.{ext}")
-        }
-    } else {
-        format!("ruby-{version}.{arch}.{ext}")
-    }
-}
-
 async fn find_latest_ruby_dev_url(url: &str) -> Result<String> {
     let redirects = false;
     let response = fetch_url(url, redirects).await?;
@@ -189,7 +789,11 @@ async fn find_latest_ruby_dev_url(url: &str) -> Result<String> {
     }
 }
 
-fn archive_cache_path(config: &Config, url: impl AsRef<str>, host: &HostPlatform) -> Utf8PathBuf {
+pub(crate) fn archive_cache_path(
+    config: &Config,
+    url: impl AsRef<str>,
+    host: &HostPlatform,
+) -> Utf8PathBuf {
     let ext = host.archive_ext();
     let cache_key = rv_cache::cache_digest(url.as_ref());
     config
@@ -309,11 +913,9 @@ async fn download_ruby_archive(
 async fn fetch_url(url: &str, redirects: bool) -> Result<reqwest::Response> {
     // Build the request with optional GitHub authentication
     let client = if !redirects {
-        reqwest::Client::builder()
-            .redirect(reqwest::redirect::Policy::none())
-            .build()?
+        rv_http_client_without_redirects("ruby_install")?
     } else {
-        reqwest::Client::new()
+        rv_http_client("ruby_install")?
     };
 
     let mut request_builder = client.get(url);
@@ -332,272 +934,11 @@ async fn fetch_url(url: &str, redirects: bool) -> Result<reqwest::Response> {
     Ok(request_builder.send().await?)
 }
 
-fn extract_ruby_archive(
-    archive_path: &Utf8Path,
-    rubies_dir: &Utf8Path,
-    version: &str,
-) -> Result<()> {
-    let host = HostPlatform::current()?;
-    let span = info_span!("Installing Ruby", version);
-    span.pb_set_style(&ProgressStyle::with_template("{spinner:.green} {span_name}").unwrap());
-    let _guard = span.enter();
-
-    if !rubies_dir.exists() {
-        fs_err::create_dir_all(rubies_dir)?;
-    }
-
-    // Determine archive type by extension
-    let extension = archive_path.extension().unwrap_or("");
-    match extension {
-        "zip" => extract_zip(archive_path, rubies_dir, version),
-        "7z" => extract_7z(archive_path, rubies_dir, version, &host),
-        _ => extract_tarball(archive_path, rubies_dir, version),
-    }
-}
-
-fn extract_tarball(tarball_path: &Utf8Path, rubies_dir: &Utf8Path, version: &str) -> Result<()> {
-    let tarball = fs_err::File::open(tarball_path)?;
-    let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(tarball));
-    let dst_dir: PathBuf = rubies_dir.as_std_path().join(format!("ruby-{}", version));
-    for e in archive.entries()? {
-        let mut entry = e?;
-        let entry_path = entry.path()?;
-
-        let mut dst_file = dst_dir.to_path_buf();
-
-        // Strip the first two path components
-        let mut path = entry_path.components();
-        path.next();
-        path.next();
-
-        // Copied from
-        // https://github.com/composefs/tar-rs/blob/fc459c149f83bf4daceaa52e17d351989002e1a9/src/entry.rs#L404-L419,
-        // xcept we raise an error if we find a path with ".." inside the archive, rather than
-        // skipping extraction of that particular file.
-        for part in path {
-            match part {
-                // Leading '/' characters, root paths, and '.'
-                // components are just ignored and treated as "empty
-                // components"
-                Component::Prefix(..) | Component::RootDir | Component::CurDir => continue,
-
-                // If any part of the filename is '..', then skip over
-                // unpacking the file to prevent directory traversal
-                // security issues.  See, e.g.: CVE-2001-1267,
-                // CVE-2002-0399, CVE-2005-1918, CVE-2007-4131
-                Component::ParentDir => {
-                    return Err(Error::DirectoryTraversalError(
-                        entry_path.display().to_string(),
-                    ));
-                }
-
-                Component::Normal(part) => dst_file.push(part),
-            }
-        }
-
-        crate::tar_utils::unpack_entry(&mut entry, &dst_file)?;
-    }
-    Ok(())
-}
-
-fn extract_zip(zip_path: &Utf8Path, rubies_dir: &Utf8Path, version: &str) -> Result<()> {
-    let file = fs_err::File::open(zip_path)?;
-    let mut archive = zip::ZipArchive::new(file)?;
-
-    for i in 0..archive.len() {
-        let mut entry = archive.by_index(i)?;
-        let entry_path = entry.name().to_string();
-
-        // Normalize path: repackage RubyInstaller format to rv format
-        let path = entry_path
-            .replace(
-                &format!("rubyinstaller-{}", version),
-                &format!("ruby-{}", version),
-            )
-            .replace('\\', "/"); // Normalize Windows path separators
-
-        if path.contains("..") {
-            return Err(Error::DirectoryTraversalError(path));
-        }
-
-        let dst = rubies_dir.join(&path);
-
-        if entry.is_dir() {
-            fs_err::create_dir_all(&dst)?;
-        } else {
-            if let Some(parent) = dst.parent() {
-                fs_err::create_dir_all(parent)?;
-            }
-            let mut outfile = fs_err::File::create(&dst)?;
-            std::io::copy(&mut entry, &mut outfile)?;
-        }
-    }
-    Ok(())
-}
-
-fn entry_extract_fn(
-    entry: &sevenz_rust2::ArchiveEntry,
-    reader: &mut dyn std::io::Read,
-    dest: &PathBuf,
-) -> std::result::Result<bool, sevenz_rust2::Error> {
-    sevenz_rust2::default_entry_extract_fn(entry, reader, dest)
-}
-
-fn extract_7z(
-    archive_path: &Utf8Path,
-    rubies_dir: &Utf8Path,
-    version: &str,
-    host: &HostPlatform,
-) -> Result<()> {
-    // Extract 7z archive to rubies_dir
-    sevenz_rust2::decompress_file_with_extract_fn(
-        archive_path.as_std_path(),
-        rubies_dir.as_std_path(),
-        entry_extract_fn,
-    )?;
-
-    // RubyInstaller2 extracts to: rubyinstaller-{request}-1-{arch}/
-    // Dev builds extract to: rubyinstaller-head-{arch}/ (no revision number)
-    // We need to rename it to: ruby-{request}/
-    let arch = host.ruby_arch_str();
-    let extracted_dir = if version == "dev" {
-        rubies_dir.join(format!("rubyinstaller-head-{arch}"))
-    } else {
-        rubies_dir.join(format!("rubyinstaller-{}-1-{arch}", version))
-    };
-    let target_dir = rubies_dir.join(format!("ruby-{}", version));
-
-    if extracted_dir.exists() {
-        fs_err::rename(&extracted_dir, &target_dir)?;
-    }
-
-    Ok(())
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
     use assert_fs::TempDir;
     use assert_fs::prelude::*;
-    use std::io::Write as _;
-
-    #[test]
-    fn test_ruby_url_unix() {
-        let host = HostPlatform::from_target_triple("aarch64-apple-darwin").unwrap();
-        let url = ruby_url("3.4.1", &host);
-
-        assert_eq!(
-            url,
-            "https://github.com/spinel-coop/rv-ruby/releases/latest/download/ruby-3.4.1.arm64_sonoma.tar.gz"
-        );
-    }
-
-    #[test]
-    fn test_ruby_url_windows() {
-        let host = HostPlatform::from_target_triple("x86_64-pc-windows-msvc").unwrap();
-        let url = ruby_url("3.4.1", &host);
-
-        assert_eq!(
-            url,
-            "https://github.com/oneclick/rubyinstaller2/releases/download/RubyInstaller-3.4.1-1/rubyinstaller-3.4.1-1-x64.7z"
-        );
-    }
-
-    #[test]
-    fn test_ruby_url_windows_arm64() {
-        let host = HostPlatform::from_target_triple("aarch64-pc-windows-msvc").unwrap();
-        let url = ruby_url("3.4.1", &host);
-
-        assert_eq!(
-            url,
-            "https://github.com/oneclick/rubyinstaller2/releases/download/RubyInstaller-3.4.1-1/rubyinstaller-3.4.1-1-arm.7z"
-        );
-    }
-
-    #[test]
-    fn test_ruby_url_unix_dev() {
-        let host = HostPlatform::from_target_triple("aarch64-apple-darwin").unwrap();
-        let url = ruby_url("dev", &host);
-
-        assert_eq!(
-            url,
-            "https://github.com/spinel-coop/rv-ruby-dev/releases/latest/download/ruby-dev.arm64_sonoma.tar.gz"
-        );
-    }
-
-    #[test]
-    fn test_ruby_url_windows_dev() {
-        let host = HostPlatform::from_target_triple("x86_64-pc-windows-msvc").unwrap();
-        let url = ruby_url("dev", &host);
-
-        assert_eq!(
-            url,
-            "https://github.com/oneclick/rubyinstaller2/releases/download/rubyinstaller-head/rubyinstaller-head-x64.7z"
-        );
-    }
-    #[test]
-    fn test_extract_zip_creates_correct_structure() {
-        let temp_dir = TempDir::new().unwrap();
-        let rubies_dir = temp_dir.child("rubies");
-        rubies_dir.create_dir_all().unwrap();
-
-        let zip_path = temp_dir.child("test-ruby.zip");
-        {
-            let file = std::fs::File::create(zip_path.path()).unwrap();
-            let mut zip = zip::ZipWriter::new(file);
-
-            let options: zip::write::SimpleFileOptions = Default::default();
-            zip.add_directory::<_, ()>("rubyinstaller-3.4.1/", options)
-                .unwrap();
-            zip.add_directory::<_, ()>("rubyinstaller-3.4.1/bin/", options)
-                .unwrap();
-
-            zip.start_file("rubyinstaller-3.4.1/bin/ruby.exe", options)
-                .unwrap();
-            zip.write_all(b"fake ruby executable").unwrap();
-
-            zip.finish().unwrap();
-        }
-
-        let rubies_path = Utf8Path::from_path(rubies_dir.path()).unwrap();
-        let zip_utf8_path = Utf8Path::from_path(zip_path.path()).unwrap();
-        extract_zip(zip_utf8_path, rubies_path, "3.4.1").unwrap();
-
-        let ruby_dir = rubies_dir.child("ruby-3.4.1");
-        assert!(ruby_dir.exists(), "ruby-3.4.1 directory should exist");
-
-        let bin_dir = ruby_dir.child("bin");
-        assert!(bin_dir.exists(), "bin directory should exist");
-
-        let ruby_exe = bin_dir.child("ruby.exe");
-        assert!(ruby_exe.exists(), "ruby.exe should exist");
-
-        let content = std::fs::read_to_string(ruby_exe.path()).unwrap();
-        assert_eq!(content, "fake ruby executable");
-    }
-
-    #[test]
-    fn test_extract_ruby_archive_delegates_to_zip_extractor() {
-        let temp_dir = TempDir::new().unwrap();
-        let rubies_dir = temp_dir.child("rubies");
-        rubies_dir.create_dir_all().unwrap();
-
-        let zip_path = temp_dir.child("test.zip");
-        {
-            let file = std::fs::File::create(zip_path.path()).unwrap();
-            let mut zip = zip::ZipWriter::new(file);
-            let options: zip::write::SimpleFileOptions = Default::default();
-            zip.add_directory::<_, ()>("rubyinstaller-3.4.1/", options)
-                .unwrap();
-            zip.finish().unwrap();
-        }
-
-        let rubies_path = Utf8Path::from_path(rubies_dir.path()).unwrap();
-        let zip_utf8_path = Utf8Path::from_path(zip_path.path()).unwrap();
-
-        let result = extract_ruby_archive(zip_utf8_path, rubies_path, "3.4.1");
-        assert!(result.is_ok());
-    }
 
     #[test]
     fn test_valid_archive_exists_returns_false_for_missing_file() {
@@ -0,0 +1,329 @@
+//! Builds rubies from source, following build definitions that organizations write for the
+//! rubies they patch, so that `rv ruby install our-ruby-3.3.9` works like installing a release.
+//!
+//! Definitions are KDL files named after the Ruby they build, like `our-ruby-3.3.9.kdl`, in the
+//! `build-definitions` directory (see docs/SETTINGS.md):
+//!
+//! ```kdl
+//! url "https://cache.ruby-lang.org/pub/ruby/3.3/ruby-3.3.9.tar.gz"
+//! sha256 "…"
+//! patch "patches/openssl-fix.patch"
+//! configure "--disable-install-doc" "--with-jemalloc"
+//! ```
+
+use std::process::{Command, Stdio};
+
+use anstream::println;
+use camino::{Utf8Path, Utf8PathBuf};
+use indicatif::ProgressStyle;
+use owo_colors::OwoColorize;
+use rv_core::provenance::Provenance;
+use tracing::{debug, info_span};
+use tracing_indicatif::span_ext::IndicatifSpanExt;
+use url::Url;
+
+use super::{
+    Error, InstallDir, Result, download_custom_archive, ensure_writable, ruby_dir_lock_path,
+    sha256_file,
+};
+use crate::config::Config;
+
+/// How to build one Ruby from source.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct Definition {
+    /// The name of the definition, which is also the name the Ruby is installed as.
+    pub name: String,
+    /// Where to download the source tarball from.
+    pub url: Url,
+    /// The expected SHA256 digest of the source tarball, if the definition pins one.
+    pub sha256: Option<String>,
+    /// Patches to apply to the source with `patch -p1`, in order.
+    pub patches: Vec<Utf8PathBuf>,
+    /// Extra arguments for `./configure`, after `--prefix`.
+    pub configure_flags: Vec<String>,
+}
+
+impl Definition {
+    /// The definition called `name` in `dir`, if there is one.
+    pub fn find(dir: &Utf8Path, name: &str) -> Result<Option<Self>> {
+        if name.contains(['/', '\\']) || name.starts_with('.') {
+            return Ok(None);
+        }
+        let path = dir.join(format!("{name}.kdl"));
+        if !path.is_file() {
+            return Ok(None);
+        }
+
+        debug!("Found build definition {path}");
+        let text = fs_err::read_to_string(&path)?;
+        Self::parse(name, &path, &text)
+            .map(Some)
+            .map_err(|reason| Error::InvalidDefinition { path, reason })
+    }
+
+    /// Parse the definition at `path`. Patches are relative to the definition's directory.
+    fn parse(name: &str, path: &Utf8Path, text: &str) -> std::result::Result<Self, String> {
+        let doc: kdl::KdlDocument = text.parse().map_err(|err: kdl::KdlError| err.to_string())?;
+        let dir = path.parent().unwrap_or(Utf8Path::new("."));
+
+        let mut url = None;
+        let mut sha256 = None;
+        let mut patches = Vec::new();
+        let mut configure_flags = Vec::new();
+        for node in doc.nodes() {
+            let key = node.name().value();
+            let values = node
+                .entries()
+                .iter()
+                .map(|entry| match entry.value() {
+                    kdl::KdlValue::String(value) => Ok(value.clone()),
+                    other => Err(format!("'{key}' expects strings, but found {other}")),
+                })
+                .collect::<std::result::Result<Vec<_>, _>>()?;
+
+            match (key, values.as_slice()) {
+                ("url", [value]) => {
+                    let parsed = Url::parse(value).map_err(|err| format!("'url' {err}"))?;
+                    url = Some(parsed);
+                }
+                ("sha256", [value]) => sha256 = Some(value.to_ascii_lowercase()),
+                ("patch", [value]) => patches.push(dir.join(value)),
+                ("configure", values) => configure_flags.extend(values.iter().cloned()),
+                ("url" | "sha256" | "patch", _) => {
+                    return Err(format!("'{key}' expects exactly one argument"));
+                }
+                _ => return Err(format!("Invalid key '{key}'")),
+            }
+        }
+
+        Ok(Self {
+            name: name.to_owned(),
+            url: url.ok_or("'url' is missing")?,
+            sha256,
+            patches,
+            configure_flags,
+        })
+    }
+
+    /// How the build differs from building the source as it is, for the record: the patches
+    /// applied and the flags given to `./configure`.
+    fn build_flags(&self) -> Vec<String> {
+        let patches = self.patches.iter().map(|patch| format!("patch:{patch}"));
+        patches
+            .chain(self.configure_flags.iter().cloned())
+            .collect()
+    }
+}
+
+/// Download, patch, configure, compile and install the Ruby `definition` describes, returning the
+/// directory it's installed in.
+pub(crate) async fn build(
+    config: &Config,
+    install_dir: InstallDir,
+    definition: &Definition,
+    force: bool,
+) -> Result<Utf8PathBuf> {
+    if cfg!(windows) {
+        return Err(Error::SourceBuildUnsupported);
+    }
+
+    let name = definition.name.as_str();
+    let install_dir = install_dir.resolve(config);
+    let ruby_dir = install_dir.join(name);
+    if ruby_dir.exists() && !force {
+        println!("{name} is already installed. If you want to overwrite it, use '--force'.");
+
+        return Ok(ruby_dir);
+    }
+
+    ensure_writable(&install_dir)?;
+
+    let _lock = rv_cache::LockedFile::acquire_async(
+        ruby_dir_lock_path(&ruby_dir),
+        &format!("ruby dir {ruby_dir}"),
+        config.cache.lock_wait(),
+    )
+    .await
+    .map_err(Error::LockFailed)?;
+
+    let tarball = download_custom_archive(config, &definition.url, name).await?;
+    let actual = sha256_file(&tarball)?;
+    if let Some(expected) = &definition.sha256
+        && &actual != expected
+    {
+        // Don't keep a bad download around to be found again next time.
+        fs_err::remove_file(&tarball)?;
+        return Err(Error::SourceChecksumMismatch {
+            url: definition.url.to_string(),
+            expected: expected.clone(),
+            actual,
+        });
+    }
+
+    let build_dir = config
+        .cache
+        .shard(rv_cache::CacheBucket::Ruby, "builds")
+        .join(name);
+    if build_dir.exists() {
+        fs_err::remove_dir_all(&build_dir)?;
+    }
+    fs_err::create_dir_all(&build_dir)?;
+
+    let span = info_span!("Building Ruby", name);
+    span.pb_set_style(&ProgressStyle::with_template("{spinner:.green} {span_name} {msg}").unwrap());
+    let _guard = span.enter();
+
+    let source_dir = unpack_source(&tarball, &build_dir)?;
+    let log_path = build_dir.join("build.log");
+    let log = fs_err::File::create(&log_path)?;
+    let run = |step: &str, command: &mut Command| -> Result<()> {
+        span.pb_set_message(step);
+        debug!("Running {step} in {source_dir}");
+        let status = command
+            .current_dir(&source_dir)
+            .stdin(Stdio::null())
+            .stdout(log.file().try_clone()?)
+            .stderr(log.file().try_clone()?)
+            .status()?;
+        if status.success() {
+            Ok(())
+        } else {
+            Err(Error::BuildFailed {
+                name: name.to_owned(),
+                step: step.to_owned(),
+                log: log_path.clone(),
+            })
+        }
+    };
+
+    for patch in &definition.patches {
+        run(
+            &format!("patch {}", patch.file_name().unwrap_or(patch.as_str())),
+            Command::new("patch")
+                .arg("-p1")
+                .arg("--forward")
+                .arg("-i")
+                .arg(patch),
+        )?;
+    }
+    if !source_dir.join("configure").exists() && source_dir.join("autogen.sh").exists() {
+        run("./autogen.sh", &mut Command::new("./autogen.sh"))?;
+    }
+    run(
+        "./configure",
+        Command::new("./configure")
+            .arg(format!("--prefix={ruby_dir}"))
+            .args(&definition.configure_flags),
+    )?;
+    let jobs = std::thread::available_parallelism().map_or(1, usize::from);
+    run("make", Command::new("make").arg(format!("-j{jobs}")))?;
+    if let Err(err) = run("make install", Command::new("make").arg("install")) {
+        // Don't leave a half-installed Ruby behind.
+        if ruby_dir.exists() {
+            fs_err::remove_dir_all(&ruby_dir)?;
+        }
+        return Err(err);
+    }
+
+    // The build succeeded, so there's nothing in the build directory worth looking at.
+    fs_err::remove_dir_all(&build_dir)?;
+
+    let mut provenance = Provenance::new(
+        definition.url.as_str(),
+        Some(actual),
+        env!("CARGO_PKG_VERSION"),
+    );
+    provenance.build_flags = definition.build_flags();
+    provenance.write(&ruby_dir)?;
+
+    println!("Installed {} to {}", name.cyan(), install_dir.cyan());
+
+    Ok(ruby_dir)
+}
+
+/// Unpack a source tarball into `build_dir`, returning the directory the source is in: the
+/// tarball's only top-level directory, like `ruby-3.3.9`, or `build_dir` itself.
+fn unpack_source(tarball: &Utf8Path, build_dir: &Utf8Path) -> Result<Utf8PathBuf> {
+    let file = fs_err::File::open(tarball)?;
+    let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(file));
+    rv_core::tar_utils::unpack_tar(&mut archive, build_dir.as_std_path())?;
+
+    let entries = build_dir
+        .read_dir_utf8()?
+        .map(|entry| entry.map(|entry| entry.path().to_path_buf()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    match entries.as_slice() {
+        [dir] if dir.is_dir() => Ok(dir.clone()),
+        _ => Ok(build_dir.to_path_buf()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_definition() {
+        let text = r#"
+            url "https://example.com/ruby-3.3.9.tar.gz"
+            sha256 "ABC123"
+            patch "patches/one.patch"
+            patch "/abs/two.patch"
+            configure "--disable-install-doc" "--with-jemalloc"
+            configure "--enable-shared"
+        "#;
+        let path = Utf8Path::new("/defs/our-ruby-3.3.9.kdl");
+        let definition = Definition::parse("our-ruby-3.3.9", path, text).unwrap();
+
+        assert_eq!(
+            definition,
+            Definition {
+                name: "our-ruby-3.3.9".into(),
+                url: Url::parse("https://example.com/ruby-3.3.9.tar.gz").unwrap(),
+                sha256: Some("abc123".into()),
+                patches: vec!["/defs/patches/one.patch".into(), "/abs/two.patch".into()],
+                configure_flags: vec![
+                    "--disable-install-doc".into(),
+                    "--with-jemalloc".into(),
+                    "--enable-shared".into()
+                ],
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid_definitions() {
+        let path = Utf8Path::new("/defs/x.kdl");
+        let error = |text| Definition::parse("x", path, text).unwrap_err();
+
+        assert_eq!(error("sha256 \"abc\""), "'url' is missing");
+        assert_eq!(
+            error("url \"https://a/b\"\nmake \"-j2\""),
+            "Invalid key 'make'"
+        );
+        assert_eq!(
+            error("url \"https://a/b\" \"https://c/d\""),
+            "'url' expects exactly one argument"
+        );
+        assert_eq!(
+            error("url \"https://a/b\"\nconfigure 1"),
+            "'configure' expects strings, but found 1"
+        );
+    }
+
+    #[test]
+    fn test_find_definition() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let dir = Utf8Path::from_path(temp_dir.path()).unwrap();
+        fs_err::write(dir.join("our-ruby.kdl"), "url \"https://a/b.tar.gz\"").unwrap();
+        fs_err::write(dir.join("broken.kdl"), "url").unwrap();
+
+        assert!(Definition::find(dir, "our-ruby").unwrap().is_some());
+        assert!(Definition::find(dir, "3.3.9").unwrap().is_none());
+        assert!(Definition::find(dir, "../our-ruby").unwrap().is_none());
+        assert!(matches!(
+            Definition::find(dir, "broken"),
+            Err(Error::InvalidDefinition { .. })
+        ));
+    }
+}
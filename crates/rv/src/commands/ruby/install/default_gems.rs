@@ -0,0 +1,263 @@
+//! Sets up the gems of a Ruby rv just installed. Prebuilt rubies ship with the RubyGems and
+//! Bundler that were current when they were released, which can be far behind, so
+//! `--update-rubygems`, `--bundler` and their settings bring them up to date, and the
+//! `default-gems` setting installs the gems every Ruby should have, like rbenv's default-gems
+//! plugin, before anything else runs the new Ruby.
+
+use std::process::{Command, Stdio};
+
+use anstream::println;
+use camino::Utf8Path;
+use indicatif::ProgressStyle;
+use owo_colors::OwoColorize;
+use rv_core::provenance::Provenance;
+use rv_ruby::Ruby;
+use tracing::{debug, info_span};
+use tracing_indicatif::span_ext::IndicatifSpanExt;
+
+use super::{Error, Result};
+use crate::config::Config;
+use crate::config::rv_settings::RvSettings;
+
+/// What's meant by giving `--update-rubygems` or `--bundler` without a version.
+pub(crate) const LATEST: &str = "latest";
+
+/// Variables that would make `gem` install into some other gem home, or load code that isn't
+/// part of the new Ruby, rather than set up the Ruby's own gems.
+const CLEARED_ENV: [&str; 4] = ["GEM_HOME", "GEM_PATH", "RUBYOPT", "RUBYLIB"];
+
+/// The versions of RubyGems and Bundler to put into a newly installed Ruby, each a version like
+/// `3.6.9` or `latest`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct DefaultGemUpdates {
+    pub rubygems: Option<String>,
+    pub bundler: Option<String>,
+}
+
+impl DefaultGemUpdates {
+    /// These updates, with the `update-rubygems` and `bundler-version` settings filling in
+    /// whatever the command line didn't ask for.
+    pub fn or_settings(self, settings: &RvSettings) -> Self {
+        Self {
+            rubygems: self.rubygems.or_else(|| settings.update_rubygems.clone()),
+            bundler: self.bundler.or_else(|| settings.bundler_version.clone()),
+        }
+    }
+
+    /// The `gem` commands that make the updates and install `gems`, and the gem each one
+    /// updates. Each of `gems` is a gem name, optionally followed by a version requirement,
+    /// like `rubocop ~> 1.65`.
+    fn commands(&self, gems: &[String]) -> Vec<(String, Vec<String>)> {
+        let mut commands = Vec::new();
+        if let Some(version) = &self.rubygems {
+            let mut args = vec!["update".to_owned(), "--system".to_owned()];
+            if version != LATEST {
+                args.push(version.clone());
+            }
+            args.push("--no-document".to_owned());
+            commands.push(("rubygems".to_owned(), args));
+        }
+        if let Some(version) = &self.bundler {
+            let mut args = vec!["install".to_owned(), "bundler".to_owned()];
+            if version != LATEST {
+                args.extend(["--version".to_owned(), version.clone()]);
+            }
+            args.push("--no-document".to_owned());
+            commands.push(("bundler".to_owned(), args));
+        }
+        for gem in gems
+            .iter()
+            .map(|gem| gem.trim())
+            .filter(|gem| !gem.is_empty())
+        {
+            let (name, requirement) = match gem.split_once(char::is_whitespace) {
+                Some((name, requirement)) => (name, Some(requirement.trim())),
+                None => (gem, None),
+            };
+            let mut args = vec!["install".to_owned(), name.to_owned()];
+            if let Some(requirement) = requirement {
+                args.extend(["--version".to_owned(), requirement.to_owned()]);
+            }
+            args.push("--no-document".to_owned());
+            commands.push((name.to_owned(), args));
+        }
+        commands
+    }
+}
+
+/// Make `updates` to the Ruby in `ruby_dir`, along with whatever the settings ask for, and
+/// record the versions it ends up with in its provenance.
+pub(super) fn update(
+    config: &Config,
+    ruby_dir: &Utf8Path,
+    updates: DefaultGemUpdates,
+) -> Result<()> {
+    let settings = &config.rv_settings;
+    let updates = updates.or_settings(settings);
+    // Which `default-gems` apply depends on the Ruby, which is only worth running to find out
+    // when there's something to do.
+    if updates == DefaultGemUpdates::default() && settings.default_gems.is_empty() {
+        return Ok(());
+    }
+
+    let ruby =
+        Ruby::from_dir(ruby_dir.to_path_buf(), true).map_err(|source| Error::InvalidArchive {
+            archive: ruby_dir.to_string(),
+            source,
+        })?;
+    let gems = settings
+        .default_gems_for(&ruby.version)
+        .map_err(crate::config::Error::from)?;
+    let commands = updates.commands(&gems);
+    if commands.is_empty() {
+        return Ok(());
+    }
+
+    let span = info_span!("Setting up gems");
+    span.pb_set_style(&ProgressStyle::with_template("{spinner:.green} {span_name}").unwrap());
+    let _guard = span.enter();
+
+    let mut updated = Vec::new();
+    for (gem, args) in commands {
+        run_gem(&ruby, &args)?;
+        let version = installed_version(&ruby, &gem)?;
+        let verb = if gem == "rubygems" {
+            "Updated"
+        } else {
+            "Installed"
+        };
+        println!("{verb} {gem} {} in {}", version.cyan(), ruby.version.cyan());
+        updated.push((gem, version));
+    }
+
+    // A Ruby without provenance wasn't installed by rv, and isn't one to start recording it for.
+    if let Some(mut provenance) = Provenance::read(ruby_dir) {
+        provenance.default_gem_updates.extend(updated);
+        provenance.write(ruby_dir)?;
+    }
+    Ok(())
+}
+
+/// Run the new Ruby's own `gem` with `args`.
+fn run_gem(ruby: &Ruby, args: &[String]) -> Result<()> {
+    let gem = ruby.bin_path().join("gem");
+    let mut cmd = ruby_command(ruby);
+    cmd.arg(&gem).args(args);
+    debug!("Running {cmd:?}");
+
+    let output = cmd.output()?;
+    if output.status.success() {
+        return Ok(());
+    }
+    // `gem` explains what went wrong on stderr, but some failures only reach stdout.
+    let output = if output.stderr.is_empty() {
+        output.stdout
+    } else {
+        output.stderr
+    };
+    Err(Error::DefaultGemUpdateFailed {
+        ruby: ruby.version.to_string(),
+        command: format!("gem {}", args.join(" ")),
+        output: String::from_utf8_lossy(&output).trim().to_owned(),
+    })
+}
+
+/// The version of `gem`, which is `rubygems` or the name of a gem, that the new Ruby now loads.
+fn installed_version(ruby: &Ruby, gem: &str) -> Result<String> {
+    let script = match gem {
+        "rubygems" => "print Gem::VERSION",
+        _ => "print Gem::Specification.find_by_name(ARGV[0]).version",
+    };
+    let output = ruby_command(ruby).arg("-e").arg(script).arg(gem).output()?;
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_owned())
+}
+
+fn ruby_command(ruby: &Ruby) -> Command {
+    let mut cmd = Command::new(ruby.executable_path());
+    cmd.stdin(Stdio::null());
+    for var in CLEARED_ENV {
+        cmd.env_remove(var);
+    }
+    cmd
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_or_settings() {
+        let settings = RvSettings {
+            update_rubygems: Some("latest".into()),
+            bundler_version: Some("2.6.9".into()),
+            ..Default::default()
+        };
+
+        let updates = DefaultGemUpdates {
+            rubygems: None,
+            bundler: Some("2.7.1".into()),
+        }
+        .or_settings(&settings);
+        assert_eq!(
+            updates,
+            DefaultGemUpdates {
+                rubygems: Some("latest".into()),
+                bundler: Some("2.7.1".into()),
+            }
+        );
+
+        let updates = DefaultGemUpdates::default().or_settings(&RvSettings::default());
+        assert!(updates.commands(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_commands() {
+        let updates = DefaultGemUpdates {
+            rubygems: Some(LATEST.into()),
+            bundler: Some("2.6.9".into()),
+        };
+        assert_eq!(
+            updates.commands(&[]),
+            vec![
+                (
+                    "rubygems".to_owned(),
+                    vec![
+                        "update".to_owned(),
+                        "--system".to_owned(),
+                        "--no-document".to_owned()
+                    ]
+                ),
+                (
+                    "bundler".to_owned(),
+                    vec![
+                        "install".to_owned(),
+                        "bundler".to_owned(),
+                        "--version".to_owned(),
+                        "2.6.9".to_owned(),
+                        "--no-document".to_owned()
+                    ]
+                ),
+            ]
+        );
+
+        let updates = DefaultGemUpdates {
+            rubygems: Some("3.6.9".into()),
+            bundler: None,
+        };
+        let commands = updates.commands(&["pry".into(), "rubocop ~> 1.65".into()]);
+        assert_eq!(commands[0].1[2], "3.6.9");
+        assert_eq!(commands[1].0, "pry");
+        assert_eq!(commands[1].1, ["install", "pry", "--no-document"]);
+        assert_eq!(commands[2].0, "rubocop");
+        assert_eq!(
+            commands[2].1,
+            [
+                "install",
+                "rubocop",
+                "--version",
+                "~> 1.65",
+                "--no-document"
+            ]
+        );
+    }
+}
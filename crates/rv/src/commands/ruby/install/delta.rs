@@ -0,0 +1,224 @@
+//! Delta downloads between patch releases. When the release source has a patch from the previous
+//! patch release's archive to the requested one, made with `zstd --patch-from`, and that archive is
+//! still in the cache, rv downloads the patch instead of the whole archive and applies it.
+//!
+//! Patches are named after the archive they produce, e.g.
+//! `ruby-3.4.2.x86_64_linux.tar.gz.from-3.4.1.zst`. Anything going wrong falls back to
+//! downloading the full archive.
+
+use std::io::{self, BufReader};
+use std::str::FromStr;
+
+use camino::Utf8Path;
+use rv_platform::HostPlatform;
+use rv_ruby::engine::RubyEngine;
+use rv_ruby::version::RubyVersion;
+use tracing::debug;
+use url::Url;
+
+use super::{
+    Error, Result, archive_cache_path, fetch_url, temp_archive_path, valid_archive_exists,
+};
+use crate::config::Config;
+use crate::config::release_source::{ArchiveLocation, ReleaseSource};
+
+/// The largest window a patch may use, which `zstd --patch-from` needs to reach back across a
+/// whole archive. This is zstd's limit on 64-bit platforms.
+const MAX_WINDOW_LOG: u32 = 31;
+
+/// Try to produce the archive of `version`, from `url`, at `archive_path` by patching the cached
+/// archive of the previous patch release. Returns whether it worked.
+pub(super) async fn download(
+    config: &Config,
+    source: &dyn ReleaseSource,
+    version: &str,
+    url: &str,
+    archive_path: &Utf8Path,
+    host: &HostPlatform,
+) -> bool {
+    let Some(previous) = previous_patch_release(version) else {
+        return false;
+    };
+    let ArchiveLocation::Url(previous_url) = source.archive(&previous, host) else {
+        return false;
+    };
+    let previous_archive = archive_cache_path(config, &previous_url, host);
+    if !valid_archive_exists(&previous_archive) {
+        debug!("No cached archive of Ruby {previous} to patch");
+        return false;
+    }
+
+    let Some(patch_url) = patch_url(config, source, url, &previous).await else {
+        debug!("The ruby source has no patch from Ruby {previous} to {version}");
+        return false;
+    };
+
+    match apply_patch(
+        config,
+        &patch_url,
+        &previous_archive,
+        url,
+        archive_path,
+        host,
+    )
+    .await
+    {
+        Ok(()) => {
+            debug!("Patched the archive of Ruby {previous} into {version}");
+            true
+        }
+        Err(err) => {
+            debug!("Downloading the full archive, since patching failed: {err}");
+            false
+        }
+    }
+}
+
+/// The patch release before `version`, like 3.4.1 for 3.4.2, if there is one.
+fn previous_patch_release(version: &str) -> Option<String> {
+    let version = RubyVersion::from_str(version).ok()?;
+    if version.engine != RubyEngine::Ruby
+        || version.patch == 0
+        || version.tiny.is_some()
+        || version.prerelease.is_some()
+    {
+        return None;
+    }
+    Some(format!(
+        "{}.{}.{}",
+        version.major,
+        version.minor,
+        version.patch - 1
+    ))
+}
+
+/// The name of the patch from the archive of `previous` to the archive at `url`.
+fn patch_name(url: &str, previous: &str) -> Option<String> {
+    let archive_name = url.rsplit('/').next().filter(|name| !name.is_empty())?;
+    Some(format!("{archive_name}.from-{previous}.zst"))
+}
+
+/// Where the source has the patch from `previous` to the archive at `url`, going by the assets
+/// of its release.
+async fn patch_url(
+    config: &Config,
+    source: &dyn ReleaseSource,
+    url: &str,
+    previous: &str,
+) -> Option<String> {
+    let name = patch_name(url, previous)?;
+    let release = match source.release(&config.cache, false).await {
+        Ok(release) => release,
+        Err(_) => source.cached_release(&config.cache)?,
+    };
+    let asset = release
+        .assets
+        .into_iter()
+        .find(|asset| asset.name == name)?;
+    // Directory indexes may link to assets relative to the archive's directory.
+    let patch_url = Url::parse(url)
+        .and_then(|url| url.join(&asset.browser_download_url))
+        .ok()?;
+    Some(patch_url.to_string())
+}
+
+/// Download the patch at `patch_url` and apply it to `previous_archive`, writing the result to
+/// `archive_path` once it's complete.
+async fn apply_patch(
+    config: &Config,
+    patch_url: &str,
+    previous_archive: &Utf8Path,
+    url: &str,
+    archive_path: &Utf8Path,
+    host: &HostPlatform,
+) -> Result<()> {
+    debug!("Downloading patch from {patch_url}");
+    let response = fetch_url(patch_url, true).await?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(Error::DownloadFailed {
+            url: patch_url.to_string(),
+            status,
+            body: String::new(),
+        });
+    }
+    let patch = response.bytes().await?;
+
+    let previous_archive = previous_archive.to_owned();
+    let temp_path = temp_archive_path(config, url, host);
+    let archive_path = archive_path.to_owned();
+    tokio::task::spawn_blocking(move || -> io::Result<()> {
+        let base = fs_err::read(&previous_archive)?;
+        let mut output = fs_err::File::create(&temp_path)?;
+        if let Err(err) = patch_archive(&base, &patch, &mut output) {
+            drop(output);
+            fs_err::remove_file(&temp_path)?;
+            return Err(err);
+        }
+        output.sync_all()?;
+        fs_err::rename(&temp_path, &archive_path)
+    })
+    .await
+    .expect("patching the archive should not panic")?;
+
+    Ok(())
+}
+
+/// Decompress `patch` with `base` as its reference, like `zstd -d --patch-from`. zstd checks the
+/// result against the checksum in the patch.
+fn patch_archive(base: &[u8], patch: &[u8], output: &mut impl io::Write) -> io::Result<u64> {
+    let mut decoder = zstd::stream::read::Decoder::with_dictionary(BufReader::new(patch), base)?;
+    decoder.window_log_max(MAX_WINDOW_LOG)?;
+    io::copy(&mut decoder, output)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    #[test]
+    fn test_previous_patch_release() {
+        assert_eq!(previous_patch_release("3.4.2").as_deref(), Some("3.4.1"));
+        assert_eq!(previous_patch_release("3.4.0"), None);
+        assert_eq!(previous_patch_release("3.5.0-preview1"), None);
+        assert_eq!(previous_patch_release("dev"), None);
+    }
+
+    #[test]
+    fn test_patch_name() {
+        assert_eq!(
+            patch_name(
+                "https://github.com/spinel-coop/rv-ruby/releases/latest/download/ruby-3.4.2.x86_64_linux.tar.gz",
+                "3.4.1"
+            )
+            .as_deref(),
+            Some("ruby-3.4.2.x86_64_linux.tar.gz.from-3.4.1.zst")
+        );
+    }
+
+    #[test]
+    fn test_patch_archive() {
+        let base: Vec<u8> = (0..100_000u32)
+            .flat_map(|i| (i % 251).to_le_bytes())
+            .collect();
+        let mut target = base.clone();
+        target[1234..1240].copy_from_slice(b"3.4.2\n");
+
+        let mut encoder =
+            zstd::stream::write::Encoder::with_dictionary(Vec::new(), 3, &base).unwrap();
+        encoder.include_checksum(true).unwrap();
+        encoder.write_all(&target).unwrap();
+        let patch = encoder.finish().unwrap();
+        assert!(patch.len() < target.len() / 10);
+
+        let mut patched = Vec::new();
+        patch_archive(&base, &patch, &mut patched).unwrap();
+        assert_eq!(patched, target);
+
+        // A patch against some other archive is caught, rather than making a broken one.
+        let mut patched = Vec::new();
+        assert!(patch_archive(&target[..1000], &patch, &mut patched).is_err());
+    }
+}
@@ -0,0 +1,99 @@
+//! Gets a Ruby that was just unpacked ready to run on macOS. Files that came from a download can
+//! carry the `com.apple.quarantine` attribute, which makes Gatekeeper stall the first run of every
+//! binary to check it, and binaries whose ad hoc signature no longer matches them, like ones
+//! relocated after they were signed, are killed on Apple Silicon as soon as they're loaded.
+
+use std::process::{Command, Stdio};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use tracing::debug;
+
+use super::{Error, Result};
+
+/// Patterns, relative to a Ruby's directory, of its Mach-O binaries: the `ruby` executable,
+/// `libruby`, and the native extensions of the standard library.
+pub(crate) const BINARY_PATTERNS: [&str; 3] = ["bin/ruby", "lib/*.dylib", "lib/ruby/**/*.bundle"];
+
+const QUARANTINE_ATTRIBUTE: &str = "com.apple.quarantine";
+
+/// Remove the quarantine attribute from everything in `ruby_dir`, and sign its binaries again,
+/// ad hoc. Does nothing on other OSes.
+pub(super) fn prepare(ruby_dir: &Utf8Path) -> Result<()> {
+    if !cfg!(target_os = "macos") {
+        return Ok(());
+    }
+
+    // `xattr` fails for files that don't have the attribute, which is most of them, so its exit
+    // status says nothing worth acting on.
+    let status = Command::new("xattr")
+        .args(["-d", "-r", QUARANTINE_ATTRIBUTE])
+        .arg(ruby_dir)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()?;
+    debug!("Removing {QUARANTINE_ATTRIBUTE} from {ruby_dir} exited with {status}");
+
+    let binaries = binaries(ruby_dir);
+    if binaries.is_empty() {
+        return Ok(());
+    }
+    let output = Command::new("codesign")
+        .args(["--force", "--sign", "-"])
+        .args(&binaries)
+        .stdin(Stdio::null())
+        .output()?;
+    if !output.status.success() {
+        return Err(Error::SigningFailed {
+            ruby_dir: ruby_dir.to_path_buf(),
+            output: String::from_utf8_lossy(&output.stderr).trim().to_owned(),
+        });
+    }
+    debug!("Signed {} binaries in {ruby_dir}", binaries.len());
+    Ok(())
+}
+
+/// The Mach-O binaries of the Ruby in `ruby_dir`.
+fn binaries(ruby_dir: &Utf8Path) -> Vec<Utf8PathBuf> {
+    BINARY_PATTERNS
+        .iter()
+        .filter_map(|pattern| glob::glob(ruby_dir.join(pattern).as_str()).ok())
+        .flat_map(|paths| paths.flatten())
+        .filter(|path| path.is_file())
+        .filter_map(|path| Utf8PathBuf::from_path_buf(path).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_binaries() {
+        let dir = camino_tempfile::tempdir().unwrap();
+        let ruby_dir = dir.path();
+        for file in [
+            "bin/ruby",
+            "bin/irb",
+            "lib/libruby.3.4.dylib",
+            "lib/pkgconfig/ruby-3.4.pc",
+            "lib/ruby/3.4.0/arm64-darwin24/openssl.bundle",
+            "lib/ruby/3.4.0/openssl.rb",
+        ] {
+            let path = ruby_dir.join(file);
+            fs_err::create_dir_all(path.parent().unwrap()).unwrap();
+            fs_err::write(path, "").unwrap();
+        }
+
+        let mut binaries = binaries(ruby_dir);
+        binaries.sort();
+        assert_eq!(
+            binaries,
+            [
+                ruby_dir.join("bin/ruby"),
+                ruby_dir.join("lib/libruby.3.4.dylib"),
+                ruby_dir.join("lib/ruby/3.4.0/arm64-darwin24/openssl.bundle"),
+            ]
+        );
+    }
+}
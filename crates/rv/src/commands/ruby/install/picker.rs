@@ -0,0 +1,241 @@
+//! An interactive, fuzzy-searchable list of Ruby versions, for `rv ruby install` without a version.
+
+use std::io::{self, Write};
+
+use console::{Key, Term};
+use owo_colors::OwoColorize;
+use rv_ruby::version::RubyVersion;
+
+/// How many rows of versions and series headers to show at once.
+const MAX_VISIBLE_ROWS: usize = 12;
+
+/// Ask the user to pick one of `versions`. Returns `None` if they cancelled.
+pub(super) fn pick(versions: Vec<RubyVersion>) -> io::Result<Option<RubyVersion>> {
+    let term = Term::stdout();
+    // Write through anstream so `--color` and `NO_COLOR` are respected.
+    let mut stdout = anstream::stdout();
+    let mut picker = Picker::new(versions);
+    let mut drawn = 0;
+
+    let choice = loop {
+        term.clear_last_lines(drawn)?;
+        let lines = picker.render();
+        drawn = lines.len();
+        for line in &lines {
+            writeln!(stdout, "{line}")?;
+        }
+        stdout.flush()?;
+
+        match term.read_key() {
+            Ok(Key::Enter) => {
+                if let Some(version) = picker.selected() {
+                    break Some(version.clone());
+                }
+            }
+            Ok(Key::Escape | Key::CtrlC) => break None,
+            Ok(Key::ArrowUp) => picker.move_selection(-1),
+            Ok(Key::ArrowDown) => picker.move_selection(1),
+            Ok(Key::Backspace) => picker.pop_char(),
+            Ok(Key::Char(c)) if !c.is_control() => picker.push_char(c),
+            Ok(_) => {}
+            Err(err) => return Err(err),
+        }
+    };
+    term.clear_last_lines(drawn)?;
+
+    Ok(choice)
+}
+
+/// A line in the picker.
+#[derive(Debug, PartialEq, Eq)]
+enum Row {
+    /// A minor series header, like "3.4".
+    Series(String),
+    /// An index into `Picker::matches`.
+    Version(usize),
+}
+
+struct Picker {
+    /// Newest first.
+    versions: Vec<RubyVersion>,
+    query: String,
+    /// The versions that match the query, newest first.
+    matches: Vec<usize>,
+    /// Index into `matches`.
+    selected: usize,
+}
+
+impl Picker {
+    fn new(mut versions: Vec<RubyVersion>) -> Self {
+        versions.sort_by(|a, b| b.cmp(a));
+        versions.dedup();
+        let mut picker = Self {
+            versions,
+            query: String::new(),
+            matches: vec![],
+            selected: 0,
+        };
+        picker.update_matches();
+        picker
+    }
+
+    fn selected(&self) -> Option<&RubyVersion> {
+        self.matches
+            .get(self.selected)
+            .map(|&index| &self.versions[index])
+    }
+
+    fn push_char(&mut self, c: char) {
+        self.query.push(c);
+        self.update_matches();
+    }
+
+    fn pop_char(&mut self) {
+        self.query.pop();
+        self.update_matches();
+    }
+
+    fn move_selection(&mut self, delta: isize) {
+        if self.matches.is_empty() {
+            return;
+        }
+        let last = self.matches.len() - 1;
+        self.selected = self.selected.saturating_add_signed(delta).min(last);
+    }
+
+    /// Filter the versions by the query, and select the latest stable release that matches.
+    fn update_matches(&mut self) {
+        self.matches = (0..self.versions.len())
+            .filter(|&index| fuzzy_match(&self.query, &self.versions[index].number()))
+            .collect();
+        self.selected = self
+            .matches
+            .iter()
+            .position(|&index| self.versions[index].prerelease.is_none())
+            .unwrap_or(0);
+    }
+
+    /// The matching versions, under a header for each minor series.
+    fn rows(&self) -> Vec<Row> {
+        let mut rows = Vec::new();
+        let mut series = None;
+        for (position, &index) in self.matches.iter().enumerate() {
+            let version = &self.versions[index];
+            let this_series = format!("{}.{}", version.major, version.minor);
+            if series.as_ref() != Some(&this_series) {
+                rows.push(Row::Series(this_series.clone()));
+                series = Some(this_series);
+            }
+            rows.push(Row::Version(position));
+        }
+        rows
+    }
+
+    fn render(&self) -> Vec<String> {
+        let mut lines = vec![
+            format!(
+                "{} Select a Ruby to install: {}",
+                "?".green().bold(),
+                self.query.cyan()
+            ),
+            format!(
+                "  {}",
+                "Type to search, ↑/↓ to move, enter to install, esc to cancel".dimmed()
+            ),
+        ];
+
+        let rows = self.rows();
+        if rows.is_empty() {
+            lines.push(format!("  {}", "No matching versions".yellow()));
+            return lines;
+        }
+
+        // Scroll so the selected version stays visible.
+        let selected_row = rows
+            .iter()
+            .position(|row| *row == Row::Version(self.selected))
+            .unwrap_or(0);
+        let start = (selected_row + 1).saturating_sub(MAX_VISIBLE_ROWS);
+        for row in rows.iter().skip(start).take(MAX_VISIBLE_ROWS) {
+            lines.push(match row {
+                Row::Series(series) => format!("  {}", series.bold()),
+                Row::Version(position) if *position == self.selected => {
+                    let version = self.versions[self.matches[*position]].number();
+                    format!("  {} {}", "›".cyan(), version.cyan().bold())
+                }
+                Row::Version(position) => {
+                    format!("    {}", self.versions[self.matches[*position]].number())
+                }
+            });
+        }
+        lines
+    }
+}
+
+/// Whether every character of the query appears in the candidate, in order.
+fn fuzzy_match(query: &str, candidate: &str) -> bool {
+    let mut candidate = candidate.chars();
+    query
+        .chars()
+        .all(|q| candidate.any(|c| c.eq_ignore_ascii_case(&q)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn picker(versions: &[&str]) -> Picker {
+        Picker::new(versions.iter().map(|v| v.parse().unwrap()).collect())
+    }
+
+    #[test]
+    fn test_fuzzy_match() {
+        assert!(fuzzy_match("", "3.4.7"));
+        assert!(fuzzy_match("3.4", "3.4.7"));
+        assert!(fuzzy_match("347", "3.4.7"));
+        assert!(fuzzy_match("prev", "3.5.0-preview1"));
+        assert!(!fuzzy_match("3.5", "3.4.7"));
+        assert!(!fuzzy_match("74", "3.4.7"));
+    }
+
+    #[test]
+    fn test_defaults_to_latest_stable() {
+        let picker = picker(&["ruby-3.3.9", "ruby-4.0.0-preview2", "ruby-3.4.7"]);
+        assert_eq!(picker.selected().unwrap().number(), "3.4.7");
+    }
+
+    #[test]
+    fn test_search_and_move() {
+        let mut picker = picker(&["ruby-3.3.8", "ruby-3.3.9", "ruby-3.4.7"]);
+        picker.push_char('3');
+        picker.push_char('3');
+        assert_eq!(picker.selected().unwrap().number(), "3.3.9");
+
+        picker.move_selection(1);
+        assert_eq!(picker.selected().unwrap().number(), "3.3.8");
+        picker.move_selection(1);
+        assert_eq!(picker.selected().unwrap().number(), "3.3.8");
+        picker.move_selection(-5);
+        assert_eq!(picker.selected().unwrap().number(), "3.3.9");
+
+        picker.push_char('x');
+        assert!(picker.selected().is_none());
+        picker.pop_char();
+        assert_eq!(picker.selected().unwrap().number(), "3.3.9");
+    }
+
+    #[test]
+    fn test_rows_are_grouped_by_series() {
+        let picker = picker(&["ruby-3.3.9", "ruby-3.4.6", "ruby-3.4.7"]);
+        assert_eq!(
+            picker.rows(),
+            vec![
+                Row::Series("3.4".to_string()),
+                Row::Version(0),
+                Row::Version(1),
+                Row::Series("3.3".to_string()),
+                Row::Version(2),
+            ]
+        );
+    }
+}
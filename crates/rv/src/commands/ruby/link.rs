@@ -0,0 +1,76 @@
+use anstream::println;
+use camino::{Utf8Path, Utf8PathBuf};
+use owo_colors::OwoColorize;
+use rv_core::linked::LinkedRubies;
+use rv_ruby::Ruby;
+
+use crate::GlobalArgs;
+
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+pub enum Error {
+    #[error(transparent)]
+    #[diagnostic(code(RV1701))]
+    IoError(#[from] std::io::Error),
+    #[error("{dir} does not contain a Ruby that rv can use")]
+    #[diagnostic(
+        code(RV1702),
+        help("Link the directory Ruby was installed into, the one with `bin/ruby` inside")
+    )]
+    InvalidRuby {
+        dir: Utf8PathBuf,
+        #[source]
+        source: rv_ruby::RubyError,
+    },
+    #[error("{dir} is not a linked Ruby")]
+    #[diagnostic(code(RV1703), help("`rv ruby list` shows every Ruby rv knows about"))]
+    NotLinked { dir: Utf8PathBuf },
+}
+
+type Result<T> = miette::Result<T, Error>;
+
+/// Add the Ruby installed in `dir`, which rv didn't install and which isn't in any of the
+/// directories rv looks in, to the rubies rv finds.
+pub(crate) fn link(_global_args: &GlobalArgs, dir: &Utf8Path) -> Result<()> {
+    let dir = rv_dirs::canonicalize_utf8(dir)?;
+    let ruby = Ruby::from_dir(dir.clone(), false).map_err(|source| Error::InvalidRuby {
+        dir: dir.clone(),
+        source,
+    })?;
+
+    let data_dir = rv_dirs::user_data_dir(&rv_dirs::root_dir());
+    let mut linked = LinkedRubies::load(&data_dir)?;
+    if linked.add(dir.clone()) {
+        linked.save(&data_dir)?;
+        println!("Linked {} in {}", ruby.version.cyan(), dir.cyan());
+    } else {
+        println!(
+            "{} in {} is already linked",
+            ruby.version.cyan(),
+            dir.cyan()
+        );
+    }
+
+    Ok(())
+}
+
+/// Stop finding the Ruby in `dir`, which was linked with [`link`]. The Ruby itself is left as it
+/// is.
+pub(crate) fn unlink(_global_args: &GlobalArgs, dir: &Utf8Path) -> Result<()> {
+    // The Ruby may be gone already, which is a good reason to unlink it.
+    let dir = match rv_dirs::canonicalize_utf8(dir) {
+        Ok(dir) => dir,
+        Err(_) => {
+            Utf8PathBuf::try_from(std::path::absolute(dir)?).map_err(|err| err.into_io_error())?
+        }
+    };
+
+    let data_dir = rv_dirs::user_data_dir(&rv_dirs::root_dir());
+    let mut linked = LinkedRubies::load(&data_dir)?;
+    if !linked.remove(&dir) {
+        return Err(Error::NotLinked { dir });
+    }
+    linked.save(&data_dir)?;
+    println!("Unlinked {}", dir.cyan());
+
+    Ok(())
+}
@@ -1,6 +1,11 @@
+use camino::{Utf8Path, Utf8PathBuf};
 use clap::Args;
 use std::io;
-use std::{borrow::Cow, collections::BTreeMap};
+use std::time::Instant;
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap, HashSet},
+};
 use tabled::{
     Table,
     settings::{Panel, Span, Style, style::HorizontalLine, themes::BorderCorrection},
@@ -8,26 +13,43 @@ use tabled::{
 
 use anstream::println;
 use owo_colors::OwoColorize;
+use rv_core::external::ExternalKind;
+use rv_core::provenance::Provenance;
 use rv_ruby::{
-    RemoteRuby, Ruby, canonical_name::CanonicalName, request::RubyRequest, version::RubyVersion,
+    RemoteRuby, Ruby, Versioned, canonical_name::CanonicalName, engine::RubyEngine,
+    request::RubyRequest, version::RubyVersion,
 };
 use serde::Serialize;
 use tracing::{info, warn};
 
-use crate::{GlobalArgs, config::Config, output_format::OutputFormat};
+mod platforms;
+
+use crate::{
+    GlobalArgs,
+    config::{Config, inferred_ruby::InferredRuby},
+    output_format::OutputFormat,
+};
 
 #[derive(Debug, thiserror::Error, miette::Diagnostic)]
 pub enum Error {
     #[error(transparent)]
+    #[diagnostic(code(RV1101))]
     SerdeJsonError(#[from] serde_json::Error),
     #[error(transparent)]
+    #[diagnostic(transparent)]
     ConfigError(#[from] crate::config::Error),
     #[error(transparent)]
+    #[diagnostic(code(RV1102))]
     IoError(#[from] std::io::Error),
     #[error(transparent)]
+    #[diagnostic(code(RV1103))]
     VersionError(#[from] rv_ruby::request::RequestError),
     #[error(transparent)]
+    #[diagnostic(code(RV1104))]
     RubyError(#[from] rv_ruby::RubyError),
+    #[error("`--quiet` only prints version numbers, so it can't be combined with `--format`")]
+    #[diagnostic(code(RV1105))]
+    QuietWithFormat,
 }
 
 type Result<T> = miette::Result<T, Error>;
@@ -39,6 +61,20 @@ struct JsonRubyEntry {
     #[serde(flatten)]
     ruby: RubyEntry,
     active: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scope: Option<InstallScope>,
+    /// Where rv installed the Ruby from, if rv installed it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    provenance: Option<Provenance>,
+    /// Who installed the Ruby, if it came with the system or Homebrew rather than from rv.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    external: Option<ExternalKind>,
+    /// Other paths that lead to the same installation, like `ruby-3.3 -> ruby-3.3.9`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    aliases: Vec<Utf8PathBuf>,
+    /// Whether this is the newest of several listed patch releases in its minor series.
+    #[serde(skip)]
+    newest_patch: bool,
     #[serde(skip)]
     color: bool,
 }
@@ -47,6 +83,22 @@ impl JsonRubyEntry {
     fn no_color(&mut self) {
         self.color = false;
     }
+
+    fn tsv_row(&self, columns: &[Column]) -> String {
+        let fields: Vec<String> = columns
+            .iter()
+            .map(|column| match column {
+                Column::Version => self.ruby.canonical_name(),
+                Column::Path => match &self.ruby {
+                    RubyEntry::Installed(ruby) => ruby.path.to_string(),
+                    RubyEntry::Remote(_) => String::new(),
+                },
+                Column::Installed => matches!(self.ruby, RubyEntry::Installed(_)).to_string(),
+                Column::Active => self.active.to_string(),
+            })
+            .collect();
+        fields.join("\t")
+    }
 }
 
 #[derive(Serialize, Debug)]
@@ -56,11 +108,33 @@ enum RubyEntry {
     Remote(RemoteRuby),
 }
 
+/// Whether an installed ruby belongs to the current user or is shared system-wide.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum InstallScope {
+    User,
+    System,
+}
+
+impl InstallScope {
+    fn of(ruby: &Ruby) -> Self {
+        if rv_dirs::is_user_path(&ruby.path) {
+            Self::User
+        } else {
+            Self::System
+        }
+    }
+}
+
 impl RubyEntry {
     pub fn canonical_name(&self) -> String {
+        self.version().canonical_name()
+    }
+
+    fn version(&self) -> &RubyVersion {
         match self {
-            Self::Installed(ruby) => ruby.version.canonical_name(),
-            Self::Remote(remote_ruby) => remote_ruby.version.canonical_name(),
+            Self::Installed(ruby) => &ruby.version,
+            Self::Remote(remote_ruby) => &remote_ruby.version,
         }
     }
 }
@@ -71,15 +145,37 @@ impl tabled::Tabled for JsonRubyEntry {
     fn fields(&self) -> Vec<Cow<'_, str>> {
         let canonical_name = self.ruby.canonical_name();
 
-        let name = if self.active {
-            format!("* {canonical_name}")
-        } else {
-            format!("  {canonical_name}")
+        let mut name = match (self.active, self.color) {
+            (true, true) => format!("{} {}", "▶".green(), canonical_name.green().bold()),
+            (true, false) => format!("▶ {canonical_name}"),
+            (false, _) => format!("  {canonical_name}"),
         };
 
+        if self.newest_patch {
+            if self.color {
+                name.push_str(&" (latest)".dimmed().to_string());
+            } else {
+                name.push_str(" (latest)");
+            }
+        }
+
         let installed = match &self.ruby {
             RubyEntry::Installed(ruby) => {
-                let short_executable_path = rv_dirs::unexpand(&ruby.executable_path());
+                let mut short_executable_path = rv_dirs::unexpand(&ruby.executable_path());
+
+                if self.external.is_some() {
+                    short_executable_path.push_str(" (external)");
+                } else if self.scope == Some(InstallScope::System) {
+                    short_executable_path.push_str(" (system)");
+                }
+                if !self.aliases.is_empty() {
+                    let aliases: Vec<String> = self
+                        .aliases
+                        .iter()
+                        .map(|alias| rv_dirs::unexpand(alias))
+                        .collect();
+                    short_executable_path.push_str(&format!(" (also {})", aliases.join(", ")));
+                }
 
                 if self.color {
                     short_executable_path.cyan().to_string().into()
@@ -106,7 +202,7 @@ impl tabled::Tabled for JsonRubyEntry {
 #[derive(Args)]
 #[group(required = false, multiple = false)]
 pub struct VersionFilter {
-    /// List all versions (Including outdated)
+    /// List every available patch version, not just the newest one of each minor series
     #[arg(long, help_heading = "Filter Options")]
     all: bool,
 
@@ -115,45 +211,155 @@ pub struct VersionFilter {
     installed_only: bool,
 }
 
+/// A column of `--format tsv` output.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Column {
+    /// The version number, e.g. `3.4.1`
+    Version,
+    /// Where the ruby is installed, empty if it isn't
+    Path,
+    /// `true` if the ruby is installed, `false` if it's only available to install
+    Installed,
+    /// `true` if this is the ruby the current directory would use
+    Active,
+}
+
+#[derive(Args)]
+pub struct LinkOptions {
+    /// List every path of a Ruby that's linked from other paths, rather than one row with the
+    /// others as its aliases
+    #[arg(long, help_heading = "Filter Options")]
+    expand_links: bool,
+}
+
+#[derive(Args)]
+pub struct ScriptOptions {
+    /// Columns to print with `--format tsv`
+    #[arg(
+        long,
+        value_enum,
+        value_delimiter = ',',
+        default_value = "version,path,installed",
+        help_heading = "Scripting Options"
+    )]
+    columns: Vec<Column>,
+}
+
 /// Lists the available and installed rubies.
 pub(crate) async fn list(
     global_args: &GlobalArgs,
     format: OutputFormat,
     version_filter: VersionFilter,
+    link_options: LinkOptions,
+    script_options: ScriptOptions,
+    platforms: bool,
     no_color: bool,
+    refresh: bool,
 ) -> Result<()> {
+    // With `--quiet`, only version numbers are printed, one per line.
+    let quiet = global_args.quiet;
+    if quiet && format != OutputFormat::Text {
+        return Err(Error::QuietWithFormat);
+    }
+
     let config = Config::new(global_args, None)?;
 
-    let installed_rubies = config.rubies();
+    if platforms {
+        return platforms::list_platforms(
+            &config,
+            format,
+            &version_filter,
+            quiet,
+            no_color,
+            refresh,
+        )
+        .await;
+    }
 
-    if version_filter.installed_only && installed_rubies.is_empty() && format == OutputFormat::Text
+    let discovery_start = Instant::now();
+    let installed_rubies = config.rubies();
+    // The system's and Homebrew's rubies are listed too, unless rv found them already.
+    let listed: HashSet<&Utf8Path> = installed_rubies.iter().map(|ruby| &*ruby.path).collect();
+    let external_rubies: Vec<Ruby> = config
+        .external_rubies()
+        .into_iter()
+        .filter(|ruby| !listed.contains(&*ruby.path))
+        .collect();
+    crate::stats::record_phase("installed rubies", discovery_start.elapsed());
+    let root = rv_dirs::root_dir();
+
+    // Scripts parse our output, so don't explain an empty list to them.
+    let explain_empty = format == OutputFormat::Text && !quiet;
+
+    if version_filter.installed_only
+        && installed_rubies.is_empty()
+        && external_rubies.is_empty()
+        && explain_empty
     {
         warn!("No Ruby installations found.");
         info!("Try installing Ruby with 'rv ruby install <version>'");
         return Ok(());
     }
 
-    let requested = config.ruby_request();
+    // When the locked gems narrow down which Ruby to use, the active one is the one they chose.
+    let inferred_ruby = config.inferred_current_ruby();
+    let requested = match &inferred_ruby {
+        Some(ruby) => RubyRequest::from(ruby.version.clone()),
+        None => config.ruby_request(),
+    };
     let mut active_ruby = false;
 
-    // Might have multiple installed rubies with the same version (e.g., "ruby-3.2.0" and "mruby-3.2.0").
-    let mut rubies_map: BTreeMap<RubyVersion, Vec<JsonRubyEntry>> = BTreeMap::new();
-
-    for ruby in installed_rubies.into_iter().rev() {
-        rubies_map.entry(ruby.version.clone()).or_default().insert(
-            0,
-            JsonRubyEntry {
-                active: active(&mut active_ruby, &ruby.version, &requested),
-                ruby: RubyEntry::Installed(ruby),
-                color: true,
-            },
+    // Grouped by engine, then sorted by version within each engine. Might have multiple installed
+    // rubies with the same version (e.g., "ruby-3.2.0" and "/opt/rubies/3.2.0").
+    let mut rubies_map: BTreeMap<(RubyEngine, RubyVersion), Vec<JsonRubyEntry>> = BTreeMap::new();
+
+    let installed_rubies = if link_options.expand_links {
+        installed_rubies
+            .into_iter()
+            .map(|ruby| (ruby, Vec::new()))
+            .collect()
+    } else {
+        collapse_links(installed_rubies, |path| {
+            rv_dirs::canonicalize_utf8(path).ok()
+        })
+    };
+
+    // rv never picks the external rubies it didn't find in its own directories, so they're never
+    // the active one.
+    let installed_rubies = installed_rubies
+        .into_iter()
+        .map(|(ruby, aliases)| (ruby, aliases, true))
+        .chain(
+            external_rubies
+                .into_iter()
+                .map(|ruby| (ruby, Vec::new(), false)),
         );
+
+    for (ruby, aliases, selectable) in installed_rubies.rev() {
+        rubies_map
+            .entry(sort_key(&ruby.version))
+            .or_default()
+            .insert(
+                0,
+                JsonRubyEntry {
+                    active: selectable && active(&mut active_ruby, &ruby.version, &requested),
+                    scope: Some(InstallScope::of(&ruby)),
+                    provenance: Provenance::read(&ruby.path),
+                    external: ExternalKind::of(&ruby.path, &root),
+                    aliases,
+                    ruby: RubyEntry::Installed(ruby),
+                    newest_patch: false,
+                    color: true,
+                },
+            );
     }
 
     let active_installed = active_ruby;
 
     if !version_filter.installed_only {
-        let remote_rubies = config.remote_rubies().await;
+        let remote_start = Instant::now();
+        let remote_rubies = config.discover_remote_rubies(refresh).await;
+        crate::stats::record_phase("remote rubies", remote_start.elapsed());
 
         let selected_remote_rubies = if version_filter.all {
             remote_rubies.clone()
@@ -164,10 +370,15 @@ pub(crate) async fn list(
         // Add selected remote rubies that are not already installed to the list
         for ruby in selected_remote_rubies.into_iter().rev() {
             rubies_map
-                .entry(ruby.version.clone())
+                .entry(sort_key(&ruby.version))
                 .or_insert(vec![JsonRubyEntry {
                     active: active(&mut active_ruby, &ruby.version, &requested),
                     ruby: RubyEntry::Remote(ruby),
+                    scope: None,
+                    provenance: None,
+                    external: None,
+                    aliases: Vec::new(),
+                    newest_patch: false,
                     color: true,
                 }]);
         }
@@ -177,27 +388,128 @@ pub(crate) async fn list(
 
             if let Some(ref ruby) = ruby {
                 rubies_map
-                    .entry(ruby.version.clone())
+                    .entry(sort_key(&ruby.version))
                     .or_insert(vec![JsonRubyEntry {
                         ruby: RubyEntry::Remote(ruby.clone()),
                         active: true,
+                        scope: None,
+                        provenance: None,
+                        external: None,
+                        aliases: Vec::new(),
+                        newest_patch: false,
                         color: true,
                     }]);
             };
         };
 
-        if rubies_map.is_empty() && format == OutputFormat::Text {
+        if rubies_map.is_empty() && explain_empty {
             warn!("No rubies found for your platform.");
             return Ok(());
         }
     }
 
     // Create entries for output
-    let entries: Vec<JsonRubyEntry> = rubies_map.into_values().flatten().collect();
+    let mut entries: Vec<JsonRubyEntry> = rubies_map.into_values().flatten().collect();
+    flag_newest_patches(&mut entries);
+
+    if quiet {
+        for entry in &entries {
+            println!("{}", entry.ruby.canonical_name());
+        }
+        return Ok(());
+    }
+
+    let explanation = match config.inferred_ruby() {
+        Some(inferred) => explain_inferred(inferred, inferred_ruby.is_some(), active_installed),
+        None => config.requested_ruby.explain(active_installed),
+    };
+
+    print_entries(
+        entries,
+        format,
+        &script_options.columns,
+        no_color,
+        &explanation,
+    )
+}
+
+/// Collapse rubies whose directories are the same installation, going by `canonicalize`, into
+/// one with the other paths as its aliases. The one named like the installation they lead to is
+/// kept, if there is one, or else the first.
+fn collapse_links(
+    rubies: Vec<Ruby>,
+    canonicalize: impl Fn(&Utf8Path) -> Option<Utf8PathBuf>,
+) -> Vec<(Ruby, Vec<Utf8PathBuf>)> {
+    let mut collapsed: Vec<(Ruby, Vec<Utf8PathBuf>)> = Vec::with_capacity(rubies.len());
+    let mut installations: HashMap<Utf8PathBuf, usize> = HashMap::new();
+
+    for ruby in rubies {
+        let Some(canonical) = canonicalize(&ruby.path) else {
+            collapsed.push((ruby, Vec::new()));
+            continue;
+        };
+        match installations.get(&canonical) {
+            Some(&index) => {
+                let (kept, aliases) = &mut collapsed[index];
+                if ruby.path.file_name() == canonical.file_name() {
+                    let alias = std::mem::replace(kept, ruby);
+                    aliases.push(alias.path);
+                } else {
+                    aliases.push(ruby.path);
+                }
+            }
+            None => {
+                installations.insert(canonical, collapsed.len());
+                collapsed.push((ruby, Vec::new()));
+            }
+        }
+    }
+
+    collapsed
+}
+
+fn sort_key(version: &RubyVersion) -> (RubyEngine, RubyVersion) {
+    (version.engine.clone(), version.clone())
+}
 
-    let explanation = config.requested_ruby.explain(active_installed);
+/// Flag the newest patch release of each minor series that has more than one listed release,
+/// so it stands out among the older patches. Expects `entries` to be sorted.
+fn flag_newest_patches(entries: &mut [JsonRubyEntry]) {
+    let series = |entry: &JsonRubyEntry| {
+        let version = entry.ruby.version();
+        (version.engine.clone(), version.major, version.minor)
+    };
+
+    for group in entries.chunk_by_mut(|a, b| series(a) == series(b)) {
+        let newest = group.last().map(|entry| entry.ruby.version().clone());
+        if group
+            .iter()
+            .all(|entry| Some(entry.ruby.version()) == newest.as_ref())
+        {
+            continue;
+        }
+        for entry in group {
+            entry.newest_patch = Some(entry.ruby.version()) == newest.as_ref();
+        }
+    }
+}
 
-    print_entries(entries, format, no_color, &explanation)
+/// Why the default version is the one it is, when the locked gems narrow it down. If no
+/// installed Ruby satisfies them, says how to get one.
+fn explain_inferred(inferred: &InferredRuby, satisfied: bool, installed: bool) -> String {
+    let lockfile = inferred.lockfile.file_name().unwrap_or("the lockfile");
+    if satisfied {
+        return format!(
+            "* Default version is the latest installed that the gems in {lockfile} support (ruby {})",
+            inferred.requirement
+        );
+    }
+    let installed_or_available = if installed { "installed" } else { "available" };
+    format!(
+        "* Default version is the latest {installed_or_available}, but the gems in {lockfile} need \
+         ruby {}. Install one that satisfies them with `rv ruby install`, then pin it with `rv ruby pin`",
+        inferred.requirement
+    )
 }
 
 fn active(active_set: &mut bool, version: &RubyVersion, requested: &RubyRequest) -> bool {
@@ -212,7 +524,7 @@ fn active(active_set: &mut bool, version: &RubyVersion, requested: &RubyRequest)
     should_activate
 }
 
-fn latest_patch_version(remote_rubies: &Vec<RemoteRuby>) -> Vec<RemoteRuby> {
+fn latest_patch_version<T: Versioned + Clone>(remote_rubies: &[T]) -> Vec<T> {
     #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
     struct NonPatchRelease {
         engine: rv_ruby::engine::RubyEngine,
@@ -229,17 +541,17 @@ fn latest_patch_version(remote_rubies: &Vec<RemoteRuby>) -> Vec<RemoteRuby> {
             }
         }
     }
-    let mut available_rubies: BTreeMap<NonPatchRelease, RemoteRuby> = BTreeMap::new();
+    let mut available_rubies: BTreeMap<NonPatchRelease, T> = BTreeMap::new();
     for ruby in remote_rubies {
         // Skip 3.5 series since they only include pre-releases
-        if ruby.version.major == 3 && ruby.version.minor == 5 {
+        if ruby.version().major == 3 && ruby.version().minor == 5 {
             continue;
         }
 
-        let key = NonPatchRelease::from(ruby.version.clone());
+        let key = NonPatchRelease::from(ruby.version().clone());
         let skip = available_rubies
             .get(&key)
-            .map(|other| other.version > ruby.version)
+            .map(|other| other.version() > ruby.version())
             .unwrap_or_default();
         if !skip {
             available_rubies.insert(key, ruby.clone());
@@ -251,6 +563,7 @@ fn latest_patch_version(remote_rubies: &Vec<RemoteRuby>) -> Vec<RemoteRuby> {
 fn print_entries(
     mut entries: Vec<JsonRubyEntry>,
     format: OutputFormat,
+    columns: &[Column],
     no_color: bool,
     explanation: &String,
 ) -> Result<()> {
@@ -278,6 +591,11 @@ fn print_entries(
         OutputFormat::Json => {
             serde_json::to_writer_pretty(io::stdout(), &entries)?;
         }
+        OutputFormat::Tsv => {
+            for entry in &entries {
+                println!("{}", entry.tsv_row(columns));
+            }
+        }
     }
     Ok(())
 }
@@ -287,7 +605,6 @@ mod tests {
     use super::*;
     use crate::GlobalArgs;
     use assert_fs::TempDir;
-    use camino::Utf8PathBuf;
     use rv_cache::CacheArgs;
     use rv_ruby::version::RubyVersion;
     use std::str::FromStr as _;
@@ -300,12 +617,18 @@ mod tests {
         let cache_args = CacheArgs {
             no_cache: false,
             cache_dir: None,
+            ..Default::default()
         };
 
         let global_args = GlobalArgs {
             ruby_dir: [ruby_dir].to_vec(),
             cache_args,
             offline: false,
+            quiet: false,
+            ruby: None,
+            no_input: false,
+            limit_rate: None,
+            dir_depth: None,
         };
 
         Ok(global_args)
@@ -319,9 +642,45 @@ mod tests {
             all: false,
             installed_only: false,
         };
-        list(&global_args, OutputFormat::Text, version_filter, true)
-            .await
-            .unwrap();
+        let link_options = LinkOptions {
+            expand_links: false,
+        };
+        let script_options = ScriptOptions { columns: vec![] };
+        list(
+            &global_args,
+            OutputFormat::Text,
+            version_filter,
+            link_options,
+            script_options,
+            false,
+            true,
+            false,
+        )
+        .await
+        .unwrap();
+    }
+
+    #[test]
+    fn test_tsv_row() {
+        let entry = JsonRubyEntry {
+            ruby: RubyEntry::Remote(ruby("ruby-3.4.1")),
+            active: true,
+            scope: None,
+            provenance: None,
+            external: None,
+            aliases: Vec::new(),
+            newest_patch: false,
+            color: false,
+        };
+
+        assert_eq!(
+            entry.tsv_row(&[Column::Version, Column::Path, Column::Installed]),
+            "3.4.1\t\tfalse"
+        );
+        assert_eq!(
+            entry.tsv_row(&[Column::Active, Column::Version]),
+            "true\t3.4.1"
+        );
     }
 
     fn ruby(version: &str) -> RemoteRuby {
@@ -398,4 +757,86 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn test_flag_newest_patches() {
+        let entry = |version: &str| JsonRubyEntry {
+            ruby: RubyEntry::Remote(ruby(version)),
+            active: false,
+            scope: None,
+            provenance: None,
+            external: None,
+            aliases: Vec::new(),
+            newest_patch: false,
+            color: false,
+        };
+        let mut entries = vec![
+            entry("ruby-3.3.5"),
+            entry("ruby-3.4.1"),
+            entry("ruby-3.4.7"),
+            entry("jruby-9.4.13.0"),
+            entry("jruby-9.4.14.0"),
+        ];
+
+        flag_newest_patches(&mut entries);
+
+        let flagged: Vec<String> = entries
+            .iter()
+            .filter(|entry| entry.newest_patch)
+            .map(|entry| entry.ruby.version().to_string())
+            .collect();
+        assert_eq!(flagged, vec!["ruby-3.4.7", "jruby-9.4.14.0"]);
+    }
+
+    #[test]
+    fn test_collapse_links() {
+        let installed = |path: &str| Ruby {
+            key: String::new(),
+            version: RubyVersion::from_str("ruby-3.3.9").unwrap(),
+            path: path.into(),
+            managed: true,
+            enable_shared: false,
+            symlink: None,
+            arch: "aarch64".into(),
+            os: "macos".into(),
+            gem_root: None,
+            rubygems_platform: "arm64-darwin-23".into(),
+        };
+        let canonicalize = |path: &Utf8Path| {
+            Some(match path.as_str() {
+                "/rubies/ruby-3.3" | "/opt/rubies/3.3.9" => "/rubies/ruby-3.3.9".into(),
+                path => path.into(),
+            })
+        };
+
+        let collapsed = collapse_links(
+            vec![
+                installed("/rubies/ruby-3.3"),
+                installed("/rubies/ruby-3.3.9"),
+                installed("/opt/rubies/3.3.9"),
+                installed("/rubies/ruby-3.4.1"),
+            ],
+            canonicalize,
+        );
+
+        let rows: Vec<(&str, Vec<&str>)> = collapsed
+            .iter()
+            .map(|(ruby, aliases)| {
+                (
+                    ruby.path.as_str(),
+                    aliases.iter().map(|alias| alias.as_str()).collect(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            rows,
+            vec![
+                (
+                    "/rubies/ruby-3.3.9",
+                    vec!["/rubies/ruby-3.3", "/opt/rubies/3.3.9"]
+                ),
+                ("/rubies/ruby-3.4.1", vec![]),
+            ]
+        );
+    }
 }
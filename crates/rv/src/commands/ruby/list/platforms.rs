@@ -0,0 +1,168 @@
+//! `rv ruby list --platforms`, which shows which platforms have a prebuilt archive of each
+//! available Ruby, so that a version can be checked against every OS and architecture that has to
+//! install it, like CI runners, before it's pinned.
+
+use std::borrow::Cow;
+use std::io;
+
+use anstream::println;
+use owo_colors::OwoColorize;
+use rv_platform::HostPlatform;
+use rv_ruby::{Versioned, canonical_name::CanonicalName, version::RubyVersion};
+use serde::Serialize;
+use tabled::{Table, settings::Style};
+use tracing::warn;
+
+use super::{Result, VersionFilter, latest_patch_version};
+use crate::{config::Config, output_format::OutputFormat};
+
+/// The platforms shown in the table, in order, with their column headers.
+const COLUMNS: [(HostPlatform, &str); 8] = [
+    (HostPlatform::MacosAarch64, "macOS arm64"),
+    (HostPlatform::MacosX86_64, "macOS x86_64"),
+    (HostPlatform::LinuxX86_64, "Linux x86_64"),
+    (HostPlatform::LinuxMuslX86_64, "Linux musl x86_64"),
+    (HostPlatform::LinuxAarch64, "Linux arm64"),
+    (HostPlatform::LinuxMuslAarch64, "Linux musl arm64"),
+    (HostPlatform::WindowsX86_64, "Windows x64"),
+    (HostPlatform::WindowsAarch64, "Windows arm64"),
+];
+
+/// A Ruby version and the platforms there's a build of it for.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+struct PlatformsEntry {
+    version: RubyVersion,
+    /// Like `linux-musl-x86_64`, in the same order as [`HostPlatform::all`].
+    #[serde(serialize_with = "serialize_platforms")]
+    platforms: Vec<HostPlatform>,
+    #[serde(skip)]
+    color: bool,
+}
+
+impl Versioned for PlatformsEntry {
+    fn version(&self) -> &RubyVersion {
+        &self.version
+    }
+}
+
+impl PlatformsEntry {
+    fn tsv_row(&self) -> String {
+        let platforms: Vec<String> = self.platforms.iter().map(platform_name).collect();
+        format!("{}\t{}", self.version.canonical_name(), platforms.join(","))
+    }
+}
+
+impl tabled::Tabled for PlatformsEntry {
+    const LENGTH: usize = 1 + COLUMNS.len();
+
+    fn fields(&self) -> Vec<Cow<'_, str>> {
+        let mut fields = vec![self.version.canonical_name().into()];
+        fields.extend(COLUMNS.iter().map(|(platform, _)| {
+            match (self.platforms.contains(platform), self.color) {
+                (true, true) => "✓".green().to_string().into(),
+                (true, false) => "✓".into(),
+                (false, _) => "".into(),
+            }
+        }));
+        fields
+    }
+
+    fn headers() -> Vec<Cow<'static, str>> {
+        let mut headers = vec!["Version".into()];
+        headers.extend(COLUMNS.iter().map(|(_, header)| Cow::Borrowed(*header)));
+        headers
+    }
+}
+
+fn platform_name(platform: &HostPlatform) -> String {
+    format!("{}-{}", platform.os(), platform.arch())
+}
+
+fn serialize_platforms<S: serde::Serializer>(
+    platforms: &[HostPlatform],
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    serializer.collect_seq(platforms.iter().map(platform_name))
+}
+
+/// Print which platforms have a build of each available Ruby, going by the lists of rubies of
+/// every platform's source.
+pub(super) async fn list_platforms(
+    config: &Config,
+    format: OutputFormat,
+    version_filter: &VersionFilter,
+    quiet: bool,
+    no_color: bool,
+    refresh: bool,
+) -> Result<()> {
+    let remote_start = std::time::Instant::now();
+    let platforms = config.remote_ruby_platforms(refresh).await;
+    crate::stats::record_phase("remote rubies", remote_start.elapsed());
+
+    let entries: Vec<PlatformsEntry> = platforms
+        .into_iter()
+        .map(|(version, platforms)| PlatformsEntry {
+            version,
+            platforms,
+            color: !no_color,
+        })
+        .collect();
+    let entries = if version_filter.all {
+        entries
+    } else {
+        latest_patch_version(&entries)
+    };
+
+    if quiet {
+        for entry in &entries {
+            println!("{}", entry.version.canonical_name());
+        }
+        return Ok(());
+    }
+
+    match format {
+        OutputFormat::Text => {
+            if entries.is_empty() {
+                warn!("No rubies found for any platform.");
+                return Ok(());
+            }
+            let mut table = Table::new(entries);
+            table.with(Style::sharp());
+            println!("{table}");
+        }
+        OutputFormat::Json => {
+            serde_json::to_writer_pretty(io::stdout(), &entries)?;
+        }
+        OutputFormat::Tsv => {
+            for entry in &entries {
+                println!("{}", entry.tsv_row());
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_platforms_entry() {
+        let entry = PlatformsEntry {
+            version: "ruby-3.4.1".parse().unwrap(),
+            platforms: vec![HostPlatform::MacosAarch64, HostPlatform::LinuxMuslX86_64],
+            color: false,
+        };
+
+        assert_eq!(entry.tsv_row(), "3.4.1\tmacos-aarch64,linux-musl-x86_64");
+        assert_eq!(
+            serde_json::to_value(&entry).unwrap(),
+            serde_json::json!({
+                "version": "ruby-3.4.1",
+                "platforms": ["macos-aarch64", "linux-musl-x86_64"],
+            })
+        );
+        let fields = tabled::Tabled::fields(&entry);
+        assert_eq!(fields, ["3.4.1", "✓", "", "", "✓", "", "", "", ""]);
+    }
+}
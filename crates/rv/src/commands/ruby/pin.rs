@@ -1,20 +1,22 @@
 use regex::Regex;
 use std::borrow::Cow;
+use std::io::IsTerminal;
 use std::str::FromStr;
 
-use anstream::println;
+use anstream::{eprint, println};
+use camino::{Utf8Path, Utf8PathBuf};
 use miette::Diagnostic;
 use once_cell::sync::Lazy;
 use owo_colors::OwoColorize;
 use rv_ruby::canonical_name::CanonicalName;
-use tracing::debug;
+use tracing::{debug, warn};
 
 use rv_ruby::request::RubyRequest;
 use rv_ruby::request::Source;
 
 use crate::{
     GlobalArgs,
-    config::{Config, RequestedRuby},
+    config::{Config, RequestedRuby, ruby_lock::RubyLock},
 };
 
 static RUBY_TOOL_VERSIONS_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"^ *ruby ").unwrap());
@@ -22,13 +24,20 @@ static RUBY_TOOL_VERSIONS_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"^ *ruby
 #[derive(Debug, thiserror::Error, Diagnostic)]
 pub enum Error {
     #[error("No Ruby version request found")]
+    #[diagnostic(code(RV1201))]
     NoRubyRequest,
     #[error(transparent)]
+    #[diagnostic(transparent)]
     ConfigError(#[from] crate::config::Error),
     #[error(transparent)]
+    #[diagnostic(code(RV1202))]
     IoError(#[from] std::io::Error),
     #[error(transparent)]
+    #[diagnostic(code(RV1203))]
     VersionError(#[from] rv_ruby::request::RequestError),
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    InstallError(#[from] crate::commands::ruby::install::Error),
 }
 
 type Result<T> = miette::Result<T, Error>;
@@ -37,6 +46,8 @@ pub(crate) async fn pin(
     global_args: &GlobalArgs,
     request: Option<String>,
     mut resolved: bool,
+    install: bool,
+    lock: bool,
 ) -> Result<()> {
     let config = &Config::new(global_args, None)?;
 
@@ -52,19 +63,103 @@ pub(crate) async fn pin(
     let ruby_request = RubyRequest::from_str(&request)?;
 
     let version = if resolved {
-        let resolved = &Config::new(global_args, Some(ruby_request.clone()))?
-            .find_matching_remote_ruby()
-            .await?;
-
-        resolved.canonical_name()
+        resolve_version(&Config::new(global_args, Some(ruby_request.clone()))?).await?
     } else {
         ruby_request.canonical_name()
     };
 
-    set_pinned_ruby(config, version)
+    let pin_file = set_pinned_ruby(config, version.clone())?;
+    // A lock that's there already is kept in step with the pin.
+    let lock_dir = pin_file.parent().unwrap_or(Utf8Path::new("."));
+    if lock || RubyLock::read(lock_dir)?.is_some() {
+        write_lock(global_args, &version, lock_dir).await?;
+    }
+    ensure_installed(global_args, &version, install).await
+}
+
+/// Lock the project pinned in `dir` to the release `version` resolves to, and the digests of its
+/// archives for every platform.
+async fn write_lock(global_args: &GlobalArgs, version: &str, dir: &Utf8Path) -> Result<()> {
+    let config = Config::new(global_args, Some(RubyRequest::from_str(version)?))?;
+    let ruby = config.resolve_requested_version().await?;
+    let sha256 = config.remote_ruby_digests(&ruby).await;
+    if sha256.is_empty() {
+        warn!(
+            "The digests of Ruby {ruby}'s archives aren't published, so `rv ruby install` can't check them"
+        );
+    }
+    let path = RubyLock {
+        ruby: ruby.clone(),
+        sha256,
+    }
+    .write(dir)?;
+    println!("{} locked to {}", path.cyan(), ruby.cyan());
+    Ok(())
 }
 
-fn set_pinned_ruby(config: &Config, version: String) -> Result<()> {
+/// Tell the user which installed Ruby satisfies the new pin. If none does, install one when
+/// `install` is set or the user agrees to, so the pin doesn't fail later on.
+async fn ensure_installed(global_args: &GlobalArgs, version: &str, install: bool) -> Result<()> {
+    let request = RubyRequest::from_str(version)?;
+    let config = Config::new(global_args, Some(request.clone()))?;
+    if let Some(ruby) = config.current_ruby() {
+        print_satisfied_by(&ruby);
+        return Ok(());
+    }
+
+    let can_prompt =
+        !global_args.no_input && std::io::stdin().is_terminal() && std::io::stderr().is_terminal();
+    let install = install || (can_prompt && confirm_install(version)?);
+    if !install {
+        warn!("Ruby {version} is not installed. Run `rv ruby install {version}` to install it.");
+        return Ok(());
+    }
+
+    crate::commands::ruby::install::install(
+        global_args,
+        crate::commands::ruby::install::InstallDir::Default,
+        Some(request.clone()),
+        None,
+        false,
+        false,
+        crate::commands::ruby::install::InstallLayout::Rv,
+        Default::default(),
+    )
+    .await?;
+
+    if let Some(ruby) = Config::new(global_args, Some(request))?.current_ruby() {
+        print_satisfied_by(&ruby);
+    }
+    Ok(())
+}
+
+fn print_satisfied_by(ruby: &rv_ruby::Ruby) {
+    println!(
+        "Using {} from {}",
+        ruby.version.to_string().cyan(),
+        ruby.path.cyan()
+    );
+}
+
+/// Ask whether to install the pinned version. Anything but "n" or "no" means yes.
+fn confirm_install(version: &str) -> Result<bool> {
+    eprint!(
+        "{} is not installed. Install it now? [Y/n] ",
+        version.cyan()
+    );
+    let answer = console::Term::stderr().read_line()?;
+    let answer = answer.trim().to_ascii_lowercase();
+    Ok(!matches!(answer.as_str(), "n" | "no"))
+}
+
+/// The full version of the newest Ruby release matching the config's request. When no release
+/// matches, e.g. because rv is offline, falls back to the newest matching installed Ruby.
+async fn resolve_version(config: &Config) -> Result<String> {
+    Ok(config.resolve_requested_version().await?.canonical_name())
+}
+
+/// Pin the project's Ruby to `version`, and return the path of the file it's pinned in.
+fn set_pinned_ruby(config: &Config, version: String) -> Result<Utf8PathBuf> {
     let project_dir = match config.requested_ruby {
         RequestedRuby::Project((_, Source::DotToolVersions(ref path))) => {
             let versions = fs_err::read_to_string(path)?;
@@ -92,8 +187,8 @@ fn set_pinned_ruby(config: &Config, version: String) -> Result<()> {
             Cow::Borrowed(path)
         }
         _ => {
-            // For Gemfile.lock source, create a .ruby-version file instead of
-            // modifying the lockfile (which is auto-generated by bundler)
+            // For Gemfile and Gemfile.lock sources, create a .ruby-version file instead of
+            // modifying the lockfile (which is auto-generated by bundler) or the Gemfile
             fs_err::write(".ruby-version", format!("{version}\n"))?;
             let path = rv_dirs::canonicalize_utf8(".ruby-version")?;
             Cow::Owned(path)
@@ -102,7 +197,7 @@ fn set_pinned_ruby(config: &Config, version: String) -> Result<()> {
 
     println!("{0} pinned to {1}", project_dir.cyan(), version.cyan());
 
-    Ok(())
+    Ok(project_dir.into_owned())
 }
 
 async fn show_pinned_ruby(config: &Config, resolved: bool) -> Result<()> {
@@ -115,11 +210,11 @@ async fn show_pinned_ruby(config: &Config, resolved: bool) -> Result<()> {
         Source::DotToolVersions(path) => Cow::Borrowed(path),
         Source::DotRubyVersion(path) => Cow::Borrowed(path),
         Source::GemfileLock(path) => Cow::Borrowed(path),
+        Source::Gemfile(path) => Cow::Borrowed(path),
     };
 
     let version = if resolved {
-        let resolved_ruby = config.find_matching_remote_ruby().await?;
-        resolved_ruby.canonical_name()
+        resolve_version(config).await?
     } else {
         ruby.canonical_name()
     };
@@ -0,0 +1,511 @@
+//! `rv ruby repair`: fix a Ruby that worked when rv installed it but doesn't anymore, usually
+//! because a shared library it links against moved, like one from Homebrew after `brew upgrade`.
+//!
+//! First, references to shared libraries that no longer exist are pointed at where the libraries
+//! are now, with `install_name_tool` on macOS and `patchelf` on Linux. If the Ruby still fails
+//! the checks of `rv ruby verify` after that, it's reinstalled from the archive it was installed
+//! from, as long as that's still on disk or in rv's cache.
+
+use std::process::{Command, Stdio};
+
+use anstream::println;
+use camino::{Utf8Path, Utf8PathBuf};
+use owo_colors::OwoColorize;
+use rv_core::provenance::Provenance;
+use rv_ruby::Ruby;
+use rv_ruby::request::RubyRequest;
+use tracing::{debug, warn};
+
+use super::{install, verify};
+use crate::{GlobalArgs, config::Config};
+
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+pub enum Error {
+    #[error("no matching ruby version found")]
+    #[diagnostic(code(RV1901))]
+    NoMatchingRuby,
+    #[error(transparent)]
+    #[diagnostic(code(RV1902))]
+    IoError(#[from] std::io::Error),
+    #[error("{ruby} wasn't installed by rv, so rv can't repair it")]
+    #[diagnostic(
+        code(RV1903),
+        help("Repair or reinstall it with the tool that installed it")
+    )]
+    NotInstalledByRv { ruby: Utf8PathBuf },
+    #[error("Relinking {binary} failed while running `{command}`")]
+    #[diagnostic(code(RV1904), help("{output}"))]
+    RelinkFailed {
+        binary: Utf8PathBuf,
+        command: String,
+        output: String,
+    },
+    #[error("{ruby} is still broken, and the archive it was installed from is gone")]
+    #[diagnostic(
+        code(RV1905),
+        help("Reinstall it with `rv ruby install --force`, which downloads it again")
+    )]
+    Unrecoverable { ruby: String },
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    ConfigError(#[from] crate::config::Error),
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    VerifyError(#[from] verify::Error),
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    InstallError(#[from] install::Error),
+}
+
+type Result<T> = miette::Result<T, Error>;
+
+/// The checks of `rv ruby verify` that fail because of the network, not because of the Ruby, so
+/// reinstalling the Ruby won't make them pass.
+const NETWORK_CHECKS: [&str; 1] = ["certificates"];
+
+/// How the binaries of a Ruby link to shared libraries on this OS.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Linkage {
+    /// Mach-O, on macOS.
+    MachO,
+    /// ELF, on Linux.
+    Elf,
+}
+
+/// A reference from one of a Ruby's binaries to a shared library that isn't where it used to
+/// be, and where the library is now.
+#[derive(Debug, PartialEq, Eq)]
+struct Relink {
+    binary: Utf8PathBuf,
+    /// The path, or on Linux the name, of the library the binary asks for.
+    library: String,
+    /// Where the library is now.
+    found_at: Utf8PathBuf,
+}
+
+impl Linkage {
+    fn current() -> Option<Self> {
+        if cfg!(target_os = "macos") {
+            Some(Self::MachO)
+        } else if cfg!(target_os = "linux") {
+            Some(Self::Elf)
+        } else {
+            None
+        }
+    }
+
+    /// The tools that finding and fixing broken references takes.
+    fn tools(self) -> &'static [&'static str] {
+        match self {
+            Self::MachO => &["otool", "install_name_tool", "codesign"],
+            Self::Elf => &["ldd", "patchelf"],
+        }
+    }
+
+    /// Patterns, relative to the Ruby's directory, of the binaries that link to libraries: the
+    /// `ruby` executable, `libruby`, and the native extensions of the standard library.
+    fn binary_patterns(self) -> &'static [&'static str] {
+        match self {
+            Self::MachO => &install::MACHO_BINARY_PATTERNS,
+            Self::Elf => &["bin/ruby", "lib/*.so*", "lib/ruby/**/*.so"],
+        }
+    }
+
+    /// The command that lists the libraries `binary` links to.
+    fn list_command(self, binary: &Utf8Path) -> Command {
+        let mut cmd = match self {
+            Self::MachO => {
+                let mut cmd = Command::new("otool");
+                cmd.arg("-L");
+                cmd
+            }
+            Self::Elf => Command::new("ldd"),
+        };
+        cmd.arg(binary);
+        cmd
+    }
+
+    /// The libraries in the output of [`Linkage::list_command`] that might not be there: the ones
+    /// `ldd` couldn't find, or the absolute paths `otool` lists outside of the system's own
+    /// libraries, which live in the dyld shared cache rather than on disk.
+    fn libraries(self, output: &str) -> Vec<String> {
+        match self {
+            Self::MachO => output
+                .lines()
+                .skip(1)
+                .filter_map(|line| line.trim().split(" (").next())
+                .filter(|library| library.starts_with('/'))
+                .filter(|library| {
+                    !library.starts_with("/usr/lib/") && !library.starts_with("/System/")
+                })
+                .map(str::to_owned)
+                .collect(),
+            Self::Elf => output
+                .lines()
+                .filter_map(|line| line.trim().strip_suffix("=> not found"))
+                .map(|library| library.trim().to_owned())
+                .collect(),
+        }
+    }
+
+    /// The commands that point `relink.binary` at the library's new location. A library in the
+    /// Ruby in `ruby_dir` is referred to relative to the binary, so the Ruby can still be moved.
+    fn relink_commands(self, ruby_dir: &Utf8Path, relink: &Relink) -> Vec<Command> {
+        let library_dir = relink
+            .found_at
+            .parent()
+            .unwrap_or(relink.found_at.as_path());
+        let relative_dir = relative_dir(ruby_dir, &relink.binary, library_dir);
+        match self {
+            Self::MachO => {
+                let new_name = match (&relative_dir, relink.found_at.file_name()) {
+                    (Some(dir), Some(name)) => {
+                        Utf8PathBuf::from("@loader_path").join(dir).join(name)
+                    }
+                    _ => relink.found_at.clone(),
+                };
+                let mut change = Command::new("install_name_tool");
+                change
+                    .arg("-change")
+                    .arg(&relink.library)
+                    .arg(new_name)
+                    .arg(&relink.binary);
+                // Changing a binary invalidates its signature, and Apple Silicon won't run
+                // binaries without one, so sign it again, ad hoc like the build did.
+                let mut sign = Command::new("codesign");
+                sign.args(["--force", "--sign", "-"]).arg(&relink.binary);
+                vec![change, sign]
+            }
+            Self::Elf => {
+                let rpath = match relative_dir {
+                    Some(dir) if dir.as_str().is_empty() => Utf8PathBuf::from("$ORIGIN"),
+                    Some(dir) => Utf8PathBuf::from("$ORIGIN").join(dir),
+                    None => library_dir.to_path_buf(),
+                };
+                let mut add_rpath = Command::new("patchelf");
+                add_rpath.arg("--add-rpath").arg(rpath).arg(&relink.binary);
+                vec![add_rpath]
+            }
+        }
+    }
+}
+
+/// `dir` relative to the directory of `binary`, like `../lib` for `bin/ruby`, if both are in the
+/// Ruby in `ruby_dir`. Empty when `dir` is the binary's own directory.
+fn relative_dir(ruby_dir: &Utf8Path, binary: &Utf8Path, dir: &Utf8Path) -> Option<Utf8PathBuf> {
+    let from: Vec<_> = binary
+        .parent()?
+        .strip_prefix(ruby_dir)
+        .ok()?
+        .components()
+        .collect();
+    let to: Vec<_> = dir.strip_prefix(ruby_dir).ok()?.components().collect();
+    let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+
+    let mut relative = Utf8PathBuf::new();
+    for _ in common..from.len() {
+        relative.push("..");
+    }
+    for component in &to[common..] {
+        relative.push(component);
+    }
+    Some(relative)
+}
+
+/// Where a library that a binary can't find is now: in the Ruby's own `lib` directory, or, for
+/// a library from a Homebrew keg that was upgraded away, in the formula's `opt` directory, which
+/// Homebrew keeps pointing at the installed version.
+fn new_location(ruby_dir: &Utf8Path, library: &str) -> Option<Utf8PathBuf> {
+    let library = Utf8Path::new(library);
+    let bundled = ruby_dir.join("lib").join(library.file_name()?);
+    if bundled.is_file() {
+        return Some(bundled);
+    }
+    homebrew_opt_path(library).filter(|path| path.is_file())
+}
+
+/// `<prefix>/opt/<formula>/<rest>` for a path in a Homebrew keg, like
+/// `<prefix>/Cellar/<formula>/<version>/<rest>`.
+fn homebrew_opt_path(library: &Utf8Path) -> Option<Utf8PathBuf> {
+    let (prefix, keg) = library.as_str().split_once("/Cellar/")?;
+    let mut parts = keg.splitn(3, '/');
+    let (formula, _version, rest) = (parts.next()?, parts.next()?, parts.next()?);
+    Some(Utf8PathBuf::from(format!("{prefix}/opt/{formula}/{rest}")))
+}
+
+/// Repair the installed Ruby that `request` matches, or the one in use.
+pub(crate) fn repair(global_args: &GlobalArgs, request: Option<RubyRequest>) -> Result<()> {
+    let config = Config::new(global_args, request)?;
+    let ruby = config.current_ruby().ok_or(Error::NoMatchingRuby)?;
+    let Some(provenance) = Provenance::read(&ruby.path) else {
+        return Err(Error::NotInstalledByRv { ruby: ruby.path });
+    };
+    println!("Repairing {} in {}", ruby.version.cyan(), ruby.path.cyan());
+
+    match Linkage::current() {
+        Some(linkage) => {
+            let relinked = relink(linkage, &ruby.path)?;
+            if relinked == 0 {
+                println!("No broken library references found");
+            }
+        }
+        None => debug!("Not checking library references, which is only done on macOS and Linux"),
+    }
+
+    let failed = match verify::verify_ruby(&config, &ruby) {
+        Ok(()) => return Ok(()),
+        Err(verify::Error::ChecksFailed { failed, .. }) => failed,
+        Err(err) => return Err(err.into()),
+    };
+    if failed
+        .iter()
+        .all(|check| NETWORK_CHECKS.contains(&check.as_str()))
+    {
+        return Err(verify::Error::ChecksFailed {
+            ruby: ruby.version.to_string(),
+            failed,
+        }
+        .into());
+    }
+
+    let archive = install::cached_archive(&config, &provenance.source).ok_or_else(|| {
+        Error::Unrecoverable {
+            ruby: ruby.version.to_string(),
+        }
+    })?;
+    println!(
+        "Reinstalling {} from {}, since it still fails {}",
+        ruby.version.cyan(),
+        archive.cyan(),
+        failed.join(", ")
+    );
+    let ruby = install::reinstall(&config, &ruby.path, &archive, provenance.source)?;
+    verify::verify_ruby(&config, &ruby)?;
+
+    println!("Repaired {}", ruby.version.cyan());
+    Ok(())
+}
+
+/// Point the binaries of the Ruby in `ruby_dir` at the libraries they can't find anymore,
+/// returning how many references were fixed.
+fn relink(linkage: Linkage, ruby_dir: &Utf8Path) -> Result<usize> {
+    if let Some(tool) = linkage
+        .tools()
+        .iter()
+        .find(|tool| which::which(tool).is_err())
+    {
+        warn!("Not checking library references, because `{tool}` isn't installed");
+        return Ok(0);
+    }
+
+    let mut relinked = 0;
+    for binary in binaries(linkage, ruby_dir) {
+        let output = linkage
+            .list_command(&binary)
+            .stdin(Stdio::null())
+            .output()?;
+        // Scripts and other files that aren't binaries make these tools fail, and have nothing
+        // to fix.
+        if !output.status.success() {
+            continue;
+        }
+
+        let output = String::from_utf8_lossy(&output.stdout);
+        for library in linkage.libraries(&output) {
+            if linkage == Linkage::MachO && Utf8Path::new(&library).exists() {
+                continue;
+            }
+            let Some(found_at) = new_location(ruby_dir, &library) else {
+                warn!("{binary} links to {library}, which is missing");
+                continue;
+            };
+
+            let relink = Relink {
+                binary: binary.clone(),
+                library,
+                found_at,
+            };
+            for mut cmd in linkage.relink_commands(ruby_dir, &relink) {
+                run(&relink.binary, &mut cmd)?;
+            }
+            println!(
+                "Relinked {}: {} is now {}",
+                relink.binary.cyan(),
+                relink.library,
+                relink.found_at.cyan()
+            );
+            relinked += 1;
+        }
+    }
+    Ok(relinked)
+}
+
+/// The binaries in the Ruby in `ruby_dir` that link to libraries.
+fn binaries(linkage: Linkage, ruby_dir: &Utf8Path) -> Vec<Utf8PathBuf> {
+    linkage
+        .binary_patterns()
+        .iter()
+        .filter_map(|pattern| glob::glob(ruby_dir.join(pattern).as_str()).ok())
+        .flat_map(|paths| paths.flatten())
+        .filter(|path| path.is_file())
+        .filter_map(|path| Utf8PathBuf::from_path_buf(path).ok())
+        .collect()
+}
+
+fn run(binary: &Utf8Path, cmd: &mut Command) -> Result<()> {
+    debug!("Running {cmd:?}");
+    let output = cmd.stdin(Stdio::null()).output()?;
+    if output.status.success() {
+        return Ok(());
+    }
+    Err(Error::RelinkFailed {
+        binary: binary.to_path_buf(),
+        command: format!(
+            "{} {}",
+            cmd.get_program().to_string_lossy(),
+            cmd.get_args()
+                .map(|arg| arg.to_string_lossy())
+                .collect::<Vec<_>>()
+                .join(" ")
+        ),
+        output: String::from_utf8_lossy(&output.stderr).trim().to_owned(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_macho_libraries() {
+        let output = "\
+/rubies/ruby-3.4.1/lib/ruby/3.4.0/arm64-darwin24/openssl.bundle:
+\t/opt/homebrew/Cellar/openssl@3/3.3.1/lib/libssl.3.dylib (compatibility version 3.0.0, current version 3.0.0)
+\t@rpath/libruby.3.4.dylib (compatibility version 3.4.0, current version 3.4.1)
+\t/usr/lib/libSystem.B.dylib (compatibility version 1.0.0, current version 1351.0.0)
+";
+        assert_eq!(
+            Linkage::MachO.libraries(output),
+            ["/opt/homebrew/Cellar/openssl@3/3.3.1/lib/libssl.3.dylib"]
+        );
+    }
+
+    #[test]
+    fn test_elf_libraries() {
+        let output = "\
+\tlinux-vdso.so.1 (0x00007ffd)
+\tlibruby.so.3.4 => /rubies/ruby-3.4.1/lib/libruby.so.3.4 (0x00007f5a)
+\tlibyaml-0.so.2 => not found
+\tlibc.so.6 => /lib/x86_64-linux-gnu/libc.so.6 (0x00007f59)
+";
+        assert_eq!(Linkage::Elf.libraries(output), ["libyaml-0.so.2"]);
+    }
+
+    #[test]
+    fn test_homebrew_opt_path() {
+        assert_eq!(
+            homebrew_opt_path(Utf8Path::new(
+                "/opt/homebrew/Cellar/openssl@3/3.3.1/lib/libssl.3.dylib"
+            )),
+            Some("/opt/homebrew/opt/openssl@3/lib/libssl.3.dylib".into())
+        );
+        assert_eq!(
+            homebrew_opt_path(Utf8Path::new("/usr/local/lib/libyaml.dylib")),
+            None
+        );
+    }
+
+    #[test]
+    fn test_relative_dir() {
+        let ruby_dir = Utf8Path::new("/rubies/ruby-3.4.1");
+        let lib = ruby_dir.join("lib");
+        assert_eq!(
+            relative_dir(ruby_dir, &ruby_dir.join("bin/ruby"), &lib),
+            Some("../lib".into())
+        );
+        assert_eq!(
+            relative_dir(ruby_dir, &lib.join("libruby.so.3.4"), &lib),
+            Some("".into())
+        );
+        assert_eq!(
+            relative_dir(
+                ruby_dir,
+                &lib.join("ruby/3.4.0/x86_64-linux/psych.so"),
+                &lib
+            ),
+            Some("../../..".into())
+        );
+        assert_eq!(
+            relative_dir(
+                ruby_dir,
+                &ruby_dir.join("bin/ruby"),
+                Utf8Path::new("/opt/homebrew/opt/libyaml/lib")
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn test_relink_commands_use_relative_paths() {
+        let ruby_dir = Utf8Path::new("/rubies/ruby-3.4.1");
+        let args = |cmd: &Command| {
+            cmd.get_args()
+                .map(|arg| arg.to_string_lossy().into_owned())
+                .collect::<Vec<_>>()
+        };
+
+        let relink = Relink {
+            binary: ruby_dir.join("bin/ruby"),
+            library: "libyaml-0.so.2".to_owned(),
+            found_at: ruby_dir.join("lib/libyaml-0.so.2"),
+        };
+        let commands = Linkage::Elf.relink_commands(ruby_dir, &relink);
+        assert_eq!(
+            args(&commands[0]),
+            [
+                "--add-rpath",
+                "$ORIGIN/../lib",
+                "/rubies/ruby-3.4.1/bin/ruby"
+            ]
+        );
+
+        let relink = Relink {
+            binary: ruby_dir.join("lib/libruby.3.4.dylib"),
+            library: "/gone/lib/libyaml-0.2.dylib".to_owned(),
+            found_at: ruby_dir.join("lib/libyaml-0.2.dylib"),
+        };
+        let commands = Linkage::MachO.relink_commands(ruby_dir, &relink);
+        assert_eq!(
+            args(&commands[0]),
+            [
+                "-change",
+                "/gone/lib/libyaml-0.2.dylib",
+                "@loader_path/libyaml-0.2.dylib",
+                "/rubies/ruby-3.4.1/lib/libruby.3.4.dylib"
+            ]
+        );
+
+        // Libraries outside of the Ruby stay where they are.
+        let relink = Relink {
+            binary: ruby_dir.join("bin/ruby"),
+            library: "libyaml-0.so.2".to_owned(),
+            found_at: "/opt/homebrew/opt/libyaml/lib/libyaml-0.so.2".into(),
+        };
+        let commands = Linkage::Elf.relink_commands(ruby_dir, &relink);
+        assert_eq!(args(&commands[0])[1], "/opt/homebrew/opt/libyaml/lib");
+    }
+
+    #[test]
+    fn test_new_location() {
+        let dir = camino_tempfile::tempdir().unwrap();
+        let ruby_dir = dir.path();
+        fs_err::create_dir_all(ruby_dir.join("lib")).unwrap();
+        fs_err::write(ruby_dir.join("lib/libyaml-0.so.2"), "").unwrap();
+
+        assert_eq!(
+            new_location(ruby_dir, "libyaml-0.so.2"),
+            Some(ruby_dir.join("lib/libyaml-0.so.2"))
+        );
+        assert_eq!(new_location(ruby_dir, "/gone/lib/libffi.8.dylib"), None);
+    }
+}
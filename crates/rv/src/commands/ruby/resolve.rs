@@ -0,0 +1,121 @@
+use anstream::{print, println};
+use rv_ruby::canonical_name::CanonicalName;
+use rv_ruby::request::RubyRequest;
+use rv_ruby::version::RubyVersion;
+
+use crate::{GlobalArgs, config::Config};
+
+mod explain;
+
+use explain::{Explanation, ExplicitSource};
+
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+pub enum Error {
+    #[error("No available or installed Ruby matches {request}")]
+    #[diagnostic(code(RV1801), help("`rv ruby list` shows every Ruby rv knows about"))]
+    NoMatchingRuby { request: RubyRequest },
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    ConfigError(#[from] crate::config::Error),
+}
+
+type Result<T> = miette::Result<T, Error>;
+
+/// Print the exact version `request`, or the pinned version if there's no request, resolves to.
+/// With `all`, print every version that can be installed instead. With `asdf_format`, versions
+/// are named like asdf and mise name them, e.g. `3.4.1` or `jruby-9.4.8.0`, and `all` prints them
+/// on one line, like an asdf plugin's `list-all`. With `explain`, print how the Ruby was chosen
+/// instead.
+pub(crate) async fn resolve(
+    global_args: &GlobalArgs,
+    request: Option<RubyRequest>,
+    all: bool,
+    asdf_format: bool,
+    explain: bool,
+) -> Result<()> {
+    let explicit_source = explicit_source(global_args, request.as_ref());
+    let config = Config::new(global_args, request)?;
+
+    if explain {
+        let current_dir = std::env::current_dir().map_err(crate::config::Error::from)?;
+        let current_dir =
+            camino::Utf8PathBuf::try_from(current_dir).map_err(crate::config::Error::from)?;
+        let version_files = config
+            .rv_settings
+            .version_file_order()
+            .map_err(crate::config::Error::from)?;
+        let explanation = Explanation::new(
+            config.requested_ruby.clone(),
+            explicit_source,
+            version_files,
+            &current_dir,
+            &rv_dirs::home_dir(),
+            &rv_dirs::root_dir(),
+            config.rubies(),
+        );
+        print!("{}", explanation.render());
+        return Ok(());
+    }
+
+    let name = |version: &RubyVersion| {
+        if asdf_format {
+            version.canonical_name()
+        } else {
+            version.to_string()
+        }
+    };
+
+    if all {
+        let mut versions: Vec<_> = config
+            .remote_rubies()
+            .await
+            .into_iter()
+            .map(|ruby| ruby.version)
+            .collect();
+        versions.sort();
+        versions.dedup();
+        let names: Vec<_> = versions.iter().map(name).collect();
+        if asdf_format {
+            println!("{}", names.join(" "));
+        } else {
+            for name in names {
+                println!("{name}");
+            }
+        }
+        return Ok(());
+    }
+
+    let version = config
+        .resolve_requested_version()
+        .await
+        .map_err(|err| match err {
+            crate::config::Error::NoMatchingRuby => Error::NoMatchingRuby {
+                request: config.ruby_request(),
+            },
+            err => err.into(),
+        })?;
+    println!("{}", name(&version));
+
+    Ok(())
+}
+
+/// Where an explicit request for a Ruby came from, if there's one.
+fn explicit_source(
+    global_args: &GlobalArgs,
+    request: Option<&RubyRequest>,
+) -> Option<ExplicitSource> {
+    if request.is_some() {
+        return Some(ExplicitSource::Argument);
+    }
+    let flag = global_args.ruby.as_ref()?;
+    // The flag takes its value from the environment variable when it's not given.
+    let from_env = std::env::var("RV_RUBY_VERSION")
+        .ok()
+        .and_then(|value| value.parse::<RubyRequest>().ok())
+        .is_some_and(|env| env.to_string() == flag.to_string());
+    Some(if from_env {
+        ExplicitSource::Env
+    } else {
+        ExplicitSource::Flag
+    })
+}
@@ -0,0 +1,331 @@
+//! `rv ruby resolve --explain`: how rv chose the Ruby it uses here. That's where the request came
+//! from, what it asks for, and why every installed Ruby was or wasn't picked.
+
+use std::fmt::Write;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use owo_colors::OwoColorize;
+use rv_core::request::{RequestedRuby, VersionFile, find_directory_ruby, project_search_dirs};
+use rv_ruby::Ruby;
+use rv_ruby::request::{RubyRequest, Source};
+
+/// Where an explicit request came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExplicitSource {
+    /// The version given to `rv ruby resolve`.
+    Argument,
+    /// The `--ruby` flag.
+    Flag,
+    /// The `RV_RUBY_VERSION` environment variable.
+    Env,
+}
+
+/// An installed Ruby, and what the request made of it.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Candidate {
+    pub name: String,
+    pub path: Utf8PathBuf,
+    pub verdict: Verdict,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Verdict {
+    /// The newest Ruby that satisfies the request.
+    Chosen,
+    /// It satisfies the request, but the chosen one is newer.
+    Older,
+    /// It's a different engine than the request asks for.
+    WrongEngine,
+    /// It doesn't satisfy the request.
+    NoMatch,
+}
+
+/// Everything that went into choosing the Ruby.
+#[derive(Debug)]
+pub struct Explanation {
+    pub requested_ruby: RequestedRuby,
+    pub explicit_source: Option<ExplicitSource>,
+    /// The directories searched for version files, nearest first, and then the home directory.
+    pub searched_dirs: Vec<Utf8PathBuf>,
+    pub version_files: Vec<VersionFile>,
+    /// Version files next to the one that won, which it took precedence over.
+    pub shadowed: Vec<(RubyRequest, Source)>,
+    pub candidates: Vec<Candidate>,
+}
+
+impl Explanation {
+    pub fn new(
+        requested_ruby: RequestedRuby,
+        explicit_source: Option<ExplicitSource>,
+        version_files: Vec<VersionFile>,
+        current_dir: &Utf8Path,
+        home_dir: &Utf8Path,
+        root: &Utf8Path,
+        rubies: Vec<Ruby>,
+    ) -> Self {
+        let mut searched_dirs = project_search_dirs(current_dir, home_dir, root, None);
+        searched_dirs.push(home_dir.to_owned());
+
+        let shadowed = match &requested_ruby {
+            RequestedRuby::Project((_, source)) | RequestedRuby::User((_, source)) => {
+                shadowed_sources(source, &version_files)
+            }
+            RequestedRuby::Explicit(_) | RequestedRuby::Global => Vec::new(),
+        };
+        // A version file only ends the search in the directory it's in.
+        if let RequestedRuby::Project((_, source)) = &requested_ruby
+            && let Some(dir) = source.path().parent()
+            && let Some(position) = searched_dirs.iter().position(|searched| searched == dir)
+        {
+            searched_dirs.truncate(position + 1);
+        }
+
+        let request = requested_ruby.ruby_request();
+        let candidates = candidates(&request, rubies);
+
+        Self {
+            requested_ruby,
+            explicit_source,
+            searched_dirs,
+            version_files,
+            shadowed,
+            candidates,
+        }
+    }
+
+    /// The explanation, for people to read.
+    pub fn render(&self) -> String {
+        let request = self.requested_ruby.ruby_request();
+        let mut out = String::new();
+
+        let source = match (&self.requested_ruby, self.explicit_source) {
+            (RequestedRuby::Explicit(_), Some(ExplicitSource::Argument)) => {
+                "the version given on the command line".to_string()
+            }
+            (RequestedRuby::Explicit(_), Some(ExplicitSource::Flag)) => "--ruby".to_string(),
+            (RequestedRuby::Explicit(_), Some(ExplicitSource::Env)) => {
+                "the RV_RUBY_VERSION environment variable".to_string()
+            }
+            (RequestedRuby::Explicit(_), None) => "an explicit request".to_string(),
+            (RequestedRuby::Project((_, source)) | RequestedRuby::User((_, source)), _) => {
+                source.path().to_string()
+            }
+            (RequestedRuby::Global, _) => "nothing, so any Ruby will do".to_string(),
+        };
+        writeln!(out, "{} {}", "Requested by:".bold(), source.cyan()).unwrap();
+        if !matches!(self.requested_ruby, RequestedRuby::Global) {
+            writeln!(
+                out,
+                "{} {} ({})",
+                "Request:".bold(),
+                request.cyan(),
+                describe_request(&request)
+            )
+            .unwrap();
+        }
+
+        if !matches!(self.requested_ruby, RequestedRuby::Explicit(_)) {
+            let files: Vec<_> = self
+                .version_files
+                .iter()
+                .map(|file| file.file_name())
+                .collect();
+            writeln!(
+                out,
+                "\n{} {}, in:",
+                "Version files, in order:".bold(),
+                files.join(", ")
+            )
+            .unwrap();
+            for dir in &self.searched_dirs {
+                writeln!(out, "  {dir}").unwrap();
+            }
+        }
+        for (shadowed_request, source) in &self.shadowed {
+            writeln!(
+                out,
+                "  {} also pins {}, but comes later in the order",
+                source.path(),
+                shadowed_request.cyan()
+            )
+            .unwrap();
+        }
+
+        writeln!(out, "\n{}", "Installed rubies:".bold()).unwrap();
+        if self.candidates.is_empty() {
+            writeln!(out, "  none").unwrap();
+        }
+        let chosen = self
+            .candidates
+            .iter()
+            .find(|candidate| candidate.verdict == Verdict::Chosen);
+        for candidate in &self.candidates {
+            let reason = match candidate.verdict {
+                Verdict::Chosen => format!(
+                    "{} the newest one that satisfies {request}",
+                    "chosen:".green()
+                ),
+                Verdict::Older => format!(
+                    "{} satisfies {request}, but {} is newer",
+                    "skipped:".yellow(),
+                    chosen.map_or("another", |chosen| chosen.name.as_str())
+                ),
+                Verdict::WrongEngine => {
+                    format!("{} a different engine than {request}", "rejected:".red())
+                }
+                Verdict::NoMatch => {
+                    format!("{} doesn't satisfy {request}", "rejected:".red())
+                }
+            };
+            writeln!(
+                out,
+                "  {} {} {reason}",
+                candidate.name,
+                candidate.path.dimmed()
+            )
+            .unwrap();
+        }
+        if chosen.is_none() {
+            writeln!(
+                out,
+                "\nNo installed Ruby satisfies {request}. Run `rv ruby install` to install one."
+            )
+            .unwrap();
+        }
+
+        out
+    }
+}
+
+/// What `request` asks for, in words.
+fn describe_request(request: &RubyRequest) -> String {
+    match request {
+        RubyRequest::Dev => "the development build of Ruby".to_string(),
+        RubyRequest::Range(range) => format!("any {} release within the range", range.engine),
+        RubyRequest::Released(released) => {
+            let parts: Vec<String> = [
+                released.major,
+                released.minor,
+                released.patch,
+                released.tiny,
+            ]
+            .into_iter()
+            .map_while(|part| part.map(|part| part.to_string()))
+            .collect();
+            let version = parts.join(".");
+            match (&released.prerelease, released.patch) {
+                (Some(prerelease), _) => {
+                    format!("{} {version}-{prerelease} exactly", released.engine)
+                }
+                (None, Some(_)) => format!("{} {version} exactly", released.engine),
+                (None, None) if parts.is_empty() => format!("the newest {}", released.engine),
+                (None, None) => {
+                    format!("the newest {} release of {version}.x", released.engine)
+                }
+            }
+        }
+    }
+}
+
+/// The files in the directory of `source` that pin a Ruby too, but come after it in
+/// `version_files`.
+fn shadowed_sources(source: &Source, version_files: &[VersionFile]) -> Vec<(RubyRequest, Source)> {
+    let Some(dir) = source.path().parent() else {
+        return Vec::new();
+    };
+    let Some(file_name) = source.path().file_name() else {
+        return Vec::new();
+    };
+    version_files
+        .iter()
+        .skip_while(|file| file.file_name() != file_name)
+        .skip(1)
+        .filter_map(|&file| find_directory_ruby(dir, &[file]).ok().flatten())
+        .collect()
+}
+
+/// Every installed Ruby, newest first, with what `request` made of it.
+fn candidates(request: &RubyRequest, mut rubies: Vec<Ruby>) -> Vec<Candidate> {
+    rubies.sort();
+    rubies.reverse();
+
+    let mut chosen = false;
+    rubies
+        .into_iter()
+        .map(|ruby| {
+            let verdict = if !satisfies(&ruby, request) {
+                match request {
+                    RubyRequest::Released(released) if released.engine != ruby.version.engine => {
+                        Verdict::WrongEngine
+                    }
+                    RubyRequest::Range(range) if range.engine != ruby.version.engine => {
+                        Verdict::WrongEngine
+                    }
+                    _ => Verdict::NoMatch,
+                }
+            } else if chosen {
+                Verdict::Older
+            } else {
+                chosen = true;
+                Verdict::Chosen
+            };
+            Candidate {
+                name: ruby.version.to_string(),
+                path: ruby.path,
+                verdict,
+            }
+        })
+        .collect()
+}
+
+/// Whether `ruby` satisfies `request`, the way rv picks rubies from installed ones.
+fn satisfies(ruby: &Ruby, request: &RubyRequest) -> bool {
+    if ruby.path.file_name() == Some("ruby-dev") {
+        request.is_dev()
+    } else {
+        ruby.version.satisfies(request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_describe_request() {
+        let describe = |request: &str| describe_request(&request.parse().unwrap());
+        assert_eq!(describe("3.4"), "the newest ruby release of 3.4.x");
+        assert_eq!(describe("3.4.1"), "ruby 3.4.1 exactly");
+        assert_eq!(describe("jruby-9.4"), "the newest jruby release of 9.4.x");
+        assert_eq!(
+            describe(">= 3.2, < 3.4"),
+            "any ruby release within the range"
+        );
+        assert_eq!(describe("dev"), "the development build of Ruby");
+        assert_eq!(describe_request(&RubyRequest::default()), "the newest ruby");
+    }
+
+    #[test]
+    fn test_shadowed_sources() {
+        let temp_dir = camino_tempfile::Utf8TempDir::new().unwrap();
+        let dir = temp_dir.path();
+        fs_err::write(dir.join(".ruby-version"), "3.4.1\n").unwrap();
+        fs_err::write(dir.join(".tool-versions"), "ruby 3.3.6\n").unwrap();
+
+        let (request, source) = find_directory_ruby(dir, &VersionFile::DEFAULT_ORDER)
+            .unwrap()
+            .unwrap();
+        assert_eq!(request.to_string(), "ruby-3.4.1");
+
+        let shadowed = shadowed_sources(&source, &VersionFile::DEFAULT_ORDER);
+        assert_eq!(shadowed.len(), 1);
+        assert_eq!(shadowed[0].0.to_string(), "ruby-3.3.6");
+        assert_eq!(shadowed[0].1.path(), dir.join(".tool-versions"));
+
+        // In the other order, .ruby-version is the one that's shadowed.
+        let order = [VersionFile::ToolVersions, VersionFile::RubyVersion];
+        let (_, source) = find_directory_ruby(dir, &order).unwrap().unwrap();
+        let shadowed = shadowed_sources(&source, &order);
+        assert_eq!(shadowed[0].1.path(), dir.join(".ruby-version"));
+    }
+}
@@ -6,6 +6,7 @@ use crate::GlobalArgs;
 #[derive(Debug, thiserror::Error, miette::Diagnostic)]
 pub enum Error {
     #[error(transparent)]
+    #[diagnostic(transparent)]
     RunError(#[from] crate::commands::run::Error),
 }
 
@@ -19,6 +20,7 @@ pub(crate) async fn run(
     global_args: &GlobalArgs,
     request: Option<RubyRequest>,
     no_install: bool,
+    isolated: bool,
     args: Vec<String>,
 ) -> Result<()> {
     let args = [vec!["ruby".to_string()], args].concat();
@@ -71,6 +73,7 @@ pub(crate) async fn run(
     let run_args = crate::commands::run::RunArgs {
         ruby: request,
         no_install,
+        isolated,
         args: args.to_vec(),
     };
 
@@ -1,6 +1,7 @@
 use anstream::println;
 use camino::Utf8PathBuf;
 use owo_colors::OwoColorize;
+use rv_core::provenance::Provenance;
 use rv_ruby::request::RubyRequest;
 
 use crate::{GlobalArgs, config::Config};
@@ -8,23 +9,51 @@ use crate::{GlobalArgs, config::Config};
 #[derive(Debug, thiserror::Error, miette::Diagnostic)]
 pub enum Error {
     #[error("no matching ruby version found")]
+    #[diagnostic(code(RV1401))]
     NoMatchingRuby,
     #[error(transparent)]
+    #[diagnostic(transparent)]
     ConfigError(#[from] crate::config::Error),
     #[error("Could not delete dir {dir}: {error}")]
+    #[diagnostic(code(RV1402))]
     IoError {
         dir: Utf8PathBuf,
         error: std::io::Error,
     },
+    #[error("No Ruby installed by rv matches {request}")]
+    #[diagnostic(
+        code(RV1403),
+        help(
+            "Rubies installed by other tools, or by older versions of rv, are left alone with `--only-rv-managed`"
+        )
+    )]
+    NoManagedRuby { request: RubyRequest },
 }
 
 type Result<T> = miette::Result<T, Error>;
 
-/// Uninstall the given Ruby version.
-pub(crate) async fn uninstall(global_args: &GlobalArgs, request: RubyRequest) -> Result<()> {
-    let config = Config::new(global_args, Some(request))?;
+/// Uninstall the given Ruby version. With `only_rv_managed`, only rubies that rv recorded
+/// installing (see [`Provenance`]) are considered, so that a Ruby another tool installed into the
+/// same directory is never deleted.
+pub(crate) async fn uninstall(
+    global_args: &GlobalArgs,
+    request: RubyRequest,
+    only_rv_managed: bool,
+) -> Result<()> {
+    let config = Config::new(global_args, Some(request.clone()))?;
 
-    let ruby = config.current_ruby().ok_or(Error::NoMatchingRuby)?;
+    let ruby = if only_rv_managed {
+        let managed: Vec<_> = config
+            .rubies()
+            .into_iter()
+            .filter(|ruby| Provenance::read(&ruby.path).is_some())
+            .collect();
+        request
+            .find_match_in(&managed)
+            .ok_or(Error::NoManagedRuby { request })?
+    } else {
+        config.current_ruby().ok_or(Error::NoMatchingRuby)?
+    };
     let ruby_path = ruby.path;
     println!("Deleting {}", ruby_path.cyan());
 
@@ -0,0 +1,196 @@
+use std::borrow::Cow;
+use std::process::{Command, Stdio};
+
+use anstream::println;
+use owo_colors::OwoColorize;
+use rv_ruby::Ruby;
+use rv_ruby::request::RubyRequest;
+use tabled::{Table, settings::Style};
+use tracing::debug;
+
+use crate::{GlobalArgs, config::Config};
+
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+pub enum Error {
+    #[error("no matching ruby version found")]
+    #[diagnostic(code(RV1601))]
+    NoMatchingRuby,
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    ConfigError(#[from] crate::config::Error),
+    #[error(transparent)]
+    #[diagnostic(code(RV1602))]
+    IoError(#[from] std::io::Error),
+    #[error("{ruby} failed {} of its checks: {}", failed.len(), failed.join(", "))]
+    #[diagnostic(
+        code(RV1603),
+        help("Reinstall it with `rv ruby install --force`, or report a broken build to rv")
+    )]
+    ChecksFailed { ruby: String, failed: Vec<String> },
+}
+
+type Result<T> = miette::Result<T, Error>;
+
+/// Something a working Ruby installation should be able to do.
+struct Check {
+    name: &'static str,
+    /// Ruby code that prints a short description of what it found, and fails if it can't.
+    script: &'static str,
+    /// Whether the check connects to rubygems.org, so it can't run offline.
+    online: bool,
+}
+
+const CHECKS: &[Check] = &[
+    Check {
+        name: "ruby -e",
+        script: "print RUBY_DESCRIPTION",
+        online: false,
+    },
+    Check {
+        name: "openssl",
+        script: "require 'openssl'; print OpenSSL::OPENSSL_LIBRARY_VERSION",
+        online: false,
+    },
+    Check {
+        name: "zlib",
+        script: "require 'zlib'; print 'zlib ', Zlib.zlib_version",
+        online: false,
+    },
+    Check {
+        name: "psych",
+        script: "require 'psych'; print 'libyaml ', Psych::LIBYAML_VERSION",
+        online: false,
+    },
+    Check {
+        name: "rubygems",
+        script: "require 'rubygems'; print 'RubyGems ', Gem::VERSION",
+        online: false,
+    },
+    Check {
+        name: "certificates",
+        script: "require 'net/http'; \
+                 Net::HTTP.start('rubygems.org', 443, use_ssl: true, \
+                   verify_mode: OpenSSL::SSL::VERIFY_PEER, open_timeout: 10, read_timeout: 10) \
+                   { |http| print 'https://rubygems.org responded ', http.head('/').code }",
+        online: true,
+    },
+];
+
+enum Outcome {
+    Pass(String),
+    Fail(String),
+    Skipped(&'static str),
+}
+
+/// The outcome of one check.
+struct CheckResult {
+    check: &'static str,
+    outcome: Outcome,
+}
+
+impl tabled::Tabled for CheckResult {
+    const LENGTH: usize = 3;
+
+    fn fields(&self) -> Vec<Cow<'_, str>> {
+        let (result, details) = match &self.outcome {
+            Outcome::Pass(details) => ("pass".green().to_string(), details.as_str()),
+            Outcome::Fail(details) => ("fail".red().to_string(), details.as_str()),
+            Outcome::Skipped(reason) => ("skipped".dimmed().to_string(), *reason),
+        };
+        vec![
+            Cow::Borrowed(self.check),
+            Cow::Owned(result),
+            Cow::Borrowed(details),
+        ]
+    }
+
+    fn headers() -> Vec<Cow<'static, str>> {
+        vec!["Check".into(), "Result".into(), "Details".into()]
+    }
+}
+
+/// Check that an installed Ruby works, by running a few things with it that broken builds get
+/// wrong, like loading its native extensions and verifying certificates.
+pub(crate) fn verify(global_args: &GlobalArgs, request: Option<RubyRequest>) -> Result<()> {
+    let config = Config::new(global_args, request)?;
+    let ruby = config.current_ruby().ok_or(Error::NoMatchingRuby)?;
+    verify_ruby(&config, &ruby)
+}
+
+/// Run every check with `ruby`, print a table of the results, and fail if any check failed.
+pub(crate) fn verify_ruby(config: &Config, ruby: &Ruby) -> Result<()> {
+    println!("Verifying {} in {}", ruby.version.cyan(), ruby.path.cyan());
+
+    let results = CHECKS
+        .iter()
+        .map(|check| run_check(config, ruby, check))
+        .collect::<Result<Vec<_>>>()?;
+    let failed: Vec<_> = results
+        .iter()
+        .filter(|result| matches!(result.outcome, Outcome::Fail(_)))
+        .map(|result| result.check.to_owned())
+        .collect();
+
+    let mut table = Table::new(results);
+    table.with(Style::sharp());
+    println!("{table}");
+
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::ChecksFailed {
+            ruby: ruby.version.to_string(),
+            failed,
+        })
+    }
+}
+
+fn run_check(config: &Config, ruby: &Ruby, check: &Check) -> Result<CheckResult> {
+    if check.online && config.offline {
+        return Ok(CheckResult {
+            check: check.name,
+            outcome: Outcome::Skipped("rv is offline"),
+        });
+    }
+
+    let (unset, set) = config.env_for(Some(ruby))?.split();
+    let mut cmd = Command::new(ruby.executable_path());
+    cmd.arg("-e").arg(check.script).stdin(Stdio::null());
+    for var in unset {
+        cmd.env_remove(var);
+    }
+    cmd.envs(set);
+    debug!("Running check {}: {:?}", check.name, cmd);
+
+    let output = cmd.output()?;
+    let outcome = if output.status.success() {
+        Outcome::Pass(first_line(&output.stdout))
+    } else {
+        // Ruby's error message is at the top of its backtrace.
+        Outcome::Fail(first_line(&output.stderr))
+    };
+    Ok(CheckResult {
+        check: check.name,
+        outcome,
+    })
+}
+
+fn first_line(output: &[u8]) -> String {
+    String::from_utf8_lossy(output)
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .unwrap_or_default()
+        .to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_first_line() {
+        assert_eq!(first_line(b"\n  ruby 3.4.1\nmore\n"), "ruby 3.4.1");
+        assert_eq!(first_line(b""), "");
+    }
+}
@@ -1,10 +1,13 @@
 use camino::{Utf8Path, Utf8PathBuf};
+use camino_tempfile::Utf8TempDir;
 use clap::Args;
 use fs_err as fs;
-use rv_ruby::request::RubyRequest;
+use rv_ruby::{Ruby, bundle_layout::BundleLayout, request::RubyRequest};
+use std::env::{JoinPathsError, join_paths};
+use std::io::{BufRead, BufReader, Read};
 use std::path::PathBuf;
-use std::process::{Command, Output};
-use tracing::debug;
+use std::process::{Command, ExitStatus, Output, Stdio};
+use tracing::{debug, warn};
 
 use crate::script_metadata;
 use crate::{GlobalArgs, config::Config};
@@ -12,15 +15,23 @@ use crate::{GlobalArgs, config::Config};
 #[derive(Debug, thiserror::Error, miette::Diagnostic)]
 pub enum Error {
     #[error("Could not read file {file}: {e}")]
+    #[diagnostic(code(RV1501))]
     CouldNotRead { file: String, e: std::io::Error },
     #[error(transparent)]
+    #[diagnostic(code(RV1502))]
     IoError(#[from] std::io::Error),
     #[error("no matching ruby version found")]
+    #[diagnostic(code(RV1503))]
     NoMatchingRuby,
     #[error(transparent)]
+    #[diagnostic(transparent)]
     ConfigError(#[from] crate::config::Error),
     #[error(transparent)]
+    #[diagnostic(transparent)]
     InstallError(#[from] crate::commands::ruby::install::Error),
+    #[error(transparent)]
+    #[diagnostic(code(RV1504))]
+    JoinPathsError(#[from] JoinPathsError),
 }
 
 type Result<T> = miette::Result<T, Error>;
@@ -36,6 +47,12 @@ pub struct RunArgs {
     #[arg(long)]
     pub no_install: bool,
 
+    /// Run in a scrubbed environment, to reproduce problems without interference from your setup.
+    /// Inherited GEM_HOME, GEM_PATH, RUBYOPT, RUBYLIB and BUNDLE_* variables are removed, gems are
+    /// installed into a temporary GEM_HOME, and PATH only contains rv-managed directories.
+    #[arg(long)]
+    pub isolated: bool,
+
     /// What to run with Ruby available, e.g. `ruby myscript.rb`
     #[arg(trailing_var_arg = true, allow_hyphen_values = true, required = true, value_names = ["COMMAND", "ARGS"])]
     pub args: Vec<String>,
@@ -52,14 +69,18 @@ pub(crate) enum Program {
 pub(crate) struct Invocation {
     pub program: Program,
 
-    pub env: Vec<(&'static str, String)>,
+    pub env: Vec<(String, String)>,
+
+    /// Run in a scrubbed environment, see [`RunArgs::isolated`].
+    pub isolated: bool,
 }
 
 impl Invocation {
     pub fn ruby(env: Vec<(&'static str, String)>) -> Self {
         Self {
             program: Program::Ruby,
-            env,
+            env: env.into_iter().map(|(k, v)| (k.to_string(), v)).collect(),
+            isolated: false,
         }
     }
 
@@ -69,11 +90,30 @@ impl Invocation {
                 executable_path: executable.into(),
                 extra_paths: vec![],
             },
-            env,
+            env: env.into_iter().map(|(k, v)| (k.to_string(), v)).collect(),
+            isolated: false,
         }
     }
+
+    /// Set more environment variables for the program, after the ones it was created with.
+    pub fn with_env(mut self, env: impl IntoIterator<Item = (String, String)>) -> Self {
+        self.env.extend(env);
+        self
+    }
 }
 
+/// Variables that change how ruby, rubygems or bundler behave, removed from isolated runs along
+/// with every `BUNDLE_*` variable.
+const ISOLATED_UNSET_VARS: [&str; 7] = [
+    "GEM_HOME",
+    "GEM_PATH",
+    "GEM_ROOT",
+    "GEM_SPEC_CACHE",
+    "RUBYOPT",
+    "RUBYLIB",
+    "RUBYGEMS_GEMDEPS",
+];
+
 pub(crate) async fn run(global_args: &GlobalArgs, args: RunArgs) -> Result<()> {
     let (script, cmd_args) = args.args.split_first().unwrap();
     let script = Utf8PathBuf::from(script);
@@ -81,7 +121,7 @@ pub(crate) async fn run(global_args: &GlobalArgs, args: RunArgs) -> Result<()> {
     let mut ruby_version = None;
 
     let script_filepath = rv_dirs::canonicalize_utf8(&script).ok();
-    let invocation = if script_filepath
+    let mut invocation = if script_filepath
         .map(|path| path.is_file())
         .unwrap_or_default()
     {
@@ -102,8 +142,10 @@ pub(crate) async fn run(global_args: &GlobalArgs, args: RunArgs) -> Result<()> {
                 extra_paths: vec![],
             },
             env: vec![],
+            isolated: false,
         }
     };
+    invocation.isolated = args.isolated;
 
     if let Some(version) = args.ruby {
         debug!("Using Ruby version from --ruby flag: {}", version);
@@ -134,6 +176,49 @@ pub(crate) fn capture_run_no_install(
     Ok(cmd.output()?)
 }
 
+/// Like [`capture_run_no_install`], but also print the command's output to stderr while it runs,
+/// each line after `prefix`, so that long builds can be followed as they happen.
+pub(crate) fn stream_run_no_install(
+    invocation: Invocation,
+    config: &Config,
+    args: Vec<String>,
+    cwd: Option<&Utf8Path>,
+    prefix: &str,
+) -> Result<Output> {
+    let mut cmd = prepare_command(invocation, config, args, cwd)?;
+
+    debug!("Running command: {:?}, and streaming its output", cmd);
+
+    let mut child = cmd.stdout(Stdio::piped()).stderr(Stdio::piped()).spawn()?;
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let stderr = child.stderr.take().expect("stderr is piped");
+    let (stdout, stderr) = std::thread::scope(|scope| {
+        let stderr = scope.spawn(|| tee_lines(stderr, prefix));
+        let stdout = tee_lines(stdout, prefix);
+        (stdout, stderr.join().expect("stderr reader panicked"))
+    });
+    let status = child.wait()?;
+
+    Ok(Output {
+        status,
+        stdout: stdout?,
+        stderr: stderr?,
+    })
+}
+
+/// Read `reader` to the end, printing each line to stderr after `prefix`, and return all of it.
+fn tee_lines(reader: impl Read, prefix: &str) -> std::io::Result<Vec<u8>> {
+    let mut reader = BufReader::new(reader);
+    let mut output = Vec::new();
+    let mut line = Vec::new();
+    while reader.read_until(b'\n', &mut line)? > 0 {
+        let text = String::from_utf8_lossy(&line);
+        anstream::eprintln!("{prefix}{}", text.trim_end_matches(['\r', '\n']));
+        output.append(&mut line);
+    }
+    Ok(output)
+}
+
 pub(crate) async fn run_command(
     invocation: Invocation,
     global_args: &GlobalArgs,
@@ -149,34 +234,83 @@ pub(crate) async fn run_command(
     if config.current_ruby().is_none() && install {
         let request = config.ruby_request();
 
-        // Not installed, try to install it.
-        // None means it'll install in whatever default ruby location it chooses.
+        // Not installed, try to install it, in whatever default ruby location it chooses.
         debug!("Ruby not found, so installing {request}");
-        let install_dir = None;
         let tarball_path = None;
         crate::commands::ruby::install::install(
             global_args,
-            install_dir,
+            crate::commands::ruby::install::InstallDir::Default,
             Some(request),
             tarball_path,
             false,
+            false,
+            crate::commands::ruby::install::InstallLayout::Rv,
+            Default::default(),
         )
         .await?
     };
 
-    let cmd = prepare_command(invocation, config, args, Default::default())?;
+    let isolated = invocation.isolated;
+    let mut cmd = prepare_command(invocation, config, args, Default::default())?;
+
+    if isolated {
+        let ruby = config.current_ruby().ok_or(Error::NoMatchingRuby)?;
+        let gem_home = camino_tempfile::Builder::new()
+            .prefix("rv-isolated-")
+            .tempdir()?;
+        isolate(&mut cmd, &ruby, gem_home.path())?;
+
+        debug!("Running isolated command: {:?}", cmd);
+        return exec_isolated(cmd, gem_home);
+    }
 
     debug!("Running command: {:?}", cmd);
     exec(cmd)
 }
 
-fn prepare_command(
+/// Scrub the environment `cmd` inherits, so it only sees `ruby`, a fresh `gem_home`, and the
+/// variables rv itself sets, including its own `BUNDLE_*` ones.
+fn isolate(cmd: &mut Command, ruby: &Ruby, gem_home: &Utf8Path) -> Result<()> {
+    let set_by_rv: Vec<_> = cmd
+        .get_envs()
+        .filter(|(_, value)| value.is_some())
+        .map(|(var, _)| var.to_owned())
+        .collect();
+    for var in ISOLATED_UNSET_VARS {
+        cmd.env_remove(var);
+    }
+    // Engines that read their options from somewhere other than RUBYOPT, like JRuby's JRUBY_OPTS.
+    cmd.env_remove(ruby.version.engine.quirks().options_var());
+    for (var, _) in std::env::vars_os() {
+        if var.to_str().is_some_and(|var| var.starts_with("BUNDLE_")) && !set_by_rv.contains(&var) {
+            cmd.env_remove(var);
+        }
+    }
+
+    let binstub_dir = BundleLayout::new(gem_home, ruby).binstub_dir();
+    let path = join_paths([binstub_dir, ruby.bin_path()])?;
+    cmd.env("PATH", path);
+    cmd.env("GEM_HOME", gem_home);
+    cmd.env("GEM_PATH", gem_home);
+
+    Ok(())
+}
+
+pub(crate) fn prepare_command(
     invocation: Invocation,
     config: &Config,
     args: Vec<String>,
     cwd: Option<&Utf8Path>,
 ) -> Result<Command> {
     let ruby = config.current_ruby().ok_or(Error::NoMatchingRuby)?;
+    for var in ruby.version.engine.quirks().required_env() {
+        if std::env::var_os(var).is_none() {
+            warn!(
+                "{} needs {var} to be set, but it isn't",
+                ruby.version.engine
+            );
+        }
+    }
     let ((unset, set), executable_path) = match invocation.program {
         Program::Ruby => (config.env_for(Some(&ruby))?.split(), ruby.executable_path()),
         Program::Tool {
@@ -253,6 +387,26 @@ fn resolve_tool_on_windows(executable: &Utf8Path, env_vars: &[(&str, String)]) -
     executable.to_owned()
 }
 
+/// Runs an isolated command to completion, then removes its temporary GEM_HOME and exits with the
+/// same code. Replacing the current process, like [`exec`] does, would leave the directory behind.
+fn exec_isolated(mut cmd: Command, gem_home: Utf8TempDir) -> Result<()> {
+    let status = cmd.status()?;
+    drop(gem_home);
+
+    #[allow(clippy::exit)]
+    std::process::exit(exit_code(status))
+}
+
+/// The code to exit with to pass on how a command ended: its own exit code, or, like shells do,
+/// 128 plus the number of the signal that killed it.
+fn exit_code(status: ExitStatus) -> i32 {
+    #[cfg(unix)]
+    if let Some(signal) = std::os::unix::process::ExitStatusExt::signal(&status) {
+        return 128 + signal;
+    }
+    status.code().unwrap_or(1)
+}
+
 /// Spawns a command exec style.
 /// On Unix, replaces the current process with the child.
 /// On Windows, spawns the child, waits, and exits with the same code.
@@ -270,5 +424,5 @@ fn exec(mut cmd: Command) -> Result<()> {
     let status = cmd.status()?;
 
     #[allow(clippy::exit)]
-    std::process::exit(status.code().unwrap_or(1))
+    std::process::exit(exit_code(status))
 }
@@ -7,9 +7,11 @@ use crate::{GlobalArgs, update};
 #[derive(Debug, thiserror::Error, miette::Diagnostic)]
 pub enum Error {
     #[error(transparent)]
+    #[diagnostic(transparent)]
     UpdateError(#[from] update::Error),
 
     #[error(transparent)]
+    #[diagnostic(code(RV5001))]
     IoError(#[from] std::io::Error),
 }
 
@@ -26,20 +28,29 @@ pub enum SelfCommand {
     #[command(about = "Update rv to the latest version")]
     Update,
     #[command(about = "Display rv's version")]
-    Version,
+    Version {
+        /// Also check whether a newer version of rv is available
+        #[arg(long)]
+        check: bool,
+    },
 }
 
 pub(crate) async fn self_cmd(_global_args: &GlobalArgs, args: SelfArgs) -> Result<()> {
     match args.command {
         SelfCommand::Update => update().await?,
-        SelfCommand::Version => version(),
+        SelfCommand::Version { check } => {
+            version();
+            if check {
+                check_for_update().await?;
+            }
+        }
     }
 
     Ok(())
 }
 
 pub(crate) async fn update() -> Result<()> {
-    match run_update("install").await {
+    match run_update("install", true).await {
         Ok(UpdateOutcome::Installed(v)) => {
             eprintln!("✅ New version of `rv` {} installed!", v);
         }
@@ -58,3 +69,19 @@ pub(crate) async fn update() -> Result<()> {
 pub(crate) fn version() {
     println!("rv {}", env!("CARGO_PKG_VERSION"));
 }
+
+/// Tell the user whether a newer rv is available, and how to install it.
+async fn check_for_update() -> Result<()> {
+    let latest = if update::is_homebrew_install() {
+        update::latest_homebrew_release().await?
+    } else {
+        update::latest_release().await?.version().to_string()
+    };
+
+    if update::is_newer(&latest) {
+        println!("rv {latest} is available. Run `rv self update` to install it.");
+    } else {
+        println!("rv is up to date.");
+    }
+    Ok(())
+}
@@ -1,14 +1,16 @@
 pub mod completions;
 pub mod env;
 pub mod init;
+pub mod prompt;
 
 use crate::GlobalArgs;
 use clap::{Args, Subcommand};
 use serde::Serialize;
 
 use crate::commands::shell::completions::completions;
-use crate::commands::shell::env::env;
+use crate::commands::shell::env::{deactivate, env};
 use crate::commands::shell::init::init;
+use crate::commands::shell::prompt::prompt;
 
 #[derive(Args)]
 #[command(args_conflicts_with_subcommands = true)]
@@ -29,6 +31,10 @@ pub enum ShellCommand {
     Completions { shell: Shell },
     #[command(hide = true)]
     Env { shell: Shell },
+    #[command(hide = true)]
+    Deactivate { shell: Shell },
+    #[command(about = "Print the active Ruby, and the pinned one if it differs, for shell prompts")]
+    Prompt,
 }
 
 #[derive(clap::ValueEnum, Clone, Default, Debug, Serialize)]
@@ -38,6 +44,7 @@ pub enum Shell {
     Bash,
     Fish,
     Nu,
+    Elvish,
     #[clap(name = "powershell")]
     PowerShell,
 }
@@ -49,6 +56,7 @@ impl std::fmt::Display for Shell {
             Self::Bash => write!(f, "bash"),
             Self::Fish => write!(f, "fish"),
             Self::Nu => write!(f, "nu"),
+            Self::Elvish => write!(f, "elvish"),
             Self::PowerShell => write!(f, "powershell"),
         }
     }
@@ -57,11 +65,17 @@ impl std::fmt::Display for Shell {
 #[derive(Debug, thiserror::Error, miette::Diagnostic)]
 pub enum Error {
     #[error(transparent)]
+    #[diagnostic(code(RV6001))]
     IoError(#[from] std::io::Error),
     #[error(transparent)]
+    #[diagnostic(transparent)]
     InitError(#[from] crate::commands::shell::init::Error),
     #[error(transparent)]
+    #[diagnostic(transparent)]
     EnvError(#[from] crate::commands::shell::env::Error),
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    PromptError(#[from] crate::commands::shell::prompt::Error),
 }
 
 type Result<T> = miette::Result<T, Error>;
@@ -76,6 +90,8 @@ pub(crate) fn shell(
         Some(ShellCommand::Init { shell }) => init(shell)?,
         Some(ShellCommand::Completions { shell }) => completions(cmd, shell),
         Some(ShellCommand::Env { shell }) => env(global_args, shell)?,
+        Some(ShellCommand::Deactivate { shell }) => deactivate(shell)?,
+        Some(ShellCommand::Prompt) => prompt(global_args)?,
     }
 
     Ok(())
@@ -135,6 +151,16 @@ fn setup(shell: Shell) -> Result<()> {
 
             Ok(())
         }
+        Shell::Elvish => {
+            printdoc! {"
+                {header}
+
+                echo 'eval ({rv} shell init elvish | slurp)' >> ~/.config/elvish/rc.elv
+                echo 'eval ({rv} shell completions elvish | slurp)' >> ~/.config/elvish/rc.elv
+            "};
+
+            Ok(())
+        }
         Shell::PowerShell => {
             printdoc! {"
                 {header}
@@ -165,3 +191,8 @@ pub(crate) fn powershell_escape(s: &str) -> String {
     }
     escaped
 }
+
+/// Quote `s` for elvish, whose single-quoted strings have no escapes other than `''` for `'`.
+pub(crate) fn elvish_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "''"))
+}
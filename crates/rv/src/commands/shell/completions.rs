@@ -1,6 +1,7 @@
-use std::io::stdout;
+use std::io::{Write, stdout};
 
 use clap_complete::{Shell as ClapCompleteShell, generate};
+use indoc::formatdoc;
 
 use super::Shell;
 
@@ -9,23 +10,124 @@ pub fn completions(cmd: &mut clap::Command, shell: Shell) {
     match shell {
         Shell::Zsh => {
             let clap_complete_shell: ClapCompleteShell = ClapCompleteShell::Zsh;
-            generate(clap_complete_shell, cmd, name, &mut stdout());
+            generate(clap_complete_shell, cmd, &name, &mut stdout());
         }
         Shell::Bash => {
             let clap_complete_shell: ClapCompleteShell = ClapCompleteShell::Bash;
-            generate(clap_complete_shell, cmd, name, &mut stdout());
+            generate(clap_complete_shell, cmd, &name, &mut stdout());
         }
         Shell::Fish => {
             let clap_complete_shell: ClapCompleteShell = ClapCompleteShell::Fish;
-            generate(clap_complete_shell, cmd, name, &mut stdout());
+            generate(clap_complete_shell, cmd, &name, &mut stdout());
         }
         Shell::Nu => {
             let clap_complete_shell = clap_complete_nushell::Nushell;
-            generate(clap_complete_shell, cmd, name, &mut stdout());
+            generate(clap_complete_shell, cmd, &name, &mut stdout());
+        }
+        Shell::Elvish => {
+            let clap_complete_shell: ClapCompleteShell = ClapCompleteShell::Elvish;
+            generate(clap_complete_shell, cmd, &name, &mut stdout());
         }
         Shell::PowerShell => {
             let clap_complete_shell: ClapCompleteShell = ClapCompleteShell::PowerShell;
-            generate(clap_complete_shell, cmd, name, &mut stdout());
+            generate(clap_complete_shell, cmd, &name, &mut stdout());
+        }
+    }
+
+    if let Some(script) = dynamic_completions(&name, &shell) {
+        let _ = stdout().write_all(script.as_bytes());
+    }
+}
+
+/// Completions for values clap doesn't know about, like Ruby versions and gem names.
+/// These wrap clap's static completions and ask `rv __complete` for the candidates.
+fn dynamic_completions(name: &str, shell: &Shell) -> Option<String> {
+    match shell {
+        Shell::Bash => Some(formatdoc! {r#"
+
+            _{name}_dynamic() {{
+                local cur="${{COMP_WORDS[COMP_CWORD]}}" kind="" words=() word
+                for word in "${{COMP_WORDS[@]:1:COMP_CWORD-1}}"; do
+                    [[ "$word" == -* ]] || words+=("$word")
+                done
+                if [[ ${{#words[@]}} -eq 2 ]]; then
+                    case "${{words[0]}} ${{words[1]}}" in
+                        "ruby install") kind=installable-rubies ;;
+                        "ruby uninstall" | "ruby pin" | "ruby find" | "ruby run") kind=installed-rubies ;;
+                        "tool install" | "tool run") kind=gems ;;
+                    esac
+                fi
+                if [[ -n "$kind" ]]; then
+                    local IFS=$'\n'
+                    COMPREPLY=($(compgen -W "$({name} __complete "$kind" "$cur" 2>/dev/null)" -- "$cur"))
+                    return 0
+                fi
+                _{name} "$@"
+            }}
+
+            if [[ "${{BASH_VERSINFO[0]}}" -eq 4 && "${{BASH_VERSINFO[1]}}" -ge 4 || "${{BASH_VERSINFO[0]}}" -gt 4 ]]; then
+                complete -F _{name}_dynamic -o nosort -o bashdefault -o default {name}
+            else
+                complete -F _{name}_dynamic -o bashdefault -o default {name}
+            fi
+        "#}),
+        Shell::Zsh => Some(formatdoc! {r#"
+
+            _{name}_dynamic() {{
+                local -a args=(${{words[2,CURRENT-1]:#-*}})
+                local kind
+                if (( ${{#args}} == 2 )); then
+                    case "${{args[1]}} ${{args[2]}}" in
+                        "ruby install") kind=installable-rubies ;;
+                        "ruby uninstall" | "ruby pin" | "ruby find" | "ruby run") kind=installed-rubies ;;
+                        "tool install" | "tool run") kind=gems ;;
+                    esac
+                fi
+                if [[ -n "$kind" ]]; then
+                    local -a candidates=(${{(f)"$({name} __complete $kind "${{words[CURRENT]}}" 2>/dev/null)"}})
+                    compadd -V $kind -a candidates
+                    return
+                fi
+                _{name} "$@"
+            }}
+
+            compdef _{name}_dynamic {name}
+        "#}),
+        Shell::Fish => Some(formatdoc! {r#"
+
+            function __fish_{name}_dynamic_kind
+                set -l args (commandline -opc | string match -v -- '-*')
+                test (count $args) -eq 3; or return 1
+                switch "$args[2] $args[3]"
+                    case 'ruby install'
+                        echo installable-rubies
+                    case 'ruby uninstall' 'ruby pin' 'ruby find' 'ruby run'
+                        echo installed-rubies
+                    case 'tool install' 'tool run'
+                        echo gems
+                    case '*'
+                        return 1
+                end
+            end
+
+            complete -c {name} -n "__fish_{name}_dynamic_kind >/dev/null" -f -k -a "({name} __complete (__fish_{name}_dynamic_kind) (commandline -ct))"
+        "#}),
+        Shell::Nu | Shell::Elvish | Shell::PowerShell => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dynamic_completions_call_rv() {
+        for shell in [Shell::Bash, Shell::Zsh, Shell::Fish] {
+            let script = dynamic_completions("rv", &shell).unwrap();
+            assert!(script.contains("rv __complete"), "{shell}: {script}");
+            assert!(script.contains("installable-rubies"), "{shell}: {script}");
         }
+        assert!(dynamic_completions("rv", &Shell::Nu).is_none());
+        assert!(dynamic_completions("rv", &Shell::Elvish).is_none());
     }
 }
@@ -1,14 +1,28 @@
+use rv_core::env::{ACTIVATION_VARS, Env};
+
 use super::Shell;
-use super::powershell_escape;
+use super::{elvish_quote, powershell_escape};
 use crate::{GlobalArgs, config::Config};
 
+/// Set by `rv shell init` once it has saved the variables rv changes, so that it only saves them
+/// before the first activation.
+pub(crate) const SAVED_MARKER: &str = "_RV_SAVED";
+
+/// Where `rv shell init` saves the value `var` had before rv changed it.
+pub(crate) fn saved_var(var: &str) -> String {
+    format!("_RV_OLD_{var}")
+}
+
 #[derive(Debug, thiserror::Error, miette::Diagnostic)]
 pub enum Error {
     #[error(transparent)]
+    #[diagnostic(code(RV6201))]
     IoError(#[from] std::io::Error),
     #[error(transparent)]
+    #[diagnostic(transparent)]
     ConfigError(#[from] crate::config::Error),
     #[error("Could not serialize JSON: {0}")]
+    #[diagnostic(code(RV6202))]
     Serde(#[from] serde_json::Error),
 }
 
@@ -18,7 +32,64 @@ pub(crate) fn env(global_args: &GlobalArgs, shell: Shell) -> Result<()> {
     let config = Config::new(global_args, None)?;
     let ruby = config.best_ruby();
     let (unset, set) = config.env_for(ruby.as_ref())?.split();
+    print_env(shell, unset, set)
+}
+
+/// Print the commands that undo the shell integration: they put back every variable rv changes as
+/// it was before the first activation, and forget the saved values, so that activating again
+/// saves them afresh.
+pub(crate) fn deactivate(shell: Shell) -> Result<()> {
+    let deactivated =
+        rv_core::env::env_for(None, Default::default()).map_err(crate::config::Error::from)?;
+    let (unset, set) = restored_env(deactivated, |var| std::env::var(var).ok());
+
+    let unset = unset.iter().map(String::as_str).collect();
+    let set = set
+        .iter()
+        .map(|(var, val)| (var.as_str(), val.clone()))
+        .collect();
+    print_env(shell, unset, set)
+}
+
+/// What to unset and set to restore the variables saved before the first activation, looking up
+/// the current environment with `lookup`. `deactivated` is the environment without any Ruby.
+///
+/// PATH is never restored, only cleaned of rv's entries, so that anything added to it since
+/// keeps working. Without saved values, all rv can do is deactivate the Ruby.
+fn restored_env(
+    deactivated: Env,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> (Vec<String>, Vec<(String, String)>) {
+    let saved = lookup(SAVED_MARKER).is_some();
+    let mut unset = Vec::new();
+    let mut set: Vec<_> = deactivated
+        .set()
+        .iter()
+        .filter(|(var, _)| *var == "PATH")
+        .map(|(var, path)| (var.to_string(), path.clone()))
+        .collect();
+
+    for var in ACTIVATION_VARS {
+        let old = saved_var(var);
+        let old_value = lookup(&old);
+        if let Some(value) = old_value.clone().filter(|_| saved) {
+            set.push((var.to_string(), value));
+        } else if lookup(var).is_some() && (saved || deactivated.unset().contains(&var)) {
+            unset.push(var.to_string());
+        }
+        if old_value.is_some() {
+            unset.push(old);
+        }
+    }
+    if saved {
+        unset.push(SAVED_MARKER.to_string());
+    }
 
+    (unset, set)
+}
+
+/// Print the commands that unset `unset` and set `set` in `shell`.
+pub(super) fn print_env(shell: Shell, unset: Vec<&str>, set: Vec<(&str, String)>) -> Result<()> {
     match shell {
         Shell::Zsh | Shell::Bash => {
             if !unset.is_empty() {
@@ -62,6 +133,15 @@ pub(crate) fn env(global_args: &GlobalArgs, shell: Shell) -> Result<()> {
             println!("{}", serialized);
             Ok(())
         }
+        Shell::Elvish => {
+            for var in unset {
+                println!("unset-env {var}");
+            }
+            for (var, val) in set {
+                println!("set-env {var} {}", elvish_quote(&val));
+            }
+            Ok(())
+        }
         Shell::PowerShell => {
             // PowerShell uses $env:VAR for environment variables
             // Use backticks to escape special characters (following uv's pattern)
@@ -76,7 +156,7 @@ pub(crate) fn env(global_args: &GlobalArgs, shell: Shell) -> Result<()> {
     }
 }
 
-fn nu_env(unset: Vec<&str>, set: Vec<(&str, String)>) -> serde_json::Value {
+pub(super) fn nu_env(unset: Vec<&str>, set: Vec<(&str, String)>) -> serde_json::Value {
     // Map from environment variable names to their new values.
     // In nushell, empty JSON object means "unset this var."
     let mut env_changes = serde_json::Map::with_capacity(set.len() + unset.len());
@@ -94,7 +174,7 @@ fn nu_env(unset: Vec<&str>, set: Vec<(&str, String)>) -> serde_json::Value {
 
 // Credit to uv's crates/uv-shell/src/lib.rs
 // Assumes strings will be outputed as "str", so escapes any \ or " character
-fn fish_var_escape(s: String) -> String {
+pub(super) fn fish_var_escape(s: String) -> String {
     let mut escaped = String::with_capacity(s.len());
     for c in s.chars() {
         match c {
@@ -132,6 +212,49 @@ mod tests {
             .expect("Serializing the Nushell env changes to JSON should always succeed");
     }
 
+    fn deactivated() -> Env {
+        let mut env = Env::default();
+        env.insert("PATH", "/usr/bin".to_owned());
+        env
+    }
+
+    #[test]
+    fn restored_env_puts_back_saved_values() {
+        let current = |var: &str| match var {
+            "_RV_SAVED" => Some("1".to_owned()),
+            "_RV_OLD_GEM_HOME" => Some("/home/user/.gem".to_owned()),
+            "GEM_HOME" => Some("/rv/gems".to_owned()),
+            "RUBY_ROOT" => Some("/rv/rubies/ruby-3.4.1".to_owned()),
+            _ => None,
+        };
+
+        let (unset, set) = restored_env(deactivated(), current);
+
+        assert_eq!(
+            set,
+            vec![
+                ("PATH".to_owned(), "/usr/bin".to_owned()),
+                ("GEM_HOME".to_owned(), "/home/user/.gem".to_owned()),
+            ]
+        );
+        assert_eq!(unset, vec!["RUBY_ROOT", "_RV_OLD_GEM_HOME", "_RV_SAVED"]);
+    }
+
+    #[test]
+    fn restored_env_without_saved_values_only_deactivates() {
+        let current = |var: &str| match var {
+            "GEM_HOME" => Some("/rv/gems".to_owned()),
+            "MANPATH" => Some("/usr/share/man".to_owned()),
+            _ => None,
+        };
+
+        let (unset, set) = restored_env(deactivated(), current);
+
+        // MANPATH may well be the user's own, so only what rv sets is unset.
+        assert_eq!(set, vec![("PATH".to_owned(), "/usr/bin".to_owned())]);
+        assert_eq!(unset, vec!["GEM_HOME"]);
+    }
+
     #[test]
     fn fish_var_escape_handles_special_chars() {
         // Typical Unix path passes through unchanged
@@ -153,6 +276,18 @@ mod tests {
         );
     }
 
+    #[test]
+    fn elvish_quote_doubles_single_quotes() {
+        assert_eq!(
+            elvish_quote("/home/user/.rubies/ruby-3.4.1/bin"),
+            "'/home/user/.rubies/ruby-3.4.1/bin'"
+        );
+        assert_eq!(
+            elvish_quote("/home/user/it's $HOME"),
+            "'/home/user/it''s $HOME'"
+        );
+    }
+
     #[test]
     fn powershell_escape_handles_special_chars() {
         // Typical Windows path passes through unchanged
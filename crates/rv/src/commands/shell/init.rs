@@ -1,13 +1,19 @@
+use rv_core::env::ACTIVATION_VARS;
 use shell_quote::{Bash, Fish, QuoteRefExt};
 
-use crate::commands::shell::powershell_escape;
+use crate::commands::shell::env::{SAVED_MARKER, fish_var_escape, saved_var};
+use crate::commands::shell::{elvish_quote, powershell_escape};
 
 use super::Shell;
 
 #[derive(Debug, thiserror::Error, miette::Diagnostic)]
 pub enum Error {
     #[error(transparent)]
+    #[diagnostic(code(RV6101))]
     IoError(#[from] std::io::Error),
+    #[error("Could not serialize JSON: {0}")]
+    #[diagnostic(code(RV6102))]
+    Serde(#[from] serde_json::Error),
 }
 
 type Result<T> = miette::Result<T, Error>;
@@ -17,6 +23,8 @@ pub fn init(shell: Shell) -> Result<()> {
 
     let current_exe = rv_dirs::current_exe()?;
 
+    save_env(&shell, saved_env())?;
+
     match shell {
         Shell::Zsh => {
             let current_exe: String = current_exe.as_str().quoted(Bash);
@@ -26,6 +34,11 @@ pub fn init(shell: Shell) -> Result<()> {
                     eval \"$({current_exe} shell env zsh)\"
                 }}
                 add-zsh-hook preexec _rv_autoload_hook
+                rv_deactivate () {{
+                    add-zsh-hook -d preexec _rv_autoload_hook
+                    eval \"$({current_exe} shell deactivate zsh)\"
+                    unfunction _rv_autoload_hook rv_deactivate
+                }}
                 _rv_autoload_hook
             "};
         }
@@ -39,6 +52,12 @@ pub fn init(shell: Shell) -> Result<()> {
                 then
                     PROMPT_COMMAND=\"_rv_autoload_hook${{PROMPT_COMMAND:+;$PROMPT_COMMAND}}\"
                 fi
+                rv_deactivate() {{
+                    PROMPT_COMMAND=\"${{PROMPT_COMMAND//_rv_autoload_hook;/}}\"
+                    PROMPT_COMMAND=\"${{PROMPT_COMMAND//_rv_autoload_hook/}}\"
+                    eval \"$({current_exe} shell deactivate bash)\"
+                    unset -f _rv_autoload_hook rv_deactivate
+                }}
                 _rv_autoload_hook
             "};
         }
@@ -48,6 +67,11 @@ pub fn init(shell: Shell) -> Result<()> {
                 function _rv_autoload_hook --on-event fish_preexec --description 'Change Ruby version before running every command'
                     {current_exe} shell env fish | source
                 end
+                function rv_deactivate --description 'Stop changing Ruby version, and restore the environment from before'
+                    functions --erase _rv_autoload_hook
+                    {current_exe} shell deactivate fish | source
+                    functions --erase rv_deactivate
+                end
                 _rv_autoload_hook
             "};
         }
@@ -56,14 +80,50 @@ pub fn init(shell: Shell) -> Result<()> {
                 .as_str()
                 .replace('\\', "\\\\")
                 .replace('\'', "\\'");
+            // PATH is a list in nushell, so it's split before loading it. Switching on `cd` too
+            // means the prompt and completions see the right Ruby before the next command runs.
             printdoc! {"
+                def --env _rv_load_env [changes: record] {{
+                    let unset = ($changes | columns | where {{|var| ($changes | get $var) == {{}} }})
+                    hide-env --ignore-errors ...$unset
+                    let set = ($changes | reject ...$unset)
+                    if \"PATH\" in ($set | columns) {{
+                        load-env ($set | update PATH {{ split row (char esep) }})
+                    }} else {{
+                        load-env $set
+                    }}
+                }}
+                def --env _rv_autoload_hook [] {{
+                    _rv_load_env (\"{current_exe}\" shell env nu | from json)
+                }}
                 $env.config = ($env.config | upsert hooks.pre_execution {{
-                    [
-                        {{||
-                            \"{current_exe}\" shell env nu | from json | load-env
-                        }}
-                    ]
+                    [{{|| _rv_autoload_hook }}]
+                }})
+                $env.config = ($env.config | upsert hooks.env_change.PWD {{
+                    [{{|before, after| _rv_autoload_hook }}]
                 }})
+                def --env rv_deactivate [] {{
+                    $env.config = ($env.config | upsert hooks.pre_execution [] | upsert hooks.env_change.PWD [])
+                    _rv_load_env (\"{current_exe}\" shell deactivate nu | from json)
+                }}
+                _rv_autoload_hook
+            "};
+        }
+        Shell::Elvish => {
+            let current_exe = elvish_quote(current_exe.as_str());
+            // Code run by `eval` can't define variables for the shell that runs it, so
+            // `rv_deactivate` is added with `edit:add-var`, and the hook is compared by identity
+            // to take it out again.
+            printdoc! {"
+                var _rv_autoload_hook = {{|@_|
+                    eval ({current_exe} shell env elvish | slurp)
+                }}
+                set edit:after-readline = [$@edit:after-readline $_rv_autoload_hook]
+                edit:add-var rv_deactivate~ {{
+                    set edit:after-readline = [(each {{|hook| if (not-eq $hook $_rv_autoload_hook) {{ put $hook }} }} $edit:after-readline)]
+                    eval ({current_exe} shell deactivate elvish | slurp)
+                }}
+                $_rv_autoload_hook
             "};
         }
         Shell::PowerShell => {
@@ -80,6 +140,12 @@ pub fn init(shell: Shell) -> Result<()> {
                     Invoke-Expression (& '{current_exe}' shell env powershell)
                     __rv_original_prompt
                 }}
+                function global:rv_deactivate {{
+                    Copy-Item Function:\\__rv_original_prompt Function:\\prompt -Force
+                    Remove-Item Function:\\__rv_original_prompt
+                    Invoke-Expression (& '{current_exe}' shell deactivate powershell)
+                    Remove-Item Function:\\rv_deactivate
+                }}
                 Invoke-Expression (& '{current_exe}' shell env powershell)
             "};
         }
@@ -87,3 +153,57 @@ pub fn init(shell: Shell) -> Result<()> {
 
     Ok(())
 }
+
+/// The values of the variables rv is about to change, to save for `rv_deactivate`. Nothing, if an
+/// activation that's still in effect already saved them, such as one in a parent shell.
+fn saved_env() -> Vec<(String, String)> {
+    if std::env::var_os(SAVED_MARKER).is_some() {
+        return Vec::new();
+    }
+
+    let mut saved: Vec<_> = ACTIVATION_VARS
+        .into_iter()
+        .filter_map(|var| Some((saved_var(var), std::env::var(var).ok()?)))
+        .collect();
+    saved.push((SAVED_MARKER.to_string(), "1".to_string()));
+    saved
+}
+
+/// Print the commands that set the variables in `saved`, before the first activation.
+fn save_env(shell: &Shell, saved: Vec<(String, String)>) -> Result<()> {
+    if saved.is_empty() {
+        return Ok(());
+    }
+
+    match shell {
+        Shell::Zsh | Shell::Bash => {
+            for (var, val) in saved {
+                println!("export {var}={}", shell_escape::unix::escape(val.into()));
+            }
+        }
+        Shell::Fish => {
+            for (var, val) in saved {
+                println!("set -gx {var} \"{}\"", fish_var_escape(val));
+            }
+        }
+        Shell::Nu => {
+            let saved: serde_json::Map<_, _> = saved
+                .into_iter()
+                .map(|(var, val)| (var, serde_json::Value::String(val)))
+                .collect();
+            println!("load-env {}", serde_json::to_string(&saved)?);
+        }
+        Shell::Elvish => {
+            for (var, val) in saved {
+                println!("set-env {var} {}", elvish_quote(&val));
+            }
+        }
+        Shell::PowerShell => {
+            for (var, val) in saved {
+                println!("$env:{var} = \"{}\"", powershell_escape(&val));
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,163 @@
+//! `rv shell prompt`: the active Ruby, for prompts like starship and powerlevel10k.
+//!
+//! Prompts run this before every prompt, so it only reads the variables the shell integration
+//! set and the project's version files. It never looks for installed rubies, or runs Ruby.
+
+use std::env;
+use std::str::FromStr;
+
+use camino::Utf8PathBuf;
+use rv_ruby::request::RubyRequest;
+use rv_ruby::version::RubyVersion;
+
+use crate::GlobalArgs;
+use crate::config::RequestedRuby;
+use crate::config::rv_settings::RvSettings;
+
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+pub enum Error {
+    #[error(transparent)]
+    #[diagnostic(code(RV6301))]
+    IoError(#[from] std::io::Error),
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    ConfigError(#[from] crate::config::Error),
+}
+
+type Result<T> = miette::Result<T, Error>;
+
+/// The Ruby the shell integration activated.
+#[derive(Debug, PartialEq, Eq)]
+struct ActiveRuby {
+    engine: String,
+    version: String,
+    root: Option<String>,
+}
+
+impl ActiveRuby {
+    /// The active Ruby, going by the variables in the environment `lookup` reads.
+    fn from_env(lookup: impl Fn(&str) -> Option<String>) -> Option<Self> {
+        Some(Self {
+            version: lookup("RUBY_VERSION").filter(|version| !version.is_empty())?,
+            engine: lookup("RUBY_ENGINE").unwrap_or_else(|| "ruby".to_string()),
+            root: lookup("RUBY_ROOT"),
+        })
+    }
+
+    /// Whether it's a Ruby that `request` asks for. A version rv can't make sense of is given
+    /// the benefit of the doubt.
+    fn satisfies(&self, request: &RubyRequest) -> bool {
+        if self
+            .root
+            .as_deref()
+            .is_some_and(|root| root.ends_with("ruby-dev"))
+        {
+            return request.is_dev();
+        }
+        RubyVersion::from_str(&format!("{}-{}", self.engine, self.version))
+            .ok()
+            .is_none_or(|version| version.satisfies(request))
+    }
+}
+
+pub(crate) fn prompt(global_args: &GlobalArgs) -> Result<()> {
+    let Some(active) = ActiveRuby::from_env(|var| env::var(var).ok()) else {
+        return Ok(());
+    };
+
+    let current_dir =
+        Utf8PathBuf::try_from(env::current_dir()?).map_err(crate::config::Error::from)?;
+    let root = rv_dirs::root_dir();
+    let home_dir = rv_dirs::home_dir();
+    let project_root = rv_dirs::project_root_of(&current_dir, &root);
+    let settings = RvSettings::new(global_args, &home_dir, &project_root)
+        .map_err(crate::config::Error::from)?;
+    let version_files = settings
+        .version_file_order()
+        .map_err(crate::config::Error::from)?;
+    let requested_ruby = RequestedRuby::new(
+        global_args.ruby.clone(),
+        &home_dir,
+        &current_dir,
+        &root,
+        &version_files,
+        settings
+            .dir_depth(global_args)
+            .map_err(crate::config::Error::from)?,
+    )
+    .map_err(crate::config::Error::from)?;
+
+    println!("{}", segment(&active, &requested_ruby));
+    Ok(())
+}
+
+/// The prompt segment: the Ruby, named like rv names them, and what the project asks for instead, if the
+/// active Ruby isn't one it asks for.
+fn segment(active: &ActiveRuby, requested_ruby: &RequestedRuby) -> String {
+    let ruby = format!("{}-{}", active.engine, active.version);
+    match requested_ruby {
+        RequestedRuby::Global => ruby,
+        requested_ruby => {
+            let request = requested_ruby.ruby_request();
+            if active.satisfies(&request) {
+                ruby
+            } else {
+                format!("{ruby} (wants {request})")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn active(engine: &str, version: &str, root: &str) -> ActiveRuby {
+        ActiveRuby {
+            engine: engine.to_string(),
+            version: version.to_string(),
+            root: Some(root.to_string()),
+        }
+    }
+
+    #[test]
+    fn test_active_ruby_from_env() {
+        let lookup = |var: &str| match var {
+            "RUBY_VERSION" => Some("3.4.1".to_string()),
+            _ => None,
+        };
+        assert_eq!(
+            ActiveRuby::from_env(lookup),
+            Some(ActiveRuby {
+                engine: "ruby".to_string(),
+                version: "3.4.1".to_string(),
+                root: None,
+            })
+        );
+        assert_eq!(ActiveRuby::from_env(|_| None), None);
+    }
+
+    #[test]
+    fn test_segment() {
+        let ruby = active("ruby", "3.4.1", "/rubies/ruby-3.4.1");
+        let pinned = |request: &str| RequestedRuby::Explicit(request.parse().unwrap());
+
+        assert_eq!(segment(&ruby, &RequestedRuby::Global), "ruby-3.4.1");
+        assert_eq!(segment(&ruby, &pinned("3.4")), "ruby-3.4.1");
+        assert_eq!(
+            segment(&ruby, &pinned("3.3")),
+            "ruby-3.4.1 (wants ruby-3.3)"
+        );
+        assert_eq!(
+            segment(&ruby, &pinned("jruby-9.4")),
+            "ruby-3.4.1 (wants jruby-9.4)"
+        );
+
+        let dev = active("ruby", "4.1.0-dev", "/rubies/ruby-dev");
+        assert_eq!(segment(&dev, &pinned("dev")), "ruby-4.1.0-dev");
+        assert_eq!(
+            segment(&dev, &pinned("3.4")),
+            "ruby-4.1.0-dev (wants ruby-3.4)"
+        );
+    }
+}
@@ -76,14 +76,19 @@ pub enum ToolCommand {
 #[derive(Debug, thiserror::Error, miette::Diagnostic)]
 pub enum Error {
     #[error(transparent)]
+    #[diagnostic(transparent)]
     ToolInstallError(#[from] tool::install::Error),
     #[error(transparent)]
+    #[diagnostic(transparent)]
     ToolListError(#[from] tool::list::Error),
     #[error(transparent)]
+    #[diagnostic(transparent)]
     ToolUninstallError(#[from] tool::uninstall::Error),
     #[error(transparent)]
+    #[diagnostic(transparent)]
     ToolRunError(#[from] tool::run::Error),
     #[error(transparent)]
+    #[diagnostic(transparent)]
     ToolDirError(#[from] tool::dir::Error),
 }
 
@@ -133,8 +138,8 @@ fn tool_dir_for(gem_name: &str, gem_release: &str) -> Utf8PathBuf {
     tool_dir().join(format!("{gem_name}@{gem_release}"))
 }
 
-/// The directory where this tool can be found.
-fn tool_dir() -> Utf8PathBuf {
+/// The directory where all tools are installed.
+pub(crate) fn tool_dir() -> Utf8PathBuf {
     rv_dirs::user_data_dir("/".into()).join("tools")
 }
 
@@ -18,34 +18,48 @@ use crate::{
 #[derive(Debug, thiserror::Error, miette::Diagnostic)]
 pub enum Error {
     #[error(transparent)]
+    #[diagnostic(transparent)]
     ConfigError(#[from] crate::config::Error),
     #[error("{0} is not a valid URL")]
+    #[diagnostic(code(RV3001))]
     BadUrl(String),
     #[error("{gem_name} doesn't exist on {server}")]
+    #[diagnostic(code(RV3002))]
     NotFound { gem_name: String, server: String },
     #[error("No version {0} available")]
+    #[diagnostic(code(RV3003))]
     NoVersionFound(Version),
     #[error("The gem does not actually have any releases published")]
+    #[diagnostic(code(RV3004))]
     NoReleasesPublished,
     #[error(transparent)]
+    #[diagnostic(code(RV3005))]
     VersionError(#[from] rv_version::VersionError),
     #[error(transparent)]
+    #[diagnostic(code(RV3006))]
     GemserverError(#[from] gemserver::Error),
     #[error("Could not parse a version from the server")]
+    #[diagnostic(code(RV3007))]
     GemReleaseParse(#[from] gemserver::GemReleaseParse),
     #[error("Could not create the cache dir: {0}")]
+    #[diagnostic(code(RV3008))]
     CouldNotCreateCacheDir(std::io::Error),
     #[error("Could not write to the cache: {0}")]
+    #[diagnostic(code(RV3009))]
     CouldNotWriteToCache(std::io::Error),
     #[error("Could not choose version: {0}")]
+    #[diagnostic(code(RV3010))]
     CouldNotChooseVersion(String),
     #[error(transparent)]
+    #[diagnostic(transparent)]
     InstallError(#[from] crate::commands::clean_install::Error),
     #[error("Could not pin Ruby version for this tool: {0}")]
+    #[diagnostic(code(RV3011))]
     CouldNotPinRubyVersion(std::io::Error),
     #[error(
         "The gem {0} cannot be installed as a tool because it provides no executable named {0}"
     )]
+    #[diagnostic(code(RV3012))]
     NoMatchingExecutable(String),
 }
 
@@ -258,10 +272,9 @@ impl LockfileBuilder {
         release_tuple: &ReleaseTuple,
         gem_release: &GemRelease,
     ) -> rv_lockfile::datatypes::Checksum<'a> {
-        rv_lockfile::datatypes::Checksum {
-            release_tuple: release_tuple.clone(),
-            algorithm: rv_lockfile::datatypes::ChecksumAlgorithm::SHA256,
-            value: gem_release.metadata.checksum.clone(),
-        }
+        rv_lockfile::datatypes::Checksum::sha256(
+            release_tuple.clone(),
+            gem_release.metadata.checksum.clone(),
+        )
     }
 }
@@ -7,9 +7,10 @@ use fs_err as fs;
 
 const NO_TOOLS_INSTALLED: &str = "No tools installed";
 
-#[derive(thiserror::Error, Debug)]
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
 pub enum Error {
     #[error("Could not read the rv tool directory: {0}")]
+    #[diagnostic(code(RV3101))]
     CouldNotReadToolDir(std::io::Error),
 }
 
@@ -31,6 +32,7 @@ pub(crate) fn list(_global_args: &GlobalArgs, format: OutputFormat) -> Result<()
             OutputFormat::Json => {
                 println!("[]"); // JSON empty list.
             }
+            OutputFormat::Tsv => {}
         }
         return Ok(());
     }
@@ -88,6 +90,11 @@ pub(crate) fn list(_global_args: &GlobalArgs, format: OutputFormat) -> Result<()
                 .expect("Serializing this data to JSON should always succeed");
             println!("{j}");
         }
+        OutputFormat::Tsv => {
+            for tool in tools {
+                println!("{}\t{}", tool.gem_name, tool.version);
+            }
+        }
     }
     Ok(())
 }
@@ -8,31 +8,41 @@ use crate::commands::run::{Invocation, Program};
 use crate::commands::tool::{Installed, install as tool_install};
 use fs_err as fs;
 
-#[derive(thiserror::Error, Debug)]
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
 pub enum Error {
     #[error(transparent)]
+    #[diagnostic(code(RV3201))]
     VersionError(#[from] VersionError),
     #[error("You cannot give the version in both the executable and the gem, give only one.")]
+    #[diagnostic(code(RV3202))]
     VersionGivenTwice,
     #[error("Could not read the rv tool directory: {0}")]
+    #[diagnostic(code(RV3203))]
     CouldNotReadToolDir(std::io::Error),
     #[error("Could not find executable {exe} under gem {gem}@{version}")]
+    #[diagnostic(code(RV3204))]
     ExecutableNotFound {
         exe: String,
         gem: String,
         version: Version,
     },
     #[error(transparent)]
+    #[diagnostic(transparent)]
     Install(#[from] tool_install::Error),
     #[error("Tool was not found, and you set --no-install so rv won't install it.")]
+    #[diagnostic(code(RV3205))]
     NotInstalled,
     #[error("No .ruby-version found for this tool")]
+    #[diagnostic(code(RV3206))]
     NoRubyVersion,
     #[error("Could not read .ruby-version: {0}")]
+    #[diagnostic(code(RV3207))]
     CouldNotReadRubyVersion(std::io::Error),
     #[error("Invalid version in .ruby-version: {0}")]
+    #[diagnostic(code(RV3208))]
     InvalidRubyVersion(rv_ruby::request::RequestError),
     #[error(transparent)]
+    #[diagnostic(transparent)]
     RunningTool(#[from] crate::commands::run::Error),
 }
 
@@ -173,7 +183,8 @@ pub(crate) async fn run(
             executable_path: file,
             extra_paths: vec![tool_bin_dir.into()],
         },
-        env: vec![("GEM_HOME", gem_home.to_string())],
+        env: vec![("GEM_HOME".to_string(), gem_home.to_string())],
+        isolated: false,
     };
     crate::commands::run::run_command(
         invocation,
@@ -2,11 +2,13 @@ use crate::GlobalArgs;
 use camino::Utf8PathBuf;
 use fs_err as fs;
 
-#[derive(Debug, thiserror::Error)]
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
 pub enum Error {
     #[error("Could not read the rv tool directory: {0}")]
+    #[diagnostic(code(RV3301))]
     CouldNotReadToolDir(std::io::Error),
     #[error("Could not delete the directory for the tool: {0}")]
+    #[diagnostic(code(RV3302))]
     CouldNotDelete(std::io::Error),
 }
 
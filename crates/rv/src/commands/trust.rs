@@ -0,0 +1,380 @@
+//! A trust-on-first-use database of gem digests, shared by every project on this machine.
+//!
+//! The first time rv downloads a gem release, it remembers the package's SHA256. If the same
+//! release ever shows up with different contents, whether from a tampered mirror or a compromised
+//! registry, installing it fails until the new digest is explicitly trusted with `rv trust allow`.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Mutex;
+
+use anstream::println;
+use camino::{Utf8Path, Utf8PathBuf};
+use clap::{Args, Subcommand};
+use owo_colors::OwoColorize;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+#[derive(Args)]
+pub struct TrustArgs {
+    #[command(subcommand)]
+    pub command: TrustCommand,
+}
+
+#[derive(Subcommand)]
+pub enum TrustCommand {
+    #[command(about = "List the gem digests rv has seen")]
+    List {
+        /// Only show releases of this gem
+        gem: Option<String>,
+    },
+    #[command(about = "Trust a different digest for a gem release")]
+    Allow {
+        /// The release, like `rack-3.1.7` or `nokogiri-1.18.8-arm64-darwin`
+        release: String,
+        /// The SHA256 of the package to trust, as printed by the failed install
+        sha256: String,
+    },
+    #[command(about = "Forget a gem release, so its next download is trusted on first use again")]
+    Forget {
+        /// The release, like `rack-3.1.7` or `nokogiri-1.18.8-arm64-darwin`
+        release: String,
+    },
+    #[command(about = "Show the path of the digest database")]
+    Path,
+}
+
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+pub enum Error {
+    #[error(transparent)]
+    #[diagnostic(code(RV7501))]
+    Io(#[from] std::io::Error),
+    #[error("Could not read the gem digest database at {path}: {error}")]
+    #[diagnostic(
+        code(RV7502),
+        help("Fix or delete the file. Deleting it makes rv trust every gem on first use again.")
+    )]
+    Corrupt {
+        path: Utf8PathBuf,
+        error: serde_json::Error,
+    },
+    #[error("{0} is not a SHA256 digest")]
+    #[diagnostic(code(RV7503), help("Expected 64 hexadecimal characters"))]
+    BadDigest(String),
+    #[error("rv has never seen {0}")]
+    #[diagnostic(
+        code(RV7504),
+        help("Run `rv trust list` to see the releases rv knows about")
+    )]
+    UnknownRelease(String),
+}
+
+type Result<T> = miette::Result<T, Error>;
+
+/// The digest rv saw the first time it downloaded a release.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct TrustedDigest {
+    pub sha256: String,
+    /// Where the release was first downloaded from.
+    pub source: String,
+}
+
+/// What's stored on disk.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Database {
+    #[serde(default)]
+    digests: BTreeMap<String, TrustedDigest>,
+    /// Further digests the user accepted for a release, on top of the first one.
+    #[serde(default)]
+    allowed: BTreeMap<String, BTreeSet<String>>,
+}
+
+/// A release whose contents changed since rv first saw it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct DigestMismatch {
+    pub trusted: TrustedDigest,
+    pub actual: String,
+}
+
+/// The digest database, loaded once and shared between concurrent downloads.
+#[derive(Debug)]
+pub(crate) struct TrustStore {
+    path: Utf8PathBuf,
+    database: Mutex<Database>,
+    /// Releases seen for the first time since the database was loaded.
+    first_seen: Mutex<BTreeMap<String, TrustedDigest>>,
+}
+
+impl TrustStore {
+    /// Where the database lives. It's kept outside the cache, so that `rv cache clean` doesn't
+    /// reset trust.
+    pub(crate) fn default_path() -> Utf8PathBuf {
+        rv_dirs::user_data_dir("/".into()).join("trusted-gems.json")
+    }
+
+    pub(crate) fn load(path: Utf8PathBuf) -> Result<Self> {
+        let database = read_database(&path)?;
+        Ok(Self {
+            path,
+            database: Mutex::new(database),
+            first_seen: Mutex::default(),
+        })
+    }
+
+    /// Check `sha256` against the digest rv first saw for `release`, remembering it if this is
+    /// the first time.
+    pub(crate) fn check(
+        &self,
+        release: &str,
+        source: &str,
+        sha256: &str,
+    ) -> std::result::Result<(), DigestMismatch> {
+        let mut database = self.database.lock().unwrap();
+        match database.digests.get(release) {
+            Some(trusted) if trusted.sha256 == sha256 => Ok(()),
+            Some(_)
+                if database
+                    .allowed
+                    .get(release)
+                    .is_some_and(|allowed| allowed.contains(sha256)) =>
+            {
+                debug!("{release} matches a digest allowed with `rv trust allow`");
+                Ok(())
+            }
+            Some(trusted) => Err(DigestMismatch {
+                trusted: trusted.clone(),
+                actual: sha256.to_string(),
+            }),
+            None => {
+                debug!("Trusting {release} with sha256 {sha256} on first use");
+                let trusted = TrustedDigest {
+                    sha256: sha256.to_string(),
+                    source: source.to_string(),
+                };
+                database
+                    .digests
+                    .insert(release.to_string(), trusted.clone());
+                self.first_seen
+                    .lock()
+                    .unwrap()
+                    .insert(release.to_string(), trusted);
+                Ok(())
+            }
+        }
+    }
+
+    /// Write releases seen for the first time back to disk. The file is re-read first, so that
+    /// digests recorded by other rv processes in the meantime are kept, and win.
+    pub(crate) fn save(&self) -> Result<()> {
+        let first_seen = std::mem::take(&mut *self.first_seen.lock().unwrap());
+        if first_seen.is_empty() {
+            return Ok(());
+        }
+
+        let _lock = lock_database(&self.path)?;
+        let mut database = read_database(&self.path)?;
+        for (release, trusted) in first_seen {
+            database.digests.entry(release).or_insert(trusted);
+        }
+        write_database(&self.path, &database)
+    }
+}
+
+/// Lock the database at `path`, so that no other rv process changes it between our reading and
+/// writing it.
+fn lock_database(path: &Utf8Path) -> Result<rv_cache::LockedFile> {
+    let lock = rv_cache::LockedFile::acquire(
+        format!("{path}.lock"),
+        "the gem digest database",
+        rv_cache::LockWait::default(),
+    )?;
+    Ok(lock)
+}
+
+fn read_database(path: &Utf8Path) -> Result<Database> {
+    let contents = match fs_err::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Database::default()),
+        Err(err) => return Err(err.into()),
+    };
+    serde_json::from_str(&contents).map_err(|error| Error::Corrupt {
+        path: path.to_owned(),
+        error,
+    })
+}
+
+fn write_database(path: &Utf8Path, database: &Database) -> Result<()> {
+    let json = serde_json::to_string_pretty(database).expect("digests serialize to JSON");
+    rv_cache::write_atomic(path, json + "\n")?;
+    Ok(())
+}
+
+pub(crate) fn trust(args: TrustArgs) -> Result<()> {
+    let path = TrustStore::default_path();
+    match args.command {
+        TrustCommand::List { gem } => list(&path, gem.as_deref()),
+        TrustCommand::Allow { release, sha256 } => allow(&path, &release, &sha256),
+        TrustCommand::Forget { release } => forget(&path, &release),
+        TrustCommand::Path => {
+            println!("{}", path.as_str().cyan());
+            Ok(())
+        }
+    }
+}
+
+fn list(path: &Utf8Path, gem: Option<&str>) -> Result<()> {
+    let database = read_database(path)?;
+    let releases = database
+        .digests
+        .iter()
+        .filter(|(release, _)| gem.is_none_or(|gem| is_release_of(release, gem)));
+    for (release, trusted) in releases {
+        println!(
+            "{} {} {}",
+            release.cyan(),
+            trusted.sha256,
+            trusted.source.dimmed()
+        );
+        for allowed in database.allowed.get(release).into_iter().flatten() {
+            println!("  {} {allowed}", "allowed".yellow());
+        }
+    }
+    Ok(())
+}
+
+fn allow(path: &Utf8Path, release: &str, sha256: &str) -> Result<()> {
+    let sha256 = sha256.to_ascii_lowercase();
+    if sha256.len() != 64 || !sha256.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return Err(Error::BadDigest(sha256));
+    }
+
+    let _lock = lock_database(path)?;
+    let mut database = read_database(path)?;
+    if !database.digests.contains_key(release) {
+        return Err(Error::UnknownRelease(release.to_string()));
+    }
+    database
+        .allowed
+        .entry(release.to_string())
+        .or_default()
+        .insert(sha256.clone());
+    write_database(path, &database)?;
+    println!("Trusting {} with sha256 {sha256}", release.cyan());
+    Ok(())
+}
+
+fn forget(path: &Utf8Path, release: &str) -> Result<()> {
+    let _lock = lock_database(path)?;
+    let mut database = read_database(path)?;
+    if database.digests.remove(release).is_none() {
+        return Err(Error::UnknownRelease(release.to_string()));
+    }
+    database.allowed.remove(release);
+    write_database(path, &database)?;
+    println!(
+        "Forgot {}, its next download will be trusted on first use",
+        release.cyan()
+    );
+    Ok(())
+}
+
+/// Whether `release` (a full name, like `rack-3.1.7`) is a release of `gem`.
+fn is_release_of(release: &str, gem: &str) -> bool {
+    release
+        .strip_prefix(gem)
+        .and_then(|rest| rest.strip_prefix('-'))
+        .is_some_and(|rest| rest.starts_with(|c: char| c.is_ascii_digit()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIGEST_A: &str = "1111111111111111111111111111111111111111111111111111111111111111";
+    const DIGEST_B: &str = "2222222222222222222222222222222222222222222222222222222222222222";
+
+    #[test]
+    fn test_trust_on_first_use() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = Utf8PathBuf::try_from(temp_dir.path().join("trusted-gems.json")).unwrap();
+
+        let store = TrustStore::load(path.clone()).unwrap();
+        store
+            .check("rack-3.1.7", "https://rubygems.org/", DIGEST_A)
+            .unwrap();
+        store
+            .check("rack-3.1.7", "https://mirror.example.com/", DIGEST_A)
+            .unwrap();
+        store.save().unwrap();
+
+        // A fresh process remembers the digest.
+        let store = TrustStore::load(path.clone()).unwrap();
+        let mismatch = store
+            .check("rack-3.1.7", "https://mirror.example.com/", DIGEST_B)
+            .unwrap_err();
+        assert_eq!(mismatch.trusted.sha256, DIGEST_A);
+        assert_eq!(mismatch.trusted.source, "https://rubygems.org/");
+        assert_eq!(mismatch.actual, DIGEST_B);
+
+        allow(&path, "rack-3.1.7", DIGEST_B).unwrap();
+        let store = TrustStore::load(path.clone()).unwrap();
+        store
+            .check("rack-3.1.7", "https://mirror.example.com/", DIGEST_B)
+            .unwrap();
+        store
+            .check("rack-3.1.7", "https://rubygems.org/", DIGEST_A)
+            .unwrap();
+
+        forget(&path, "rack-3.1.7").unwrap();
+        let store = TrustStore::load(path).unwrap();
+        store
+            .check("rack-3.1.7", "https://mirror.example.com/", DIGEST_B)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_save_keeps_digests_from_other_processes() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = Utf8PathBuf::try_from(temp_dir.path().join("trusted-gems.json")).unwrap();
+
+        let first = TrustStore::load(path.clone()).unwrap();
+        let second = TrustStore::load(path.clone()).unwrap();
+        first
+            .check("rack-3.1.7", "https://a.example/", DIGEST_A)
+            .unwrap();
+        second
+            .check("rack-3.1.7", "https://b.example/", DIGEST_B)
+            .unwrap();
+        second
+            .check("rake-13.3.1", "https://b.example/", DIGEST_B)
+            .unwrap();
+        first.save().unwrap();
+        second.save().unwrap();
+
+        let database = read_database(&path).unwrap();
+        assert_eq!(database.digests["rack-3.1.7"].sha256, DIGEST_A);
+        assert_eq!(database.digests["rake-13.3.1"].sha256, DIGEST_B);
+    }
+
+    #[test]
+    fn test_allow_rejects_bad_input() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = Utf8PathBuf::try_from(temp_dir.path().join("trusted-gems.json")).unwrap();
+
+        assert!(matches!(
+            allow(&path, "rack-3.1.7", "abc"),
+            Err(Error::BadDigest(_))
+        ));
+        assert!(matches!(
+            allow(&path, "rack-3.1.7", DIGEST_A),
+            Err(Error::UnknownRelease(_))
+        ));
+    }
+
+    #[test]
+    fn test_is_release_of() {
+        assert!(is_release_of("rack-3.1.7", "rack"));
+        assert!(is_release_of("nokogiri-1.18.8-arm64-darwin", "nokogiri"));
+        assert!(!is_release_of("rack-test-2.2.0", "rack"));
+        assert!(!is_release_of("rack-3.1.7", "rake"));
+    }
+}
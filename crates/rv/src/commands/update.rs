@@ -0,0 +1,615 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use anstream::println;
+use camino::Utf8PathBuf;
+use clap::Args;
+use owo_colors::OwoColorize;
+use rv_gem_types::{Platform, ProjectDependency, VersionPlatform};
+use rv_lockfile::datatypes::{Checksum, GemfileDotLock, Spec};
+use rv_version::Version;
+use tracing::debug;
+use url::Url;
+
+use crate::GlobalArgs;
+use crate::commands::clean_install::find_lockfile_path;
+use crate::config::Config;
+use crate::gemserver::{self, GemName, GemRelease, Gemserver};
+use crate::resolver::{Preferences, Resolution};
+
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+pub enum Error {
+    #[error(transparent)]
+    #[diagnostic(code(RV7401))]
+    IoError(#[from] std::io::Error),
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    LockfileNotFound(#[from] crate::commands::clean_install::Error),
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    ConfigError(#[from] crate::config::Error),
+    #[error("Could not parse {lockfile}")]
+    #[diagnostic(code(RV7402))]
+    Parse {
+        lockfile: Utf8PathBuf,
+        #[diagnostic_source]
+        source: rv_lockfile::ParseErrors,
+    },
+    #[error("{} not locked from a gem server: {}", if gems.len() == 1 { "This gem is" } else { "These gems are" }, gems.join(", "))]
+    #[diagnostic(
+        code(RV7403),
+        help(
+            "Only gems in the lockfile's GEM sections can be updated. Add new gems to the Gemfile and run `bundle lock`."
+        )
+    )]
+    NotLocked { gems: Vec<String> },
+    #[error(transparent)]
+    #[diagnostic(code(RV7404))]
+    GemserverError(#[from] gemserver::Error),
+    #[error("{0} is not a valid gem server URL")]
+    #[diagnostic(code(RV7405))]
+    BadRemote(String),
+    #[error("Could not find versions that work together: {0}")]
+    #[diagnostic(code(RV7406))]
+    CouldNotResolve(String),
+}
+
+type Result<T> = miette::Result<T, Error>;
+
+#[derive(Args)]
+pub struct UpdateArgs {
+    /// Gems to update, along with the gems they depend on. Everything else stays at its locked
+    /// version. Updates every gem when none are given
+    pub gems: Vec<String>,
+
+    /// Path to Gemfile
+    #[arg(long, env = "BUNDLE_GEMFILE")]
+    pub gemfile: Option<Utf8PathBuf>,
+
+    /// Which versions of the gems being updated to pick, when more than one would work
+    #[arg(long, value_enum, default_value_t = Resolution::Highest, value_name = "STRATEGY")]
+    pub resolution: Resolution,
+}
+
+/// How a gem's locked version changed.
+#[derive(Debug, PartialEq, Eq)]
+struct Change {
+    name: String,
+    from: Option<Version>,
+    to: Option<Version>,
+}
+
+pub(crate) async fn update(global_args: &GlobalArgs, args: UpdateArgs) -> Result<()> {
+    let config = Config::with_settings(global_args, None)?;
+    let lockfile_path = find_lockfile_path(&args.gemfile)?;
+    let raw_contents = fs_err::read_to_string(&lockfile_path)?;
+    let contents = rv_lockfile::normalize_line_endings(&raw_contents);
+    let mut lockfile = rv_lockfile::parse(&contents).map_err(|source| Error::Parse {
+        lockfile: lockfile_path.clone(),
+        source,
+    })?;
+
+    let unlocked = unlocked_gems(&lockfile, &args.gems)?;
+    debug!("Re-resolving {} gems", unlocked.len());
+    let ruby_version = config
+        .current_ruby()
+        .map(|ruby| Version::from(&ruby.version));
+
+    // Every version of the unlocked gems, and of any gems they start depending on, is a
+    // candidate. Every other gem can only be its locked version.
+    let mut gem_info = locked_releases(&lockfile, &unlocked);
+    let mut available: HashMap<GemName, Vec<GemRelease>> = HashMap::new();
+    let mut sections: HashMap<GemName, usize> = section_of_each_gem(&lockfile);
+    let mut gemservers: HashMap<usize, Gemserver> = HashMap::new();
+    let mut to_fetch: Vec<(GemName, usize)> = unlocked
+        .iter()
+        .map(|name| (name.clone(), sections[name]))
+        .collect();
+    while let Some((name, section)) = to_fetch.pop() {
+        if available.contains_key(&name) || gem_info.contains_key(&name) {
+            continue;
+        }
+        let gemserver = match gemservers.entry(section) {
+            std::collections::hash_map::Entry::Occupied(entry) => entry.into_mut(),
+            std::collections::hash_map::Entry::Vacant(entry) => {
+                let remote = lockfile.gem[section]
+                    .remote
+                    .unwrap_or("https://rubygems.org/");
+                let url: Url = remote
+                    .parse()
+                    .map_err(|_| Error::BadRemote(remote.to_string()))?;
+                entry.insert(Gemserver::new(&config, url)?)
+            }
+        };
+
+        debug!("Fetching the versions of {name}");
+        let body = gemserver.get_releases_for_gem(&name).await?;
+        let releases =
+            gemserver::parse_all_releases_from_body(&body).map_err(gemserver::Error::from)?;
+        let locked_version = locked_version(&lockfile, &name);
+        let candidates = candidates(&releases, ruby_version.as_ref(), locked_version.as_ref());
+        for dep in candidates.values().flat_map(|release| &release.deps) {
+            to_fetch.push((dep.name.clone(), section));
+        }
+        sections.entry(name.clone()).or_insert(section);
+        gem_info.insert(name.clone(), candidates);
+        available.insert(name, releases);
+    }
+
+    let dependencies = lockfile
+        .dependencies
+        .iter()
+        .map(|dep| ProjectDependency {
+            name: dep.name.to_string(),
+            requirement: dep.requirement.clone(),
+        })
+        .collect();
+    let preferences = Preferences {
+        resolution: args.resolution,
+        locked: lockfile
+            .gem
+            .iter()
+            .flat_map(|section| &section.specs)
+            .map(|spec| {
+                let tuple = &spec.release_tuple;
+                (tuple.name.clone(), tuple.version.clone())
+            })
+            .collect(),
+    };
+    let resolved: HashMap<GemName, GemRelease> =
+        crate::resolver::solve_project_preferring(dependencies, gem_info, preferences)
+            .map_err(|err| Error::CouldNotResolve(crate::resolver::explain(&err)))?
+            .into_iter()
+            .map(|(tuple, release)| (tuple.name, release))
+            .collect();
+
+    let changes = apply_resolution(&mut lockfile, &resolved, &available, &sections);
+    if changes.is_empty() {
+        match args.resolution {
+            Resolution::Highest => {
+                println!("Every gem is already at the newest version the Gemfile allows")
+            }
+            Resolution::LowestDirect | Resolution::MinimalChanges => {
+                println!("Every gem is already at the version the resolution picks")
+            }
+        }
+        return Ok(());
+    }
+
+    lockfile.normalize();
+    let updated = lockfile.to_string();
+    rv_lockfile::write_preserving_line_endings(&lockfile_path, &updated, &raw_contents)?;
+
+    for change in &changes {
+        match (&change.from, &change.to) {
+            (Some(from), Some(to)) if to < from => {
+                println!("Downgraded {} {to} (was {from})", change.name.cyan())
+            }
+            (Some(from), Some(to)) => {
+                println!("Updated {} {to} (was {from})", change.name.cyan())
+            }
+            (None, Some(to)) => println!("Added {} {to}", change.name.cyan()),
+            (Some(from), None) => println!("Removed {} {from}", change.name.cyan()),
+            (None, None) => {}
+        }
+    }
+    println!("Wrote {}", lockfile_path.cyan());
+
+    Ok(())
+}
+
+/// The gems to re-resolve: the named gems and everything they depend on, or every gem from a gem
+/// server when no gems are named. Gems from git or a path always stay as they are.
+fn unlocked_gems(lockfile: &GemfileDotLock, names: &[String]) -> Result<HashSet<String>> {
+    let gem_specs: Vec<&Spec> = lockfile
+        .gem
+        .iter()
+        .flat_map(|section| &section.specs)
+        .collect();
+    if names.is_empty() {
+        return Ok(gem_specs
+            .iter()
+            .map(|spec| spec.release_tuple.name.clone())
+            .collect());
+    }
+
+    let not_locked: Vec<String> = names
+        .iter()
+        .filter(|name| {
+            !gem_specs
+                .iter()
+                .any(|spec| spec.release_tuple.name == **name)
+        })
+        .cloned()
+        .collect();
+    if !not_locked.is_empty() {
+        return Err(Error::NotLocked { gems: not_locked });
+    }
+
+    let mut unlocked = HashSet::new();
+    let mut to_visit: Vec<&str> = names.iter().map(String::as_str).collect();
+    while let Some(name) = to_visit.pop() {
+        if !unlocked.insert(name.to_string()) {
+            continue;
+        }
+        for spec in gem_specs
+            .iter()
+            .filter(|spec| spec.release_tuple.name == name)
+        {
+            to_visit.extend(
+                spec.deps
+                    .iter()
+                    .map(|dep| dep.name.as_str())
+                    .filter(|dep| gem_specs.iter().any(|s| s.release_tuple.name == *dep)),
+            );
+        }
+    }
+    Ok(unlocked)
+}
+
+/// Every gem that isn't being updated, at its locked version, with its locked dependencies.
+pub(crate) fn locked_releases(
+    lockfile: &GemfileDotLock,
+    unlocked: &HashSet<String>,
+) -> HashMap<GemName, HashMap<VersionPlatform, GemRelease>> {
+    let mut releases: HashMap<GemName, HashMap<VersionPlatform, GemRelease>> = HashMap::new();
+    let all_specs = lockfile
+        .gem
+        .iter()
+        .flat_map(|section| &section.specs)
+        .chain(lockfile.git.iter().flat_map(|section| &section.specs))
+        .chain(lockfile.path.iter().flat_map(|section| &section.specs));
+    for spec in all_specs {
+        let tuple = &spec.release_tuple;
+        if unlocked.contains(&tuple.name) {
+            continue;
+        }
+        let version_platform = VersionPlatform {
+            version: tuple.version.clone(),
+            platform: tuple.platform.clone(),
+        };
+        let release = GemRelease {
+            version_platform: version_platform.clone(),
+            deps: spec.deps.clone(),
+            metadata: Default::default(),
+        };
+        releases
+            .entry(tuple.name.clone())
+            .or_default()
+            .insert(version_platform, release);
+    }
+    releases
+}
+
+/// The index of the GEM section each gem is locked in.
+fn section_of_each_gem(lockfile: &GemfileDotLock) -> HashMap<GemName, usize> {
+    let mut sections = HashMap::new();
+    for (index, section) in lockfile.gem.iter().enumerate() {
+        for spec in &section.specs {
+            sections
+                .entry(spec.release_tuple.name.clone())
+                .or_insert(index);
+        }
+    }
+    sections
+}
+
+fn locked_version(lockfile: &GemfileDotLock, name: &str) -> Option<Version> {
+    lockfile
+        .gem
+        .iter()
+        .flat_map(|section| &section.specs)
+        .find(|spec| spec.release_tuple.name == name)
+        .map(|spec| spec.release_tuple.version.clone())
+}
+
+/// The releases the resolver may pick from: ones that install here and work with the current
+/// Ruby. Prereleases are only candidates if the locked version is one too, like in Bundler.
+fn candidates(
+    releases: &[GemRelease],
+    ruby_version: Option<&Version>,
+    locked_version: Option<&Version>,
+) -> HashMap<VersionPlatform, GemRelease> {
+    let allow_prerelease = locked_version.is_some_and(Version::is_prerelease);
+    releases
+        .iter()
+        .filter(|release| release.platform().is_local())
+        .filter(|release| allow_prerelease || !release.version().is_prerelease())
+        .filter(|release| {
+            ruby_version.is_none_or(|ruby_version| release.metadata.ruby.satisfied_by(ruby_version))
+        })
+        .map(|release| (release.version_platform.clone(), release.clone()))
+        .collect()
+}
+
+/// Rewrite the GEM sections, and checksums, for the gems whose version the resolution changed.
+/// Returns the changes, sorted by gem name.
+fn apply_resolution(
+    lockfile: &mut GemfileDotLock,
+    resolved: &HashMap<GemName, GemRelease>,
+    available: &HashMap<GemName, Vec<GemRelease>>,
+    sections: &HashMap<GemName, usize>,
+) -> Vec<Change> {
+    let mut changes: BTreeMap<String, Change> = BTreeMap::new();
+
+    // Drop the gems that changed version or aren't needed anymore.
+    for section in &mut lockfile.gem {
+        section.specs.retain(|spec| {
+            let tuple = &spec.release_tuple;
+            let keep = resolved
+                .get(&tuple.name)
+                .is_some_and(|release| release.version() == &tuple.version);
+            if !keep {
+                changes.entry(tuple.name.clone()).or_insert(Change {
+                    name: tuple.name.clone(),
+                    from: Some(tuple.version.clone()),
+                    to: None,
+                });
+            }
+            keep
+        });
+    }
+    let still_locked: HashSet<String> = lockfile
+        .gem
+        .iter()
+        .flat_map(|section| &section.specs)
+        .chain(lockfile.git.iter().flat_map(|section| &section.specs))
+        .chain(lockfile.path.iter().flat_map(|section| &section.specs))
+        .map(|spec| spec.release_tuple.name.clone())
+        .collect();
+
+    // Lock the new versions, for each of the lockfile's platforms.
+    for (name, release) in resolved {
+        if still_locked.contains(name) {
+            continue;
+        }
+        let releases = available.get(name).map_or(&[][..], Vec::as_slice);
+        let section = sections.get(name).copied().unwrap_or_default();
+        for release in releases_for_platforms(releases, release, &lockfile.platforms) {
+            let tuple = rv_gem_types::ReleaseTuple {
+                name: name.clone(),
+                version: release.version().clone(),
+                platform: release.platform().clone(),
+            };
+            if let Some(checksums) = &mut lockfile.checksums {
+                checksums.push(Checksum::sha256(
+                    tuple.clone(),
+                    release.metadata.checksum.clone(),
+                ));
+            }
+            if let Some(section) = lockfile.gem.get_mut(section) {
+                section.specs.push(Spec {
+                    release_tuple: tuple,
+                    deps: release.deps.clone(),
+                });
+            }
+        }
+        changes
+            .entry(name.clone())
+            .or_insert(Change {
+                name: name.clone(),
+                from: None,
+                to: None,
+            })
+            .to = Some(release.version().clone());
+    }
+
+    if let Some(checksums) = &mut lockfile.checksums {
+        let locked: HashSet<_> = lockfile
+            .gem
+            .iter()
+            .flat_map(|section| &section.specs)
+            .chain(lockfile.git.iter().flat_map(|section| &section.specs))
+            .chain(lockfile.path.iter().flat_map(|section| &section.specs))
+            .map(|spec| &spec.release_tuple)
+            .collect();
+        checksums.retain(|checksum| locked.contains(&checksum.release_tuple));
+    }
+
+    changes
+        .into_values()
+        .filter(|change| change.from != change.to)
+        .collect()
+}
+
+/// The releases of the resolved version to lock: for each platform in the lockfile, the
+/// precompiled release for it if there is one, or else the generic "ruby" release.
+fn releases_for_platforms<'a>(
+    releases: &'a [GemRelease],
+    resolved: &'a GemRelease,
+    platforms: &[Platform],
+) -> Vec<&'a GemRelease> {
+    let same_version: Vec<&GemRelease> = releases
+        .iter()
+        .filter(|release| release.version() == resolved.version())
+        .collect();
+    let generic = same_version
+        .iter()
+        .find(|release| release.platform().is_ruby())
+        .copied();
+
+    let mut chosen: Vec<&GemRelease> = vec![];
+    for platform in platforms {
+        let precompiled = if platform.is_ruby() {
+            None
+        } else {
+            same_version
+                .iter()
+                .find(|release| {
+                    !release.platform().is_ruby() && platform.matches(release.platform())
+                })
+                .copied()
+        };
+        if let Some(release) = precompiled.or(generic)
+            && !chosen.contains(&release)
+        {
+            chosen.push(release);
+        }
+    }
+    if chosen.is_empty() {
+        chosen.push(resolved);
+    }
+    chosen
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOCKFILE: &str = "\
+GEM
+  remote: https://rubygems.org/
+  specs:
+    nokogiri (1.18.9)
+      racc (~> 1.4)
+    nokogiri (1.18.9-x86_64-linux-gnu)
+      racc (~> 1.4)
+    racc (1.7.0)
+    rake (13.3.0)
+
+PLATFORMS
+  ruby
+  x86_64-linux-gnu
+
+DEPENDENCIES
+  nokogiri
+  rake
+
+CHECKSUMS
+  nokogiri (1.18.9) sha256=ac5a7d93fd0e3cef388800b037407890882413feccca79eb0272a2715a82fa33
+  nokogiri (1.18.9-x86_64-linux-gnu) sha256=4a7f6929691dbec8b5209a0b373bc2614882b55fc5d2e447a21aaa691303d62f
+  racc (1.7.0) sha256=0468f784a7f98bc2498d0e14596fc694182d957f9f1831309c6e94d075055f6f
+  rake (13.3.0) sha256=934d178848b6c55fae8db6108e6e8af0954af8cb374ff9526d098abebf247b7b
+
+BUNDLED WITH
+   2.6.9
+";
+
+    fn release(line: &str) -> GemRelease {
+        gemserver::parse_all_releases_from_body(line)
+            .unwrap()
+            .remove(0)
+    }
+
+    #[test]
+    fn test_unlocked_gems() {
+        let lockfile = rv_lockfile::parse(LOCKFILE).unwrap();
+
+        let unlocked = unlocked_gems(&lockfile, &["nokogiri".to_string()]).unwrap();
+        assert_eq!(
+            unlocked,
+            HashSet::from(["nokogiri".to_string(), "racc".to_string()])
+        );
+
+        let unlocked = unlocked_gems(&lockfile, &["racc".to_string()]).unwrap();
+        assert_eq!(unlocked, HashSet::from(["racc".to_string()]));
+
+        assert_eq!(unlocked_gems(&lockfile, &[]).unwrap().len(), 3);
+
+        let err = unlocked_gems(&lockfile, &["rails".to_string()]).unwrap_err();
+        assert!(matches!(err, Error::NotLocked { gems } if gems == ["rails"]));
+    }
+
+    #[test]
+    fn test_candidates() {
+        let releases = [
+            release("1.7.0 |checksum:aa"),
+            release("1.8.0.rc1 |checksum:aa"),
+            release("1.8.1 |checksum:aa,ruby:>= 9.0"),
+            release("1.8.1-java |checksum:aa"),
+        ];
+        let ruby: Version = "3.4.1".parse().unwrap();
+
+        let candidates = candidates(&releases, Some(&ruby), None);
+        let versions: Vec<String> = candidates.keys().map(ToString::to_string).collect();
+        assert_eq!(versions, vec!["1.7.0"]);
+
+        let prerelease: Version = "1.8.0.rc0".parse().unwrap();
+        let candidates = super::candidates(&releases, Some(&ruby), Some(&prerelease));
+        assert_eq!(candidates.len(), 2);
+    }
+
+    #[test]
+    fn test_apply_resolution() {
+        let mut lockfile = rv_lockfile::parse(LOCKFILE).unwrap();
+        let racc = release(
+            "1.8.1 |checksum:4a7f6929691dbec8b5209a0b373bc2614882b55fc5d2e447a21aaa691303d62f",
+        );
+        let available = HashMap::from([(
+            "racc".to_string(),
+            vec![
+                racc.clone(),
+                release(
+                    "1.8.1-java |checksum:54f2e6d1e1b91c154013277d986f52a90e5ececbe91465d29172e49342732b98",
+                ),
+            ],
+        )]);
+        let mut resolved: HashMap<GemName, GemRelease> = HashMap::from([
+            ("racc".to_string(), racc),
+            ("rake".to_string(), release("13.3.0 |")),
+        ]);
+        resolved.insert("nokogiri".to_string(), release("1.18.9 racc:~> 1.4|"));
+
+        let changes = apply_resolution(
+            &mut lockfile,
+            &resolved,
+            &available,
+            &section_of_each_gem(&rv_lockfile::parse(LOCKFILE).unwrap()),
+        );
+        assert_eq!(
+            changes,
+            vec![Change {
+                name: "racc".to_string(),
+                from: Some("1.7.0".parse().unwrap()),
+                to: Some("1.8.1".parse().unwrap()),
+            }]
+        );
+
+        lockfile.normalize();
+        let updated = lockfile.to_string();
+        assert!(updated.contains("    racc (1.8.1)\n"));
+        assert!(!updated.contains("racc (1.7.0)"));
+        assert!(!updated.contains("java"));
+        assert!(updated.contains(
+            "  racc (1.8.1) sha256=4a7f6929691dbec8b5209a0b373bc2614882b55fc5d2e447a21aaa691303d62f\n"
+        ));
+        // Gems that kept their version keep every platform they were locked for.
+        assert!(updated.contains("nokogiri (1.18.9-x86_64-linux-gnu)"));
+    }
+
+    #[test]
+    fn test_releases_for_platforms() {
+        let releases = [
+            release("1.18.10 |"),
+            release("1.18.10-x86_64-linux-gnu |"),
+            release("1.18.10-arm64-darwin |"),
+            release("1.18.9-x86_64-linux-gnu |"),
+        ];
+        let platforms = |names: &[&str]| -> Vec<Platform> {
+            names
+                .iter()
+                .map(|name| Platform::new(name).unwrap())
+                .collect()
+        };
+        let full_names = |chosen: Vec<&GemRelease>| -> Vec<String> {
+            chosen.iter().map(|release| release.full_name()).collect()
+        };
+
+        assert_eq!(
+            full_names(releases_for_platforms(
+                &releases,
+                &releases[0],
+                &platforms(&["ruby", "x86_64-linux-gnu"])
+            )),
+            vec!["1.18.10", "1.18.10-x86_64-linux-gnu"]
+        );
+        // Platforms without a precompiled release fall back to the generic one.
+        assert_eq!(
+            full_names(releases_for_platforms(
+                &releases,
+                &releases[0],
+                &platforms(&["x86_64-darwin"])
+            )),
+            vec!["1.18.10"]
+        );
+    }
+}
@@ -0,0 +1,254 @@
+//! `rv why`: every chain of requirements from the Gemfile down to a locked gem, so it's clear
+//! what keeps a gem in the lockfile, or holds it back at an older version.
+
+use std::collections::BTreeSet;
+
+use anstream::{print, println};
+use camino::Utf8PathBuf;
+use clap::Args;
+use owo_colors::OwoColorize;
+use rv_gem_types::requirement::Requirement;
+use rv_lockfile::datatypes::GemfileDotLock;
+use rv_lockfile::graph::DependencyGraph;
+use serde::Serialize;
+
+use crate::GlobalArgs;
+use crate::commands::clean_install::{find_lockfile_path, gemfile_path};
+use crate::config::Config;
+use crate::output_format::OutputFormat;
+
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+pub enum Error {
+    #[error(transparent)]
+    #[diagnostic(code(RV8201))]
+    IoError(#[from] std::io::Error),
+    #[error(transparent)]
+    #[diagnostic(code(RV8202))]
+    Parse(
+        #[from]
+        #[diagnostic_source]
+        rv_lockfile::ParseErrors,
+    ),
+    #[error("{gem} is not in {lockfile}")]
+    #[diagnostic(code(RV8203))]
+    NotLocked { gem: String, lockfile: Utf8PathBuf },
+    #[error("Could not write the requirement chains as JSON")]
+    #[diagnostic(code(RV8204))]
+    Json(#[from] serde_json::Error),
+    #[error("`rv why` can't print tab-separated values")]
+    #[diagnostic(code(RV8205), help("Use `--format text` or `--format json`"))]
+    UnsupportedFormat,
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    ConfigError(#[from] crate::config::Error),
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    CiError(#[from] crate::commands::clean_install::Error),
+}
+
+type Result<T> = miette::Result<T, Error>;
+
+#[derive(Args)]
+pub struct WhyArgs {
+    /// The gem to explain
+    gem: String,
+
+    /// Path to Gemfile
+    #[arg(long, env = "BUNDLE_GEMFILE")]
+    gemfile: Option<Utf8PathBuf>,
+
+    /// Output format for the requirement chains
+    #[arg(long, value_enum, default_value = "text")]
+    format: OutputFormat,
+}
+
+/// One gem in a chain, and what the gem before it, or the Gemfile, requires of it.
+#[derive(Debug, Serialize, PartialEq, Eq)]
+struct Link {
+    name: String,
+    /// The requirements on the gem, which differ when several locked variants of the gem before
+    /// it require different versions. Empty when any version will do.
+    requirements: Vec<String>,
+    /// The versions of the gem the lockfile locks.
+    versions: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct JsonWhy<'a> {
+    gem: &'a str,
+    versions: &'a [String],
+    chains: &'a [Vec<Link>],
+}
+
+pub(crate) fn why(global_args: &GlobalArgs, args: WhyArgs) -> Result<()> {
+    if args.format == OutputFormat::Tsv {
+        return Err(Error::UnsupportedFormat);
+    }
+    let config = Config::with_settings(global_args, None)?;
+    let lockfile_path = find_lockfile_path(&gemfile_path(&config, args.gemfile.as_deref()))?;
+    let contents = fs_err::read_to_string(&lockfile_path)?;
+    let contents = rv_lockfile::normalize_line_endings(&contents);
+    let lockfile = rv_lockfile::parse(&contents)?;
+
+    let graph = DependencyGraph::new(&lockfile);
+    if !graph.contains(&args.gem) {
+        return Err(Error::NotLocked {
+            gem: args.gem,
+            lockfile: lockfile_path,
+        });
+    }
+    let chains = chains_to(&lockfile, &graph, &args.gem);
+    let versions = locked_versions(&graph, &args.gem);
+
+    if args.format == OutputFormat::Json {
+        let json = JsonWhy {
+            gem: &args.gem,
+            versions: &versions,
+            chains: &chains,
+        };
+        println!("{}", serde_json::to_string_pretty(&json)?);
+        return Ok(());
+    }
+
+    let gem = format!("{} {}", args.gem, versions.join(", "));
+    if chains.is_empty() {
+        println!(
+            "{} is locked, but nothing in the Gemfile requires it anymore",
+            gem.cyan()
+        );
+        return Ok(());
+    }
+    println!(
+        "{} is required by {} {}:",
+        gem.cyan(),
+        chains.len(),
+        if chains.len() == 1 { "chain" } else { "chains" }
+    );
+    for chain in &chains {
+        println!();
+        print!("{}", render_chain(chain));
+    }
+    Ok(())
+}
+
+/// Every chain from the Gemfile down to `gem`, with the requirements along the way.
+fn chains_to(lockfile: &GemfileDotLock, graph: &DependencyGraph, gem: &str) -> Vec<Vec<Link>> {
+    graph
+        .paths_to(gem)
+        .into_iter()
+        .map(|path| {
+            let mut previous: Option<&str> = None;
+            path.into_iter()
+                .map(|name| {
+                    let requirements = match previous {
+                        None => lockfile
+                            .dependencies
+                            .iter()
+                            .filter(|dep| dep.name == name)
+                            .map(|dep| &dep.requirement)
+                            .filter_map(shown)
+                            .collect(),
+                        Some(dependent) => graph
+                            .node(dependent)
+                            .into_iter()
+                            .flat_map(|node| &node.specs)
+                            .flat_map(|spec| &spec.deps)
+                            .filter(|dep| dep.name == name)
+                            .map(|dep| &dep.requirement)
+                            .filter_map(shown)
+                            .collect::<BTreeSet<_>>()
+                            .into_iter()
+                            .collect(),
+                    };
+                    previous = Some(name);
+                    Link {
+                        name: name.to_string(),
+                        requirements,
+                        versions: locked_versions(graph, name),
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// A requirement as the lockfile writes it, unless it allows any version.
+fn shown(requirement: &Requirement) -> Option<String> {
+    (!requirement.is_latest_version()).then(|| requirement.to_string())
+}
+
+/// The versions of `name` the lockfile locks, without the platforms of its variants.
+fn locked_versions(graph: &DependencyGraph, name: &str) -> Vec<String> {
+    let versions: BTreeSet<_> = graph
+        .node(name)
+        .into_iter()
+        .flat_map(|node| &node.specs)
+        .map(|spec| &spec.release_tuple.version)
+        .collect();
+    versions.into_iter().map(ToString::to_string).collect()
+}
+
+/// A chain, one requirement per line, each indented under the gem that has it.
+fn render_chain(chain: &[Link]) -> String {
+    let mut out = String::new();
+    let mut dependent = "Gemfile".to_string();
+    for (depth, link) in chain.iter().enumerate() {
+        let requirement = if link.requirements.is_empty() {
+            String::new()
+        } else {
+            format!(" ({})", link.requirements.join(" or "))
+        };
+        out += &format!(
+            "{:indent$}{dependent} requires {}{requirement}\n",
+            "",
+            link.name,
+            indent = 2 * depth
+        );
+        dependent = format!("{} {}", link.name, link.versions.join(", "));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOCKFILE: &str = "\
+GEM
+  remote: https://rubygems.org/
+  specs:
+    actionpack (8.0.2)
+      rack (>= 2.2.4)
+    rack (3.1.16)
+    rack-test (2.2.0)
+      rack (>= 1.3)
+    rails (8.0.2)
+      actionpack (= 8.0.2)
+
+PLATFORMS
+  ruby
+
+DEPENDENCIES
+  rack-test
+  rails (~> 8.0)
+
+BUNDLED WITH
+   2.6.9
+";
+
+    #[test]
+    fn test_render_chains() {
+        let lockfile = rv_lockfile::parse(LOCKFILE).unwrap();
+        let graph = DependencyGraph::new(&lockfile);
+        let chains = chains_to(&lockfile, &graph, "rack");
+        let rendered: Vec<String> = chains.iter().map(|chain| render_chain(chain)).collect();
+        insta::assert_snapshot!(rendered.join("\n"), @r"
+        Gemfile requires rack-test
+          rack-test 2.2.0 requires rack (>= 1.3)
+
+        Gemfile requires rails (~> 8.0)
+          rails 8.0.2 requires actionpack (= 8.0.2)
+            actionpack 8.0.2 requires rack (>= 2.2.4)
+        ");
+    }
+}
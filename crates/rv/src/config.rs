@@ -1,7 +1,7 @@
 use std::{
-    env::{self, JoinPathsError, join_paths, split_paths},
+    env::{self, JoinPathsError},
     path::PathBuf,
-    str::FromStr,
+    sync::OnceLock,
 };
 
 use bundler_settings::Error as BundlerSettingsError;
@@ -10,52 +10,85 @@ use rv_settings::Error as RvSettingsError;
 use bundler_settings::BundlerSettings;
 use camino::{FromPathBufError, Utf8Path, Utf8PathBuf};
 use indexmap::IndexSet;
-use rv_settings::RvSettings;
+use rv_settings::{Isolation, RvSettings};
 use tracing::{debug, error, instrument};
 
+use rv_core::{discovery::RubyDirs, env::EnvOptions, linked::LinkedRubies};
 use rv_ruby::{
     RemoteRuby, Ruby,
-    request::{RequestError, RubyRequest, Source},
+    request::{RequestError, RubyRequest},
     version::RubyVersion,
 };
 
+pub use rv_core::{env::Env, request::RequestedRuby};
+
 use rv_gem_types::Requirement;
 
 use crate::GlobalArgs;
+use crate::config::inferred_ruby::InferredRuby;
 use crate::update;
 
 pub mod bundler_settings;
+pub mod credentials;
+pub(crate) mod gemfile;
 pub mod github;
-mod ruby_cache;
+pub(crate) mod inferred_ruby;
+pub(crate) mod release_source;
 mod ruby_fetcher;
+pub(crate) mod ruby_lock;
 pub mod rv_settings;
 
 #[derive(Debug, thiserror::Error, miette::Diagnostic)]
 pub enum Error {
     #[error(transparent)]
+    #[diagnostic(code(RV0101))]
     NonUtf8Path(#[from] FromPathBufError),
     #[error("Ruby cache miss or invalid cache for {}", ruby_path)]
+    #[diagnostic(code(RV0102))]
     RubyCacheMiss { ruby_path: Utf8PathBuf },
     #[error(transparent)]
+    #[diagnostic(code(RV0103))]
     IoError(#[from] std::io::Error),
     #[error(transparent)]
+    #[diagnostic(code(RV0104))]
     RequestError(#[from] RequestError),
     #[error(transparent)]
+    #[diagnostic(code(RV0105))]
     JoinPathsError(#[from] JoinPathsError),
     #[error(transparent)]
+    #[diagnostic(transparent)]
     RvSettingsError(#[from] RvSettingsError),
     #[error(transparent)]
+    #[diagnostic(transparent)]
     BundlerSettingsError(#[from] BundlerSettingsError),
     #[error("no matching ruby version found")]
+    #[diagnostic(code(RV0106))]
     NoMatchingRuby,
     #[error(
         "No available Ruby matched the Ruby requirements. The requirements were {requirement:?}"
     )]
+    #[diagnostic(code(RV0107))]
     NoRubyMatchingRequirement { requirement: Requirement },
+    #[error("{path} is not a valid rv.lock")]
+    #[diagnostic(code(RV0108), help("Write it again with `rv ruby pin --lock`"))]
+    InvalidRubyLock {
+        path: Utf8PathBuf,
+        #[source]
+        source: serde_json::Error,
+    },
 }
 
 type Result<T> = miette::Result<T, Error>;
 
+impl From<rv_core::request::Error> for Error {
+    fn from(err: rv_core::request::Error) -> Self {
+        match err {
+            rv_core::request::Error::Io(err) => Self::IoError(err),
+            rv_core::request::Error::Request(err) => Self::RequestError(err),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Config {
     pub ruby_dirs: IndexSet<Utf8PathBuf>,
@@ -65,77 +98,78 @@ pub struct Config {
     pub bundler_settings: BundlerSettings,
     pub rv_settings: RvSettings,
     pub offline: bool,
+    /// Rubies outside of `ruby_dirs` that were added with `rv ruby link`.
+    pub linked_rubies: Vec<Utf8PathBuf>,
+    /// Rubies in `ruby_dirs` to leave out, from the `ruby-dirs` setting.
+    pub excluded_rubies: Vec<glob::Pattern>,
+    /// How many directories to search for a version file, from `--dir-depth` or the `dir-depth`
+    /// setting.
+    pub dir_depth: Option<usize>,
+    /// What the locked gems require of Ruby, worked out the first time it's needed.
+    inferred_ruby: OnceLock<Option<InferredRuby>>,
 }
 
-#[derive(Debug, Clone)]
-pub enum RequestedRuby {
-    Explicit(RubyRequest),
-    Project((RubyRequest, Source)),
-    User((RubyRequest, Source)),
-    Global,
-}
+impl Config {
+    pub(crate) fn new(global_args: &GlobalArgs, request: Option<RubyRequest>) -> Result<Self> {
+        let current_dir = Utf8PathBuf::try_from(env::current_dir()?)?;
+        Self::new_in(global_args, request, &current_dir)
+    }
 
-impl RequestedRuby {
-    pub fn new(
+    /// The configuration rv would use if it were run in `current_dir`.
+    pub(crate) fn new_in(
+        global_args: &GlobalArgs,
         request: Option<RubyRequest>,
-        home_dir: &Utf8PathBuf,
-        project_root: &Utf8PathBuf,
+        current_dir: &Utf8Path,
     ) -> Result<Self> {
-        let requested_ruby = match request {
-            Some(req) => {
-                debug!("Explicit ruby request for {} received", req);
-                Self::Explicit(req)
-            }
-            None => {
-                if let Some(req) = find_directory_ruby(project_root)? {
-                    debug!("Found project ruby request for {} in {:?}", req.0, req.1);
-                    Self::Project(req)
-                } else if let Some(req) = find_directory_ruby(home_dir)? {
-                    debug!("Found user ruby request for {} in {:?}", req.0, req.1);
-                    Self::User(req)
-                } else {
-                    Self::Global
-                }
-            }
-        };
-
-        Ok(requested_ruby)
-    }
-
-    pub fn explain(&self, installed: bool) -> String {
-        match self {
-            Self::Explicit(_) => "* Default version explicitly selected".to_string(),
-            Self::Project((_, source)) => format!(
-                "* Default version pinned by {}",
-                rv_dirs::relativize(source.path())
-            ),
-            Self::User((_, source)) => format!(
-                "* Default version pinned by {}",
-                rv_dirs::unexpand(source.path())
-            ),
-            Self::Global => {
-                let installed_or_available = if installed { "installed" } else { "available" };
-                format!("* Default version is the latest {installed_or_available}")
-            }
-        }
-    }
-}
-
-impl Config {
-    pub(crate) fn new(global_args: &GlobalArgs, request: Option<RubyRequest>) -> Result<Self> {
         let root = rv_dirs::root_dir();
         let ruby_dirs = rv_dirs::canonical_ruby_dirs(&global_args.ruby_dir, &root)?;
         let cache = global_args.cache_args.to_cache()?;
 
-        let project_root = rv_dirs::project_root(&root)?;
+        let project_root = rv_dirs::project_root_of(current_dir, &root);
         debug!("Found project directory in {}", project_root);
 
         let home_dir = rv_dirs::home_dir();
 
-        let requested_ruby = RequestedRuby::new(request, &home_dir, &project_root)?;
+        let rv_settings = RvSettings::new(global_args, &home_dir, &project_root)?;
+        let request = request.or_else(|| global_args.ruby.clone());
+        let dir_depth = rv_settings.dir_depth(global_args)?;
+        let requested_ruby = RequestedRuby::new(
+            request,
+            &home_dir,
+            current_dir,
+            &root,
+            &rv_settings.version_file_order()?,
+            dir_depth,
+        )?;
         let bundler_settings = BundlerSettings::default();
-        let rv_settings = RvSettings::default();
         let offline = global_args.offline;
+        let linked_rubies = LinkedRubies::load(&rv_dirs::user_data_dir(&root))
+            .inspect_err(|err| error!("Could not read the linked rubies: {err}"))
+            .unwrap_or_default()
+            .paths()
+            .to_vec();
+
+        // A configured install dir takes precedence over the default, unless
+        // directories were given explicitly on the command line.
+        let mut ruby_dirs = ruby_dirs;
+        if global_args.ruby_dir.is_empty()
+            && let Some(install_dir) = rv_settings.ruby_install_dir_as_utf8pathbuf()
+        {
+            ruby_dirs.shift_insert(0, install_dir);
+        }
+        let (configured_dirs, excluded_rubies) = rv_settings.ruby_dir_patterns(&home_dir)?;
+        if global_args.ruby_dir.is_empty() {
+            ruby_dirs.extend(configured_dirs);
+        }
+
+        // Every HTTP client created from here on checks the pinned certificates, and every
+        // download is limited to the configured bandwidth.
+        rv_client::pin::set_certificate_pins(rv_settings.certificate_pins()?);
+        let download_rate = match global_args.limit_rate {
+            Some(rate) => Some(rate),
+            None => rv_settings.download_rate()?,
+        };
+        rv_client::rate_limit::set_download_rate(download_rate);
 
         Ok(Self {
             ruby_dirs,
@@ -145,6 +179,10 @@ impl Config {
             bundler_settings,
             rv_settings,
             offline,
+            linked_rubies,
+            excluded_rubies,
+            dir_depth,
+            inferred_ruby: OnceLock::new(),
         })
     }
 
@@ -152,17 +190,40 @@ impl Config {
         global_args: &GlobalArgs,
         request: Option<RubyRequest>,
     ) -> Result<Self> {
-        let mut config = Self::new(global_args, request)?;
+        let current_dir = Utf8PathBuf::try_from(env::current_dir()?)?;
+        Self::with_settings_in(global_args, request, &current_dir)
+    }
+
+    /// Like [`Config::with_settings`], as if rv were run in `current_dir`.
+    pub(crate) fn with_settings_in(
+        global_args: &GlobalArgs,
+        request: Option<RubyRequest>,
+        current_dir: &Utf8Path,
+    ) -> Result<Self> {
+        let mut config = Self::new_in(global_args, request, current_dir)?;
         let home_dir = rv_dirs::home_dir();
 
         config.bundler_settings = BundlerSettings::new(&home_dir, &config.project_root)
             .inspect_err(|err| error!("{}", err))
             .unwrap_or_default();
-        config.rv_settings = RvSettings::new(global_args, &home_dir, &config.project_root)?;
 
         Ok(config)
     }
 
+    /// The credentials for the gem sources on `host`: what the helper of the `credential-helper`
+    /// setting has, or else Bundler's `BUNDLE_<HOST>` setting.
+    pub fn credentials_for_host(
+        &self,
+        host: &str,
+    ) -> credentials::Result<Option<credentials::Userinfo>> {
+        if let Some(name) = &self.rv_settings.credential_helper
+            && let Some(userinfo) = credentials::CredentialHelper::from_name(name).get(host)?
+        {
+            return Ok(Some(userinfo));
+        }
+        Ok(self.bundler_settings.userinfo_for_host(host))
+    }
+
     pub async fn self_update_if_needed(&self) {
         update::check(&self.rv_settings.update_mode).await;
     }
@@ -187,16 +248,30 @@ impl Config {
             bundler_settings: BundlerSettings::default(),
             rv_settings: RvSettings::default(),
             offline: false,
+            linked_rubies: Vec::new(),
+            excluded_rubies: Vec::new(),
+            dir_depth: None,
+            inferred_ruby: OnceLock::new(),
         }
     }
 
     #[instrument(skip_all, level = "trace")]
     pub fn rubies(&self) -> Vec<Ruby> {
-        self.discover_installed_rubies()
+        self.installed_ruby_dirs().installed_rubies()
+    }
+
+    /// The valid rubies the operating system or Homebrew installed, which rv lists but never
+    /// picks for a project.
+    pub fn external_rubies(&self) -> Vec<Ruby> {
+        let ruby_dirs = self.installed_ruby_dirs();
+        rv_core::external::external_ruby_paths(&rv_dirs::root_dir())
+            .iter()
+            .filter_map(|path| ruby_dirs.ruby_in(path, false))
+            .collect()
     }
 
     pub async fn remote_rubies(&self) -> Vec<RemoteRuby> {
-        self.discover_remote_rubies().await
+        self.discover_remote_rubies(false).await
     }
 
     pub async fn find_matching_remote_ruby(&self) -> Result<RubyVersion> {
@@ -219,6 +294,24 @@ impl Config {
         }
     }
 
+    /// The exact version the request means: the newest matching release, or, when no release
+    /// matches, e.g. because rv is offline, the newest matching installed Ruby.
+    pub async fn resolve_requested_version(&self) -> Result<RubyVersion> {
+        match self.find_matching_remote_ruby().await {
+            Ok(version) => Ok(version),
+            Err(err) => match self.current_ruby() {
+                Some(ruby) => {
+                    debug!(
+                        "No matching Ruby release found ({err}), using {}",
+                        ruby.path
+                    );
+                    Ok(ruby.version)
+                }
+                None => Err(err),
+            },
+        }
+    }
+
     pub fn best_ruby(&self) -> Option<Ruby> {
         self.current_ruby()
             .or_else(|| self.highest_ruby_matching(&RubyRequest::default()))
@@ -249,16 +342,46 @@ impl Config {
     }
 
     pub fn current_ruby(&self) -> Option<Ruby> {
-        self.highest_ruby_matching(&self.ruby_request())
+        self.inferred_current_ruby()
+            .or_else(|| self.highest_ruby_matching(&self.ruby_request()))
     }
 
-    pub fn ruby_request(&self) -> RubyRequest {
-        match &self.requested_ruby {
-            RequestedRuby::Explicit(request) => request.clone(),
-            RequestedRuby::Project((request, _)) => request.clone(),
-            RequestedRuby::User((request, _)) => request.clone(),
-            RequestedRuby::Global => RubyRequest::default(),
+    /// What the project's locked gems require of Ruby, when nothing pins a Ruby for it.
+    pub(crate) fn inferred_ruby(&self) -> Option<&InferredRuby> {
+        if !matches!(self.requested_ruby, RequestedRuby::Global) {
+            return None;
+        }
+        self.inferred_ruby
+            .get_or_init(|| InferredRuby::for_project(&self.project_root, &self.cache))
+            .as_ref()
+    }
+
+    /// The newest installed Ruby the project's locked gems all run on, when nothing pins a Ruby
+    /// for the project.
+    pub(crate) fn inferred_current_ruby(&self) -> Option<Ruby> {
+        let inferred = self.inferred_ruby()?;
+        let request = self.ruby_request();
+        let rubies = self
+            .rubies()
+            .into_iter()
+            .filter(|ruby| ruby.version.satisfies(&request))
+            .collect();
+        let ruby = inferred.newest_satisfying(rubies);
+        match &ruby {
+            Some(ruby) => debug!(
+                "Using {} because the gems locked in {} require ruby {}",
+                ruby.version, inferred.lockfile, inferred.requirement
+            ),
+            None => debug!(
+                "No installed Ruby satisfies the gems locked in {}, which require ruby {}",
+                inferred.lockfile, inferred.requirement
+            ),
         }
+        ruby
+    }
+
+    pub fn ruby_request(&self) -> RubyRequest {
+        self.requested_ruby.ruby_request()
     }
 
     pub fn is_requested_ruby_installed_in_dir(&self, install_root: &Utf8Path) -> bool {
@@ -268,11 +391,13 @@ impl Config {
 
         let managed = self.ruby_dirs.first().is_some_and(|d| *d == *install_root);
 
-        Ruby::from_dir(install_path, managed)
-            .map(|ruby| ruby.is_valid())
-            .unwrap_or(false)
+        self.installed_ruby_dirs()
+            .ruby_in(&install_path, managed)
+            .is_some()
     }
 
+    /// Where gems for `ruby` are installed. Everything that installs gems or points Ruby at them
+    /// (`rv ci`, `rv shell env`, `rv run`, binstubs) asks this, so they always agree.
     pub fn gem_home(&self, ruby: &Ruby) -> Utf8PathBuf {
         if let Some(install_path) = &self.rv_settings.install_path_as_utf8pathbuf() {
             return install_path.join(ruby.gem_scope());
@@ -282,173 +407,40 @@ impl Config {
             return path.join(ruby.gem_scope());
         }
 
+        if self.is_project_isolated() {
+            return self.project_root.join(".rv/gems").join(ruby.gem_scope());
+        }
+
         ruby.gem_home()
     }
 
+    /// Whether the project's gems are kept apart from those of every other project, including
+    /// gems installed with `gem install --user-install`.
+    pub fn is_project_isolated(&self) -> bool {
+        self.rv_settings.isolation == Isolation::Project
+    }
+
     pub fn env_for(&self, ruby: Option<&Ruby>) -> Result<Env> {
         self.env_with_path_for(ruby, Default::default())
     }
 
     pub fn env_with_path_for(&self, ruby: Option<&Ruby>, extra_paths: Vec<PathBuf>) -> Result<Env> {
-        let mut env = Env::default();
-
-        let pathstr = env::var("PATH").unwrap_or_else(|_| String::new());
-        let mut paths = split_paths(&pathstr).collect::<IndexSet<_>>();
-        for extra_path in extra_paths {
-            paths.insert(extra_path);
-        }
-
-        let old_ruby_paths: Vec<PathBuf> = ["RUBY_ROOT", "GEM_HOME"]
-            .iter()
-            .filter_map(|var| env::var(var).ok())
-            .map(|p| std::path::Path::new(&p).join("bin"))
-            .collect();
-
-        let old_gem_paths: Vec<PathBuf> =
-            env::var("GEM_PATH").map_or_else(|_| vec![], |p| split_paths(&p).collect::<Vec<_>>());
-
-        // Remove old Ruby and Gem paths from PATH
-        paths.retain(|p| !old_ruby_paths.contains(p) && !old_gem_paths.contains(p));
-
-        if let Some(ruby) = ruby {
-            let mut gem_paths = vec![];
-            paths.insert_before(0, ruby.bin_path().into());
-            env.insert("RUBY_ROOT", ruby.path.to_string());
-            env.insert("RUBY_ENGINE", ruby.version.engine.name().into());
-            env.insert("RUBY_VERSION", ruby.version.number());
-            let gem_home = self.gem_home(ruby);
-            paths.insert_before(0, gem_home.join("bin").into());
-            gem_paths.insert(0, gem_home.clone());
-            env.insert("GEM_HOME", gem_home.into_string());
-            let user_home = ruby.user_home();
-            paths.insert_before(0, user_home.join("bin").into());
-            gem_paths.insert(0, user_home);
-            let gem_path = join_paths(gem_paths)?;
-            if let Some(gem_path) = gem_path.to_str() {
-                env.insert("GEM_PATH", gem_path.into());
-            }
-
-            // Set MANPATH so `man ruby`, `man irb`, etc. work correctly.
-            // MANPATH is a Unix concept — Windows has no man page system.
-            // A trailing colon means "also search system man directories".
-            #[cfg(not(windows))]
-            if let Some(man_path) = ruby.man_path() {
-                let existing = env::var("MANPATH").unwrap_or_default();
-                let man_paths = split_paths(&existing).collect::<Vec<_>>();
-
-                if !man_paths.contains(&man_path.to_path_buf().into_std_path_buf()) {
-                    env.insert("MANPATH", format!("{}:{}", man_path, existing));
-                }
-            }
-        }
-
-        let path = join_paths(paths)?;
-        if let Some(path) = path.to_str() {
-            env.insert("PATH", path.into());
-        }
-
-        Ok(env)
+        let options = EnvOptions {
+            gem_home: ruby.map(|ruby| self.gem_home(ruby)),
+            isolated: self.is_project_isolated(),
+            extra_paths,
+        };
+        Ok(rv_core::env::env_for(ruby, options)?)
     }
 
     fn highest_ruby_matching(&self, request: &RubyRequest) -> Option<Ruby> {
-        self.discover_rubies_matching(|dir_name| {
-            if dir_name == "ruby-dev" {
-                request.is_dev()
-            } else {
-                RubyVersion::from_str(dir_name).is_ok_and(|v| v.satisfies(request))
-            }
-        })
-        .last()
-        .cloned()
-    }
-}
-
-fn find_directory_ruby(dir: &Utf8PathBuf) -> Result<Option<(RubyRequest, Source)>> {
-    let ruby_version = dir.join(".ruby-version");
-    if ruby_version.exists() {
-        let ruby_version_string = std::fs::read_to_string(&ruby_version)?;
-        return Ok(Some((
-            ruby_version_string.parse()?,
-            Source::DotRubyVersion(ruby_version),
-        )));
-    }
-
-    let tool_versions = dir.join(".tool-versions");
-    if tool_versions.exists() {
-        let tool_versions_string = std::fs::read_to_string(&tool_versions)?;
-        let tool_version = tool_versions_string
-            .lines()
-            .find_map(|l| l.trim_start().strip_prefix("ruby "));
-
-        if let Some(version) = tool_version {
-            return Ok(Some((
-                version.parse()?,
-                Source::DotToolVersions(tool_versions),
-            )));
-        }
-    }
-
-    let lockfile = dir.join("Gemfile.lock");
-    if lockfile.exists() {
-        let raw_contents = std::fs::read_to_string(&lockfile)?;
-        // Normalize Windows line endings (CRLF) to Unix (LF) for the parser
-        let lockfile_contents = rv_lockfile::normalize_line_endings(&raw_contents);
-
-        if let Ok(parsed_lockfile) = rv_lockfile::parse(&lockfile_contents) {
-            let lockfile_ruby = parsed_lockfile.ruby_version;
-
-            if let Some(lockfile_ruby) = lockfile_ruby {
-                return Ok(Some((
-                    lockfile_ruby.cruby_version.into(),
-                    Source::GemfileLock(lockfile),
-                )));
-            }
-        } else {
-            debug!(
-                "Ignoring {} while discovering ruby version to use because it could not be parsed",
-                lockfile
-            );
-        }
-    }
-
-    Ok(None)
-}
-
-pub struct Env {
-    unset: Vec<&'static str>,
-
-    set: Vec<(&'static str, String)>,
-}
-
-impl Default for Env {
-    fn default() -> Self {
-        Self {
-            set: vec![],
-            unset: Self::ENV_VARS.into(),
-        }
-    }
-}
-
-impl Env {
-    const ENV_VARS: [&str; 6] = [
-        "RUBY_ROOT",
-        "RUBY_ENGINE",
-        "RUBY_VERSION",
-        "RUBYOPT",
-        "GEM_HOME",
-        "GEM_PATH",
-    ];
-
-    pub fn insert(&mut self, var: &'static str, val: String) {
-        // PATH is never in the list to unset
-        if let Some(i) = self.unset.iter().position(|i| *i == var) {
-            self.unset.remove(i);
-        }
-
-        self.set.push((var, val));
+        self.installed_ruby_dirs().highest_matching(request)
     }
 
-    pub fn split(&self) -> (Vec<&'static str>, Vec<(&'static str, String)>) {
-        (self.unset.clone(), self.set.clone())
+    /// The directories rubies are installed in, to look for rubies there.
+    pub fn installed_ruby_dirs(&self) -> RubyDirs<'_> {
+        RubyDirs::new(&self.ruby_dirs, &self.cache)
+            .with_linked(&self.linked_rubies)
+            .with_excluded(&self.excluded_rubies)
     }
 }
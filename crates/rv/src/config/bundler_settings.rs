@@ -1,18 +1,20 @@
-use camino::Utf8PathBuf;
+use camino::{Utf8Path, Utf8PathBuf};
 use config::{Config as ConfigRs, Environment, File, FileFormat};
 use miette::Diagnostic;
 use serde_json::Value as JsonValue;
 use std::collections::HashMap;
 use std::path::absolute;
 
+use super::credentials::{Userinfo, parse_userinfo};
+
 #[derive(Debug, thiserror::Error, Diagnostic)]
 pub enum Error {
     #[error("Error parsing Bundler configuration: {0}")]
-    #[diagnostic(code(rv::bundler_build_error))]
+    #[diagnostic(code(RV0121))]
     BuildError(String),
 
     #[error("Failed to deserialize configuration: {0}")]
-    #[diagnostic(code(rv::bundler_deserialization_error))]
+    #[diagnostic(code(RV0122))]
     DeserializationError(String),
 }
 
@@ -24,13 +26,37 @@ pub struct BundlerSettings {
     settings: HashMap<String, JsonValue>,
 }
 
+/// Where Bundler's global and local (project) config files are, in that order.
+/// Like Bundler, `BUNDLE_USER_CONFIG` or `BUNDLE_USER_HOME` move the global config file,
+/// and `BUNDLE_APP_CONFIG` moves the project's config directory.
+fn config_paths(
+    home_dir: &Utf8Path,
+    project_dir: &Utf8Path,
+    env_var: impl Fn(&str) -> Option<String>,
+) -> (Utf8PathBuf, Utf8PathBuf) {
+    let global = match (env_var("BUNDLE_USER_CONFIG"), env_var("BUNDLE_USER_HOME")) {
+        (Some(config), _) => Utf8PathBuf::from(config),
+        (None, Some(user_home)) => Utf8PathBuf::from(user_home).join("config"),
+        (None, None) => home_dir.join(".bundle/config"),
+    };
+    let local = match env_var("BUNDLE_APP_CONFIG") {
+        Some(app_config) => project_dir.join(app_config).join("config"),
+        None => project_dir.join(".bundle/config"),
+    };
+    (global, local)
+}
+
 impl BundlerSettings {
     pub fn new(home_dir: &Utf8PathBuf, project_dir: &Utf8PathBuf) -> Result<Self> {
+        let (global_config, local_config) = config_paths(home_dir, project_dir, |var| {
+            std::env::var(var).ok().filter(|value| !value.is_empty())
+        });
+
+        // Same precedence as Bundler: project config, then environment, then global config.
         let mut builder = ConfigRs::builder();
 
-        builder = builder.add_source(
-            File::new(home_dir.join(".bundle/config").as_str(), FileFormat::Yaml).required(false),
-        );
+        builder =
+            builder.add_source(File::new(global_config.as_str(), FileFormat::Yaml).required(false));
 
         builder = builder.add_source(
             Environment::with_prefix("BUNDLE")
@@ -38,13 +64,8 @@ impl BundlerSettings {
                 .convert_case(config::Case::UpperSnake),
         );
 
-        builder = builder.add_source(
-            File::new(
-                project_dir.join(".bundle/config").as_str(),
-                FileFormat::Yaml,
-            )
-            .required(false),
-        );
+        builder =
+            builder.add_source(File::new(local_config.as_str(), FileFormat::Yaml).required(false));
 
         let config = builder
             .build()
@@ -110,16 +131,43 @@ impl BundlerSettings {
     /// Returns `(username, password)`. For a bare token, the whole config value is the username
     /// and password is `None`. If the value contains `:`, splits on the first `:` only (password
     /// may contain more colons), matching Bundler.
-    pub fn userinfo_for_host(&self, host: &str) -> Option<(String, Option<String>)> {
-        let key = format!("BUNDLE_{}", host.to_uppercase().replace('.', "__"));
+    pub fn userinfo_for_host(&self, host: &str) -> Option<Userinfo> {
+        let key = format!("BUNDLE_{}", key_part(host));
         let raw = self.get_string(&key)?;
-        Some(match raw.split_once(':') {
-            None => (raw, None),
-            Some((user, password)) => (user.to_string(), Some(password.to_string())),
-        })
+        Some(parse_userinfo(&raw))
+    }
+
+    /// The mirror configured for a gem source with Bundler `BUNDLE_MIRROR__<URL>` keys (same as
+    /// `bundle config mirror.<url>`), falling back to the mirror for all sources,
+    /// `BUNDLE_MIRROR__ALL`.
+    pub fn mirror_for(&self, remote: &str) -> Option<String> {
+        let remote = if remote.ends_with('/') {
+            remote.to_string()
+        } else {
+            format!("{remote}/")
+        };
+        let key = format!("BUNDLE_MIRROR__{}", key_part(&remote));
+        self.get_string(&key)
+            .or_else(|| self.get_string("BUNDLE_MIRROR__ALL"))
+    }
+
+    /// Extra arguments for building a gem's native extensions, from Bundler `BUNDLE_BUILD__<GEM>`
+    /// keys (same as `bundle config build.<gem>`). Arguments are separated by whitespace.
+    pub fn build_flags(&self, gem_name: &str) -> Vec<String> {
+        let key = format!("BUNDLE_BUILD__{}", key_part(gem_name));
+        self.get_string(&key)
+            .map(|flags| flags.split_whitespace().map(str::to_string).collect())
+            .unwrap_or_default()
     }
 }
 
+/// How Bundler writes `name`, like a host, URL or gem name, in the key of a setting: uppercased,
+/// with dots as `__` and dashes as `___`. `bundle config mirror.https://gems.my-corp.com` is
+/// `BUNDLE_MIRROR__HTTPS://GEMS__MY___CORP__COM/`.
+pub(crate) fn key_part(name: &str) -> String {
+    name.replace('.', "__").replace('-', "___").to_uppercase()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -344,4 +392,99 @@ BUNDLE_GITLAB__COM: "__token__:ghp_abc"
             settings.userinfo_for_host("gitlab.com")
         );
     }
+
+    #[test]
+    fn test_build_flags_from_config() {
+        let temp_dir = Utf8TempDir::new().expect("Failed to create temporary directory");
+        let home_dir = temp_dir.path().join("home");
+        let project_dir = temp_dir.path().join("project");
+
+        let config_dir = project_dir.join(".bundle");
+        std::fs::create_dir_all(&config_dir).unwrap();
+        let config_file = config_dir.join("config");
+
+        let config_content = r#"---
+
+BUNDLE_BUILD__NOKOGIRI: "--use-system-libraries  --with-xml2-include=/opt/include"
+BUNDLE_BUILD__LIBV8___NODE: "--with-system-v8"
+"#;
+        std::fs::write(&config_file, config_content).expect("Failed to write config");
+
+        let settings = BundlerSettings::new(&home_dir, &project_dir).unwrap();
+        assert_eq!(
+            vec!["--use-system-libraries", "--with-xml2-include=/opt/include"],
+            settings.build_flags("nokogiri")
+        );
+        assert_eq!(vec!["--with-system-v8"], settings.build_flags("libv8-node"));
+        assert!(settings.build_flags("json").is_empty());
+    }
+
+    #[test]
+    fn test_config_paths() {
+        let home_dir = Utf8Path::new("/home/me");
+        let project_dir = Utf8Path::new("/src/app");
+
+        assert_eq!(
+            config_paths(home_dir, project_dir, |_| None),
+            (
+                Utf8PathBuf::from("/home/me/.bundle/config"),
+                Utf8PathBuf::from("/src/app/.bundle/config")
+            )
+        );
+
+        let env = |var: &str| match var {
+            "BUNDLE_USER_HOME" => Some("/opt/bundle".to_string()),
+            "BUNDLE_APP_CONFIG" => Some("ci/bundle".to_string()),
+            _ => None,
+        };
+        assert_eq!(
+            config_paths(home_dir, project_dir, env),
+            (
+                Utf8PathBuf::from("/opt/bundle/config"),
+                Utf8PathBuf::from("/src/app/ci/bundle/config")
+            )
+        );
+
+        let env = |var: &str| (var == "BUNDLE_USER_CONFIG").then(|| "/etc/bundle".to_string());
+        assert_eq!(
+            config_paths(home_dir, project_dir, env).0,
+            Utf8PathBuf::from("/etc/bundle")
+        );
+    }
+
+    #[test]
+    fn test_mirror_for() {
+        let temp_dir = Utf8TempDir::new().expect("Failed to create temporary directory");
+        let home_dir = temp_dir.path().join("home");
+        let project_dir = temp_dir.path().join("project");
+
+        let config_dir = project_dir.join(".bundle");
+        std::fs::create_dir_all(&config_dir).unwrap();
+        let config_file = config_dir.join("config");
+
+        let config_content = r#"---
+
+BUNDLE_MIRROR__HTTPS://RUBYGEMS__ORG/: "https://mirror.example.com/rubygems/"
+"#;
+        std::fs::write(&config_file, config_content).expect("Failed to write config");
+
+        let settings = BundlerSettings::new(&home_dir, &project_dir).unwrap();
+        assert_eq!(
+            Some("https://mirror.example.com/rubygems/".to_string()),
+            settings.mirror_for("https://rubygems.org")
+        );
+        assert_eq!(
+            Some("https://mirror.example.com/rubygems/".to_string()),
+            settings.mirror_for("https://rubygems.org/")
+        );
+        assert_eq!(None, settings.mirror_for("https://gem.coop/"));
+    }
+
+    #[test]
+    fn test_key_part() {
+        assert_eq!(key_part("nokogiri"), "NOKOGIRI");
+        assert_eq!(key_part("libv8-node"), "LIBV8___NODE");
+        assert_eq!(key_part("gems.my-corp.com"), "GEMS__MY___CORP__COM");
+        assert_eq!(key_part("https://rubygems.org/"), "HTTPS://RUBYGEMS__ORG/");
+    }
 }
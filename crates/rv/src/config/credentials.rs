@@ -0,0 +1,192 @@
+//! Credentials for private gem sources from a credential helper, configured with the
+//! `credential-helper` setting, so that tokens don't have to be stored in plain text.
+//!
+//! Like git's credential helpers, a helper named `<name>` is a program called
+//! `rv-credential-<name>` on the `PATH`. rv runs `rv-credential-<name> get <host>`, and the helper
+//! prints the credentials for `<host>` on stdout: a token, or `<username>:<password>`. Printing
+//! nothing means it has no credentials for that host. Two helpers are built in:
+//!
+//! - `env` reads `RV_CREDENTIAL_<HOST>`, with the host written like Bundler's `BUNDLE_<HOST>`
+//!   settings, e.g. `RV_CREDENTIAL_GEMS__EXAMPLE__COM`.
+//! - `keychain` reads the password of the macOS Keychain's internet password for the host.
+
+use std::process::{Command, ExitStatus, Output, Stdio};
+
+use tracing::debug;
+
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+pub enum Error {
+    #[error("Could not run the credential helper {program}: {source}")]
+    #[diagnostic(
+        code(RV0141),
+        help(
+            "Install {program} somewhere on your PATH, or change the `credential-helper` setting"
+        )
+    )]
+    HelperNotRun {
+        program: String,
+        source: std::io::Error,
+    },
+    #[error("The credential helper {program} failed to get credentials for {host}: {reason}")]
+    #[diagnostic(code(RV0142))]
+    HelperFailed {
+        program: String,
+        host: String,
+        reason: String,
+    },
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// A username and an optional password. A bare token is a username without a password, which is
+/// how Bundler sends it too.
+pub type Userinfo = (String, Option<String>);
+
+/// Split credentials like Bundler does: on the first `:` only, since passwords may contain more.
+pub fn parse_userinfo(raw: &str) -> Userinfo {
+    match raw.split_once(':') {
+        None => (raw.to_string(), None),
+        Some((user, password)) => (user.to_string(), Some(password.to_string())),
+    }
+}
+
+/// Where to get credentials for gem sources from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CredentialHelper {
+    /// The `RV_CREDENTIAL_<HOST>` environment variables.
+    Env,
+    /// The macOS Keychain.
+    Keychain,
+    /// An external `rv-credential-<name>` program.
+    External(String),
+}
+
+impl CredentialHelper {
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "env" => Self::Env,
+            "keychain" => Self::Keychain,
+            name => Self::External(name.to_owned()),
+        }
+    }
+
+    /// The credentials the helper has for `host`, if any.
+    pub fn get(&self, host: &str) -> Result<Option<Userinfo>> {
+        let raw = match self {
+            Self::Env => std::env::var(env_var_name(host)).ok(),
+            Self::Keychain => keychain_password(host)?,
+            Self::External(name) => {
+                let program = format!("rv-credential-{name}");
+                let mut command = Command::new(&program);
+                command.arg("get").arg(host);
+                run_helper(&program, host, command)?
+            }
+        };
+        Ok(raw
+            .map(|raw| raw.trim().to_owned())
+            .filter(|raw| !raw.is_empty())
+            .map(|raw| parse_userinfo(&raw)))
+    }
+}
+
+/// The environment variable the `env` helper reads the credentials for `host` from.
+fn env_var_name(host: &str) -> String {
+    format!(
+        "RV_CREDENTIAL_{}",
+        host.to_uppercase().replace('-', "___").replace('.', "__")
+    )
+}
+
+#[cfg(target_os = "macos")]
+fn keychain_password(host: &str) -> Result<Option<String>> {
+    let program = "security";
+    let mut command = Command::new(program);
+    command
+        .args(["find-internet-password", "-s", host, "-w"])
+        .stderr(Stdio::null());
+    let output = helper_output(program, host, command)?;
+    // `security` exits with 44 when the Keychain has no such item, which isn't an error.
+    match output.status.code() {
+        Some(0) => Ok(Some(String::from_utf8_lossy(&output.stdout).into_owned())),
+        Some(44) => Ok(None),
+        _ => Err(helper_failed(program, host, output.status)),
+    }
+}
+
+#[cfg(not(target_os = "macos"))]
+fn keychain_password(_host: &str) -> Result<Option<String>> {
+    debug!("The keychain credential helper only works on macOS");
+    Ok(None)
+}
+
+/// Run a helper and read what it printed on stdout.
+fn run_helper(program: &str, host: &str, command: Command) -> Result<Option<String>> {
+    let output = helper_output(program, host, command)?;
+    if !output.status.success() {
+        return Err(helper_failed(program, host, output.status));
+    }
+    Ok(Some(String::from_utf8_lossy(&output.stdout).into_owned()))
+}
+
+fn helper_output(program: &str, host: &str, mut command: Command) -> Result<Output> {
+    debug!("Getting credentials for {host} from {program}");
+    command
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|source| Error::HelperNotRun {
+            program: program.to_owned(),
+            source,
+        })
+}
+
+fn helper_failed(program: &str, host: &str, status: ExitStatus) -> Error {
+    Error::HelperFailed {
+        program: program.to_owned(),
+        host: host.to_owned(),
+        reason: format!("it exited with {status}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_userinfo() {
+        assert_eq!(parse_userinfo("token"), ("token".to_owned(), None));
+        assert_eq!(
+            parse_userinfo("user:pass:word"),
+            ("user".to_owned(), Some("pass:word".to_owned()))
+        );
+    }
+
+    #[test]
+    fn test_env_helper() {
+        assert_eq!(
+            env_var_name("gems.my-company.com"),
+            "RV_CREDENTIAL_GEMS__MY___COMPANY__COM"
+        );
+        unsafe {
+            std::env::set_var("RV_CREDENTIAL_GEMS__EXAMPLE__ORG", "ci:s3cret\n");
+        }
+        let helper = CredentialHelper::from_name("env");
+        assert_eq!(
+            helper.get("gems.example.org").unwrap(),
+            Some(("ci".to_owned(), Some("s3cret".to_owned())))
+        );
+        assert_eq!(helper.get("rubygems.org").unwrap(), None);
+    }
+
+    #[test]
+    fn test_missing_external_helper() {
+        let helper = CredentialHelper::from_name("does-not-exist");
+        assert_eq!(
+            helper,
+            CredentialHelper::External("does-not-exist".to_owned())
+        );
+        assert!(matches!(
+            helper.get("gems.example.org"),
+            Err(Error::HelperNotRun { program, .. }) if program == "rv-credential-does-not-exist"
+        ));
+    }
+}
@@ -0,0 +1,792 @@
+//! Reads what a Gemfile says about its gems: their groups, platforms, sources and `install_if`
+//! conditions, including the gems a `gemspec` directive pulls in. Its `ruby` directive is read by
+//! [`rv_core::gemfile`].
+//!
+//! This isn't a Ruby parser. It only understands the common literal forms of the directives,
+//! which covers what Bundler itself documents. Gemfiles that need Ruby to work out their gems
+//! can be evaluated with [`eval`] instead.
+
+pub(crate) mod eval;
+pub(crate) mod lint;
+
+use camino::Utf8Path;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use rv_core::gemfile::{ARGUMENT_REGEX, strip_comment};
+use rv_gem_types::requirement::VersionConstraint;
+use rv_ruby::Ruby;
+use rv_ruby::engine::RubyEngine;
+use serde::Serialize;
+use std::collections::HashMap;
+use tracing::{debug, info, warn};
+
+/// Matches a group name, written as a symbol (`:test`) or a string (`"test"`).
+static GROUP_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"(?:^|[^\w:]):(\w+)|["']([^"']*)["']"#).expect("valid regex"));
+
+/// Matches the `group:` or `groups:` option of a `gem` directive, in either hash syntax.
+static GROUP_OPTION_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"(?:\bgroups?:|:groups?\s*=>)\s*(%[iw]\[[^\]]*\]|\[[^\]]*\]|\S+)"#)
+        .expect("valid regex")
+});
+
+/// Matches the `development_group:` option of a `gemspec` directive, in either hash syntax.
+static DEVELOPMENT_GROUP_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"(?:\bdevelopment_group:|:development_group\s*=>)\s*(?::(\w+)|["']([^"']*)["'])"#)
+        .expect("valid regex")
+});
+
+/// Matches `spec.name = "foo"` in a gemspec.
+static GEMSPEC_NAME_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"^\w+\.name\s*=\s*["']([^"']+)["']"#).expect("valid regex"));
+
+/// Matches the dependency declarations in a gemspec, like `spec.add_dependency "rack", "~> 3.0"`.
+static GEMSPEC_DEPENDENCY_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r#"^\w+\.add_(dependency|runtime_dependency|development_dependency)[\s(]+["']([^"']+)["']"#,
+    )
+    .expect("valid regex")
+});
+
+/// Matches the `platform:` or `platforms:` option of a `gem` directive, in either hash syntax.
+static PLATFORMS_OPTION_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"(?:\bplatforms?:|:platforms?\s*=>)\s*(%[iw]\[[^\]]*\]|\[[^\]]*\]|\S+)"#)
+        .expect("valid regex")
+});
+
+/// Matches the `install_if:` option of a `gem` directive, e.g. `install_if: -> { ENV["CI"] }`.
+static INSTALL_IF_OPTION_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"(?:\binstall_if:|:install_if\s*=>)\s*(->\s*(?:\([^)]*\))?\s*\{[^}]*\}|[^,]+)"#)
+        .expect("valid regex")
+});
+
+/// Matches a `git_source` definition: its name, block variable, and template if it's on the
+/// same line.
+static GIT_SOURCE_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"^\(?\s*:(\w+)\s*\)?\s*(?:\{|do)\s*\|\s*(\w+)\s*\|\s*(?:"([^"]*)"|'([^']*)')?"#)
+        .expect("valid regex")
+});
+
+/// Matches a string literal on its own line.
+static STRING_LITERAL_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"^(?:"([^"]*)"|'([^']*)')$"#).expect("valid regex"));
+
+/// The group of gems declared outside of any `group`.
+pub(crate) const DEFAULT_GROUP: &str = "default";
+
+/// The group Bundler puts a gemspec's development dependencies in, unless the `gemspec`
+/// directive says otherwise.
+const DEFAULT_DEVELOPMENT_GROUP: &str = "development";
+
+/// What the Gemfile says about one gem.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub(crate) struct GemDeclaration {
+    /// Never empty: gems declared outside of any `group` are in the [`DEFAULT_GROUP`].
+    pub groups: Vec<String>,
+    /// Bundler platforms, like `mri` or `jruby`, that the gem is only installed on. Empty means
+    /// every platform.
+    pub platforms: Vec<String>,
+    /// The gem server from an enclosing `source "..." do` block or a `source:` option.
+    pub source: Option<String>,
+    /// The git repository from a `git:` option, or a `git_source` shorthand like `github:`.
+    pub git: Option<String>,
+    /// The Ruby condition of an `install_if`, which rv can't evaluate.
+    pub install_if: Option<String>,
+    /// The version requirements of a `gem` directive, like `~> 7.1`. `None` for gems that come
+    /// from a gemspec, whose requirements rv doesn't read.
+    pub requirements: Option<Vec<String>>,
+}
+
+impl GemDeclaration {
+    /// Whether Bundler would install the gem with `ruby`, going by its `platforms`.
+    pub(crate) fn installs_on(&self, ruby: &Ruby) -> bool {
+        self.platforms.is_empty()
+            || self
+                .platforms
+                .iter()
+                .any(|platform| platform_matches(platform, ruby))
+    }
+
+    fn merge(&mut self, other: GemDeclaration) {
+        for group in other.groups {
+            if !self.groups.contains(&group) {
+                self.groups.push(group);
+            }
+        }
+        // A gem declared for some platforms in one place and for all of them in another is
+        // installed everywhere.
+        if self.platforms.is_empty() || other.platforms.is_empty() {
+            self.platforms.clear();
+        } else {
+            self.platforms.extend(other.platforms);
+        }
+        self.source = self.source.take().or(other.source);
+        self.git = self.git.take().or(other.git);
+        self.install_if = self.install_if.take().or(other.install_if);
+        self.requirements = self.requirements.take().or(other.requirements);
+    }
+}
+
+/// A `do ... end` block the current line is inside of.
+enum Block {
+    Group(Vec<String>),
+    Platforms(Vec<String>),
+    Source(String),
+    InstallIf(String),
+    /// Any other block, like an `if` or a `git_source` definition.
+    Other,
+}
+
+/// Every gem declared in the Gemfile, by gem name, with the groups, platforms, source and
+/// `install_if` condition from the blocks it's in and its own options. Gems declared outside of
+/// any `group` block, and without a `group:` option, are in the [`DEFAULT_GROUP`].
+///
+/// A `gemspec` directive declares the gem in the gemspec it points to, relative to `gemfile_dir`,
+/// and that gem's development dependencies, which are in the `development` group unless the
+/// directive's `development_group:` says otherwise. This matches what Bundler lists under
+/// DEPENDENCIES: the gemspec's runtime dependencies are dependencies of the gem itself.
+pub(crate) fn gem_declarations(
+    gemfile: &str,
+    gemfile_dir: &Utf8Path,
+) -> HashMap<String, GemDeclaration> {
+    let mut blocks: Vec<Block> = vec![];
+    let mut gems: HashMap<String, GemDeclaration> = HashMap::new();
+    let mut git_sources = GitSources::default();
+
+    for line in gemfile.lines() {
+        let line = strip_comment(line).trim();
+        if line == "end" || line.starts_with("end ") {
+            blocks.pop();
+            continue;
+        }
+        if git_sources.define_pending(line) {
+            continue;
+        }
+
+        let opens_block = line.ends_with(" do") || (line.contains(" do |") && line.ends_with('|'));
+        let block = if let Some(args) = directive_args(line, "group") {
+            Block::Group(parse_groups(block_args(args)))
+        } else if let Some(args) =
+            directive_args(line, "platforms").or_else(|| directive_args(line, "platform"))
+        {
+            Block::Platforms(parse_groups(block_args(args)))
+        } else if let Some(args) = directive_args(line, "source") {
+            match ARGUMENT_REGEX.captures(args).and_then(|c| c.get(3)) {
+                Some(source) => Block::Source(source.as_str().to_string()),
+                None => Block::Other,
+            }
+        } else if let Some(args) = directive_args(line, "install_if") {
+            Block::InstallIf(block_args(args).to_string())
+        } else if let Some(args) = directive_args(line, "git_source") {
+            git_sources.define(args);
+            Block::Other
+        } else if let Some(args) = directive_args(line, "gem") {
+            let Some(name) = ARGUMENT_REGEX.captures(args).and_then(|c| c.get(3)) else {
+                continue;
+            };
+            let mut declaration = declaration_in(&blocks);
+            declaration.requirements = Some(vec![]);
+            if let Some(option) = GROUP_OPTION_REGEX.captures(args).and_then(|c| c.get(1)) {
+                declaration.groups.extend(parse_groups(option.as_str()));
+            }
+            if let Some(option) = PLATFORMS_OPTION_REGEX.captures(args).and_then(|c| c.get(1)) {
+                declaration.platforms.extend(parse_groups(option.as_str()));
+            }
+            if let Some(option) = INSTALL_IF_OPTION_REGEX
+                .captures(args)
+                .and_then(|c| c.get(1))
+            {
+                declaration.install_if = Some(option.as_str().to_string());
+            }
+            for captures in ARGUMENT_REGEX.captures_iter(args).skip(1) {
+                let key = captures.get(1).or_else(|| captures.get(2));
+                let value = captures.get(3).map_or("", |value| value.as_str());
+                match key.map(|key| key.as_str()) {
+                    Some("source") => declaration.source = Some(value.to_string()),
+                    Some("git") => declaration.git = Some(value.to_string()),
+                    Some(key) => {
+                        if let Some(git) = git_sources.expand(key, value) {
+                            declaration.git = Some(git);
+                        }
+                    }
+                    // Strings inside other options, like an `install_if` lambda, aren't
+                    // requirements even though they aren't keyed.
+                    None if VersionConstraint::try_from(value).is_ok() => {
+                        if let Some(requirements) = &mut declaration.requirements {
+                            requirements.push(value.to_string());
+                        }
+                    }
+                    None => {}
+                }
+            }
+            add_gem(&mut gems, name.as_str(), declaration);
+            continue;
+        } else if line == "gemspec" || directive_args(line, "gemspec").is_some() {
+            let directive = GemspecDirective::parse(line.trim_start_matches("gemspec"));
+            let Some(gemspec) = directive.load(gemfile_dir) else {
+                continue;
+            };
+            add_gem(&mut gems, &gemspec.name, declaration_in(&blocks));
+            for dependency in &gemspec.development_dependencies {
+                let declaration = GemDeclaration {
+                    groups: vec![directive.development_group.clone()],
+                    ..Default::default()
+                };
+                add_gem(&mut gems, dependency, declaration);
+            }
+            continue;
+        } else {
+            Block::Other
+        };
+
+        if opens_block {
+            blocks.push(block);
+        }
+    }
+
+    gems
+}
+
+/// The gem servers the Gemfile's `source` directives name, in order and without repeats,
+/// including the ones that only apply to the gems in their block.
+pub(crate) fn sources(gemfile: &str) -> Vec<String> {
+    let mut sources: Vec<String> = vec![];
+    for line in gemfile.lines() {
+        let Some(args) = directive_args(strip_comment(line).trim(), "source") else {
+            continue;
+        };
+        if let Some(source) = ARGUMENT_REGEX.captures(args).and_then(|c| c.get(3))
+            && !sources.iter().any(|known| known == source.as_str())
+        {
+            sources.push(source.as_str().to_string());
+        }
+    }
+    sources
+}
+
+/// The arguments of a directive that opens a block, without the `do`.
+fn block_args(args: &str) -> &str {
+    args.trim_end_matches(" do").trim()
+}
+
+/// [`gem_declarations`] for the Gemfile at `gemfile_path`, which contains `gemfile`. Gemfiles that
+/// use Ruby logic rv can't read are evaluated with `ruby` instead, if there is one.
+pub(crate) fn read_gem_declarations(
+    gemfile_path: &Utf8Path,
+    gemfile: &str,
+    ruby: Option<&Utf8Path>,
+) -> HashMap<String, GemDeclaration> {
+    let gemfile_dir = gemfile_path.parent().unwrap_or(Utf8Path::new("."));
+    let Some((line, construct)) = eval::dynamic_construct(gemfile) else {
+        return gem_declarations(gemfile, gemfile_dir);
+    };
+    let Some(ruby) = ruby else {
+        warn!(
+            "{gemfile_path}:{line} uses Ruby logic (`{construct}`), but there's no Ruby to evaluate it with, so some gems may be missed"
+        );
+        return gem_declarations(gemfile, gemfile_dir);
+    };
+
+    match eval::evaluate(ruby, gemfile_path) {
+        Ok(declarations) => {
+            info!(
+                "{gemfile_path}:{line} uses Ruby logic (`{construct}`), so its gems were read by evaluating it with {ruby}"
+            );
+            declarations
+        }
+        Err(err) => {
+            warn!(
+                "{gemfile_path}:{line} uses Ruby logic (`{construct}`), but evaluating it failed, so some gems may be missed: {err}"
+            );
+            gem_declarations(gemfile, gemfile_dir)
+        }
+    }
+}
+
+/// What the blocks around a gem say about it.
+fn declaration_in(blocks: &[Block]) -> GemDeclaration {
+    let mut declaration = GemDeclaration::default();
+    for block in blocks {
+        match block {
+            Block::Group(groups) => declaration.groups.extend(groups.iter().cloned()),
+            Block::Platforms(platforms) => declaration.platforms.extend(platforms.iter().cloned()),
+            Block::Source(source) => declaration.source = Some(source.clone()),
+            Block::InstallIf(condition) => declaration.install_if = Some(condition.clone()),
+            Block::Other => {}
+        }
+    }
+    declaration
+}
+
+/// Add a declaration of the gem called `name`, in the [`DEFAULT_GROUP`] if it has no groups.
+fn add_gem(
+    gems: &mut HashMap<String, GemDeclaration>,
+    name: &str,
+    mut declaration: GemDeclaration,
+) {
+    if declaration.groups.is_empty() {
+        declaration.groups.push(DEFAULT_GROUP.to_string());
+    }
+    match gems.get_mut(name) {
+        Some(existing) => existing.merge(declaration),
+        None => {
+            gems.insert(name.to_string(), declaration);
+        }
+    }
+}
+
+/// Whether `ruby` is one of Bundler's platforms, like `mri`, `jruby`, `windows` or `ruby_33`.
+fn platform_matches(platform: &str, ruby: &Ruby) -> bool {
+    // Versioned platforms, like `mri_33`, only match that minor version.
+    let (platform, version) = match platform.rsplit_once('_') {
+        Some((platform, version))
+            if version.len() >= 2 && version.chars().all(|c| c.is_ascii_digit()) =>
+        {
+            (platform, Some(version))
+        }
+        _ => (platform, None),
+    };
+    if let Some(version) = version {
+        let (major, minor) = version.split_at(1);
+        if major != ruby.version.major.to_string() || minor != ruby.version.minor.to_string() {
+            return false;
+        }
+    }
+
+    let windows = ["mingw", "mswin"]
+        .iter()
+        .any(|os| ruby.rubygems_platform.contains(os));
+    match platform {
+        "ruby" | "mri" | "c" => ruby.version.engine == RubyEngine::Ruby && !windows,
+        "jruby" => ruby.version.engine == RubyEngine::JRuby,
+        "truffleruby" => ruby.version.engine == RubyEngine::TruffleRuby,
+        "windows" | "mingw" | "x64_mingw" => windows,
+        "mswin" | "mswin64" => ruby.rubygems_platform.contains("mswin"),
+        _ => false,
+    }
+}
+
+/// Templates for `git_source` shorthands like `github: "rails/rails"`, with Bundler's built-in
+/// ones and any the Gemfile defines.
+#[derive(Default)]
+struct GitSources {
+    custom: HashMap<String, (String, String)>,
+    /// A `git_source(:name) do |repo|` whose template is on the next line.
+    pending: Option<(String, String)>,
+}
+
+impl GitSources {
+    /// Read a definition like `git_source(:gitlab) { |repo| "https://gitlab.com/#{repo}.git" }`.
+    fn define(&mut self, args: &str) {
+        let Some(captures) = GIT_SOURCE_REGEX.captures(args) else {
+            return;
+        };
+        let name = captures[1].to_string();
+        let variable = captures[2].to_string();
+        match captures.get(3).or_else(|| captures.get(4)) {
+            Some(template) => {
+                self.custom
+                    .insert(name, (variable, template.as_str().to_string()));
+            }
+            None => self.pending = Some((name, variable)),
+        }
+    }
+
+    /// Use `line` as the template of a multi-line `git_source` definition, if one is pending.
+    fn define_pending(&mut self, line: &str) -> bool {
+        let Some((name, variable)) = self.pending.take() else {
+            return false;
+        };
+        let Some(template) = STRING_LITERAL_REGEX
+            .captures(line)
+            .and_then(|captures| captures.get(1).or_else(|| captures.get(2)))
+        else {
+            return false;
+        };
+        self.custom
+            .insert(name, (variable, template.as_str().to_string()));
+        true
+    }
+
+    /// The git URL for a `name: "repo"` option, if `name` is a git source.
+    fn expand(&self, name: &str, repo: &str) -> Option<String> {
+        if let Some((variable, template)) = self.custom.get(name) {
+            return Some(template.replace(&format!("#{{{variable}}}"), repo));
+        }
+
+        // Bundler's own shorthands, where a repo without a slash is owned by a user of the same
+        // name.
+        let owned = |repo: &str| match repo.contains('/') {
+            true => repo.to_string(),
+            false => format!("{repo}/{repo}"),
+        };
+        match name {
+            "github" => Some(format!("https://github.com/{}.git", owned(repo))),
+            "gist" => Some(format!("https://gist.github.com/{repo}.git")),
+            "bitbucket" => {
+                let repo = owned(repo);
+                let user = repo.split('/').next().unwrap_or_default();
+                Some(format!("https://{user}@bitbucket.org/{repo}.git"))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// A `gemspec` directive, like `gemspec path: "gems/foo", name: "foo", development_group: :dev`.
+#[derive(Debug, PartialEq, Eq)]
+struct GemspecDirective {
+    /// The directory with the gemspec, relative to the Gemfile.
+    path: String,
+    /// Which gemspec to use, when the directory has several.
+    name: Option<String>,
+    development_group: String,
+}
+
+impl GemspecDirective {
+    fn parse(args: &str) -> Self {
+        let mut directive = Self {
+            path: ".".to_string(),
+            name: None,
+            development_group: DEFAULT_DEVELOPMENT_GROUP.to_string(),
+        };
+        for captures in ARGUMENT_REGEX.captures_iter(args) {
+            let key = captures.get(1).or_else(|| captures.get(2));
+            let value = captures
+                .get(3)
+                .map_or("", |value| value.as_str())
+                .to_string();
+            match key.map(|key| key.as_str()) {
+                Some("path") => directive.path = value,
+                Some("name") => directive.name = Some(value),
+                _ => {}
+            }
+        }
+        if let Some(group) = DEVELOPMENT_GROUP_REGEX
+            .captures(args)
+            .and_then(|captures| captures.get(1).or_else(|| captures.get(2)))
+        {
+            directive.development_group = group.as_str().to_string();
+        }
+        directive
+    }
+
+    /// Find and read the gemspec. Like Bundler, a directory with several gemspecs needs a `name:`.
+    fn load(&self, gemfile_dir: &Utf8Path) -> Option<Gemspec> {
+        let dir = gemfile_dir.join(&self.path);
+        let path = match &self.name {
+            Some(name) => dir.join(format!("{name}.gemspec")),
+            None => {
+                let mut gemspecs: Vec<_> = fs_err::read_dir(&dir)
+                    .ok()?
+                    .filter_map(|entry| entry.ok())
+                    .filter_map(|entry| camino::Utf8PathBuf::try_from(entry.path()).ok())
+                    .filter(|path| path.extension() == Some("gemspec"))
+                    .collect();
+                if gemspecs.len() != 1 {
+                    debug!(
+                        "Expected one gemspec in {dir} but found {}, so the `gemspec` directive's gems are in the default group",
+                        gemspecs.len()
+                    );
+                    return None;
+                }
+                gemspecs.pop()?
+            }
+        };
+
+        let contents = match fs_err::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(err) => {
+                debug!("Could not read the gemspec for the `gemspec` directive: {err}");
+                return None;
+            }
+        };
+        let mut gemspec = Gemspec::parse(&contents);
+        if gemspec.name.is_empty() {
+            gemspec.name = path.file_stem()?.to_string();
+        }
+        Some(gemspec)
+    }
+}
+
+/// What a `.gemspec` file says about the gem's name and dependencies.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct Gemspec {
+    /// Empty if the name isn't a string literal.
+    pub name: String,
+    pub runtime_dependencies: Vec<String>,
+    pub development_dependencies: Vec<String>,
+}
+
+impl Gemspec {
+    /// Read a gemspec's `name` and `add_*dependency` calls. Like the Gemfile, only literal
+    /// strings are understood.
+    pub(crate) fn parse(contents: &str) -> Self {
+        let mut gemspec = Self::default();
+        for line in contents.lines() {
+            let line = line.trim();
+            if let Some(captures) = GEMSPEC_NAME_REGEX.captures(line) {
+                gemspec.name = captures[1].to_string();
+            } else if let Some(captures) = GEMSPEC_DEPENDENCY_REGEX.captures(line) {
+                let dependencies = if &captures[1] == "development_dependency" {
+                    &mut gemspec.development_dependencies
+                } else {
+                    &mut gemspec.runtime_dependencies
+                };
+                dependencies.push(captures[2].to_string());
+            }
+        }
+        gemspec
+    }
+}
+
+/// The arguments of a directive like `gem "rake"` or `group(:test)`, if the line is one.
+fn directive_args<'a>(line: &'a str, directive: &str) -> Option<&'a str> {
+    let args = line.strip_prefix(directive)?;
+    args.starts_with([' ', '\t', '(']).then_some(args)
+}
+
+fn parse_groups(args: &str) -> Vec<String> {
+    // Word arrays, like `%i[development test]`, list bare names.
+    if let Some(words) = args
+        .strip_prefix("%i[")
+        .or_else(|| args.strip_prefix("%w["))
+        .and_then(|words| words.split(']').next())
+    {
+        return words.split_whitespace().map(str::to_string).collect();
+    }
+    GROUP_REGEX
+        .captures_iter(args)
+        .filter_map(|captures| captures.get(1).or_else(|| captures.get(2)))
+        .map(|group| group.as_str().to_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use camino::Utf8PathBuf;
+
+    #[test]
+    fn test_gem_groups() {
+        let gemfile = r#"
+source "https://rubygems.org"
+
+gem "rails", "~> 8.0"
+gem "debug", group: :development
+gem "rubocop", groups: [:development, :lint], require: false
+gem 'pry', :group => 'development'
+
+group :development, :test do
+  gem "rspec-rails"
+
+  platforms :mri do
+    gem "byebug"
+  end
+end
+
+group :test, optional: true do
+  gem "capybara"
+  gem "rails", require: false # Also declared above
+end
+
+# gem "commented-out", group: :test
+gem "pg"
+"#;
+        let groups = gem_declarations(gemfile, Utf8Path::new("."));
+        let groups_of = |name: &str| groups[name].groups.join(",");
+
+        assert_eq!(groups_of("rails"), "default,test");
+        assert_eq!(groups_of("debug"), "development");
+        assert_eq!(groups_of("rubocop"), "development,lint");
+        assert_eq!(groups_of("pry"), "development");
+        assert_eq!(groups_of("rspec-rails"), "development,test");
+        assert_eq!(groups_of("byebug"), "development,test");
+        assert_eq!(groups_of("capybara"), "test");
+        assert_eq!(groups_of("pg"), "default");
+        assert!(!groups.contains_key("commented-out"));
+    }
+
+    #[test]
+    fn test_parse_gemspec() {
+        let gemspec = Gemspec::parse(
+            r#"
+Gem::Specification.new do |spec|
+  spec.name          = "my_gem"
+  spec.version       = MyGem::VERSION
+  spec.add_dependency "rack", ">= 2.0"
+  spec.add_runtime_dependency('zeitwerk', '~> 2.6')
+  spec.add_development_dependency "rspec", "~> 3.13"
+  # spec.add_dependency "commented-out"
+end
+"#,
+        );
+        assert_eq!(
+            gemspec,
+            Gemspec {
+                name: "my_gem".to_string(),
+                runtime_dependencies: vec!["rack".to_string(), "zeitwerk".to_string()],
+                development_dependencies: vec!["rspec".to_string()],
+            }
+        );
+    }
+
+    #[test]
+    fn test_gemspec_directive_options() {
+        assert_eq!(
+            GemspecDirective::parse(r#" path: "gems/foo", name: 'foo', development_group: :dev"#),
+            GemspecDirective {
+                path: "gems/foo".to_string(),
+                name: Some("foo".to_string()),
+                development_group: "dev".to_string(),
+            }
+        );
+        assert_eq!(
+            GemspecDirective::parse(""),
+            GemspecDirective {
+                path: ".".to_string(),
+                name: None,
+                development_group: "development".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_gem_groups_with_gemspec() {
+        let dir = camino_tempfile::tempdir().unwrap();
+        fs_err::create_dir_all(dir.path().join("gems/widget")).unwrap();
+        fs_err::write(
+            dir.path().join("gems/widget/widget.gemspec"),
+            "Gem::Specification.new do |s|\n  s.add_dependency \"rack\"\n  s.add_development_dependency \"minitest\"\nend\n",
+        )
+        .unwrap();
+
+        let gemfile = r#"
+source "https://rubygems.org"
+
+gemspec path: "gems/widget", development_group: :test
+gem "rake", group: :development
+"#;
+        let groups = gem_declarations(gemfile, dir.path());
+        let groups_of = |name: &str| groups[name].groups.join(",");
+
+        // The name falls back to the file name when the gemspec doesn't set one literally.
+        assert_eq!(groups_of("widget"), "default");
+        assert_eq!(groups_of("minitest"), "test");
+        assert_eq!(groups_of("rake"), "development");
+        // Runtime dependencies are the gem's own dependencies, not the Gemfile's.
+        assert!(!groups.contains_key("rack"));
+
+        // A gemspec that can't be found adds nothing.
+        let groups = gem_declarations("gemspec name: \"missing\"\n", dir.path());
+        assert!(groups.is_empty());
+    }
+
+    #[test]
+    fn test_gem_declarations() {
+        let gemfile = r#"
+source "https://rubygems.org"
+
+git_source(:gitlab) { |repo| "https://gitlab.com/#{repo}.git" }
+git_source :internal do |name|
+  "https://git.example.com/#{name}.git"
+end
+
+gem "rails", github: "rails/rails"
+gem "rack", github: "rack"
+gem "widgets", gitlab: "acme/widgets", branch: "main"
+gem "secret", internal: "secret"
+gem "nokogiri", git: "https://github.com/sparklemotion/nokogiri.git"
+gem "tzinfo-data", platforms: %i[windows jruby]
+gem "bootsnap", require: false, platform: :mri
+gem "pg", install_if: -> { ENV["DB"] == "postgres" }
+
+platforms :jruby do
+  gem "activerecord-jdbc-adapter"
+end
+
+source "https://gems.example.com" do
+  gem "private-gem"
+end
+
+install_if -> { RUBY_PLATFORM =~ /darwin/ } do
+  gem "terminal-notifier"
+end
+"#;
+        let gems = gem_declarations(gemfile, Utf8Path::new("."));
+        let git_of = |name: &str| gems[name].git.as_deref();
+
+        assert_eq!(git_of("rails"), Some("https://github.com/rails/rails.git"));
+        assert_eq!(git_of("rack"), Some("https://github.com/rack/rack.git"));
+        assert_eq!(
+            git_of("widgets"),
+            Some("https://gitlab.com/acme/widgets.git")
+        );
+        assert_eq!(git_of("secret"), Some("https://git.example.com/secret.git"));
+        assert_eq!(
+            git_of("nokogiri"),
+            Some("https://github.com/sparklemotion/nokogiri.git")
+        );
+        assert_eq!(gems["tzinfo-data"].platforms, vec!["windows", "jruby"]);
+        assert_eq!(gems["bootsnap"].platforms, vec!["mri"]);
+        assert_eq!(gems["activerecord-jdbc-adapter"].platforms, vec!["jruby"]);
+        assert_eq!(
+            gems["private-gem"].source.as_deref(),
+            Some("https://gems.example.com")
+        );
+        assert_eq!(gems["rails"].source, None);
+        assert_eq!(
+            gems["pg"].install_if.as_deref(),
+            Some(r#"-> { ENV["DB"] == "postgres" }"#)
+        );
+        assert_eq!(
+            gems["terminal-notifier"].install_if.as_deref(),
+            Some("-> { RUBY_PLATFORM =~ /darwin/ }")
+        );
+        for gem in gems.values() {
+            assert_eq!(gem.groups, vec![DEFAULT_GROUP]);
+        }
+        assert!(!gems.contains_key("gitlab"));
+        assert_eq!(
+            sources(gemfile),
+            vec!["https://rubygems.org", "https://gems.example.com"]
+        );
+    }
+
+    #[test]
+    fn test_installs_on() {
+        let ruby = |engine: RubyEngine, version: &str, platform: &str| {
+            let mut ruby = Ruby {
+                key: String::new(),
+                version: version.parse().unwrap(),
+                path: Utf8PathBuf::new(),
+                managed: true,
+                symlink: None,
+                arch: String::new(),
+                os: String::new(),
+                gem_root: None,
+                enable_shared: false,
+                rubygems_platform: platform.to_string(),
+            };
+            ruby.version.engine = engine;
+            ruby
+        };
+        let on = |platforms: &[&str]| GemDeclaration {
+            platforms: platforms.iter().map(|p| p.to_string()).collect(),
+            ..Default::default()
+        };
+        let mri = ruby(RubyEngine::Ruby, "3.3.5", "arm64-darwin-23");
+        let windows = ruby(RubyEngine::Ruby, "3.3.5", "x64-mingw-ucrt");
+        let jruby = ruby(RubyEngine::JRuby, "9.4.8.0", "universal-java-17");
+
+        assert!(on(&[]).installs_on(&mri));
+        assert!(on(&["mri"]).installs_on(&mri));
+        assert!(on(&["ruby_33"]).installs_on(&mri));
+        assert!(!on(&["mri_32"]).installs_on(&mri));
+        assert!(!on(&["mri"]).installs_on(&windows));
+        assert!(on(&["windows"]).installs_on(&windows));
+        assert!(!on(&["mswin"]).installs_on(&windows));
+        assert!(!on(&["windows", "mri"]).installs_on(&jruby));
+        assert!(on(&["windows", "jruby"]).installs_on(&jruby));
+    }
+}
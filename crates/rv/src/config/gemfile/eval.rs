@@ -0,0 +1,268 @@
+//! Evaluates Gemfiles that compute their gems with Ruby logic, like loops over a list of names or
+//! versions read from a file, which [`super::gem_declarations`] can't read.
+//!
+//! The Gemfile runs in a fresh Ruby process through Bundler's own DSL, with the user's
+//! environment minus the variables that would point Ruby or Bundler somewhere else, no stdin and
+//! a time limit, and reports its dependencies back as JSON. It runs with the user's permissions,
+//! like it would under `bundle install`.
+
+use std::collections::HashMap;
+use std::io::Read;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use camino::Utf8Path;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use rv_core::gemfile::strip_comment;
+use serde::Deserialize;
+
+use super::{GemDeclaration, INSTALL_IF_OPTION_REGEX, directive_args};
+
+/// Lines that only make sense to a Ruby interpreter: control flow, file and environment access,
+/// and loops.
+static DYNAMIC_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r#"^(?:if|unless|case|while|until|for|def|eval_gemfile|eval|instance_eval|require|require_relative|load)\b|\b(?:File|IO|Dir|ENV)\b[.\[]|\.(?:each|each_with_index|map|times)\b|\s(?:if|unless)\s"#,
+    )
+    .expect("valid regex")
+});
+
+/// Prints the Gemfile's dependencies as JSON on the last line of stdout, using the same DSL
+/// Bundler evaluates Gemfiles with.
+const EVAL_SCRIPT: &str = r#"
+require "bundler"
+require "json"
+
+dsl = Bundler::Dsl.new
+dsl.eval_gemfile(ARGV.fetch(0))
+gems = dsl.dependencies.map do |dep|
+  source = dep.source
+  {
+    name: dep.name,
+    groups: dep.groups.map(&:to_s),
+    platforms: dep.platforms.map(&:to_s),
+    requirements: dep.requirement.as_list,
+    source: source.respond_to?(:remotes) ? source.remotes.first&.to_s : nil,
+    git: source.is_a?(Bundler::Source::Git) ? source.uri : nil,
+  }
+end
+$stdout.puts
+$stdout.puts JSON.generate(gems)
+"#;
+
+/// Prefixes of the environment variables the Gemfile doesn't get, because they'd make Ruby load
+/// other code or Bundler read another Gemfile or config.
+const REMOVED_ENV_PREFIXES: &[&str] = &["GEM_", "RUBY", "BUNDLE_"];
+
+/// How long the Gemfile gets to evaluate before it's killed.
+const EVAL_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How often to check whether the Gemfile has finished evaluating.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum EvalError {
+    #[error("could not run {ruby}: {source}")]
+    Spawn {
+        ruby: String,
+        source: std::io::Error,
+    },
+    #[error("Ruby exited with {status}: {stderr}")]
+    Failed { status: String, stderr: String },
+    #[error("Ruby didn't finish within {} seconds, so it was stopped", .0.as_secs())]
+    TimedOut(Duration),
+    #[error("could not wait for Ruby: {0}")]
+    Wait(#[from] std::io::Error),
+    #[error("could not read the gems Ruby reported: {0}")]
+    Output(#[from] serde_json::Error),
+}
+
+/// A dependency as reported by [`EVAL_SCRIPT`].
+#[derive(Debug, Deserialize)]
+struct EvaluatedGem {
+    name: String,
+    groups: Vec<String>,
+    platforms: Vec<String>,
+    source: Option<String>,
+    git: Option<String>,
+    #[serde(default)]
+    requirements: Vec<String>,
+}
+
+/// The first line of the Gemfile, and its 1-based number, that rv can't read without running it.
+pub(crate) fn dynamic_construct(gemfile: &str) -> Option<(usize, &str)> {
+    gemfile
+        .lines()
+        .map(|line| strip_comment(line).trim())
+        .enumerate()
+        // `install_if` conditions are Ruby, but the gems they guard are still read without it.
+        .filter(|(_, line)| directive_args(line, "install_if").is_none())
+        .find(|(_, line)| DYNAMIC_REGEX.is_match(&INSTALL_IF_OPTION_REGEX.replace_all(line, "")))
+        .map(|(index, line)| (index + 1, line))
+}
+
+/// Run the Gemfile at `gemfile_path` with `ruby`, and read back every gem it declares.
+pub(crate) fn evaluate(
+    ruby: &Utf8Path,
+    gemfile_path: &Utf8Path,
+) -> Result<HashMap<String, GemDeclaration>, EvalError> {
+    let mut command = Command::new(ruby);
+    command.args(["-e", EVAL_SCRIPT, "--", gemfile_path.as_str()]);
+    for (name, _) in std::env::vars_os() {
+        let removed = name.to_str().is_some_and(|name| {
+            REMOVED_ENV_PREFIXES
+                .iter()
+                .any(|prefix| name.starts_with(prefix))
+        });
+        if removed {
+            command.env_remove(name);
+        }
+    }
+    command
+        .env("BUNDLE_GEMFILE", gemfile_path)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    if let Some(dir) = gemfile_path.parent().filter(|dir| !dir.as_str().is_empty()) {
+        command.current_dir(dir);
+    }
+
+    let mut child = command.spawn().map_err(|source| EvalError::Spawn {
+        ruby: ruby.to_string(),
+        source,
+    })?;
+    // Read the output while Ruby runs, so a Gemfile that prints a lot can't fill the pipe and
+    // hang.
+    let stdout = read_in_background(child.stdout.take());
+    let stderr = read_in_background(child.stderr.take());
+
+    let deadline = Instant::now() + EVAL_TIMEOUT;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            child.kill()?;
+            child.wait()?;
+            return Err(EvalError::TimedOut(EVAL_TIMEOUT));
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    };
+
+    let stdout = stdout.join().unwrap_or_default();
+    let stderr = stderr.join().unwrap_or_default();
+    if !status.success() {
+        return Err(EvalError::Failed {
+            status: status.to_string(),
+            stderr: String::from_utf8_lossy(&stderr).trim().to_string(),
+        });
+    }
+
+    parse_output(&String::from_utf8_lossy(&stdout))
+}
+
+/// Read all of `pipe` on another thread.
+fn read_in_background(
+    pipe: Option<impl Read + Send + 'static>,
+) -> std::thread::JoinHandle<Vec<u8>> {
+    std::thread::spawn(move || {
+        let mut bytes = vec![];
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut bytes);
+        }
+        bytes
+    })
+}
+
+/// Read the JSON on the last line of [`EVAL_SCRIPT`]'s output. Anything the Gemfile printed
+/// itself comes before it.
+fn parse_output(stdout: &str) -> Result<HashMap<String, GemDeclaration>, EvalError> {
+    let json = stdout.trim_end().lines().last().unwrap_or_default();
+    let gems: Vec<EvaluatedGem> = serde_json::from_str(json)?;
+
+    let mut declarations = HashMap::new();
+    for gem in gems {
+        let declaration = GemDeclaration {
+            groups: gem.groups,
+            platforms: gem.platforms,
+            source: gem.source,
+            git: gem.git,
+            install_if: None,
+            requirements: Some(gem.requirements),
+        };
+        super::add_gem(&mut declarations, &gem.name, declaration);
+    }
+    Ok(declarations)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dynamic_construct() {
+        let static_gemfile = r#"
+source "https://rubygems.org"
+# File.read("VERSION") is only mentioned in a comment
+gem "rails", "~> 7.1"
+gem "pg", install_if: -> { ENV["DB"] == "postgres" }
+group :test do
+  gem "rspec"
+end
+"#;
+        assert_eq!(dynamic_construct(static_gemfile), None);
+
+        let dynamic_gemfile = r#"
+source "https://rubygems.org"
+%w[rspec-core rspec-mocks].each { |name| gem name }
+"#;
+        assert_eq!(
+            dynamic_construct(dynamic_gemfile),
+            Some((3, "%w[rspec-core rspec-mocks].each { |name| gem name }"))
+        );
+
+        assert!(dynamic_construct("gem \"rails\", File.read(\"RAILS_VERSION\")\n").is_some());
+        assert!(dynamic_construct("eval_gemfile \"Gemfile.local\"\n").is_some());
+        assert!(dynamic_construct("if ENV[\"CI\"]\n  gem \"ci-reporter\"\nend\n").is_some());
+        assert!(dynamic_construct("gem \"byebug\" unless RUBY_ENGINE == \"jruby\"\n").is_some());
+
+        // Comments after a gem, and `install_if` conditions, don't need Ruby.
+        assert_eq!(dynamic_construct("gem \"rake\" # only if needed\n"), None);
+        assert_eq!(
+            dynamic_construct("gem \"pg\", install_if: -> { ENV[\"DB\"] == \"postgres\" }\n"),
+            None
+        );
+        // But the rest of a line with an `install_if` still does.
+        assert!(
+            dynamic_construct(
+                "gem \"pg\", File.read(\"PG_VERSION\"), install_if: -> { ENV[\"DB\"] }\n"
+            )
+            .is_some()
+        );
+    }
+
+    #[test]
+    fn test_parse_output() {
+        let stdout = r#"Loading the Gemfile...
+
+[{"name":"rails","groups":["default"],"platforms":[],"source":null,"git":"https://github.com/rails/rails.git"},{"name":"byebug","groups":["development","test"],"platforms":["mri"],"source":"https://gems.example.com/","git":null}]
+"#;
+        let gems = parse_output(stdout).unwrap();
+        assert_eq!(
+            gems["rails"].git.as_deref(),
+            Some("https://github.com/rails/rails.git")
+        );
+        assert_eq!(gems["byebug"].groups, vec!["development", "test"]);
+        assert_eq!(gems["byebug"].platforms, vec!["mri"]);
+        assert_eq!(
+            gems["byebug"].source.as_deref(),
+            Some("https://gems.example.com/")
+        );
+
+        assert!(matches!(
+            parse_output("syntax error, unexpected end-of-input\n"),
+            Err(EvalError::Output(_))
+        ));
+    }
+}
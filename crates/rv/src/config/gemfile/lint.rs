@@ -0,0 +1,561 @@
+//! Mistakes in a Gemfile that Bundler would only report once it evaluates it, each with the
+//! corrected line: commas missing between arguments, options a directive doesn't take, misspelled
+//! directives, and strings that are never closed or use curly quotes.
+//!
+//! Like the rest of [`super`], this reads the Gemfile line by line instead of running it, so it
+//! only looks at the directives themselves, and stays quiet about anything it can't be sure of.
+
+use std::ops::Range;
+use std::sync::Arc;
+
+use miette::SourceSpan;
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+use super::{GIT_SOURCE_REGEX, directive_args};
+
+/// Matches whitespace after an argument that's followed by another argument or an option, where
+/// a comma should be.
+static NEXT_ARGUMENT_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"^[ \t]+(?:["']|\w+:(?:\s|$)|:\w+\s*=>)"#).expect("valid regex"));
+
+/// Matches a value, like `false` or `:test`, followed by an option without a comma in between.
+static NEXT_OPTION_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"[^\s,(\[{][ \t]+(?:\w+:(?:\s|$)|:\w+\s*=>)").expect("valid regex"));
+
+/// Matches an option's key, in either hash syntax: `require: false` or `:require => false`.
+static OPTION_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?:^|[\s,(])(?:(\w+):(?:\s|$)|:(\w+)\s*=>)").expect("valid regex"));
+
+/// The directives whose arguments are separated by commas.
+const DIRECTIVES: &[&str] = &[
+    "gem",
+    "gemspec",
+    "source",
+    "ruby",
+    "git",
+    "path",
+    "github",
+    "group",
+    "platforms",
+    "platform",
+    "plugin",
+];
+
+/// Everything else a Gemfile commonly calls, which is never a misspelled directive.
+const OTHER_METHODS: &[&str] = &[
+    "git_source",
+    "install_if",
+    "eval_gemfile",
+    "env",
+    "require",
+    "require_relative",
+    "load",
+    "puts",
+    "print",
+    "warn",
+    "raise",
+];
+
+/// The options Bundler's `gem` takes, besides the names of the Gemfile's `git_source`s.
+const GEM_OPTIONS: &[&str] = &[
+    "group",
+    "groups",
+    "platform",
+    "platforms",
+    "require",
+    "git",
+    "github",
+    "gist",
+    "bitbucket",
+    "branch",
+    "ref",
+    "tag",
+    "submodules",
+    "path",
+    "glob",
+    "name",
+    "source",
+    "install_if",
+    "force_ruby_platform",
+];
+
+/// The options Bundler's `gemspec` takes.
+const GEMSPEC_OPTIONS: &[&str] = &["path", "name", "glob", "development_group"];
+
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+pub(crate) enum Problem {
+    #[error("Missing a comma between the arguments of `{directive}`")]
+    #[diagnostic(code(RV8404))]
+    MissingComma {
+        directive: String,
+        #[source_code]
+        gemfile: Arc<String>,
+        #[label("add a comma here")]
+        span: SourceSpan,
+        #[help]
+        fix: String,
+    },
+
+    #[error("`{directive}` doesn't take a `{option}` option")]
+    #[diagnostic(code(RV8405))]
+    UnknownOption {
+        directive: String,
+        option: String,
+        #[source_code]
+        gemfile: Arc<String>,
+        #[label("unknown option")]
+        span: SourceSpan,
+        #[help]
+        fix: String,
+    },
+
+    #[error("`{name}` isn't a Gemfile directive")]
+    #[diagnostic(code(RV8406))]
+    UnknownDirective {
+        name: String,
+        #[source_code]
+        gemfile: Arc<String>,
+        #[label("unknown directive")]
+        span: SourceSpan,
+        #[help]
+        fix: String,
+    },
+
+    #[error("This string is never closed")]
+    #[diagnostic(code(RV8407))]
+    UnterminatedString {
+        #[source_code]
+        gemfile: Arc<String>,
+        #[label("the string starts here")]
+        span: SourceSpan,
+        #[help]
+        fix: String,
+    },
+
+    #[error("Curly quotes don't make a string in Ruby")]
+    #[diagnostic(code(RV8408))]
+    CurlyQuotes {
+        #[source_code]
+        gemfile: Arc<String>,
+        #[label("use straight quotes")]
+        span: SourceSpan,
+        #[help]
+        fix: String,
+    },
+}
+
+/// Every problem in `gemfile`, in the order they appear.
+pub(crate) fn lint(gemfile: &str) -> Vec<Problem> {
+    let source = Arc::new(gemfile.to_string());
+    let git_sources: Vec<String> = gemfile
+        .lines()
+        .filter_map(|line| directive_args(line.trim(), "git_source"))
+        .filter_map(|args| GIT_SOURCE_REGEX.captures(args.trim()))
+        .map(|captures| captures[1].to_string())
+        .collect();
+
+    let mut problems = vec![];
+    let mut line_start = 0;
+    for raw_line in gemfile.split_inclusive('\n') {
+        let indent = raw_line.len() - raw_line.trim_start().len();
+        let line = raw_line.trim();
+        let linter = LineLinter {
+            source: &source,
+            line,
+            offset: line_start + indent,
+            git_sources: &git_sources,
+        };
+        problems.extend(linter.lint());
+        line_start += raw_line.len();
+    }
+    problems
+}
+
+struct LineLinter<'a> {
+    source: &'a Arc<String>,
+    /// The line, without the whitespace around it.
+    line: &'a str,
+    /// Where the line starts in the Gemfile.
+    offset: usize,
+    git_sources: &'a [String],
+}
+
+impl LineLinter<'_> {
+    fn lint(&self) -> Vec<Problem> {
+        let line = self.line;
+        if line.is_empty() || line.starts_with('#') {
+            return vec![];
+        }
+
+        let scanned = scan(line);
+        if let Some(start) = scanned.unterminated {
+            let quote = &line[start..start + 1];
+            return vec![Problem::UnterminatedString {
+                gemfile: self.source.clone(),
+                span: self.span(start..line.len()),
+                fix: format!("Close the string: `{line}{quote}`"),
+            }];
+        }
+
+        let code = &line[..scanned.code_end];
+        let masked = mask(code, &scanned.strings);
+        if let Some(start) = masked.find(is_curly_quote) {
+            let end = masked.rfind(is_curly_quote).unwrap_or(start);
+            let end = end + masked[end..].chars().next().map_or(0, char::len_utf8);
+            let fixed: String = line
+                .chars()
+                .map(|c| match c {
+                    '\u{201C}' | '\u{201D}' => '"',
+                    '\u{2018}' | '\u{2019}' => '\'',
+                    c => c,
+                })
+                .collect();
+            return vec![Problem::CurlyQuotes {
+                gemfile: self.source.clone(),
+                span: self.span(start..end),
+                fix: format!("Use straight quotes: `{fixed}`"),
+            }];
+        }
+
+        let name = code
+            .split(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .next()
+            .unwrap_or_default();
+        if directive_args(code, name).is_none() {
+            return vec![];
+        }
+        if !DIRECTIVES.contains(&name) {
+            return self.unknown_directive(name, code).into_iter().collect();
+        }
+
+        let mut problems = self.missing_commas(name, code, &masked, &scanned.strings);
+        let options: Vec<&str> = match name {
+            "gem" => GEM_OPTIONS
+                .iter()
+                .copied()
+                .chain(self.git_sources.iter().map(String::as_str))
+                .collect(),
+            "gemspec" => GEMSPEC_OPTIONS.to_vec(),
+            _ => return problems,
+        };
+        problems.extend(self.unknown_options(name, &masked, &options));
+        problems
+    }
+
+    fn missing_commas(
+        &self,
+        directive: &str,
+        code: &str,
+        masked: &str,
+        strings: &[Range<usize>],
+    ) -> Vec<Problem> {
+        // Strings followed by another argument, like `gem "rails" "~> 8.0"`.
+        let after_strings = strings.iter().filter_map(|string| {
+            let found = NEXT_ARGUMENT_REGEX.find(&code[string.end..])?;
+            let gap = found.as_str().len() - found.as_str().trim_start().len();
+            Some(string.end..string.end + gap)
+        });
+        // Other values followed by an option, like `require: false group: :test`. Strings are
+        // masked out, so these never overlap the ones above.
+        let after_values = NEXT_OPTION_REGEX
+            .find_iter(masked)
+            .filter(|found| found.start() >= directive.len())
+            .map(|found| {
+                let gap = &found.as_str()[1..];
+                let start = found.start() + 1;
+                start..start + gap.len() - gap.trim_start().len()
+            });
+
+        let mut gaps: Vec<Range<usize>> = after_strings.chain(after_values).collect();
+        gaps.sort_by_key(|gap| gap.start);
+        gaps.into_iter()
+            .map(|gap| Problem::MissingComma {
+                directive: directive.to_string(),
+                gemfile: self.source.clone(),
+                span: self.span(gap.clone()),
+                fix: format!(
+                    "Add a comma: `{},{}`",
+                    &self.line[..gap.start],
+                    &self.line[gap.start..]
+                ),
+            })
+            .collect()
+    }
+
+    fn unknown_options(&self, directive: &str, masked: &str, options: &[&str]) -> Vec<Problem> {
+        OPTION_REGEX
+            .captures_iter(&masked[directive.len()..])
+            .filter_map(|captures| captures.get(1).or_else(|| captures.get(2)))
+            .filter(|option| !options.contains(&option.as_str()))
+            .map(|option| {
+                let range = directive.len() + option.start()..directive.len() + option.end();
+                let fix = match closest(option.as_str(), options) {
+                    Some(suggestion) => format!(
+                        "Did you mean `{suggestion}`? `{}{suggestion}{}`",
+                        &self.line[..range.start],
+                        &self.line[range.end..]
+                    ),
+                    None => format!("`{directive}` takes these options: {}", options.join(", ")),
+                };
+                Problem::UnknownOption {
+                    directive: directive.to_string(),
+                    option: option.as_str().to_string(),
+                    gemfile: self.source.clone(),
+                    span: self.span(range),
+                    fix,
+                }
+            })
+            .collect()
+    }
+
+    /// A call that looks like a directive, like `gme "rails"`, whose name is a typo of one.
+    fn unknown_directive(&self, name: &str, code: &str) -> Option<Problem> {
+        if OTHER_METHODS.contains(&name) {
+            return None;
+        }
+        let args = directive_args(code, name)?.trim_start_matches([' ', '\t', '(']);
+        if !args.starts_with(['"', '\'', ':']) {
+            return None;
+        }
+        let suggestion = closest(name, DIRECTIVES)?;
+        Some(Problem::UnknownDirective {
+            name: name.to_string(),
+            gemfile: self.source.clone(),
+            span: self.span(0..name.len()),
+            fix: format!(
+                "Did you mean `{suggestion}`? `{suggestion}{}`",
+                &self.line[name.len()..]
+            ),
+        })
+    }
+
+    fn span(&self, range: Range<usize>) -> SourceSpan {
+        SourceSpan::new((self.offset + range.start).into(), range.len())
+    }
+}
+
+/// Where the strings and the comment on a line are.
+struct Scanned {
+    /// The line's string literals, quotes included.
+    strings: Vec<Range<usize>>,
+    /// Where a string that's never closed starts.
+    unterminated: Option<usize>,
+    /// Where the code ends and the comment, if any, starts.
+    code_end: usize,
+}
+
+fn scan(line: &str) -> Scanned {
+    let mut strings = vec![];
+    let mut chars = line.char_indices();
+    while let Some((start, c)) = chars.next() {
+        match c {
+            '#' => {
+                return Scanned {
+                    strings,
+                    unterminated: None,
+                    code_end: start,
+                };
+            }
+            '"' | '\'' => {
+                let mut escaped = false;
+                let end = chars.by_ref().find_map(|(i, next)| {
+                    if escaped {
+                        escaped = false;
+                    } else if next == '\\' {
+                        escaped = true;
+                    } else if next == c {
+                        return Some(i + 1);
+                    }
+                    None
+                });
+                match end {
+                    Some(end) => strings.push(start..end),
+                    None => {
+                        return Scanned {
+                            strings,
+                            unterminated: Some(start),
+                            code_end: line.len(),
+                        };
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Scanned {
+        strings,
+        unterminated: None,
+        code_end: line.len(),
+    }
+}
+
+/// `code` with every string blanked out, so nothing inside one is mistaken for code. Offsets
+/// into it are offsets into `code`.
+fn mask(code: &str, strings: &[Range<usize>]) -> String {
+    let mut masked = code.as_bytes().to_vec();
+    for string in strings {
+        masked[string.clone()].fill(b' ');
+    }
+    String::from_utf8(masked).expect("whole strings are blanked out")
+}
+
+fn is_curly_quote(c: char) -> bool {
+    matches!(c, '\u{201C}' | '\u{201D}' | '\u{2018}' | '\u{2019}')
+}
+
+/// The candidate `word` is most likely a typo of, if any is close enough.
+fn closest<'a>(word: &str, candidates: &[&'a str]) -> Option<&'a str> {
+    let limit = (word.chars().count() / 3).clamp(1, 2);
+    candidates
+        .iter()
+        .map(|candidate| (edit_distance(word, candidate), *candidate))
+        .filter(|(distance, _)| *distance <= limit)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// How many characters to insert, remove, replace or swap to turn `a` into `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut rows = vec![(0..=b.len()).collect::<Vec<_>>()];
+    for i in 1..=a.len() {
+        let mut row = vec![i; b.len() + 1];
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            row[j] = (rows[i - 1][j] + 1)
+                .min(row[j - 1] + 1)
+                .min(rows[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                row[j] = row[j].min(rows[i - 2][j - 2] + 1);
+            }
+        }
+        rows.push(row);
+    }
+    rows[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use miette::Diagnostic;
+
+    fn fixes(gemfile: &str) -> Vec<String> {
+        lint(gemfile).iter().map(fix).collect()
+    }
+
+    fn fix(problem: &Problem) -> String {
+        problem.help().expect("every problem has a fix").to_string()
+    }
+
+    fn span(problem: &Problem) -> SourceSpan {
+        *problem.labels().unwrap().next().unwrap().inner()
+    }
+
+    #[test]
+    fn test_clean_gemfile() {
+        let gemfile = r#"
+source "https://rubygems.org"
+git_source(:company) { |repo| "https://git.example.com/#{repo}.git" }
+
+ruby file: ".ruby-version"
+gemspec path: "engine", development_group: :dev
+
+gem "rails", "~> 8.0", ">= 8.0.1"
+gem 'pg', '>= 1.1', require: false # it's fine
+gem "nokogiri", :platforms => [:mri, :windows], :require => "nokogiri"
+gem "internal", company: "internal/gem", branch: "main"
+gem "tzinfo-data", platforms: %i[windows jruby]
+gem "listen", install_if: -> { RUBY_PLATFORM =~ /darwin/ }
+gem "title", "~> 1" # “quoted” in a comment
+
+group :development, :test do
+  gem "debug", platforms: [:mri], require: "debug/prelude"
+end
+require "set"
+"#;
+        assert!(lint(gemfile).is_empty(), "{:?}", fixes(gemfile));
+    }
+
+    #[test]
+    fn test_missing_comma() {
+        let gemfile = "source \"https://rubygems.org\"\ngem \"rails\" \"~> 8.0\"\n";
+        let problems = lint(gemfile);
+        assert_eq!(problems.len(), 1);
+        assert!(
+            matches!(&problems[0], Problem::MissingComma { directive, .. } if directive == "gem")
+        );
+        assert_eq!(span(&problems[0]), SourceSpan::new(41.into(), 1));
+        assert_eq!(fix(&problems[0]), r#"Add a comma: `gem "rails", "~> 8.0"`"#);
+
+        assert_eq!(
+            fixes("  gem 'debug', require: false group: :test\n"),
+            vec!["Add a comma: `gem 'debug', require: false, group: :test`"]
+        );
+        assert_eq!(
+            fixes("gem \"pry\" :require => false\n"),
+            vec![r#"Add a comma: `gem "pry", :require => false`"#]
+        );
+    }
+
+    #[test]
+    fn test_unknown_option() {
+        assert_eq!(
+            fixes("gem \"rails\", requires: false\n"),
+            vec![r#"Did you mean `require`? `gem "rails", require: false`"#]
+        );
+        assert_eq!(
+            fixes("gem \"rails\", :platfroms => :mri\n"),
+            vec![r#"Did you mean `platforms`? `gem "rails", :platforms => :mri`"#]
+        );
+        assert_eq!(
+            fixes("gemspec development_groups: :dev\n"),
+            vec!["Did you mean `development_group`? `gemspec development_group: :dev`"]
+        );
+        let problems = lint("gem \"rails\", optional: true\n");
+        assert!(
+            matches!(&problems[..], [Problem::UnknownOption { option, .. }] if option == "optional")
+        );
+        assert!(fix(&problems[0]).starts_with("`gem` takes these options: group,"));
+    }
+
+    #[test]
+    fn test_unknown_directive() {
+        assert_eq!(
+            fixes("gme \"rails\"\n"),
+            vec![r#"Did you mean `gem`? `gem "rails"`"#]
+        );
+        assert_eq!(
+            fixes("soruce 'https://rubygems.org'\n"),
+            vec!["Did you mean `source`? `source 'https://rubygems.org'`"]
+        );
+        assert!(lint("puts \"hello\"\nfoo :bar\nconfigure 'x'\n").is_empty());
+    }
+
+    #[test]
+    fn test_strings() {
+        let problems = lint("gem \"rails\n");
+        assert!(matches!(
+            &problems[..],
+            [Problem::UnterminatedString { .. }]
+        ));
+        assert_eq!(span(&problems[0]), SourceSpan::new(4.into(), 6));
+        assert_eq!(fix(&problems[0]), r#"Close the string: `gem "rails"`"#);
+
+        assert_eq!(
+            fixes("gem \u{201C}rails\u{201D}, \u{2018}~> 8.0\u{2019}\n"),
+            vec![r#"Use straight quotes: `gem "rails", '~> 8.0'`"#]
+        );
+        assert!(lint("gem \"it's \\\"fine\\\"\"\n").is_empty());
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("gem", "gem"), 0);
+        assert_eq!(edit_distance("gme", "gem"), 1);
+        assert_eq!(edit_distance("requires", "require"), 1);
+        assert_eq!(edit_distance("platfroms", "platforms"), 1);
+        assert_eq!(closest("configure", DIRECTIVES), None);
+    }
+}
@@ -19,12 +19,12 @@ pub fn github_token() -> Option<String> {
         .or_else(|| std::env::var("GH_TOKEN").ok())
 }
 
-/// Builds a `reqwest::RequestBuilder` for a GitHub API endpoint with standard headers
+/// Builds a `RequestBuilder` for a GitHub API endpoint with standard headers
 /// and optional authentication.
 pub fn github_api_get(
-    client: &reqwest::Client,
+    client: &rv_client::http_client::Client,
     url: impl reqwest::IntoUrl,
-) -> reqwest::RequestBuilder {
+) -> rv_client::http_client::RequestBuilder {
     use tracing::debug;
 
     let mut builder = client
@@ -0,0 +1,259 @@
+//! The rubies a project's locked gems can run on, for projects that don't pin a Ruby.
+//!
+//! Each locked gem may require some Ruby versions. rv knows which from the compact index it
+//! cached when it resolved or installed the gem, or from the gemspec it cached for a path or git
+//! gem. Together, the requirements narrow down which installed Ruby the project can use.
+
+use camino::{Utf8Path, Utf8PathBuf};
+use rv_cache::{Cache, CacheBucket};
+use rv_gem_types::requirement::Requirement;
+use rv_gem_types::{ReleaseTuple, Version};
+use rv_lockfile::datatypes::GemfileDotLock;
+use rv_ruby::Ruby;
+use tracing::debug;
+
+use crate::gemserver::parse_all_releases_from_body;
+
+/// The lockfiles Bundler writes, in the order it looks for them.
+pub(crate) const LOCKFILE_NAMES: [&str; 2] = ["Gemfile.lock", "gems.locked"];
+
+/// What the locked gems of a project require of Ruby.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InferredRuby {
+    /// The lockfile the gems are locked in.
+    pub lockfile: Utf8PathBuf,
+    /// Every constraint of every gem's requirement, which a Ruby has to satisfy all of.
+    pub requirement: Requirement,
+    /// The gems that require something of Ruby, and what, sorted by name.
+    pub required_by: Vec<(String, Requirement)>,
+}
+
+impl InferredRuby {
+    /// What the gems locked in `project_root` require of Ruby, going by what's in `cache`. None
+    /// when there's no lockfile, or no locked gem rv knows to require anything.
+    pub fn for_project(project_root: &Utf8Path, cache: &Cache) -> Option<Self> {
+        let lockfile = LOCKFILE_NAMES
+            .iter()
+            .map(|name| project_root.join(name))
+            .find(|path| path.is_file())?;
+        let contents = fs_err::read_to_string(&lockfile).ok()?;
+        let contents = rv_lockfile::normalize_line_endings(&contents);
+        let parsed = rv_lockfile::parse(&contents)
+            .inspect_err(|err| debug!("Could not parse {lockfile} to infer a Ruby: {err}"))
+            .ok()?;
+        let inferred = Self::from_lockfile(lockfile, &parsed, cache);
+        if let Some(inferred) = &inferred {
+            debug!(
+                "The gems locked in {} require ruby {}",
+                inferred.lockfile, inferred.requirement
+            );
+        }
+        inferred
+    }
+
+    fn from_lockfile(
+        lockfile: Utf8PathBuf,
+        parsed: &GemfileDotLock,
+        cache: &Cache,
+    ) -> Option<Self> {
+        let compact_index_dir = cache
+            .shard(CacheBucket::GemDeps, "compact_index")
+            .into_path_buf();
+        let gemspecs_dir = cache
+            .shard(CacheBucket::Gemspec, "gemspecs")
+            .into_path_buf();
+
+        let from_index = parsed
+            .gem
+            .iter()
+            .flat_map(|section| &section.specs)
+            .map(|spec| {
+                let release = &spec.release_tuple;
+                (release, indexed_requirement(&compact_index_dir, release))
+            });
+        let from_paths = parsed.path.iter().flat_map(|section| {
+            let key = rv_cache::cache_digest(section.remote);
+            section.specs.iter().map(move |spec| {
+                let release = &spec.release_tuple;
+                let path = gemspecs_dir.join(format!("{key}-{}", release.spec_name()));
+                (release, gemspec_requirement(&path))
+            })
+        });
+        let from_git = parsed.git.iter().flat_map(|section| {
+            section.specs.iter().map(|spec| {
+                let release = &spec.release_tuple;
+                let path =
+                    gemspecs_dir.join(format!("{}-{}", section.revision, release.spec_name()));
+                (release, gemspec_requirement(&path))
+            })
+        });
+
+        let mut required_by: Vec<(String, Requirement)> = from_index
+            .chain(from_paths)
+            .chain(from_git)
+            .filter_map(|(release, requirement)| Some((release.name.clone(), requirement?)))
+            .filter(|(_, requirement)| {
+                !requirement.constraints.is_empty() && !requirement.is_latest_version()
+            })
+            .collect();
+        if required_by.is_empty() {
+            return None;
+        }
+        required_by.sort_by(|(a, _), (b, _)| a.cmp(b));
+        required_by.dedup();
+
+        let mut constraints = Vec::new();
+        for constraint in required_by
+            .iter()
+            .flat_map(|(_, requirement)| &requirement.constraints)
+        {
+            if !constraints.contains(constraint) {
+                constraints.push(constraint.clone());
+            }
+        }
+
+        Some(Self {
+            lockfile,
+            requirement: constraints.into(),
+            required_by,
+        })
+    }
+
+    /// Whether a Ruby of `version` satisfies every locked gem.
+    pub fn satisfied_by(&self, version: &Version) -> bool {
+        self.requirement.satisfied_by(version)
+    }
+
+    /// The newest of `rubies` that satisfies every locked gem.
+    pub fn newest_satisfying(&self, rubies: Vec<Ruby>) -> Option<Ruby> {
+        rubies
+            .into_iter()
+            .filter(|ruby| self.satisfied_by(&Version::from(&ruby.version)))
+            .max()
+    }
+}
+
+/// The Ruby requirement of `release`, from the compact index cached in `compact_index_dir`.
+fn indexed_requirement(
+    compact_index_dir: &Utf8Path,
+    release: &ReleaseTuple,
+) -> Option<Requirement> {
+    let info = std::fs::read_to_string(compact_index_dir.join("info").join(&release.name)).ok()?;
+    let prefix = format!("{} ", release.full_version());
+    let line = info.lines().find(|line| line.starts_with(&prefix))?;
+    let mut releases = parse_all_releases_from_body(line).ok()?;
+    releases.pop().map(|release| release.metadata.ruby)
+}
+
+/// The Ruby requirement in the gemspec cached at `path`.
+fn gemspec_requirement(path: &Utf8Path) -> Option<Requirement> {
+    let yaml = std::fs::read_to_string(path).ok()?;
+    let spec = rv_gem_specification_yaml::parse(&yaml).ok()?;
+    Some(spec.required_ruby_version)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use rv_ruby::version::RubyVersion;
+
+    use super::*;
+
+    const LOCKFILE: &str = "\
+GEM
+  remote: https://rubygems.org/
+  specs:
+    nokogiri (1.18.9-x86_64-linux)
+    rack (3.1.16)
+    rails (8.0.2)
+      rack (>= 2.2.4)
+
+PLATFORMS
+  x86_64-linux
+
+DEPENDENCIES
+  nokogiri
+  rails (~> 8.0)
+
+BUNDLED WITH
+   2.6.9
+";
+
+    fn write_info(cache: &Cache, gem: &str, contents: &str) {
+        let dir = cache
+            .shard(CacheBucket::GemDeps, "compact_index")
+            .into_path_buf()
+            .join("info");
+        fs_err::create_dir_all(&dir).unwrap();
+        fs_err::write(dir.join(gem), contents).unwrap();
+    }
+
+    fn ruby(version: &str) -> Ruby {
+        Ruby {
+            key: String::new(),
+            version: RubyVersion::from_str(version).unwrap(),
+            path: format!("/rubies/{version}").into(),
+            managed: true,
+            enable_shared: false,
+            symlink: None,
+            arch: "x86_64".into(),
+            os: "linux".into(),
+            gem_root: None,
+            rubygems_platform: "x86_64-linux".into(),
+        }
+    }
+
+    #[test]
+    fn test_infer_from_compact_index() {
+        let cache = Cache::temp().unwrap();
+        write_info(
+            &cache,
+            "nokogiri",
+            "---\n\
+             1.18.9 racc:~> 1.4|checksum:aa,ruby:>= 3.1.0\n\
+             1.18.9-x86_64-linux racc:~> 1.4|checksum:bb,ruby:>= 3.1&< 3.5.dev\n",
+        );
+        write_info(
+            &cache,
+            "rails",
+            "---\n\
+             7.2.0 |checksum:cc,ruby:>= 3.1.0\n\
+             8.0.2 |checksum:dd,ruby:>= 3.2.0\n",
+        );
+        // Allowing any Ruby doesn't count as a requirement.
+        write_info(&cache, "rack", "---\n3.1.16 |checksum:ee,ruby:>= 0\n");
+
+        let parsed = rv_lockfile::parse(LOCKFILE).unwrap();
+        let inferred = InferredRuby::from_lockfile("Gemfile.lock".into(), &parsed, &cache).unwrap();
+        let gems: Vec<&str> = inferred
+            .required_by
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect();
+        assert_eq!(gems, vec!["nokogiri", "rails"]);
+        assert_eq!(
+            inferred.requirement.to_string(),
+            ">= 3.1, < 3.5.dev, >= 3.2.0"
+        );
+
+        let installed = vec![ruby("ruby-3.1.7"), ruby("ruby-3.3.9"), ruby("ruby-3.5.0")];
+        let chosen = inferred.newest_satisfying(installed).unwrap();
+        assert_eq!(chosen.version.to_string(), "ruby-3.3.9");
+        assert!(
+            inferred
+                .newest_satisfying(vec![ruby("ruby-3.1.7")])
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_nothing_to_infer_without_a_cache() {
+        let cache = Cache::temp().unwrap();
+        let parsed = rv_lockfile::parse(LOCKFILE).unwrap();
+        assert_eq!(
+            InferredRuby::from_lockfile("Gemfile.lock".into(), &parsed, &cache),
+            None
+        );
+    }
+}
@@ -0,0 +1,408 @@
+//! Where rv finds the rubies it can install: rv's own builds on GitHub, unless the `ruby-source`
+//! setting points it at a mirror, like a directory index served over HTTPS or a directory on disk.
+
+use async_trait::async_trait;
+use camino::Utf8PathBuf;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use rv_cache::Cache;
+use rv_platform::HostPlatform;
+use rv_ruby::{Asset, Release};
+use tracing::debug;
+
+use super::ruby_fetcher::{Endpoint, Result, fetch_cached_release, read_cached_release};
+use super::rv_settings::RubySourceSetting;
+
+static HREF_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r#"href\s*=\s*"([^"]+)""#).unwrap());
+
+/// Where the archive of a Ruby is.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ArchiveLocation {
+    /// An archive to download.
+    Url(String),
+    /// A URL that redirects to the latest archive, for builds of Ruby's development branch.
+    LatestRedirect(String),
+    /// An archive on disk.
+    Path(Utf8PathBuf),
+}
+
+/// A place rubies can be installed from.
+#[async_trait]
+pub trait ReleaseSource: Send + Sync {
+    /// Every archive this source has, for any platform, as one release. Asset names are like
+    /// those of rv's own builds, e.g. `ruby-3.4.1.x86_64_linux.tar.gz`. With `refresh`, nothing
+    /// cached is used.
+    async fn release(&self, cache: &Cache, refresh: bool) -> Result<Release>;
+
+    /// What [`ReleaseSource::release`] found last time, for when rv is offline or fetching
+    /// the release fails.
+    fn cached_release(&self, cache: &Cache) -> Option<Release>;
+
+    /// Where the archive of `version` for `host` is.
+    fn archive(&self, version: &str, host: &HostPlatform) -> ArchiveLocation;
+}
+
+/// The source `settings` configure for `host`: the first one without an `os` or `arch` that
+/// doesn't match it, or rv's own builds if there's none.
+pub(crate) fn release_source(
+    settings: &[RubySourceSetting],
+    host: &HostPlatform,
+) -> Box<dyn ReleaseSource> {
+    let Some(setting) = configured_source(settings, host).map(|index| &settings[index]) else {
+        return Box::new(GithubReleases::for_host(host));
+    };
+    debug!("Using ruby source {}", setting.url);
+
+    let url = setting.url.as_str();
+    if url == "github" {
+        Box::new(GithubReleases::for_host(host))
+    } else if url.starts_with("https://") || url.starts_with("http://") {
+        Box::new(DirectoryIndex {
+            url: url.trim_end_matches('/').to_owned(),
+        })
+    } else {
+        let dir = url::Url::parse(url)
+            .ok()
+            .filter(|url| url.scheme() == "file")
+            .and_then(|url| url.to_file_path().ok())
+            .and_then(|path| Utf8PathBuf::from_path_buf(path).ok())
+            .unwrap_or_else(|| Utf8PathBuf::from(url));
+        Box::new(LocalMirror { dir })
+    }
+}
+
+/// The sources `settings` configure for every platform rv supports, each with the platforms it's
+/// the source for, so that a source several platforms share is only fetched once.
+pub(crate) fn release_sources(
+    settings: &[RubySourceSetting],
+) -> Vec<(Box<dyn ReleaseSource>, Vec<HostPlatform>)> {
+    let mut groups: Vec<((Option<usize>, bool), Vec<HostPlatform>)> = Vec::new();
+    for host in HostPlatform::all() {
+        let index = configured_source(settings, host);
+        // rv's own builds are one release for every OS but Windows, whose rubies come from
+        // RubyInstaller2. A mirror has the same archives for every platform.
+        let github = index.is_none_or(|index| settings[index].url == "github");
+        let key = (index, github && host.is_windows());
+        match groups.iter_mut().find(|(other, _)| *other == key) {
+            Some((_, hosts)) => hosts.push(*host),
+            None => groups.push((key, vec![*host])),
+        }
+    }
+    groups
+        .into_iter()
+        .map(|(_, hosts)| (release_source(settings, &hosts[0]), hosts))
+        .collect()
+}
+
+/// The index of the first of `settings` without an `os` or `arch` that doesn't match `host`.
+fn configured_source(settings: &[RubySourceSetting], host: &HostPlatform) -> Option<usize> {
+    settings.iter().position(|setting| {
+        setting.os.as_deref().is_none_or(|os| os == host.os())
+            && setting
+                .arch
+                .as_deref()
+                .is_none_or(|arch| arch == host.arch())
+    })
+}
+
+/// The archive name rv's own builds use, which mirrors use on every platform.
+fn archive_name(version: &str, host: &HostPlatform) -> String {
+    format!(
+        "ruby-{version}.{}.{}",
+        host.ruby_arch_str(),
+        host.archive_ext()
+    )
+}
+
+/// A release on GitHub: rv-ruby's latest release, which has every version (macOS/Linux), or
+/// RubyInstaller2's releases, one per version (Windows).
+pub struct GithubReleases {
+    endpoint: Endpoint,
+    /// The environment variable that overrides `default_url`, mostly for tests.
+    env_var: &'static str,
+    default_url: &'static str,
+}
+
+impl GithubReleases {
+    fn for_host(host: &HostPlatform) -> Self {
+        if host.is_windows() {
+            Self {
+                endpoint: Endpoint {
+                    cache_file: "rubyinstaller2.json",
+                    github: true,
+                    transform: |body| {
+                        let releases: Vec<Release> = serde_json::from_slice(&body)?;
+                        Ok(super::ruby_fetcher::combine_rubyinstaller2_releases(
+                            releases,
+                        ))
+                    },
+                },
+                env_var: "RV_WINDOWS_LIST_URL",
+                default_url: "https://api.github.com/repos/oneclick/rubyinstaller2/releases?per_page=100",
+            }
+        } else {
+            Self {
+                endpoint: Endpoint {
+                    cache_file: "available_rubies.json",
+                    github: true,
+                    transform: |body| Ok(serde_json::from_slice(&body)?),
+                },
+                env_var: "RV_LIST_URL",
+                default_url: "https://api.github.com/repos/spinel-coop/rv-ruby/releases/latest",
+            }
+        }
+    }
+
+    fn url(&self) -> String {
+        std::env::var(self.env_var).unwrap_or_else(|_| self.default_url.to_string())
+    }
+}
+
+#[async_trait]
+impl ReleaseSource for GithubReleases {
+    async fn release(&self, cache: &Cache, refresh: bool) -> Result<Release> {
+        let url = self.url();
+        if url == "-" {
+            debug!(
+                "{} is '-', returning empty list without network request.",
+                self.env_var
+            );
+            return Ok(Release {
+                name: "Empty release".to_owned(),
+                assets: Vec::new(),
+            });
+        }
+        fetch_cached_release(cache, self.endpoint, &url, refresh).await
+    }
+
+    fn cached_release(&self, cache: &Cache) -> Option<Release> {
+        read_cached_release(cache, self.endpoint.cache_file, &self.url()).map(|c| c.release)
+    }
+
+    fn archive(&self, version: &str, host: &HostPlatform) -> ArchiveLocation {
+        let url = rv_core::install::ruby_url(version, host);
+        if version == "dev" && !host.is_windows() {
+            ArchiveLocation::LatestRedirect(url)
+        } else {
+            ArchiveLocation::Url(url)
+        }
+    }
+}
+
+/// A directory of archives served over HTTP(S), listed in an HTML index page like the ones nginx,
+/// Apache and most static file servers generate.
+pub struct DirectoryIndex {
+    /// The URL of the directory, without a trailing slash.
+    url: String,
+}
+
+impl DirectoryIndex {
+    const ENDPOINT: Endpoint = Endpoint {
+        cache_file: "ruby_index.json",
+        github: false,
+        transform: |body| Ok(parse_index(&String::from_utf8_lossy(&body))),
+    };
+
+    fn index_url(&self) -> String {
+        format!("{}/", self.url)
+    }
+}
+
+#[async_trait]
+impl ReleaseSource for DirectoryIndex {
+    async fn release(&self, cache: &Cache, refresh: bool) -> Result<Release> {
+        fetch_cached_release(cache, Self::ENDPOINT, &self.index_url(), refresh).await
+    }
+
+    fn cached_release(&self, cache: &Cache) -> Option<Release> {
+        read_cached_release(cache, Self::ENDPOINT.cache_file, &self.index_url()).map(|c| c.release)
+    }
+
+    fn archive(&self, version: &str, host: &HostPlatform) -> ArchiveLocation {
+        ArchiveLocation::Url(format!("{}/{}", self.url, archive_name(version, host)))
+    }
+}
+
+/// The Ruby archives an index page links to.
+fn parse_index(html: &str) -> Release {
+    let assets = HREF_REGEX
+        .captures_iter(html)
+        .filter_map(|caps| {
+            let href = caps.get(1)?.as_str();
+            let name = href.rsplit('/').next()?;
+            name.starts_with("ruby-").then(|| Asset {
+                name: name.to_owned(),
+                browser_download_url: href.to_owned(),
+                digest: None,
+            })
+        })
+        .collect();
+    Release {
+        name: "Directory index".to_owned(),
+        assets,
+    }
+}
+
+/// A directory of archives on disk, like a network share.
+pub struct LocalMirror {
+    dir: Utf8PathBuf,
+}
+
+impl LocalMirror {
+    fn read_dir(&self) -> Result<Release> {
+        let mut assets = Vec::new();
+        for entry in self.dir.read_dir_utf8()? {
+            let entry = entry?;
+            if entry.file_name().starts_with("ruby-") && entry.file_type()?.is_file() {
+                assets.push(Asset {
+                    name: entry.file_name().to_owned(),
+                    browser_download_url: entry.path().to_string(),
+                    digest: None,
+                });
+            }
+        }
+        Ok(Release {
+            name: self.dir.to_string(),
+            assets,
+        })
+    }
+}
+
+#[async_trait]
+impl ReleaseSource for LocalMirror {
+    async fn release(&self, _cache: &Cache, _refresh: bool) -> Result<Release> {
+        self.read_dir()
+    }
+
+    // Reading the directory is as quick as reading a cache, and works offline.
+    fn cached_release(&self, _cache: &Cache) -> Option<Release> {
+        self.read_dir().ok()
+    }
+
+    fn archive(&self, version: &str, host: &HostPlatform) -> ArchiveLocation {
+        ArchiveLocation::Path(self.dir.join(archive_name(version, host)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setting(url: &str, os: Option<&str>, arch: Option<&str>) -> RubySourceSetting {
+        RubySourceSetting {
+            url: url.to_owned(),
+            os: os.map(str::to_owned),
+            arch: arch.map(str::to_owned),
+        }
+    }
+
+    #[test]
+    fn test_release_source_selection() {
+        let linux = HostPlatform::from_target_triple("x86_64-unknown-linux-gnu").unwrap();
+        let mac = HostPlatform::from_target_triple("aarch64-apple-darwin").unwrap();
+        let settings = [
+            setting("/mnt/rubies", Some("linux"), Some("x86_64")),
+            setting("https://rubies.example.com/builds/", Some("macos"), None),
+        ];
+
+        assert_eq!(
+            release_source(&settings, &linux).archive("3.4.1", &linux),
+            ArchiveLocation::Path(
+                Utf8PathBuf::from("/mnt/rubies").join("ruby-3.4.1.x86_64_linux.tar.gz")
+            )
+        );
+        assert_eq!(
+            release_source(&settings, &mac).archive("3.4.1", &mac),
+            ArchiveLocation::Url(
+                "https://rubies.example.com/builds/ruby-3.4.1.arm64_sonoma.tar.gz".into()
+            )
+        );
+
+        let arm_linux = HostPlatform::from_target_triple("aarch64-unknown-linux-gnu").unwrap();
+        assert!(matches!(
+            release_source(&settings, &arm_linux).archive("3.4.1", &arm_linux),
+            ArchiveLocation::Url(url) if url.contains("github.com")
+        ));
+    }
+
+    #[test]
+    fn test_release_sources() {
+        let platforms = |settings: &[RubySourceSetting]| -> Vec<Vec<&str>> {
+            release_sources(settings)
+                .into_iter()
+                .map(|(_, hosts)| hosts.iter().map(|host| host.target_triple()).collect())
+                .collect()
+        };
+
+        assert_eq!(
+            platforms(&[]),
+            [
+                vec![
+                    "aarch64-apple-darwin",
+                    "x86_64-apple-darwin",
+                    "x86_64-unknown-linux-gnu",
+                    "x86_64-unknown-linux-musl",
+                    "aarch64-unknown-linux-gnu",
+                    "aarch64-unknown-linux-musl",
+                ],
+                vec!["x86_64-pc-windows-msvc", "aarch64-pc-windows-msvc"],
+            ]
+        );
+        assert_eq!(
+            platforms(&[
+                setting("/mnt/rubies", Some("linux"), None),
+                setting("https://rubies.example.com/builds/", None, None),
+            ]),
+            [
+                vec![
+                    "aarch64-apple-darwin",
+                    "x86_64-apple-darwin",
+                    "x86_64-unknown-linux-musl",
+                    "aarch64-unknown-linux-musl",
+                    "x86_64-pc-windows-msvc",
+                    "aarch64-pc-windows-msvc",
+                ],
+                vec!["x86_64-unknown-linux-gnu", "aarch64-unknown-linux-gnu"],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_index() {
+        let html = r#"<html><body><h1>Index of /rubies/</h1>
+            <a href="../">../</a>
+            <a href="ruby-3.4.1.x86_64_linux.tar.gz">ruby-3.4.1.x86_64_linux.tar.gz</a>
+            <a href="/rubies/ruby-3.3.9.arm64_linux.tar.gz">ruby-3.3.9.arm64_linux.tar.gz</a>
+            <a href="README.txt">README.txt</a>
+        </body></html>"#;
+        let names: Vec<_> = parse_index(html)
+            .assets
+            .into_iter()
+            .map(|asset| asset.name)
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "ruby-3.4.1.x86_64_linux.tar.gz",
+                "ruby-3.3.9.arm64_linux.tar.gz"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_local_mirror() {
+        let dir = camino_tempfile::tempdir().unwrap();
+        fs_err::write(dir.path().join("ruby-3.4.1.x86_64_linux.tar.gz"), "").unwrap();
+        fs_err::write(dir.path().join("checksums.txt"), "").unwrap();
+
+        let mirror = LocalMirror {
+            dir: dir.path().to_owned(),
+        };
+        let release = mirror
+            .release(&Cache::temp().unwrap(), false)
+            .await
+            .unwrap();
+        assert_eq!(release.assets.len(), 1);
+        assert_eq!(release.assets[0].name, "ruby-3.4.1.x86_64_linux.tar.gz");
+    }
+}
@@ -1,9 +1,12 @@
 use std::{
+    collections::BTreeMap,
     io,
     time::{Duration, SystemTime},
 };
 
 use super::Config;
+use super::release_source::{ReleaseSource, release_source, release_sources};
+use super::ruby_lock::platform_key;
 use fs_err as fs;
 use once_cell::sync::Lazy;
 use regex::Regex;
@@ -11,13 +14,22 @@ use serde::{Deserialize, Serialize};
 use tracing::{debug, warn};
 
 use rv_platform::HostPlatform;
-use rv_ruby::{Asset, Release, RemoteRuby, request::RequestError, version::ParseVersionError};
+use rv_ruby::{
+    Asset, Release, RemoteRuby,
+    request::RequestError,
+    version::{ParseVersionError, RubyVersion},
+};
 
 // Use GitHub's TTL, but don't re-check more than every 60 seconds.
 const MINIMUM_CACHE_TTL: Duration = Duration::from_secs(60);
 
-static ARCH_REGEX: Lazy<Regex> =
-    Lazy::new(|| Regex::new(r"ruby-[\d\.a-z-]+\.(?P<arch>[a-zA-Z0-9_]+)\.(?:tar\.gz|7z)").unwrap());
+/// How long to wait for a stale cached release to be revalidated before using it anyway. The
+/// revalidation carries on in the background, and updates the cache if it finishes before rv does.
+const STALE_REVALIDATION_TIMEOUT: Duration = Duration::from_secs(2);
+
+static ARCH_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"ruby-[\d\.a-z-]+\.(?P<arch>[a-zA-Z0-9_]+)\.(?:tar\.gz|7z)$").unwrap()
+});
 
 static PARSE_MAX_AGE_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"max-age=(\d+)").unwrap());
 
@@ -30,39 +42,46 @@ static RUBYINSTALLER_REGEX: Lazy<Regex> =
 
 // Updated struct to hold ETag and calculated expiry time
 #[derive(Serialize, Deserialize, Debug)]
-struct CachedRelease {
+pub(super) struct CachedRelease {
     expires_at: SystemTime,
     etag: Option<String>,
-    release: Release,
+    pub(super) release: Release,
 }
 
 #[derive(Debug, thiserror::Error, miette::Diagnostic)]
 pub enum Error {
     #[error(transparent)]
+    #[diagnostic(code(RV0131))]
     SerdeJson(#[from] serde_json::Error),
     #[error(transparent)]
+    #[diagnostic(code(RV0132))]
     Io(#[from] std::io::Error),
     #[error(transparent)]
+    #[diagnostic(code(RV0133))]
     Request(#[from] RequestError),
-    #[error("Failed to fetch available ruby versions from GitHub")]
+    #[error("Failed to fetch available ruby versions")]
+    #[diagnostic(code(RV0134))]
     GithubRequest(#[from] reqwest::Error),
     #[error(transparent)]
+    #[diagnostic(code(RV0135))]
     ParseVersion(#[from] ParseVersionError),
 }
 
-type Result<T> = miette::Result<T, Error>;
+pub(super) type Result<T> = miette::Result<T, Error>;
 
 impl Config {
-    /// Discover all remotely available Ruby versions with caching.
-    ///
-    /// On Windows, fetches from `oneclick/rubyinstaller2` (one release per Ruby version).
-    /// On other platforms, fetches from `spinel-coop/rv-ruby` (all versions in one release).
-    pub async fn discover_remote_rubies(&self) -> Vec<RemoteRuby> {
-        if self.offline {
-            debug!("OFFLINE: skipping remote ruby fetch");
-            return vec![];
-        }
+    /// Where rubies are installed from on `host`.
+    pub(crate) fn release_source(&self, host: &HostPlatform) -> Box<dyn ReleaseSource> {
+        release_source(&self.rv_settings.ruby_source, host)
+    }
 
+    /// Discover all remotely available Ruby versions with caching. With `refresh`, the cached
+    /// list is revalidated even if it's fresh. Offline, the cached list is used however old it is.
+    ///
+    /// Unless the `ruby-source` setting says otherwise, fetches from `oneclick/rubyinstaller2` on
+    /// Windows (one release per Ruby version), and from `spinel-coop/rv-ruby` on other platforms
+    /// (all versions in one release).
+    pub async fn discover_remote_rubies(&self, refresh: bool) -> Vec<RemoteRuby> {
         // Detect host first — this decides which release source to query.
         let host = match HostPlatform::current() {
             Ok(h) => h,
@@ -72,25 +91,8 @@ impl Config {
             }
         };
 
-        let ((fetch_result, url), cache_file) = if host.is_windows() {
-            (
-                fetch_rubyinstaller2_rubies(&self.cache).await,
-                "rubyinstaller2.json",
-            )
-        } else {
-            (
-                fetch_available_rubies(&self.cache).await,
-                "available_rubies.json",
-            )
-        };
-
-        let release = match fetch_result {
-            Ok(release) => release,
-            Err(e) => {
-                warn!("Could not fetch available Ruby versions: {}", e);
-                stale_cache_fallback(&self.cache, cache_file, &url)
-            }
-        };
+        let source = self.release_source(&host);
+        let release = self.fetch_release(source.as_ref(), refresh).await;
 
         let desired_os = host.os();
         let desired_arch = host.arch();
@@ -112,61 +114,215 @@ impl Config {
 
         rubies
     }
+
+    /// Every remotely available Ruby version, with the platforms there's a build of it for, in
+    /// the order of [`HostPlatform::all`]. Fetches the list of rubies of every platform's source
+    /// the way [`Config::discover_remote_rubies`] fetches the host's.
+    pub async fn remote_ruby_platforms(
+        &self,
+        refresh: bool,
+    ) -> BTreeMap<RubyVersion, Vec<HostPlatform>> {
+        let mut platforms = BTreeMap::new();
+        for (source, hosts) in release_sources(&self.rv_settings.ruby_source) {
+            let release = self.fetch_release(source.as_ref(), refresh).await;
+            add_platforms(&mut platforms, &release, &hosts);
+        }
+        for hosts in platforms.values_mut() {
+            hosts.sort_by_key(|host| HostPlatform::all().iter().position(|other| other == host));
+        }
+        platforms
+    }
+
+    /// The SHA256 digests, as lowercase hex, that the sources of every platform publish for the
+    /// archives of `version`, by platform as `rv.lock` names them. Platforms without an archive of
+    /// `version`, or whose source publishes no digests, are left out.
+    pub async fn remote_ruby_digests(&self, version: &RubyVersion) -> BTreeMap<String, String> {
+        let mut digests = BTreeMap::new();
+        for (source, hosts) in release_sources(&self.rv_settings.ruby_source) {
+            let release = self.fetch_release(source.as_ref(), false).await;
+            add_digests(&mut digests, &release, &hosts, version);
+        }
+        digests
+    }
+
+    /// The release `source` has. Offline, or if fetching it fails, the cached one.
+    async fn fetch_release(&self, source: &dyn ReleaseSource, refresh: bool) -> Release {
+        if self.offline {
+            debug!("OFFLINE: using the cached list of rubies, if there is one");
+            return source
+                .cached_release(&self.cache)
+                .unwrap_or_else(empty_release);
+        }
+        match source.release(&self.cache, refresh).await {
+            Ok(release) => release,
+            Err(e) => {
+                warn!("Could not fetch available Ruby versions: {}", e);
+                stale_cache_fallback(&self.cache, source)
+            }
+        }
+    }
+}
+
+/// Record which of `hosts` each Ruby in `release` has a build for.
+fn add_platforms(
+    platforms: &mut BTreeMap<RubyVersion, Vec<HostPlatform>>,
+    release: &Release,
+    hosts: &[HostPlatform],
+) {
+    for ruby in release
+        .assets
+        .iter()
+        .filter_map(|asset| ruby_from_asset(asset).ok())
+    {
+        let Some(host) = hosts
+            .iter()
+            .find(|host| host.os() == ruby.os && host.arch() == ruby.arch)
+        else {
+            continue;
+        };
+        let hosts = platforms.entry(ruby.version).or_default();
+        if !hosts.contains(host) {
+            hosts.push(*host);
+        }
+    }
+}
+
+/// Record the SHA256 digests `release` has for the archives of `version` for each of `hosts`.
+fn add_digests(
+    digests: &mut BTreeMap<String, String>,
+    release: &Release,
+    hosts: &[HostPlatform],
+    version: &RubyVersion,
+) {
+    for asset in &release.assets {
+        let Some(sha256) = asset
+            .digest
+            .as_deref()
+            .and_then(|digest| digest.strip_prefix("sha256:"))
+        else {
+            continue;
+        };
+        let Ok(ruby) = ruby_from_asset(asset) else {
+            continue;
+        };
+        if ruby.version != *version {
+            continue;
+        }
+        if let Some(host) = hosts
+            .iter()
+            .find(|host| host.os() == ruby.os && host.arch() == ruby.arch)
+        {
+            digests.insert(platform_key(host), sha256.to_ascii_lowercase());
+        }
+    }
 }
 
 fn cache_key_for(url: &str, cache_file: &str) -> String {
     rv_cache::cache_digest(format!("{}-{}", url, cache_file))
 }
 
-fn url_for(env_var: &str, default_url: &str) -> String {
-    std::env::var(env_var).unwrap_or_else(|_| default_url.to_string())
+/// An HTTP endpoint that lists rubies, whose response is cached with its ETag.
+#[derive(Clone, Copy)]
+pub(super) struct Endpoint {
+    /// The file name the release is cached under.
+    pub(super) cache_file: &'static str,
+    /// Whether the endpoint is GitHub's API, which gets GitHub's headers and token.
+    pub(super) github: bool,
+    /// Converts the raw response body into a `Release`.
+    pub(super) transform: fn(bytes::Bytes) -> Result<Release>,
 }
 
-/// Fetches a GitHub releases endpoint with ETag/TTL caching.
-///
-/// The `transform` closure converts the raw JSON response body into a `Release`.
-/// For rv-ruby this is identity (response is already a single `Release`).
-/// For RubyInstaller2 this combines a `Vec<Release>` into one synthetic `Release`.
-async fn fetch_cached_github_release(
+fn empty_release() -> Release {
+    Release {
+        name: "Empty".to_owned(),
+        assets: Vec::new(),
+    }
+}
+
+fn cache_entry_for(cache: &rv_cache::Cache, cache_file: &str, url: &str) -> rv_cache::CacheEntry {
+    let cache_key = cache_key_for(url, cache_file);
+    cache.entry(rv_cache::CacheBucket::Ruby, "releases", cache_key)
+}
+
+/// The cached release from `url`, however old it is.
+pub(super) fn read_cached_release(
     cache: &rv_cache::Cache,
     cache_file: &str,
-    env_var: &str,
     url: &str,
-    transform: impl FnOnce(bytes::Bytes) -> Result<Release>,
-) -> Result<Release> {
-    let client = reqwest::Client::new();
-
-    if url == "-" {
-        debug!("{env_var} is '-', returning empty list without network request.");
-        return Ok(Release {
-            name: "Empty release".to_owned(),
-            assets: Vec::new(),
-        });
-    }
+) -> Option<CachedRelease> {
+    let content = fs::read_to_string(cache_entry_for(cache, cache_file, url).path()).ok()?;
+    serde_json::from_str(&content).ok()
+}
 
-    let cache_key = cache_key_for(url, cache_file);
-    let cache_entry = cache.entry(rv_cache::CacheBucket::Ruby, "releases", cache_key);
+/// Fetches a release from `url` with ETag/TTL caching.
+///
+/// A fresh cached release is used as it is, unless `refresh` is set. A stale one is revalidated
+/// with the server, but if that takes too long the stale release is used while the revalidation
+/// finishes in the background.
+pub(super) async fn fetch_cached_release(
+    cache: &rv_cache::Cache,
+    endpoint: Endpoint,
+    url: &str,
+    refresh: bool,
+) -> Result<Release> {
+    let cache_file = endpoint.cache_file;
 
     // 1. Try to read from the disk cache.
-    let cached_data: Option<CachedRelease> =
-        if let Ok(content) = fs::read_to_string(cache_entry.path()) {
-            serde_json::from_str(&content).ok()
-        } else {
-            None
-        };
+    let cached_data = read_cached_release(cache, cache_file, url);
 
     // 2. If we have fresh cached data, use it immediately.
-    if let Some(cache) = &cached_data {
-        if SystemTime::now() < cache.expires_at {
+    let stale_release = match &cached_data {
+        Some(_) if refresh => {
+            debug!("Refreshing {cache_file}, re-validating with server.");
+            None
+        }
+        Some(cached) if SystemTime::now() < cached.expires_at => {
             debug!("Using cached release data from {cache_file}.");
-            return Ok(cache.release.clone());
+            rv_cache::record_hit(rv_cache::CacheBucket::Ruby);
+            return Ok(cached.release.clone());
         }
-        debug!("Cache {cache_file} is stale, re-validating with server.");
-    }
+        Some(cached) => {
+            debug!("Cache {cache_file} is stale, re-validating with server.");
+            Some(cached.release.clone())
+        }
+        None => None,
+    };
 
     // 3. Cache is stale or missing.
+    rv_cache::record_miss(rv_cache::CacheBucket::Ruby);
+    let cache_path = cache_entry_for(cache, cache_file, url).into_path_buf();
+    let revalidation = revalidate_release(endpoint, url.to_owned(), cache_path, cached_data);
+    let Some(stale_release) = stale_release else {
+        return revalidation.await;
+    };
+
+    let mut revalidation = tokio::spawn(revalidation);
+    match tokio::time::timeout(STALE_REVALIDATION_TIMEOUT, &mut revalidation).await {
+        Ok(Ok(result)) => result,
+        Ok(Err(join_error)) => Err(io::Error::other(join_error).into()),
+        Err(_) => {
+            debug!("Re-validating {cache_file} is slow, using the stale copy meanwhile.");
+            Ok(stale_release)
+        }
+    }
+}
+
+/// Asks the server whether the release has changed since it was cached, with the cached ETag,
+/// and updates the cache at `cache_path` with the answer.
+async fn revalidate_release(
+    endpoint: Endpoint,
+    url: String,
+    cache_path: camino::Utf8PathBuf,
+    cached_data: Option<CachedRelease>,
+) -> Result<Release> {
+    let cache_file = endpoint.cache_file;
+    let client = rv_client::http_client::rv_http_client("ruby_list")?;
     let etag = cached_data.as_ref().and_then(|c| c.etag.clone());
-    let mut request_builder = super::github::github_api_get(&client, url);
+    let mut request_builder = if endpoint.github {
+        super::github::github_api_get(&client, &url)
+    } else {
+        client.get(&url).header("User-Agent", "rv-cli")
+    };
 
     if let Some(etag) = &etag {
         debug!("Using ETag for conditional request: {}", etag);
@@ -189,7 +345,7 @@ async fn fetch_cached_github_release(
                 .unwrap_or(Duration::from_secs(60));
 
             stale_cache.expires_at = SystemTime::now() + max_age.max(MINIMUM_CACHE_TTL);
-            fs::write(cache_entry.path(), serde_json::to_string(&stale_cache)?)?;
+            rv_cache::write_atomic(&cache_path, serde_json::to_string(&stale_cache)?)?;
             Ok(stale_cache.release)
         }
         reqwest::StatusCode::OK => {
@@ -207,7 +363,7 @@ async fn fetch_cached_github_release(
                 .unwrap_or(Duration::from_secs(60));
 
             let body = response.bytes().await?;
-            let release = transform(body)?;
+            let release = (endpoint.transform)(body)?;
 
             let new_cache_entry = CachedRelease {
                 expires_at: SystemTime::now() + max_age.max(MINIMUM_CACHE_TTL),
@@ -215,10 +371,7 @@ async fn fetch_cached_github_release(
                 release: release.clone(),
             };
 
-            if let Some(parent) = cache_entry.path().parent() {
-                fs::create_dir_all(parent)?;
-            }
-            fs::write(cache_entry.path(), serde_json::to_string(&new_cache_entry)?)?;
+            rv_cache::write_atomic(&cache_path, serde_json::to_string(&new_cache_entry)?)?;
 
             Ok(release)
         }
@@ -229,47 +382,13 @@ async fn fetch_cached_github_release(
     }
 }
 
-/// Fetches available rubies from rv-ruby (macOS/Linux).
-async fn fetch_available_rubies(cache: &rv_cache::Cache) -> (Result<Release>, String) {
-    let env_var = "RV_LIST_URL";
-    let default_url = "https://api.github.com/repos/spinel-coop/rv-ruby/releases/latest";
-    let url = url_for(env_var, default_url);
-    let release =
-        fetch_cached_github_release(cache, "available_rubies.json", env_var, &url, |body| {
-            Ok(serde_json::from_slice(&body)?)
-        })
-        .await;
-    (release, url)
-}
-
-/// Fetches available rubies from RubyInstaller2 (Windows).
-async fn fetch_rubyinstaller2_rubies(cache: &rv_cache::Cache) -> (Result<Release>, String) {
-    let env_var = "RV_WINDOWS_LIST_URL";
-    let default_url = "https://api.github.com/repos/oneclick/rubyinstaller2/releases?per_page=100";
-    let url = url_for(env_var, default_url);
-    let release =
-        fetch_cached_github_release(cache, "rubyinstaller2.json", env_var, &url, |body| {
-            let releases: Vec<Release> = serde_json::from_slice(&body)?;
-            Ok(combine_rubyinstaller2_releases(releases))
-        })
-        .await;
-    (release, url)
-}
-
 /// Falls back to a stale cache file when a fresh fetch fails.
-fn stale_cache_fallback(cache: &rv_cache::Cache, cache_file: &str, url: &str) -> Release {
-    let cache_key = cache_key_for(url, cache_file);
-    let cache_entry = cache.entry(rv_cache::CacheBucket::Ruby, "releases", cache_key);
-    if let Ok(content) = fs::read_to_string(cache_entry.path())
-        && let Ok(cached_data) = serde_json::from_str::<CachedRelease>(&content)
-    {
+fn stale_cache_fallback(cache: &rv_cache::Cache, source: &dyn ReleaseSource) -> Release {
+    if let Some(release) = source.cached_release(cache) {
         warn!("Displaying stale list of available rubies from cache.");
-        cached_data.release
+        release
     } else {
-        Release {
-            name: "Empty".to_owned(),
-            assets: Vec::new(),
-        }
+        empty_release()
     }
 }
 
@@ -284,7 +403,7 @@ fn stale_cache_fallback(cache: &rv_cache::Cache, cache_file: &str, url: &str) ->
 ///
 /// The normalized names are designed to match the existing `ARCH_REGEX`, so
 /// `ruby_from_asset()` and the platform filtering pipeline work unchanged.
-fn combine_rubyinstaller2_releases(releases: Vec<Release>) -> Release {
+pub(super) fn combine_rubyinstaller2_releases(releases: Vec<Release>) -> Release {
     use std::collections::HashMap;
 
     // Key: (version, arch), Value: (revision, normalized Asset)
@@ -314,6 +433,7 @@ fn combine_rubyinstaller2_releases(releases: Vec<Release>) -> Release {
                 let normalized_asset = Asset {
                     name: normalized_name,
                     browser_download_url: asset.browser_download_url.clone(),
+                    digest: asset.digest.clone(),
                 };
 
                 match best.entry(key) {
@@ -426,6 +546,7 @@ mod tests {
             let asset = Asset {
                 name: filename.to_owned(),
                 browser_download_url: format!("https://example.com/{filename}"),
+                digest: None,
             };
             let ruby = ruby_from_asset(&asset).unwrap();
             assert_eq!(ruby.os, expected_os, "Wrong OS for {filename}");
@@ -439,6 +560,7 @@ mod tests {
         let asset = Asset {
             name: "ruby-3.3.0.sparc_solaris.tar.gz".to_owned(),
             browser_download_url: "https://example.com/ruby-3.3.0.sparc_solaris.tar.gz".to_owned(),
+            digest: None,
         };
         let ruby = ruby_from_asset(&asset).unwrap();
         assert_eq!(ruby.os, "unknown");
@@ -446,10 +568,95 @@ mod tests {
         assert_eq!(ruby.version.major, 3);
     }
 
+    #[test]
+    fn test_ruby_from_asset_skips_patches() {
+        // Patches between releases, for delta downloads, aren't rubies themselves.
+        let asset = make_asset("ruby-3.4.2.x86_64_linux.tar.gz.from-3.4.1.zst");
+        assert!(ruby_from_asset(&asset).is_err());
+    }
+
+    #[test]
+    fn test_add_platforms() {
+        let mut platforms = BTreeMap::new();
+        let release = make_release(
+            "latest",
+            &[
+                "ruby-3.4.1.arm64_sonoma.tar.gz",
+                "ruby-3.4.1.x86_64_linux.tar.gz",
+                "ruby-3.4.1.x86_64_linux_musl.tar.gz",
+                "ruby-3.3.9.x86_64_linux.tar.gz",
+                "ruby-3.3.9.x64.7z",
+                "checksums.txt",
+            ],
+        );
+        add_platforms(
+            &mut platforms,
+            &release,
+            &[HostPlatform::MacosAarch64, HostPlatform::LinuxX86_64],
+        );
+        add_platforms(
+            &mut platforms,
+            &make_release("RubyInstaller2", &["ruby-3.4.1.x64.7z"]),
+            &[HostPlatform::WindowsX86_64],
+        );
+
+        let version = |version: &str| version.parse::<RubyVersion>().unwrap();
+        assert_eq!(
+            platforms,
+            BTreeMap::from([
+                (version("ruby-3.3.9"), vec![HostPlatform::LinuxX86_64]),
+                (
+                    version("ruby-3.4.1"),
+                    vec![
+                        HostPlatform::MacosAarch64,
+                        HostPlatform::LinuxX86_64,
+                        HostPlatform::WindowsX86_64
+                    ]
+                ),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_add_digests() {
+        let mut release = make_release(
+            "latest",
+            &[
+                "ruby-3.4.1.arm64_sonoma.tar.gz",
+                "ruby-3.4.1.x86_64_linux.tar.gz",
+                "ruby-3.4.1.arm64_linux.tar.gz",
+                "ruby-3.3.9.arm64_sonoma.tar.gz",
+            ],
+        );
+        release.assets[0].digest = Some("sha256:AA11".to_owned());
+        release.assets[1].digest = Some("sha256:bb22".to_owned());
+        release.assets[3].digest = Some("sha256:cc33".to_owned());
+
+        let mut digests = BTreeMap::new();
+        add_digests(
+            &mut digests,
+            &release,
+            &[
+                HostPlatform::MacosAarch64,
+                HostPlatform::LinuxX86_64,
+                HostPlatform::LinuxAarch64,
+            ],
+            &"ruby-3.4.1".parse().unwrap(),
+        );
+        assert_eq!(
+            digests,
+            BTreeMap::from([
+                ("linux-x86_64".to_owned(), "bb22".to_owned()),
+                ("macos-aarch64".to_owned(), "aa11".to_owned()),
+            ])
+        );
+    }
+
     fn make_asset(name: &str) -> Asset {
         Asset {
             name: name.to_string(),
             browser_download_url: format!("https://github.com/download/{name}"),
+            digest: None,
         }
     }
 
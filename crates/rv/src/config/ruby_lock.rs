@@ -0,0 +1,110 @@
+//! `rv.lock`, which pins a project to one exact Ruby release. It's written next to the file that
+//! pins the project's Ruby, by `rv ruby pin --lock`, and records the version the pin resolved to
+//! and the SHA256 digest of that version's archive for every platform that has one, so that
+//! `rv ruby install` installs the very same archive on every developer's machine and CI runner,
+//! and refuses one that's different.
+
+use std::collections::BTreeMap;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use rv_platform::HostPlatform;
+use rv_ruby::version::RubyVersion;
+use serde::{Deserialize, Serialize};
+
+use super::{Config, Error, RequestedRuby, Result};
+
+/// The name of the lock file, in the directory of the project's version file.
+pub const FILE_NAME: &str = "rv.lock";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RubyLock {
+    /// The exact version the project's Ruby is locked to, like `ruby-3.4.1`.
+    pub ruby: RubyVersion,
+    /// The SHA256 digest of the archive of `ruby`, as lowercase hex, by platform, like
+    /// `linux-musl-x86_64`. Platforms whose archives have no published digest are left out.
+    pub sha256: BTreeMap<String, String>,
+}
+
+impl RubyLock {
+    /// The lock in `dir`, if there is one.
+    pub fn read(dir: &Utf8Path) -> Result<Option<Self>> {
+        let path = dir.join(FILE_NAME);
+        let text = match fs_err::read_to_string(&path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        serde_json::from_str(&text)
+            .map(Some)
+            .map_err(|source| Error::InvalidRubyLock { path, source })
+    }
+
+    /// Write this lock into `dir`, and return the lock file's path. The lock is written to a
+    /// temporary file and renamed into place, so a failed write never leaves half a lock behind.
+    pub fn write(&self, dir: &Utf8Path) -> Result<Utf8PathBuf> {
+        let path = dir.join(FILE_NAME);
+        let mut json = serde_json::to_string_pretty(self).map_err(std::io::Error::from)?;
+        json.push('\n');
+        rv_cache::write_atomic(&path, json)?;
+        Ok(path)
+    }
+
+    /// The digest the archive for `host` must have, if the lock has one.
+    pub fn sha256_for(&self, host: &HostPlatform) -> Option<&str> {
+        self.sha256.get(&platform_key(host)).map(String::as_str)
+    }
+}
+
+/// How `rv.lock` names `host`, like `macos-aarch64`.
+pub fn platform_key(host: &HostPlatform) -> String {
+    format!("{}-{}", host.os(), host.arch())
+}
+
+impl Config {
+    /// The directory the project's Ruby is pinned in, which is where its `rv.lock` goes. None
+    /// when the Ruby isn't pinned by the project.
+    pub(crate) fn ruby_lock_dir(&self) -> Option<Utf8PathBuf> {
+        match &self.requested_ruby {
+            RequestedRuby::Project((_, source)) => source.path().parent().map(Utf8Path::to_owned),
+            _ => None,
+        }
+    }
+
+    /// The project's `rv.lock`, if it has one, and the directory it's in.
+    pub(crate) fn ruby_lock(&self) -> Result<Option<(Utf8PathBuf, RubyLock)>> {
+        let Some(dir) = self.ruby_lock_dir() else {
+            return Ok(None);
+        };
+        Ok(RubyLock::read(&dir)?.map(|lock| (dir, lock)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let dir = camino_tempfile::tempdir().unwrap();
+        assert_eq!(RubyLock::read(dir.path()).unwrap(), None);
+
+        let lock = RubyLock {
+            ruby: "ruby-3.4.1".parse().unwrap(),
+            sha256: BTreeMap::from([
+                ("linux-x86_64".to_owned(), "aa11".to_owned()),
+                ("macos-aarch64".to_owned(), "bb22".to_owned()),
+            ]),
+        };
+        let path = lock.write(dir.path()).unwrap();
+        assert_eq!(path, dir.path().join(FILE_NAME));
+        assert_eq!(RubyLock::read(dir.path()).unwrap(), Some(lock.clone()));
+        assert_eq!(lock.sha256_for(&HostPlatform::MacosAarch64), Some("bb22"));
+        assert_eq!(lock.sha256_for(&HostPlatform::LinuxMuslX86_64), None);
+
+        fs_err::write(&path, "ruby 3.4.1").unwrap();
+        assert!(matches!(
+            RubyLock::read(dir.path()),
+            Err(Error::InvalidRubyLock { .. })
+        ));
+    }
+}
@@ -1,21 +1,31 @@
 use crate::GlobalArgs;
-use camino::Utf8PathBuf;
+use camino::{Utf8Path, Utf8PathBuf};
 use config::{
     Config as ConfigRs, Environment, File, FileStoredFormat, Format, Map, Value, ValueKind,
 };
+use glob::Pattern;
+use rv_client::pin::CertificatePin;
+use rv_client::rate_limit::Rate;
+use rv_core::request::VersionFile;
+use rv_ruby::request::RubyRequest;
+use rv_ruby::version::RubyVersion;
 
 #[derive(Debug, thiserror::Error, miette::Diagnostic)]
 pub enum Error {
     #[error("Multiple config files found: {0:?}")]
+    #[diagnostic(code(RV0111))]
     MultipleConfigFiles(Vec<String>),
 
     #[error("Error building configuration: {0}")]
+    #[diagnostic(code(RV0112))]
     BuildError(String),
 
     #[error("Failed to deserialize configuration: {0}")]
+    #[diagnostic(code(RV0113))]
     DeserializationError(String),
 
     #[error("{} is not a valid value for {}", value, setting)]
+    #[diagnostic(code(RV0114))]
     SettingsValidationError { value: String, setting: String },
 }
 
@@ -25,8 +35,205 @@ type Result<T> = miette::Result<T, Error>;
 pub struct RvSettings {
     pub install_path: Option<String>,
 
+    pub ruby_install_dir: Option<String>,
+
     #[serde(default = "default_update_mode")]
     pub update_mode: String,
+
+    #[serde(default)]
+    pub isolation: Isolation,
+
+    pub build_definitions: Option<String>,
+
+    pub update_rubygems: Option<String>,
+
+    pub bundler_version: Option<String>,
+
+    #[serde(default, deserialize_with = "deserialize_default_gems")]
+    pub default_gems: Vec<DefaultGemsSetting>,
+
+    #[serde(default, deserialize_with = "deserialize_path_list")]
+    pub ruby_dirs: Vec<String>,
+
+    #[serde(default, deserialize_with = "deserialize_ruby_sources")]
+    pub ruby_source: Vec<RubySourceSetting>,
+
+    #[serde(default, deserialize_with = "deserialize_path_list")]
+    pub version_files: Vec<String>,
+
+    #[serde(default)]
+    pub hooks: Vec<HookSetting>,
+
+    pub credential_helper: Option<String>,
+
+    #[serde(default)]
+    pub certificate_pins: Vec<CertificatePinSetting>,
+
+    pub limit_rate: Option<String>,
+
+    pub dir_depth: Option<String>,
+
+    pub remote_cache: Option<String>,
+
+    pub remote_cache_endpoint: Option<String>,
+
+    pub remote_cache_region: Option<String>,
+
+    pub remote_cache_mode: Option<String>,
+}
+
+/// A point in the installation of each gem where `rv ci` can run a hook.
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum HookEvent {
+    /// Before the gem is downloaded. A failing hook stops the install before anything is fetched.
+    PreDownload,
+    /// After the gem's files are unpacked into its directory.
+    PostUnpack,
+    /// After the gem's native extensions are built, for gems that have any.
+    PostCompile,
+    /// Once everything is installed, for every gem that was.
+    PostInstall,
+}
+
+impl HookEvent {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::PreDownload => "pre-download",
+            Self::PostUnpack => "post-unpack",
+            Self::PostCompile => "post-compile",
+            Self::PostInstall => "post-install",
+        }
+    }
+}
+
+impl std::fmt::Display for HookEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A shell command to run at `event`, from a `hook` entry of `rv.kdl`.
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct HookSetting {
+    pub event: HookEvent,
+    pub command: String,
+}
+
+/// A certificate or public key that the TLS certificates of `host` must include, from a
+/// `certificate-pin` entry of `rv.kdl`.
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct CertificatePinSetting {
+    pub host: String,
+    pub pin: String,
+}
+
+/// Gems to install into every new Ruby, or only into the ones `ruby` matches, from a
+/// `default-gems` entry of `rv.kdl`.
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct DefaultGemsSetting {
+    /// Gem names, each optionally followed by a version requirement, like `rubocop ~> 1.65`.
+    pub gems: Vec<String>,
+    /// A Ruby request, like `3.3` or `< 3.2`, that the entry only applies to.
+    pub ruby: Option<String>,
+}
+
+/// Where to install rubies from, on the platforms that match `os` and `arch`.
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct RubySourceSetting {
+    /// `github` for rv's own builds, the URL of a directory index, or the path of a directory.
+    pub url: String,
+    /// Only use this source on this OS, like `linux` or `macos`.
+    pub os: Option<String>,
+    /// Only use this source on this architecture, like `x86_64` or `aarch64`.
+    pub arch: Option<String>,
+}
+
+/// The `ruby-source` entries of `rv.kdl`, or the one source in an environment variable, which is
+/// used on every platform.
+fn deserialize_ruby_sources<'de, D>(
+    deserializer: D,
+) -> std::result::Result<Vec<RubySourceSetting>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(serde::Deserialize)]
+    #[serde(untagged)]
+    enum Sources {
+        One(String),
+        Many(Vec<RubySourceSetting>),
+    }
+
+    Ok(match serde::Deserialize::deserialize(deserializer)? {
+        Sources::One(url) => vec![RubySourceSetting {
+            url,
+            os: None,
+            arch: None,
+        }],
+        Sources::Many(sources) => sources,
+    })
+}
+
+/// The `default-gems` entries of `rv.kdl`, or one list of gems for every Ruby in an environment
+/// variable, where they are separated by commas.
+fn deserialize_default_gems<'de, D>(
+    deserializer: D,
+) -> std::result::Result<Vec<DefaultGemsSetting>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(serde::Deserialize)]
+    #[serde(untagged)]
+    enum DefaultGems {
+        One(String),
+        Many(Vec<DefaultGemsSetting>),
+    }
+
+    Ok(match serde::Deserialize::deserialize(deserializer)? {
+        DefaultGems::One(gems) => vec![DefaultGemsSetting {
+            gems: gems
+                .split(',')
+                .map(str::trim)
+                .filter(|gem| !gem.is_empty())
+                .map(str::to_owned)
+                .collect(),
+            ruby: None,
+        }],
+        DefaultGems::Many(entries) => entries,
+    })
+}
+
+/// A list of paths, either from a list in `rv.kdl` or from an environment variable, where they
+/// are separated like in `PATH`.
+fn deserialize_path_list<'de, D>(deserializer: D) -> std::result::Result<Vec<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(serde::Deserialize)]
+    #[serde(untagged)]
+    enum PathList {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    Ok(match serde::Deserialize::deserialize(deserializer)? {
+        PathList::One(paths) => std::env::split_paths(&paths)
+            .filter_map(|path| path.into_os_string().into_string().ok())
+            .filter(|path| !path.is_empty())
+            .collect(),
+        PathList::Many(paths) => paths,
+    })
+}
+
+/// Where gems are installed for a project.
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Isolation {
+    /// Gems go in the Ruby's shared gem home, used by every project on that Ruby.
+    #[default]
+    Shared,
+    /// Each project gets its own gem home, in `<project>/.rv/gems`.
+    Project,
 }
 
 fn default_update_mode() -> String {
@@ -54,7 +261,34 @@ impl Format for RvSettingsFormat {
             .children()
             .ok_or("Missing children in 'rv' node")?;
 
-        const ALLOWED_KEYS: &[&str] = &["install-path", "update-mode"];
+        const ALLOWED_KEYS: &[&str] = &[
+            "install-path",
+            "ruby-install-dir",
+            "update-mode",
+            "isolation",
+            "build-definitions",
+            "update-rubygems",
+            "bundler-version",
+            "default-gems",
+            "ruby-dirs",
+            "ruby-source",
+            "version-files",
+            "hook",
+            "credential-helper",
+            "certificate-pin",
+            "limit-rate",
+            "dir-depth",
+            "remote-cache",
+            "remote-cache-endpoint",
+            "remote-cache-region",
+            "remote-cache-mode",
+        ];
+
+        // Keys that take any number of arguments, rather than just one.
+        const LIST_KEYS: &[&str] = &["ruby-dirs", "version-files"];
+
+        // Keys that can be given once per platform, with `os` and `arch` properties.
+        const PLATFORM_KEYS: &[&str] = &["ruby-source"];
 
         let mut map = Map::new();
 
@@ -69,25 +303,113 @@ impl Format for RvSettingsFormat {
                 return Err(format!("The key '{}' expects argument(s)", key).into());
             }
 
-            // this logic works just for the first argument. If we need to support Arrays then it
-            // will require an update
-            let entry = node.entry(0).unwrap();
-
-            let value_str = match entry.value() {
+            let value_str = |entry: &kdl::KdlEntry| match entry.value() {
                 kdl::KdlValue::String(s) => s.clone(),
                 other => other.to_string(),
             };
 
-            map.insert(
-                key.to_string().replace("-", "_"),
-                Value::new(None, ValueKind::String(value_str)),
-            );
+            let value = if PLATFORM_KEYS.contains(&key) {
+                let url = node
+                    .entry(0)
+                    .ok_or_else(|| format!("The key '{}' expects a URL or a path", key))?;
+                let mut table = Map::new();
+                table.insert(
+                    "url".to_owned(),
+                    Value::new(None, ValueKind::String(value_str(url))),
+                );
+                for property in ["os", "arch"] {
+                    if let Some(entry) = node.entry(property) {
+                        table.insert(
+                            property.to_owned(),
+                            Value::new(None, ValueKind::String(value_str(entry))),
+                        );
+                    }
+                }
+
+                push_table(&mut map, &key.replace("-", "_"), table);
+                continue;
+            } else if key == "hook" {
+                // `hook "post-install" "./script/scan-gem"`, which can be given more than once.
+                let (Some(event), Some(command)) = (node.entry(0), node.entry(1)) else {
+                    return Err("The key 'hook' expects an event and a command".into());
+                };
+                let mut table = Map::new();
+                table.insert(
+                    "event".to_owned(),
+                    Value::new(None, ValueKind::String(value_str(event))),
+                );
+                table.insert(
+                    "command".to_owned(),
+                    Value::new(None, ValueKind::String(value_str(command))),
+                );
+                push_table(&mut map, "hooks", table);
+                continue;
+            } else if key == "certificate-pin" {
+                // `certificate-pin "gems.example.com" "sha256/…"`, once per pin.
+                let (Some(host), Some(pin)) = (node.entry(0), node.entry(1)) else {
+                    return Err("The key 'certificate-pin' expects a host and a pin".into());
+                };
+                let mut table = Map::new();
+                table.insert(
+                    "host".to_owned(),
+                    Value::new(None, ValueKind::String(value_str(host))),
+                );
+                table.insert(
+                    "pin".to_owned(),
+                    Value::new(None, ValueKind::String(value_str(pin))),
+                );
+                push_table(&mut map, "certificate_pins", table);
+                continue;
+            } else if key == "default-gems" {
+                // `default-gems "pry" "rubocop ~> 1.65"`, optionally with `ruby="3.3"`, which can
+                // be given once for every Ruby and once per `ruby`.
+                let gems = node
+                    .entries()
+                    .iter()
+                    .filter(|entry| entry.name().is_none())
+                    .map(|entry| Value::new(None, ValueKind::String(value_str(entry))))
+                    .collect();
+                let mut table = Map::new();
+                table.insert("gems".to_owned(), Value::new(None, ValueKind::Array(gems)));
+                if let Some(ruby) = node.entry("ruby") {
+                    table.insert(
+                        "ruby".to_owned(),
+                        Value::new(None, ValueKind::String(value_str(ruby))),
+                    );
+                }
+                push_table(&mut map, "default_gems", table);
+                continue;
+            } else if LIST_KEYS.contains(&key) {
+                let values = node
+                    .entries()
+                    .iter()
+                    .map(|entry| Value::new(None, ValueKind::String(value_str(entry))))
+                    .collect();
+                ValueKind::Array(values)
+            } else {
+                ValueKind::String(value_str(node.entry(0).unwrap()))
+            };
+
+            map.insert(key.to_string().replace("-", "_"), Value::new(None, value));
         }
 
         Ok(map)
     }
 }
 
+/// Add `table` to the list of tables that the repeated nodes of `key` are collected in.
+fn push_table(map: &mut Map<String, Value>, key: &str, table: Map<String, Value>) {
+    let mut entries = match map.remove(key) {
+        Some(Value {
+            kind: ValueKind::Array(entries),
+            ..
+        }) => entries,
+        _ => Vec::new(),
+    };
+    entries.push(Value::new(None, ValueKind::Table(table)));
+    map.insert(key.to_owned(), Value::new(None, ValueKind::Array(entries)));
+}
+
 impl FileStoredFormat for RvSettingsFormat {
     fn file_extensions(&self) -> &'static [&'static str] {
         &["kdl"]
@@ -189,6 +511,144 @@ impl RvSettings {
             .as_ref()
             .map(|s| Utf8PathBuf::from(s.as_str()))
     }
+
+    pub fn ruby_install_dir_as_utf8pathbuf(&self) -> Option<Utf8PathBuf> {
+        self.ruby_install_dir
+            .as_ref()
+            .map(|s| Utf8PathBuf::from(s.as_str()))
+    }
+
+    /// The directory of definitions for building rubies from source, `build-definitions` in
+    /// rv's config directory unless the setting says otherwise.
+    pub fn build_definitions_dir(&self, root: &Utf8Path) -> Utf8PathBuf {
+        match &self.build_definitions {
+            Some(dir) => Utf8PathBuf::from(dir),
+            None => rv_dirs::user_config_dir(root).join("build-definitions"),
+        }
+    }
+
+    /// The directories `ruby-dirs` adds to the ones rv looks for rubies in, with `~` and glob
+    /// patterns expanded, and the patterns of the rubies it excludes, which start with `!`.
+    pub fn ruby_dir_patterns(
+        &self,
+        home_dir: &Utf8Path,
+    ) -> Result<(Vec<Utf8PathBuf>, Vec<Pattern>)> {
+        let mut included = Vec::new();
+        let mut excluded = Vec::new();
+
+        for entry in &self.ruby_dirs {
+            let (negated, path) = match entry.strip_prefix('!') {
+                Some(path) => (true, path),
+                None => (false, entry.as_str()),
+            };
+            let path = match path.strip_prefix("~/") {
+                Some(rest) => home_dir.join(rest).into_string(),
+                None => path.to_owned(),
+            };
+            let invalid = || Error::SettingsValidationError {
+                value: entry.clone(),
+                setting: "ruby_dirs".to_string(),
+            };
+
+            if negated {
+                excluded.push(Pattern::new(&path).map_err(|_| invalid())?);
+            } else {
+                let matches = glob::glob(&path).map_err(|_| invalid())?;
+                included.extend(
+                    matches
+                        .flatten()
+                        .filter(|dir| dir.is_dir())
+                        .filter_map(|dir| Utf8PathBuf::from_path_buf(dir).ok()),
+                );
+            }
+        }
+
+        Ok((included, excluded))
+    }
+
+    /// The certificate pins of gem servers, parsed.
+    pub fn certificate_pins(&self) -> Result<Vec<CertificatePin>> {
+        self.certificate_pins
+            .iter()
+            .map(|setting| {
+                CertificatePin::new(&setting.host, &setting.pin).map_err(|_| {
+                    Error::SettingsValidationError {
+                        value: setting.pin.clone(),
+                        setting: "certificate_pins".to_string(),
+                    }
+                })
+            })
+            .collect()
+    }
+
+    /// The bandwidth `limit-rate` limits downloads to, if it's set.
+    pub fn download_rate(&self) -> Result<Option<Rate>> {
+        self.limit_rate
+            .as_ref()
+            .map(|rate| {
+                rate.parse().map_err(|_| Error::SettingsValidationError {
+                    value: rate.clone(),
+                    setting: "limit_rate".to_string(),
+                })
+            })
+            .transpose()
+    }
+
+    /// How many directories to search for a version file: `--dir-depth`, or else the
+    /// `dir-depth` setting, if either is given.
+    pub(crate) fn dir_depth(&self, global_args: &GlobalArgs) -> Result<Option<usize>> {
+        if let Some(depth) = global_args.dir_depth {
+            return Ok(Some(depth));
+        }
+        self.dir_depth
+            .as_ref()
+            .map(|depth| {
+                depth.parse().map_err(|_| Error::SettingsValidationError {
+                    value: depth.clone(),
+                    setting: "dir_depth".to_string(),
+                })
+            })
+            .transpose()
+    }
+
+    /// The gems to install into a new Ruby with `version`: those of the first `default-gems`
+    /// entry whose `ruby` matches it, or else those of the entry for every Ruby.
+    pub fn default_gems_for(&self, version: &RubyVersion) -> Result<Vec<String>> {
+        let mut fallback = None;
+        for entry in &self.default_gems {
+            let Some(ruby) = &entry.ruby else {
+                fallback = fallback.or(Some(&entry.gems));
+                continue;
+            };
+            let request: RubyRequest =
+                ruby.parse().map_err(|_| Error::SettingsValidationError {
+                    value: ruby.clone(),
+                    setting: "default_gems".to_string(),
+                })?;
+            if version.satisfies(&request) {
+                return Ok(entry.gems.clone());
+            }
+        }
+        Ok(fallback.cloned().unwrap_or_default())
+    }
+
+    /// The files that can pin a Ruby, in the order `version-files` gives them precedence, or
+    /// every one in the default order if it's not set.
+    pub fn version_file_order(&self) -> Result<Vec<VersionFile>> {
+        if self.version_files.is_empty() {
+            return Ok(VersionFile::DEFAULT_ORDER.to_vec());
+        }
+
+        self.version_files
+            .iter()
+            .map(|name| {
+                VersionFile::from_file_name(name).ok_or_else(|| Error::SettingsValidationError {
+                    value: name.clone(),
+                    setting: "version_files".to_string(),
+                })
+            })
+            .collect()
+    }
 }
 
 #[cfg(test)]
@@ -206,6 +666,11 @@ mod tests {
             ruby_dir: Vec::new(),
             cache_args: CacheArgs::default(),
             offline: false,
+            quiet: false,
+            ruby: None,
+            no_input: false,
+            limit_rate: None,
+            dir_depth: None,
         }
     }
 
@@ -236,6 +701,317 @@ rv{
         )
     }
 
+    #[test]
+    fn test_ruby_install_dir() {
+        let temp_dir = Utf8TempDir::new().expect("Failed to create temporary directory");
+
+        let home_dir = temp_dir.path().join("home");
+        let project_dir = temp_dir.path().join("project");
+
+        std::fs::create_dir_all(&home_dir).unwrap();
+        std::fs::write(
+            home_dir.join(".rv.kdl"),
+            "rv {\n  ruby-install-dir \"/opt/rubies\"\n}\n",
+        )
+        .expect("Failed to write config");
+
+        let rv_settings = RvSettings::new(&fake_global_args(), &home_dir, &project_dir).unwrap();
+
+        assert_eq!(
+            Some(Utf8PathBuf::from("/opt/rubies")),
+            rv_settings.ruby_install_dir_as_utf8pathbuf()
+        )
+    }
+
+    #[test]
+    fn test_project_isolation() {
+        let temp_dir = Utf8TempDir::new().expect("Failed to create temporary directory");
+
+        let home_dir = temp_dir.path().join("home");
+        let project_dir = temp_dir.path().join("project");
+
+        std::fs::create_dir_all(&project_dir).unwrap();
+        std::fs::write(
+            project_dir.join("rv.kdl"),
+            "rv {\n  isolation \"project\"\n}\n",
+        )
+        .expect("Failed to write config");
+
+        let rv_settings = RvSettings::new(&fake_global_args(), &home_dir, &project_dir).unwrap();
+        assert_eq!(rv_settings.isolation, Isolation::Project);
+
+        std::fs::write(
+            project_dir.join("rv.kdl"),
+            "rv {\n  isolation \"nope\"\n}\n",
+        )
+        .expect("Failed to write config");
+        assert!(RvSettings::new(&fake_global_args(), &home_dir, &project_dir).is_err());
+    }
+
+    #[test]
+    fn test_ruby_dirs() {
+        let temp_dir = Utf8TempDir::new().expect("Failed to create temporary directory");
+
+        let home_dir = temp_dir.path().join("home");
+        let project_dir = temp_dir.path().join("project");
+        std::fs::create_dir_all(home_dir.join("src/rubies-a")).unwrap();
+        std::fs::create_dir_all(home_dir.join("src/rubies-b")).unwrap();
+        std::fs::write(home_dir.join("src/rubies-c"), "not a directory").unwrap();
+        std::fs::write(
+            home_dir.join(".rv.kdl"),
+            "rv {\n  ruby-dirs \"~/src/rubies-*\" \"!/opt/homebrew/**\"\n}\n",
+        )
+        .expect("Failed to write config");
+
+        let rv_settings = RvSettings::new(&fake_global_args(), &home_dir, &project_dir).unwrap();
+        let (included, excluded) = rv_settings.ruby_dir_patterns(&home_dir).unwrap();
+        assert_eq!(
+            included,
+            vec![home_dir.join("src/rubies-a"), home_dir.join("src/rubies-b")]
+        );
+        assert_eq!(excluded, vec![Pattern::new("/opt/homebrew/**").unwrap()]);
+
+        let rv_settings = RvSettings {
+            ruby_dirs: vec!["![".to_owned()],
+            ..RvSettings::default()
+        };
+        assert!(rv_settings.ruby_dir_patterns(&home_dir).is_err());
+    }
+
+    #[test]
+    fn test_ruby_source() {
+        let temp_dir = Utf8TempDir::new().expect("Failed to create temporary directory");
+
+        let home_dir = temp_dir.path().join("home");
+        let project_dir = temp_dir.path().join("project");
+        std::fs::create_dir_all(&home_dir).unwrap();
+        std::fs::write(
+            home_dir.join(".rv.kdl"),
+            r#"rv {
+  ruby-source "/mnt/rubies" os="linux" arch="x86_64"
+  ruby-source "https://rubies.example.com/"
+}
+"#,
+        )
+        .expect("Failed to write config");
+
+        let rv_settings = RvSettings::new(&fake_global_args(), &home_dir, &project_dir).unwrap();
+        assert_eq!(
+            rv_settings.ruby_source,
+            vec![
+                RubySourceSetting {
+                    url: "/mnt/rubies".to_owned(),
+                    os: Some("linux".to_owned()),
+                    arch: Some("x86_64".to_owned()),
+                },
+                RubySourceSetting {
+                    url: "https://rubies.example.com/".to_owned(),
+                    os: None,
+                    arch: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_default_gems() {
+        let temp_dir = Utf8TempDir::new().expect("Failed to create temporary directory");
+
+        let home_dir = temp_dir.path().join("home");
+        let project_dir = temp_dir.path().join("project");
+        std::fs::create_dir_all(&home_dir).unwrap();
+        std::fs::write(
+            home_dir.join(".rv.kdl"),
+            r#"rv {
+  default-gems "irb" "pry"
+  default-gems "pry" "solargraph ~> 0.50" ruby="3.4"
+}
+"#,
+        )
+        .expect("Failed to write config");
+
+        let rv_settings = RvSettings::new(&fake_global_args(), &home_dir, &project_dir).unwrap();
+        let gems_for = |version: &str| {
+            rv_settings
+                .default_gems_for(&version.parse().unwrap())
+                .unwrap()
+        };
+        assert_eq!(gems_for("ruby-3.4.1"), ["pry", "solargraph ~> 0.50"]);
+        assert_eq!(gems_for("ruby-3.3.9"), ["irb", "pry"]);
+
+        let rv_settings = RvSettings {
+            default_gems: vec![DefaultGemsSetting {
+                gems: vec!["pry".to_owned()],
+                ruby: Some(">= three".to_owned()),
+            }],
+            ..RvSettings::default()
+        };
+        assert!(
+            rv_settings
+                .default_gems_for(&"ruby-3.4.1".parse().unwrap())
+                .is_err()
+        );
+        assert!(
+            RvSettings::default()
+                .default_gems_for(&"ruby-3.4.1".parse().unwrap())
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_version_files() {
+        let temp_dir = Utf8TempDir::new().expect("Failed to create temporary directory");
+
+        let home_dir = temp_dir.path().join("home");
+        let project_dir = temp_dir.path().join("project");
+        std::fs::create_dir_all(&project_dir).unwrap();
+
+        let rv_settings = RvSettings::new(&fake_global_args(), &home_dir, &project_dir).unwrap();
+        assert_eq!(
+            rv_settings.version_file_order().unwrap(),
+            VersionFile::DEFAULT_ORDER
+        );
+
+        std::fs::write(
+            project_dir.join("rv.kdl"),
+            "rv {\n  version-files \".tool-versions\" \".ruby-version\"\n}\n",
+        )
+        .expect("Failed to write config");
+        let rv_settings = RvSettings::new(&fake_global_args(), &home_dir, &project_dir).unwrap();
+        assert_eq!(
+            rv_settings.version_file_order().unwrap(),
+            [VersionFile::ToolVersions, VersionFile::RubyVersion]
+        );
+
+        let rv_settings = RvSettings {
+            version_files: vec![".python-version".to_owned()],
+            ..RvSettings::default()
+        };
+        assert!(rv_settings.version_file_order().is_err());
+    }
+
+    #[test]
+    fn test_hooks() {
+        let temp_dir = Utf8TempDir::new().expect("Failed to create temporary directory");
+
+        let home_dir = temp_dir.path().join("home");
+        let project_dir = temp_dir.path().join("project");
+        std::fs::create_dir_all(&project_dir).unwrap();
+        std::fs::write(
+            project_dir.join("rv.kdl"),
+            r#"rv {
+  hook "pre-download" "./script/check-allowlist"
+  hook "post-install" "scan-gem --quiet"
+}
+"#,
+        )
+        .expect("Failed to write config");
+
+        let rv_settings = RvSettings::new(&fake_global_args(), &home_dir, &project_dir).unwrap();
+        assert_eq!(
+            rv_settings.hooks,
+            vec![
+                HookSetting {
+                    event: HookEvent::PreDownload,
+                    command: "./script/check-allowlist".to_owned(),
+                },
+                HookSetting {
+                    event: HookEvent::PostInstall,
+                    command: "scan-gem --quiet".to_owned(),
+                },
+            ]
+        );
+
+        std::fs::write(
+            project_dir.join("rv.kdl"),
+            "rv {\n  hook \"post-checkout\" \"true\"\n}\n",
+        )
+        .expect("Failed to write config");
+        assert!(RvSettings::new(&fake_global_args(), &home_dir, &project_dir).is_err());
+    }
+
+    #[test]
+    fn test_certificate_pins() {
+        let temp_dir = Utf8TempDir::new().expect("Failed to create temporary directory");
+
+        let home_dir = temp_dir.path().join("home");
+        let project_dir = temp_dir.path().join("project");
+        std::fs::create_dir_all(&project_dir).unwrap();
+        std::fs::write(
+            project_dir.join("rv.kdl"),
+            r#"rv {
+  certificate-pin "gems.example.com" "sha256/47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU="
+  certificate-pin "gems.example.com" "cert-sha256/e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+}
+"#,
+        )
+        .expect("Failed to write config");
+
+        let rv_settings = RvSettings::new(&fake_global_args(), &home_dir, &project_dir).unwrap();
+        let pins = rv_settings.certificate_pins().unwrap();
+        assert_eq!(pins.len(), 2);
+        assert!(pins.iter().all(|pin| pin.host == "gems.example.com"));
+
+        let rv_settings = RvSettings {
+            certificate_pins: vec![CertificatePinSetting {
+                host: "gems.example.com".to_owned(),
+                pin: "sha1/abc".to_owned(),
+            }],
+            ..RvSettings::default()
+        };
+        assert!(rv_settings.certificate_pins().is_err());
+    }
+
+    #[test]
+    fn test_limit_rate() {
+        let temp_dir = Utf8TempDir::new().expect("Failed to create temporary directory");
+
+        let home_dir = temp_dir.path().join("home");
+        let project_dir = temp_dir.path().join("project");
+        std::fs::create_dir_all(&home_dir).unwrap();
+        std::fs::write(
+            home_dir.join(".rv.kdl"),
+            "rv {\n  limit-rate \"10MB/s\"\n}\n",
+        )
+        .expect("Failed to write config");
+
+        let rv_settings = RvSettings::new(&fake_global_args(), &home_dir, &project_dir).unwrap();
+        assert_eq!(rv_settings.download_rate().unwrap(), Some(Rate(10_000_000)));
+
+        let rv_settings = RvSettings {
+            limit_rate: Some("fast".to_owned()),
+            ..RvSettings::default()
+        };
+        assert!(rv_settings.download_rate().is_err());
+    }
+
+    #[test]
+    fn test_dir_depth() {
+        let temp_dir = Utf8TempDir::new().expect("Failed to create temporary directory");
+
+        let home_dir = temp_dir.path().join("home");
+        let project_dir = temp_dir.path().join("project");
+        std::fs::create_dir_all(&home_dir).unwrap();
+        std::fs::write(home_dir.join(".rv.kdl"), "rv {\n  dir-depth 2\n}\n")
+            .expect("Failed to write config");
+
+        let rv_settings = RvSettings::new(&fake_global_args(), &home_dir, &project_dir).unwrap();
+        assert_eq!(rv_settings.dir_depth(&fake_global_args()).unwrap(), Some(2));
+
+        let global_args = GlobalArgs {
+            dir_depth: Some(5),
+            ..fake_global_args()
+        };
+        assert_eq!(rv_settings.dir_depth(&global_args).unwrap(), Some(5));
+
+        let rv_settings = RvSettings {
+            dir_depth: Some("deep".to_owned()),
+            ..RvSettings::default()
+        };
+        assert!(rv_settings.dir_depth(&fake_global_args()).is_err());
+    }
+
     #[test]
     fn test_fallback_to_defaults_when_no_env_vars_and_no_files() {
         let temp_dir = Utf8TempDir::new().expect("Failed to create temporary directory");
@@ -247,6 +1023,7 @@ rv{
             .expect("Failed to load settings");
 
         assert!(rv_settings.install_path.is_none());
+        assert_eq!(rv_settings.isolation, Isolation::Shared);
     }
 
     #[test]
@@ -0,0 +1,160 @@
+//! Machine-readable errors, for tools that wrap rv. Every error rv reports has a stable code like
+//! `RV1306`; docs/ERRORS.md lists them, along with the exit codes rv uses.
+
+use miette::{Diagnostic, SourceCode};
+use serde::Serialize;
+
+/// The code for errors that don't have one of their own.
+const UNKNOWN_CODE: &str = "RV0000";
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum ErrorFormat {
+    /// Human-readable errors
+    #[default]
+    Text,
+    /// One JSON object per error, with its code, message, hints and source spans
+    Json,
+}
+
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub(crate) struct JsonError {
+    pub code: String,
+    pub message: String,
+    pub hints: Vec<String>,
+    pub spans: Vec<JsonSpan>,
+}
+
+/// A labelled piece of the file an error was found in, e.g. a line of a Gemfile.lock.
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub(crate) struct JsonSpan {
+    /// The message of the error the span belongs to, which can be more specific than the
+    /// top-level message.
+    pub message: String,
+    pub label: Option<String>,
+    pub offset: usize,
+    pub length: usize,
+    /// 1-based line and column of the start of the span.
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+}
+
+impl JsonError {
+    pub(crate) fn new(diagnostic: &dyn Diagnostic) -> Self {
+        let mut hints = Vec::new();
+        let mut spans = Vec::new();
+        collect(diagnostic, None, &mut hints, &mut spans);
+
+        Self {
+            code: code(diagnostic).unwrap_or_else(|| UNKNOWN_CODE.to_owned()),
+            message: diagnostic.to_string(),
+            hints,
+            spans,
+        }
+    }
+}
+
+/// The first code found in the diagnostic or the diagnostics it wraps.
+fn code(diagnostic: &dyn Diagnostic) -> Option<String> {
+    diagnostic
+        .code()
+        .map(|code| code.to_string())
+        .or_else(|| diagnostic.diagnostic_source().and_then(code))
+}
+
+/// Gather hints and spans from a diagnostic, its source, and any related diagnostics.
+/// Related diagnostics often point into their parent's source code instead of having their own.
+fn collect<'a>(
+    diagnostic: &'a dyn Diagnostic,
+    parent_source: Option<&'a dyn SourceCode>,
+    hints: &mut Vec<String>,
+    spans: &mut Vec<JsonSpan>,
+) {
+    let source_code = diagnostic.source_code().or(parent_source);
+    if let Some(help) = diagnostic.help() {
+        hints.push(help.to_string());
+    }
+
+    if let Some(labels) = diagnostic.labels() {
+        for label in labels {
+            let location = source_code
+                .and_then(|source| source.read_span(label.inner(), 0, 0).ok())
+                .map(|contents| (contents.line() + 1, contents.column() + 1));
+            spans.push(JsonSpan {
+                message: diagnostic.to_string(),
+                label: label.label().map(ToOwned::to_owned),
+                offset: label.offset(),
+                length: label.len(),
+                line: location.map(|(line, _)| line),
+                column: location.map(|(_, column)| column),
+            });
+        }
+    }
+
+    if let Some(source) = diagnostic.diagnostic_source() {
+        collect(source, None, hints, spans);
+    }
+    if let Some(related) = diagnostic.related() {
+        for related in related {
+            collect(related, source_code, hints, spans);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, thiserror::Error, Diagnostic)]
+    enum InnerError {
+        #[error("bad version")]
+        #[diagnostic(code(RV9999), help("Use a real version"))]
+        BadVersion {
+            #[source_code]
+            contents: String,
+            #[label("this one")]
+            span: miette::SourceSpan,
+        },
+    }
+
+    #[derive(Debug, thiserror::Error, Diagnostic)]
+    enum OuterError {
+        #[error(transparent)]
+        #[diagnostic(transparent)]
+        Inner(#[from] InnerError),
+        #[error(transparent)]
+        Io(#[from] std::io::Error),
+    }
+
+    #[test]
+    fn test_json_error_from_wrapped_diagnostic() {
+        let error = OuterError::from(InnerError::BadVersion {
+            contents: "ruby 3.4.1\nbundler x.y\n".to_owned(),
+            span: (19, 3).into(),
+        });
+        assert_eq!(
+            JsonError::new(&error),
+            JsonError {
+                code: "RV9999".to_owned(),
+                message: "bad version".to_owned(),
+                hints: vec!["Use a real version".to_owned()],
+                spans: vec![JsonSpan {
+                    message: "bad version".to_owned(),
+                    label: Some("this one".to_owned()),
+                    offset: 19,
+                    length: 3,
+                    line: Some(2),
+                    column: Some(9),
+                }],
+            }
+        );
+    }
+
+    #[test]
+    fn test_json_error_without_code() {
+        let error = OuterError::from(std::io::Error::other("disk on fire"));
+        let json = JsonError::new(&error);
+        assert_eq!(json.code, UNKNOWN_CODE);
+        assert_eq!(json.message, "disk on fire");
+        assert!(json.hints.is_empty());
+    }
+}
@@ -0,0 +1,258 @@
+//! Machine-readable reports of what failed in `rv ci` and `rv matrix`, so failures show up in CI
+//! dashboards and pull request checks instead of only in the build log.
+
+use std::fmt::Write as _;
+use std::time::Duration;
+
+use anstream::println;
+use camino::{Utf8Path, Utf8PathBuf};
+use clap::Args;
+
+/// How many lines of a failure's log to include in reports.
+const LOG_EXCERPT_LINES: usize = 20;
+
+#[derive(Args, Debug, Clone, Default)]
+pub struct ReportArgs {
+    /// Write a JUnit XML report to this file, with one test case per gem or Ruby.
+    #[arg(long, value_name = "FILE")]
+    pub report: Option<Utf8PathBuf>,
+
+    /// Print failures as GitHub Actions `::error` annotations.
+    /// On by default when running in GitHub Actions.
+    #[arg(long, env = "GITHUB_ACTIONS")]
+    pub github_annotations: bool,
+}
+
+impl ReportArgs {
+    /// Write every report that was asked for.
+    pub fn emit(&self, suite: &str, cases: &[TestCase]) -> std::io::Result<()> {
+        if self.github_annotations {
+            for case in cases {
+                if let Some(failure) = &case.failure {
+                    println!("{}", github_annotation(suite, &case.name, failure));
+                }
+            }
+        }
+        if let Some(path) = &self.report {
+            write_junit(path, suite, cases)?;
+        }
+        Ok(())
+    }
+}
+
+/// What rv was doing when something failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Download,
+    Compile,
+    Run,
+}
+
+impl Phase {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Download => "download",
+            Self::Compile => "compile",
+            Self::Run => "run",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Failure {
+    pub phase: Phase,
+    pub message: String,
+    /// The end of the failing command's output, if there was one.
+    pub log: Option<String>,
+}
+
+impl Failure {
+    pub fn new(phase: Phase, message: impl Into<String>) -> Self {
+        Self {
+            phase,
+            message: message.into(),
+            log: None,
+        }
+    }
+
+    #[must_use]
+    pub fn with_log(mut self, log: &str) -> Self {
+        let excerpt = log_excerpt(log);
+        self.log = (!excerpt.is_empty()).then_some(excerpt);
+        self
+    }
+}
+
+/// One gem installed by `rv ci`, or one Ruby run by `rv matrix`.
+#[derive(Debug, Clone)]
+pub struct TestCase {
+    pub name: String,
+    pub duration: Option<Duration>,
+    pub failure: Option<Failure>,
+}
+
+/// The last few lines of a log, which is usually where the error is.
+fn log_excerpt(log: &str) -> String {
+    let lines: Vec<_> = log.trim_end().lines().collect();
+    let start = lines.len().saturating_sub(LOG_EXCERPT_LINES);
+    lines[start..].join("\n")
+}
+
+/// Format a failure as a GitHub Actions workflow command. See
+/// <https://docs.github.com/en/actions/reference/workflow-commands-for-github-actions>.
+fn github_annotation(suite: &str, name: &str, failure: &Failure) -> String {
+    let title = format!("{suite}: {name} failed to {}", failure.phase.as_str());
+    let mut message = failure.message.clone();
+    if let Some(log) = &failure.log {
+        message.push_str("\n\n");
+        message.push_str(log);
+    }
+    format!(
+        "::error title={}::{}",
+        escape_annotation_property(&title),
+        escape_annotation_data(&message)
+    )
+}
+
+fn escape_annotation_data(data: &str) -> String {
+    data.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+fn escape_annotation_property(property: &str) -> String {
+    escape_annotation_data(property)
+        .replace(':', "%3A")
+        .replace(',', "%2C")
+}
+
+fn write_junit(path: &Utf8Path, suite: &str, cases: &[TestCase]) -> std::io::Result<()> {
+    if let Some(parent) = path.parent()
+        && !parent.as_str().is_empty()
+    {
+        fs_err::create_dir_all(parent)?;
+    }
+    fs_err::write(path, junit_xml(suite, cases))
+}
+
+fn junit_xml(suite: &str, cases: &[TestCase]) -> String {
+    let failures = cases.iter().filter(|case| case.failure.is_some()).count();
+    let total_time: Duration = cases.iter().filter_map(|case| case.duration).sum();
+
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    let _ = writeln!(
+        xml,
+        "<testsuites name=\"{suite}\" tests=\"{}\" failures=\"{failures}\" time=\"{:.3}\">",
+        cases.len(),
+        total_time.as_secs_f64(),
+        suite = escape_xml(suite),
+    );
+    let _ = writeln!(
+        xml,
+        "  <testsuite name=\"{suite}\" tests=\"{}\" failures=\"{failures}\" time=\"{:.3}\">",
+        cases.len(),
+        total_time.as_secs_f64(),
+        suite = escape_xml(suite),
+    );
+    for case in cases {
+        let _ = write!(
+            xml,
+            "    <testcase name=\"{}\" classname=\"{}\" time=\"{:.3}\"",
+            escape_xml(&case.name),
+            escape_xml(suite),
+            case.duration.unwrap_or_default().as_secs_f64(),
+        );
+        match &case.failure {
+            None => xml.push_str("/>\n"),
+            Some(failure) => {
+                let _ = writeln!(
+                    xml,
+                    ">\n      <failure message=\"{}\" type=\"{}\">{}</failure>\n    </testcase>",
+                    escape_xml(&failure.message),
+                    failure.phase.as_str(),
+                    escape_xml(failure.log.as_deref().unwrap_or(&failure.message)),
+                );
+            }
+        }
+    }
+    xml.push_str("  </testsuite>\n</testsuites>\n");
+    xml
+}
+
+fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            // Control characters other than whitespace aren't allowed in XML 1.0.
+            c if c.is_control() && !matches!(c, '\n' | '\r' | '\t') => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cases() -> Vec<TestCase> {
+        vec![
+            TestCase {
+                name: "nokogiri-1.18.0".to_string(),
+                duration: Some(Duration::from_millis(1500)),
+                failure: Some(
+                    Failure::new(Phase::Compile, "Could not compile extensions")
+                        .with_log("checking for xml2.h... no\nextconf.rb failed <libxml2>"),
+                ),
+            },
+            TestCase {
+                name: "rake-13.3.0".to_string(),
+                duration: Some(Duration::from_millis(250)),
+                failure: None,
+            },
+        ]
+    }
+
+    #[test]
+    fn test_junit_xml() {
+        let xml = junit_xml("rv ci", &cases());
+        assert_eq!(
+            xml,
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<testsuites name="rv ci" tests="2" failures="1" time="1.750">
+  <testsuite name="rv ci" tests="2" failures="1" time="1.750">
+    <testcase name="nokogiri-1.18.0" classname="rv ci" time="1.500">
+      <failure message="Could not compile extensions" type="compile">checking for xml2.h... no
+extconf.rb failed &lt;libxml2&gt;</failure>
+    </testcase>
+    <testcase name="rake-13.3.0" classname="rv ci" time="0.250"/>
+  </testsuite>
+</testsuites>
+"#
+        );
+    }
+
+    #[test]
+    fn test_github_annotation() {
+        let failure = Failure::new(Phase::Download, "HTTP status 503, 100% broken")
+            .with_log("first line\nsecond line\n");
+        assert_eq!(
+            github_annotation("rv ci", "rack-3.1.0", &failure),
+            "::error title=rv ci%3A rack-3.1.0 failed to download::HTTP status 503, 100%25 broken%0A%0Afirst line%0Asecond line"
+        );
+    }
+
+    #[test]
+    fn test_log_excerpt_keeps_the_last_lines() {
+        let log: String = (1..=30).map(|n| format!("line {n}\n")).collect();
+        let excerpt = log_excerpt(&log);
+        assert_eq!(excerpt.lines().count(), LOG_EXCERPT_LINES);
+        assert!(excerpt.starts_with("line 11\n"));
+        assert!(excerpt.ends_with("line 30"));
+    }
+}
@@ -44,6 +44,8 @@ pub enum Error {
     CouldNotCreateCacheDir(std::io::Error),
     #[error("The url {url} unexpectedly returned an empty response")]
     EmptyResponse { url: Url },
+    #[error(transparent)]
+    Credentials(#[from] crate::config::credentials::Error),
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -57,7 +59,11 @@ impl Gemserver {
 
         fs_err::create_dir_all(&cache_dir).map_err(Error::CouldNotCreateCacheDir)?;
 
-        let client = HttpFetcher::new("install")?;
+        let credentials = match url.host_str() {
+            Some(host) => config.credentials_for_host(host)?,
+            None => None,
+        };
+        let client = HttpFetcher::new("install")?.with_credentials(credentials);
         let storage = FilesystemStorage::new(cache_dir.into());
         let updater = Updater::new(client);
 
@@ -101,8 +107,10 @@ impl Gemserver {
         let info_url = self.url.join(&info_key).expect("valid info URL");
 
         let blob = if let Ok(blob) = self.storage.read_blob(&info_key).await {
+            rv_cache::record_hit(rv_cache::CacheBucket::GemDeps);
             self.updater.update(info_url.as_str(), blob).await
         } else {
+            rv_cache::record_miss(rv_cache::CacheBucket::GemDeps);
             self.updater.fetch(info_url.as_str()).await
         }
         .map_err(|err| {
@@ -249,6 +257,16 @@ pub fn parse_release_from_body(index_body: &str) -> ParseResult<Vec<GemRelease>>
         .collect()
 }
 
+/// Like [`parse_release_from_body`], but keeps the releases for every platform, not just the ones
+/// that install on this machine.
+pub fn parse_all_releases_from_body(index_body: &str) -> ParseResult<Vec<GemRelease>> {
+    index_body
+        .lines()
+        .filter(|line| *line != "---")
+        .map(GemRelease::parse)
+        .collect()
+}
+
 /// All the information about a release of a gem available on some Gemserver.
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct GemRelease {
@@ -1,7 +1,9 @@
 use async_trait::async_trait;
-use rv_client::http_client::rv_http_client;
+use rv_client::http_client::{Client, rv_http_client};
 use std::collections::HashMap;
 
+use crate::config::credentials::Userinfo;
+
 #[derive(Debug, Clone)]
 pub struct Response {
     pub body: Vec<u8>,
@@ -70,7 +72,9 @@ fn strip_wrapper(s: &str, wrapper: char) -> Option<&str> {
 
 #[derive(Clone)]
 pub struct HttpFetcher {
-    client: reqwest::Client,
+    client: Client,
+    /// Credentials for the gem server, sent with every request.
+    credentials: Option<Userinfo>,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -85,8 +89,14 @@ impl HttpFetcher {
     pub fn new(command: &'static str) -> Result<Self> {
         Ok(Self {
             client: rv_http_client(command)?,
+            credentials: None,
         })
     }
+
+    pub fn with_credentials(mut self, credentials: Option<Userinfo>) -> Self {
+        self.credentials = credentials;
+        self
+    }
 }
 
 /// Trait for fetching remote resources
@@ -100,6 +110,9 @@ impl Fetcher for HttpFetcher {
     /// Make a single HTTP call without retry logic
     async fn call(&self, remote_path: &str, headers: HashMap<String, String>) -> Result<Response> {
         let mut request = self.client.get(remote_path);
+        if let Some((user, password)) = &self.credentials {
+            request = request.basic_auth(user, password.as_ref());
+        }
 
         // Add all headers to the request
         for (key, value) in headers {
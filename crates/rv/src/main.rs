@@ -2,31 +2,53 @@ use anstream::stream::IsTerminal;
 use camino::Utf8PathBuf;
 use clap::builder::Styles;
 use clap::builder::styling::AnsiColor;
-use clap::{ArgAction, CommandFactory, Parser, Subcommand};
+use clap::{ArgAction, CommandFactory, FromArgMatches, Parser, Subcommand};
 use clap_verbosity_flag::tracing::LevelFilter;
 use miette::Report;
 use rv_cache::CacheArgs;
+use rv_client::rate_limit::Rate;
+use rv_ruby::request::RubyRequest;
+use std::time::{Instant, SystemTime};
 use tokio::main;
 use tracing_indicatif::IndicatifLayer;
 use tracing_subscriber::{EnvFilter, layer::SubscriberExt as _, util::SubscriberInitExt as _};
 
 pub mod commands;
 pub mod config;
+pub mod error_format;
+pub mod failure_report;
 pub mod gemserver;
 pub mod output_format;
 pub mod progress;
 pub mod resolver;
 pub mod script_metadata;
-pub mod tar_utils;
+pub mod stats;
 pub mod update;
 
 use crate::commands::cache::{CacheCommandArgs, cache};
-use crate::commands::clean_install::{CleanInstallArgs, ci};
+use crate::commands::clean::{CleanArgs, clean};
+use crate::commands::clean_install::{CleanInstallArgs, OutputMode, ci};
+use crate::commands::complete::{CompleteArgs, complete};
+use crate::commands::doctor::doctor;
+use crate::commands::gc::{GcArgs, gc};
+use crate::commands::gem::{GemArgs, gem};
+use crate::commands::gemfile::{GemfileArgs, gemfile};
+use crate::commands::generate::{GenerateArgs, generate};
+use crate::commands::lock::{LockArgs, lock};
+use crate::commands::matrix::{MatrixArgs, matrix};
+use crate::commands::migrate::{MigrateArgs, migrate};
+use crate::commands::prune::{PruneArgs, prune};
+use crate::commands::rpc::{RpcArgs, rpc};
 use crate::commands::ruby::{RubyArgs, ruby};
 use crate::commands::run::{RunArgs, run};
 use crate::commands::self_cmd::{SelfArgs, self_cmd};
 use crate::commands::shell::{ShellArgs, shell};
 use crate::commands::tool::{ToolArgs, tool};
+use crate::commands::trust::{TrustArgs, trust};
+use crate::commands::update::UpdateArgs;
+use crate::commands::why::{WhyArgs, why};
+use crate::error_format::{ErrorFormat, JsonError};
+use crate::stats::Stats;
 
 const STYLES: Styles = Styles::styled()
     .header(AnsiColor::Green.on_default().bold())
@@ -44,6 +66,21 @@ struct GlobalArgs {
     cache_args: CacheArgs,
 
     offline: bool,
+
+    /// Whether `--quiet` was given, so commands should only report errors
+    quiet: bool,
+
+    /// Ruby to use instead of the project's pinned Ruby
+    ruby: Option<RubyRequest>,
+
+    /// Whether `--no-input` was given, so commands must never prompt
+    no_input: bool,
+
+    /// The bandwidth to limit downloads to, overriding the `limit-rate` setting
+    limit_rate: Option<Rate>,
+
+    /// How many directories to search for a version file, overriding the `dir-depth` setting
+    dir_depth: Option<usize>,
 }
 
 /// An extremely fast Ruby version manager.
@@ -75,6 +112,11 @@ struct Cli {
     )]
     ruby_dir: Vec<Utf8PathBuf>,
 
+    /// Use this Ruby instead of the one pinned by the project, e.g. to test against several
+    /// Ruby versions without editing files. Applies to every command that picks a Ruby.
+    #[arg(long, env = "RV_RUBY_VERSION", value_name = "REQUEST")]
+    ruby: Option<RubyRequest>,
+
     #[command(flatten)]
     verbose: clap_verbosity_flag::Verbosity<clap_verbosity_flag::InfoLevel>,
 
@@ -85,6 +127,46 @@ struct Cli {
     #[arg(long, env = "RV_COLOR")]
     color: Option<ColorMode>,
 
+    /// How to print errors. `json` prints one object with a stable error code, for tools that
+    /// wrap rv.
+    #[arg(
+        long,
+        env = "RV_ERROR_FORMAT",
+        global = true,
+        value_enum,
+        default_value_t = ErrorFormat::Text,
+        value_name = "FORMAT"
+    )]
+    error_format: ErrorFormat,
+
+    /// Never prompt for input, e.g. in scripts. Commands that would ask a question do what they
+    /// would do without a terminal instead.
+    #[arg(long, env = "RV_NO_INPUT", global = true)]
+    no_input: bool,
+
+    /// Limit the bandwidth of downloads, like `10MB/s` or `500K`, shared by everything rv
+    /// downloads at once
+    #[arg(long, env = "RV_LIMIT_RATE", global = true, value_name = "RATE")]
+    limit_rate: Option<Rate>,
+
+    /// Search at most this many directories, starting with the current one, for a file that pins
+    /// the project's Ruby
+    #[arg(long, env = "RV_DIR_DEPTH", global = true, value_name = "DEPTH")]
+    dir_depth: Option<usize>,
+
+    /// Print how many requests the command made, how much it downloaded, how often the cache had
+    /// what it needed, and how long each phase took, to stderr when it finishes
+    #[arg(long, env = "RV_STATS", global = true)]
+    stats: bool,
+
+    /// Add the same stats as `--stats` to this file, as one line of JSON per run
+    #[arg(long, env = "RV_STATS_FILE", global = true, value_name = "PATH")]
+    stats_file: Option<Utf8PathBuf>,
+
+    /// Override the detected libc (gnu or musl) when picking Linux Ruby builds and precompiled gems
+    #[arg(long, env = "RV_LIBC", global = true, value_name = "LIBC")]
+    libc: Option<rv_platform::Libc>,
+
     /// Run rv in offline mode if possible
     /// TODO: Hide until really apply offline mode in all parts of rv.
     #[arg(long, hide = true, global = true)]
@@ -103,6 +185,11 @@ impl Cli {
             ruby_dir: self.ruby_dir.clone(),
             cache_args: self.cache_args.clone(),
             offline: self.offline,
+            quiet: self.verbose.tracing_level_filter() < LevelFilter::INFO,
+            ruby: self.ruby.clone(),
+            no_input: self.no_input,
+            limit_rate: self.limit_rate,
+            dir_depth: self.dir_depth,
         }
     }
 }
@@ -113,10 +200,34 @@ enum Commands {
     Ruby(RubyArgs),
     #[command(about = "Manage rv's cache")]
     Cache(CacheCommandArgs),
+    #[command(about = "Check for rubies rv didn't install that would run instead of rv's")]
+    Doctor,
     #[command(about = "Configure your shell to use rv")]
     Shell(ShellArgs),
     #[command(about = "Clean install from a Gemfile.lock", visible_alias = "ci")]
     CleanInstall(CleanInstallArgs),
+    #[command(about = "Remove the gems, binstubs and build logs rv installed into the project")]
+    Clean(CleanArgs),
+    #[command(
+        about = "Remove gem homes and tool environments left behind by rubies that were uninstalled"
+    )]
+    Gc(GcArgs),
+    #[command(about = "Remove installed gems that the Gemfile.lock doesn't lock any more")]
+    Prune(PruneArgs),
+    #[command(about = "Check and tidy up a Gemfile.lock")]
+    Lock(LockArgs),
+    #[command(about = "Update gems in the Gemfile.lock to the newest versions the Gemfile allows")]
+    Update(UpdateArgs),
+    #[command(about = "Show every chain of requirements from the Gemfile down to a locked gem")]
+    Why(WhyArgs),
+    #[command(about = "Manage the digests of gems rv has downloaded before")]
+    Trust(TrustArgs),
+    #[command(about = "Work with .gem files directly")]
+    Gem(GemArgs),
+    #[command(about = "Check a Gemfile, or print what rv reads from it, without running it")]
+    Gemfile(GemfileArgs),
+    #[command(about = "Answer editors' questions about rubies and lockfiles over JSON-RPC")]
+    Rpc(RpcArgs),
     #[command(
         name = "self",
         about = "Manage rv itself",
@@ -132,6 +243,17 @@ enum Commands {
         dont_delimit_trailing_values = true
     )]
     Run(RunArgs),
+    #[command(
+        about = "Run a command with several Ruby versions",
+        dont_delimit_trailing_values = true
+    )]
+    Matrix(MatrixArgs),
+    #[command(about = "Generate configuration files that use rv")]
+    Generate(GenerateArgs),
+    #[command(about = "Move files from older layouts to where rv keeps them now")]
+    Migrate(MigrateArgs),
+    #[command(name = "__complete", hide = true)]
+    Complete(CompleteArgs),
 }
 
 #[derive(Debug, Copy, Clone, clap::ValueEnum)]
@@ -179,26 +301,82 @@ impl From<ColorMode> for anstream::ColorChoice {
 #[derive(Debug, thiserror::Error, miette::Diagnostic)]
 pub enum Error {
     #[error(transparent)]
+    #[diagnostic(code(RV0001))]
     FromEnvError(#[from] tracing_subscriber::filter::FromEnvError),
     #[error(transparent)]
+    #[diagnostic(code(RV0002))]
     IoError(#[from] std::io::Error),
     #[error(transparent)]
+    #[diagnostic(transparent)]
     RubyError(#[from] commands::ruby::Error),
     #[error(transparent)]
+    #[diagnostic(transparent)]
     CiError(#[from] commands::clean_install::Error),
     #[error(transparent)]
+    #[diagnostic(transparent)]
     RunError(#[from] commands::ruby::run::Error),
     #[error(transparent)]
+    #[diagnostic(transparent)]
     ScriptRunError(#[from] commands::run::Error),
     #[error(transparent)]
+    #[diagnostic(transparent)]
     CacheError(#[from] commands::cache::Error),
     #[error(transparent)]
+    #[diagnostic(transparent)]
     SelfCmdError(#[from] commands::self_cmd::Error),
     #[error(transparent)]
+    #[diagnostic(transparent)]
     ShellError(#[from] commands::shell::Error),
     #[error(transparent)]
+    #[diagnostic(transparent)]
     ToolError(#[from] commands::tool::Error),
     #[error(transparent)]
+    #[diagnostic(transparent)]
+    MatrixError(#[from] commands::matrix::Error),
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    GenerateError(#[from] commands::generate::Error),
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    CleanError(#[from] commands::clean::Error),
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    GcError(#[from] commands::gc::Error),
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    DoctorError(#[from] commands::doctor::Error),
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    MigrateError(#[from] commands::migrate::Error),
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    PruneError(#[from] commands::prune::Error),
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    LockError(#[from] commands::lock::Error),
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    UpdateError(#[from] commands::update::Error),
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    WhyError(#[from] commands::why::Error),
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    TrustError(#[from] commands::trust::Error),
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    GemError(#[from] commands::gem::Error),
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    GemfileError(#[from] commands::gemfile::Error),
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    RpcError(#[from] commands::rpc::Error),
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    CompleteError(#[from] commands::complete::Error),
+    #[error(transparent)]
+    #[diagnostic(transparent)]
     ConfigError(#[from] crate::config::Error),
 }
 
@@ -206,30 +384,62 @@ type Result<T> = miette::Result<T, Error>;
 
 #[main]
 async fn main() {
-    if let Err(err) = main_inner().await {
-        let is_tty = std::io::stderr().is_terminal();
-        if is_tty {
-            eprintln!("{:?}", Report::new(err));
-        } else {
-            eprintln!("Error: {:?}", err);
+    let (cli, command_name) = parse_cli();
+    let error_format = cli.error_format;
+
+    if let Err(err) = main_inner(cli, command_name).await {
+        match error_format {
+            ErrorFormat::Json => {
+                let json = serde_json::to_string(&JsonError::new(&err))
+                    .expect("errors can always be serialized");
+                eprintln!("{json}");
+            }
+            ErrorFormat::Text => {
+                let is_tty = std::io::stderr().is_terminal();
+                if is_tty {
+                    eprintln!("{:?}", Report::new(err));
+                } else {
+                    eprintln!("Error: {:?}", err);
+                }
+            }
         }
         std::process::exit(1);
     }
 }
 
-async fn main_inner() -> Result<()> {
-    let is_rvx = std::env::args().next().unwrap().ends_with("rvx");
-    let cli = if is_rvx {
-        let mut args = std::env::args().collect::<Vec<String>>();
+/// The parsed command line, and the name of the command it runs, like `ruby list`.
+fn parse_cli() -> (Cli, String) {
+    let mut args = std::env::args().collect::<Vec<String>>();
+    let is_rvx = args[0].ends_with("rvx");
+    if is_rvx {
         let rvx_args = ["rv", "tool", "run"].map(|s| s.to_string());
         args.splice(0..1, rvx_args);
-        Cli::parse_from(args)
-    } else {
-        Cli::parse()
-    };
+    }
+    let matches = Cli::command().get_matches_from(args);
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+    (cli, command_name(&matches))
+}
+
+fn command_name(mut matches: &clap::ArgMatches) -> String {
+    let mut names = Vec::new();
+    while let Some((name, sub_matches)) = matches.subcommand() {
+        names.push(name);
+        matches = sub_matches;
+    }
+    names.join(" ")
+}
 
+async fn main_inner(cli: Cli, command_name: String) -> Result<()> {
     let indicatif_layer = IndicatifLayer::new();
 
+    // Progress bars would get in the way of quiet or machine-readable output.
+    let show_progress = match &cli.command {
+        Commands::CleanInstall(ci_args) => {
+            ci_args.output_mode(cli.global_args().quiet) == OutputMode::Human
+        }
+        _ => true,
+    };
+
     let color_mode = match cli.color {
         Some(color_mode) => color_mode,
         None => {
@@ -250,6 +460,10 @@ async fn main_inner() -> Result<()> {
 
     anstream::ColorChoice::write_global(color_mode.into());
 
+    if let Some(libc) = cli.libc {
+        rv_platform::Libc::set_override(libc);
+    }
+
     let writer = std::sync::Mutex::new(anstream::AutoStream::new(
         Box::new(indicatif_layer.get_stderr_writer()) as Box<dyn std::io::Write + Send>,
         color_mode.color_choice_for_terminal(std::io::stderr()),
@@ -290,11 +504,27 @@ async fn main_inner() -> Result<()> {
             None
         })
         .with(filter)
-        .with(indicatif_layer);
+        .with(show_progress.then_some(indicatif_layer));
 
     reg.init();
 
-    run_cmd(&cli.global_args(), cli.command).await
+    if !cli.stats && cli.stats_file.is_none() {
+        return run_cmd(&cli.global_args(), cli.command).await;
+    }
+
+    let started_at = SystemTime::now();
+    let start = Instant::now();
+    let result = run_cmd(&cli.global_args(), cli.command).await;
+    let stats = Stats::collect(command_name, result.is_ok(), started_at, start.elapsed());
+    if cli.stats {
+        eprint!("{}", stats.render());
+    }
+    if let Some(path) = &cli.stats_file
+        && let Err(err) = stats.append_to(path)
+    {
+        tracing::warn!("Couldn't write stats to {path}: {err}");
+    }
+    result
 }
 
 /// Run an `rv` subcommand.
@@ -304,11 +534,26 @@ async fn run_cmd(global_args: &GlobalArgs, command: Commands) -> Result<()> {
     match command {
         Commands::Ruby(ruby_args) => ruby(global_args, ruby_args).await?,
         Commands::CleanInstall(ci_args) => ci(global_args, ci_args).await?,
-        Commands::Cache(cache_args) => cache(global_args, cache_args)?,
+        Commands::Cache(cache_args) => cache(global_args, cache_args).await?,
         Commands::SelfCmd(self_args) => self_cmd(global_args, self_args).await?,
         Commands::Shell(shell_args) => shell(global_args, &mut Cli::command(), shell_args)?,
         Commands::Tool(tool_args) => tool(global_args, tool_args).await?,
         Commands::Run(run_args) => run(global_args, run_args).await?,
+        Commands::Matrix(matrix_args) => matrix(global_args, matrix_args).await?,
+        Commands::Generate(generate_args) => generate(global_args, generate_args)?,
+        Commands::Migrate(migrate_args) => migrate(migrate_args)?,
+        Commands::Clean(clean_args) => clean(global_args, clean_args)?,
+        Commands::Doctor => doctor(global_args)?,
+        Commands::Gc(gc_args) => gc(global_args, gc_args)?,
+        Commands::Prune(prune_args) => prune(global_args, prune_args)?,
+        Commands::Lock(lock_args) => lock(global_args, lock_args)?,
+        Commands::Update(update_args) => commands::update::update(global_args, update_args).await?,
+        Commands::Why(why_args) => why(global_args, why_args)?,
+        Commands::Trust(trust_args) => trust(trust_args)?,
+        Commands::Gem(gem_args) => gem(gem_args)?,
+        Commands::Gemfile(gemfile_args) => gemfile(global_args, gemfile_args)?,
+        Commands::Rpc(rpc_args) => rpc(global_args, rpc_args)?,
+        Commands::Complete(complete_args) => complete(global_args, complete_args).await?,
     };
 
     Ok(())
@@ -2,4 +2,6 @@
 pub enum OutputFormat {
     Text,
     Json,
+    /// Tab-separated values, one row per line, without headers or borders
+    Tsv,
 }
@@ -1,23 +1,182 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
 
-use rv_gem_types::{ReleaseTuple, VersionPlatform};
+use rv_gem_types::{ProjectDependency, ReleaseTuple, VersionPlatform};
+use rv_version::Version;
 
 use super::gemserver::{GemName, GemRelease};
 
-use pubgrub::Ranges;
+use pubgrub::{Dependencies, DependencyProvider, PackageResolutionStatistics, Ranges, Reporter};
 
 pub type DepProvider = pubgrub::OfflineDependencyProvider<GemName, Ranges<VersionPlatform>>;
-pub type ResolutionError = pubgrub::PubGrubError<DepProvider>;
+pub type ResolutionError = pubgrub::PubGrubError<PreferringProvider>;
+
+/// The package PubGrub resolves a whole project from, which depends on every gem in its
+/// Gemfile. Gem names can't contain parentheses, so it never clashes with a real gem.
+const PROJECT: &str = "(project)";
+
+/// Which version of a gem the resolver tries first, when more than one would do.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Resolution {
+    /// The newest version of every gem
+    #[default]
+    Highest,
+    /// The oldest version of each gem the Gemfile lists, and the newest of the gems they depend
+    /// on, to test the oldest versions a project claims to support
+    LowestDirect,
+    /// The locked version of every gem, unless it doesn't work anymore, so the lockfile changes
+    /// as little as possible
+    MinimalChanges,
+}
+
+/// What the resolver prefers, beyond the requirements every resolution has to satisfy.
+#[derive(Debug, Clone, Default)]
+pub struct Preferences {
+    pub resolution: Resolution,
+    /// The version each gem is locked to, for [`Resolution::MinimalChanges`].
+    pub locked: HashMap<GemName, Version>,
+}
+
+/// A dependency provider that picks versions the way [`Preferences`] say to, rather than always
+/// the newest one.
+pub struct PreferringProvider {
+    dependencies: DepProvider,
+    preferences: Preferences,
+    /// The gems the project depends on directly.
+    direct: HashSet<GemName>,
+}
+
+impl DependencyProvider for PreferringProvider {
+    type P = GemName;
+    type V = VersionPlatform;
+    type VS = Ranges<VersionPlatform>;
+    type M = <DepProvider as DependencyProvider>::M;
+    type Priority = <DepProvider as DependencyProvider>::Priority;
+    type Err = <DepProvider as DependencyProvider>::Err;
+
+    fn prioritize(
+        &self,
+        package: &Self::P,
+        range: &Self::VS,
+        package_statistics: &PackageResolutionStatistics,
+    ) -> Self::Priority {
+        self.dependencies
+            .prioritize(package, range, package_statistics)
+    }
+
+    fn choose_version(
+        &self,
+        package: &Self::P,
+        range: &Self::VS,
+    ) -> Result<Option<Self::V>, Self::Err> {
+        let candidates: Vec<&VersionPlatform> = self
+            .dependencies
+            .versions(package)
+            .into_iter()
+            .flatten()
+            .filter(|version| range.contains(version))
+            .collect();
+        let newest = candidates.last();
+        let chosen = match self.preferences.resolution {
+            Resolution::Highest => newest,
+            Resolution::LowestDirect if self.direct.contains(package) => candidates.first(),
+            Resolution::LowestDirect => newest,
+            Resolution::MinimalChanges => self
+                .preferences
+                .locked
+                .get(package)
+                .and_then(|locked| {
+                    candidates
+                        .iter()
+                        .rev()
+                        .find(|candidate| &candidate.version == locked)
+                })
+                .or(newest),
+        };
+        Ok(chosen.map(|version| (*version).clone()))
+    }
+
+    fn get_dependencies(
+        &self,
+        package: &Self::P,
+        version: &Self::V,
+    ) -> Result<Dependencies<Self::P, Self::VS, Self::M>, Self::Err> {
+        self.dependencies.get_dependencies(package, version)
+    }
+}
 
 pub fn solve(
     gem: GemName,
     release: GemRelease,
     gem_info: HashMap<GemName, HashMap<VersionPlatform, GemRelease>>,
 ) -> Result<Vec<(ReleaseTuple, GemRelease)>, ResolutionError> {
-    let provider = all_dependencies(&gem_info);
+    let provider = PreferringProvider {
+        dependencies: all_dependencies(&gem_info),
+        preferences: Preferences::default(),
+        direct: HashSet::from([gem.clone()]),
+    };
     let solution = pubgrub::resolve(&provider, gem, release.version_platform)?;
 
-    Ok(solution
+    Ok(releases_in(solution, &gem_info))
+}
+
+/// Resolve every gem a project needs, given the requirements its Gemfile lists, to the newest
+/// versions that work.
+pub fn solve_project(
+    dependencies: Vec<ProjectDependency>,
+    gem_info: HashMap<GemName, HashMap<VersionPlatform, GemRelease>>,
+) -> Result<Vec<(ReleaseTuple, GemRelease)>, ResolutionError> {
+    solve_project_preferring(dependencies, gem_info, Preferences::default())
+}
+
+/// Resolve every gem a project needs, given the requirements its Gemfile lists, picking versions
+/// the way `preferences` say to.
+pub fn solve_project_preferring(
+    dependencies: Vec<ProjectDependency>,
+    gem_info: HashMap<GemName, HashMap<VersionPlatform, GemRelease>>,
+    preferences: Preferences,
+) -> Result<Vec<(ReleaseTuple, GemRelease)>, ResolutionError> {
+    let mut provider = all_dependencies(&gem_info);
+    let project_version = VersionPlatform::from_str("0").expect("0 is a valid version");
+    let direct = dependencies.iter().map(|dep| dep.name.clone()).collect();
+    provider.add_dependencies(
+        PROJECT.to_string(),
+        project_version.clone(),
+        dependencies
+            .into_iter()
+            .map(|dep| (dep.name, dep.requirement.into())),
+    );
+    let provider = PreferringProvider {
+        dependencies: provider,
+        preferences,
+        direct,
+    };
+    let solution = pubgrub::resolve(&provider, PROJECT.to_string(), project_version)?;
+
+    Ok(releases_in(
+        solution.into_iter().filter(|(gem, _)| gem != PROJECT),
+        &gem_info,
+    ))
+}
+
+/// Explain why there's no resolution, in terms of which gems need which versions.
+pub fn explain(err: &ResolutionError) -> String {
+    match err {
+        pubgrub::PubGrubError::NoSolution(tree) => {
+            let mut tree = tree.clone();
+            tree.collapse_no_versions();
+            pubgrub::DefaultStringReporter::report(&tree)
+        }
+        other => other.to_string(),
+    }
+}
+
+/// Look up the releases PubGrub chose.
+fn releases_in(
+    solution: impl IntoIterator<Item = (GemName, VersionPlatform)>,
+    gem_info: &HashMap<GemName, HashMap<VersionPlatform, GemRelease>>,
+) -> Vec<(ReleaseTuple, GemRelease)> {
+    solution
         .into_iter()
         .map(|(p, vp)| {
             let gem_release = gem_info[&p][&vp].clone();
@@ -29,7 +188,7 @@ pub fn solve(
 
             (release_tuple, gem_release)
         })
-        .collect())
+        .collect()
 }
 
 /// Build a PubGrub "dependency provider", i.e. something that can be queried
@@ -68,6 +227,83 @@ mod tests {
         VersionPlatform::from_str(input).unwrap()
     }
 
+    fn dep(name: &str, requirement: &str) -> ProjectDependency {
+        ProjectDependency {
+            name: name.to_string(),
+            requirement: requirement.parse().unwrap(),
+        }
+    }
+
+    /// Resolve a project that depends on `app`, which depends on `lib`, and return the versions
+    /// it picked.
+    fn resolve_with(preferences: Preferences) -> Vec<String> {
+        let mut gem_info: HashMap<GemName, HashMap<VersionPlatform, GemRelease>> = HashMap::new();
+        for (name, version, deps) in [
+            ("app", "1.0.0", vec![dep("lib", ">= 1.0")]),
+            ("app", "1.1.0", vec![dep("lib", ">= 1.0")]),
+            ("app", "2.0.0", vec![dep("lib", ">= 2.0")]),
+            ("lib", "1.0.0", vec![]),
+            ("lib", "2.0.0", vec![]),
+            ("lib", "2.1.0", vec![]),
+        ] {
+            let version_platform = vp(version);
+            gem_info.entry(name.to_string()).or_default().insert(
+                version_platform.clone(),
+                GemRelease {
+                    version_platform,
+                    deps,
+                    metadata: Default::default(),
+                },
+            );
+        }
+
+        let resolved =
+            solve_project_preferring(vec![dep("app", ">= 1.1")], gem_info, preferences).unwrap();
+        let mut versions: Vec<String> = resolved
+            .into_iter()
+            .map(|(tuple, _)| format!("{}-{}", tuple.name, tuple.version))
+            .collect();
+        versions.sort();
+        versions
+    }
+
+    #[test]
+    fn test_resolution_preferences() {
+        assert_eq!(
+            resolve_with(Preferences::default()),
+            ["app-2.0.0", "lib-2.1.0"]
+        );
+        assert_eq!(
+            resolve_with(Preferences {
+                resolution: Resolution::LowestDirect,
+                ..Default::default()
+            }),
+            ["app-1.1.0", "lib-2.1.0"]
+        );
+
+        let locked = |app: &str, lib: &str| {
+            HashMap::from([
+                ("app".to_string(), app.parse().unwrap()),
+                ("lib".to_string(), lib.parse().unwrap()),
+            ])
+        };
+        assert_eq!(
+            resolve_with(Preferences {
+                resolution: Resolution::MinimalChanges,
+                locked: locked("1.1.0", "1.0.0"),
+            }),
+            ["app-1.1.0", "lib-1.0.0"]
+        );
+        // A locked version that the Gemfile doesn't allow anymore gives way to the newest one.
+        assert_eq!(
+            resolve_with(Preferences {
+                resolution: Resolution::MinimalChanges,
+                locked: locked("1.0.0", "1.0.0"),
+            }),
+            ["app-2.0.0", "lib-2.1.0"]
+        );
+    }
+
     /// Tests that the conversion from RubyGems requirements to PubGrub ranges is correct.
     #[test]
     fn test_mapping() {
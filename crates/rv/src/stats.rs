@@ -0,0 +1,198 @@
+//! `--stats`: how many requests a command made, how much it downloaded, how often each cache
+//! bucket had what it needed, and how long each phase of the command took.
+
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use bytesize::ByteSize;
+use camino::Utf8Path;
+use rv_cache::BucketStats;
+use serde::Serialize;
+
+use crate::commands::clean_install::format_duration;
+
+static PHASES: Mutex<Vec<Phase>> = Mutex::new(Vec::new());
+
+/// A part of a command that's timed on its own, like downloading gems.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub(crate) struct Phase {
+    pub name: &'static str,
+    pub ms: u64,
+}
+
+/// Remember that the phase `name` took `elapsed`.
+pub(crate) fn record_phase(name: &'static str, elapsed: Duration) {
+    PHASES
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .push(Phase {
+            name,
+            ms: millis(elapsed),
+        });
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub(crate) struct Stats {
+    /// The command that ran, like `ruby list`.
+    pub command: String,
+    /// Whether it succeeded.
+    pub ok: bool,
+    /// When it started, in seconds since the Unix epoch.
+    pub started_at: u64,
+    pub total_ms: u64,
+    pub requests: u64,
+    pub http2_requests: u64,
+    pub bytes: u64,
+    /// Hits and misses by cache bucket, for the buckets the command looked in.
+    pub cache: BTreeMap<&'static str, BucketStats>,
+    pub phases: Vec<Phase>,
+}
+
+impl Stats {
+    /// Everything counted while `command`, which started at `started_at` and took `total`, ran.
+    pub(crate) fn collect(
+        command: String,
+        ok: bool,
+        started_at: SystemTime,
+        total: Duration,
+    ) -> Self {
+        let http = rv_client::stats::http_stats();
+        Self {
+            command,
+            ok,
+            started_at: started_at
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |since| since.as_secs()),
+            total_ms: millis(total),
+            requests: http.requests,
+            http2_requests: http.http2_requests,
+            bytes: http.bytes,
+            cache: rv_cache::cache_stats(),
+            phases: PHASES
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .clone(),
+        }
+    }
+
+    /// A summary for people, in the same layout as `rv ci --timings`.
+    pub(crate) fn render(&self) -> String {
+        let mut out = format!("Stats for `rv {}`:\n", self.command);
+        out += &format!(
+            "  {:<16}{:>8}  ({} over HTTP/2)\n",
+            "requests", self.requests, self.http2_requests
+        );
+        out += &format!(
+            "  {:<16}{:>8}\n",
+            "downloaded",
+            ByteSize(self.bytes).to_string()
+        );
+        for (bucket, stats) in &self.cache {
+            out += &format!(
+                "  {:<16}{:>8}  ({} hits, {} misses)\n",
+                format!("cache {bucket}"),
+                hit_rate(stats),
+                stats.hits,
+                stats.misses
+            );
+        }
+        for phase in &self.phases {
+            out += &format!(
+                "  {:<16}{:>8}\n",
+                phase.name,
+                format_duration(Duration::from_millis(phase.ms))
+            );
+        }
+        out += &format!(
+            "  {:<16}{:>8}\n",
+            "total",
+            format_duration(Duration::from_millis(self.total_ms))
+        );
+        out
+    }
+
+    /// Add the stats to the file at `path` as one line of JSON, so a file can collect the stats
+    /// of many runs.
+    pub(crate) fn append_to(&self, path: &Utf8Path) -> io::Result<()> {
+        if let Some(parent) = path.parent().filter(|parent| !parent.as_str().is_empty()) {
+            fs_err::create_dir_all(parent)?;
+        }
+        let mut line = serde_json::to_string(self).map_err(io::Error::other)?;
+        line.push('\n');
+        let mut file = fs_err::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+        file.write_all(line.as_bytes())
+    }
+}
+
+fn hit_rate(stats: &BucketStats) -> String {
+    let lookups = stats.hits + stats.misses;
+    if lookups == 0 {
+        return "-".to_string();
+    }
+    format!("{}%", stats.hits * 100 / lookups)
+}
+
+fn millis(duration: Duration) -> u64 {
+    u64::try_from(duration.as_millis()).unwrap_or(u64::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats() -> Stats {
+        Stats {
+            command: "ruby list".to_string(),
+            ok: true,
+            started_at: 1_700_000_000,
+            total_ms: 1500,
+            requests: 3,
+            http2_requests: 2,
+            bytes: 2048,
+            cache: BTreeMap::from([
+                ("gem", BucketStats { hits: 3, misses: 1 }),
+                ("ruby", BucketStats { hits: 0, misses: 2 }),
+            ]),
+            phases: vec![Phase {
+                name: "remote rubies",
+                ms: 1200,
+            }],
+        }
+    }
+
+    #[test]
+    fn test_render() {
+        insta::assert_snapshot!(stats().render(), @r"
+        Stats for `rv ruby list`:
+          requests               3  (2 over HTTP/2)
+          downloaded       2.0 KiB
+          cache gem            75%  (3 hits, 1 misses)
+          cache ruby            0%  (0 hits, 2 misses)
+          remote rubies       1.2s
+          total               1.5s
+        ");
+    }
+
+    #[test]
+    fn test_append_to() {
+        let temp_dir = camino_tempfile::Utf8TempDir::new().unwrap();
+        let path = temp_dir.path().join("logs/stats.jsonl");
+        stats().append_to(&path).unwrap();
+        stats().append_to(&path).unwrap();
+
+        let contents = fs_err::read_to_string(&path).unwrap();
+        let lines: Vec<serde_json::Value> = contents
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["command"], "ruby list");
+        assert_eq!(lines[0]["cache"]["gem"]["hits"], 3);
+        assert_eq!(lines[0]["phases"][0]["name"], "remote rubies");
+    }
+}
@@ -1,14 +1,21 @@
 use axoupdater::AxoUpdater;
+use camino::{Utf8Path, Utf8PathBuf};
+use minisign_verify::{PublicKey, Signature};
 use rv_client::http_client::rv_http_client;
 use rv_dirs::user_state_dir;
+use rv_platform::HostPlatform;
+use rv_ruby::Asset;
 use rv_version::Version;
 use serde::Deserialize;
+use std::io::Read;
 use std::path::PathBuf;
 use std::process::{Command, Stdio};
 use std::time::{SystemTime, UNIX_EPOCH};
 use std::{env, fs};
 use tracing::{debug, error};
 
+use crate::config::github::github_api_get;
+
 #[cfg(target_os = "windows")]
 use std::thread;
 #[cfg(target_os = "windows")]
@@ -16,28 +23,65 @@ use std::time::Duration;
 
 const UPDATE_CHECK_FILENAME: &str = "rv_last_update_check";
 const CHECK_INTERVAL_SECS: u64 = 60 * 60;
+const LATEST_RELEASE_URL: &str = "https://api.github.com/repos/spinel-coop/rv/releases/latest";
+/// The minisign public key release archives are signed with, set by the release workflow from
+/// the `RV_RELEASE_PUBLIC_KEY` repository variable.
+const RELEASE_PUBLIC_KEY: Option<&str> = option_env!("RV_RELEASE_PUBLIC_KEY");
 
 type Result<T> = miette::Result<T, Error>;
 
 #[derive(Debug, thiserror::Error, miette::Diagnostic)]
 pub enum Error {
     #[error(transparent)]
+    #[diagnostic(code(RV5101))]
     AxoupdateError(#[from] axoupdater::AxoupdateError),
 
     #[error(transparent)]
+    #[diagnostic(code(RV5102))]
     ReqwestError(#[from] reqwest::Error),
 
     #[error(transparent)]
+    #[diagnostic(code(RV5103))]
     IoError(#[from] std::io::Error),
 
     #[error("brew command failed: {0}")]
+    #[diagnostic(code(RV5104))]
     BrewFailed(String),
 
     #[error("update receipt invalid or not for this executable: {0}")]
+    #[diagnostic(code(RV5105))]
     ReceiptInvalid(String),
 
     #[error("relaunch failed: {0}")]
+    #[diagnostic(code(RV5106))]
     RelaunchFailed(String),
+
+    #[error(transparent)]
+    #[diagnostic(code(RV5107))]
+    UnsupportedPlatform(#[from] rv_platform::UnsupportedPlatformError),
+
+    #[error("the latest rv release has no {0} asset")]
+    #[diagnostic(code(RV5108))]
+    MissingAsset(String),
+
+    #[error("{file} does not have a valid signature: {reason}")]
+    #[diagnostic(code(RV5109))]
+    InvalidSignature { file: String, reason: String },
+
+    #[error("{0} does not contain the rv executable")]
+    #[diagnostic(code(RV5110))]
+    MissingExecutable(String),
+
+    #[error("could not find the running rv executable: {0}")]
+    #[diagnostic(code(RV5111))]
+    CurrentExe(String),
+
+    #[error("this rv was built without a release signing key, so it can't verify an update")]
+    #[diagnostic(
+        code(RV5112),
+        help("Update rv the way you installed it, or reinstall it with the rv installer.")
+    )]
+    NoReleaseKey,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -52,7 +96,7 @@ pub(crate) async fn check(update_mode: &str) {
         return;
     }
 
-    match run_update(update_mode).await {
+    match run_update(update_mode, false).await {
         Ok(UpdateOutcome::Installed(v)) => {
             eprintln!("✅ New version of `rv` {} installed!", v);
 
@@ -81,7 +125,9 @@ pub(crate) async fn check(update_mode: &str) {
     }
 }
 
-pub(crate) async fn run_update(update_mode: &str) -> Result<UpdateOutcome> {
+/// Update rv, or find out whether there's an update, as `update_mode` says. `explicit` is whether
+/// the user asked for the update with `rv self update`, rather than it being the automatic check.
+pub(crate) async fn run_update(update_mode: &str, explicit: bool) -> Result<UpdateOutcome> {
     let current_version = env!("CARGO_PKG_VERSION").to_string();
 
     if is_homebrew_install() {
@@ -102,7 +148,16 @@ pub(crate) async fn run_update(update_mode: &str) -> Result<UpdateOutcome> {
 
     let mut updater = AxoUpdater::new_for("rv");
 
-    updater.load_receipt().map_err(Error::AxoupdateError)?;
+    if let Err(err) = updater.load_receipt() {
+        // Without a receipt, rv may well have been installed by a package manager, whose files
+        // rv mustn't overwrite behind its back.
+        if explicit || is_rv_owned_install() {
+            debug!("No install receipt ({err}), so updating from the latest release directly.");
+            return update_from_release(update_mode).await;
+        }
+        debug!("No install receipt ({err}), so only checking for a newer release.");
+        return update_from_release("warning").await;
+    }
 
     let is_for_executable = updater
         .check_receipt_is_for_this_executable()
@@ -133,6 +188,223 @@ pub(crate) async fn run_update(update_mode: &str) -> Result<UpdateOutcome> {
     }
 }
 
+/// An rv release on GitHub.
+#[derive(Debug, Deserialize)]
+pub struct RvRelease {
+    tag_name: String,
+    assets: Vec<Asset>,
+}
+
+impl RvRelease {
+    pub fn version(&self) -> &str {
+        self.tag_name.trim_start_matches('v')
+    }
+
+    fn asset(&self, name: &str) -> Result<&Asset> {
+        self.assets
+            .iter()
+            .find(|asset| asset.name == name)
+            .ok_or_else(|| Error::MissingAsset(name.to_string()))
+    }
+}
+
+pub async fn latest_release() -> Result<RvRelease> {
+    let url = env::var("RV_SELF_UPDATE_URL").unwrap_or_else(|_| LATEST_RELEASE_URL.to_string());
+    let client = rv_http_client("rv_update")?;
+    let release = github_api_get(&client, url)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    Ok(release)
+}
+
+/// Is `latest` newer than the running rv?
+pub fn is_newer(latest: &str) -> bool {
+    let current = Version::new(env!("CARGO_PKG_VERSION")).unwrap();
+    Version::new(latest).is_ok_and(|latest| current < latest)
+}
+
+/// Whether the running rv is in the directory the rv installer puts it in, `$CARGO_HOME/bin`,
+/// rather than somewhere a package manager might own.
+fn is_rv_owned_install() -> bool {
+    let Ok(current_exe) = current_exe() else {
+        return false;
+    };
+    let cargo_home = env::var("CARGO_HOME")
+        .map(Utf8PathBuf::from)
+        .unwrap_or_else(|_| rv_dirs::home_dir().join(".cargo"));
+    current_exe.parent() == Some(cargo_home.join("bin").as_path())
+}
+
+/// The running rv executable, with symlinks resolved.
+fn current_exe() -> Result<Utf8PathBuf> {
+    let current_exe = env::current_exe()
+        .and_then(fs::canonicalize)
+        .map_err(|err| Error::CurrentExe(err.to_string()))?;
+    Utf8PathBuf::try_from(current_exe).map_err(|err| Error::CurrentExe(err.to_string()))
+}
+
+/// Update rv without an installer: download the release archive for this platform, check its
+/// minisign signature against the release key built into rv, and swap the new executables in for
+/// the old ones.
+async fn update_from_release(update_mode: &str) -> Result<UpdateOutcome> {
+    let release = latest_release().await?;
+    let latest_version = release.version().to_string();
+    if !is_newer(&latest_version) {
+        return Ok(UpdateOutcome::AlreadyUpToDate);
+    }
+    if update_mode == "warning" {
+        return Ok(UpdateOutcome::UpdateAvailable(latest_version));
+    }
+
+    let public_key = RELEASE_PUBLIC_KEY
+        .filter(|key| !key.is_empty())
+        .ok_or(Error::NoReleaseKey)?;
+    let host = HostPlatform::current()?;
+    let archive_name = release_archive_name(host);
+    let archive_asset = release.asset(&archive_name)?;
+    let signature_asset = release.asset(&format!("{archive_name}.minisig"))?;
+
+    let client = rv_http_client("rv_update")?;
+    debug!("Downloading {}", archive_asset.browser_download_url);
+    let archive = client
+        .get(&archive_asset.browser_download_url)
+        .send()
+        .await?
+        .error_for_status()?
+        .bytes()
+        .await?;
+    let signature = client
+        .get(&signature_asset.browser_download_url)
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+    verify_signature(&archive_name, &archive, &signature, public_key)?;
+
+    let current_exe = current_exe()?;
+    let install_dir = current_exe
+        .parent()
+        .ok_or_else(|| Error::CurrentExe(current_exe.to_string()))?;
+
+    // Unpack next to the current executables, so they can be replaced with a rename.
+    let unpack_dir = camino_tempfile::tempdir_in(install_dir)?;
+    let executables = unpack_executables(&archive_name, &archive, unpack_dir.path())?;
+    if !executables
+        .iter()
+        .any(|exe| current_exe.file_name() == exe.file_name())
+    {
+        return Err(Error::MissingExecutable(archive_name));
+    }
+
+    for new_exe in executables {
+        let name = new_exe.file_name().unwrap();
+        let installed = install_dir.join(name);
+        // Only replace the executables this install has, e.g. don't add `rvw` next to a lone `rv`.
+        if installed.exists() {
+            debug!("Replacing {installed}");
+            replace_executable(&new_exe, &installed)?;
+        }
+    }
+
+    Ok(UpdateOutcome::Installed(latest_version))
+}
+
+/// The name of the release archive built for `host`.
+fn release_archive_name(host: HostPlatform) -> String {
+    let extension = if host.is_windows() { "zip" } else { "tar.gz" };
+    format!("rv-{}.{extension}", host.target_triple())
+}
+
+/// Check `contents` against its minisign `signature` file, made with the secret half of the
+/// base64 `public_key`.
+fn verify_signature(file: &str, contents: &[u8], signature: &str, public_key: &str) -> Result<()> {
+    let invalid = |reason: String| Error::InvalidSignature {
+        file: file.to_string(),
+        reason,
+    };
+    let public_key = PublicKey::from_base64(public_key).map_err(|err| invalid(err.to_string()))?;
+    let signature = Signature::decode(signature).map_err(|err| invalid(err.to_string()))?;
+    public_key
+        .verify(contents, &signature, false)
+        .map_err(|err| invalid(err.to_string()))
+}
+
+fn is_rv_executable(name: &str) -> bool {
+    let name = name.strip_suffix(".exe").unwrap_or(name);
+    matches!(name, "rv" | "rvx" | "rvw")
+}
+
+/// Unpack the rv executables from a release archive into `dir`, wherever they are in the archive.
+fn unpack_executables(
+    archive_name: &str,
+    archive: &[u8],
+    dir: &Utf8Path,
+) -> Result<Vec<Utf8PathBuf>> {
+    let mut unpacked = vec![];
+    let mut write = |name: &str, reader: &mut dyn Read| -> Result<()> {
+        let path = dir.join(name);
+        let mut file = fs_err::File::create(&path)?;
+        std::io::copy(reader, &mut file)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs_err::set_permissions(&path, fs::Permissions::from_mode(0o755))?;
+        }
+        unpacked.push(path);
+        Ok(())
+    };
+
+    if archive_name.ends_with(".zip") {
+        let mut zip = zip::ZipArchive::new(std::io::Cursor::new(archive))
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+        for i in 0..zip.len() {
+            let mut entry = zip
+                .by_index(i)
+                .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+            let Some(name) = entry
+                .enclosed_name()
+                .and_then(|path| path.file_name()?.to_str().map(str::to_string))
+            else {
+                continue;
+            };
+            if entry.is_file() && is_rv_executable(&name) {
+                write(&name, &mut entry)?;
+            }
+        }
+    } else {
+        let mut tar = tar::Archive::new(flate2::read::GzDecoder::new(archive));
+        for entry in tar.entries()? {
+            let mut entry = entry?;
+            let path = entry.path()?.into_owned();
+            let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+                continue;
+            };
+            if entry.header().entry_type().is_file() && is_rv_executable(name) {
+                write(name, &mut entry)?;
+            }
+        }
+    }
+
+    Ok(unpacked)
+}
+
+/// Atomically put `new` in place of `installed`. Windows won't overwrite a running executable,
+/// but it can rename one, so the old executable is moved aside first, to be removed by the next
+/// update.
+fn replace_executable(new: &Utf8Path, installed: &Utf8Path) -> Result<()> {
+    if cfg!(windows) {
+        let old = installed.with_extension("exe.old");
+        let _ = fs_err::remove_file(&old);
+        fs_err::rename(installed, &old)?;
+    }
+    fs_err::rename(new, installed)?;
+    Ok(())
+}
+
 fn is_time_to_check() -> bool {
     let state_dir = user_state_dir("/".into());
 
@@ -329,3 +601,79 @@ pub fn relaunch() -> Result<()> {
         std::process::exit(1);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tar_gz(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(flate2::write::GzEncoder::new(
+            Vec::new(),
+            flate2::Compression::default(),
+        ));
+        for (path, contents) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, path, *contents).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    #[test]
+    fn test_release_archive_name() {
+        let linux = HostPlatform::from_target_triple("x86_64-unknown-linux-gnu").unwrap();
+        assert_eq!(
+            release_archive_name(linux),
+            "rv-x86_64-unknown-linux-gnu.tar.gz"
+        );
+        let windows = HostPlatform::from_target_triple("x86_64-pc-windows-msvc").unwrap();
+        assert_eq!(
+            release_archive_name(windows),
+            "rv-x86_64-pc-windows-msvc.zip"
+        );
+    }
+
+    #[test]
+    fn test_verify_signature() {
+        // A key made for this test, and its minisign signature of `rv`.
+        let public_key = "RWQw9OHQeOMI7jOJbCe0LPvOht9Rsglsv/uNrWjjZ66jEZg1inuaOQSx";
+        let signature = "untrusted comment: signature from minisign secret key\n\
+            RUQw9OHQeOMI7gIRk7eFUFZ7FknNoDsAIh9agyezocK4D8Uqk2G+xnKzHdy8MglrEQFz6nk04qrSHCtYj9p09i93hLK58clHYgo=\n\
+            trusted comment: timestamp:1760000000\tfile:rv.tar.gz\thashed\n\
+            gT4cm1wDTP3HvB8sGupEIoQ7/bkZSzdl8wxUSjTWvA7xocs166Fg0gS/d0txebQrBDuSxv0lRZSLMF6exkZUBQ==\n";
+        verify_signature("rv.tar.gz", b"rv", signature, public_key).unwrap();
+
+        let err = verify_signature("rv.tar.gz", b"not rv", signature, public_key).unwrap_err();
+        assert!(matches!(err, Error::InvalidSignature { .. }));
+
+        let err = verify_signature("rv.tar.gz", b"rv", "garbage", public_key).unwrap_err();
+        assert!(matches!(err, Error::InvalidSignature { .. }));
+    }
+
+    #[test]
+    fn test_unpack_and_replace_executables() {
+        let dir = camino_tempfile::tempdir().unwrap();
+        let archive = tar_gz(&[
+            ("rv-x86_64-unknown-linux-gnu/rv", b"new rv"),
+            ("rv-x86_64-unknown-linux-gnu/rvx", b"new rvx"),
+            ("rv-x86_64-unknown-linux-gnu/README.md", b"readme"),
+        ]);
+
+        let unpack_dir = dir.path().join("unpack");
+        fs_err::create_dir_all(&unpack_dir).unwrap();
+        let mut executables = unpack_executables("rv.tar.gz", &archive, &unpack_dir).unwrap();
+        executables.sort();
+        assert_eq!(
+            executables,
+            vec![unpack_dir.join("rv"), unpack_dir.join("rvx")]
+        );
+
+        let installed = dir.path().join("rv");
+        fs_err::write(&installed, "old rv").unwrap();
+        replace_executable(&executables[0], &installed).unwrap();
+        assert_eq!(fs_err::read_to_string(&installed).unwrap(), "new rv");
+        assert!(!executables[0].exists());
+    }
+}
@@ -0,0 +1,149 @@
+use crate::common::RvTest;
+
+#[test]
+fn test_cache_verify_empty_cache() {
+    let mut test = RvTest::new();
+    test.enable_cache();
+
+    let output = test.rv(&["cache", "verify"]);
+    output.assert_success();
+    output.assert_stdout_contains("Verified 0 cache entries, 0 corrupt");
+}
+
+#[test]
+fn test_cache_verify_finds_and_fixes_truncated_archive() {
+    let mut test = RvTest::new();
+    let cache_dir = test.enable_cache();
+
+    let tarballs = cache_dir.join("ruby-v0/tarballs");
+    std::fs::create_dir_all(&tarballs).unwrap();
+    let tarball = test.create_mock_tarball("3.4.1");
+    let archive = tarballs.join("deadbeef.tar.gz");
+    std::fs::write(&archive, &tarball[..tarball.len() / 2]).unwrap();
+
+    let output = test.rv(&["cache", "verify"]);
+    output.assert_failure();
+    output.assert_stdout_contains("corrupt ruby archive");
+    output.assert_stderr_contains("CorruptEntries { count: 1 }");
+    assert!(archive.exists());
+
+    let output = test.rv(&["cache", "verify", "--fix"]);
+    output.assert_success();
+    output.assert_stdout_contains("Removed 1 corrupt entries");
+    assert!(!archive.exists());
+}
+
+#[test]
+fn test_cache_verify_fix_downloads_corrupt_gem_again() {
+    let mut test = RvTest::new();
+    let cache_dir = test.enable_cache();
+
+    test.create_ruby_dir("ruby-4.0.1");
+    test.use_gemfile("../rv-lockfile/tests/inputs/Gemfile.testsource");
+    test.use_lockfile("../rv-lockfile/tests/inputs/Gemfile.testsource.lock");
+    test.replace_source("http://gems.example.com", &test.server_url());
+
+    let download = test
+        .mock_gem_download("test-gem-1.0.0.gem")
+        .expect(2)
+        .create();
+    test.ci(&[]).assert_success();
+
+    let gems = cache_dir.join("gem-v0/gems");
+    let cached = std::fs::read_dir(&gems)
+        .unwrap()
+        .next()
+        .unwrap()
+        .unwrap()
+        .path();
+    let contents = std::fs::read(&cached).unwrap();
+    std::fs::write(&cached, &contents[..contents.len() / 2]).unwrap();
+
+    let output = test.rv(&["cache", "verify", "--fix"]);
+    output.assert_success();
+    output.assert_stdout_contains("corrupt gem");
+    output.assert_stdout_contains("Downloaded 1 corrupt entries again");
+    download.assert();
+    assert_eq!(std::fs::read(&cached).unwrap(), contents);
+}
+
+#[test]
+fn test_corrupt_cached_gem_is_quarantined_and_downloaded_again() {
+    let mut test = RvTest::new();
+    let cache_dir = test.enable_cache();
+
+    test.create_ruby_dir("ruby-4.0.1");
+    test.use_gemfile("../rv-lockfile/tests/inputs/Gemfile.testsource");
+    test.use_lockfile("../rv-lockfile/tests/inputs/Gemfile.testsource.lock");
+    test.replace_source("http://gems.example.com", &test.server_url());
+
+    let download = test
+        .mock_gem_download("test-gem-1.0.0.gem")
+        .expect(2)
+        .create();
+    test.ci(&[]).assert_success();
+
+    let gems = cache_dir.join("gem-v0/gems");
+    let cached = std::fs::read_dir(&gems)
+        .unwrap()
+        .next()
+        .unwrap()
+        .unwrap()
+        .path();
+    let contents = std::fs::read(&cached).unwrap();
+    std::fs::write(&cached, &contents[..contents.len() / 2]).unwrap();
+
+    let output = test.ci(&["--force"]);
+    output.assert_success();
+    output.assert_stderr_contains("is corrupt");
+    download.assert();
+
+    assert_eq!(std::fs::read(&cached).unwrap(), contents);
+    let quarantined = cache_dir
+        .join("gem-v0/quarantine")
+        .join(cached.file_name().unwrap());
+    assert_eq!(
+        std::fs::read(quarantined).unwrap(),
+        &contents[..contents.len() / 2]
+    );
+}
+
+#[test]
+fn test_cache_export_and_import() {
+    let mut test = RvTest::new();
+    let cache_dir = test.enable_cache();
+
+    test.create_ruby_dir("ruby-4.0.1");
+    test.use_gemfile("../rv-lockfile/tests/inputs/Gemfile.testsource");
+    test.use_lockfile("../rv-lockfile/tests/inputs/Gemfile.testsource.lock");
+    test.replace_source("http://gems.example.com", &test.server_url());
+
+    // Nothing is cached yet, so there's nothing to export.
+    let archive = test.temp_root().join("rv-cache.tar.gz");
+    let output = test.rv(&["cache", "export", archive.as_str()]);
+    output.assert_failure();
+    output.assert_stderr_contains("NotCached");
+
+    let download = test.mock_gem_download("test-gem-1.0.0.gem").create();
+    test.ci(&[]).assert_success();
+    download.assert();
+
+    let output = test.rv(&["cache", "export", archive.as_str()]);
+    output.assert_success();
+    output.assert_stdout_contains("Exported 1 cache entries (1 files)");
+
+    std::fs::remove_dir_all(&cache_dir).unwrap();
+    let output = test.rv(&["cache", "import", archive.as_str()]);
+    output.assert_success();
+    output.assert_stdout_contains("Imported 1 cache entries");
+    assert_eq!(
+        std::fs::read_dir(cache_dir.join("gem-v0/gems"))
+            .unwrap()
+            .count(),
+        1
+    );
+
+    let output = test.rv(&["cache", "import", archive.as_str()]);
+    output.assert_success();
+    output.assert_stdout_contains("skipped 1 that were already cached");
+}
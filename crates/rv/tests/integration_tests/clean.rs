@@ -0,0 +1,71 @@
+use crate::common::RvTest;
+
+fn write(path: &camino::Utf8Path, contents: &str) {
+    fs_err::create_dir_all(path.parent().unwrap()).unwrap();
+    fs_err::write(path, contents).unwrap();
+}
+
+#[test]
+fn test_clean_removes_project_artifacts() {
+    let mut test = RvTest::new();
+    let cache_dir = test.enable_cache();
+    write(&cache_dir.join("gems/rake.gem"), "cached");
+
+    let project_dir = test.temp_root().join("project");
+    write(
+        &project_dir.join(".rv/gems/ruby/3.4.0/bin/rake"),
+        "0123456789",
+    );
+    write(
+        &project_dir.join(".bundle/config"),
+        "BUNDLE_PATH: \"vendor/bundle\"\n",
+    );
+    write(
+        &project_dir.join("vendor/bundle/ruby/3.4.0/extensions/json/build_ext.log"),
+        "01234",
+    );
+    write(&project_dir.join("Gemfile.lock"), "");
+    test.cwd = project_dir.clone();
+
+    let output = test.rv(&["clean", "--dry-run"]);
+    output.assert_success();
+    output.assert_stdout_contains("Would remove .rv (project gems");
+    output.assert_stdout_contains("Would remove vendor/bundle (Bundler's `path`, 5B)");
+    output.assert_stdout_contains("Would free 15B");
+    assert!(project_dir.join(".rv").exists());
+    assert!(project_dir.join("vendor/bundle").exists());
+
+    let output = test.rv(&["clean"]);
+    output.assert_success();
+    output.assert_stdout_contains("Removed vendor/bundle");
+    output.assert_stdout_contains("Freed 15B");
+    assert!(!project_dir.join(".rv").exists());
+    assert!(!project_dir.join("vendor/bundle").exists());
+    assert!(project_dir.join("Gemfile.lock").exists());
+    assert!(cache_dir.join("gems/rake.gem").exists());
+
+    let output = test.rv(&["clean"]);
+    output.assert_success();
+    output.assert_stdout_contains("Nothing to clean");
+}
+
+#[test]
+fn test_clean_leaves_gem_paths_outside_the_project() {
+    let mut test = RvTest::new();
+
+    let shared_gems = test.temp_root().join("shared-gems");
+    write(&shared_gems.join("ruby/3.4.0/bin/rake"), "rake");
+
+    let project_dir = test.temp_root().join("project");
+    write(
+        &project_dir.join(".bundle/config"),
+        &format!("BUNDLE_PATH: \"{shared_gems}\"\n"),
+    );
+    write(&project_dir.join("Gemfile.lock"), "");
+    test.cwd = project_dir;
+
+    let output = test.rv(&["clean"]);
+    output.assert_success();
+    output.assert_stdout_contains("Nothing to clean");
+    assert!(shared_gems.join("ruby/3.4.0/bin/rake").exists());
+}
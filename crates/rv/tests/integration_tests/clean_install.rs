@@ -26,6 +26,240 @@ fn test_clean_install_download_test_gem() {
     mock.assert();
 }
 
+#[test]
+fn test_clean_install_porcelain() {
+    let mut test = RvTest::new();
+
+    test.create_ruby_dir("ruby-4.0.1");
+
+    test.use_gemfile("../rv-lockfile/tests/inputs/Gemfile.testsource");
+    test.use_lockfile("../rv-lockfile/tests/inputs/Gemfile.testsource.lock");
+    test.replace_source("http://gems.example.com", &test.server_url());
+
+    let mock = test.mock_gem_download("test-gem-1.0.0.gem").create();
+
+    let output = test.ci(&["--porcelain"]);
+    output.assert_success();
+    mock.assert();
+
+    let stdout = output.normalized_stdout();
+    let fields: Vec<_> = stdout.trim_end().split('\t').collect();
+    assert_eq!(fields[..2], ["installed", "test-gem-1.0.0"], "{stdout}");
+    assert!(fields[2].parse::<u128>().is_ok(), "{stdout}");
+
+    // Running again skips the gem, which is already installed
+    let output = test.ci(&["--porcelain"]);
+    output.assert_success();
+    assert_eq!(output.normalized_stdout(), "skipped\ttest-gem-1.0.0\t\n");
+}
+
+#[test]
+fn test_clean_install_retries_transient_download_failures() {
+    let mut test = RvTest::new();
+
+    test.create_ruby_dir("ruby-4.0.1");
+
+    test.use_gemfile("../rv-lockfile/tests/inputs/Gemfile.testsource");
+    test.use_lockfile("../rv-lockfile/tests/inputs/Gemfile.testsource.lock");
+    test.replace_source("http://gems.example.com", &test.server_url());
+
+    let path = test.gem_package_download_path("test-gem-1.0.0.gem");
+    let mock = test
+        .mock_request("GET", &path)
+        .with_status(503)
+        .expect(2)
+        .create();
+
+    let output = test.ci(&["--retries", "1", "--keep-going"]);
+    output.assert_failure();
+    mock.assert();
+    output.assert_stderr_contains("test-gem-1.0.0: HTTP status server error (503");
+    output.assert_stderr_contains("DownloadFailures");
+}
+
+#[test]
+fn test_clean_install_reports_failures() {
+    let mut test = RvTest::new();
+
+    test.create_ruby_dir("ruby-4.0.1");
+
+    test.use_gemfile("../rv-lockfile/tests/inputs/Gemfile.testsource");
+    test.use_lockfile("../rv-lockfile/tests/inputs/Gemfile.testsource.lock");
+    test.replace_source("http://gems.example.com", &test.server_url());
+
+    let path = test.gem_package_download_path("test-gem-1.0.0.gem");
+    let mock = test.mock_request("GET", &path).with_status(404).create();
+
+    let report = test.temp_root().join("reports/junit.xml");
+    let output = test.ci(&["--report", report.as_str(), "--github-annotations"]);
+    output.assert_failure();
+    mock.assert();
+    output.assert_stdout_contains("::error title=rv ci%3A test-gem-1.0.0 failed to download::");
+
+    let junit = std::fs::read_to_string(&report).unwrap();
+    assert!(junit.contains(r#"<testsuite name="rv ci" tests="1" failures="1""#));
+    assert!(junit.contains(r#"<failure message="#));
+    assert!(junit.contains(r#"type="download""#));
+}
+
+#[test]
+fn test_clean_install_timings() {
+    let mut test = RvTest::new();
+
+    test.create_ruby_dir("ruby-4.0.1");
+
+    test.use_gemfile("../rv-lockfile/tests/inputs/Gemfile.testsource");
+    test.use_lockfile("../rv-lockfile/tests/inputs/Gemfile.testsource.lock");
+    test.replace_source("http://gems.example.com", &test.server_url());
+
+    let mock = test.mock_gem_download("test-gem-1.0.0.gem").create();
+
+    let output = test.ci(&["--timings"]);
+    output.assert_success();
+    mock.assert();
+
+    output.assert_stdout_contains("Timings:");
+    output
+        .assert_stdout_contains("0 cached, 1 downloaded in 1 requests, 0 over HTTP/2, 0 coalesced");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_clean_install_runs_hooks() {
+    let mut test = RvTest::new();
+
+    test.create_ruby_dir("ruby-4.0.1");
+
+    test.use_gemfile("../rv-lockfile/tests/inputs/Gemfile.testsource");
+    test.use_lockfile("../rv-lockfile/tests/inputs/Gemfile.testsource.lock");
+    test.replace_source("http://gems.example.com", &test.server_url());
+
+    let log = test.temp_root().join("hooks.log");
+    let hook = format!("echo $RV_HOOK $RV_GEM_FULL_NAME >> {log}");
+    std::fs::write(
+        test.current_dir().join("rv.kdl"),
+        format!(
+            "rv {{\n  hook \"pre-download\" \"{hook}\"\n  hook \"post-unpack\" \"{hook}\"\n  hook \"post-install\" \"{hook}\"\n}}\n"
+        ),
+    )
+    .unwrap();
+
+    let mock = test.mock_gem_download("test-gem-1.0.0.gem").create();
+
+    let output = test.ci(&[]);
+    output.assert_success();
+    mock.assert();
+
+    assert_eq!(
+        std::fs::read_to_string(&log).unwrap(),
+        "pre-download test-gem-1.0.0\npost-unpack test-gem-1.0.0\npost-install test-gem-1.0.0\n"
+    );
+
+    // A failing hook stops the install.
+    std::fs::write(
+        test.current_dir().join("rv.kdl"),
+        "rv {\n  hook \"pre-download\" \"exit 1\"\n}\n",
+    )
+    .unwrap();
+    let output = test.ci(&["--force"]);
+    output.assert_failure();
+    output.assert_stderr_contains("The pre-download hook `exit 1` failed for test-gem-1.0.0");
+}
+
+#[test]
+fn test_clean_install_credential_helper() {
+    let mut test = RvTest::new();
+
+    test.create_ruby_dir("ruby-4.0.1");
+
+    test.use_gemfile("../rv-lockfile/tests/inputs/Gemfile.testsource");
+    test.use_lockfile("../rv-lockfile/tests/inputs/Gemfile.testsource.lock");
+    test.replace_source("http://gems.example.com", &test.server_url());
+
+    std::fs::write(
+        test.current_dir().join("rv.kdl"),
+        "rv {\n  credential-helper \"env\"\n}\n",
+    )
+    .unwrap();
+    test.env
+        .insert("RV_CREDENTIAL_127__0__0__1".into(), "ci:s3cret".into());
+
+    let mock = test
+        .mock_gem_download("test-gem-1.0.0.gem")
+        .match_header("authorization", "Basic Y2k6czNjcmV0")
+        .create();
+
+    let output = test.ci(&[]);
+    output.assert_success();
+    mock.assert();
+}
+
+#[test]
+fn test_clean_install_remote_cache_write_through() {
+    let mut test = RvTest::new();
+
+    test.create_ruby_dir("ruby-4.0.1");
+
+    test.use_gemfile("../rv-lockfile/tests/inputs/Gemfile.testsource");
+    test.use_lockfile("../rv-lockfile/tests/inputs/Gemfile.testsource.lock");
+    test.replace_source("http://gems.example.com", &test.server_url());
+
+    std::fs::write(
+        test.current_dir().join("rv.kdl"),
+        format!(
+            "rv {{\n  remote-cache \"s3://ci-cache/rv\"\n  remote-cache-endpoint \"{}\"\n  remote-cache-mode \"write-through\"\n}}\n",
+            test.server_url()
+        ),
+    )
+    .unwrap();
+
+    let object = mockito::Matcher::Regex(r"^/ci-cache/rv/gems/[^/]+\.gem$".to_owned());
+    let miss = test
+        .server
+        .mock("GET", object.clone())
+        .with_status(404)
+        .create();
+    let upload = test.server.mock("PUT", object).with_status(200).create();
+    let download = test.mock_gem_download("test-gem-1.0.0.gem").create();
+
+    let output = test.ci(&[]);
+    output.assert_success();
+    miss.assert();
+    download.assert();
+    upload.assert();
+}
+
+#[test]
+fn test_clean_install_sha512_checksums() {
+    let mut test = RvTest::new();
+
+    test.create_ruby_dir("ruby-4.0.1");
+
+    test.use_gemfile("../rv-lockfile/tests/inputs/Gemfile.testsource");
+    test.use_lockfile("../rv-lockfile/tests/inputs/Gemfile.testsource.lock");
+    test.replace_source("http://gems.example.com", &test.server_url());
+    let _download = test.mock_gem_download("test-gem-1.0.0.gem").create();
+
+    let unchecked = "  test-gem (1.0.0)\n\nBUNDLED";
+    let wrong = format!("  test-gem (1.0.0) sha512={}\n\nBUNDLED", "00".repeat(64));
+    test.replace_source(unchecked, &wrong);
+    let output = test.ci(&[]);
+    output.assert_failure();
+    output.assert_stderr_contains("LockfileChecksumFail");
+    output.assert_stderr_contains("algo: \"sha512\"");
+
+    let right = "  test-gem (1.0.0) sha512=1d7cb51250f7e924d6ee8c38af02a022d100d0763b1a8ae41b3b341503dcdc77d246cad1a8b8723afa63f589ce4b85ac8f11d86107b7e9607e71363335f4a1ab\n\nBUNDLED";
+    test.replace_source(&wrong, right);
+    test.ci(&[]).assert_success();
+
+    let unsupported = format!("  test-gem (1.0.0) blake3={}\n\nBUNDLED", "00".repeat(32));
+    test.replace_source(right, &unsupported);
+    let output = test.ci(&["--unsupported-checksums", "error"]);
+    output.assert_failure();
+    output.assert_stderr_contains("UnsupportedChecksum");
+    test.ci(&["--force"]).assert_success();
+}
+
 #[test]
 fn test_clean_install_input_validation() {
     let mut test = RvTest::new();
@@ -367,3 +601,41 @@ fn find_all_files_in_dir(cwd: &std::path::Path) -> String {
     lines.sort();
     lines.join("\n")
 }
+
+#[test]
+fn test_clean_install_vendor_and_local() {
+    let mut test = RvTest::new();
+
+    test.create_ruby_dir("ruby-4.0.1");
+
+    test.use_gemfile("../rv-lockfile/tests/inputs/Gemfile.testsource");
+    test.use_lockfile("../rv-lockfile/tests/inputs/Gemfile.testsource.lock");
+    test.replace_source("http://gems.example.com", &test.server_url());
+
+    // Without a vendored copy, a local install has nothing to install from.
+    let output = test.rv(&["--error-format", "json", "ci", "--local"]);
+    output.assert_failure();
+    output.assert_stderr_contains(r#""code":"RV2022""#);
+
+    let mock = test.mock_gem_download("test-gem-1.0.0.gem").create();
+    let output = test.ci(&["--vendor"]);
+    output.assert_success();
+    output.assert_stdout_contains("Vendored gems into");
+    mock.assert();
+
+    let vendored = test.current_dir().join("vendor/cache/test-gem-1.0.0.gem");
+    assert_eq!(
+        fs_err::read(&vendored).unwrap(),
+        fs_err::read("../rv-gem-package/tests/fixtures/test-gem-1.0.0.gem").unwrap()
+    );
+
+    // Installing again never touches the gem server.
+    fs_err::remove_dir_all(test.current_dir().join("app")).unwrap();
+    test.ci(&["--local"]).assert_success();
+    assert!(
+        test.current_dir()
+            .join("app/ruby/4.0.0/specifications/test-gem-1.0.0.gemspec")
+            .exists()
+    );
+    mock.assert();
+}
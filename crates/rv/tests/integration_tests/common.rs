@@ -501,7 +501,13 @@ impl RvTest {
         } else {
             name
         };
-        let ruby_dir = self.rubies_dir().join(dir_name);
+        self.create_ruby_dir_at(&self.rubies_dir().join(dir_name), name)
+    }
+
+    /// Create a mock Ruby named like `name`, e.g. `ruby-3.4.1`, in `ruby_dir`, which can be
+    /// outside of the rubies directory.
+    pub fn create_ruby_dir_at(&self, ruby_dir: &Utf8Path, name: &str) -> Utf8PathBuf {
+        let ruby_dir = ruby_dir.to_path_buf();
         std::fs::create_dir_all(&ruby_dir).expect("Failed to create ruby directory");
 
         let bin_dir = ruby_dir.join("bin");
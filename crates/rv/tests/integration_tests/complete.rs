@@ -0,0 +1,51 @@
+use crate::common::RvTest;
+
+#[test]
+fn test_complete_installed_rubies() {
+    let test = RvTest::new();
+    test.create_ruby_dir("ruby-3.3.5");
+    test.create_ruby_dir("ruby-3.4.1");
+    test.create_ruby_dir("jruby-9.4.12.0");
+
+    let output = test.rv(&["__complete", "installed-rubies"]);
+    output.assert_success();
+    let stdout = output.normalized_stdout();
+    let candidates: Vec<_> = stdout.lines().collect();
+    assert!(candidates.contains(&"3.3.5"), "{candidates:?}");
+    assert!(candidates.contains(&"3.4.1"), "{candidates:?}");
+    assert!(candidates.contains(&"jruby-9.4.12.0"), "{candidates:?}");
+
+    let output = test.rv(&["__complete", "installed-rubies", "3.4"]);
+    output.assert_success();
+    assert_eq!(output.normalized_stdout(), "3.4.1\n");
+}
+
+#[test]
+fn test_complete_cached_gems() {
+    let mut test = RvTest::new();
+    let cache_dir = test.enable_cache();
+    let info_dir = cache_dir.join("gemdeps-v0/compact_index/info");
+    std::fs::create_dir_all(&info_dir).unwrap();
+    for gem in ["rack", "rails", "rake"] {
+        std::fs::write(info_dir.join(gem), "---\n").unwrap();
+    }
+
+    let output = test.rv(&["__complete", "gems", "ra"]);
+    output.assert_success();
+    let stdout = output.normalized_stdout();
+    let mut candidates: Vec<_> = stdout.lines().collect();
+    candidates.sort();
+    assert_eq!(candidates, ["rack", "rails", "rake"]);
+
+    let output = test.rv(&["__complete", "gems", "rai"]);
+    output.assert_success();
+    assert_eq!(output.normalized_stdout(), "rails\n");
+}
+
+#[test]
+fn test_complete_is_hidden() {
+    let test = RvTest::new();
+    let output = test.rv(&["--help"]);
+    output.assert_success();
+    assert!(!output.stdout().contains("__complete"));
+}
@@ -0,0 +1,51 @@
+use crate::common::RvTest;
+
+#[cfg(unix)]
+fn set_path(test: &mut RvTest, dirs: &[camino::Utf8PathBuf]) {
+    let path = std::env::join_paths(dirs).unwrap();
+    test.env
+        .insert("PATH".into(), path.to_str().unwrap().to_string());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_doctor_warns_about_system_ruby_before_rv_rubies() {
+    let mut test = RvTest::new();
+    let rv_ruby = test.create_ruby_dir("ruby-3.4.1");
+    let system_ruby = test.create_ruby_dir_at(&test.temp_root().join("usr"), "ruby-2.6.10");
+    set_path(&mut test, &[system_ruby.join("bin"), rv_ruby.join("bin")]);
+
+    let output = test.rv(&["doctor"]);
+    output.assert_failure();
+    output.assert_stdout_contains("The `ruby` on PATH is the system's Ruby");
+    output.assert_stdout_contains("comes before the rubies rv installed");
+    output.assert_stdout_contains("System Integrity Protection");
+    output.assert_stdout_contains("Found the system's Ruby, ruby-2.6.10");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_doctor_is_happy_with_rv_ruby_first() {
+    let mut test = RvTest::new();
+    let rv_ruby = test.create_ruby_dir("ruby-3.4.1");
+    let homebrew_ruby = test.create_ruby_dir_at(
+        &test.temp_root().join("opt/homebrew/Cellar/ruby/3.4.7"),
+        "ruby-3.4.7",
+    );
+    set_path(&mut test, &[rv_ruby.join("bin"), homebrew_ruby.join("bin")]);
+
+    let output = test.rv(&["doctor"]);
+    output.assert_success();
+    output.assert_stdout_contains("is one rv found");
+    output.assert_stdout_contains("Found Homebrew's Ruby, ruby-3.4.7");
+}
+
+#[test]
+fn test_doctor_warns_without_ruby_on_path() {
+    let test = RvTest::new();
+    test.create_ruby_dir("ruby-3.4.1");
+
+    let output = test.rv(&["doctor"]);
+    output.assert_failure();
+    output.assert_stdout_contains("There's no `ruby` on PATH");
+}
@@ -0,0 +1,71 @@
+use crate::common::RvTest;
+
+fn json_error(stderr: &str) -> serde_json::Value {
+    let line = stderr
+        .lines()
+        .find(|line| line.starts_with('{'))
+        .unwrap_or_else(|| panic!("no JSON error in stderr:\n{stderr}"));
+    serde_json::from_str(line).unwrap()
+}
+
+#[test]
+fn test_error_format_json() {
+    let test = RvTest::new();
+    test.create_ruby_dir("ruby-3.4.1");
+
+    let output = test.rv(&["--error-format", "json", "ruby", "find", "3.3"]);
+    output.assert_failure();
+    assert_eq!(
+        json_error(&output.stderr()),
+        serde_json::json!({
+            "code": "RV1001",
+            "message": "no matching ruby version found",
+            "hints": [],
+            "spans": [],
+        })
+    );
+}
+
+#[test]
+fn test_error_format_json_includes_hints() {
+    let test = RvTest::new();
+    std::fs::create_dir_all(test.temp_root().join(".github/workflows")).unwrap();
+    std::fs::write(test.temp_root().join(".github/workflows/rv.yml"), "").unwrap();
+
+    let output = test.rv(&["generate", "github-actions", "--error-format", "json"]);
+    output.assert_failure();
+    let error = json_error(&output.stderr());
+    assert_eq!(error["code"], "RV7102");
+    assert_eq!(
+        error["hints"],
+        serde_json::json!(["Use `--force` to overwrite it, or `--stdout` to print the workflow"])
+    );
+}
+
+#[test]
+fn test_error_format_json_includes_lockfile_spans() {
+    let mut test = RvTest::new();
+    test.create_ruby_dir("ruby-3.4.1");
+    std::fs::write(test.current_dir().join("Gemfile"), "").unwrap();
+    std::fs::write(
+        test.current_dir().join("Gemfile.lock"),
+        "GEM\n  remote: https://rubygems.org/\n  specs:\n    rake (13.0\n",
+    )
+    .unwrap();
+
+    test.env.insert("RV_ERROR_FORMAT".into(), "json".into());
+    let output = test.rv(&["ci"]);
+    output.assert_failure();
+    let error = json_error(&output.stderr());
+    assert_eq!(error["code"], "RV2011");
+    let span = &error["spans"][0];
+    assert_eq!(span["label"], "Parsing failed here");
+    assert_eq!(span["line"], 4, "{error}");
+    assert!(
+        span["message"]
+            .as_str()
+            .unwrap()
+            .starts_with("Could not parse: "),
+        "{error}"
+    );
+}
@@ -0,0 +1,43 @@
+use crate::common::RvTest;
+
+fn write(path: &camino::Utf8Path, contents: &str) {
+    fs_err::create_dir_all(path.parent().unwrap()).unwrap();
+    fs_err::write(path, contents).unwrap();
+}
+
+#[test]
+fn test_gc_removes_environments_of_uninstalled_rubies() {
+    let mut test = RvTest::new();
+    let temp_dir = test.temp_root().join("tmp");
+    fs_err::create_dir_all(&temp_dir).unwrap();
+    for var in ["TMPDIR", "TMP", "TEMP"] {
+        test.env.insert(var.into(), temp_dir.to_string());
+    }
+
+    test.create_ruby_dir("ruby-3.3.5");
+    let gems_dir = test.data_dir().join("rv/gems");
+    write(&gems_dir.join("ruby/3.3.0/bin/rake"), "0123456789");
+    write(&gems_dir.join("ruby/3.2.0/bin/rake"), "0123456789");
+    let tools_dir = test.data_dir().join("rv/tools");
+    write(&tools_dir.join("rake@13.0.0/.ruby-version"), "3.2.0\n");
+    write(&tools_dir.join("rubocop@1.80.0/.ruby-version"), "3.3.5\n");
+
+    let output = test.rv(&["gc", "--dry-run"]);
+    output.assert_success();
+    output.assert_stdout_contains("gems for ruby/3.2.0, which is not installed, 10B)");
+    output.assert_stdout_contains("tool for Ruby 3.2.0, which is not installed");
+    output.assert_stdout_contains("Would free 16B");
+    assert!(gems_dir.join("ruby/3.2.0").exists());
+
+    let output = test.rv(&["gc"]);
+    output.assert_success();
+    output.assert_stdout_contains("Freed 16B");
+    assert!(!gems_dir.join("ruby/3.2.0").exists());
+    assert!(!tools_dir.join("rake@13.0.0").exists());
+    assert!(gems_dir.join("ruby/3.3.0").exists());
+    assert!(tools_dir.join("rubocop@1.80.0").exists());
+
+    let output = test.rv(&["gc"]);
+    output.assert_success();
+    output.assert_stdout_contains("Nothing to collect");
+}
@@ -0,0 +1,22 @@
+use crate::common::RvTest;
+
+#[test]
+fn test_gem_verify() {
+    let test = RvTest::new();
+    let contents = fs_err::read("../rv-gem-package/tests/fixtures/test-gem-1.0.0.gem").unwrap();
+    let good = test.temp_root().join("test-gem-1.0.0.gem");
+    fs_err::write(&good, &contents).unwrap();
+
+    let output = test.rv(&["gem", "verify", good.as_str()]);
+    output.assert_success();
+    output.assert_stdout_contains(
+        "test-gem 1.0.0, 2 files, checksums of data.tar.gz, metadata.gz match",
+    );
+
+    let truncated = test.temp_root().join("truncated-1.0.0.gem");
+    fs_err::write(&truncated, &contents[..contents.len() / 2]).unwrap();
+    let output = test.rv(&["gem", "verify", good.as_str(), truncated.as_str()]);
+    output.assert_failure();
+    output.assert_stdout_contains(&format!("corrupt {truncated}"));
+    output.assert_stderr_contains("VerificationFailed { count: 1, total: 2 }");
+}
@@ -0,0 +1,75 @@
+use crate::common::RvTest;
+
+#[test]
+fn test_gemfile_lint() {
+    let test = RvTest::new();
+    let gemfile = test.current_dir().join("Gemfile");
+    fs_err::write(
+        &gemfile,
+        "source \"https://rubygems.org\"\n\ngem \"rails\", \"~> 8.0\"\ngem \"debug\", require: false\n",
+    )
+    .unwrap();
+
+    let output = test.rv(&["gemfile", "lint"]);
+    output.assert_success();
+    output.assert_stdout_contains(&format!("{gemfile} has no problems"));
+
+    fs_err::write(
+        &gemfile,
+        "source \"https://rubygems.org\"\n\ngem \"rails\" \"~> 8.0\"\ngem \"debug\", requires: false\n",
+    )
+    .unwrap();
+    let output = test.rv(&["gemfile", "lint", "--gemfile", gemfile.as_str()]);
+    output.assert_failure();
+    output.assert_stderr_contains("ProblemsFound");
+    output.assert_stderr_contains("MissingComma");
+    output.assert_stderr_contains("Did you mean `require`? `gem \\\"debug\\\", require: false`");
+}
+
+#[test]
+fn test_gemfile_parse() {
+    let test = RvTest::new();
+    fs_err::write(
+        test.current_dir().join("Gemfile"),
+        r#"source "https://rubygems.org"
+ruby "3.4.1"
+
+gem "rails", "~> 8.0"
+group :test do
+  gem "minitest", platforms: :mri
+end
+"#,
+    )
+    .unwrap();
+
+    let output = test.rv(&["gemfile", "parse", "--json"]);
+    output.assert_success();
+    let snapshot: serde_json::Value = serde_json::from_str(&output.stdout()).unwrap();
+    assert_eq!(
+        snapshot,
+        serde_json::json!({
+            "ruby": "ruby-3.4.1",
+            "sources": ["https://rubygems.org"],
+            "gems": [
+                {
+                    "name": "minitest",
+                    "groups": ["test"],
+                    "platforms": ["mri"],
+                    "source": null,
+                    "git": null,
+                    "install_if": null,
+                    "requirements": [],
+                },
+                {
+                    "name": "rails",
+                    "groups": ["default"],
+                    "platforms": [],
+                    "source": null,
+                    "git": null,
+                    "install_if": null,
+                    "requirements": ["~> 8.0"],
+                },
+            ],
+        })
+    );
+}
@@ -0,0 +1,41 @@
+use crate::common::RvTest;
+
+#[test]
+fn test_generate_github_actions_uses_pinned_ruby() {
+    let test = RvTest::new();
+    test.write_ruby_version_file("3.4.1");
+
+    let output = test.rv(&["generate", "github-actions"]);
+    output.assert_success();
+
+    let workflow_path = test.temp_root().join(".github/workflows/rv.yml");
+    let workflow = std::fs::read_to_string(&workflow_path).unwrap();
+    assert!(workflow.contains("        ruby: [\"ruby-3.4.1\"]\n"));
+    assert!(workflow.contains("        run: rv ci\n"));
+
+    // Don't overwrite a workflow without being asked to.
+    let output = test.rv(&["generate", "github-actions"]);
+    output.assert_failure();
+    output.assert_stderr_contains("AlreadyExists");
+
+    let output = test.rv(&["generate", "github-actions", "--ruby", "3.3,3.4", "--force"]);
+    output.assert_success();
+    let workflow = std::fs::read_to_string(&workflow_path).unwrap();
+    assert!(workflow.contains("        ruby: [\"ruby-3.3\", \"ruby-3.4\"]\n"));
+}
+
+#[test]
+fn test_generate_github_actions_to_stdout() {
+    let test = RvTest::new();
+
+    let output = test.rv(&[
+        "generate",
+        "github-actions",
+        "--stdout",
+        "--os",
+        "macos-latest",
+    ]);
+    output.assert_success();
+    output.assert_stdout_contains("        os: [\"macos-latest\"]\n");
+    assert!(!test.temp_root().join(".github").exists());
+}
@@ -0,0 +1,259 @@
+use crate::common::RvTest;
+
+const UNSORTED_LOCKFILE: &str = "\
+GEM
+  remote: https://rubygems.org/
+  specs:
+    rake (13.3.0)
+    minitest (5.25.5)
+    rake (13.3.0)
+
+PLATFORMS
+  x86_64-linux
+  ruby
+
+DEPENDENCIES
+  rake
+  minitest
+
+BUNDLED WITH
+   2.6.9
+";
+
+#[test]
+fn test_lock_normalize() {
+    let test = RvTest::new();
+    let lockfile_path = test.temp_root().join("Gemfile.lock");
+    fs_err::write(&lockfile_path, UNSORTED_LOCKFILE).unwrap();
+
+    let output = test.rv(&["lock", "--normalize"]);
+    output.assert_success();
+    output.assert_stdout_contains("Normalized");
+    assert_eq!(
+        fs_err::read_to_string(&lockfile_path).unwrap(),
+        "\
+GEM
+  remote: https://rubygems.org/
+  specs:
+    minitest (5.25.5)
+    rake (13.3.0)
+
+PLATFORMS
+  ruby
+  x86_64-linux
+
+DEPENDENCIES
+  minitest
+  rake
+
+BUNDLED WITH
+   2.6.9
+"
+    );
+
+    let output = test.rv(&["lock", "--normalize"]);
+    output.assert_success();
+    output.assert_stdout_contains("is already normalized");
+}
+
+#[test]
+fn test_lock_normalize_reports_missing_ruby_fallbacks() {
+    let test = RvTest::new();
+    fs_err::write(
+        test.temp_root().join("Gemfile.lock"),
+        "\
+GEM
+  remote: https://rubygems.org/
+  specs:
+    sqlite3 (2.7.0-arm64-darwin)
+
+PLATFORMS
+  arm64-darwin
+  ruby
+
+DEPENDENCIES
+  sqlite3
+
+BUNDLED WITH
+   2.6.9
+",
+    )
+    .unwrap();
+
+    let output = test.rv(&["--error-format", "json", "lock", "--normalize"]);
+    output.assert_failure();
+    output.assert_stdout_contains("sqlite3-2.7.0-arm64-darwin has no ruby platform version");
+    output.assert_stderr_contains("\"code\":\"RV7303\"");
+}
+
+#[test]
+fn test_lock_check() {
+    let test = RvTest::new();
+    fs_err::write(
+        test.temp_root().join("Gemfile"),
+        "source \"https://rubygems.org\"\n\ngem \"rake\"\ngem \"minitest\", \"~> 5.25\"\n",
+    )
+    .unwrap();
+    fs_err::write(
+        test.temp_root().join("Gemfile.lock"),
+        "\
+GEM
+  remote: https://rubygems.org/
+  specs:
+    minitest (5.25.5)
+    rake (13.3.0)
+
+PLATFORMS
+  ruby
+
+DEPENDENCIES
+  minitest (~> 5.25)
+  rake
+
+BUNDLED WITH
+   2.6.9
+",
+    )
+    .unwrap();
+
+    let output = test.rv(&["lock", "--check"]);
+    output.assert_success();
+    output.assert_stdout_contains("is up to date with the Gemfile");
+
+    fs_err::write(
+        test.temp_root().join("Gemfile"),
+        "source \"https://rubygems.org\"\n\ngem \"rake\"\ngem \"minitest\", \"~> 6.0\"\n",
+    )
+    .unwrap();
+
+    let output = test.rv(&["--error-format", "json", "lock", "--check"]);
+    output.assert_failure();
+    output.assert_stdout_contains("- minitest (~> 5.25)");
+    output.assert_stdout_contains("+ minitest (~> 6.0)");
+    output.assert_stderr_contains("\"code\":\"RV7304\"");
+}
+
+#[test]
+fn test_lock_merge_conflicts() {
+    let test = RvTest::new();
+    let lockfile_path = test.temp_root().join("Gemfile.lock");
+    fs_err::write(
+        &lockfile_path,
+        "\
+GEM
+  remote: https://rubygems.org/
+  specs:
+<<<<<<< HEAD
+    minitest (5.25.5)
+    rake (13.2.1)
+=======
+    minitest (5.25.4)
+    rake (13.3.0)
+>>>>>>> upgrade-rake
+
+PLATFORMS
+  ruby
+
+DEPENDENCIES
+  minitest
+  rake
+
+BUNDLED WITH
+   2.6.9
+",
+    )
+    .unwrap();
+
+    let output = test.rv(&["lock", "--merge"]);
+    output.assert_success();
+    output.assert_stdout_contains("Merged minitest at 5.25.5");
+    output.assert_stdout_contains("Merged rake at 13.3.0");
+    assert_eq!(
+        fs_err::read_to_string(&lockfile_path).unwrap(),
+        "\
+GEM
+  remote: https://rubygems.org/
+  specs:
+    minitest (5.25.5)
+    rake (13.3.0)
+
+PLATFORMS
+  ruby
+
+DEPENDENCIES
+  minitest
+  rake
+
+BUNDLED WITH
+   2.6.9
+"
+    );
+
+    let output = test.rv(&["lock", "--merge"]);
+    output.assert_success();
+    output.assert_stdout_contains("has no merge conflicts");
+}
+
+#[test]
+fn test_lock_diff() {
+    let test = RvTest::new();
+    let old_path = test.temp_root().join("old.lock");
+    let new_path = test.temp_root().join("new.lock");
+    fs_err::write(&old_path, UNSORTED_LOCKFILE).unwrap();
+    fs_err::write(
+        &new_path,
+        UNSORTED_LOCKFILE
+            .replace("rake (13.3.0)", "rake (13.2.1)")
+            .replace("  x86_64-linux\n", ""),
+    )
+    .unwrap();
+
+    let output = test.rv(&["lock", "diff", old_path.as_str(), new_path.as_str()]);
+    output.assert_success();
+    output.assert_stdout_contains("rake 13.3.0 -> 13.2.1");
+    output.assert_stdout_contains("- platform x86_64-linux");
+
+    let output = test.rv(&[
+        "lock",
+        "diff",
+        "--format",
+        "json",
+        old_path.as_str(),
+        new_path.as_str(),
+    ]);
+    output.assert_success();
+    let diff: serde_json::Value = serde_json::from_str(&output.stdout()).unwrap();
+    assert_eq!(
+        diff,
+        serde_json::json!({
+            "gems": [
+                { "name": "rake", "change": "downgraded", "from": "13.3.0", "to": "13.2.1" }
+            ],
+            "platforms": { "added": [], "removed": ["x86_64-linux"] }
+        })
+    );
+}
+
+#[test]
+fn test_lock_parse() {
+    let test = RvTest::new();
+    fs_err::write(test.temp_root().join("Gemfile.lock"), UNSORTED_LOCKFILE).unwrap();
+
+    let output = test.rv(&["lock", "parse", "--json"]);
+    output.assert_success();
+    let snapshot: serde_json::Value = serde_json::from_str(&output.stdout()).unwrap();
+    assert_eq!(snapshot["sources"][0]["type"], "gem");
+    assert_eq!(snapshot["sources"][0]["remote"], "https://rubygems.org/");
+    assert_eq!(snapshot["sources"][0]["specs"][1]["name"], "minitest");
+    assert_eq!(snapshot["sources"][0]["specs"][1]["version"], "5.25.5");
+    assert_eq!(
+        snapshot["platforms"],
+        serde_json::json!(["x86_64-linux", "ruby"])
+    );
+    assert_eq!(
+        snapshot["dependencies"][0],
+        serde_json::json!({ "name": "rake", "requirements": [], "pinned": false })
+    );
+    assert_eq!(snapshot["bundled_with"], "2.6.9");
+    assert_eq!(snapshot["checksums"], serde_json::Value::Null);
+}
@@ -1,9 +1,27 @@
+mod cache;
+mod clean;
 mod clean_install;
 mod common;
+mod complete;
+mod doctor;
+mod error_format;
+mod gc;
+mod gem;
+mod gemfile;
+mod generate;
+mod lock;
+mod matrix;
+mod migrate;
+mod rpc;
 mod ruby;
 mod run;
+mod self_cmd;
 mod shell;
+mod stats;
 mod tool;
+mod trust;
+mod update;
+mod why;
 
 use crate::common::RvTest;
 use regex::Regex;
@@ -0,0 +1,80 @@
+use crate::common::RvTest;
+
+#[test]
+fn test_matrix_runs_with_each_ruby() {
+    let test = RvTest::new();
+    test.create_ruby_dir("ruby-3.3.5");
+    test.create_ruby_dir("ruby-3.4.1");
+
+    let output = test.rv(&[
+        "matrix",
+        "--no-install",
+        "--ruby",
+        "3.3.5,3.4.1",
+        "--",
+        "ruby",
+        "-e",
+        "puts RUBY_VERSION",
+    ]);
+
+    output.assert_success();
+    output.assert_stderr_contains("==> ruby-3.3.5");
+    output.assert_stderr_contains("==> ruby-3.4.1");
+    output.assert_stdout_contains("3.3.5");
+    output.assert_stdout_contains("3.4.1");
+    output.assert_stdout_contains("pass");
+}
+
+#[test]
+fn test_matrix_fails_without_installed_ruby() {
+    let test = RvTest::new();
+    test.create_ruby_dir("ruby-3.3.5");
+
+    let output = test.rv(&[
+        "matrix",
+        "--no-install",
+        "--ruby",
+        "3.3.5,3.2",
+        "--",
+        "ruby",
+        "-v",
+    ]);
+
+    output.assert_failure();
+    output.assert_stderr_contains("NoMatchingRuby");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_matrix_reports_failures() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let test = RvTest::new();
+    let ruby_dir = test.create_ruby_dir("ruby-3.3.5");
+    let script = ruby_dir.join("bin/failing-test");
+    std::fs::write(&script, "#!/bin/bash\necho broken\nexit 3\n").unwrap();
+    std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let report = test.temp_root().join("junit.xml");
+    let output = test.rv(&[
+        "matrix",
+        "--no-install",
+        "--parallel",
+        "--report",
+        report.as_str(),
+        "--github-annotations",
+        "--ruby",
+        "3.3.5",
+        "--",
+        "failing-test",
+    ]);
+
+    output.assert_failure();
+    output.assert_stdout_contains(
+        "::error title=rv matrix%3A ruby-3.3.5 failed to run::The command exited with code 3%0A%0Abroken",
+    );
+
+    let junit = std::fs::read_to_string(&report).unwrap();
+    assert!(junit.contains(r#"<testcase name="ruby-3.3.5" classname="rv matrix""#));
+    assert!(junit.contains(">broken</failure>"));
+}
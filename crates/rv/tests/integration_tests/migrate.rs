@@ -0,0 +1,50 @@
+use crate::common::RvTest;
+
+fn write(path: &camino::Utf8Path, contents: &str) {
+    fs_err::create_dir_all(path.parent().unwrap()).unwrap();
+    fs_err::write(path, contents).unwrap();
+}
+
+#[test]
+fn test_migrate_gem_home_moves_legacy_gems() {
+    let mut test = RvTest::new();
+    test.create_ruby_dir("ruby-3.3.5");
+    let legacy_home = test.legacy_gem_path("3.3");
+    write(&legacy_home.join("gems/rake-13.3.0/README"), "rake");
+    let rv_home = test.data_dir().join("rv/gems/ruby/3.3.0");
+
+    let output = test.rv(&["migrate", "gem-home", "--dry-run"]);
+    output.assert_success();
+    output.assert_stdout_contains("Would move");
+    assert!(legacy_home.join("gems/rake-13.3.0/README").exists());
+
+    let output = test.rv(&["migrate", "gem-home"]);
+    output.assert_success();
+    output.assert_stdout_contains("rv will install user gems into");
+    assert!(rv_home.join("gems/rake-13.3.0/README").exists());
+    assert!(!legacy_home.exists());
+
+    // Even if something makes the legacy directory again, rv sticks with its own.
+    fs_err::create_dir_all(&legacy_home).unwrap();
+    test.env.insert("PATH".into(), "/tmp/bin".into());
+    let output = test.rv(&["shell", "env", "zsh"]);
+    output.assert_success();
+    let stdout = output.normalized_stdout();
+    assert!(stdout.contains("GEM_PATH='/tmp/home/.local/share/rv/gems/ruby/3.3.0"));
+    assert!(!stdout.contains("/tmp/home/.gem/"));
+}
+
+#[test]
+fn test_migrate_gem_home_keep_legacy() {
+    let mut test = RvTest::new();
+    test.create_ruby_dir("ruby-3.3.5");
+
+    let output = test.rv(&["migrate", "gem-home", "--keep-legacy"]);
+    output.assert_success();
+
+    // The legacy directory is used, even before anything's installed into it.
+    test.env.insert("PATH".into(), "/tmp/bin".into());
+    let output = test.rv(&["shell", "env", "zsh"]);
+    output.assert_success();
+    output.assert_stdout_contains("GEM_PATH='/tmp/home/.gem/ruby/3.3.0");
+}
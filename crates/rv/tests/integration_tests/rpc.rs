@@ -0,0 +1,110 @@
+use std::io::{BufRead, BufReader, Write};
+use std::process::{ChildStdin, ChildStdout, Stdio};
+
+use serde_json::{Value, json};
+
+use crate::common::RvTest;
+
+#[test]
+fn test_rpc_stdio() {
+    let test = RvTest::new();
+    test.create_ruby_dir("ruby-3.3.5");
+    test.create_ruby_dir("ruby-3.4.1");
+    fs_err::write(test.current_dir().join("Gemfile.lock"), "GEM\n  remote: \n").unwrap();
+    fs_err::write(
+        test.current_dir().join("Gemfile"),
+        "gem \"rails\" \"~> 8.0\"\n",
+    )
+    .unwrap();
+
+    let mut child = test
+        .rv_command()
+        .args(["rpc", "--stdio", "--poll-interval", "20"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut client = Client {
+        stdin: child.stdin.take().unwrap(),
+        stdout: BufReader::new(child.stdout.take().unwrap()),
+    };
+
+    let dir = test.current_dir();
+    let resolved = client
+        .call(json!({"jsonrpc": "2.0", "id": 1, "method": "resolveRuby", "params": {"dir": dir}}));
+    assert_eq!(resolved["id"], 1);
+    assert_eq!(resolved["result"]["request"], "ruby");
+    assert_eq!(resolved["result"]["source"], Value::Null);
+    assert_eq!(resolved["result"]["ruby"]["version"], "ruby-3.4.1");
+
+    let env = client.call(
+        json!({"jsonrpc": "2.0", "id": 2, "method": "envForDir", "params": {"dir": dir.join("Gemfile.lock")}}),
+    );
+    assert_eq!(env["result"]["set"]["RUBY_VERSION"], "3.4.1");
+    assert!(
+        env["result"]["set"]["PATH"]
+            .as_str()
+            .unwrap()
+            .contains("ruby-3.4.1/bin")
+    );
+
+    let rubies = client.call(json!({"jsonrpc": "2.0", "id": 3, "method": "listRubies"}));
+    assert_eq!(rubies["result"].as_array().unwrap().len(), 2);
+
+    let diagnostics = client.call(
+        json!({"jsonrpc": "2.0", "id": 4, "method": "lockfileDiagnostics", "params": {"dir": dir}}),
+    );
+    assert_eq!(diagnostics["result"]["diagnostics"][0]["code"], "RV7302");
+    assert!(diagnostics["result"]["diagnostics"][0]["spans"][0]["line"].is_number());
+
+    let diagnostics = client.call(
+        json!({"jsonrpc": "2.0", "id": 5, "method": "gemfileDiagnostics", "params": {"dir": dir}}),
+    );
+    let missing_comma = &diagnostics["result"]["diagnostics"][0];
+    assert_eq!(missing_comma["code"], "RV8404");
+    assert_eq!(
+        missing_comma["hints"][0],
+        "Add a comma: `gem \"rails\", \"~> 8.0\"`"
+    );
+    assert_eq!(missing_comma["spans"][0]["line"], 1);
+
+    let unknown = client.call(json!({"jsonrpc": "2.0", "id": 6, "method": "frobnicate"}));
+    assert_eq!(unknown["error"]["code"], -32601);
+
+    let missing =
+        client.call(json!({"jsonrpc": "2.0", "id": 7, "method": "resolveRuby", "params": {}}));
+    assert_eq!(missing["error"]["code"], -32602);
+
+    // Pinning another ruby is noticed without being asked.
+    fs_err::write(dir.join(".ruby-version"), "3.3.5\n").unwrap();
+    let changed = client.read();
+    assert_eq!(changed["method"], "rubyChanged");
+    assert_eq!(changed["params"]["request"], "ruby-3.3.5");
+    assert_eq!(changed["params"]["ruby"]["version"], "ruby-3.3.5");
+    assert_eq!(
+        changed["params"]["source"],
+        dir.join(".ruby-version").as_str()
+    );
+
+    let shutdown = client.call(json!({"jsonrpc": "2.0", "id": 8, "method": "shutdown"}));
+    assert_eq!(shutdown["result"], Value::Null);
+    assert!(child.wait().unwrap().success());
+}
+
+struct Client {
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl Client {
+    fn call(&mut self, message: Value) -> Value {
+        writeln!(self.stdin, "{message}").unwrap();
+        self.read()
+    }
+
+    fn read(&mut self) -> Value {
+        let mut line = String::new();
+        self.stdout.read_line(&mut line).unwrap();
+        serde_json::from_str(&line).unwrap_or_else(|err| panic!("{err}: {line:?}"))
+    }
+}
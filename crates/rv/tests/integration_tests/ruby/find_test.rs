@@ -130,3 +130,124 @@ fn test_ruby_find_matching_jruby() {
         "/tmp/home/.local/share/rv/rubies/jruby-9.4.8.0/bin/ruby\n"
     );
 }
+
+#[test]
+fn test_ruby_find_with_ruby_override() {
+    let mut test = RvTest::new();
+    test.write_ruby_version_file("3.4.5");
+    test.create_ruby_dir("ruby-3.3.5");
+    test.create_ruby_dir("ruby-3.4.5");
+
+    let find = test.rv(&["--ruby", "3.3", "ruby", "find"]);
+    find.assert_success();
+    assert_eq!(
+        find.normalized_stdout(),
+        "/tmp/home/.local/share/rv/rubies/ruby-3.3.5/bin/ruby\n"
+    );
+
+    test.env.insert("RV_RUBY_VERSION".into(), "3.3.5".into());
+    let find = test.ruby_find(&[]);
+    find.assert_success();
+    assert_eq!(
+        find.normalized_stdout(),
+        "/tmp/home/.local/share/rv/rubies/ruby-3.3.5/bin/ruby\n"
+    );
+
+    // An explicit request still wins
+    let find = test.ruby_find(&["3.4"]);
+    find.assert_success();
+    assert_eq!(
+        find.normalized_stdout(),
+        "/tmp/home/.local/share/rv/rubies/ruby-3.4.5/bin/ruby\n"
+    );
+}
+
+#[test]
+fn test_ruby_find_tool_versions() {
+    let test = RvTest::new();
+    std::fs::write(
+        test.temp_root().join(".tool-versions"),
+        "nodejs 22.1.0\nruby 3.3.5\n",
+    )
+    .unwrap();
+    test.create_ruby_dir("ruby-3.3.5");
+    test.create_ruby_dir("ruby-3.4.5");
+    let find = test.ruby_find(&[]);
+    find.assert_success();
+    assert_eq!(
+        find.normalized_stdout(),
+        "/tmp/home/.local/share/rv/rubies/ruby-3.3.5/bin/ruby\n"
+    );
+}
+
+#[test]
+fn test_ruby_find_configured_version_file_order() {
+    let mut test = RvTest::new();
+    test.write_ruby_version_file("3.4.5");
+    std::fs::write(test.temp_root().join(".tool-versions"), "ruby 3.3.5\n").unwrap();
+    test.create_ruby_dir("ruby-3.3.5");
+    test.create_ruby_dir("ruby-3.4.5");
+
+    let find = test.ruby_find(&[]);
+    find.assert_success();
+    assert_eq!(
+        find.normalized_stdout(),
+        "/tmp/home/.local/share/rv/rubies/ruby-3.4.5/bin/ruby\n"
+    );
+
+    std::fs::write(
+        test.temp_root().join("rv.kdl"),
+        "rv {\n  version-files \".tool-versions\" \".ruby-version\"\n}\n",
+    )
+    .unwrap();
+    let find = test.ruby_find(&[]);
+    find.assert_success();
+    assert_eq!(
+        find.normalized_stdout(),
+        "/tmp/home/.local/share/rv/rubies/ruby-3.3.5/bin/ruby\n"
+    );
+}
+
+#[test]
+fn test_ruby_find_inferred_from_lockfile() {
+    let mut test = RvTest::new();
+    let cache_dir = test.enable_cache();
+    std::fs::write(
+        test.temp_root().join("Gemfile.lock"),
+        "GEM\n  remote: https://rubygems.org/\n  specs:\n    rails (8.0.2)\n\nPLATFORMS\n  ruby\n\nDEPENDENCIES\n  rails\n\nBUNDLED WITH\n   2.6.9\n",
+    )
+    .unwrap();
+    test.create_ruby_dir("ruby-3.3.5");
+    test.create_ruby_dir("ruby-3.4.5");
+
+    // Nothing is known about the locked gems yet, so the latest Ruby it is.
+    let find = test.ruby_find(&[]);
+    find.assert_success();
+    assert_eq!(
+        find.normalized_stdout(),
+        "/tmp/home/.local/share/rv/rubies/ruby-3.4.5/bin/ruby\n"
+    );
+
+    let info_dir = cache_dir.join("gemdeps-v0/compact_index/info");
+    std::fs::create_dir_all(&info_dir).unwrap();
+    std::fs::write(
+        info_dir.join("rails"),
+        "---\n8.0.2 |checksum:aa,ruby:>= 3.2.0&< 3.4.dev\n",
+    )
+    .unwrap();
+    let find = test.ruby_find(&[]);
+    find.assert_success();
+    assert_eq!(
+        find.normalized_stdout(),
+        "/tmp/home/.local/share/rv/rubies/ruby-3.3.5/bin/ruby\n"
+    );
+
+    // A pin still wins.
+    test.write_ruby_version_file("3.4.5");
+    let find = test.ruby_find(&[]);
+    find.assert_success();
+    assert_eq!(
+        find.normalized_stdout(),
+        "/tmp/home/.local/share/rv/rubies/ruby-3.4.5/bin/ruby\n"
+    );
+}
@@ -27,6 +27,23 @@ fn test_ruby_install_no_specific_version() {
     assert!(tarball_path.exists(), "Tarball should be cached");
 }
 
+#[test]
+fn test_ruby_install_yes_installs_latest_without_asking() {
+    let mut test = RvTest::new();
+
+    let ruby_mock = test.mock_ruby_download("3.4.5").create();
+    let mock = test.mock_releases(["3.3.9", "3.4.5"].to_vec());
+
+    let output = test.rv(&["ruby", "install", "--yes"]);
+
+    ruby_mock.assert();
+    mock.assert();
+    output.assert_success();
+    output
+        .assert_stdout_contains("Installed Ruby version 3.4.5 to /tmp/home/.local/share/rv/rubies");
+    assert!(!output.stdout().contains("Select a Ruby to install"));
+}
+
 #[test]
 fn test_ruby_install_incomplete_request() {
     let mut test = RvTest::new();
@@ -111,6 +128,171 @@ fn test_ruby_install_from_tarball() {
     output.assert_stdout_contains("ruby\n3.4.5");
 }
 
+#[test]
+fn test_ruby_install_records_provenance() {
+    let mut test = RvTest::new();
+
+    let tarball_content = test.create_mock_tarball("3.4.5");
+    let tarball_file = test.mock_tarball_on_disk("3.4.5", tarball_content);
+    let output = test.rv(&[
+        "ruby",
+        "install",
+        "--tarball-path",
+        tarball_file.as_str(),
+        "3.4.5",
+    ]);
+    output.assert_success();
+
+    let meta = fs::read_to_string(test.rubies_dir().join("ruby-3.4.5/.rv-meta.json")).unwrap();
+    let meta: serde_json::Value = serde_json::from_str(&meta).unwrap();
+    assert_eq!(meta["source"], tarball_file.as_str());
+    assert_eq!(meta["sha256"].as_str().map(str::len), Some(64));
+    assert_eq!(meta["rv_version"], env!("CARGO_PKG_VERSION"));
+
+    let output = test.rv(&["ruby", "list", "--installed-only", "--format", "json"]);
+    output.assert_success();
+    let list: serde_json::Value = serde_json::from_str(&output.normalized_stdout()).unwrap();
+    assert_eq!(list[0]["provenance"], meta);
+
+    // A Ruby rv didn't install is left alone.
+    test.create_ruby_dir("ruby-3.3.5");
+    let output = test.rv(&["ruby", "uninstall", "--only-rv-managed", "3.3.5"]);
+    output.assert_failure();
+    output.assert_stderr_contains("NoManagedRuby");
+    assert!(test.rubies_dir().join("ruby-3.3.5").is_dir());
+
+    let output = test.rv(&["ruby", "uninstall", "--only-rv-managed", "3.4.5"]);
+    output.assert_success();
+    assert!(!test.rubies_dir().join("ruby-3.4.5").exists());
+}
+
+#[test]
+fn test_ruby_install_from_custom_archive() {
+    let mut test = RvTest::new();
+
+    let tarball_content = test.create_mock_tarball("3.3.9");
+    let tarball_file = test.mock_tarball_on_disk("3.3.9", tarball_content);
+
+    let output = test.rv(&[
+        "ruby",
+        "install",
+        "--archive",
+        tarball_file.as_str(),
+        "--name",
+        "ruby-3.3.9-internal",
+    ]);
+
+    output.assert_success();
+    output.assert_stdout_contains("Installed ruby-3.3.9-internal to");
+    assert!(test.rubies_dir().join("ruby-3.3.9-internal/bin").is_dir());
+
+    let output = test.rv(&["ruby", "install", "--archive", tarball_file.as_str()]);
+    output.assert_failure();
+    output.assert_stderr_contains("--name");
+}
+
+#[test]
+fn test_ruby_install_from_custom_url() {
+    let mut test = RvTest::new();
+    test.enable_cache();
+
+    let tarball_content = test.create_mock_tarball("3.3.9");
+    let mock = test
+        .mock_request("GET", "internal/ruby-3.3.9.tar.gz")
+        .with_body(tarball_content)
+        .expect(1)
+        .create();
+    let url = format!("{}/internal/ruby-3.3.9.tar.gz", test.server_url());
+
+    let output = test.rv(&["ruby", "install", "--url", &url, "--name", "3.3.9-internal"]);
+    output.assert_success();
+    assert!(test.rubies_dir().join("ruby-3.3.9-internal/bin").is_dir());
+
+    // The archive is cached, like rv's own builds.
+    let output = test.rv(&[
+        "ruby",
+        "install",
+        "--url",
+        &url,
+        "--name",
+        "3.3.9-internal",
+        "--force",
+    ]);
+    output.assert_success();
+    mock.assert();
+}
+
+#[test]
+fn test_ruby_install_from_custom_archive_without_ruby() {
+    let test = RvTest::new();
+
+    let mut tar_data = Vec::new();
+    {
+        let mut builder = tar::Builder::new(&mut tar_data);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(5);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, "ruby/3.3.9/README", &b"hello"[..])
+            .unwrap();
+        builder.finish().unwrap();
+    }
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    std::io::Write::write_all(&mut encoder, &tar_data).unwrap();
+    let archive = test.temp_root().join("not-a-ruby.tar.gz");
+    fs_err::write(&archive, encoder.finish().unwrap()).unwrap();
+
+    let output = test.rv(&[
+        "ruby",
+        "install",
+        "--archive",
+        archive.as_str(),
+        "--name",
+        "ruby-3.3.9-internal",
+    ]);
+
+    output.assert_failure();
+    output.assert_stderr_contains("does not contain a Ruby that rv can use");
+    assert!(!test.rubies_dir().join("ruby-3.3.9-internal").exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_ruby_install_from_build_definition_checks_the_source_digest() {
+    let mut test = RvTest::new();
+
+    let mock = test
+        .mock_request("GET", "src/ruby-3.3.9.tar.gz")
+        .with_body("not the tarball the definition expects")
+        .create();
+    let definitions = test.temp_root().join("definitions");
+    fs_err::create_dir_all(&definitions).unwrap();
+    fs_err::write(
+        definitions.join("our-ruby-3.3.9.kdl"),
+        format!(
+            "url \"{}/src/ruby-3.3.9.tar.gz\"\nsha256 \"{}\"\n",
+            test.server_url(),
+            "0".repeat(64)
+        ),
+    )
+    .unwrap();
+    test.env
+        .insert("RV_BUILD_DEFINITIONS".into(), definitions.into());
+
+    let output = test.rv(&["ruby", "install", "our-ruby-3.3.9"]);
+
+    mock.assert();
+    output.assert_failure();
+    output.assert_stderr_contains("but its definition expects");
+    assert!(!test.rubies_dir().join("our-ruby-3.3.9").exists());
+
+    // Names without a definition are still Ruby versions.
+    let output = test.rv(&["ruby", "install", "not-a-version"]);
+    output.assert_failure();
+    output.assert_stderr_contains("not-a-version");
+}
+
 #[test]
 fn test_ruby_install_from_tarball_with_files_falling_outside_root() {
     let test = RvTest::new();
@@ -304,7 +486,12 @@ fn test_ruby_install_invalid_url() {
 
     let tarballs_dir = cache_dir.join("ruby-v0").join("tarballs");
     if tarballs_dir.exists() {
-        let entries: Vec<_> = fs::read_dir(&tarballs_dir).unwrap().collect();
+        // The lock file of the archive stays behind, but nothing else may.
+        let entries: Vec<_> = fs::read_dir(&tarballs_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_none_or(|ext| ext != "lock"))
+            .collect();
         assert!(
             entries.is_empty(),
             "No files should be created in tarballs directory"
@@ -419,3 +606,130 @@ fn test_ruby_install_with_dev() {
         .join(format!("{}.tar.gz", cache_key));
     assert!(tarball_path.exists(), "Tarball should be cached");
 }
+
+#[test]
+fn test_ruby_install_from_local_mirror() {
+    let mut test = RvTest::new();
+
+    let tarball_content = test.create_mock_tarball("3.4.5");
+    let tarball_file = test.mock_tarball_on_disk("3.4.5", tarball_content);
+    let mirror = tarball_file.parent().unwrap();
+    test.env
+        .insert("RV_RUBY_SOURCE".into(), mirror.as_str().into());
+
+    let output = test.rv(&["ruby", "list", "--format", "json"]);
+    output.assert_success();
+    output.assert_stdout_contains("3.4.5");
+
+    let output = test.rv(&["ruby", "install", "3.4.5"]);
+    output.assert_success();
+
+    let output = test.rv(&["run", "ruby"]);
+    output.assert_stdout_contains("ruby\n3.4.5");
+}
+
+#[test]
+fn test_ruby_install_from_directory_index() {
+    let mut test = RvTest::new();
+
+    let tarball_content = test.create_mock_tarball("3.4.5");
+    let file_name = test
+        .mock_tarball_on_disk("3.4.5", tarball_content.clone())
+        .file_name()
+        .unwrap()
+        .to_owned();
+    let index = test
+        .mock_request("GET", "mirror/rubies/")
+        .with_status(200)
+        .with_header("content-type", "text/html")
+        .with_body(format!(
+            r#"<a href="../">../</a><a href="{file_name}">{file_name}</a>"#
+        ))
+        .create();
+    let download = test
+        .mock_tarball_download(&format!("mirror/rubies/{file_name}"), &tarball_content)
+        .create();
+    test.env.insert(
+        "RV_RUBY_SOURCE".into(),
+        format!("{}/mirror/rubies", test.server_url()).into(),
+    );
+
+    let output = test.rv(&["ruby", "install", "3.4"]);
+    output.assert_success();
+    index.assert();
+    download.assert();
+
+    let output = test.rv(&["run", "ruby"]);
+    output.assert_stdout_contains("ruby\n3.4.5");
+}
+
+#[test]
+fn test_ruby_install_asdf_layout() {
+    let mut test = RvTest::new();
+
+    let ruby_mock = test.mock_ruby_download("3.4.5").create();
+    let installs_dir = test.temp_root().join(".asdf/installs/ruby");
+
+    let output = test.rv(&[
+        "ruby",
+        "install",
+        "--layout",
+        "asdf",
+        "--install-dir",
+        installs_dir.as_str(),
+        "3.4.5",
+    ]);
+    ruby_mock.assert();
+    output.assert_success();
+
+    assert!(installs_dir.join("3.4.5/bin").is_dir());
+    assert!(!installs_dir.join("ruby-3.4.5").exists());
+
+    let output = test.rv(&[
+        "ruby",
+        "install",
+        "--layout",
+        "asdf",
+        "--install-dir",
+        installs_dir.as_str(),
+        "3.4.5",
+    ]);
+    output.assert_success();
+    output.assert_stdout_contains("Version already installed");
+}
+
+#[test]
+fn test_ruby_install_checks_the_locked_digest() {
+    use sha2::{Digest, Sha256};
+
+    let mut test = RvTest::new();
+    let tarball_content = test.create_mock_tarball("3.4.5");
+    let sha256 = hex::encode(Sha256::digest(&tarball_content));
+    let tarball_file = test.mock_tarball_on_disk("3.4.5", tarball_content);
+    let platform = format!("{}-{}", test.platform.os(), test.platform.arch());
+    fs::write(test.temp_root().join(".ruby-version"), "3.4.5\n").unwrap();
+    let lock = |sha256: &str| {
+        let lock = serde_json::json!({"ruby": "ruby-3.4.5", "sha256": {&platform: sha256}});
+        fs::write(test.temp_root().join("rv.lock"), lock.to_string()).unwrap();
+    };
+    let install = || {
+        test.rv(&[
+            "ruby",
+            "install",
+            "--tarball-path",
+            tarball_file.as_str(),
+            "3.4.5",
+        ])
+    };
+
+    lock(&"0".repeat(64));
+    let output = install();
+    output.assert_failure();
+    output.assert_stderr_contains("LockedDigestMismatch");
+    assert!(!test.rubies_dir().join("ruby-3.4.5").exists());
+
+    lock(&sha256);
+    let output = install();
+    output.assert_success();
+    assert!(test.rubies_dir().join("ruby-3.4.5/bin").is_dir());
+}
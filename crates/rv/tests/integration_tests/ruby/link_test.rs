@@ -0,0 +1,50 @@
+use crate::common::RvTest;
+
+#[test]
+fn test_ruby_link_and_unlink() {
+    let test = RvTest::new();
+    let built = test.create_ruby_dir("ruby-3.3.9");
+    let custom_dir = test.temp_root().join("opt/custom/ruby-3.3");
+    fs_err::create_dir_all(custom_dir.parent().unwrap()).unwrap();
+    fs_err::rename(&built, &custom_dir).unwrap();
+
+    let find = test.rv(&["ruby", "find", "3.3.9"]);
+    find.assert_failure();
+
+    let link = test.rv(&["ruby", "link", custom_dir.as_str()]);
+    link.assert_success();
+    link.assert_stdout_contains("Linked ruby-3.3.9 in");
+
+    let find = test.rv(&["ruby", "find", "3.3.9"]);
+    find.assert_success();
+    find.assert_stdout_contains("opt/custom/ruby-3.3/bin/ruby");
+
+    let list = test.rv(&["ruby", "list", "--installed-only", "--format", "json"]);
+    list.assert_success();
+    list.assert_stdout_contains("opt/custom/ruby-3.3");
+
+    let link = test.rv(&["ruby", "link", custom_dir.as_str()]);
+    link.assert_success();
+    link.assert_stdout_contains("is already linked");
+
+    let unlink = test.rv(&["ruby", "unlink", custom_dir.as_str()]);
+    unlink.assert_success();
+
+    let find = test.rv(&["ruby", "find", "3.3.9"]);
+    find.assert_failure();
+
+    let unlink = test.rv(&["ruby", "unlink", custom_dir.as_str()]);
+    unlink.assert_failure();
+    unlink.assert_stderr_contains("NotLinked");
+}
+
+#[test]
+fn test_ruby_link_requires_a_ruby() {
+    let test = RvTest::new();
+    let dir = test.temp_root().join("opt/not-a-ruby");
+    fs_err::create_dir_all(&dir).unwrap();
+
+    let link = test.rv(&["ruby", "link", dir.as_str()]);
+    link.assert_failure();
+    link.assert_stderr_contains("InvalidRuby");
+}
@@ -49,6 +49,27 @@ fn test_ruby_list_text_output_with_rubies() {
     assert_snapshot!(output.normalized_stdout());
 }
 
+#[test]
+fn test_ruby_list_text_output_marks_active_and_latest_rubies() {
+    let test = RvTest::new();
+    test.create_ruby_dir("ruby-3.3.4");
+    test.create_ruby_dir("ruby-3.3.5");
+
+    let output = test.ruby_list(&["--installed-only", "--no-color"]);
+
+    output.assert_success();
+    assert_snapshot!(output.normalized_stdout(), @r"
+    ┌──────────────────┬──────────────────────────────────────────────┐
+    │ Version          │ Installed                                    │
+    ├──────────────────┼──────────────────────────────────────────────┤
+    │   3.3.4          │ ~/.local/share/rv/rubies/ruby-3.3.4/bin/ruby │
+    │ ▶ 3.3.5 (latest) │ ~/.local/share/rv/rubies/ruby-3.3.5/bin/ruby │
+    ├──────────────────┴──────────────────────────────────────────────┤
+    │ * Default version is the latest installed                       │
+    └─────────────────────────────────────────────────────────────────┘
+    ");
+}
+
 #[test]
 fn test_ruby_list_json_output_with_rubies() {
     let mut test = RvTest::new();
@@ -105,7 +126,8 @@ fn test_ruby_list_multiple_matching_rubies() {
           "enable_shared": true,
           "rubygems_platform": "aarch64-darwin23"
         },
-        "active": false
+        "active": false,
+        "scope": "user"
       },
       {
         "Installed": {
@@ -119,7 +141,8 @@ fn test_ruby_list_multiple_matching_rubies() {
           "enable_shared": true,
           "rubygems_platform": "aarch64-darwin23"
         },
-        "active": false
+        "active": false,
+        "scope": "user"
       },
       {
         "Installed": {
@@ -133,7 +156,8 @@ fn test_ruby_list_multiple_matching_rubies() {
           "enable_shared": true,
           "rubygems_platform": "aarch64-darwin23"
         },
-        "active": true
+        "active": true,
+        "scope": "user"
       }
     ]
     "#);
@@ -155,7 +179,8 @@ fn test_ruby_list_multiple_matching_rubies() {
           "enable_shared": true,
           "rubygems_platform": "aarch64-darwin23"
         },
-        "active": false
+        "active": false,
+        "scope": "user"
       },
       {
         "Installed": {
@@ -169,7 +194,8 @@ fn test_ruby_list_multiple_matching_rubies() {
           "enable_shared": true,
           "rubygems_platform": "aarch64-darwin23"
         },
-        "active": false
+        "active": false,
+        "scope": "user"
       },
       {
         "Installed": {
@@ -183,7 +209,8 @@ fn test_ruby_list_multiple_matching_rubies() {
           "enable_shared": true,
           "rubygems_platform": "aarch64-darwin23"
         },
-        "active": false
+        "active": false,
+        "scope": "user"
       },
       {
         "Installed": {
@@ -197,7 +224,8 @@ fn test_ruby_list_multiple_matching_rubies() {
           "enable_shared": true,
           "rubygems_platform": "aarch64-darwin23"
         },
-        "active": true
+        "active": true,
+        "scope": "user"
       }
     ]
     "#);
@@ -223,7 +251,8 @@ fn test_ruby_list_multiple_matching_rubies() {
           "enable_shared": true,
           "rubygems_platform": "aarch64-darwin23"
         },
-        "active": false
+        "active": false,
+        "scope": "user"
       },
       {
         "Installed": {
@@ -237,7 +266,8 @@ fn test_ruby_list_multiple_matching_rubies() {
           "enable_shared": true,
           "rubygems_platform": "aarch64-darwin23"
         },
-        "active": false
+        "active": false,
+        "scope": "user"
       },
       {
         "Installed": {
@@ -251,7 +281,8 @@ fn test_ruby_list_multiple_matching_rubies() {
           "enable_shared": true,
           "rubygems_platform": "aarch64-darwin23"
         },
-        "active": false
+        "active": false,
+        "scope": "user"
       },
       {
         "Installed": {
@@ -265,7 +296,8 @@ fn test_ruby_list_multiple_matching_rubies() {
           "enable_shared": true,
           "rubygems_platform": "aarch64-darwin23"
         },
-        "active": true
+        "active": true,
+        "scope": "user"
       }
     ]
     "#);
@@ -331,6 +363,57 @@ fn test_ruby_list_with_available_and_installed_remotes_keep_only_latest_patch()
     insta::assert_snapshot!(output.normalized_stdout());
 }
 
+#[test]
+fn test_ruby_list_tsv_output() {
+    let mut test = RvTest::new();
+    test.create_ruby_dir("ruby-3.1.4");
+
+    let mock = test.mock_releases(["3.4.5"].to_vec());
+    let output = test.ruby_list(&["--format", "tsv"]);
+
+    mock.assert();
+    output.assert_success();
+    assert_eq!(
+        output.normalized_stdout(),
+        "3.1.4\t/tmp/home/.local/share/rv/rubies/ruby-3.1.4\ttrue\n3.4.5\t\tfalse\n"
+    );
+}
+
+#[test]
+fn test_ruby_list_tsv_output_with_columns() {
+    let mut test = RvTest::new();
+    test.create_ruby_dir("ruby-3.1.4");
+
+    let mock = test.mock_releases(["3.4.5"].to_vec());
+    let output = test.ruby_list(&["--format", "tsv", "--columns", "active,version"]);
+
+    mock.assert();
+    output.assert_success();
+    assert_eq!(output.normalized_stdout(), "true\t3.1.4\nfalse\t3.4.5\n");
+}
+
+#[test]
+fn test_ruby_list_quiet() {
+    let mut test = RvTest::new();
+    test.create_ruby_dir("ruby-3.1.4");
+
+    let mock = test.mock_releases(["3.4.5"].to_vec());
+    let output = test.ruby_list(&["--quiet"]);
+
+    mock.assert();
+    output.assert_success();
+    assert!(output.stderr().is_empty());
+    assert_eq!(output.normalized_stdout(), "3.1.4\n3.4.5\n");
+}
+
+#[test]
+fn test_ruby_list_quiet_conflicts_with_format() {
+    let test = RvTest::new();
+    let output = test.ruby_list(&["--quiet", "--format", "json"]);
+
+    output.assert_failure();
+}
+
 #[test]
 fn test_ruby_list_with_no_installed_rubies_is_empty() {
     let mut test = RvTest::new();
@@ -454,3 +537,190 @@ fn test_ruby_list_all_platforms_find_rubies() {
         );
     }
 }
+
+#[test]
+fn test_ruby_list_excludes_configured_rubies() {
+    let test = RvTest::new();
+    test.create_ruby_dir("ruby-3.1.4");
+    test.create_ruby_dir("ruby-3.2.0");
+
+    // Glob patterns match either separator on Windows, and KDL strings treat `\` as an escape.
+    let excluded = test
+        .rubies_dir()
+        .join("ruby-3.1.*")
+        .as_str()
+        .replace('\\', "/");
+    fs_err::create_dir_all(test.temp_home()).unwrap();
+    fs_err::write(
+        test.temp_home().join(".rv.kdl"),
+        format!("rv {{\n  ruby-dirs \"!{excluded}\"\n}}\n"),
+    )
+    .unwrap();
+
+    let output = test.ruby_list(&["--installed-only", "--format", "json"]);
+    output.assert_success();
+    let stdout = output.normalized_stdout();
+    assert!(stdout.contains("ruby-3.2.0"), "got: {stdout}");
+    assert!(!stdout.contains("ruby-3.1.4"), "got: {stdout}");
+
+    test.rv(&["ruby", "find", "3.1"]).assert_failure();
+}
+
+#[test]
+fn test_ruby_list_uses_cached_releases() {
+    let mut test = RvTest::new();
+    test.enable_cache();
+    let mock = test.mock_releases(["3.4.1"].to_vec());
+
+    let output = test.ruby_list(&["--format", "json"]);
+    output.assert_success();
+    assert!(output.stdout().contains("3.4.1"));
+    mock.assert();
+
+    // The cached list is used offline, and while it's fresh.
+    mock.remove();
+    let output = test.ruby_list(&["--format", "json", "--offline"]);
+    output.assert_success();
+    assert!(output.stdout().contains("3.4.1"));
+
+    let output = test.ruby_list(&["--format", "json"]);
+    output.assert_success();
+    assert!(output.stdout().contains("3.4.1"));
+    assert!(output.stderr().is_empty(), "got: {}", output.stderr());
+
+    // `--refresh` asks again, and falls back to the cached list when that fails.
+    let output = test.ruby_list(&["--format", "json", "--refresh"]);
+    output.assert_success();
+    assert!(output.stdout().contains("3.4.1"));
+    output.assert_stderr_contains("stale list of available rubies");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_ruby_list_collapses_linked_installations() {
+    let test = RvTest::new();
+    test.create_ruby_dir("ruby-3.3.9");
+    std::os::unix::fs::symlink(
+        test.rubies_dir().join("ruby-3.3.9"),
+        test.rubies_dir().join("ruby-3.3"),
+    )
+    .unwrap();
+
+    let output = test.ruby_list(&["--installed-only", "--format", "tsv", "--columns", "path"]);
+    output.assert_success();
+    assert_eq!(
+        output.normalized_stdout(),
+        "/tmp/home/.local/share/rv/rubies/ruby-3.3.9\n"
+    );
+
+    let output = test.ruby_list(&["--installed-only", "--format", "json"]);
+    output.assert_success();
+    let rubies: serde_json::Value = serde_json::from_str(&output.normalized_stdout()).unwrap();
+    assert_eq!(
+        rubies[0]["aliases"],
+        serde_json::json!(["/tmp/home/.local/share/rv/rubies/ruby-3.3"])
+    );
+
+    let output = test.ruby_list(&[
+        "--installed-only",
+        "--expand-links",
+        "--format",
+        "tsv",
+        "--columns",
+        "path",
+    ]);
+    output.assert_success();
+    let stdout = output.normalized_stdout();
+    let mut paths: Vec<&str> = stdout.lines().collect();
+    paths.sort();
+    assert_eq!(
+        paths,
+        vec![
+            "/tmp/home/.local/share/rv/rubies/ruby-3.3",
+            "/tmp/home/.local/share/rv/rubies/ruby-3.3.9"
+        ]
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_ruby_list_marks_external_rubies() {
+    let test = RvTest::new();
+    test.create_ruby_dir("ruby-3.4.1");
+    test.create_ruby_dir_at(
+        &test.temp_root().join("opt/homebrew/Cellar/ruby/3.4.7"),
+        "ruby-3.4.7",
+    );
+
+    let output = test.ruby_list(&["--installed-only", "--format", "json"]);
+    output.assert_success();
+    let rubies: serde_json::Value = serde_json::from_str(&output.normalized_stdout()).unwrap();
+    assert_eq!(
+        rubies[0]["path"],
+        "/tmp/home/.local/share/rv/rubies/ruby-3.4.1"
+    );
+    assert_eq!(rubies[0].get("external"), None);
+    assert_eq!(rubies[1]["path"], "/tmp/opt/homebrew/Cellar/ruby/3.4.7");
+    assert_eq!(rubies[1]["external"], "homebrew");
+    // rv never picks an external Ruby.
+    assert_eq!(rubies[1]["active"], false);
+
+    let output = test.ruby_list(&["--installed-only", "--no-color"]);
+    output.assert_success();
+    output.assert_stdout_contains("/tmp/opt/homebrew/Cellar/ruby/3.4.7/bin/ruby (external)");
+}
+
+#[test]
+fn test_ruby_list_platforms() {
+    let mut test = RvTest::new();
+    let mock = test.mock_releases_all_platforms(["3.4.1", "3.3.9"].to_vec());
+    let windows_mock = test.mock_windows_releases(["3.4.1"].to_vec());
+
+    let output = test.ruby_list(&["--platforms", "--format", "json"]);
+
+    mock.assert();
+    windows_mock.assert();
+    output.assert_success();
+    let entries: serde_json::Value = serde_json::from_str(&output.stdout()).unwrap();
+    let non_windows = [
+        "macos-aarch64",
+        "macos-x86_64",
+        "linux-x86_64",
+        "linux-musl-x86_64",
+        "linux-aarch64",
+        "linux-musl-aarch64",
+    ];
+    let mut with_windows = non_windows.to_vec();
+    with_windows.push("windows-x86_64");
+    assert_eq!(
+        entries,
+        serde_json::json!([
+            {"version": "ruby-3.3.9", "platforms": non_windows},
+            {"version": "ruby-3.4.1", "platforms": with_windows},
+        ])
+    );
+}
+
+#[test]
+fn test_ruby_list_platforms_tsv() {
+    let mut test = RvTest::new();
+    test.set_platform(HostPlatform::LinuxX86_64);
+    let mock = test.mock_releases(["3.4.1"].to_vec());
+    let windows_mock = test.mock_windows_releases([].to_vec());
+
+    let output = test.ruby_list(&["--platforms", "--format", "tsv"]);
+
+    mock.assert();
+    windows_mock.assert();
+    output.assert_success();
+    assert_eq!(output.stdout(), "3.4.1\tlinux-x86_64\n");
+}
+
+#[test]
+fn test_ruby_list_platforms_conflicts_with_installed_only() {
+    let test = RvTest::new();
+
+    let output = test.ruby_list(&["--platforms", "--installed-only"]);
+
+    output.assert_failure();
+}
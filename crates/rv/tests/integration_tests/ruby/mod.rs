@@ -1,6 +1,10 @@
 mod find_test;
 mod install_test;
+mod link_test;
 mod list_test;
 mod pin_test;
+mod repair_test;
+mod resolve_test;
 mod run_test;
 mod uninstall_test;
+mod verify_test;
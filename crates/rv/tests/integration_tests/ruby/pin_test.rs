@@ -1,4 +1,5 @@
 use crate::common::{RvOutput, RvTest};
+use rv_platform::HostPlatform;
 
 impl RvTest {
     pub fn ruby_pin(&self, args: &[&str]) -> RvOutput {
@@ -137,6 +138,42 @@ fn test_pin_runs_with_tool_versions() {
     assert_eq!(content, " ruby 3.3.0\n");
 }
 
+#[test]
+fn test_pin_runs_with_gemfile_ruby_directive() {
+    let test = RvTest::new();
+
+    let gemfile = test.temp_root().join("Gemfile");
+    fs_err::write(
+        &gemfile,
+        "source \"https://rubygems.org\"\n\nruby \"3.3.0\"\n\ngem \"rake\"\n",
+    )
+    .unwrap();
+
+    let show_pin = test.ruby_pin(&[]);
+    show_pin.assert_success();
+    assert_eq!(
+        show_pin.normalized_stdout(),
+        "/tmp/Gemfile is pinned to 3.3.0\n"
+    );
+
+    // Setting a pin writes a .ruby-version and leaves the Gemfile alone
+    let set_pin = test.ruby_pin(&["3.4.0"]);
+    set_pin.assert_success();
+    assert_eq!(
+        set_pin.normalized_stdout(),
+        "/tmp/.ruby-version pinned to 3.4.0\n"
+    );
+    let content = fs_err::read_to_string(&gemfile).unwrap();
+    assert!(content.contains("ruby \"3.3.0\""));
+
+    let show_pin = test.ruby_pin(&[]);
+    show_pin.assert_success();
+    assert_eq!(
+        show_pin.normalized_stdout(),
+        "/tmp/.ruby-version is pinned to 3.4.0\n"
+    );
+}
+
 #[test]
 fn test_ruby_pin_without_resolve() {
     let test = RvTest::new();
@@ -195,3 +232,151 @@ fn test_ruby_pin_show_with_resolve() {
         "/tmp/.ruby-version is pinned to 3.4.7\n"
     );
 }
+
+#[test]
+fn test_ruby_pin_latest_flag() {
+    let mut test = RvTest::new();
+
+    test.mock_releases(["3.3.9", "3.4.7"].to_vec());
+
+    let set_pin = test.ruby_pin(&["--latest"]);
+    set_pin.assert_success();
+
+    assert_eq!(
+        set_pin.normalized_stdout(),
+        "/tmp/.ruby-version pinned to 3.4.7\n"
+    );
+}
+
+#[test]
+fn test_ruby_pin_resolve_falls_back_to_installed_rubies() {
+    let test = RvTest::new();
+
+    test.create_ruby_dir("ruby-3.3.5");
+    test.create_ruby_dir("ruby-3.4.1");
+
+    let set_pin = test.ruby_pin(&["3.3", "--resolve", "--offline"]);
+    set_pin.assert_success();
+
+    assert_eq!(
+        set_pin.normalized_stdout(),
+        "/tmp/.ruby-version pinned to 3.3.5\n\
+         Using ruby-3.3.5 from /tmp/home/.local/share/rv/rubies/ruby-3.3.5\n"
+    );
+}
+
+#[test]
+fn test_ruby_pin_warns_when_pinned_version_is_not_installed() {
+    let test = RvTest::new();
+
+    let set_pin = test.ruby_pin(&["3.4.7", "--no-input"]);
+    set_pin.assert_success();
+
+    assert_eq!(
+        set_pin.normalized_stdout(),
+        "/tmp/.ruby-version pinned to 3.4.7\n"
+    );
+    set_pin.assert_stderr_contains(
+        "Ruby 3.4.7 is not installed. Run `rv ruby install 3.4.7` to install it.",
+    );
+}
+
+#[test]
+fn test_ruby_pin_install_installs_pinned_version() {
+    let mut test = RvTest::new();
+
+    let ruby_mock = test.mock_ruby_download("3.4.5").create();
+    let releases_mock = test.mock_releases(["3.3.9", "3.4.5"].to_vec());
+
+    let set_pin = test.ruby_pin(&["3.4", "--install"]);
+
+    ruby_mock.assert();
+    releases_mock.assert();
+    set_pin.assert_success();
+    set_pin.assert_stdout_contains("/tmp/.ruby-version pinned to 3.4");
+    set_pin
+        .assert_stdout_contains("Installed Ruby version 3.4.5 to /tmp/home/.local/share/rv/rubies");
+    set_pin.assert_stdout_contains(
+        "Using ruby-3.4.5 from /tmp/home/.local/share/rv/rubies/ruby-3.4.5",
+    );
+
+    // Already installed, so nothing else to do.
+    let set_pin = test.ruby_pin(&["3.4", "--install"]);
+    set_pin.assert_success();
+    assert!(!set_pin.stdout().contains("Installed Ruby version"));
+}
+
+#[test]
+fn test_pin_finds_version_file_in_ancestor_directories() {
+    let mut test = RvTest::new();
+
+    test.write_ruby_version_file("3.4.7");
+    let app_dir = test.temp_root().join("app");
+    let nested_dir = app_dir.join("lib/tasks");
+    fs_err::create_dir_all(&nested_dir).unwrap();
+    test.cwd = nested_dir;
+
+    let show_pin = test.ruby_pin(&[]);
+    show_pin.assert_success();
+    assert_eq!(
+        show_pin.normalized_stdout(),
+        "/tmp/.ruby-version is pinned to 3.4.7\n"
+    );
+
+    // Too far away for the configured depth
+    test.env.insert("RV_DIR_DEPTH".into(), "2".into());
+    let show_pin = test.ruby_pin(&[]);
+    show_pin.assert_failure();
+    show_pin.assert_stderr_contains("Error: RubyError(PinError(NoRubyRequest");
+    test.env.remove("RV_DIR_DEPTH");
+
+    // The search stops at the repository root
+    fs_err::create_dir_all(app_dir.join(".git")).unwrap();
+    let show_pin = test.ruby_pin(&[]);
+    show_pin.assert_failure();
+    show_pin.assert_stderr_contains("Error: RubyError(PinError(NoRubyRequest");
+}
+
+#[test]
+fn test_ruby_pin_lock() {
+    let mut test = RvTest::new();
+    test.set_platform(HostPlatform::MacosAarch64);
+    let asset = |name: &str, digest: Option<&str>| {
+        serde_json::json!({
+            "name": name,
+            "browser_download_url": format!("http://.../{name}"),
+            "digest": digest.map(|digest| format!("sha256:{digest}")),
+        })
+    };
+    let release = serde_json::json!({
+        "name": "latest",
+        "assets": [
+            asset("ruby-3.4.1.arm64_sonoma.tar.gz", Some("aa11")),
+            asset("ruby-3.4.1.x86_64_linux.tar.gz", Some("bb22")),
+            asset("ruby-3.4.1.arm64_linux.tar.gz", None),
+            asset("ruby-3.3.9.arm64_sonoma.tar.gz", Some("cc33")),
+        ],
+    });
+    test.mock_request("GET", "repos/spinel-coop/rv-ruby/releases/latest")
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .with_body(release.to_string())
+        .create();
+    test.mock_windows_releases([].to_vec());
+
+    let output = test.ruby_pin(&["3.4", "--lock"]);
+
+    output.assert_success();
+    output.assert_stdout_contains("/tmp/rv.lock locked to ruby-3.4.1");
+    let version_file = fs_err::read_to_string(test.temp_root().join(".ruby-version")).unwrap();
+    assert_eq!(version_file, "3.4\n");
+    let lock = fs_err::read_to_string(test.temp_root().join("rv.lock")).unwrap();
+    let lock: serde_json::Value = serde_json::from_str(&lock).unwrap();
+    assert_eq!(
+        lock,
+        serde_json::json!({
+            "ruby": "ruby-3.4.1",
+            "sha256": {"macos-aarch64": "aa11", "linux-x86_64": "bb22"},
+        })
+    );
+}
@@ -0,0 +1,59 @@
+use crate::common::{RvOutput, RvTest};
+
+impl RvTest {
+    pub fn ruby_repair(&self, args: &[&str]) -> RvOutput {
+        self.rv(&[&["--offline", "ruby", "repair"], args].concat())
+    }
+}
+
+#[test]
+fn test_ruby_repair_no_matching_rubies() {
+    let test = RvTest::new();
+    test.create_ruby_dir("ruby-3.3.5");
+    let repair = test.ruby_repair(&["3.4.5"]);
+    repair.assert_failure();
+    assert_eq!(
+        repair.normalized_stderr(),
+        "Error: RubyError(RepairError(NoMatchingRuby))\n"
+    );
+}
+
+#[test]
+fn test_ruby_repair_only_repairs_rubies_rv_installed() {
+    let test = RvTest::new();
+    test.create_ruby_dir("ruby-3.3.5");
+    let repair = test.ruby_repair(&["3.3.5"]);
+    repair.assert_failure();
+    let stderr = repair.normalized_stderr();
+    assert!(stderr.contains("RepairError(NotInstalledByRv"), "{stderr}");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_ruby_repair_without_archive() {
+    let test = RvTest::new();
+    let ruby_dir = test.create_ruby_dir("ruby-3.3.5");
+    fs_err::write(
+        ruby_dir.join(".rv-meta.json"),
+        r#"{"source": "/gone/ruby-3.3.5.tar.gz", "installed_at": 1, "rv_version": "0.6.0"}"#,
+    )
+    .unwrap();
+
+    // A Ruby that can't load OpenSSL anymore.
+    let ruby = ruby_dir.join("bin/ruby");
+    let script = fs_err::read_to_string(&ruby).unwrap().replacen(
+        "#!/bin/bash\n",
+        "#!/bin/bash\nif [[ \"$2\" == *openssl* ]]; then echo \"cannot load such file -- openssl (LoadError)\" >&2; exit 1; fi\n",
+        1,
+    );
+    fs_err::write(&ruby, script).unwrap();
+
+    let repair = test.ruby_repair(&["3.3.5"]);
+    repair.assert_failure();
+    let stdout = repair.normalized_stdout();
+    assert!(stdout.contains("Repairing ruby-3.3.5"), "{stdout}");
+    assert_eq!(
+        repair.normalized_stderr(),
+        "Error: RubyError(RepairError(Unrecoverable { ruby: \"ruby-3.3.5\" }))\n"
+    );
+}
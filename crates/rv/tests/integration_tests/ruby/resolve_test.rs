@@ -0,0 +1,87 @@
+use crate::common::{RvOutput, RvTest};
+
+impl RvTest {
+    pub fn ruby_resolve(&self, args: &[&str]) -> RvOutput {
+        self.rv(&[&["ruby", "resolve"], args].concat())
+    }
+}
+
+#[test]
+fn test_ruby_resolve_request() {
+    let mut test = RvTest::new();
+    let mock = test.mock_releases(["3.3.9", "3.4.4", "3.4.5"].to_vec());
+
+    let output = test.ruby_resolve(&["3.4"]);
+    mock.assert();
+    output.assert_success();
+    assert_eq!(output.normalized_stdout(), "ruby-3.4.5\n");
+
+    let output = test.ruby_resolve(&["3.4", "--asdf-format"]);
+    output.assert_success();
+    assert_eq!(output.normalized_stdout(), "3.4.5\n");
+}
+
+#[test]
+fn test_ruby_resolve_pinned_version() {
+    let mut test = RvTest::new();
+    let mock = test.mock_releases(["3.3.9", "3.4.5"].to_vec());
+    std::fs::write(test.temp_root().join(".tool-versions"), "ruby 3.3\n").unwrap();
+
+    let output = test.ruby_resolve(&["--asdf-format"]);
+    mock.assert();
+    output.assert_success();
+    assert_eq!(output.normalized_stdout(), "3.3.9\n");
+}
+
+#[test]
+fn test_ruby_resolve_all_asdf_format() {
+    let mut test = RvTest::new();
+    let mock = test.mock_releases(["3.4.5", "3.3.9"].to_vec());
+
+    let output = test.ruby_resolve(&["--all", "--asdf-format"]);
+    mock.assert();
+    output.assert_success();
+    assert_eq!(output.normalized_stdout(), "3.3.9 3.4.5\n");
+}
+
+#[test]
+fn test_ruby_resolve_no_match() {
+    let mut test = RvTest::new();
+    let mock = test.mock_releases(["3.4.5"].to_vec());
+
+    let output = test.ruby_resolve(&["2.7"]);
+    mock.assert();
+    output.assert_failure();
+}
+
+#[test]
+fn test_ruby_resolve_explain() {
+    let test = RvTest::new();
+    test.create_ruby_dir("ruby-3.3.5");
+    test.create_ruby_dir("ruby-3.4.1");
+    test.create_ruby_dir("ruby-3.4.4");
+    test.write_ruby_version_file("3.4");
+    std::fs::write(test.temp_root().join(".tool-versions"), "ruby 3.3\n").unwrap();
+
+    let output = test.ruby_resolve(&["--explain"]);
+    output.assert_success();
+    let stdout = output.normalized_stdout();
+    assert!(
+        stdout.contains("Requested by: /tmp/.ruby-version\n"),
+        "{stdout}"
+    );
+    assert!(stdout.contains("Request: ruby-3.4 (the newest ruby release of 3.4.x)\n"));
+    assert!(
+        stdout.contains("/tmp/.tool-versions also pins ruby-3.3, but comes later in the order\n")
+    );
+    assert!(stdout.contains("ruby-3.4.4 /tmp/home/.local/share/rv/rubies/ruby-3.4.4 chosen:"));
+    assert!(stdout.contains("ruby-3.4.1 /tmp/home/.local/share/rv/rubies/ruby-3.4.1 skipped: satisfies ruby-3.4, but ruby-3.4.4 is newer\n"));
+    assert!(stdout.contains(
+        "ruby-3.3.5 /tmp/home/.local/share/rv/rubies/ruby-3.3.5 rejected: doesn't satisfy ruby-3.4\n"
+    ));
+
+    let output = test.ruby_resolve(&["3.5", "--explain"]);
+    output.assert_success();
+    output.assert_stdout_contains("Requested by: the version given on the command line\n");
+    output.assert_stdout_contains("No installed Ruby satisfies ruby-3.5.");
+}
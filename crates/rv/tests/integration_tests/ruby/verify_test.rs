@@ -0,0 +1,63 @@
+use crate::common::{RvOutput, RvTest};
+
+impl RvTest {
+    pub fn ruby_verify(&self, args: &[&str]) -> RvOutput {
+        self.rv(&[&["--offline", "ruby", "verify"], args].concat())
+    }
+}
+
+#[test]
+fn test_ruby_verify_no_matching_rubies() {
+    let test = RvTest::new();
+    test.create_ruby_dir("ruby-3.3.5");
+    let verify = test.ruby_verify(&["3.4.5"]);
+    verify.assert_failure();
+    assert_eq!(
+        verify.normalized_stderr(),
+        "Error: RubyError(VerifyError(NoMatchingRuby))\n"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_ruby_verify_passes() {
+    let test = RvTest::new();
+    test.create_ruby_dir("ruby-3.3.5");
+    let verify = test.ruby_verify(&["3.3.5"]);
+    verify.assert_success();
+
+    let stdout = verify.normalized_stdout();
+    assert!(stdout.contains("Verifying ruby-3.3.5"), "{stdout}");
+    assert!(stdout.contains("openssl"), "{stdout}");
+    assert!(!stdout.contains("fail"), "{stdout}");
+    // Certificates can't be checked against rubygems.org offline.
+    assert!(stdout.contains("rv is offline"), "{stdout}");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_ruby_verify_reports_failed_checks() {
+    let test = RvTest::new();
+    let ruby_dir = test.create_ruby_dir("ruby-3.3.5");
+
+    // A Ruby that was built without OpenSSL.
+    let ruby = ruby_dir.join("bin/ruby");
+    let script = fs_err::read_to_string(&ruby).unwrap().replacen(
+        "#!/bin/bash\n",
+        "#!/bin/bash\nif [[ \"$2\" == *openssl* ]]; then echo \"cannot load such file -- openssl (LoadError)\" >&2; exit 1; fi\n",
+        1,
+    );
+    fs_err::write(&ruby, script).unwrap();
+
+    let verify = test.ruby_verify(&["3.3.5"]);
+    verify.assert_failure();
+    assert!(
+        verify
+            .normalized_stdout()
+            .contains("cannot load such file -- openssl (LoadError)")
+    );
+    assert_eq!(
+        verify.normalized_stderr(),
+        "Error: RubyError(VerifyError(ChecksFailed { ruby: \"ruby-3.3.5\", failed: [\"openssl\"] }))\n"
+    );
+}
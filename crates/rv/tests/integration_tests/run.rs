@@ -398,3 +398,63 @@ puts RUBY_VERSION
         "jruby\n9.4.8.0\naarch64-darwin23\naarch64\ndarwin23\n\n"
     );
 }
+
+#[cfg(unix)]
+#[test]
+fn test_run_isolated_scrubs_environment() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mut test = RvTest::new();
+    let ruby_dir = test.create_ruby_dir("ruby-3.3.5");
+
+    let show_env = ruby_dir.join("bin/show-env");
+    fs::write(
+        &show_env,
+        "#!/bin/sh\necho \"GEM_HOME=$GEM_HOME\"\necho \"GEM_PATH=$GEM_PATH\"\necho \"RUBYOPT=$RUBYOPT\"\necho \"BUNDLE_GEMFILE=$BUNDLE_GEMFILE\"\necho \"PATH=$PATH\"\n",
+    )
+    .unwrap();
+    fs::set_permissions(&show_env, fs::Permissions::from_mode(0o755)).unwrap();
+
+    test.env.insert("RUBYOPT".into(), "-w".into());
+    test.env
+        .insert("BUNDLE_GEMFILE".into(), "/elsewhere/Gemfile".into());
+
+    let output = test.rv(&["run", "--isolated", "--ruby", "3.3.5", "show-env"]);
+    output.assert_success();
+
+    let stdout = output.stdout();
+    let var = |name: &str| {
+        stdout
+            .lines()
+            .find_map(|line| line.strip_prefix(&format!("{name}=")))
+            .unwrap()
+            .to_string()
+    };
+
+    let gem_home = var("GEM_HOME");
+    assert!(gem_home.contains("rv-isolated-"), "{gem_home}");
+    assert_eq!(var("GEM_PATH"), gem_home);
+    assert_eq!(var("RUBYOPT"), "");
+    assert_eq!(var("BUNDLE_GEMFILE"), "");
+    assert_eq!(var("PATH"), format!("{gem_home}/bin:{ruby_dir}/bin"));
+
+    // The temporary GEM_HOME is removed once the command finishes
+    assert!(!std::path::Path::new(&gem_home).exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_ruby_exec_isolated_passes_on_the_signal_that_killed_the_command() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mut test = RvTest::new();
+    let ruby_dir = test.create_ruby_dir("ruby-3.3.5");
+
+    let kill_self = ruby_dir.join("bin/kill-self");
+    fs::write(&kill_self, "#!/bin/sh\nkill -TERM $$\n").unwrap();
+    fs::set_permissions(&kill_self, fs::Permissions::from_mode(0o755)).unwrap();
+
+    let output = test.rv(&["ruby", "exec", "--isolated", "--ruby", "3.3.5", "kill-self"]);
+    // 128 + SIGTERM, like a shell reports it.
+    assert_eq!(output.output.status.code(), Some(143));
+}
@@ -0,0 +1,40 @@
+use crate::common::RvTest;
+
+#[test]
+fn test_self_version_check_finds_newer_release() {
+    let mut test = RvTest::new();
+    let mock = test
+        .mock_request("GET", "repos/spinel-coop/rv/releases/latest")
+        .with_body(r#"{"tag_name": "v99.0.0", "assets": []}"#)
+        .create();
+    test.env.insert(
+        "RV_SELF_UPDATE_URL".into(),
+        format!("{}/repos/spinel-coop/rv/releases/latest", test.server_url()),
+    );
+
+    let output = test.rv(&["self", "version", "--check"]);
+    output.assert_success();
+    mock.assert();
+    output.assert_stdout_contains("rv 99.0.0 is available. Run `rv self update` to install it.");
+}
+
+#[test]
+fn test_self_version_check_when_up_to_date() {
+    let mut test = RvTest::new();
+    let mock = test
+        .mock_request("GET", "repos/spinel-coop/rv/releases/latest")
+        .with_body(format!(
+            r#"{{"tag_name": "v{}", "assets": []}}"#,
+            env!("CARGO_PKG_VERSION")
+        ))
+        .create();
+    test.env.insert(
+        "RV_SELF_UPDATE_URL".into(),
+        format!("{}/repos/spinel-coop/rv/releases/latest", test.server_url()),
+    );
+
+    let output = test.rv(&["self", "version", "--check"]);
+    output.assert_success();
+    mock.assert();
+    output.assert_stdout_contains("rv is up to date.");
+}
@@ -1,5 +1,6 @@
 mod env_test;
 mod init_test;
+mod prompt_test;
 
 use crate::common::RvTest;
 use insta::assert_snapshot;
@@ -40,6 +41,15 @@ fn test_nu_succeeds() {
     assert_snapshot!(output.normalized_stdout());
 }
 
+#[test]
+fn test_elvish_succeeds() {
+    let test = RvTest::new();
+    let output = test.rv(&["shell", "elvish"]);
+    output.assert_success();
+
+    assert_snapshot!(output.normalized_stdout());
+}
+
 #[test]
 fn test_fails_without_shell() {
     let test = RvTest::new();
@@ -40,6 +40,15 @@ fn test_nu_succeeds() {
     output.assert_success();
 }
 
+#[test]
+fn test_elvish_succeeds() {
+    let test = RvTest::new();
+    let output = test.rv(&["shell", "env", "elvish"]);
+
+    assert_snapshot!(output.normalized_stdout());
+    output.assert_success();
+}
+
 #[test]
 fn test_powershell_succeeds() {
     let test = RvTest::new();
@@ -92,6 +101,35 @@ fn test_shell_env_with_path() {
     output.assert_stdout_contains(&format!("export PATH='{expected_path}'"));
 }
 
+#[test]
+fn test_shell_env_with_project_isolation() {
+    let mut test = RvTest::new();
+    test.env.insert("PATH".into(), "/tmp/bin".into());
+    test.create_ruby_dir("ruby-3.3.5");
+
+    let project_dir = test.temp_root().join("project");
+    std::fs::create_dir_all(project_dir.as_path()).unwrap();
+    std::fs::write(
+        project_dir.join("rv.kdl"),
+        "rv {\n  isolation \"project\"\n}\n",
+    )
+    .unwrap();
+    test.cwd = project_dir;
+
+    // The project's own gems replace the shared ones, and user gems are left out
+    let expected_path = [
+        "/tmp/project/.rv/gems/ruby/3.3.0/bin",
+        "/tmp/home/.local/share/rv/rubies/ruby-3.3.5/bin",
+        "/tmp/bin",
+    ]
+    .join(":");
+    let output = test.rv(&["shell", "env", "zsh"]);
+    output.assert_success();
+    output.assert_stdout_contains(&format!("export PATH='{expected_path}'"));
+    output.assert_stdout_contains("export GEM_HOME=/tmp/project/.rv/gems/ruby/3.3.0");
+    output.assert_stdout_contains("export GEM_PATH=/tmp/project/.rv/gems/ruby/3.3.0");
+}
+
 #[test]
 fn test_shell_env_clears_ruby_and_gem_vars_when_no_rubies_available() {
     let mut test = RvTest::new();
@@ -111,6 +149,46 @@ fn test_shell_env_clears_ruby_and_gem_vars_when_no_rubies_available() {
     output.assert_success();
 }
 
+// PATH is joined with `:`, which isn't the separator on Windows.
+#[cfg(unix)]
+#[test]
+fn test_shell_deactivate_restores_saved_vars() {
+    let mut test = RvTest::new();
+    let rubies_dir = test.rubies_dir();
+    test.env.insert(
+        "PATH".into(),
+        format!("{rubies_dir}/ruby-3.3.5/bin:/tmp/bin"),
+    );
+    test.env
+        .insert("RUBY_ROOT".into(), format!("{rubies_dir}/ruby-3.3.5"));
+    test.env.insert("GEM_HOME".into(), "/tmp/rv-gems".into());
+    test.env
+        .insert("_RV_OLD_GEM_HOME".into(), "/tmp/user-gems".into());
+    test.env.insert("_RV_SAVED".into(), "1".into());
+
+    let output = test.rv(&["shell", "deactivate", "bash"]);
+    output.assert_success();
+    assert_eq!(
+        output.normalized_stdout(),
+        "unset RUBY_ROOT _RV_OLD_GEM_HOME _RV_SAVED\nexport PATH=/tmp/bin\nexport GEM_HOME=/tmp/user-gems\nhash -r\n"
+    );
+}
+
+#[test]
+fn test_shell_deactivate_without_saved_vars() {
+    let mut test = RvTest::new();
+    test.env.insert("PATH".into(), "/tmp/bin".into());
+    test.env.insert("GEM_HOME".into(), "/tmp/rv-gems".into());
+    test.env.insert("MANPATH".into(), "/tmp/man".into());
+
+    let output = test.rv(&["shell", "deactivate", "fish"]);
+    output.assert_success();
+    assert_eq!(
+        output.normalized_stdout(),
+        "set -ge GEM_HOME\nset -gx PATH \"/tmp/bin\"\n"
+    );
+}
+
 #[test]
 fn test_shell_env_fallback_to_highest_installed_ruby_if_no_rubies_matching_pin_installed() {
     let mut test = RvTest::new();
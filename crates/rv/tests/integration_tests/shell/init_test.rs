@@ -71,6 +71,50 @@ fn test_switch_rubies_zsh() -> Result<(), Box<dyn std::error::Error>> {
     switch_rubies(shell)
 }
 
+#[cfg(unix)]
+#[test]
+fn test_deactivate_and_reactivate_bash() -> Result<(), Box<dyn std::error::Error>> {
+    let mut test = RvTest::new();
+    test.env.insert("GEM_HOME".into(), "/tmp/user-gems".into());
+    test.create_ruby_dir("3.4.1");
+
+    let shell = Shell {
+        name: "bash",
+        startup_flag: "--norc",
+        prompt_setter: "PS1='PEXPECT>'",
+    };
+    let init = format!(
+        "eval \"$({} shell init bash)\"",
+        test.rv_command().get_program().display()
+    );
+
+    let mut session = test.make_session(shell)?;
+    session.send_line("ruby")?;
+    session.exp_string("ruby\r\n3.4.1")?;
+    session.wait_for_prompt()?;
+
+    // Deactivating puts back what was there before, and stops switching rubies.
+    session.send_line("rv_deactivate")?;
+    session.wait_for_prompt()?;
+    session.send_line(r#"echo "gems=$GEM_HOME root=${RUBY_ROOT:-none} hook=$PROMPT_COMMAND""#)?;
+    session.exp_string("gems=/tmp/user-gems root=none hook=\r\n")?;
+    session.wait_for_prompt()?;
+
+    // Activating again saves the restored values afresh, so deactivating again still works.
+    session.send_line(&init)?;
+    session.wait_for_prompt()?;
+    session.send_line("ruby")?;
+    session.exp_string("ruby\r\n3.4.1")?;
+    session.wait_for_prompt()?;
+    session.send_line("rv_deactivate")?;
+    session.wait_for_prompt()?;
+    session.send_line(r#"echo "gems=$GEM_HOME saved=${_RV_SAVED:-none} path=$PATH""#)?;
+    session.exp_string("gems=/tmp/user-gems saved=none path=\r\n")?;
+    session.wait_for_prompt()?;
+
+    Ok(())
+}
+
 #[test]
 fn test_zsh_shell_init_succeeds() {
     let test = RvTest::new();
@@ -107,6 +151,15 @@ fn test_nu_shell_init_succeeds() {
     assert_snapshot!(output.normalized_stdout());
 }
 
+#[test]
+fn test_elvish_shell_init_succeeds() {
+    let test = RvTest::new();
+    let output = test.rv(&["shell", "init", "elvish"]);
+    output.assert_success();
+
+    assert_snapshot!(output.normalized_stdout());
+}
+
 #[test]
 fn test_powershell_shell_init_succeeds() {
     let test = RvTest::new();
@@ -0,0 +1,32 @@
+use crate::common::RvTest;
+
+#[test]
+fn test_shell_prompt_without_active_ruby() {
+    let test = RvTest::new();
+    test.write_ruby_version_file("3.4");
+
+    let output = test.rv(&["shell", "prompt"]);
+    output.assert_success();
+    assert_eq!(output.normalized_stdout(), "");
+}
+
+#[test]
+fn test_shell_prompt_shows_active_ruby() {
+    let mut test = RvTest::new();
+    test.env.insert("RUBY_ENGINE".into(), "ruby".into());
+    test.env.insert("RUBY_VERSION".into(), "3.4.1".into());
+
+    let output = test.rv(&["shell", "prompt"]);
+    output.assert_success();
+    assert_eq!(output.normalized_stdout(), "ruby-3.4.1\n");
+
+    test.write_ruby_version_file("3.4");
+    let output = test.rv(&["shell", "prompt"]);
+    output.assert_success();
+    assert_eq!(output.normalized_stdout(), "ruby-3.4.1\n");
+
+    test.write_ruby_version_file("3.3");
+    let output = test.rv(&["shell", "prompt"]);
+    output.assert_success();
+    assert_eq!(output.normalized_stdout(), "ruby-3.4.1 (wants ruby-3.3)\n");
+}
@@ -0,0 +1,55 @@
+use crate::common::RvTest;
+
+#[test]
+fn test_stats_counts_ruby_cache_lookups() {
+    let test = RvTest::new();
+    test.create_ruby_dir("ruby-3.4.1");
+    let stats_file = test.temp_root().join("stats.jsonl");
+
+    let args = [
+        "ruby",
+        "list",
+        "--installed-only",
+        "--stats",
+        "--stats-file",
+        stats_file.as_str(),
+    ];
+    // The first run learns about the Ruby by running it, and the second reads the cache.
+    test.rv(&args).assert_success();
+    let output = test.rv(&args);
+    output.assert_success();
+    output.assert_stderr_contains("Stats for `rv ruby list`:");
+    output.assert_stderr_contains("cache ruby");
+    output.assert_stderr_contains("installed rubies");
+
+    let runs: Vec<serde_json::Value> = fs_err::read_to_string(&stats_file)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(runs.len(), 2);
+    assert_eq!(runs[0]["command"], "ruby list");
+    assert_eq!(runs[0]["ok"], true);
+    assert_eq!(runs[0]["requests"], 0);
+    assert!(runs[0]["cache"]["ruby"]["misses"].as_u64().unwrap() > 0);
+    assert!(runs[1]["cache"]["ruby"]["hits"].as_u64().unwrap() > 0);
+    assert_eq!(runs[1]["cache"]["ruby"]["misses"], 0);
+    assert_eq!(runs[1]["phases"][0]["name"], "installed rubies");
+}
+
+#[test]
+fn test_stats_file_without_stats_prints_nothing() {
+    let test = RvTest::new();
+    let stats_file = test.temp_root().join("stats.jsonl");
+
+    let output = test.rv(&[
+        "ruby",
+        "list",
+        "--installed-only",
+        "--stats-file",
+        stats_file.as_str(),
+    ]);
+    output.assert_success();
+    assert!(!output.stderr().contains("Stats for"));
+    assert!(stats_file.exists());
+}
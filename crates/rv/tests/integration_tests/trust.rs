@@ -0,0 +1,55 @@
+use crate::common::RvTest;
+
+#[test]
+fn test_trust_on_first_use() {
+    let mut test = RvTest::new();
+
+    test.create_ruby_dir("ruby-4.0.1");
+
+    test.use_gemfile("../rv-lockfile/tests/inputs/Gemfile.testsource");
+    test.use_lockfile("../rv-lockfile/tests/inputs/Gemfile.testsource.lock");
+    test.replace_source("http://gems.example.com", &test.server_url());
+
+    let mock = test
+        .mock_gem_download("test-gem-1.0.0.gem")
+        .expect(3)
+        .create();
+
+    test.ci(&[]).assert_success();
+    let database = test.data_dir().join("rv/trusted-gems.json");
+    let contents = fs_err::read_to_string(&database).unwrap();
+    assert!(contents.contains("test-gem-1.0.0"));
+
+    let listed = test.rv(&["trust", "list", "test-gem"]);
+    listed.assert_success();
+    listed.assert_stdout_contains("test-gem-1.0.0");
+
+    // Pretend the release was first seen with different contents.
+    let value: serde_json::Value = serde_json::from_str(&contents).unwrap();
+    let digest = value["digests"]["test-gem-1.0.0"]["sha256"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    let tampered = "0".repeat(64);
+    fs_err::write(&database, contents.replace(&digest, &tampered)).unwrap();
+
+    let output = test.rv(&["--error-format", "json", "ci", "--force", "--keep-going"]);
+    output.assert_failure();
+    output.assert_stderr_contains(r#""code":"RV2021""#);
+    output.assert_stderr_contains("changed since rv first downloaded it");
+    output.assert_stderr_contains(&format!("rv trust allow test-gem-1.0.0 {digest}"));
+
+    test.rv(&["trust", "allow", "test-gem-1.0.0", &digest])
+        .assert_success();
+    test.ci(&["--force"]).assert_success();
+    mock.assert();
+}
+
+#[test]
+fn test_trust_forget_unknown_release() {
+    let test = RvTest::new();
+
+    let output = test.rv(&["--error-format", "json", "trust", "forget", "rack-3.1.7"]);
+    output.assert_failure();
+    output.assert_stderr_contains(r#""code":"RV7504""#);
+}
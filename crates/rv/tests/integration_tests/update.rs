@@ -0,0 +1,89 @@
+use crate::common::RvTest;
+
+fn write_lockfile(test: &RvTest) {
+    fs_err::write(
+        test.temp_root().join("Gemfile.lock"),
+        format!(
+            "\
+GEM
+  remote: {}/
+  specs:
+    racc (1.7.0)
+    test-gem (1.0.0)
+
+PLATFORMS
+  ruby
+
+DEPENDENCIES
+  racc (~> 1.7)
+  test-gem
+
+BUNDLED WITH
+   2.6.9
+",
+            test.server_url()
+        ),
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_update_one_gem() {
+    let mut test = RvTest::new();
+    write_lockfile(&test);
+    let racc_mock = test.mock_info_endpoint("racc").create();
+
+    let output = test.rv(&["update", "racc"]);
+    output.assert_success();
+    racc_mock.assert();
+    output.assert_stdout_contains("Updated racc 1.8.1 (was 1.7.0)");
+
+    let lockfile = fs_err::read_to_string(test.temp_root().join("Gemfile.lock")).unwrap();
+    assert!(lockfile.contains("    racc (1.8.1)\n"), "{lockfile}");
+    assert!(lockfile.contains("    test-gem (1.0.0)\n"), "{lockfile}");
+}
+
+#[test]
+fn test_update_gem_not_in_lockfile() {
+    let test = RvTest::new();
+    write_lockfile(&test);
+
+    let output = test.rv(&["--error-format", "json", "update", "rails"]);
+    output.assert_failure();
+    output.assert_stderr_contains("\"code\":\"RV7403\"");
+}
+
+#[test]
+fn test_update_with_minimal_changes_keeps_locked_versions() {
+    let mut test = RvTest::new();
+    write_lockfile(&test);
+    let _racc_mock = test.mock_info_endpoint("racc").create();
+
+    let output = test.rv(&["update", "racc", "--resolution", "minimal-changes"]);
+    output.assert_success();
+    output.assert_stdout_contains("Every gem is already at the version the resolution picks");
+
+    let lockfile = fs_err::read_to_string(test.temp_root().join("Gemfile.lock")).unwrap();
+    assert!(lockfile.contains("    racc (1.7.0)\n"), "{lockfile}");
+}
+
+#[test]
+fn test_update_with_lowest_direct_picks_oldest_allowed_version() {
+    let mut test = RvTest::new();
+    write_lockfile(&test);
+    let lockfile_path = test.temp_root().join("Gemfile.lock");
+    let lockfile = fs_err::read_to_string(&lockfile_path).unwrap();
+    fs_err::write(
+        &lockfile_path,
+        lockfile.replace("racc (1.7.0)", "racc (1.8.1)"),
+    )
+    .unwrap();
+    let _racc_mock = test.mock_info_endpoint("racc").create();
+
+    let output = test.rv(&["update", "racc", "--resolution", "lowest-direct"]);
+    output.assert_success();
+    output.assert_stdout_contains("Downgraded racc 1.7.0 (was 1.8.1)");
+
+    let lockfile = fs_err::read_to_string(&lockfile_path).unwrap();
+    assert!(lockfile.contains("    racc (1.7.0)\n"), "{lockfile}");
+}
@@ -0,0 +1,74 @@
+use crate::common::RvTest;
+
+const LOCKFILE: &str = "\
+GEM
+  remote: https://rubygems.org/
+  specs:
+    actionpack (8.0.2)
+      rack (>= 2.2.4)
+    rack (3.1.16)
+    rack-test (2.2.0)
+      rack (>= 1.3)
+    rails (8.0.2)
+      actionpack (= 8.0.2)
+
+PLATFORMS
+  ruby
+
+DEPENDENCIES
+  rack-test
+  rails (~> 8.0)
+
+BUNDLED WITH
+   2.6.9
+";
+
+#[test]
+fn test_why_prints_every_chain() {
+    let test = RvTest::new();
+    fs_err::write(test.temp_root().join("Gemfile.lock"), LOCKFILE).unwrap();
+
+    let output = test.rv(&["why", "rack"]);
+    output.assert_success();
+    insta::assert_snapshot!(output.normalized_stdout(), @r"
+    rack 3.1.16 is required by 2 chains:
+
+    Gemfile requires rack-test
+      rack-test 2.2.0 requires rack (>= 1.3)
+
+    Gemfile requires rails (~> 8.0)
+      rails 8.0.2 requires actionpack (= 8.0.2)
+        actionpack 8.0.2 requires rack (>= 2.2.4)
+    ");
+}
+
+#[test]
+fn test_why_json() {
+    let test = RvTest::new();
+    fs_err::write(test.temp_root().join("Gemfile.lock"), LOCKFILE).unwrap();
+
+    let output = test.rv(&["why", "actionpack", "--format", "json"]);
+    output.assert_success();
+    let json: serde_json::Value = serde_json::from_str(&output.stdout()).unwrap();
+    assert_eq!(
+        json,
+        serde_json::json!({
+            "gem": "actionpack",
+            "versions": ["8.0.2"],
+            "chains": [[
+                {"name": "rails", "requirements": ["~> 8.0"], "versions": ["8.0.2"]},
+                {"name": "actionpack", "requirements": ["= 8.0.2"], "versions": ["8.0.2"]},
+            ]],
+        })
+    );
+}
+
+#[test]
+fn test_why_gem_not_in_lockfile() {
+    let test = RvTest::new();
+    fs_err::write(test.temp_root().join("Gemfile.lock"), LOCKFILE).unwrap();
+
+    let output = test.rv(&["--error-format", "json", "why", "sinatra"]);
+    output.assert_failure();
+    output.assert_stderr_contains("\"code\":\"RV8203\"");
+}